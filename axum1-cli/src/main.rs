@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UnixStream;
 
@@ -28,12 +30,49 @@ enum Commands {
     Pause,
     /// Reload config
     ReloadConfig,
+    /// Recompute the cached nutrition of every recipe
+    RecomputeNutrition,
+    /// Write the JSON Schema of the public request/response types, one file per type.
+    /// This runs locally and doesn't need the server.
+    DumpSchemas {
+        /// The directory to write the schemas to.
+        #[arg(short, long, default_value = "schemas")]
+        out_dir: PathBuf,
+    },
+    /// Create an admin user, prompting for their password. For setting up a new deployment, it
+    /// refuses to touch an existing user. This runs locally with the server's configuration and
+    /// doesn't need the server.
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        name: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    if let Some(Commands::DumpSchemas { out_dir }) = &cli.command {
+        for path in axum1::schema::dump_schemas(out_dir)? {
+            println!("Wrote {}", path.display());
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::CreateAdmin { email, name }) = &cli.command {
+        let password = rpassword::prompt_password("Password: ")?;
+        if rpassword::prompt_password("Repeat the password: ")? != password {
+            return Err("The passwords don't match".into());
+        }
+        let settings = axum1::config::Settings::reload()?;
+        let user_id =
+            axum1::cli::admin::create_admin(&settings, name, email, password.into()).await?;
+        println!("Created the admin {name} <{email}> with the id {user_id}");
+        return Ok(());
+    }
+
     let socket_path = cli
         .socket_path
         .as_deref()
@@ -48,6 +87,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(Commands::Resume) => "resume",
         Some(Commands::Pause) => "pause",
         Some(Commands::ReloadConfig) => "reload_config",
+        Some(Commands::RecomputeNutrition) => "recompute_nutrition",
         _ => "todo",
     };
 
@@ -0,0 +1,3 @@
+-- Password reset tokens are generated by the same helper as the confirmation tokens,
+-- so they are no longer UUIDs.
+ALTER TABLE forget_password_tokens ALTER COLUMN token TYPE TEXT USING token::TEXT;
@@ -0,0 +1,16 @@
+-- A cache of the nutrition summary of each recipe, computed from its ingredients.
+-- It's kept up-to-date by the nutrition recomputation job, so it's always safe to rebuild.
+CREATE TABLE recipe_nutrition
+(
+    recipe_id     UUID PRIMARY KEY REFERENCES "recipes" (id) ON DELETE CASCADE,
+    calories      REAL NOT NULL,
+    protein       REAL NOT NULL,
+    fat           REAL NOT NULL,
+    carbohydrate  REAL NOT NULL,
+    sugar         REAL NOT NULL,
+    fiber         REAL NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at    TIMESTAMPTZ
+);
+
+SELECT trigger_updated_at('recipe_nutrition');
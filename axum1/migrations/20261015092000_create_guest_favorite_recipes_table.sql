@@ -0,0 +1,12 @@
+-- Favorites of guests (sessions without a registered user). These are merged into `favorite_recipe`
+-- when the guest registers or logs in, so there's no reference to the `users` table here.
+CREATE TABLE guest_favorite_recipes
+(
+    guest_id    UUID NOT NULL,
+
+    recipe_id   UUID NOT NULL REFERENCES "recipes" (id) ON DELETE CASCADE,
+
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (guest_id, recipe_id)
+);
@@ -0,0 +1,22 @@
+-- Recipes reported by enough users are hidden until a moderator reviews them.
+ALTER TABLE recipes ADD COLUMN hidden BOOLEAN NOT NULL DEFAULT 'FALSE';
+
+CREATE TABLE reports
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+
+    recipe_id   UUID NOT NULL REFERENCES "recipes" (id)    ON DELETE CASCADE,
+
+    reporter_id UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    reason      TEXT NOT NULL,
+
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    updated_at  TIMESTAMPTZ,
+
+    -- A user can only report the same recipe once.
+    UNIQUE (recipe_id, reporter_id)
+);
+
+SELECT trigger_updated_at('reports');
@@ -0,0 +1,5 @@
+-- Backs the Postgres fallback search, see `search_recipes_pg`.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX recipes_name_trgm_idx ON recipes USING GIN (name gin_trgm_ops);
+CREATE INDEX recipes_description_trgm_idx ON recipes USING GIN (description gin_trgm_ops);
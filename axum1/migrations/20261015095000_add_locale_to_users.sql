@@ -0,0 +1,2 @@
+-- The preferred language of the user, e.g. 'en'. Emails fall back to English when unset.
+ALTER TABLE users ADD COLUMN locale TEXT;
@@ -0,0 +1,15 @@
+-- Maps users to their sessions in the session store, so they can be revoked together.
+CREATE TABLE user_sessions
+(
+    session_id TEXT PRIMARY KEY,
+
+    user_id    UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    updated_at TIMESTAMPTZ
+);
+
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+SELECT trigger_updated_at('user_sessions');
@@ -0,0 +1,8 @@
+-- The defaults are only for the existing rows, new tokens get their expiry from the configuration.
+ALTER TABLE confirmation_tokens ADD COLUMN expires_at TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '1 day';
+ALTER TABLE forget_password_tokens ADD COLUMN expires_at TIMESTAMPTZ NOT NULL DEFAULT NOW() + INTERVAL '2 days';
+
+UPDATE forget_password_tokens SET expires_at = created_at + INTERVAL '2 days';
+
+CREATE INDEX confirmation_tokens_expires_at_idx ON confirmation_tokens (expires_at);
+CREATE INDEX forget_password_tokens_expires_at_idx ON forget_password_tokens (expires_at);
@@ -0,0 +1,13 @@
+-- NULL means nobody has checked yet, recipes using such ingredients are "unverified" for that diet.
+ALTER TABLE ingredients
+    ADD COLUMN vegan BOOLEAN,
+    ADD COLUMN vegetarian BOOLEAN,
+    ADD COLUMN gluten_free BOOLEAN,
+    ADD COLUMN contains_nuts BOOLEAN;
+
+-- The categories are enough to rule out some diets for the existing ingredients.
+UPDATE ingredients SET vegan = FALSE, vegetarian = FALSE
+WHERE category && '{meat,seafood}'::food_category[];
+
+UPDATE ingredients SET vegan = FALSE
+WHERE category && '{dairy,eggs}'::food_category[];
@@ -0,0 +1,20 @@
+-- Audit trail of admins acting as other users for support.
+CREATE TABLE impersonations
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+
+    admin_id    UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    user_id     UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    -- Set when the admin returns to their own identity or logs out.
+    ended_at    TIMESTAMPTZ,
+
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    updated_at  TIMESTAMPTZ
+);
+
+SELECT trigger_updated_at('impersonations');
+
+CREATE INDEX impersonations_user_id_idx ON impersonations (user_id);
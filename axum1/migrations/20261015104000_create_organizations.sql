@@ -0,0 +1,50 @@
+-- Separate communities hosted in one deployment. Users, recipes and ingredients belong to exactly
+-- one organization, and names only have to be unique within it.
+--
+-- Migrating existing single-tenant data: everything is moved into the `default` organization
+-- (with a fixed, nil id), which is also used for requests that don't name an organization.
+-- To split an existing community later, create the new organization and move its users,
+-- recipes and ingredients over by updating their `org_id` in a single transaction. Recipes must
+-- only reference ingredients of their own organization.
+CREATE TABLE organizations
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+
+    -- Used in the subdomain and the `X-Organization` header.
+    slug        TEXT NOT NULL COLLATE "case_insensitive" UNIQUE,
+
+    name        TEXT NOT NULL,
+
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    updated_at  TIMESTAMPTZ
+);
+
+SELECT trigger_updated_at('organizations');
+
+INSERT INTO organizations (id, slug, name)
+VALUES ('00000000-0000-0000-0000-000000000000', 'default', 'Default');
+
+ALTER TABLE users
+    ADD COLUMN org_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000'
+        REFERENCES organizations (id),
+    -- May act in every organization, the only way to cross organization boundaries.
+    ADD COLUMN is_super_admin BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE recipes
+    ADD COLUMN org_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000'
+        REFERENCES organizations (id);
+
+ALTER TABLE ingredients
+    ADD COLUMN org_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000'
+        REFERENCES organizations (id);
+
+-- The constraint names are kept, the handlers map violations of them to `409 Conflict`.
+-- Emails stay unique across the deployment, so logging in doesn't depend on the organization.
+ALTER TABLE recipes
+    DROP CONSTRAINT recipes_name_key,
+    ADD CONSTRAINT recipes_name_key UNIQUE (org_id, name);
+
+ALTER TABLE ingredients
+    DROP CONSTRAINT ingredients_name_key,
+    ADD CONSTRAINT ingredients_name_key UNIQUE (org_id, name);
@@ -0,0 +1,25 @@
+-- Opt-in digests of new recipes. Users without a row don't get any.
+CREATE TABLE digest_preferences
+(
+    user_id           UUID PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+
+    frequency         TEXT NOT NULL DEFAULT 'weekly' CHECK (frequency IN ('weekly', 'off')),
+
+    -- Ingredient or cuisine names, on top of the user's favorite ingredients.
+    topics            TEXT[] NOT NULL DEFAULT '{}',
+
+    -- An IANA time zone name, weeks start on Monday midnight in it.
+    time_zone         TEXT NOT NULL DEFAULT 'UTC',
+
+    -- Lets the links in the emails unsubscribe without logging in.
+    unsubscribe_token TEXT NOT NULL UNIQUE,
+
+    -- The end of the last week a digest was considered for, whether or not anything was sent.
+    last_period_end   TIMESTAMPTZ,
+
+    created_at        TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    updated_at        TIMESTAMPTZ
+);
+
+SELECT trigger_updated_at('digest_preferences');
@@ -0,0 +1,13 @@
+-- Optional prices for cost estimates. The amount and its ISO 4217 currency code are either both
+-- set or both missing, amounts in different currencies are never added up.
+ALTER TABLE ingredients
+    ADD COLUMN price_per_100g REAL CHECK (price_per_100g >= 0),
+    ADD COLUMN price_currency TEXT CHECK (price_currency ~ '^[A-Z]{3}$'),
+    ADD CONSTRAINT ingredients_price_pair_check
+        CHECK ((price_per_100g IS NULL) = (price_currency IS NULL));
+
+ALTER TABLE ingredient_suggestions
+    ADD COLUMN price_per_100g REAL CHECK (price_per_100g >= 0),
+    ADD COLUMN price_currency TEXT CHECK (price_currency ~ '^[A-Z]{3}$'),
+    ADD CONSTRAINT ingredient_suggestions_price_pair_check
+        CHECK ((price_per_100g IS NULL) = (price_currency IS NULL));
@@ -0,0 +1,2 @@
+-- The history itself lives in Redis, this is only the user's choice to keep one.
+ALTER TABLE users ADD COLUMN track_recently_viewed BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,10 @@
+-- How many ingredient suggestions each user submitted on a (UTC) day, to cap them. Only the
+-- current day matters, older rows are dropped when the user suggests again.
+CREATE TABLE suggestion_counts
+(
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    day     DATE NOT NULL,
+    count   INTEGER NOT NULL,
+
+    PRIMARY KEY (user_id, day)
+);
@@ -0,0 +1,10 @@
+-- Text uploads may be compressed at rest. `bytes` stays the original size (the quota is counted
+-- with it), `stored_bytes` is the size on disk.
+ALTER TABLE uploads
+    ADD COLUMN stored_bytes     INT,
+    ADD COLUMN content_type     TEXT NOT NULL DEFAULT 'application/octet-stream',
+    ADD COLUMN content_encoding TEXT CHECK (content_encoding IN ('zstd'));
+
+UPDATE uploads SET stored_bytes = bytes;
+
+ALTER TABLE uploads ALTER COLUMN stored_bytes SET NOT NULL;
@@ -0,0 +1,25 @@
+-- Announcements broadcast by admins. Rows are kept as the audit trail of who announced what, to
+-- whom, even when the announcement wasn't saved for the users.
+CREATE TABLE announcements
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+    org_id      UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+    author_id   UUID REFERENCES users (user_id) ON DELETE SET NULL,
+    audience    TEXT NOT NULL CHECK (audience IN ('all', 'admins')),
+    title       TEXT NOT NULL,
+    body        TEXT NOT NULL,
+    persisted   BOOLEAN NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Saved announcements, so users who weren't connected can read them later.
+CREATE TABLE user_notifications
+(
+    user_id         UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    announcement_id UUID NOT NULL REFERENCES announcements (id) ON DELETE CASCADE,
+    read_at         TIMESTAMPTZ,
+
+    PRIMARY KEY (user_id, announcement_id)
+);
+
+CREATE INDEX user_notifications_unread_idx ON user_notifications (user_id) WHERE read_at IS NULL;
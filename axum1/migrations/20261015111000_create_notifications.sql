@@ -0,0 +1,27 @@
+-- Notifications saved for their recipients, so users who weren't connected when one was sent can
+-- still see it. `payload` is the notification as it's sent live, `kind` is its event name.
+CREATE TABLE notifications
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+    user_id     UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    kind        TEXT NOT NULL,
+    payload     JSONB NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    read_at     TIMESTAMPTZ
+);
+
+CREATE INDEX notifications_user_id_created_at_idx ON notifications (user_id, created_at DESC);
+CREATE INDEX notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
+
+-- Saved announcements were the only notifications stored so far.
+INSERT INTO notifications (user_id, kind, payload, created_at, read_at)
+SELECT
+    un.user_id,
+    'system_announcement',
+    jsonb_build_object('id', a.id, 'title', a.title, 'body', a.body, 'created_at', a.created_at),
+    a.created_at,
+    un.read_at
+FROM user_notifications un
+INNER JOIN announcements a ON a.id = un.announcement_id;
+
+DROP TABLE user_notifications;
@@ -0,0 +1,95 @@
+-- Recipes are addressed by an immutable, URL friendly slug generated from the name, so the name
+-- can be edited without breaking links.
+--
+-- Slugs are unique within an organization. They're generated by the database, so every way of
+-- creating recipes gets one. Moving a recipe to another organization fails if its slug is taken
+-- there.
+
+-- Folds the accents `RE_RECIPE` allows (and a few more), lowercases, and joins everything else
+-- with dashes.
+CREATE FUNCTION slugify(value TEXT) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE AS $$
+    SELECT COALESCE(
+        NULLIF(
+            trim(BOTH '-' FROM regexp_replace(
+                lower(translate(
+                    value,
+                    'áàâäãåéèêëíìîïóòôöõőøúùûüűñçÁÀÂÄÃÅÉÈÊËÍÌÎÏÓÒÔÖÕŐØÚÙÛÜŰÑÇ',
+                    'aaaaaaeeeeiiiiooooooouuuuuncAAAAAAEEEEIIIIOOOOOOOUUUUUNC'
+                )),
+                '[^a-z0-9]+', '-', 'g'
+            )),
+            ''
+        ),
+        'recipe'
+    )
+$$;
+
+-- Addresses that used to lead to a recipe, and still do.
+CREATE TABLE recipe_slug_history
+(
+    org_id      UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+    slug        TEXT NOT NULL COLLATE "case_insensitive",
+    recipe_id   UUID NOT NULL REFERENCES recipes (id) ON DELETE CASCADE,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (org_id, slug)
+);
+
+-- The slugified name, with a numeric suffix if it's taken, by a current or a former address.
+-- Names are case insensitive, and regular expressions don't support that collation.
+CREATE FUNCTION unique_recipe_slug(org UUID, recipe_name TEXT) RETURNS TEXT
+LANGUAGE plpgsql AS $$
+DECLARE
+    base        TEXT := slugify(recipe_name COLLATE "default");
+    candidate   TEXT := base;
+    suffix      INT := 1;
+BEGIN
+    WHILE EXISTS (SELECT 1 FROM recipes WHERE org_id = org AND slug = candidate)
+       OR EXISTS (SELECT 1 FROM recipe_slug_history WHERE org_id = org AND slug = candidate)
+    LOOP
+        suffix := suffix + 1;
+        candidate := base || '-' || suffix;
+    END LOOP;
+    RETURN candidate;
+END;
+$$;
+
+ALTER TABLE recipes ADD COLUMN slug TEXT COLLATE "case_insensitive";
+
+-- The oldest recipe keeps the plain slug.
+DO $$
+DECLARE
+    recipe RECORD;
+BEGIN
+    FOR recipe IN SELECT id, org_id, name FROM recipes ORDER BY created_at, id LOOP
+        UPDATE recipes SET slug = unique_recipe_slug(recipe.org_id, recipe.name) WHERE id = recipe.id;
+    END LOOP;
+END;
+$$;
+
+ALTER TABLE recipes
+    ALTER COLUMN slug SET NOT NULL,
+    ADD CONSTRAINT recipes_slug_key UNIQUE (org_id, slug);
+
+-- Links from before slugs addressed recipes by their name.
+INSERT INTO recipe_slug_history (org_id, slug, recipe_id)
+SELECT org_id, name, id FROM recipes WHERE name <> slug;
+
+CREATE FUNCTION set_recipe_slug() RETURNS TRIGGER
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.slug IS NULL THEN
+            NEW.slug := unique_recipe_slug(NEW.org_id, NEW.name);
+        END IF;
+    ELSIF NEW.slug IS DISTINCT FROM OLD.slug THEN
+        RAISE EXCEPTION 'the slug of a recipe can not be changed';
+    END IF;
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER recipes_slug
+    BEFORE INSERT OR UPDATE ON recipes
+    FOR EACH ROW EXECUTE FUNCTION set_recipe_slug();
@@ -0,0 +1,6 @@
+-- The retention purge deletes the oldest rows of the log tables in batches, these keep it from
+-- scanning the whole table for every batch.
+CREATE INDEX impersonations_ended_at_idx ON impersonations (ended_at) WHERE ended_at IS NOT NULL;
+CREATE INDEX announcements_created_at_idx ON announcements (created_at);
+CREATE INDEX failed_jobs_failed_at_idx ON failed_jobs (failed_at);
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
@@ -0,0 +1,18 @@
+-- Events written in the same transaction as the change that caused them, and published to the
+-- connected clients by the outbox relay afterwards. `payload` and `kind` are as in `notifications`,
+-- `recipient` is the user the event is meant for, if it's not for everyone.
+CREATE TABLE events_outbox
+(
+    id               UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+    kind             TEXT NOT NULL,
+    recipient        UUID,
+    payload          JSONB NOT NULL,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    attempts         INT NOT NULL DEFAULT 0,
+    next_attempt_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error       TEXT,
+    sent_at          TIMESTAMPTZ
+);
+
+CREATE INDEX events_outbox_pending_idx ON events_outbox (next_attempt_at) WHERE sent_at IS NULL;
+CREATE INDEX events_outbox_sent_at_idx ON events_outbox (sent_at) WHERE sent_at IS NOT NULL;
@@ -0,0 +1,8 @@
+-- A recipe may use one of its author's uploads as its image. Uploads in use can't be deleted
+-- until they're detached.
+ALTER TABLE recipes
+    ADD COLUMN image TEXT,
+    ADD CONSTRAINT recipes_image_fkey
+        FOREIGN KEY (creator_id, image) REFERENCES uploads (uploader_id, file_name);
+
+CREATE INDEX recipes_image_idx ON recipes (creator_id, image) WHERE image IS NOT NULL;
@@ -0,0 +1,23 @@
+CREATE TYPE allergen AS ENUM (
+    'gluten',
+    'crustaceans',
+    'eggs',
+    'fish',
+    'peanuts',
+    'soy',
+    'dairy',
+    'nuts',
+    'celery',
+    'mustard',
+    'sesame',
+    'sulphites',
+    'lupin',
+    'molluscs'
+);
+
+-- NULL means nobody has checked yet, an empty array means checked and free of allergens.
+-- Recipes using unchecked ingredients are "unverified", they may contain anything.
+ALTER TABLE ingredients ADD COLUMN allergens allergen[];
+
+-- A suggestion leaves the allergens as they are when NULL.
+ALTER TABLE ingredient_suggestions ADD COLUMN allergens allergen[];
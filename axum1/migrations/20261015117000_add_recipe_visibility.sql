@@ -0,0 +1,5 @@
+CREATE TYPE recipe_visibility AS ENUM ('private', 'unlisted', 'public');
+
+-- The existing recipes stay public, new ones start as private drafts.
+ALTER TABLE recipes ADD COLUMN visibility recipe_visibility NOT NULL DEFAULT 'public';
+ALTER TABLE recipes ALTER COLUMN visibility SET DEFAULT 'private';
@@ -0,0 +1,42 @@
+-- Remembers the recipes that were deleted, so syncing clients can be told to drop them. Kept for
+-- `worker.retention.tombstones_days`, clients with an older cursor have to resync in full.
+CREATE TABLE recipe_tombstones (
+    recipe_id UUID PRIMARY KEY,
+    org_id UUID NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX recipe_tombstones_org_deleted_at_idx ON recipe_tombstones (org_id, deleted_at, recipe_id);
+
+-- Also catches the recipes deleted along with their author.
+CREATE OR REPLACE FUNCTION record_recipe_tombstone()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    INSERT INTO recipe_tombstones (recipe_id, org_id) VALUES (OLD.id, OLD.org_id)
+    ON CONFLICT (recipe_id) DO UPDATE SET deleted_at = NOW();
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_recipe_tombstone
+    AFTER DELETE ON recipes
+    FOR EACH ROW
+EXECUTE FUNCTION record_recipe_tombstone();
+
+-- Changing the ingredients of a recipe changes the recipe for the clients syncing it.
+CREATE OR REPLACE FUNCTION touch_recipe()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    UPDATE recipes SET updated_at = NOW() WHERE id = COALESCE(NEW.recipe_id, OLD.recipe_id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER touch_recipe
+    AFTER INSERT OR UPDATE OR DELETE ON ingredients_to_recipes
+    FOR EACH ROW
+EXECUTE FUNCTION touch_recipe();
+
+CREATE INDEX recipes_org_changed_at_idx ON recipes (org_id, (COALESCE(updated_at, created_at)), id);
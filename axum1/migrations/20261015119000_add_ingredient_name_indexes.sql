@@ -0,0 +1,6 @@
+-- Back resolving typed ingredient names, see `routes::ingredient::resolve`. Names are compared
+-- slugified, so casing, accents and punctuation don't matter. `slugify` uses regular expressions,
+-- which don't support the nondeterministic collation of the names.
+CREATE INDEX ingredients_name_slug_idx ON ingredients (org_id, slugify(name COLLATE "default"));
+CREATE INDEX ingredients_name_slug_trgm_idx ON ingredients
+    USING GIN (slugify(name COLLATE "default") gin_trgm_ops);
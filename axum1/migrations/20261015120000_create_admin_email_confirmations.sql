@@ -0,0 +1,13 @@
+-- Audit trail of admins confirming a user's email address on their behalf.
+CREATE TABLE admin_email_confirmations
+(
+    id          UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+
+    admin_id    UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    user_id     UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX admin_email_confirmations_user_id_idx ON admin_email_confirmations (user_id);
@@ -0,0 +1,3 @@
+-- The primary key starts with the recipe, this finds the recipes using an ingredient, like the
+-- pantry match does.
+CREATE INDEX ingredients_to_recipes_ingredient_id_idx ON ingredients_to_recipes (ingredient_id, recipe_id);
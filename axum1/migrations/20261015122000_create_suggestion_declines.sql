@@ -0,0 +1,23 @@
+-- Audit trail of moderators declining ingredient suggestions. The suggestions themselves are
+-- deleted, so what they were about is copied here.
+CREATE TABLE suggestion_declines
+(
+    id              UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+
+    org_id          UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+
+    suggestion_id   UUID NOT NULL,
+
+    moderator_id    UUID NOT NULL REFERENCES "users" (user_id) ON DELETE CASCADE,
+
+    -- The author of the suggestion.
+    user_id         UUID REFERENCES "users" (user_id) ON DELETE SET NULL,
+
+    ingredient      TEXT,
+
+    reason          TEXT,
+
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX suggestion_declines_org_id_created_at_idx ON suggestion_declines (org_id, created_at);
@@ -0,0 +1,17 @@
+-- The data of an ingredient after each applied, merged or reverted change, numbered from 1 per
+-- ingredient. The first revision is the data before the first recorded change.
+CREATE TABLE ingredient_revisions
+(
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+    ingredient_id UUID NOT NULL REFERENCES ingredients (id) ON DELETE CASCADE,
+    revision INT NOT NULL,
+    snapshot JSONB NOT NULL,
+    -- Kept when the user is deleted, the change still happened.
+    applied_by UUID REFERENCES users (user_id) ON DELETE SET NULL,
+    -- The suggestions are deleted once applied, so there's nothing to reference.
+    suggestion_ids UUID[] NOT NULL DEFAULT '{}',
+    -- The revision a revert restored.
+    reverted_to INT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (ingredient_id, revision)
+);
@@ -0,0 +1,13 @@
+-- The requests that may have changed something while an admin was impersonating a user, so
+-- the changes can be told apart from the user's own. Purged with their impersonation.
+CREATE TABLE impersonated_requests
+(
+    id               UUID PRIMARY KEY DEFAULT uuid_generate_v1mc(),
+    impersonation_id UUID        NOT NULL REFERENCES impersonations (id) ON DELETE CASCADE,
+    method           TEXT        NOT NULL,
+    path             TEXT        NOT NULL,
+    status           SMALLINT    NOT NULL,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX impersonated_requests_impersonation_id_idx ON impersonated_requests (impersonation_id);
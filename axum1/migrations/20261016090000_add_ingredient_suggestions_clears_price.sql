@@ -0,0 +1,6 @@
+-- Suggestions may remove the price of the ingredient, which a missing price can't tell apart from
+-- leaving it as it is.
+ALTER TABLE ingredient_suggestions
+    ADD COLUMN clears_price BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD CONSTRAINT ingredient_suggestions_clears_price_check
+        CHECK (NOT clears_price OR price_per_100g IS NULL);
@@ -0,0 +1,17 @@
+-- Confirmation and password reset tokens are stored as their SHA-256 digest, and looked up by it.
+-- The delivery queue keeps the token itself until the email is sent, referencing its digest.
+ALTER TABLE confirmation_delivery_queue
+    DROP CONSTRAINT confirmation_delivery_queue_confirmation_id_fkey,
+    ADD COLUMN token_hash TEXT;
+
+UPDATE confirmation_delivery_queue
+SET token_hash = encode(sha256(convert_to(confirmation_id, 'UTF8')), 'hex');
+UPDATE confirmation_tokens
+SET confirmation_token = encode(sha256(convert_to(confirmation_token, 'UTF8')), 'hex');
+UPDATE forget_password_tokens
+SET token = encode(sha256(convert_to(token, 'UTF8')), 'hex');
+
+ALTER TABLE confirmation_delivery_queue
+    ALTER COLUMN token_hash SET NOT NULL,
+    ADD CONSTRAINT confirmation_delivery_queue_token_hash_fkey
+        FOREIGN KEY (token_hash) REFERENCES confirmation_tokens (confirmation_token) ON DELETE CASCADE;
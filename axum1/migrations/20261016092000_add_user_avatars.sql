@@ -0,0 +1,6 @@
+-- A user may pick one of their image uploads as their avatar. Like recipe images, uploads in use
+-- can't be deleted until they're detached.
+ALTER TABLE users
+    ADD COLUMN avatar TEXT,
+    ADD CONSTRAINT users_avatar_fkey
+        FOREIGN KEY (user_id, avatar) REFERENCES uploads (uploader_id, file_name);
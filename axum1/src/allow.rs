@@ -0,0 +1,42 @@
+use axum::{
+    extract::Request,
+    http::{header::ALLOW, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Answers `OPTIONS` requests with `204 No Content` and an `Allow` header listing the methods
+/// of the matched route.
+///
+/// Routes don't register `OPTIONS` handlers, so the router answers them with a
+/// `405 Method Not Allowed` which already carries the `Allow` header. We only have to turn that
+/// into a successful response. The router adds that header last, so this must wrap the whole of
+/// it, see `startup::outermost`. Unknown paths stay a `404`. CORS preflight requests are answered
+/// by the `CorsLayer` before they get here.
+pub async fn answer_options(request: Request, next: Next) -> Response {
+    if request.method() != Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let Some(allow) = response
+        .headers()
+        .get(ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+    else {
+        return response;
+    };
+
+    let allow = if allow.is_empty() {
+        "OPTIONS".to_owned()
+    } else {
+        format!("{allow},OPTIONS")
+    };
+    match HeaderValue::from_str(&allow) {
+        Ok(allow) => (StatusCode::NO_CONTENT, [(ALLOW, allow)]).into_response(),
+        Err(_) => response,
+    }
+}
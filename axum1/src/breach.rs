@@ -0,0 +1,137 @@
+//! Checking new passwords against the Have I Been Pwned corpus of breached passwords.
+//!
+//! The range API is k-anonymous: only the first 5 hex characters of the password's SHA-1 hash
+//! leave the server, and the API answers with the suffixes of every breached hash sharing them.
+use secrecy::{ExposeSecret, SecretString};
+use sha1::{Digest, Sha1};
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool, interfaces::KeysInterface, types::Expiration,
+};
+
+use crate::{
+    config::{BreachCheckFailure, BreachedPasswordSettings},
+    error::ApiError,
+};
+
+#[derive(Clone)]
+pub struct BreachedPasswords {
+    http: reqwest::Client,
+    redis: RedisPool,
+}
+
+impl BreachedPasswords {
+    pub fn new(redis: RedisPool) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            redis,
+        }
+    }
+
+    fn key(prefix: &str) -> String {
+        format!("breached_passwords:{prefix}")
+    }
+
+    /// Whether the password appeared in a known breach.
+    pub async fn is_breached(
+        &self,
+        settings: &BreachedPasswordSettings,
+        password: &SecretString,
+    ) -> Result<bool, anyhow::Error> {
+        let (prefix, suffix) = hash_parts(password);
+        let range = self.range(settings, &prefix).await?;
+        Ok(range_contains(&range, &suffix))
+    }
+
+    /// The API's answer for the prefix, cached for a while. The cache is best effort, the API is
+    /// asked when Redis can't be reached.
+    async fn range(
+        &self,
+        settings: &BreachedPasswordSettings,
+        prefix: &str,
+    ) -> Result<String, anyhow::Error> {
+        let key = Self::key(prefix);
+        match self.redis.get::<Option<String>, _>(&key).await {
+            Ok(Some(range)) => return Ok(range),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(error.message = %e, "Failed to read cached breach range"),
+        }
+
+        let range = self
+            .http
+            .get(format!("{}/range/{prefix}", settings.api_url()))
+            // Pads the answer with fake suffixes, so its size doesn't reveal the prefix either.
+            .header("Add-Padding", "true")
+            .timeout(settings.timeout())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let cached: Result<(), _> = self
+            .redis
+            .set(
+                &key,
+                range.as_str(),
+                Some(Expiration::EX(settings.cache_seconds())),
+                None,
+                false,
+            )
+            .await;
+        if let Err(e) = cached {
+            tracing::warn!(error.message = %e, "Failed to cache breach range");
+        }
+        Ok(range)
+    }
+}
+
+/// The first 5 and the remaining 35 characters of the password's uppercase hex SHA-1 hash.
+pub fn hash_parts(password: &SecretString) -> (String, String) {
+    let hash = format!("{:X}", Sha1::digest(password.expose_secret().as_bytes()));
+    let (prefix, suffix) = hash.split_at(5);
+    (prefix.to_owned(), suffix.to_owned())
+}
+
+/// Whether a range answer lists the suffix. Its lines are `SUFFIX:COUNT`, the padding has a count
+/// of 0.
+pub fn range_contains(range: &str, suffix: &str) -> bool {
+    range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .any(|(candidate, count)| {
+            candidate.eq_ignore_ascii_case(suffix)
+                && count.trim().parse::<u64>().is_ok_and(|count| count > 0)
+        })
+}
+
+/// Rejects passwords that appeared in a known breach. Does nothing unless it's enabled.
+///
+/// When the API can't be reached, the password is accepted or rejected as configured.
+pub async fn ensure_password_not_breached(
+    breached_passwords: &BreachedPasswords,
+    settings: &BreachedPasswordSettings,
+    password: &SecretString,
+) -> Result<(), ApiError> {
+    if !settings.enabled {
+        return Ok(());
+    }
+    match breached_passwords.is_breached(settings, password).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(ApiError::PasswordBreached),
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                on_error = ?settings.on_error,
+                "Failed to check a password against known breaches"
+            );
+            match settings.on_error {
+                BreachCheckFailure::FailOpen => Ok(()),
+                BreachCheckFailure::FailClosed => Err(ApiError::unprocessable_entity([(
+                    "password",
+                    "couldn't be checked against known data breaches, please try again later",
+                )])),
+            }
+        }
+    }
+}
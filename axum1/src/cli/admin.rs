@@ -0,0 +1,81 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use validator::ValidateEmail;
+
+use crate::{
+    config::Settings, queue::get_connection_pool, routes::auth::password::compute_password_hash,
+    RE_USERNAME,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CreateAdminError {
+    #[error("{0}")]
+    Invalid(&'static str),
+    #[error("a user with the email {0} already exists")]
+    EmailTaken(String),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+/// Creates a confirmed admin user, for setting up a new deployment. It never touches an existing
+/// user, so running it again with the same email fails instead of resetting their password.
+///
+/// This connects to the database of the configuration directly, the server doesn't have to run.
+/// The migrations are run first, like at the server's startup.
+pub async fn create_admin(
+    settings: &Settings,
+    name: &str,
+    email: &str,
+    password: SecretString,
+) -> Result<uuid::Uuid, CreateAdminError> {
+    if !(2..=40).contains(&name.chars().count()) || !RE_USERNAME.is_match(name) {
+        return Err(CreateAdminError::Invalid(
+            "the name must be 2 to 40 letters, digits or non-consecutive inner periods",
+        ));
+    }
+    if !email.validate_email() {
+        return Err(CreateAdminError::Invalid("the email must be valid"));
+    }
+    if password.expose_secret().is_empty() {
+        return Err(CreateAdminError::Invalid("the password can't be empty"));
+    }
+
+    let pool = get_connection_pool(&settings.database);
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .context("Failed to run the migrations")?;
+
+    let params = settings.password_hashing.params();
+    let password_hash =
+        crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+            .await
+            .context("Failed to hash password")??;
+
+    insert_admin(&pool, name, email, &password_hash).await
+}
+
+/// Inserts the admin with an already hashed password, unless the email is taken.
+pub async fn insert_admin(
+    pool: &PgPool,
+    name: &str,
+    email: &str,
+    password_hash: &SecretString,
+) -> Result<uuid::Uuid, CreateAdminError> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO users (name, email, password_hash, confirmed, is_admin)
+        VALUES ($1, $2, $3, TRUE, TRUE)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING user_id
+        "#,
+        name,
+        email,
+        password_hash.expose_secret(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to insert the admin user")?
+    .ok_or_else(|| CreateAdminError::EmailTaken(email.to_owned()))
+}
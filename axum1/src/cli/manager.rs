@@ -1,10 +1,10 @@
-use meilisearch_sdk::client::Client;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UnixListener;
 
 use crate::config::Settings;
 use crate::queue::get_connection_pool;
-use crate::search::run_meili_indexer;
+use crate::routes::recipe::nutrition::recompute_all_recipe_nutrition;
+use crate::search::{meili_client, run_meili_indexer};
 use crate::task::PausableFutureSupervisor;
 use crate::utils::report_exit;
 
@@ -61,6 +61,26 @@ pub async fn cli_manager(
                 supervisor.pause();
                 socket.write_all(b"ok").await?;
             }
+            "recompute_nutrition" => {
+                tracing::warn!("Recomputing recipe nutrition through CLI..");
+                let database = config.borrow().database.clone();
+                let pool = get_connection_pool(&database);
+                match recompute_all_recipe_nutrition(&pool).await {
+                    Ok(updated) => {
+                        socket
+                            .write_all(format!("ok, {updated} recipes updated").as_bytes())
+                            .await?;
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "Failed to recompute recipe nutrition"
+                        );
+                        socket.write_all(b"error").await?;
+                    }
+                }
+            }
             "reload_config" => {
                 tracing::warn!("Reloading configuration..");
                 config.send(Settings::reload()?)?;
@@ -83,7 +103,7 @@ pub async fn run_meili_indexer_once(
     let Settings {
         database, meili, ..
     } = config.borrow_and_update().clone();
-    let meili_client = Client::new(meili.url, Some(meili.master_key))?;
+    let meili_client = meili_client(&meili)?;
     let pool = get_connection_pool(&database);
     run_meili_indexer(&pool, &meili_client).await
 }
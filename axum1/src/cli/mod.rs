@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod manager;
 
 pub use manager::cli_manager;
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use sqlx::{
@@ -8,18 +10,43 @@ use sqlx::{
 use crate::{
     email::{Email, EmailClient},
     error::ApiError,
+    versioning::ApiVersion,
 };
 
-#[derive(Deserialize, Clone)]
+/// Secrets are wrapped in [`SecretString`], so `Debug` (and logging the settings) redacts them.
+/// They're only exposed where they're used, like in [`DatabaseSettings::connection_string`].
+#[derive(Deserialize, Clone, Debug)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub redis: RedisSettings,
     pub application_settings: ApplicationSettings,
     pub frontend_url: String,
-    pub sentry_dsn: Option<String>,
+    pub sentry_dsn: Option<SecretString>,
     pub email_client: EmailClientSettings,
     pub meili: MeiliConfig,
     pub oauth: OAuth,
+    #[serde(default)]
+    pub tokens: TokenSettings,
+    #[serde(default)]
+    pub session: SessionSettings,
+    #[serde(default)]
+    pub worker: WorkerSettings,
+    #[serde(default)]
+    pub pagination: PaginationSettings,
+    #[serde(default)]
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub password_hashing: PasswordHashingSettings,
+    #[serde(default)]
+    pub auth: AuthSettings,
+    #[serde(default)]
+    pub recipe_import: RecipeImportSettings,
+    #[serde(default)]
+    pub search: SearchSettings,
+    #[serde(default)]
+    pub security: SecuritySettings,
 }
 
 impl Settings {
@@ -28,41 +55,718 @@ impl Settings {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct ApplicationSettings {
     pub port: u16,
     pub host: [u8; 4],
     pub daily_upload_limit_bytes: i64,
     pub cli_unix_socket: Option<String>,
+    pub max_recipe_batch_size: Option<usize>,
+    /// Always respond with `application/problem+json` errors, regardless of the `Accept` header.
+    pub problem_json: Option<bool>,
+    /// The minimum `zxcvbn` score (0-4) a new password must reach.
+    pub min_password_score: Option<u8>,
+    pub password_strength_requests_per_minute: Option<u32>,
+    /// The number of reports after which a recipe is hidden until a moderator reviews it.
+    pub report_hide_threshold: Option<i64>,
+    /// With this set, `<slug>.<base_domain>` selects the organization `slug`.
+    pub base_domain: Option<String>,
+    /// Reject logins to accounts that haven't confirmed their email yet. Off by default.
+    pub require_confirmed_email: Option<bool>,
+    /// Peers in these ranges may tell the client's address in `X-Forwarded-For` or `X-Real-IP`.
+    /// Nobody is trusted by default, as anyone could spoof those headers.
+    #[serde(default)]
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    /// Require the `X-CSRF-Token` header on unsafe requests. On by default, it may be turned off
+    /// if the session cookie is `SameSite=Strict`.
+    pub csrf_protection: Option<bool>,
+    /// How many recently viewed recipes are remembered per user. Defaults to 20.
+    pub recently_viewed_limit: Option<usize>,
+    /// How many ingredient suggestions a user may submit per (UTC) day. Defaults to 50.
+    pub max_suggestions_per_day: Option<u32>,
+    /// Content types of uploads compressed at rest, like `text/*` or `application/json`. Nothing is
+    /// compressed by default.
+    pub compressed_upload_types: Option<Vec<String>>,
+    /// How many announcements may be broadcast per hour. Defaults to 10.
+    pub broadcasts_per_hour: Option<u32>,
+    /// How many emails admins may confirm on behalf of users per hour. Defaults to 20.
+    pub admin_email_confirmations_per_hour: Option<u32>,
+    /// How many username and email availability checks a client may make per minute. Defaults
+    /// to 10.
+    pub availability_checks_per_minute: Option<u32>,
+    /// Honor `X-Sandbox: true`, rolling back the database changes of the request. Off by
+    /// default, and not for production, see `crate::sandbox` for what isn't rolled back.
+    pub sandbox_mode: Option<bool>,
+    /// How long a viewer of a recipe counts as present after their last heartbeat. Defaults to
+    /// 30 seconds.
+    pub presence_timeout_seconds: Option<u64>,
+    /// How often idle SSE streams get a keep-alive comment. Defaults to 15 seconds, 0 disables
+    /// them. Keep it below the idle timeout of any proxy in front of the app (60 seconds with
+    /// nginx's `proxy_read_timeout`), or the proxy drops quiet streams.
+    pub sse_keep_alive_seconds: Option<u64>,
+    /// The text of the keep-alive comments. Empty by default.
+    pub sse_keep_alive_text: Option<String>,
+    /// How many SSE streams and WebSockets a user or guest may have open at once on an instance,
+    /// the ones over it get `429`. Defaults to 5, 0 disables the limit.
+    pub max_connections_per_user: Option<usize>,
+    /// How many SSE streams and WebSockets may be open at once on an instance. Defaults to 10000,
+    /// 0 disables the limit.
+    pub max_connections: Option<usize>,
+    /// Reject request bodies with fields the endpoint doesn't know, instead of ignoring them. Off
+    /// by default, as some clients send extra metadata. Only read at startup.
+    pub strict_request_bodies: Option<bool>,
+    /// The language numbers are formatted in when `Accept-Language` names no supported one, like
+    /// `hu`. Defaults to English.
+    pub default_locale: Option<String>,
+    /// The most ingredients a recipe may have. Defaults to 100.
+    pub max_recipe_ingredients: Option<usize>,
+    /// The most steps a recipe may have. Defaults to 100.
+    pub max_recipe_steps: Option<usize>,
+    /// The most recipes `GET /r/compare` compares at once. Defaults to 5.
+    pub max_compared_recipes: Option<usize>,
+    /// The most changes `GET /r/changes` returns at once. Defaults to 500.
+    pub max_sync_changes: Option<usize>,
+    /// The most requests handled at once, the ones over it get `503` with `Retry-After` right
+    /// away instead of waiting for a database connection. Defaults to 4 per database
+    /// connection, 0 disables it. Only read at startup.
+    pub max_concurrent_requests: Option<usize>,
+    /// The API version the paths without a version prefix serve, like `v1`. Defaults to the
+    /// first version. Only read at startup.
+    pub default_api_version: Option<ApiVersion>,
+    /// Redirect the non-canonical addresses of recipes, like `/r/Tomato%20Soup/`, to the
+    /// canonical one with `301`. On by default.
+    pub canonical_recipe_urls: Option<bool>,
+    /// The address clients reach the API at, like `https://api.example.com`, for the links in
+    /// emails that lead to the API instead of the frontend. Defaults to `http://localhost:<port>`.
+    pub public_url: Option<String>,
+}
+
+impl ApplicationSettings {
+    pub fn max_recipe_batch_size(&self) -> usize {
+        self.max_recipe_batch_size.unwrap_or(25)
+    }
+
+    pub fn max_recipe_ingredients(&self) -> usize {
+        self.max_recipe_ingredients.unwrap_or(100)
+    }
+
+    pub fn max_recipe_steps(&self) -> usize {
+        self.max_recipe_steps.unwrap_or(100)
+    }
+
+    pub fn max_compared_recipes(&self) -> usize {
+        self.max_compared_recipes.unwrap_or(5)
+    }
+
+    pub fn max_sync_changes(&self) -> usize {
+        self.max_sync_changes.unwrap_or(500)
+    }
+
+    pub fn default_api_version(&self) -> ApiVersion {
+        self.default_api_version.unwrap_or_default()
+    }
+
+    pub fn canonical_recipe_urls(&self) -> bool {
+        self.canonical_recipe_urls.unwrap_or(true)
+    }
+
+    pub fn public_url(&self) -> String {
+        self.public_url
+            .clone()
+            .unwrap_or_else(|| format!("http://localhost:{}", self.port))
+    }
+
+    pub fn min_password_score(&self) -> u8 {
+        self.min_password_score.unwrap_or(2)
+    }
+
+    pub fn password_strength_requests_per_minute(&self) -> u32 {
+        self.password_strength_requests_per_minute.unwrap_or(30)
+    }
+
+    pub fn report_hide_threshold(&self) -> i64 {
+        self.report_hide_threshold.unwrap_or(5)
+    }
+
+    pub fn require_confirmed_email(&self) -> bool {
+        self.require_confirmed_email.unwrap_or(false)
+    }
+
+    pub fn csrf_protection(&self) -> bool {
+        self.csrf_protection.unwrap_or(true)
+    }
+
+    pub fn recently_viewed_limit(&self) -> usize {
+        self.recently_viewed_limit.unwrap_or(20)
+    }
+
+    pub fn max_suggestions_per_day(&self) -> u32 {
+        self.max_suggestions_per_day.unwrap_or(50)
+    }
+
+    pub fn compressed_upload_types(&self) -> Vec<String> {
+        self.compressed_upload_types.clone().unwrap_or_default()
+    }
+
+    pub fn broadcasts_per_hour(&self) -> u32 {
+        self.broadcasts_per_hour.unwrap_or(10)
+    }
+
+    pub fn admin_email_confirmations_per_hour(&self) -> u32 {
+        self.admin_email_confirmations_per_hour.unwrap_or(20)
+    }
+
+    pub fn availability_checks_per_minute(&self) -> u32 {
+        self.availability_checks_per_minute.unwrap_or(10)
+    }
+
+    pub fn sandbox_mode(&self) -> bool {
+        self.sandbox_mode.unwrap_or(false)
+    }
+
+    pub fn presence_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.presence_timeout_seconds.unwrap_or(30).max(1))
+    }
+
+    pub fn sse_keep_alive_interval(&self) -> Option<std::time::Duration> {
+        match self.sse_keep_alive_seconds.unwrap_or(15) {
+            0 => None,
+            secs => Some(std::time::Duration::from_secs(secs)),
+        }
+    }
+
+    pub fn connection_limits(&self) -> crate::connections::ConnectionLimits {
+        let limit = |max: usize| (max > 0).then_some(max);
+        crate::connections::ConnectionLimits {
+            per_user: limit(self.max_connections_per_user.unwrap_or(5)),
+            total: limit(self.max_connections.unwrap_or(10_000)),
+        }
+    }
+
+    pub fn max_concurrent_requests(&self, db_pool_size: u32) -> Option<usize> {
+        match self
+            .max_concurrent_requests
+            .unwrap_or(db_pool_size as usize * 4)
+        {
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    pub fn strict_request_bodies(&self) -> bool {
+        self.strict_request_bodies.unwrap_or(false)
+    }
+
+    pub fn default_locale(&self) -> crate::locale::Locale {
+        self.default_locale
+            .as_deref()
+            .and_then(crate::locale::Locale::parse)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct WorkerSettings {
+    /// The number of queue workers running concurrently in this process. Defaults to 4.
+    pub concurrency: Option<usize>,
+    /// The maximum number of workers processing a given job type at the same time, so a spike
+    /// in one job type doesn't starve the others. Job types without a limit may use every worker.
+    #[serde(default)]
+    pub job_type_limits: HashMap<String, usize>,
+    /// How often expired confirmation and password reset tokens are deleted. Defaults to an hour.
+    pub token_cleanup_interval_seconds: Option<u64>,
+    /// How often due digests are looked for. Defaults to an hour, so digests go out at most an
+    /// hour after the week ends in the user's time zone.
+    pub digest_interval_seconds: Option<u64>,
+    /// How often the outbox relay looks for events that weren't published right after their
+    /// transaction committed. Defaults to 5 seconds. The relay runs next to the server.
+    pub outbox_relay_interval_seconds: Option<u64>,
+    #[serde(default)]
+    pub retention: RetentionSettings,
+}
+
+impl WorkerSettings {
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(4).max(1)
+    }
+
+    pub fn token_cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.token_cleanup_interval_seconds.unwrap_or(3600))
+    }
+
+    pub fn digest_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.digest_interval_seconds.unwrap_or(3600))
+    }
+
+    pub fn outbox_relay_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.outbox_relay_interval_seconds.unwrap_or(5).max(1))
+    }
+}
+
+/// How long the log tables keep their rows, in days. `0` keeps them forever.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RetentionSettings {
+    /// Finished impersonations. Defaults to 365 days.
+    pub security_days: Option<u32>,
+    /// Admin announcements. Defaults to 365 days.
+    pub admin_days: Option<u32>,
+    /// Failed queue jobs. Defaults to 30 days.
+    pub failed_jobs_days: Option<u32>,
+    /// User notifications, read or not. Defaults to 90 days.
+    pub notifications_days: Option<u32>,
+    /// Published outbox events. Defaults to 7 days, pending ones are never purged.
+    pub outbox_days: Option<u32>,
+    /// Deleted recipes, as remembered for syncing clients. Clients that last synced before that
+    /// have to resync in full. Defaults to 30 days.
+    pub tombstones_days: Option<u32>,
+    /// How often the purge runs. Defaults to a day.
+    pub interval_seconds: Option<u64>,
+    /// The number of rows deleted by one statement. Defaults to 1000.
+    pub batch_size: Option<i64>,
+}
+
+impl RetentionSettings {
+    pub fn security_days(&self) -> u32 {
+        self.security_days.unwrap_or(365)
+    }
+
+    pub fn admin_days(&self) -> u32 {
+        self.admin_days.unwrap_or(365)
+    }
+
+    pub fn failed_jobs_days(&self) -> u32 {
+        self.failed_jobs_days.unwrap_or(30)
+    }
+
+    pub fn notifications_days(&self) -> u32 {
+        self.notifications_days.unwrap_or(90)
+    }
+
+    pub fn outbox_days(&self) -> u32 {
+        self.outbox_days.unwrap_or(7)
+    }
+
+    pub fn tombstones_days(&self) -> u32 {
+        self.tombstones_days.unwrap_or(30)
+    }
+
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_seconds.unwrap_or(24 * 3600))
+    }
+
+    pub fn batch_size(&self) -> i64 {
+        self.batch_size.unwrap_or(1000).max(1)
+    }
+}
+
+/// Page sizes for the listing endpoints. Requested sizes are clamped into `1..=max_per_page`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct PaginationSettings {
+    /// The page size when the client doesn't ask for one. Defaults to 20.
+    pub default_per_page: Option<i64>,
+    /// The largest page size a client may ask for. Defaults to 100.
+    pub max_per_page: Option<i64>,
 }
 
-#[derive(Deserialize, Clone)]
+impl PaginationSettings {
+    pub fn max_per_page(&self) -> i64 {
+        self.max_per_page.unwrap_or(100).max(1)
+    }
+
+    pub fn default_per_page(&self) -> i64 {
+        self.default_per_page
+            .unwrap_or(20)
+            .clamp(1, self.max_per_page())
+    }
+}
+
+/// Limits of the recipe search and listing requests, checked before the query reaches the
+/// database.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct SearchSettings {
+    /// The longest `q` accepted, in characters. Defaults to 200.
+    pub max_query_length: Option<usize>,
+    /// The most query parameters accepted, pagination included. Defaults to 20.
+    pub max_filter_values: Option<usize>,
+}
+
+impl SearchSettings {
+    pub fn max_query_length(&self) -> usize {
+        self.max_query_length.unwrap_or(200)
+    }
+
+    pub fn max_filter_values(&self) -> usize {
+        self.max_filter_values.unwrap_or(20)
+    }
+}
+
+/// Fetching other sites' recipe pages for `POST /r/import_url`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RecipeImportSettings {
+    /// How long fetching a page may take in total, redirects included. Defaults to 5000.
+    pub timeout_milliseconds: Option<u64>,
+    /// Larger pages are rejected. Defaults to 2 MiB.
+    pub max_page_bytes: Option<usize>,
+    /// Defaults to 3.
+    pub max_redirects: Option<usize>,
+    /// How many pages a client may import per minute. Defaults to 10.
+    pub requests_per_minute: Option<u32>,
+}
+
+impl RecipeImportSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds.unwrap_or(5000))
+    }
+
+    pub fn max_page_bytes(&self) -> usize {
+        self.max_page_bytes.unwrap_or(2 * 1024 * 1024)
+    }
+
+    pub fn max_redirects(&self) -> usize {
+        self.max_redirects.unwrap_or(3)
+    }
+
+    pub fn requests_per_minute(&self) -> u32 {
+        self.requests_per_minute.unwrap_or(10)
+    }
+}
+
+/// Cross-origin requests are credentialed (the session cookie), so allowed origins are echoed
+/// back exactly, never as `*`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct CorsSettings {
+    /// The origins allowed to make requests, e.g. `https://recipes.example.com`. Defaults to
+    /// `frontend_url`. `*` is not allowed.
+    pub allowed_origins: Option<Vec<String>>,
+    /// How long browsers may cache a preflight response. Defaults to 10 minutes.
+    pub max_age_seconds: Option<u64>,
+}
+
+impl CorsSettings {
+    pub fn allowed_origins(&self, frontend_url: &str) -> Vec<String> {
+        self.allowed_origins
+            .clone()
+            .unwrap_or_else(|| vec![frontend_url.to_owned()])
+    }
+
+    pub fn max_age(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.max_age_seconds.unwrap_or(600))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct AuthSettings {
+    /// Only emails in these domains may sign up, with a password or OAuth. A domain like
+    /// `company.com` only allows itself, `*.company.com` allows its subdomains but not itself.
+    /// Anyone may sign up if it's empty, the default. Existing accounts aren't affected.
+    #[serde(default)]
+    pub allowed_email_domains: Vec<String>,
+    #[serde(default)]
+    pub breached_passwords: BreachedPasswordSettings,
+}
+
+impl AuthSettings {
+    /// Matches case-insensitively.
+    pub fn is_email_domain_allowed(&self, email: &str) -> bool {
+        if self.allowed_email_domains.is_empty() {
+            return true;
+        }
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        self.allowed_email_domains.iter().any(|rule| {
+            let rule = rule.trim().trim_start_matches('@').to_lowercase();
+            match rule.strip_prefix("*.") {
+                Some(parent) => domain
+                    .strip_suffix(parent)
+                    .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+                None => domain == rule,
+            }
+        })
+    }
+}
+
+/// Rejecting new passwords that appeared in known data breaches, with the Have I Been Pwned range
+/// API. Only the first 5 characters of the password's SHA-1 hash are sent. Off by default.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct BreachedPasswordSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Defaults to `https://api.pwnedpasswords.com`.
+    pub api_url: Option<String>,
+    /// Defaults to 2000.
+    pub timeout_milliseconds: Option<u64>,
+    /// What to do with the password when the API can't be reached in time.
+    #[serde(default)]
+    pub on_error: BreachCheckFailure,
+    /// How long the API's answers are cached in Redis. Defaults to 300.
+    pub cache_seconds: Option<u64>,
+}
+
+impl BreachedPasswordSettings {
+    pub fn api_url(&self) -> &str {
+        self.api_url
+            .as_deref()
+            .unwrap_or("https://api.pwnedpasswords.com")
+            .trim_end_matches('/')
+    }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds.unwrap_or(2000))
+    }
+
+    pub fn cache_seconds(&self) -> i64 {
+        self.cache_seconds.unwrap_or(300) as i64
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreachCheckFailure {
+    /// Accept the password, so an outage of the API doesn't block sign-ups.
+    #[default]
+    FailOpen,
+    /// Reject the password, the user has to try again later.
+    FailClosed,
+}
+
+/// The Argon2id cost of new password hashes. Hashes with a lower cost are upgraded the next time
+/// their user logs in.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct PasswordHashingSettings {
+    /// Defaults to 15000.
+    pub memory_kib: Option<u32>,
+    /// Defaults to 2.
+    pub iterations: Option<u32>,
+    /// Defaults to 1.
+    pub parallelism: Option<u32>,
+}
+
+impl PasswordHashingSettings {
+    /// Falls back to the defaults if the configured values are out of Argon2's range.
+    pub fn params(&self) -> argon2::Params {
+        argon2::Params::new(
+            self.memory_kib.unwrap_or(15000),
+            self.iterations.unwrap_or(2),
+            self.parallelism.unwrap_or(1),
+            None,
+        )
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Invalid password hashing parameters, using the defaults.");
+            Self::default().params()
+        })
+    }
+}
+
+/// Exporting traces to an OpenTelemetry collector. Off unless `otlp_endpoint` is set.
+///
+/// Only read at startup, reloading the configuration doesn't change it.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct TelemetrySettings {
+    /// The OTLP/gRPC endpoint of the collector, e.g. `http://localhost:4317`.
+    pub otlp_endpoint: Option<String>,
+    /// Reported as `service.name`. Defaults to `axum1`.
+    pub service_name: Option<String>,
+    /// The fraction of traces that are sampled, between 0 and 1. Defaults to 1. Requests that
+    /// come with a sampling decision in `traceparent` follow it instead.
+    pub sample_ratio: Option<f64>,
+}
+
+impl TelemetrySettings {
+    pub fn service_name(&self) -> &str {
+        self.service_name.as_deref().unwrap_or("axum1")
+    }
+
+    pub fn sample_ratio(&self) -> f64 {
+        self.sample_ratio.unwrap_or(1.0).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct TokenSettings {
+    /// The number of random bytes in a token. Defaults to 32 (256 bits), and may not be less than
+    /// [`MIN_TOKEN_BYTES`].
+    #[serde(default, deserialize_with = "token_bytes")]
+    pub bytes: Option<usize>,
+    pub encoding: Option<TokenEncoding>,
+    /// How long a registration confirmation token is valid. Defaults to 24 hours.
+    pub confirmation_expiry_hours: Option<i32>,
+    /// How long a password reset token is valid. Defaults to 48 hours.
+    pub password_reset_expiry_hours: Option<i32>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEncoding {
+    #[default]
+    Base64Url,
+    Hex,
+}
+
+/// Tokens with less entropy could be guessed.
+pub const MIN_TOKEN_BYTES: usize = 16;
+
+fn token_bytes<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let bytes = Option::<usize>::deserialize(deserializer)?;
+    if bytes.is_some_and(|bytes| bytes < MIN_TOKEN_BYTES) {
+        return Err(serde::de::Error::custom(format!(
+            "`tokens.bytes` must be at least {MIN_TOKEN_BYTES}"
+        )));
+    }
+    Ok(bytes)
+}
+
+impl TokenSettings {
+    pub fn bytes(&self) -> usize {
+        self.bytes.unwrap_or(32)
+    }
+
+    pub fn encoding(&self) -> TokenEncoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    pub fn confirmation_expiry_hours(&self) -> i32 {
+        self.confirmation_expiry_hours.unwrap_or(24).max(1)
+    }
+
+    pub fn password_reset_expiry_hours(&self) -> i32 {
+        self.password_reset_expiry_hours.unwrap_or(48).max(1)
+    }
+}
+
+/// Attributes of the session cookie. Everything falls back to the previous defaults when unset.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct SessionSettings {
+    pub cookie_name: Option<String>,
+    pub same_site: Option<SameSitePolicy>,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub secure: Option<bool>,
+    /// Whether `GET` requests of endpoints that work anonymously are served as anonymous while
+    /// the session store is unreachable, instead of failing with `503`. Defaults to true.
+    pub anonymous_on_store_error: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSitePolicy> for tower_sessions::cookie::SameSite {
+    fn from(policy: SameSitePolicy) -> Self {
+        match policy {
+            SameSitePolicy::Strict => Self::Strict,
+            SameSitePolicy::Lax => Self::Lax,
+            SameSitePolicy::None => Self::None,
+        }
+    }
+}
+
+impl SessionSettings {
+    pub fn anonymous_on_store_error(&self) -> bool {
+        self.anonymous_on_store_error.unwrap_or(true)
+    }
+
+    /// Browsers reject `SameSite=None` cookies without `Secure`, so that combination forces it.
+    /// Otherwise it defaults to being secure in production only.
+    pub fn secure(&self) -> bool {
+        match (self.same_site, self.secure) {
+            (Some(SameSitePolicy::None), secure) => {
+                if secure == Some(false) {
+                    tracing::warn!("`SameSite=None` requires a secure session cookie, ignoring `secure: false`");
+                }
+                true
+            }
+            (_, Some(secure)) => secure,
+            (_, None) => {
+                std::env::var("APP_ENVIRONMENT").unwrap_or_else(|_| String::from("local"))
+                    == "production"
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct SecuritySettings {
+    /// Only ever send cookies over HTTPS. The session cookie is `Secure` then, whatever
+    /// `APP_ENVIRONMENT` is, and responses setting cookies over plain HTTP are replaced by a `403`.
+    /// Off by default.
+    pub require_secure_cookies: Option<bool>,
+    /// Answer the endpoints that look accounts up, like `POST /forget_password_gen`, in about the
+    /// same time whether the account exists or not, so they can't be used to find out. On by
+    /// default.
+    pub constant_time_auth: Option<bool>,
+}
+
+impl SecuritySettings {
+    pub fn require_secure_cookies(&self) -> bool {
+        self.require_secure_cookies.unwrap_or(false)
+    }
+
+    pub fn constant_time_auth(&self) -> bool {
+        self.constant_time_auth.unwrap_or(true)
+    }
+
+    /// Whether the session cookie is `Secure`. Fails if the session settings explicitly turn it
+    /// off despite `require_secure_cookies`, rather than quietly overriding either of them.
+    pub fn secure_session_cookie(&self, session: &SessionSettings) -> anyhow::Result<bool> {
+        if !self.require_secure_cookies() {
+            return Ok(session.secure());
+        }
+        if session.secure == Some(false) {
+            anyhow::bail!(
+                "`security.require_secure_cookies` is set, but `session.secure` is false. \
+                Remove one of them."
+            );
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
 pub struct MeiliConfig {
     pub url: String,
-    pub master_key: String,
+    pub master_key: SecretString,
     pub retry_seconds: Option<u64>,
     pub max_retries: Option<usize>,
     pub indexing_interval_seconds: Option<u64>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct EmailClientSettings {
     pub base_url: String,
+    #[serde(alias = "from_address")]
     pub sender_email: String,
+    /// The display name of the sender. Defaults to "Recipe App".
+    pub from_name: Option<String>,
+    pub reply_to: Option<String>,
+    /// The domain verified with the email provider. Sending from other domains hurts deliverability,
+    /// so that's warned about at startup.
+    pub sending_domain: Option<String>,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    /// An address that accepts email without delivering it, for the admin self-check. The email
+    /// check is skipped without one.
+    pub selfcheck_sink: Option<String>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct OAuth {
     pub discord: OAuthCredentials,
     pub google: OAuthCredentials,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct OAuthCredentials {
     pub client_id: String,
-    pub client_secret: String,
+    pub client_secret: SecretString,
     pub auth_url: String,
     pub token_url: String,
     pub redirect_url: String,
@@ -74,15 +778,42 @@ impl EmailClientSettings {
         Email::parse(self.sender_email.clone())
     }
 
+    pub fn from_name(&self) -> &str {
+        self.from_name.as_deref().unwrap_or("Recipe App")
+    }
+
+    pub fn reply_to(&self) -> Result<Option<Email>, ApiError> {
+        self.reply_to.clone().map(Email::parse).transpose()
+    }
+
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
+        let reply_to = self.reply_to().expect("Invalid reply-to email address.");
+
+        if let Some(sending_domain) = &self.sending_domain {
+            let sender_domain = sender_email.as_ref().rsplit('@').next().unwrap_or_default();
+            if !sender_domain.eq_ignore_ascii_case(sending_domain) {
+                tracing::warn!(
+                    %sender_domain,
+                    %sending_domain,
+                    "the sender address is not on the sending domain, emails may end up in spam"
+                );
+            }
+        }
+
+        let from_name = self.from_name().to_owned();
         let timeout = self.timeout();
-        EmailClient::new(
+        let mut client = EmailClient::new(
             self.base_url,
             sender_email.into(),
             self.authorization_token,
             timeout,
         )
+        .with_from_name(from_name);
+        if let Some(reply_to) = reply_to {
+            client = client.with_reply_to(reply_to);
+        }
+        client
     }
 
     pub fn timeout(&self) -> std::time::Duration {
@@ -90,7 +821,7 @@ impl EmailClientSettings {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct DatabaseSettings {
     pub username: String,
     pub password: SecretString,
@@ -98,13 +829,34 @@ pub struct DatabaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// Statements running longer than this are logged at `warn`. Defaults to 500 ms.
+    pub slow_query_threshold_ms: Option<u64>,
+    /// Statements running longer than this are cancelled by Postgres. Defaults to 30 seconds,
+    /// 0 disables it.
+    pub statement_timeout_ms: Option<u64>,
+    /// Requests executing more statements than this are logged at `warn`. Defaults to 30.
+    pub statements_per_request_warning: Option<usize>,
+    /// A read replica for the read-heavy endpoints. They use the primary without one.
+    pub replica: Option<ReplicaSettings>,
 }
 
-#[derive(Deserialize, Clone)]
+/// A streaming replica of the primary, so it's reached with the same credentials and database
+/// name.
+///
+/// It lags behind the primary. A client reading from it right after a write may not see that
+/// write yet, so only the endpoints that can live with that use it, see
+/// [`crate::extractors::ReadDatabaseConnection`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct ReplicaSettings {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Deserialize, Clone, Debug)]
 pub struct RedisSettings {
     pub host: String,
     pub port: u16,
-    pub secret_key: String,
+    pub secret_key: SecretString,
     pub username: Option<String>,
     pub password: Option<SecretString>,
 }
@@ -137,7 +889,34 @@ impl DatabaseSettings {
 
     pub fn with_db(&self) -> PgConnectOptions {
         let options = self.without_db().database(&self.database_name);
-        options.log_statements(tracing::log::LevelFilter::Trace)
+        // SQLx logs slow statements under the `sqlx::query` target inside the current span, so
+        // the handler or task that issued it shows up too. Only the SQL text and the elapsed
+        // time are logged, bound parameters (passwords, tokens and the like) never are.
+        options
+            .log_statements(tracing::log::LevelFilter::Trace)
+            .log_slow_statements(tracing::log::LevelFilter::Warn, self.slow_query_threshold())
+    }
+
+    /// Like [`Self::with_db`], but for the replica, if there's one.
+    pub fn replica_with_db(&self) -> Option<PgConnectOptions> {
+        self.replica
+            .as_ref()
+            .map(|replica| self.with_db().host(&replica.host).port(replica.port))
+    }
+
+    pub fn slow_query_threshold(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.slow_query_threshold_ms.unwrap_or(500))
+    }
+
+    pub fn statements_per_request_warning(&self) -> usize {
+        self.statements_per_request_warning.unwrap_or(30)
+    }
+
+    pub fn statement_timeout(&self) -> Option<std::time::Duration> {
+        match self.statement_timeout_ms.unwrap_or(30_000) {
+            0 => None,
+            ms => Some(std::time::Duration::from_millis(ms)),
+        }
     }
 }
 
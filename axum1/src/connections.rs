@@ -0,0 +1,94 @@
+//! The live connections open on this instance, the SSE streams and WebSockets, counted per user
+//! or guest, see `application_settings.max_connections_per_user`. Each one holds a
+//! [`ConnectionGuard`], which gives its slot back when the connection is dropped, however it ended.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// How many connections may be open at once, `None` for no limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLimits {
+    pub per_user: Option<usize>,
+    pub total: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: usize,
+    /// Only the users and guests with a connection open, so it doesn't grow with every visitor.
+    per_user: HashMap<uuid::Uuid, usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Connections {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Connections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a new connection for the user or guest, unless that would exceed the limits.
+    /// Anonymous clients without a guest id only count toward the total.
+    pub fn try_open(
+        &self,
+        user_id: Option<uuid::Uuid>,
+        limits: ConnectionLimits,
+    ) -> Option<ConnectionGuard> {
+        let mut counts = self.counts.lock().unwrap();
+        if limits.total.is_some_and(|max| counts.total >= max) {
+            return None;
+        }
+        if let Some(user_id) = user_id {
+            let open = counts.per_user.get(&user_id).copied().unwrap_or(0);
+            if limits.per_user.is_some_and(|max| open >= max) {
+                return None;
+            }
+            counts.per_user.insert(user_id, open + 1);
+        }
+        counts.total += 1;
+        Some(ConnectionGuard {
+            counts: Arc::clone(&self.counts),
+            user_id,
+        })
+    }
+
+    /// The connections open by the user or guest.
+    pub fn open_by(&self, user_id: uuid::Uuid) -> usize {
+        let counts = self.counts.lock().unwrap();
+        counts.per_user.get(&user_id).copied().unwrap_or(0)
+    }
+
+    /// The connections open in total.
+    pub fn open(&self) -> usize {
+        self.counts.lock().unwrap().total
+    }
+}
+
+/// A counted connection. Keep it alongside the connection, e.g. in its stream or task.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    counts: Arc<Mutex<Counts>>,
+    user_id: Option<uuid::Uuid>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        // Don't panic again while unwinding from a panic that poisoned the lock.
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        counts.total -= 1;
+        if let Some(user_id) = self.user_id {
+            if let Some(open) = counts.per_user.get_mut(&user_id) {
+                *open -= 1;
+                if *open == 0 {
+                    counts.per_user.remove(&user_id);
+                }
+            }
+        }
+    }
+}
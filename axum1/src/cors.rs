@@ -0,0 +1,34 @@
+use axum::http::HeaderValue;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use crate::config::CorsSettings;
+
+/// Allows credentialed requests from the configured origins.
+///
+/// The `Origin` of a request is echoed back in `Access-Control-Allow-Origin` if it's in the
+/// allowlist, other origins get no CORS headers at all, so the browser blocks them. A wildcard
+/// can't be combined with credentials, so `*` entries are skipped.
+pub fn cors_layer(settings: &CorsSettings, frontend_url: &str) -> CorsLayer {
+    let origins: Vec<HeaderValue> = settings
+        .allowed_origins(frontend_url)
+        .iter()
+        // Origins never have a trailing slash, but URLs in the configuration often do.
+        .map(|origin| origin.trim().trim_end_matches('/'))
+        .filter_map(|origin| {
+            if origin == "*" {
+                tracing::warn!("Ignoring the `*` CORS origin, credentialed requests can't use it.");
+                return None;
+            }
+            HeaderValue::from_str(origin)
+                .inspect_err(|_| tracing::warn!(origin, "Ignoring an invalid CORS origin."))
+                .ok()
+        })
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .max_age(settings.max_age())
+}
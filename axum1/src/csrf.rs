@@ -0,0 +1,108 @@
+//! Double-submit CSRF protection for cookie-authenticated requests.
+//!
+//! The client fetches a token with `GET /csrf`, which is stored in its session, and sends it back
+//! in the `X-CSRF-Token` header on every unsafe request. A cross-site form can make the browser
+//! send the session cookie, but it can't read the token or set the header.
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tower_sessions::Session;
+
+use crate::{
+    config::TokenSettings,
+    error::ApiError,
+    token::{generate_token, verify_token},
+    versioning::ApiVersion,
+};
+
+pub const CSRF_HEADER: &str = "x-csrf-token";
+pub const API_KEY_HEADER: &str = "x-api-key";
+const CSRF_SESSION_KEY: &str = "csrf_token";
+
+/// Paths authenticated by a token of their own in the request, instead of the session. A
+/// cross-site form can't know that token either, and they're posted to from outside the frontend,
+/// like from the page the digest unsubscribe link leads to.
+const TOKEN_AUTHENTICATED_PATHS: [&str; 1] = ["/digest/unsubscribe"];
+
+#[derive(Debug, serde::Serialize)]
+pub struct CsrfToken {
+    token: String,
+}
+
+/// Returns the token of the session, creating one if needed.
+pub async fn csrf_token(session: Session) -> Result<Json<CsrfToken>, ApiError> {
+    let token = match session.get::<String>(CSRF_SESSION_KEY).await? {
+        Some(token) => token,
+        None => {
+            let token = generate_token(&TokenSettings::default());
+            session.insert(CSRF_SESSION_KEY, &token).await?;
+            token
+        }
+    };
+    Ok(Json(CsrfToken { token }))
+}
+
+/// Invalidates the token of the session, so one captured before a login can't be replayed
+/// after it. The client has to fetch a new one.
+pub async fn rotate_csrf_token(session: &Session) -> Result<(), ApiError> {
+    session.remove::<String>(CSRF_SESSION_KEY).await?;
+    Ok(())
+}
+
+/// Rejects unsafe requests without the session's token in the `X-CSRF-Token` header with `403`.
+///
+/// Safe methods are exempt, and so are the paths authenticated by a token of their own, and
+/// requests authenticated by a header without a session cookie. A cross-site request may carry
+/// such a header as well, but then the browser attaches the cookie too, so it's checked like any
+/// other. With `enabled` unset (e.g. when the session cookie is `SameSite=Strict`), every request
+/// is let through.
+pub async fn csrf_protect(
+    State(enabled): State<bool>,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !enabled || is_exempt(&request, &session) {
+        return next.run(request).await;
+    }
+
+    let provided = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|token| token.to_str().ok());
+    let expected = match session.get::<String>(CSRF_SESSION_KEY).await {
+        Ok(expected) => expected,
+        Err(e) => return ApiError::from(e).into_response(),
+    };
+
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if verify_token(&expected, provided) => {
+            next.run(request).await
+        }
+        _ => ApiError::Forbidden.into_response(),
+    }
+}
+
+fn is_exempt(request: &Request, session: &Session) -> bool {
+    let safe_method = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    let headers = request.headers();
+    let header_auth = headers.contains_key(AUTHORIZATION) || headers.contains_key(API_KEY_HEADER);
+    safe_method
+        || is_token_authenticated(request.uri().path())
+        || (header_auth && session.id().is_none())
+}
+
+fn is_token_authenticated(path: &str) -> bool {
+    let unversioned = ApiVersion::ALL
+        .into_iter()
+        .find_map(|version| path.strip_prefix(&version.prefix()))
+        .unwrap_or(path);
+    TOKEN_AUTHENTICATED_PATHS.contains(&unversioned)
+}
@@ -1,7 +1,12 @@
 use reqwest::Client;
 use secrecy::{ExposeSecret, SecretString};
 
-use crate::{config::EmailClientSettings, error::ApiError};
+use crate::{
+    config::{EmailClientSettings, Settings},
+    error::ApiError,
+    locale::Locale,
+    queue::digest::DigestRecipe,
+};
 
 #[derive(Debug, Clone)]
 pub struct Email(String);
@@ -39,6 +44,8 @@ pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: String,
+    from_name: Option<String>,
+    reply_to: Option<Email>,
     authorization_token: SecretString,
 }
 
@@ -54,16 +61,40 @@ impl EmailClient {
             http_client,
             base_url,
             sender,
+            from_name: None,
+            reply_to: None,
             authorization_token,
         }
     }
 
+    /// Shown as the sender next to the address.
+    pub fn with_from_name(mut self, from_name: impl Into<String>) -> Self {
+        self.from_name = Some(from_name.into());
+        self
+    }
+
+    /// Where replies go, instead of the sender address.
+    pub fn with_reply_to(mut self, reply_to: Email) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
     pub fn from_config(config: EmailClientSettings) -> Self {
-        let sender = config.sender().unwrap().into();
-        let timeout = std::time::Duration::from_millis(config.timeout_milliseconds);
-        Self::new(config.base_url, sender, config.authorization_token, timeout)
+        config.client()
+    }
+
+    /// The `From` header, with the display name if there's one.
+    pub fn from_header(&self) -> String {
+        match &self.from_name {
+            Some(name) => {
+                let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+                format!("\"{name}\" <{}>", self.sender)
+            }
+            None => self.sender.clone(),
+        }
     }
 
+    /// Convenience wrapper around [`EmailClient::send_message`] for the common single-recipient case.
     pub async fn send_mail(
         &self,
         recipient: Email,
@@ -71,13 +102,22 @@ impl EmailClient {
         html_content: &str,
         text_content: &str,
     ) -> Result<(), reqwest::Error> {
+        self.send_message(Message::new(recipient, subject, html_content, text_content))
+            .await
+    }
+
+    pub async fn send_message(&self, message: Message) -> Result<(), reqwest::Error> {
         let url = format!("{}/email", self.base_url);
+        let from = self.from_header();
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
+            from: &from,
+            reply_to: self.reply_to.as_ref().map(AsRef::as_ref),
+            to: join_addresses(&message.to),
+            cc: (!message.cc.is_empty()).then(|| join_addresses(&message.cc)),
+            bcc: (!message.bcc.is_empty()).then(|| join_addresses(&message.bcc)),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
         };
         self.http_client
             .post(&url)
@@ -93,12 +133,238 @@ impl EmailClient {
     }
 }
 
+/// An email with possibly multiple recipients.
+///
+/// Every address is an already validated [`Email`], so use `Email::parse` to build them.
+#[derive(Debug, Clone)]
+pub struct Message {
+    to: Vec<Email>,
+    cc: Vec<Email>,
+    bcc: Vec<Email>,
+    subject: String,
+    html_body: String,
+    text_body: String,
+}
+
+impl Message {
+    pub fn new(
+        to: Email,
+        subject: impl Into<String>,
+        html_body: impl Into<String>,
+        text_body: impl Into<String>,
+    ) -> Self {
+        Self {
+            to: vec![to],
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+        }
+    }
+
+    pub fn to(mut self, recipient: Email) -> Self {
+        self.to.push(recipient);
+        self
+    }
+
+    pub fn cc(mut self, recipient: Email) -> Self {
+        self.cc.push(recipient);
+        self
+    }
+
+    pub fn bcc(mut self, recipient: Email) -> Self {
+        self.bcc.push(recipient);
+        self
+    }
+}
+
+// Postmark accepts multiple recipients as a comma separated list.
+fn join_addresses(addresses: &[Email]) -> String {
+    addresses
+        .iter()
+        .map(AsRef::as_ref)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
     from: &'a str,
-    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bcc: Option<String>,
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
 }
+
+/// Where the links in emails lead.
+#[derive(Debug, Clone)]
+pub struct EmailLinks {
+    /// The frontend, for the pages users act on, like confirming their registration.
+    frontend_url: String,
+    /// The API, for what works without the frontend, like unsubscribing from digests.
+    api_url: String,
+}
+
+impl EmailLinks {
+    pub fn new(frontend_url: &str, api_url: &str) -> Self {
+        Self {
+            frontend_url: frontend_url.trim_end_matches('/').to_owned(),
+            api_url: api_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            &settings.frontend_url,
+            &settings.application_settings.public_url(),
+        )
+    }
+}
+
+/// The registration confirmation email, in the recipient's language.
+pub fn confirmation_message(to: Email, locale: Locale, links: &EmailLinks, token: &str) -> Message {
+    let link = format!(
+        "{}/confirm?token={token}&lang={}",
+        links.frontend_url,
+        locale.as_str()
+    );
+    let href = html_escape(&link);
+    match locale {
+        Locale::En => Message::new(
+            to,
+            "Recipe App confirm registration",
+            format!("Visit <a href=\"{href}\">the website</a> to confirm your registration."),
+            format!("Visit {link} to confirm your registration."),
+        ),
+        Locale::Hu => Message::new(
+            to,
+            "Recipe App - regisztráció megerősítése",
+            format!(
+                "A regisztráció megerősítéséhez látogass el <a href=\"{href}\">az oldalra</a>."
+            ),
+            format!("A regisztráció megerősítéséhez látogass el ide: {link}"),
+        ),
+    }
+}
+
+/// The password reset email, in the recipient's language.
+pub fn password_reset_message(
+    to: Email,
+    locale: Locale,
+    links: &EmailLinks,
+    token: &str,
+) -> Message {
+    let link = format!(
+        "{}/forget_password?token={token}&lang={}",
+        links.frontend_url,
+        locale.as_str()
+    );
+    match locale {
+        Locale::En => Message::new(
+            to,
+            "Recipe App - Your password reset",
+            format!("Visit {link}"),
+            format!("Visit {link}"),
+        ),
+        Locale::Hu => Message::new(
+            to,
+            "Recipe App - Jelszó visszaállítása",
+            format!("Új jelszót itt állíthatsz be: {link}"),
+            format!("Új jelszót itt állíthatsz be: {link}"),
+        ),
+    }
+}
+
+/// Tells the user their password was changed and every other session was logged out.
+pub fn password_changed_message(to: Email, locale: Locale) -> Message {
+    match locale {
+        Locale::En => Message::new(
+            to,
+            "Recipe App - Your password was changed",
+            "Your password was changed and you were logged out everywhere else. \
+            If this wasn't you, reset your password immediately.",
+            "Your password was changed and you were logged out everywhere else. \
+            If this wasn't you, reset your password immediately.",
+        ),
+        Locale::Hu => Message::new(
+            to,
+            "Recipe App - Megváltozott a jelszavad",
+            "A jelszavad megváltozott, és minden más eszközön kijelentkeztettünk. \
+            Ha nem te voltál, azonnal állítsd vissza a jelszavad.",
+            "A jelszavad megváltozott, és minden más eszközön kijelentkeztettünk. \
+            Ha nem te voltál, azonnal állítsd vissza a jelszavad.",
+        ),
+    }
+}
+
+/// The digest of new recipes, with a link that unsubscribes without logging in.
+pub fn digest_message(
+    to: Email,
+    locale: Locale,
+    links: &EmailLinks,
+    recipes: &[DigestRecipe],
+    unsubscribe_token: &str,
+) -> Message {
+    let unsubscribe_link = format!(
+        "{}/digest/unsubscribe?token={unsubscribe_token}&lang={}",
+        links.api_url,
+        locale.as_str()
+    );
+    let unsubscribe_href = html_escape(&unsubscribe_link);
+    let html_list: String = recipes
+        .iter()
+        .map(|r| {
+            format!(
+                "<li><b>{}</b> - {}</li>",
+                html_escape(&r.name),
+                html_escape(&r.description)
+            )
+        })
+        .collect();
+    let text_list: String = recipes
+        .iter()
+        .map(|r| format!("- {}: {}\n", r.name, r.description))
+        .collect();
+    match locale {
+        Locale::En => Message::new(
+            to,
+            "Recipe App - New recipes this week",
+            format!(
+                "New recipes you might like:<ul>{html_list}</ul>\
+                <a href=\"{unsubscribe_href}\">Unsubscribe</a> from these emails."
+            ),
+            format!(
+                "New recipes you might like:\n{text_list}\n\
+                Unsubscribe from these emails: {unsubscribe_link}"
+            ),
+        ),
+        Locale::Hu => Message::new(
+            to,
+            "Recipe App - A hét új receptjei",
+            format!(
+                "Új receptek, amik érdekelhetnek:<ul>{html_list}</ul>\
+                <a href=\"{unsubscribe_href}\">Leiratkozás</a> ezekről a levelekről."
+            ),
+            format!(
+                "Új receptek, amik érdekelhetnek:\n{text_list}\n\
+                Leiratkozás ezekről a levelekről: {unsubscribe_link}"
+            ),
+        ),
+    }
+}
+
+// Recipe names and descriptions are user input, and links have `&`s in their query.
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,6 +1,10 @@
-use axum::http::header::WWW_AUTHENTICATE;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::body::Body;
+use axum::extract::rejection::{JsonRejection, RawFormRejection};
+use axum::extract::{Request, State};
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use sqlx::error::DatabaseError;
 use std::borrow::Cow;
@@ -28,6 +32,12 @@ pub enum ApiError {
     #[error("user may not perform that action")]
     Forbidden,
 
+    /// Return `403 Forbidden`
+    ///
+    /// The user may use the endpoint, but not change this particular field.
+    #[error("user may not change the `{field}` field")]
+    FieldForbidden { field: &'static str },
+
     /// Return `404 Not Found`
     #[error("request path not found")]
     NotFound,
@@ -35,6 +45,49 @@ pub enum ApiError {
     #[error("conflict")]
     Conflict,
 
+    /// Return `409 Conflict`
+    ///
+    /// The organization already has a recipe with that name. The body has the first free name
+    /// like it under `suggestion`, so the client can offer it instead.
+    #[error("a recipe with that name already exists")]
+    RecipeNameTaken { suggestion: String },
+
+    /// Return `429 Too Many Requests`
+    #[error("too many requests")]
+    TooManyRequests,
+
+    /// Return `410 Gone`
+    ///
+    /// The token was valid once, so the client should offer to send a new one.
+    #[error("the token has expired, please request a new one")]
+    TokenExpired,
+
+    /// Return `403 Forbidden`
+    ///
+    /// Unlike `Forbidden`, the client should offer to send a new confirmation email.
+    #[error("the email address is not confirmed yet")]
+    EmailUnconfirmed,
+
+    /// Return `403 Forbidden`
+    ///
+    /// The response would have set a cookie over plain HTTP, while secure cookies are required.
+    #[error("cookies are only sent over HTTPS")]
+    HttpsRequired,
+
+    /// Return `422 Unprocessable Entity`
+    ///
+    /// The new password appeared in a known data breach. Rendered like `UnprocessableEntity`, with
+    /// the reason under the `password` key, but with its own code.
+    #[error("the password has appeared in a data breach")]
+    PasswordBreached,
+
+    /// Return `422 Unprocessable Entity`
+    ///
+    /// A search request over one of the configured limits. Rendered like `UnprocessableEntity`,
+    /// with the reason under `key`, but with its own code.
+    #[error("the search request exceeds a limit")]
+    SearchLimitExceeded { key: &'static str, reason: String },
+
     /// Return `422 Unprocessable Entity`
     ///
     /// This also serializes the `errors` map to JSON.
@@ -80,8 +133,32 @@ pub enum ApiError {
     #[error("an internal server error occurred")]
     Reqwest(#[from] reqwest::Error),
 
+    /// Return `503 Service Unavailable`
+    ///
+    /// The session store couldn't be reached, so it's unknown who is signed in. Converted from
+    /// `tower_sessions::session::Error` like `Session`, the message is logged instead of returned.
+    #[error("the session store is unavailable, please try again later")]
+    SessionStoreUnavailable(tower_sessions::session_store::Error),
+
     #[error("an internal server error occurred")]
-    Session(#[from] tower_sessions::session::Error),
+    Session(tower_sessions::session::Error),
+
+    /// Return `503 Service Unavailable` with `Retry-After`
+    ///
+    /// Too many requests are in flight already, see `crate::load_shed`.
+    #[error("the server is overloaded, please try again later")]
+    Overloaded,
+}
+
+/// Failing to reach the session store is temporary, so it's told apart from the other session
+/// errors.
+impl From<tower_sessions::session::Error> for ApiError {
+    fn from(e: tower_sessions::session::Error) -> Self {
+        match e {
+            tower_sessions::session::Error::Store(e) => Self::SessionStoreUnavailable(e),
+            e => Self::Session(e),
+        }
+    }
 }
 
 impl ApiError {
@@ -125,14 +202,82 @@ impl ApiError {
         Self::UnprocessableEntity { errors }
     }
 
+    /// A copy of the error for the waiters of a shared computation, see
+    /// [`crate::utils::SingleFlight::try_run`]. Client errors are copied as they are, the internal
+    /// ones keep their status and message, but not their source.
+    pub fn shared_copy(&self) -> Self {
+        match self {
+            Self::BadRequest => Self::BadRequest,
+            Self::Unauthorized => Self::Unauthorized,
+            Self::Forbidden => Self::Forbidden,
+            Self::FieldForbidden { field } => Self::FieldForbidden { field },
+            Self::NotFound => Self::NotFound,
+            Self::Conflict => Self::Conflict,
+            Self::RecipeNameTaken { suggestion } => Self::RecipeNameTaken {
+                suggestion: suggestion.clone(),
+            },
+            Self::TooManyRequests => Self::TooManyRequests,
+            Self::TokenExpired => Self::TokenExpired,
+            Self::EmailUnconfirmed => Self::EmailUnconfirmed,
+            Self::HttpsRequired => Self::HttpsRequired,
+            Self::PasswordBreached => Self::PasswordBreached,
+            Self::SearchLimitExceeded { key, reason } => Self::SearchLimitExceeded {
+                key,
+                reason: reason.clone(),
+            },
+            Self::UnprocessableEntity { errors } => Self::UnprocessableEntity {
+                errors: errors.clone(),
+            },
+            Self::Overloaded => Self::Overloaded,
+            Self::SessionStoreUnavailable(e) => Self::SessionStoreUnavailable(
+                tower_sessions::session_store::Error::Backend(e.to_string()),
+            ),
+            Self::Sqlx(_) | Self::Anyhow(_) | Self::Reqwest(_) | Self::Session(_) => {
+                Self::Anyhow(anyhow::anyhow!("shared computation failed: {self:?}"))
+            }
+        }
+    }
+
+    /// A stable, machine readable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::FieldForbidden { .. } => "field_forbidden",
+            Self::NotFound => "not_found",
+            Self::Conflict => "conflict",
+            Self::RecipeNameTaken { .. } => "recipe_name_taken",
+            Self::TooManyRequests => "too_many_requests",
+            Self::TokenExpired => "token_expired",
+            Self::EmailUnconfirmed => "email_unconfirmed",
+            Self::HttpsRequired => "https_required",
+            Self::UnprocessableEntity { .. } => "unprocessable_entity",
+            Self::PasswordBreached => "password_breached",
+            Self::SessionStoreUnavailable(_) => "session_store_unavailable",
+            Self::Overloaded => "overloaded",
+            Self::SearchLimitExceeded { .. } => "search_limit_exceeded",
+            Self::Sqlx(_) | Self::Anyhow(_) | Self::Reqwest(_) | Self::Session(_) => {
+                "internal_server_error"
+            }
+        }
+    }
+
     fn status_code(&self) -> StatusCode {
         match self {
             Self::BadRequest => StatusCode::BAD_REQUEST,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::FieldForbidden { .. } => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
-            Self::Conflict => StatusCode::CONFLICT,
-            Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Conflict | Self::RecipeNameTaken { .. } => StatusCode::CONFLICT,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Self::TokenExpired => StatusCode::GONE,
+            Self::EmailUnconfirmed | Self::HttpsRequired => StatusCode::FORBIDDEN,
+            Self::UnprocessableEntity { .. }
+            | Self::PasswordBreached
+            | Self::SearchLimitExceeded { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::SessionStoreUnavailable(_) | Self::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
             Self::Sqlx(_) | Self::Anyhow(_) | Self::Reqwest(_) | Self::Session(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
@@ -140,14 +285,108 @@ impl ApiError {
     }
 }
 
+/// Bodies that aren't valid JSON, or don't match the expected shape, are reported under the
+/// `body` key with serde's message, e.g. which field is missing. Failing to read the body at all
+/// is a plain `400 Bad Request`.
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(_)
+            | JsonRejection::JsonSyntaxError(_)
+            | JsonRejection::MissingJsonContentType(_) => {
+                Self::unprocessable_entity([("body", rejection.body_text())])
+            }
+            _ => Self::BadRequest,
+        }
+    }
+}
+
+/// Like the `JsonRejection` conversion, but for urlencoded bodies.
+impl From<axum_extra::extract::FormRejection> for ApiError {
+    fn from(rejection: axum_extra::extract::FormRejection) -> Self {
+        use axum_extra::extract::FormRejection;
+
+        match rejection {
+            FormRejection::FailedToDeserializeForm(_)
+            | FormRejection::RawFormRejection(RawFormRejection::InvalidFormContentType(_)) => {
+                Self::unprocessable_entity([("body", rejection.to_string())])
+            }
+            _ => Self::BadRequest,
+        }
+    }
+}
+
 /// Axum allows you to return `Result` from handler functions, but the error type
 /// also must be some sort of response type.
 ///
 /// By default, the generated `Display` impl is used to return a plaintext error message
 /// to the client.
 impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
+    fn into_response(self) -> Response {
+        let problem = Problem {
+            code: self.code(),
+            detail: self.to_string(),
+            errors: match &self {
+                Self::UnprocessableEntity { errors } => Some(errors.clone()),
+                Self::PasswordBreached => Some(Self::password_breached_errors()),
+                Self::SearchLimitExceeded { key, reason } => Some(HashMap::from([(
+                    (*key).into(),
+                    vec![reason.clone().into()],
+                )])),
+                Self::RecipeNameTaken { .. } => Some(Self::recipe_name_taken_errors()),
+                _ => None,
+            },
+            suggestion: match &self {
+                Self::RecipeNameTaken { suggestion } => Some(suggestion.clone()),
+                _ => None,
+            },
+        };
+        let mut response = self.render();
+        // Attached so that `problem_details` can re-render the error if the client asks for it.
+        response.extensions_mut().insert(problem);
+        response
+    }
+}
+
+impl ApiError {
+    fn password_breached_errors() -> HashMap<Cow<'static, str>, Vec<Cow<'static, str>>> {
+        HashMap::from([(
+            "password".into(),
+            vec!["has appeared in a data breach, please choose another one".into()],
+        )])
+    }
+
+    fn recipe_name_taken_errors() -> HashMap<Cow<'static, str>, Vec<Cow<'static, str>>> {
+        HashMap::from([("name".into(), vec!["is already taken".into()])])
+    }
+
+    fn render(self) -> Response {
         match self {
+            Self::PasswordBreached => {
+                return Self::UnprocessableEntity {
+                    errors: Self::password_breached_errors(),
+                }
+                .render();
+            }
+            Self::SearchLimitExceeded { key, reason } => {
+                return Self::unprocessable_entity([(key, reason)]).render();
+            }
+            Self::RecipeNameTaken { suggestion } => {
+                #[derive(serde::Serialize)]
+                struct NameTaken {
+                    errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
+                    suggestion: String,
+                }
+
+                return (
+                    StatusCode::CONFLICT,
+                    Json(NameTaken {
+                        errors: Self::recipe_name_taken_errors(),
+                        suggestion,
+                    }),
+                )
+                    .into_response();
+            }
             Self::UnprocessableEntity { errors } => {
                 #[derive(serde::Serialize)]
                 struct Errors {
@@ -156,6 +395,14 @@ impl IntoResponse for ApiError {
 
                 return (StatusCode::UNPROCESSABLE_ENTITY, Json(Errors { errors })).into_response();
             }
+            Self::Overloaded => {
+                return (
+                    self.status_code(),
+                    [(RETRY_AFTER, RETRY_OVERLOADED_AFTER_SECONDS.to_string())],
+                    self.to_string(),
+                )
+                    .into_response();
+            }
             Self::Unauthorized => {
                 return (
                     self.status_code(),
@@ -182,6 +429,9 @@ impl IntoResponse for ApiError {
             Self::Session(ref e) => {
                 tracing::error!("Session error: {:?}", e);
             }
+            Self::SessionStoreUnavailable(ref e) => {
+                tracing::error!("Session store error: {:?}", e);
+            }
 
             // Other errors get mapped normally.
             _ => (),
@@ -191,6 +441,91 @@ impl IntoResponse for ApiError {
     }
 }
 
+/// The parts of an `ApiError` needed to build an RFC 7807 problem document.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    code: &'static str,
+    detail: String,
+    errors: Option<HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>>,
+    suggestion: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ProblemDocument<'a> {
+    #[serde(rename = "type")]
+    type_uri: String,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    instance: String,
+    // Extension members
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<&'a HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<&'a str>,
+}
+
+/// Overloads pass quickly, so clients are asked to come back soon.
+pub const RETRY_OVERLOADED_AFTER_SECONDS: u64 = 1;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+pub const REQUEST_ID: &str = "x-request-id";
+
+/// Middleware that renders `ApiError`s as `application/problem+json` (RFC 7807) documents
+/// when the client asks for it in the `Accept` header, or when `always` is set in the config.
+///
+/// Otherwise the responses are passed through unchanged.
+pub async fn problem_details(State(always): State<bool>, request: Request, next: Next) -> Response {
+    let wants_problem = always
+        || request
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains(PROBLEM_JSON));
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let response = next.run(request).await;
+
+    let Some(problem) = response
+        .extensions()
+        .get::<Problem>()
+        .filter(|_| wants_problem)
+        .cloned()
+    else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    let document = ProblemDocument {
+        type_uri: format!("/problems/{}", problem.code),
+        title: parts.status.canonical_reason().unwrap_or_default(),
+        status: parts.status.as_u16(),
+        detail: &problem.detail,
+        instance: format!("urn:uuid:{request_id}"),
+        code: problem.code,
+        errors: problem.errors.as_ref(),
+        suggestion: problem.suggestion.as_deref(),
+    };
+    let Ok(body) = serde_json::to_vec(&document) else {
+        return (parts.status, problem.detail).into_response();
+    };
+
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    parts.headers.remove(CONTENT_LENGTH);
+    if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+        parts.headers.insert(REQUEST_ID, request_id);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
 /// A little helper trait for more easily converting database constraint errors into API errors.
 ///
 /// ```rust,ignore
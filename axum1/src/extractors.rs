@@ -1,15 +1,69 @@
-use std::{convert::Infallible, ops::Deref};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    ops::{Deref, DerefMut},
+};
 
-use crate::{error::ApiError, state::AppState};
+use crate::{
+    error::ApiError, personalized::mark_personalized, sandbox::SandboxTransaction, state::AppState,
+};
+use anyhow::Context;
 use axum::{
     async_trait,
-    extract::{FromRef, FromRequestParts},
-    http::request::Parts,
+    extract::{ConnectInfo, FromRef, FromRequest, FromRequestParts, Request},
+    http::{request::Parts, Extensions, HeaderMap},
+    response::{IntoResponse, Response},
 };
-use sqlx::{pool, Postgres};
+use futures::future::BoxFuture;
+use ipnet::IpNet;
+use meilisearch_sdk::client::Client;
+use sqlx::{pool, Acquire, PgConnection, Postgres, Transaction};
 use tower_sessions::Session;
 
-pub struct DatabaseConnection(pub pool::PoolConnection<Postgres>);
+/// A connection from the pool, or the transaction of a sandboxed request, see
+/// [`crate::sandbox`]. Either way, it's used as a `PgConnection`.
+pub enum DbConnection {
+    // Boxed, it would make every `DbConnection` as large as a whole `PgConnection` otherwise.
+    Pooled(Box<pool::PoolConnection<Postgres>>),
+    Sandboxed(tokio::sync::OwnedMutexGuard<Transaction<'static, Postgres>>),
+}
+
+impl Deref for DbConnection {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Pooled(conn) => conn,
+            Self::Sandboxed(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for DbConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Pooled(conn) => conn,
+            Self::Sandboxed(tx) => tx,
+        }
+    }
+}
+
+// So `conn.begin()` keeps working like it did on a `PoolConnection`. In the sandbox, that's a
+// savepoint in the request's transaction.
+impl<'c> Acquire<'c> for &'c mut DbConnection {
+    type Database = Postgres;
+    type Connection = &'c mut PgConnection;
+
+    fn acquire(self) -> BoxFuture<'c, Result<Self::Connection, sqlx::Error>> {
+        Acquire::acquire(&mut **self)
+    }
+
+    fn begin(self) -> BoxFuture<'c, Result<Transaction<'c, Postgres>, sqlx::Error>> {
+        Acquire::begin(&mut **self)
+    }
+}
+
+pub struct DatabaseConnection(pub DbConnection);
 
 #[async_trait]
 impl<S> FromRequestParts<S> for DatabaseConnection
@@ -19,10 +73,58 @@ where
 {
     type Rejection = ApiError;
 
-    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(sandbox) = parts.extensions.get::<SandboxTransaction>() {
+            return Ok(Self(DbConnection::Sandboxed(sandbox.lock()?)));
+        }
         let AppState { db_pool, .. } = AppState::from_ref(state);
         let conn = db_pool.acquire().await?;
-        Ok(Self(conn))
+        Ok(Self(DbConnection::Pooled(Box::new(conn))))
+    }
+}
+
+/// A connection to the read replica, or to the primary if there's none, for the read-heavy
+/// endpoints, like the listings, the search and the exports.
+///
+/// The replica lags behind the primary, usually by less than a second. So don't use it where a
+/// client reads what it has just written (read-your-writes), like a handler that writes, or the
+/// detail page a client is redirected to after an edit: the write may not be there yet. Sandboxed
+/// requests use their transaction, like with [`DatabaseConnection`], to see their own writes.
+pub struct ReadDatabaseConnection(pub DbConnection);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ReadDatabaseConnection
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(sandbox) = parts.extensions.get::<SandboxTransaction>() {
+            return Ok(Self(DbConnection::Sandboxed(sandbox.lock()?)));
+        }
+        let state = AppState::from_ref(state);
+        let conn = state.read_pool().acquire().await?;
+        Ok(Self(DbConnection::Pooled(Box::new(conn))))
+    }
+}
+
+/// The MeiliSearch client of the app, shared like the database pool instead of being built for
+/// every request.
+pub struct MeiliConnection(pub Client);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MeiliConnection
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AppState { meili_client, .. } = AppState::from_ref(state);
+        Ok(Self(meili_client))
     }
 }
 
@@ -51,6 +153,7 @@ where
     type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        mark_personalized(&parts.extensions);
         let session = Session::from_request_parts(parts, state)
             .await
             .expect("`SessionLayer` should be added");
@@ -63,32 +166,104 @@ where
     }
 }
 
-pub struct MaybeAuthUser(pub Option<AuthUser>);
+/// The id of an anonymous session, created with `POST /guest`.
+///
+/// It lets guests collect state (like favorites) that gets merged into their account
+/// once they register or log in.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct GuestId(uuid::Uuid);
+
+impl GuestId {
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4())
+    }
+}
+
+impl Default for GuestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for GuestId {
+    type Target = uuid::Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct MaybeAuthUser(pub Option<AuthUser>, pub Option<GuestId>);
 
 impl MaybeAuthUser {
     pub fn into_inner(self) -> Option<AuthUser> {
         self.0
     }
+
+    /// The guest id, if this is an anonymous guest session.
+    pub fn guest_id(&self) -> Option<GuestId> {
+        self.1
+    }
 }
 
+/// The signed-in user or guest, if any.
+///
+/// If the session can't be read, `GET` and `HEAD` requests are served as anonymous when
+/// [`SessionFallback`] allows it. Other requests fail like `AuthUser` does, so nothing is changed
+/// on behalf of the wrong user.
 #[async_trait]
 impl<S> FromRequestParts<S> for MaybeAuthUser
 where
     S: Send + Sync,
 {
-    type Rejection = Infallible;
+    type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        mark_personalized(&parts.extensions);
         let session = Session::from_request_parts(parts, state)
             .await
             .expect("`SessionLayer` should be added");
 
-        let user_id = session.get::<uuid::Uuid>("user_id").await;
+        match session.get::<uuid::Uuid>("user_id").await {
+            Ok(Some(id)) => Ok(Self(Some(AuthUser::new(id)), None)),
+            Ok(None) => {
+                let guest_id = session.get::<GuestId>("guest_id").await.ok().flatten();
+                Ok(Self(None, guest_id))
+            }
+            Err(e) => {
+                SessionFallback::serve_anonymously(parts, e)?;
+                Ok(Self(None, None))
+            }
+        }
+    }
+}
+
+/// Whether `MaybeAuthUser` and `Org` treat an unreadable session as anonymous, as set in
+/// `session.anonymous_on_store_error`. They do without it in the request extensions.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionFallback(pub bool);
 
-        match user_id {
-            Ok(Some(id)) => Ok(Self(Some(AuthUser::new(id)))),
-            Ok(None) | Err(_) => Ok(Self(None)),
+impl SessionFallback {
+    fn enabled(extensions: &Extensions) -> bool {
+        !matches!(extensions.get::<Self>(), Some(Self(false)))
+    }
+
+    /// Decides what to do when the session of the request can't be read. `Ok` means going on as
+    /// anonymous, which only safe requests do, and only with the fallback enabled.
+    pub(crate) fn serve_anonymously(
+        parts: &Parts,
+        e: tower_sessions::session::Error,
+    ) -> Result<(), ApiError> {
+        if !parts.method.is_safe() || !Self::enabled(&parts.extensions) {
+            return Err(e.into());
         }
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to read the session, serving the request as anonymous."
+        );
+        metrics::counter!("session_store_fallbacks_total").increment(1);
+        Ok(())
     }
 }
 
@@ -146,3 +321,249 @@ where
         }
     }
 }
+
+/// The proxies allowed to tell the client's address, see [`ClientIp`].
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    pub(crate) fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl FromRef<AppState> for TrustedProxies {
+    fn from_ref(state: &AppState) -> Self {
+        Self(
+            state
+                .config
+                .borrow()
+                .application_settings
+                .trusted_proxies
+                .clone(),
+        )
+    }
+}
+
+/// The address of the client, even behind a load balancer.
+///
+/// The forwarding headers are only honored if the peer is a trusted proxy, otherwise anyone could
+/// pick their own address (and rate limit key). Falls back to the unspecified address when the
+/// server wasn't started with `ConnectInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> Self {
+        if !trusted.contains(&peer) {
+            return Self(peer);
+        }
+
+        // Each proxy appends the address it got the request from, so walk back from the right
+        // until the first hop that's not ours. Everything left of that could be forged.
+        let forwarded_for: Vec<Option<IpAddr>> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().parse().ok())
+            .collect();
+        if !forwarded_for.is_empty() {
+            for hop in forwarded_for.iter().rev() {
+                match hop {
+                    Some(ip) if trusted.contains(ip) => continue,
+                    Some(ip) => return Self(*ip),
+                    // Can't tell who sent it, but the peer is a trusted proxy at least.
+                    None => return Self(peer),
+                }
+            }
+            // Only trusted proxies, the first one is the closest to the client.
+            return forwarded_for[0].map_or(Self(peer), Self);
+        }
+
+        headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map_or(Self(peer), Self)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+    TrustedProxies: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Through the extractor, which also falls back to `MockConnectInfo`.
+        let peer = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .map(|ConnectInfo(addr)| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+        Ok(Self::resolve(
+            peer,
+            &parts.headers,
+            &TrustedProxies::from_ref(state),
+        ))
+    }
+}
+
+/// `axum::Json`, but a body that can't be deserialized is rejected with the usual `ApiError`
+/// envelope instead of a plain text message. Also usable as a response, just like `axum::Json`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !StrictBodies::enabled(&req) {
+            let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+            return Ok(Self(value));
+        }
+
+        let axum::Json(body) = axum::Json::<serde_json::Value>::from_request(req, state).await?;
+        if let Some(object) = body.as_object() {
+            reject_unknown_fields::<T>(object.keys())?;
+        }
+        serde_json::from_value(body)
+            .map(Self)
+            .map_err(|e| ApiError::unprocessable_entity([("body", e.to_string())]))
+    }
+}
+
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// `axum_extra`'s `Form`, which also handles repeated keys, but a body that can't be
+/// deserialized is rejected with the usual `ApiError` envelope instead of a plain text message.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Form<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !StrictBodies::enabled(&req) {
+            let axum_extra::extract::Form(value) =
+                axum_extra::extract::Form::<T>::from_request(req, state).await?;
+            return Ok(Self(value));
+        }
+
+        let axum_extra::extract::Form(pairs) =
+            axum_extra::extract::Form::<Vec<(String, String)>>::from_request(req, state).await?;
+        reject_unknown_fields::<T>(pairs.iter().map(|(key, _)| key))?;
+        // Decoded again, so repeated keys are handled just like without strictness.
+        let encoded = serde_html_form::to_string(&pairs).context("Failed to encode form")?;
+        serde_html_form::from_str(&encoded)
+            .map(Self)
+            .map_err(|e| ApiError::unprocessable_entity([("body", e.to_string())]))
+    }
+}
+
+/// Whether `Json` and `Form` reject bodies with unknown fields, as set in
+/// `application_settings.strict_request_bodies`. Bodies are lenient without it in the request
+/// extensions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StrictBodies(pub bool);
+
+impl StrictBodies {
+    fn enabled(req: &Request) -> bool {
+        req.extensions()
+            .get::<Self>()
+            .is_some_and(|strict| strict.0)
+    }
+}
+
+/// Rejects the keys that aren't fields of `T` with `422`, each under its own name. Only the top
+/// level is checked, and nothing is checked if `T` isn't a plain struct, e.g. it has flattened
+/// fields.
+fn reject_unknown_fields<'a, T: serde::de::DeserializeOwned>(
+    keys: impl IntoIterator<Item = &'a String>,
+) -> Result<(), ApiError> {
+    let Some(fields) = struct_fields::<T>() else {
+        return Ok(());
+    };
+    let mut unknown: Vec<&String> = keys
+        .into_iter()
+        .filter(|key| !fields.contains(&key.as_str()))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort_unstable();
+    unknown.dedup();
+    Err(ApiError::unprocessable_entity(
+        unknown
+            .into_iter()
+            .map(|key| (key.clone(), "is not a known field")),
+    ))
+}
+
+/// The field names (aliases included) `T` expects, as serde passes them to the deserializer.
+fn struct_fields<T: serde::de::DeserializeOwned>() -> Option<&'static [&'static str]> {
+    /// A deserializer that only answers what it was asked for.
+    struct Probe;
+
+    #[derive(Debug)]
+    struct Fields(Option<&'static [&'static str]>);
+
+    impl std::fmt::Display for Fields {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("probing the fields of a struct")
+        }
+    }
+
+    impl std::error::Error for Fields {}
+
+    impl serde::de::Error for Fields {
+        fn custom<M: std::fmt::Display>(_: M) -> Self {
+            Self(None)
+        }
+    }
+
+    impl<'de> serde::Deserializer<'de> for Probe {
+        type Error = Fields;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Fields> {
+            Err(Fields(None))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Fields> {
+            Err(Fields(Some(fields)))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    match <T as serde::Deserialize>::deserialize(Probe) {
+        Ok(_) => None,
+        Err(Fields(fields)) => fields,
+    }
+}
@@ -0,0 +1,80 @@
+//! Liveness and readiness for orchestrators.
+//!
+//! The listener is bound before the slow parts of startup, like running the migrations, and
+//! answers `/admin/live` the whole time, so the process isn't killed for taking long to start.
+//! `/admin/ready` fails until the application is built and plugged in, so no traffic is routed to
+//! it before then.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn mark_ready(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Serves the probes in front of the application, which is plugged in with [`Startup::serve_app`]
+/// once it's built. Until then every other request gets `503 Service Unavailable`.
+#[derive(Clone, Default)]
+pub struct Startup {
+    readiness: Readiness,
+    app: Arc<OnceLock<Router>>,
+}
+
+impl Startup {
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/admin/live", get(|| async { StatusCode::OK }))
+            .route(
+                "/admin/ready",
+                get(ready).with_state(self.readiness.clone()),
+            )
+            .fallback(forward)
+            .with_state(self.app.clone())
+    }
+
+    /// Starts routing requests to the application, and reports ready.
+    pub fn serve_app(&self, app: Router) {
+        if self.app.set(app).is_err() {
+            tracing::warn!("The application was already being served");
+        }
+        self.readiness.mark_ready();
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+}
+
+async fn ready(State(readiness): State<Readiness>) -> StatusCode {
+    if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+async fn forward(State(app): State<Arc<OnceLock<Router>>>, request: Request) -> Response {
+    match app.get() {
+        Some(app) => app.clone().oneshot(request).await.into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "starting up").into_response(),
+    }
+}
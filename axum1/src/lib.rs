@@ -1,20 +1,42 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+pub mod allow;
+pub mod breach;
 pub mod cli;
 pub mod config;
+pub mod connections;
+pub mod cors;
+pub mod csrf;
 pub mod email;
 pub mod error;
 pub mod extractors;
+pub mod health;
+pub mod load_shed;
+pub mod locale;
+pub mod org;
+pub mod pagination;
+pub mod personalized;
+pub mod presence;
 pub mod queue;
+pub mod rate_limit;
+pub mod recent;
 pub mod routes;
+pub mod sandbox;
+pub mod schema;
 pub mod search;
+pub mod secure_cookies;
 pub mod sse;
 pub mod startup;
 pub mod state;
 pub mod task;
+pub mod telemetry;
+pub mod time_range;
+pub mod token;
 pub mod upload;
 pub mod utils;
+pub mod versioning;
+pub mod ws;
 
 static RE_USERNAME: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^[a-zA-Z0-9 íáéúőóüöűÍÁÉÚŐÓÜÖŰ](\.?[a-zA-Z0-9 íáéúőóüöűÍÁÉÚŐÓÜÖŰ])*$").unwrap()
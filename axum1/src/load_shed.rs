@@ -0,0 +1,51 @@
+//! Sheds the requests over `max_concurrent_requests`.
+//!
+//! The database pool is small, so requests over what it can serve would just queue up for a
+//! connection until they time out, while holding on to everything else they've acquired. They
+//! are rejected with `503` and `Retry-After` right away instead.
+//!
+//! The liveness and readiness probes are answered before the application (see `crate::health`),
+//! so they're never shed.
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+use crate::error::ApiError;
+
+/// The state of [`shed_load`], shared by every request. Without a limit, nothing is shed.
+#[derive(Clone)]
+pub struct ConcurrencyLimit(Option<Arc<Semaphore>>);
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent_requests: Option<usize>) -> Self {
+        Self(max_concurrent_requests.map(|max| Arc::new(Semaphore::new(max))))
+    }
+}
+
+/// Paths that are cheap and need to answer precisely when the app is busy, like the metrics.
+fn is_exempt(path: &str) -> bool {
+    path == "/metrics" || path.ends_with("/health_check")
+}
+
+/// Rejects the request with [`ApiError::Overloaded`] if the limit is reached, without waiting for
+/// a request to finish. The slot is held until the response is ready, not until its body is
+/// sent, so long streams like SSE don't count against it.
+pub async fn shed_load(
+    State(ConcurrencyLimit(permits)): State<ConcurrencyLimit>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(permits) = permits.filter(|_| !is_exempt(request.uri().path())) else {
+        return next.run(request).await;
+    };
+    let Ok(_permit) = permits.try_acquire() else {
+        metrics::counter!("http_requests_shed_total").increment(1);
+        return ApiError::Overloaded.into_response();
+    };
+    next.run(request).await
+}
@@ -0,0 +1,123 @@
+use axum::http::{header::ACCEPT_LANGUAGE, HeaderMap};
+
+/// The languages user facing messages (emails, and formatted numbers) are translated to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Hu,
+}
+
+impl Locale {
+    /// Parses a language tag, only looking at its primary subtag, e.g. `hu-HU` is `Hu`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Self::En),
+            "hu" => Some(Self::Hu),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported language the client prefers the most from the `Accept-Language` header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = headers.get(ACCEPT_LANGUAGE)?.to_str().ok()?;
+        let mut candidates: Vec<(Self, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let locale = Self::parse(parts.next()?)?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((locale, quality))
+            })
+            .collect();
+        // A stable sort, so equally weighted languages keep the client's order.
+        candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        candidates.first().map(|(locale, _)| *locale)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Hu => "hu",
+        }
+    }
+}
+
+/// The units amounts are displayed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Milligram,
+    Gram,
+    Kilogram,
+    Kilocalorie,
+}
+
+impl Unit {
+    /// Parses the unit of an ingredient quantity, like `g`. Quantities without a unit have none.
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit.trim().to_ascii_lowercase().as_str() {
+            "mg" => Some(Self::Milligram),
+            "g" => Some(Self::Gram),
+            "kg" => Some(Self::Kilogram),
+            "kcal" => Some(Self::Kilocalorie),
+            _ => None,
+        }
+    }
+}
+
+impl Locale {
+    /// Formats a number for display, rounded to at most `max_decimals` decimals without trailing
+    /// zeros, and with its thousands grouped, e.g. `1,234.5` in English and `1 234,5` in
+    /// Hungarian. The Hungarian group separator is a non-breaking space.
+    pub fn format_number(&self, value: f64, max_decimals: usize) -> String {
+        let (decimal_separator, group_separator) = match self {
+            Self::En => ('.', ','),
+            Self::Hu => (',', '\u{a0}'),
+        };
+
+        let rounded = format!("{:.*}", max_decimals, value.abs());
+        let (integer, fraction) = rounded.split_once('.').unwrap_or((rounded.as_str(), ""));
+        let fraction = fraction.trim_end_matches('0');
+
+        let mut formatted = String::with_capacity(rounded.len() + integer.len() / 3 + 1);
+        // Rounding may leave a negative zero.
+        if value < 0.0 && rounded.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            formatted.push('-');
+        }
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                formatted.push(group_separator);
+            }
+            formatted.push(digit);
+        }
+        if !fraction.is_empty() {
+            formatted.push(decimal_separator);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    /// The symbol of the unit. The supported languages all use the SI symbols, languages that
+    /// don't would map them here.
+    pub fn unit_abbreviation(&self, unit: Unit) -> &'static str {
+        match unit {
+            Unit::Milligram => "mg",
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Kilocalorie => "kcal",
+        }
+    }
+
+    /// An amount with its unit, like `12.5 g`, or just the number without a unit.
+    pub fn format_amount(&self, value: f64, max_decimals: usize, unit: Option<Unit>) -> String {
+        let number = self.format_number(value, max_decimals);
+        match unit {
+            Some(unit) => format!("{number}\u{a0}{}", self.unit_abbreviation(unit)),
+            None => number,
+        }
+    }
+}
@@ -5,10 +5,19 @@ use axum1::{
     search::run_meili_indexer_until_stopped,
     startup::application,
     task::supervised_task,
+    telemetry::{init_tracer, StatementCounterLayer, STATEMENT_TARGET},
     utils::{init_tracing_panic_hook, report_exit},
 };
+use secrecy::ExposeSecret;
+use std::time::Duration;
+
 use tokio::sync::watch;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::Level;
+use tracing_subscriber::{
+    filter::Targets,
+    layer::{Layer, SubscriberExt},
+    util::SubscriberInitExt,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -16,21 +25,30 @@ async fn main() -> anyhow::Result<()> {
     let (tx, rx) = watch::channel(initial_configuration);
     let cfg = tx.borrow();
     let _guard = sentry::init((
-        cfg.clone().sentry_dsn,
+        cfg.sentry_dsn
+            .as_ref()
+            .map(|dsn| dsn.expose_secret().to_owned()),
         sentry::ClientOptions {
             release: sentry::release_name!(),
             ..Default::default()
         },
     ));
+    let otel = init_tracer(&cfg.telemetry).expect("Failed to set up OpenTelemetry.");
     drop(cfg);
 
+    let (tracer_provider, tracer) = otel.unzip();
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "axum1=debug,tower_http=debug,sqlx::query=warn".into());
+    // The statement counter sees every statement, whatever `RUST_LOG` prints.
+    let statement_filter = Targets::new().with_target(STATEMENT_TARGET, Level::TRACE);
     tracing_subscriber::registry()
+        .with(StatementCounterLayer.with_filter(statement_filter))
         .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "axum1=debug,tower_http=debug".into()),
+            tracing_subscriber::fmt::layer()
+                .and_then(sentry_tracing::layer())
+                .and_then(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+                .with_filter(env_filter),
         )
-        .with(tracing_subscriber::fmt::layer())
-        .with(sentry_tracing::layer())
         .init();
 
     init_tracing_panic_hook();
@@ -40,17 +58,31 @@ async fn main() -> anyhow::Result<()> {
     let meili_indexing_task = run_meili_indexer_until_stopped(rx.clone());
 
     let (meili_task_spawned, meili_supervisor) = supervised_task(meili_indexing_task);
-    let (worker_task_spawned, worker_supervisor) = supervised_task(worker_task);
+    let (mut worker_task_spawned, worker_supervisor) = supervised_task(worker_task);
 
     let cli_manager_task =
         tokio::spawn(cli_manager(tx.clone(), meili_supervisor, worker_supervisor));
 
-    tokio::select! {
-        f = application_task => report_exit("server", f),
-        f = meili_task_spawned => report_exit("meili indexing", f),
-        f = worker_task_spawned => report_exit("queue", f),
-        f = cli_manager_task => report_exit("CLI Manager", f),
+    let server_exited = tokio::select! {
+        f = application_task => { report_exit("server", f); true },
+        f = meili_task_spawned => { report_exit("meili indexing", f); false },
+        f = &mut worker_task_spawned => { report_exit("queue", f); false },
+        f = cli_manager_task => { report_exit("CLI Manager", f); false },
     };
 
+    // On a graceful shutdown, give the queue workers a chance to finish their in-flight tasks.
+    if server_exited && !worker_task_spawned.is_finished() {
+        match tokio::time::timeout(Duration::from_secs(30), worker_task_spawned).await {
+            Ok(f) => report_exit("queue", f),
+            Err(_) => tracing::warn!("queue workers did not finish in time"),
+        }
+    }
+
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!(error = %e, "Failed to flush the remaining spans");
+        }
+    }
+
     Ok(())
 }
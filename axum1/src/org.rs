@@ -0,0 +1,127 @@
+use std::ops::Deref;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{
+        header::{HeaderMap, HOST},
+        request::Parts,
+    },
+};
+use sqlx::PgConnection;
+use tower_sessions::Session;
+
+use crate::{
+    error::ApiError, extractors::SessionFallback, personalized::mark_personalized, state::AppState,
+};
+
+/// The organization everything predating multi-tenancy was moved into. Requests that don't name
+/// an organization belong to it.
+pub const DEFAULT_ORG_ID: uuid::Uuid = uuid::Uuid::nil();
+
+/// Names the organization explicitly, e.g. for API clients without their own subdomain.
+pub const ORG_HEADER: &str = "x-organization";
+
+/// The organization the request is scoped to, resolved from the `X-Organization` header or the
+/// subdomain of the configured base domain.
+///
+/// Logged in users may only act in their own organization (super-admins in any), so every
+/// handler taking this can safely scope its queries by it. A session that can't be read is
+/// handled like [`MaybeAuthUser`](crate::extractors::MaybeAuthUser) does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Org(uuid::Uuid);
+
+impl Org {
+    pub const DEFAULT: Self = Self(DEFAULT_ORG_ID);
+
+    /// Rejects users of other organizations with `403`. Super-admins belong to every organization.
+    pub async fn ensure_member(
+        self,
+        conn: &mut PgConnection,
+        user_id: uuid::Uuid,
+    ) -> Result<(), ApiError> {
+        let member = sqlx::query!(
+            "SELECT org_id, is_super_admin FROM users WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+        if member.org_id != self.0 && !member.is_super_admin {
+            return Err(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Org {
+    type Target = uuid::Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// The organization slug named by the request, if any. The header takes precedence over the
+/// subdomain, which is only considered if `base_domain` is configured.
+pub fn requested_org_slug(headers: &HeaderMap, base_domain: Option<&str>) -> Option<String> {
+    if let Some(slug) = headers.get(ORG_HEADER).and_then(|v| v.to_str().ok()) {
+        return Some(slug.trim().to_owned()).filter(|slug| !slug.is_empty());
+    }
+
+    let base_domain = base_domain?;
+    let host = headers.get(HOST)?.to_str().ok()?;
+    let host = host.split(':').next().unwrap_or(host);
+    let subdomain = host.strip_suffix(base_domain)?.strip_suffix('.')?;
+    // Only direct subdomains, and `www` is just the base domain.
+    Some(subdomain.to_owned()).filter(|s| !s.is_empty() && !s.contains('.') && s != "www")
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Org
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AppState {
+            db_pool, config, ..
+        } = AppState::from_ref(state);
+        let base_domain = config.borrow().application_settings.base_domain.clone();
+        let slug = requested_org_slug(&parts.headers, base_domain.as_deref());
+
+        let mut conn = db_pool.acquire().await?;
+
+        let org_id = match slug {
+            Some(slug) => sqlx::query_scalar!("SELECT id FROM organizations WHERE slug = $1", slug)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(ApiError::NotFound)?,
+            None => DEFAULT_ORG_ID,
+        };
+
+        // Who is asking decides whether the request is allowed, so the response must not be
+        // shared with anyone else.
+        mark_personalized(&parts.extensions);
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .expect("`SessionLayer` should be added");
+        let org = Self(org_id);
+        let user_id = match session.get::<uuid::Uuid>("user_id").await {
+            Ok(user_id) => user_id,
+            // As anonymous, the request only gets what anyone in the organization may see.
+            Err(e) => {
+                SessionFallback::serve_anonymously(parts, e)?;
+                None
+            }
+        };
+        if let Some(user_id) = user_id {
+            org.ensure_member(&mut conn, user_id).await?;
+        }
+
+        Ok(org)
+    }
+}
@@ -0,0 +1,164 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query, Request},
+    http::{header::LINK, request::Parts, HeaderValue, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::PaginationSettings, error::ApiError, state::AppState};
+
+impl FromRef<AppState> for PaginationSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.borrow().pagination.clone()
+    }
+}
+
+#[derive(Deserialize)]
+struct PaginationQuery {
+    page: Option<i64>,
+    // The listing endpoints used to take `limit`, keep accepting it.
+    #[serde(alias = "limit")]
+    per_page: Option<i64>,
+}
+
+/// The requested page of a listing endpoint, read from the `page` (1-based) and `per_page`
+/// query parameters.
+///
+/// Out of range values are clamped rather than rejected, the effective values are echoed back in
+/// the [`Paginated`] envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Pagination {
+    pub fn offset(&self) -> i64 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Pagination
+where
+    S: Send + Sync,
+    PaginationSettings: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PaginationQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::BadRequest)?;
+        let settings = PaginationSettings::from_ref(state);
+
+        Ok(Self {
+            page: query.page.unwrap_or(1).max(1),
+            per_page: query
+                .per_page
+                .unwrap_or_else(|| settings.default_per_page())
+                .clamp(1, settings.max_per_page()),
+        })
+    }
+}
+
+/// The response envelope of the listing endpoints.
+///
+/// Responding with it also sets the `Link` header, see [`pagination_links`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The number of items on all pages.
+    pub total: i64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, pagination: Pagination, total: i64) -> Self {
+        Self {
+            items,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total,
+        }
+    }
+
+    /// An empty listing still has a (first) page.
+    pub fn last_page(&self) -> i64 {
+        ((self.total + self.per_page - 1) / self.per_page).max(1)
+    }
+}
+
+impl<T: Serialize> IntoResponse for Paginated<T> {
+    fn into_response(self) -> Response {
+        let position = PagePosition {
+            page: self.page,
+            per_page: self.per_page,
+            last_page: self.last_page(),
+        };
+        (Extension(position), Json(self)).into_response()
+    }
+}
+
+/// Where a [`Paginated`] response is in its listing, left in the response extensions for
+/// [`pagination_links`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagePosition {
+    pub page: i64,
+    pub per_page: i64,
+    pub last_page: i64,
+}
+
+/// Builds an RFC 5988 `Link` header value pointing to the first, previous, next and last pages.
+///
+/// The links are `uri` with its `page` and `per_page` parameters replaced, every other parameter
+/// is kept as is. `prev` is omitted on the first page and `next` on the last one.
+pub fn link_header(uri: &Uri, position: PagePosition) -> String {
+    // The pairs stay percent-encoded, so they are copied over verbatim.
+    let kept: String = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !pair.is_empty() && !matches!(key, "page" | "per_page" | "limit")
+        })
+        .map(|pair| format!("{pair}&"))
+        .collect();
+
+    let link = |page: i64, rel: &str| {
+        format!(
+            "<{}?{kept}page={page}&per_page={}>; rel=\"{rel}\"",
+            uri.path(),
+            position.per_page
+        )
+    };
+
+    let mut links = vec![link(1, "first")];
+    if position.page > 1 {
+        links.push(link((position.page - 1).min(position.last_page), "prev"));
+    }
+    if position.page < position.last_page {
+        links.push(link(position.page + 1, "next"));
+    }
+    links.push(link(position.last_page, "last"));
+    links.join(", ")
+}
+
+/// Sets the `Link` header on [`Paginated`] responses. This must wrap the whole router, nested
+/// routers only see their part of the URL.
+pub async fn pagination_links(request: Request, next: Next) -> Response {
+    let uri = request.uri().clone();
+    let mut response = next.run(request).await;
+
+    if let Some(position) = response.extensions().get::<PagePosition>().copied() {
+        if let Ok(value) = HeaderValue::from_str(&link_header(&uri, position)) {
+            response.headers_mut().insert(LINK, value);
+        }
+    }
+    response
+}
@@ -0,0 +1,92 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::Request,
+    http::{
+        header::{CACHE_CONTROL, VARY},
+        HeaderMap, HeaderValue,
+    },
+    middleware::Next,
+    response::Response,
+};
+
+/// Set by the auth extractors when the session was looked at, meaning the response may differ
+/// between users.
+///
+/// The middleware puts it into the request extensions before the handler runs, and checks it
+/// once the response is ready. Extractors can't touch the response, so the flag is shared.
+#[derive(Debug, Clone, Default)]
+pub struct Personalized(Arc<AtomicBool>);
+
+impl Personalized {
+    pub fn mark(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_marked(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Marks the request as personalized, if the middleware is installed.
+pub fn mark_personalized(extensions: &axum::http::Extensions) {
+    if let Some(personalized) = extensions.get::<Personalized>() {
+        personalized.mark();
+    }
+}
+
+/// Adds `Vary: Cookie` and `Cache-Control: private` to responses that depend on the session, so
+/// shared caches never serve one user's response to another. Public responses are left alone.
+pub async fn vary_personalized(mut request: Request, next: Next) -> Response {
+    let personalized = Personalized::default();
+    request.extensions_mut().insert(personalized.clone());
+
+    let mut response = next.run(request).await;
+    if personalized.is_marked() {
+        mark_response_private(response.headers_mut());
+    }
+    response
+}
+
+fn mark_response_private(headers: &mut HeaderMap) {
+    let varies_on_cookie = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|v| v == "*" || v.eq_ignore_ascii_case("cookie"));
+    if !varies_on_cookie {
+        headers.append(VARY, HeaderValue::from_static("Cookie"));
+    }
+
+    let cache_control =
+        private_cache_control(headers.get(CACHE_CONTROL).and_then(|v| v.to_str().ok()));
+    if let Ok(value) = HeaderValue::from_str(&cache_control) {
+        headers.insert(CACHE_CONTROL, value);
+    }
+}
+
+/// Keeps the directives set by the handler, but never lets the response be `public`.
+pub fn private_cache_control(existing: Option<&str>) -> String {
+    let directives: Vec<&str> = existing
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|d| !d.is_empty() && !d.eq_ignore_ascii_case("public"))
+        .collect();
+
+    if directives
+        .iter()
+        .any(|d| d.eq_ignore_ascii_case("private") || d.eq_ignore_ascii_case("no-store"))
+    {
+        return directives.join(", ");
+    }
+    std::iter::once("private")
+        .chain(directives)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
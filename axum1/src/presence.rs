@@ -0,0 +1,111 @@
+//! Who is looking at a recipe right now, for collaborative editing. Kept in Redis, so it's shared
+//! by every instance and nothing needs cleaning up after a crash.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool,
+    error::RedisError,
+    interfaces::{KeysInterface, SortedSetsInterface},
+};
+
+/// A sorted set of user ids per recipe, scored by when their presence expires. Clients keep it
+/// fresh with heartbeats, users who stop sending them are dropped after the timeout.
+///
+/// The set itself expires along with its last member, so a recipe everyone left without saying so
+/// doesn't stay behind.
+#[derive(Clone)]
+pub struct Presence {
+    redis: RedisPool,
+    timeout: Duration,
+}
+
+impl Presence {
+    pub fn new(redis: RedisPool, timeout: Duration) -> Self {
+        Self { redis, timeout }
+    }
+
+    fn key(recipe_id: uuid::Uuid) -> String {
+        format!("presence:{recipe_id}")
+    }
+
+    fn now() -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+
+    /// Marks the user present for another timeout. Returns whether they just joined.
+    pub async fn heartbeat(
+        &self,
+        recipe_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+    ) -> Result<bool, RedisError> {
+        let key = Self::key(recipe_id);
+        let expires_at = Self::now() + self.timeout.as_secs_f64();
+        let pipeline = self.redis.next().pipeline();
+        let _: () = pipeline
+            .zadd(
+                &key,
+                None,
+                None,
+                false,
+                false,
+                (expires_at, user_id.to_string()),
+            )
+            .await?;
+        let _: () = pipeline
+            .expire(&key, self.timeout.as_secs() as i64 + 1)
+            .await?;
+        let (added, _): (i64, i64) = pipeline.all().await?;
+        Ok(added == 1)
+    }
+
+    /// Returns whether the user was present.
+    pub async fn leave(
+        &self,
+        recipe_id: uuid::Uuid,
+        user_id: uuid::Uuid,
+    ) -> Result<bool, RedisError> {
+        let removed: i64 = self
+            .redis
+            .zrem(Self::key(recipe_id), user_id.to_string())
+            .await?;
+        Ok(removed == 1)
+    }
+
+    /// The users present, and those whose presence expired since the last look.
+    ///
+    /// Every expired user is only returned once, even with concurrent calls, so they can be
+    /// announced as gone.
+    pub async fn list(
+        &self,
+        recipe_id: uuid::Uuid,
+    ) -> Result<(Vec<uuid::Uuid>, Vec<uuid::Uuid>), RedisError> {
+        let key = Self::key(recipe_id);
+        let now = Self::now();
+
+        let stale: Vec<String> = self
+            .redis
+            .zrangebyscore(&key, "-inf", now, false, None)
+            .await?;
+        let mut expired = Vec::with_capacity(stale.len());
+        for member in stale {
+            // Removed one by one, to know which of them this call removed.
+            let removed: i64 = self.redis.zrem(&key, member.as_str()).await?;
+            if removed == 1 {
+                expired.extend(member.parse::<uuid::Uuid>().ok());
+            }
+        }
+
+        let present: Vec<String> = self
+            .redis
+            .zrangebyscore(&key, now, "+inf", false, None)
+            .await?;
+        let present = present
+            .iter()
+            .filter_map(|member| member.parse().ok())
+            .collect();
+        Ok((present, expired))
+    }
+}
@@ -0,0 +1,252 @@
+//! Weekly digests of the new recipes matching a user's interests.
+//!
+//! Weeks start on Monday midnight in the user's own time zone. Every run picks up the users
+//! whose last week hasn't been considered yet, so a digest goes out within one
+//! `digest_interval` after their week ends, and at most once per week.
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use tracing::{field::display, Span};
+
+use crate::{
+    email::{digest_message, Email, EmailClient, EmailLinks},
+    locale::Locale,
+};
+
+const DIGEST_DELIVERY_JOB: &str = "digest_delivery";
+
+/// At most this many recipes are listed in one digest.
+const MAX_DIGEST_RECIPES: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    Weekly,
+    Off,
+}
+
+impl DigestFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Weekly => "weekly",
+            Self::Off => "off",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "weekly" => Some(Self::Weekly),
+            "off" => Some(Self::Off),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DigestRecipe {
+    pub name: String,
+    pub description: String,
+}
+
+/// What happened to the digest of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestOutcome {
+    Sent,
+    /// Nothing matched during the week, so no email was sent.
+    NothingNew,
+    /// The email couldn't be sent, it was recorded in `failed_jobs`.
+    Failed,
+}
+
+struct DueDigest {
+    user_id: uuid::Uuid,
+    email: String,
+    locale: Option<String>,
+    org_id: uuid::Uuid,
+    topics: Vec<String>,
+    unsubscribe_token: String,
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+}
+
+/// Sends the digests that are due until none is left, returning how many were processed.
+pub async fn send_due_digests(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    links: &EmailLinks,
+) -> Result<u64, anyhow::Error> {
+    let mut processed = 0;
+    while send_next_digest(pool, email_client, links).await?.is_some() {
+        processed += 1;
+    }
+    Ok(processed)
+}
+
+/// Claims one due digest and sends it, if it has any content. `None` if no digest is due.
+#[tracing::instrument(skip_all, fields(task.id = tracing::field::Empty))]
+pub async fn send_next_digest(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    links: &EmailLinks,
+) -> Result<Option<DigestOutcome>, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    // The row stays locked until the week is marked as done, so concurrent workers never send
+    // the same digest twice.
+    let due = sqlx::query_as!(
+        DueDigest,
+        r#"
+        SELECT d.user_id, u.email, u.locale, u.org_id, d.topics, d.unsubscribe_token,
+            (date_trunc('week', NOW() AT TIME ZONE d.time_zone) - INTERVAL '1 week')
+                AT TIME ZONE d.time_zone AS "period_start!",
+            date_trunc('week', NOW() AT TIME ZONE d.time_zone)
+                AT TIME ZONE d.time_zone AS "period_end!"
+        FROM digest_preferences d
+        INNER JOIN users u ON u.user_id = d.user_id
+        WHERE d.frequency = 'weekly'
+          AND (d.last_period_end IS NULL
+            OR d.last_period_end < date_trunc('week', NOW() AT TIME ZONE d.time_zone)
+                AT TIME ZONE d.time_zone)
+        FOR UPDATE OF d
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(due) = due else {
+        return Ok(None);
+    };
+    Span::current().record("task.id", display(due.user_id));
+
+    let recipes = matching_recipes(
+        &mut tx,
+        due.org_id,
+        due.user_id,
+        &due.topics,
+        due.period_start,
+        due.period_end,
+    )
+    .await?;
+
+    let outcome = if recipes.is_empty() {
+        DigestOutcome::NothingNew
+    } else {
+        let locale = due
+            .locale
+            .as_deref()
+            .and_then(Locale::parse)
+            .unwrap_or_default();
+        let sent = match Email::parse(due.email.clone()) {
+            Ok(email) => email_client
+                .send_message(digest_message(
+                    email,
+                    locale,
+                    links,
+                    &recipes,
+                    &due.unsubscribe_token,
+                ))
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match sent {
+            Ok(()) => DigestOutcome::Sent,
+            Err(e) => {
+                sqlx::query!(
+                    "INSERT INTO failed_jobs (job_type, context) VALUES ($1, $2)",
+                    DIGEST_DELIVERY_JOB,
+                    serde_json::json!({ "user_id": due.user_id, "error": e })
+                )
+                .execute(&mut *tx)
+                .await?;
+                metrics::counter!("queue_tasks_failed_total", "job_type" => DIGEST_DELIVERY_JOB)
+                    .increment(1);
+                tracing::error!(error.message = %e, "Failed to deliver digest email. Skipping.");
+                DigestOutcome::Failed
+            }
+        }
+    };
+
+    sqlx::query!(
+        "UPDATE digest_preferences SET last_period_end = $2 WHERE user_id = $1",
+        due.user_id,
+        due.period_end
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(outcome))
+}
+
+/// The visible recipes created during the period by someone else, that use one of the user's
+/// favorite ingredients, or have an ingredient or cuisine named in their topics.
+pub async fn matching_recipes(
+    conn: &mut PgConnection,
+    org_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    topics: &[String],
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+) -> Result<Vec<DigestRecipe>, sqlx::Error> {
+    sqlx::query_as!(
+        DigestRecipe,
+        r#"
+        SELECT r.name, r.description
+        FROM recipes r
+        INNER JOIN cuisines c ON c.id = r.cuisine_id
+        WHERE r.org_id = $1
+          AND r.creator_id <> $2
+          AND NOT r.hidden
+          AND r.visibility = 'public'
+          AND r.created_at >= $3 AND r.created_at < $4
+          AND (
+            c.name = ANY($5::text[])
+            OR EXISTS (
+                SELECT 1
+                FROM ingredients_to_recipes ir
+                INNER JOIN ingredients i ON i.id = ir.ingredient_id
+                LEFT JOIN favorite_ingredient f
+                    ON f.ingredient_id = i.id AND f.user_id = $2
+                WHERE ir.recipe_id = r.id
+                  AND (f.user_id IS NOT NULL OR i.name = ANY($5::text[]))
+            )
+          )
+        ORDER BY r.created_at
+        LIMIT $6
+        "#,
+        org_id,
+        user_id,
+        period_start,
+        period_end,
+        topics,
+        MAX_DIGEST_RECIPES
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Periodically sends the due digests, until shutdown.
+pub(super) async fn digest_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    links: EmailLinks,
+    interval: Duration,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    while !*stop.borrow() {
+        match send_due_digests(&pool, &email_client, &links).await {
+            Ok(processed) => tracing::info!(processed, "Processed due digests"),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to process digests, retrying later."
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = stop.changed() => {}
+        }
+    }
+    Ok(())
+}
@@ -1,44 +1,270 @@
-use std::time::Duration;
+pub mod digest;
+pub mod outbox;
+pub mod retention;
 
-use sqlx::{postgres::PgPoolOptions, PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::config::{DatabaseSettings, Settings};
+use sqlx::{postgres::PgPoolOptions, Executor, PgConnection, PgPool, Postgres, Transaction};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+use tracing::{field::display, Instrument, Span};
 
-use crate::email::{Email, EmailClient};
+use crate::config::{DatabaseSettings, Settings, WorkerSettings};
+use crate::utils::shutdown_signal;
+
+use crate::email::{confirmation_message, Email, EmailClient, EmailLinks};
 use crate::error::ApiError;
+use crate::locale::Locale;
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
-    PgPoolOptions::new()
+    with_statement_timeout(PgPoolOptions::new(), configuration.statement_timeout())
         .acquire_timeout(std::time::Duration::from_secs(2))
         .connect_lazy_with(configuration.with_db())
 }
 
+/// Sets `statement_timeout` on every new connection of the pool, so a pathological query can't
+/// hold on to a connection forever.
+pub fn with_statement_timeout(options: PgPoolOptions, timeout: Option<Duration>) -> PgPoolOptions {
+    options.after_connect(move |conn, _meta| {
+        Box::pin(async move {
+            conn.execute(statement_timeout_query("SET", timeout).as_str())
+                .await?;
+            Ok(())
+        })
+    })
+}
+
+/// Overrides the statement timeout until the end of the transaction, for long-running
+/// operations like reindexing or exports. `None` disables the timeout.
+pub async fn set_local_statement_timeout(
+    tx: &mut PgConnection,
+    timeout: Option<Duration>,
+) -> Result<(), sqlx::Error> {
+    tx.execute(statement_timeout_query("SET LOCAL", timeout).as_str())
+        .await?;
+    Ok(())
+}
+
+// `SET` doesn't take bind parameters, but an integer is safe to format into the query.
+fn statement_timeout_query(set: &str, timeout: Option<Duration>) -> String {
+    let ms = timeout.map_or(0, |timeout| timeout.as_millis().max(1));
+    format!("{set} statement_timeout = {ms}")
+}
+
 pub async fn run_worker_until_stopped(
     mut configuration: tokio::sync::watch::Receiver<Settings>,
 ) -> Result<(), anyhow::Error> {
+    let settings = configuration.borrow_and_update().clone();
+    let links = EmailLinks::from_settings(&settings);
     let Settings {
         database,
         email_client,
+        worker,
         ..
-    } = configuration.borrow_and_update().clone();
+    } = settings;
     let connection_pool = get_connection_pool(&database);
     let email_client = email_client.client();
-    worker_loop(connection_pool, email_client).await
+    run_workers(
+        connection_pool,
+        email_client,
+        links,
+        &worker,
+        shutdown_signal(),
+    )
+    .await
+}
+
+const EMAIL_DELIVERY_JOB: &str = "email_delivery";
+
+/// Per job type concurrency caps, shared by every worker of the process.
+#[derive(Clone, Default)]
+struct JobTypeLimits(Arc<HashMap<String, Arc<Semaphore>>>);
+
+impl JobTypeLimits {
+    fn new(settings: &WorkerSettings) -> Self {
+        let limits = settings
+            .job_type_limits
+            .iter()
+            .map(|(job_type, &limit)| (job_type.clone(), Arc::new(Semaphore::new(limit.max(1)))))
+            .collect();
+        Self(Arc::new(limits))
+    }
+
+    /// Waits until the job type has a free slot. Job types without a limit never wait.
+    async fn acquire(&self, job_type: &str) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.0.get(job_type)?;
+        Arc::clone(semaphore).acquire_owned().await.ok()
+    }
+}
+
+/// Runs the configured number of workers, the expired token cleanup, the digests and the log
+/// retention purge until `shutdown` resolves.
+/// Workers finish the task they're processing before exiting, so this only returns once every
+/// in-flight task is done.
+pub async fn run_workers(
+    pool: PgPool,
+    email_client: EmailClient,
+    links: EmailLinks,
+    settings: &WorkerSettings,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), anyhow::Error> {
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    let limits = JobTypeLimits::new(settings);
+
+    let mut workers = JoinSet::new();
+    for worker_id in 0..settings.concurrency() {
+        workers.spawn(worker_loop(
+            worker_id,
+            pool.clone(),
+            email_client.clone(),
+            links.clone(),
+            limits.clone(),
+            stop_rx.clone(),
+        ));
+    }
+    workers.spawn(token_cleanup_loop(
+        pool.clone(),
+        settings.token_cleanup_interval(),
+        stop_rx.clone(),
+    ));
+    workers.spawn(digest::digest_loop(
+        pool.clone(),
+        email_client.clone(),
+        links,
+        settings.digest_interval(),
+        stop_rx.clone(),
+    ));
+    workers.spawn(retention::retention_loop(
+        pool.clone(),
+        settings.retention.clone(),
+        stop_rx.clone(),
+    ));
+
+    tokio::select! {
+        _ = shutdown => {}
+        // Workers only return on shutdown, so this is an unexpected exit, most likely a panic.
+        Some(outcome) = workers.join_next() => {
+            tracing::error!(?outcome, "Queue worker exited unexpectedly, stopping the rest.");
+        }
+    }
+
+    let _ = stop_tx.send(true);
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+async fn worker_loop(
+    worker_id: usize,
+    pool: PgPool,
+    email_client: EmailClient,
+    links: EmailLinks,
+    limits: JobTypeLimits,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    // Counts consecutive failed iterations, so a stuck queue is visible in the spans.
+    let mut attempt: u64 = 1;
+    while !*stop.borrow() {
+        let permit = limits.acquire(EMAIL_DELIVERY_JOB).await;
+        let span = tracing::info_span!(
+            "queue_task",
+            worker.id = worker_id,
+            task.id = tracing::field::Empty,
+            task.job_type = EMAIL_DELIVERY_JOB,
+            task.attempt = attempt,
+            task.duration_ms = tracing::field::Empty,
+        );
+        let started = Instant::now();
+        // `instrument` (instead of `span.enter()`) keeps the span correctly entered and exited
+        // across the `.await` points, so the recorded duration is accurate.
+        let outcome = try_execute_task(&pool, &email_client, &links)
+            .instrument(span.clone())
+            .await;
+        let elapsed = started.elapsed();
+        span.record("task.duration_ms", elapsed.as_millis() as u64);
+        drop(permit);
+
+        let backoff = match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                attempt = 1;
+                Duration::from_secs(10)
+            }
+            Err(e) => {
+                metrics::counter!("queue_tasks_failed_total", "job_type" => EMAIL_DELIVERY_JOB)
+                    .increment(1);
+                tracing::error!(
+                    parent: &span,
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to execute queue task, retrying."
+                );
+                attempt += 1;
+                Duration::from_secs(1)
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(ExecutionOutcome::TaskCompleted) => {
+                attempt = 1;
+                metrics::counter!("queue_tasks_processed_total", "job_type" => EMAIL_DELIVERY_JOB)
+                    .increment(1);
+                metrics::histogram!("queue_task_duration_seconds", "job_type" => EMAIL_DELIVERY_JOB)
+                    .record(elapsed.as_secs_f64());
+                continue;
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stop.changed() => {}
         }
     }
+    Ok(())
+}
+
+/// Periodically deletes the expired tokens, until shutdown.
+async fn token_cleanup_loop(
+    pool: PgPool,
+    interval: Duration,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    while !*stop.borrow() {
+        match delete_expired_tokens(&pool).await {
+            Ok(deleted) => tracing::info!(deleted, "Deleted expired tokens"),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to delete expired tokens, retrying later."
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = stop.changed() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the expired confirmation and password reset tokens, returning how many were deleted.
+/// The pending confirmation emails of the deleted tokens are dropped along with them.
+#[tracing::instrument(skip_all)]
+pub async fn delete_expired_tokens(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let confirmation = sqlx::query!("DELETE FROM confirmation_tokens WHERE expires_at < NOW()")
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let password_reset =
+        sqlx::query!("DELETE FROM forget_password_tokens WHERE expires_at < NOW()")
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    tx.commit().await?;
+    Ok(confirmation + password_reset)
 }
 
 pub enum ExecutionOutcome {
@@ -49,33 +275,39 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    links: &EmailLinks,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (mut transaction, confirmation_id, email) = task.unwrap();
-    Span::current()
-        .record("confirmation_id", display(confirmation_id.clone()))
-        .record("user_email", display(&email));
+    let (mut transaction, confirmation_id, email, locale) = task.unwrap();
+    // The confirmation id is a secret token, so only a digest of it is recorded.
+    Span::current().record("task.id", display(task_id(&confirmation_id)));
+    let locale = locale
+        .as_deref()
+        .and_then(Locale::parse)
+        .unwrap_or_default();
     match Email::parse(email.clone()) {
         Ok(email) => {
             if let Err(e) = email_client
-                .send_mail(
+                .send_message(confirmation_message(
                     email.clone(),
-                    "Recipe App confirm registration",
-                    &format!(
-                        "Visit http://localhost:3001/confirm?token={} to confirm your registration.",
-                        confirmation_id
-                    ),
-                    &format!(
-                        "Visit <a href=http://localhost:3001/confirm?token={}>the website</a> to confirm your registration.",
-                        confirmation_id
-                    ),
-                )
+                    locale,
+                    links,
+                    &confirmation_id,
+                ))
                 .await
             {
-                insert_failed_task(&mut *transaction, confirmation_id.clone(), email.as_ref(), &e).await?;
+                insert_failed_task(
+                    &mut *transaction,
+                    confirmation_id.clone(),
+                    email.as_ref(),
+                    &e,
+                )
+                .await?;
+                metrics::counter!("queue_tasks_failed_total", "job_type" => EMAIL_DELIVERY_JOB)
+                    .increment(1);
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
@@ -86,6 +318,8 @@ pub async fn try_execute_task(
         }
         Err(e) => {
             insert_failed_task(&mut *transaction, confirmation_id.clone(), &email, &e).await?;
+            metrics::counter!("queue_tasks_failed_total", "job_type" => EMAIL_DELIVERY_JOB)
+                .increment(1);
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
@@ -98,18 +332,27 @@ pub async fn try_execute_task(
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// A stable, non-reversible identifier of a task, safe to put in logs and spans.
+fn task_id(confirmation_id: &str) -> String {
+    blake3::hash(confirmation_id.as_bytes()).to_hex()[..16].to_owned()
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
+/// The transaction holding the task's row lock, then its confirmation id, email and locale.
+type DequeuedTask = (PgTransaction, String, String, Option<String>);
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
-    pool: &PgPool,
-) -> Result<Option<(PgTransaction, String, String)>, anyhow::Error> {
+async fn dequeue_task(pool: &PgPool) -> Result<Option<DequeuedTask>, anyhow::Error> {
     let mut tx = pool.begin().await?;
+    // `SKIP LOCKED` lets concurrent workers claim different rows instead of waiting on each other,
+    // and the row lock is held until `delete_task` commits, so no task is processed twice.
     let r = sqlx::query!(
         r#"
-        SELECT confirmation_id, user_email
-        FROM confirmation_delivery_queue
-        FOR UPDATE
+        SELECT q.confirmation_id, q.user_email, u.locale
+        FROM confirmation_delivery_queue q
+        INNER JOIN confirmation_tokens ct ON ct.confirmation_token = q.token_hash
+        INNER JOIN users u ON u.user_id = ct.user_id
+        FOR UPDATE OF q
         SKIP LOCKED
         LIMIT 1
         "#,
@@ -117,7 +360,7 @@ async fn dequeue_task(
     .fetch_optional(&mut *tx)
     .await?;
     if let Some(r) = r {
-        Ok(Some((tx, r.confirmation_id, r.user_email)))
+        Ok(Some((tx, r.confirmation_id, r.user_email, r.locale)))
     } else {
         Ok(None)
     }
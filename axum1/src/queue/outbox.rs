@@ -0,0 +1,139 @@
+//! A transactional outbox for the notifications that must not get lost.
+//!
+//! Sending a notification right after committing loses it if the process dies in between. Instead
+//! it's written to `events_outbox` in the transaction of the change that caused it, so it exists
+//! if and only if the change does, and the relay publishes it afterwards. Every event is published
+//! at least once: one that was sent but not yet marked as sent is sent again.
+use std::time::Duration;
+
+use sqlx::{Acquire, PgConnection, PgPool};
+use tokio::sync::broadcast;
+
+use crate::sse::Notification;
+
+/// The most events published by one relay run.
+const BATCH_SIZE: i64 = 100;
+
+/// Writes the notification to the outbox. Call it in the transaction of the change, after the
+/// notification is saved for its recipient, so the saved copy's id goes out with it.
+pub async fn enqueue(
+    conn: &mut PgConnection,
+    notification: &Notification,
+) -> Result<uuid::Uuid, sqlx::Error> {
+    let payload = serde_json::to_value(notification).expect("notifications are serializable");
+    sqlx::query_scalar!(
+        "INSERT INTO events_outbox (kind, recipient, payload) VALUES ($1, $2, $3) RETURNING id",
+        notification.name(),
+        notification.recipient(),
+        payload
+    )
+    .fetch_one(&mut *conn)
+    .await
+}
+
+/// Publishes the due events of the outbox and marks them sent. Returns how many were published.
+///
+/// Events that can't be published are retried later, with an exponential backoff. Concurrent
+/// relays skip the events the others are publishing, instead of waiting for them.
+#[tracing::instrument(skip_all)]
+pub async fn relay_pending(
+    conn: &mut PgConnection,
+    channel: &broadcast::Sender<Notification>,
+) -> Result<usize, anyhow::Error> {
+    let mut tx = conn.begin().await?;
+    let events = sqlx::query!(
+        r#"
+        SELECT id, kind, recipient, payload FROM events_outbox
+        WHERE sent_at IS NULL AND next_attempt_at <= NOW()
+        ORDER BY created_at
+        LIMIT $1
+        FOR UPDATE SKIP LOCKED
+        "#,
+        BATCH_SIZE
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut sent = Vec::with_capacity(events.len());
+    for event in events {
+        let outcome = Notification::from_stored(&event.kind, event.recipient, event.payload)
+            .and_then(|notification| {
+                channel
+                    .send(notification)
+                    .map_err(|_| anyhow::anyhow!("nobody is listening"))
+            });
+        match outcome {
+            Ok(_) => sent.push(event.id),
+            Err(e) => {
+                tracing::warn!(
+                    event.id = %event.id,
+                    event.kind = %event.kind,
+                    error.message = %e,
+                    "Failed to publish an event, retrying later."
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE events_outbox
+                    SET attempts = attempts + 1,
+                        next_attempt_at = NOW() + make_interval(secs => power(2, LEAST(attempts, 10))),
+                        last_error = $2
+                    WHERE id = $1
+                    "#,
+                    event.id,
+                    e.to_string()
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE events_outbox SET sent_at = NOW() WHERE id = ANY($1)",
+        &sent
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    metrics::counter!("outbox_events_published_total").increment(sent.len() as u64);
+    Ok(sent.len())
+}
+
+/// Publishes the pending events right away, instead of waiting for the next relay run. Failing
+/// is fine, the relay retries.
+pub async fn relay_now(conn: &mut PgConnection, channel: &broadcast::Sender<Notification>) {
+    if let Err(e) = relay_pending(conn, channel).await {
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to publish events, the relay will retry."
+        );
+    }
+}
+
+/// Periodically publishes the pending events, until `stop` changes.
+///
+/// The events go to the clients connected to this instance, so it runs next to the server, not in
+/// the queue workers.
+pub async fn relay_loop(
+    pool: PgPool,
+    channel: broadcast::Sender<Notification>,
+    interval: Duration,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    while !*stop.borrow() {
+        match pool.acquire().await {
+            Ok(mut conn) => relay_now(&mut conn, &channel).await,
+            Err(e) => tracing::warn!(
+                error.message = %e,
+                "Failed to connect to publish events, retrying later."
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = stop.changed() => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::config::RetentionSettings;
+
+/// Gives request traffic a chance at the tables between two batches.
+const BATCH_PAUSE: Duration = Duration::from_millis(50);
+
+/// The log tables, each kept for its own configured number of days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCategory {
+    /// Finished impersonations. Ongoing ones are never purged.
+    Security,
+    /// Announcements broadcast by admins.
+    Admin,
+    /// Queue jobs that failed.
+    FailedJobs,
+    /// Notifications of users.
+    Notifications,
+    /// Outbox events that were published.
+    Outbox,
+    /// Deleted recipes.
+    Tombstones,
+}
+
+impl LogCategory {
+    pub const ALL: [Self; 6] = [
+        Self::Security,
+        Self::Admin,
+        Self::FailedJobs,
+        Self::Notifications,
+        Self::Outbox,
+        Self::Tombstones,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Security => "security",
+            Self::Admin => "admin",
+            Self::FailedJobs => "failed_jobs",
+            Self::Notifications => "notifications",
+            Self::Outbox => "outbox",
+            Self::Tombstones => "tombstones",
+        }
+    }
+
+    fn retention_days(self, settings: &RetentionSettings) -> u32 {
+        match self {
+            Self::Security => settings.security_days(),
+            Self::Admin => settings.admin_days(),
+            Self::FailedJobs => settings.failed_jobs_days(),
+            Self::Notifications => settings.notifications_days(),
+            Self::Outbox => settings.outbox_days(),
+            Self::Tombstones => settings.tombstones_days(),
+        }
+    }
+}
+
+/// Deletes at most `batch_size` rows of the category older than `days`, returning how many were
+/// deleted. Rows locked by a request are skipped instead of waited for, the next run gets them.
+async fn purge_batch(
+    pool: &PgPool,
+    category: LogCategory,
+    days: i32,
+    batch_size: i64,
+) -> Result<u64, sqlx::Error> {
+    let query = match category {
+        LogCategory::Security => sqlx::query!(
+            r#"
+            DELETE FROM impersonations WHERE id IN (
+                SELECT id FROM impersonations
+                WHERE ended_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+        LogCategory::Admin => sqlx::query!(
+            r#"
+            DELETE FROM announcements WHERE id IN (
+                SELECT id FROM announcements
+                WHERE created_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+        LogCategory::FailedJobs => sqlx::query!(
+            r#"
+            DELETE FROM failed_jobs WHERE job_id IN (
+                SELECT job_id FROM failed_jobs
+                WHERE failed_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+        LogCategory::Notifications => sqlx::query!(
+            r#"
+            DELETE FROM notifications WHERE id IN (
+                SELECT id FROM notifications
+                WHERE created_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+        LogCategory::Outbox => sqlx::query!(
+            r#"
+            DELETE FROM events_outbox WHERE id IN (
+                SELECT id FROM events_outbox
+                WHERE sent_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+        LogCategory::Tombstones => sqlx::query!(
+            r#"
+            DELETE FROM recipe_tombstones WHERE recipe_id IN (
+                SELECT recipe_id FROM recipe_tombstones
+                WHERE deleted_at < NOW() - make_interval(days => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            days,
+            batch_size
+        ),
+    };
+    Ok(query.execute(pool).await?.rows_affected())
+}
+
+/// Deletes the rows of every log table past its retention window, returning how many were
+/// deleted per category.
+///
+/// Every batch is its own statement, so locks are only held for the duration of a batch.
+#[tracing::instrument(skip_all)]
+pub async fn purge_expired_logs(
+    pool: &PgPool,
+    settings: &RetentionSettings,
+) -> Result<Vec<(LogCategory, u64)>, sqlx::Error> {
+    let batch_size = settings.batch_size();
+    let mut purged = Vec::with_capacity(LogCategory::ALL.len());
+    for category in LogCategory::ALL {
+        let days = category.retention_days(settings);
+        if days == 0 {
+            continue;
+        }
+        let days = i32::try_from(days).unwrap_or(i32::MAX);
+
+        let mut total = 0;
+        loop {
+            let deleted = purge_batch(pool, category, days, batch_size).await?;
+            total += deleted;
+            if deleted < batch_size as u64 {
+                break;
+            }
+            tokio::time::sleep(BATCH_PAUSE).await;
+        }
+        metrics::counter!("log_rows_purged_total", "category" => category.name()).increment(total);
+        purged.push((category, total));
+    }
+    Ok(purged)
+}
+
+/// Periodically purges the log tables, until shutdown.
+pub(super) async fn retention_loop(
+    pool: PgPool,
+    settings: RetentionSettings,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) -> Result<(), anyhow::Error> {
+    while !*stop.borrow() {
+        match purge_expired_logs(&pool, &settings).await {
+            Ok(purged) => {
+                for (category, purged) in purged {
+                    tracing::info!(category = category.name(), purged, "Purged expired logs");
+                }
+            }
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to purge expired logs, retrying later."
+            ),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(settings.interval()) => {}
+            _ = stop.changed() => {}
+        }
+    }
+    Ok(())
+}
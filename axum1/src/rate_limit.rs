@@ -0,0 +1,97 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRef, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    error::ApiError,
+    extractors::{ClientIp, TrustedProxies},
+};
+
+/// A simple in-memory, fixed window rate limiter keyed by the client's IP address.
+///
+/// It's local to a single instance, which is good enough for cheap endpoints that we just
+/// don't want to be hammered.
+#[derive(Clone)]
+pub struct RateLimiter {
+    windows: Arc<Mutex<HashMap<IpAddr, Window>>>,
+    max_requests: u32,
+    period: Duration,
+    trusted_proxies: TrustedProxies,
+}
+
+struct Window {
+    started_at: Instant,
+    requests: u32,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, period: Duration) -> Self {
+        Self {
+            windows: Arc::new(Mutex::new(HashMap::new())),
+            max_requests,
+            period,
+            trusted_proxies: TrustedProxies::default(),
+        }
+    }
+
+    /// Keys the requests forwarded by these proxies by the original client's address.
+    pub fn trusting(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    pub fn per_minute(max_requests: u32) -> Self {
+        Self::new(max_requests, Duration::from_secs(60))
+    }
+
+    /// Registers a request from `key`, and returns whether it's allowed to proceed.
+    pub fn check(&self, key: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        // Don't let the map grow unbounded with clients we haven't seen in a while.
+        if windows.len() > 10_000 {
+            windows.retain(|_, w| now.duration_since(w.started_at) < self.period);
+        }
+
+        let window = windows.entry(key).or_insert(Window {
+            started_at: now,
+            requests: 0,
+        });
+        if now.duration_since(window.started_at) >= self.period {
+            window.started_at = now;
+            window.requests = 0;
+        }
+        window.requests += 1;
+        window.requests <= self.max_requests
+    }
+}
+
+impl FromRef<RateLimiter> for TrustedProxies {
+    fn from_ref(limiter: &RateLimiter) -> Self {
+        limiter.trusted_proxies.clone()
+    }
+}
+
+/// Middleware rejecting requests with `429 Too Many Requests` once a client exceeds the limit.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    if limiter.check(ip) {
+        next.run(request).await
+    } else {
+        ApiError::TooManyRequests.into_response()
+    }
+}
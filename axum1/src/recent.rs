@@ -0,0 +1,80 @@
+//! The recipes each user looked at recently, kept in Redis so serving a recipe never writes to
+//! the database.
+use sqlx::PgPool;
+use tower_sessions_redis_store::fred::{
+    clients::RedisPool,
+    error::RedisError,
+    interfaces::{KeysInterface, ListInterface},
+};
+
+/// Histories of users who stopped coming back expire after this long.
+const HISTORY_TTL_SECONDS: i64 = 90 * 24 * 60 * 60;
+
+/// A capped, most-recent-first list of recipe slugs per user, without duplicates. Slugs never
+/// change, unlike names.
+#[derive(Clone)]
+pub struct RecentlyViewed {
+    redis: RedisPool,
+    limit: usize,
+}
+
+impl RecentlyViewed {
+    pub fn new(redis: RedisPool, limit: usize) -> Self {
+        Self {
+            redis,
+            limit: limit.max(1),
+        }
+    }
+
+    fn key(user_id: uuid::Uuid) -> String {
+        format!("recently_viewed:{user_id}")
+    }
+
+    /// Moves the recipe to the front of the user's history, dropping the oldest entries over
+    /// the limit.
+    pub async fn record(&self, user_id: uuid::Uuid, recipe_slug: &str) -> Result<(), RedisError> {
+        let key = Self::key(user_id);
+        // Sent at once, so a concurrent view can't observe the list between the steps.
+        let pipeline = self.redis.next().pipeline();
+        let _: () = pipeline.lrem(&key, 0, recipe_slug).await?;
+        let _: () = pipeline.lpush(&key, recipe_slug).await?;
+        let _: () = pipeline.ltrim(&key, 0, self.limit as i64 - 1).await?;
+        let _: () = pipeline.expire(&key, HISTORY_TTL_SECONDS).await?;
+        let _: () = pipeline.all().await?;
+        Ok(())
+    }
+
+    /// The recipe slugs, the most recently viewed first.
+    pub async fn list(&self, user_id: uuid::Uuid) -> Result<Vec<String>, RedisError> {
+        self.redis
+            .lrange(Self::key(user_id), 0, self.limit as i64 - 1)
+            .await
+    }
+
+    pub async fn clear(&self, user_id: uuid::Uuid) -> Result<(), RedisError> {
+        self.redis.del(Self::key(user_id)).await
+    }
+
+    /// Records a view, unless the user turned tracking off. Meant to be spawned after the
+    /// response is ready, failures are only logged.
+    pub async fn record_if_enabled(self, pool: PgPool, user_id: uuid::Uuid, recipe_slug: String) {
+        let enabled = sqlx::query_scalar!(
+            "SELECT track_recently_viewed FROM users WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&pool)
+        .await;
+        let outcome = match enabled {
+            Ok(Some(true)) => self.record(user_id, &recipe_slug).await.map_err(Into::into),
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e)),
+        };
+        if let Err(e) = outcome {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record a recently viewed recipe"
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{extract::State, Json};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection},
+    org::Org,
+    sse::{Notification, SystemAnnouncement},
+    state::AppState,
+};
+
+/// Who an announcement is addressed to, always within the organization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Audience {
+    All,
+    Admins,
+}
+
+impl Audience {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Admins => "admins",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Broadcast {
+    pub title: String,
+    pub body: String,
+    #[serde(default = "default_audience")]
+    pub audience: Audience,
+    /// Save it for every recipient, so the ones who aren't connected see it later in
+    /// `GET /me/notifications`. Otherwise only the connected users ever see it.
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn default_audience() -> Audience {
+    Audience::All
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BroadcastOutcome {
+    pub id: uuid::Uuid,
+    pub recipients: usize,
+}
+
+/// Records the announcement (and saves it for the recipients if asked to), and returns the
+/// notification to send to the connected ones.
+pub async fn create_announcement(
+    conn: &mut PgConnection,
+    org: Org,
+    author_id: uuid::Uuid,
+    broadcast: &Broadcast,
+) -> Result<SystemAnnouncement, ApiError> {
+    let title = broadcast.title.trim();
+    let body = broadcast.body.trim();
+    if title.is_empty() || body.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "announcement",
+            "title and body must not be empty",
+        )]));
+    }
+
+    let announcement = sqlx::query!(
+        r#"
+        INSERT INTO announcements (org_id, author_id, audience, title, body, persisted)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, created_at
+        "#,
+        *org,
+        author_id,
+        broadcast.audience.as_str(),
+        title,
+        body,
+        broadcast.persist
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let recipients = sqlx::query_scalar!(
+        r#"
+        SELECT user_id FROM users
+        WHERE org_id = $1 AND ($2 = 'all' OR is_admin OR is_super_admin)
+        "#,
+        *org,
+        broadcast.audience.as_str()
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let announcement = SystemAnnouncement {
+        id: announcement.id,
+        title: title.to_owned(),
+        body: body.to_owned(),
+        created_at: announcement.created_at,
+        recipients: Arc::new(recipients.iter().copied().collect::<HashSet<_>>()),
+    };
+
+    if broadcast.persist {
+        Notification::SystemAnnouncement(announcement.clone())
+            .save_for_users(&mut *conn, &recipients)
+            .await?;
+    }
+
+    Ok(announcement)
+}
+
+/// Announces something to the users of the organization, or only to its admins.
+#[tracing::instrument(skip(tx, conn, admin))]
+pub async fn broadcast(
+    State(AppState { tx, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    admin: AuthUser,
+    Json(broadcast): Json<Broadcast>,
+) -> Result<Json<BroadcastOutcome>, ApiError> {
+    let mut db_tx = conn.begin().await?;
+    let announcement = create_announcement(&mut db_tx, org, *admin, &broadcast).await?;
+    db_tx.commit().await?;
+
+    tracing::info!(
+        announcement_id = %announcement.id,
+        admin_id = %*admin,
+        audience = broadcast.audience.as_str(),
+        "Broadcasting an announcement"
+    );
+
+    let outcome = BroadcastOutcome {
+        id: announcement.id,
+        recipients: announcement.recipients.len(),
+    };
+    // Nobody being connected isn't an error, the saved copies are still there.
+    let _ = tx.send(Notification::SystemAnnouncement(announcement));
+
+    Ok(Json(outcome))
+}
@@ -0,0 +1,103 @@
+use axum::extract::{Path, State};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, Json},
+    org::Org,
+    sse::{Notification, SecurityAlertReason},
+    state::AppState,
+};
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConfirmEmailOutcome {
+    /// Set if the user had already confirmed their email, in which case nothing changed.
+    pub already_confirmed: bool,
+}
+
+/// Confirms `user_id`'s email on their behalf, dropping their outstanding confirmation tokens
+/// (and with them, the queued confirmation emails) and auditing who did it.
+///
+/// Only users of the organization may be confirmed, unless the admin is a super-admin.
+///
+/// Returns the alert for the user, already saved for them, or `None` if the email was already
+/// confirmed. Send it once the transaction is committed.
+pub async fn confirm_user_email(
+    conn: &mut PgConnection,
+    org: Org,
+    admin_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+) -> Result<Option<Notification>, ApiError> {
+    let target = sqlx::query!(
+        r#"
+        SELECT t.confirmed, t.org_id, a.is_super_admin AS actor_is_super_admin
+        FROM users t, users a
+        WHERE t.user_id = $1 AND a.user_id = $2
+        FOR UPDATE OF t
+        "#,
+        user_id,
+        admin_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+    // Users of other organizations don't exist as far as their admins are concerned.
+    if target.org_id != *org && !target.actor_is_super_admin {
+        return Err(ApiError::NotFound);
+    }
+    if target.confirmed {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "UPDATE users SET confirmed = TRUE WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM confirmation_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO admin_email_confirmations (admin_id, user_id) VALUES ($1, $2)",
+        admin_id,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let alert = Notification::security_alert(user_id, SecurityAlertReason::EmailConfirmedByAdmin)
+        .save_for_recipient(&mut *conn)
+        .await?;
+    Ok(Some(alert))
+}
+
+/// For users whose confirmation emails never arrive. Must be behind the `AdminUser` guard.
+#[tracing::instrument(skip(tx, conn))]
+pub async fn confirm_email(
+    admin: AuthUser,
+    State(AppState { tx, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Path(user_id): Path<uuid::Uuid>,
+) -> Result<Json<ConfirmEmailOutcome>, ApiError> {
+    let mut db_tx = conn.begin().await?;
+    let alert = confirm_user_email(&mut db_tx, org, *admin, user_id).await?;
+    db_tx.commit().await?;
+
+    let already_confirmed = alert.is_none();
+    if let Some(alert) = alert {
+        tracing::warn!(
+            admin_id = %*admin,
+            %user_id,
+            "admin confirmed a user's email"
+        );
+        // Users who aren't connected see the saved copy later.
+        let _ = tx.send(alert);
+    }
+
+    Ok(Json(ConfirmEmailOutcome { already_confirmed }))
+}
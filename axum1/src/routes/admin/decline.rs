@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+
+use axum::extract::State;
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, Json},
+    org::Org,
+    queue::outbox,
+    sse::Notification,
+    state::AppState,
+};
+
+/// The most suggestions declined at once.
+pub const MAX_DECLINED_SUGGESTIONS: usize = 100;
+
+/// The longest decline reason, in characters.
+const MAX_REASON_LENGTH: usize = 500;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DeclineSuggestions {
+    pub ids: Vec<uuid::Uuid>,
+    /// Kept in the audit trail, and sent to the authors if they're notified.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Let the authors know their suggestions were declined.
+    #[serde(default)]
+    pub notify_authors: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DeclineOutcome {
+    pub deleted: usize,
+    /// The ids of suggestions that don't exist, e.g. because they were already applied or
+    /// declined, or that belong to another organization.
+    pub not_found: usize,
+}
+
+/// Deletes the suggestions of the organization among `ids`, recording each in the audit trail.
+/// The ones that don't exist are counted, but don't fail the rest.
+///
+/// Notifications for the authors go through the outbox, so run it in a transaction and relay
+/// them once it's committed. Suggestions for ingredients that don't exist belong to the
+/// organization of their author.
+pub async fn decline_suggestion_batch(
+    conn: &mut PgConnection,
+    org: Org,
+    moderator_id: uuid::Uuid,
+    request: &DeclineSuggestions,
+) -> Result<DeclineOutcome, ApiError> {
+    let ids: Vec<_> = request
+        .ids
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if ids.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "ids",
+            "at least one suggestion is required",
+        )]));
+    }
+    if ids.len() > MAX_DECLINED_SUGGESTIONS {
+        return Err(ApiError::unprocessable_entity([(
+            "ids",
+            format!("at most {MAX_DECLINED_SUGGESTIONS} suggestions can be declined at once"),
+        )]));
+    }
+    let reason = request
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH) {
+        return Err(ApiError::unprocessable_entity([(
+            "reason",
+            format!("must be at most {MAX_REASON_LENGTH} characters"),
+        )]));
+    }
+
+    let declined = sqlx::query!(
+        r#"
+        WITH declined AS (
+            DELETE FROM ingredient_suggestions s
+            USING ingredient_suggestions found
+            LEFT JOIN ingredients i ON i.id = found.ingredient_id
+            LEFT JOIN users u ON u.user_id = found.user_id
+            WHERE s.id = found.id AND found.id = ANY($1)
+                AND COALESCE(i.org_id, u.org_id) = $2
+            RETURNING s.id, s.user_id, COALESCE(i.name, s.name) AS ingredient
+        )
+        INSERT INTO suggestion_declines (org_id, suggestion_id, moderator_id, user_id, ingredient, reason)
+        SELECT $2, id, $3, user_id, ingredient, $4 FROM declined
+        RETURNING user_id, ingredient
+        "#,
+        &ids,
+        *org,
+        moderator_id,
+        reason
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if request.notify_authors {
+        // One notification per author and ingredient, however many of their suggestions went.
+        let authors: BTreeSet<_> = declined
+            .iter()
+            .filter_map(|row| Some((row.user_id?, row.ingredient.clone())))
+            .collect();
+        for (user_id, ingredient) in authors {
+            let notification =
+                Notification::suggestion_declined(user_id, ingredient, reason.map(str::to_owned))
+                    .save_for_recipient(&mut *conn)
+                    .await?;
+            outbox::enqueue(&mut *conn, &notification).await?;
+        }
+    }
+
+    Ok(DeclineOutcome {
+        deleted: declined.len(),
+        not_found: ids.len() - declined.len(),
+    })
+}
+
+/// `POST /admin/suggestions/decline`, for clearing spam. Must be behind the `AdminUser` guard,
+/// admins act as the moderators.
+#[tracing::instrument(skip(channel, conn, request))]
+pub async fn decline_suggestions(
+    moderator: AuthUser,
+    State(AppState { tx: channel, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Json(request): Json<DeclineSuggestions>,
+) -> Result<Json<DeclineOutcome>, ApiError> {
+    let mut tx = conn.begin().await?;
+    let outcome = decline_suggestion_batch(&mut tx, org, *moderator, &request).await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        moderator_id = %*moderator,
+        deleted = outcome.deleted,
+        not_found = outcome.not_found,
+        "declined suggestions"
+    );
+    if request.notify_authors {
+        outbox::relay_now(&mut conn, &channel).await;
+    }
+
+    Ok(Json(outcome))
+}
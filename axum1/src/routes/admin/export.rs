@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+};
+use futures::Stream;
+use sqlx::PgPool;
+
+use crate::{
+    error::ApiError, org::Org, queue::set_local_statement_timeout, state::AppState,
+    time_range::TimeRange,
+};
+
+/// Exports may legitimately run for a long time, the usual statement timeout is too strict.
+const EXPORT_STATEMENT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The rows fetched from the cursor at once.
+const EXPORT_CHUNK_ROWS: usize = 500;
+
+/// The tables that can be exported. Only the listed columns are, secrets like password hashes
+/// never leave the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTable {
+    Recipes,
+    Users,
+}
+
+impl ExportTable {
+    /// Parses the `<table>.ndjson` file name of the export route.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        match file_name.strip_suffix(".ndjson")? {
+            "recipes" => Some(Self::Recipes),
+            "users" => Some(Self::Users),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Recipes => "recipes",
+            Self::Users => "users",
+        }
+    }
+
+    /// Postgres renders each row as JSON, the binds are the organization and the time range.
+    fn query(&self) -> &'static str {
+        match self {
+            Self::Recipes => {
+                r#"
+                SELECT row_to_json(e)::text FROM (
+                    SELECT r.id, r.name, r.description, u.name AS creator, r.prep_time,
+                        r.cook_time, r.difficulty, r.steps, c.name AS cuisine, r.meal_type,
+                        r.hidden, r.created_at, r.updated_at
+                    FROM recipes r
+                    INNER JOIN users u ON u.user_id = r.creator_id
+                    INNER JOIN cuisines c ON c.id = r.cuisine_id
+                    WHERE r.org_id = $1
+                      AND ($2::timestamptz IS NULL OR r.created_at >= $2)
+                      AND ($3::timestamptz IS NULL OR r.created_at < $3)
+                    ORDER BY r.created_at, r.id
+                ) e
+                "#
+            }
+            Self::Users => {
+                r#"
+                SELECT row_to_json(e)::text FROM (
+                    SELECT user_id, name, email, confirmed, is_admin, locale, oauth_provider,
+                        created_at, updated_at
+                    FROM users
+                    WHERE org_id = $1
+                      AND ($2::timestamptz IS NULL OR created_at >= $2)
+                      AND ($3::timestamptz IS NULL OR created_at < $3)
+                    ORDER BY created_at, user_id
+                ) e
+                "#
+            }
+        }
+    }
+}
+
+/// Streams the table as newline-delimited JSON.
+///
+/// Rows are only fetched as fast as the stream is consumed, so a slow client holds a connection
+/// (and a transaction) for longer, but never makes the whole table pile up in memory.
+///
+/// They're read through a cursor, a chunk at a time. When the client disconnects, the stream is
+/// dropped between two chunks at most, rolling back the transaction. That closes the cursor and
+/// returns the connection right away, instead of Postgres running the query to the end.
+pub fn export_stream(
+    pool: PgPool,
+    org: Org,
+    table: ExportTable,
+    range: TimeRange,
+) -> impl Stream<Item = Result<Bytes, sqlx::Error>> {
+    async_stream::try_stream! {
+        let mut tx = pool.begin().await?;
+        set_local_statement_timeout(&mut tx, Some(EXPORT_STATEMENT_TIMEOUT)).await?;
+
+        let declare = format!("DECLARE export_cursor NO SCROLL CURSOR FOR {}", table.query());
+        sqlx::query(&declare)
+            .bind(*org)
+            .bind(range.since)
+            .bind(range.until)
+            .execute(&mut *tx)
+            .await?;
+
+        let fetch = format!("FETCH FORWARD {EXPORT_CHUNK_ROWS} FROM export_cursor");
+        loop {
+            let lines = sqlx::query_scalar::<_, String>(&fetch)
+                .fetch_all(&mut *tx)
+                .await?;
+            let exhausted = lines.len() < EXPORT_CHUNK_ROWS;
+            for mut line in lines {
+                line.push('\n');
+                yield Bytes::from(line);
+            }
+            if exhausted {
+                break;
+            }
+        }
+        // Nothing was written, committing would only close the cursor too.
+        tx.rollback().await?;
+    }
+}
+
+/// Reads from the replica, if there's one.
+#[tracing::instrument(skip(state))]
+pub async fn export(
+    State(state): State<AppState>,
+    org: Org,
+    Path(file_name): Path<String>,
+    range: TimeRange,
+) -> Result<impl IntoResponse, ApiError> {
+    let table = ExportTable::from_file_name(&file_name).ok_or(ApiError::NotFound)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/x-ndjson".to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.ndjson\"", table.as_str()),
+            ),
+        ],
+        Body::from_stream(export_stream(state.read_pool().clone(), org, table, range)),
+    ))
+}
@@ -0,0 +1,89 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::Json;
+use meilisearch_sdk::client::Client;
+
+use crate::{
+    error::ApiError,
+    extractors::MeiliConnection,
+    search::{indexing_status, IndexingStatus},
+};
+
+/// Don't let an unresponsive MeiliSearch hang the health check.
+const MEILI_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeiliStatus {
+    Ok,
+    /// MeiliSearch is unreachable or its stats couldn't be read. Search falls back to Postgres.
+    Degraded,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MeiliHealth {
+    pub status: MeiliStatus,
+    pub reachable: bool,
+    /// Why the status is degraded.
+    pub error: Option<String>,
+    pub indexes: BTreeMap<String, MeiliIndexStats>,
+    pub indexing: IndexingStatus,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MeiliIndexStats {
+    pub documents: usize,
+    pub is_indexing: bool,
+}
+
+/// Collects the state of MeiliSearch. Failures are reported in the result, so this can be called
+/// precisely when MeiliSearch is down.
+pub async fn meili_health(client: &Client) -> MeiliHealth {
+    let mut health = MeiliHealth {
+        status: MeiliStatus::Degraded,
+        reachable: false,
+        error: None,
+        indexes: BTreeMap::new(),
+        indexing: indexing_status(),
+    };
+
+    match tokio::time::timeout(MEILI_TIMEOUT, client.health()).await {
+        Ok(Ok(_)) => health.reachable = true,
+        Ok(Err(e)) => {
+            health.error = Some(e.to_string());
+            return health;
+        }
+        Err(_) => {
+            health.error = Some("timed out".to_owned());
+            return health;
+        }
+    }
+
+    match tokio::time::timeout(MEILI_TIMEOUT, client.get_stats()).await {
+        Ok(Ok(stats)) => {
+            health.status = MeiliStatus::Ok;
+            health.indexes = stats
+                .indexes
+                .into_iter()
+                .map(|(name, index)| {
+                    let stats = MeiliIndexStats {
+                        documents: index.number_of_documents,
+                        is_indexing: index.is_indexing,
+                    };
+                    (name, stats)
+                })
+                .collect();
+        }
+        Ok(Err(e)) => health.error = Some(e.to_string()),
+        Err(_) => health.error = Some("timed out reading stats".to_owned()),
+    }
+
+    health
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn meili(
+    MeiliConnection(client): MeiliConnection,
+) -> Result<Json<MeiliHealth>, ApiError> {
+    Ok(Json(meili_health(&client).await))
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, Json},
+    org::Org,
+    routes::recipe::nutrition::refresh_recipe_nutrition,
+    sse::Notification,
+    state::AppState,
+};
+
+/// Which ingredient's nutrition data the merged ingredient keeps. Optional data only one of
+/// them has, like the weight of a piece, the price or the dietary flags, is kept either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NutritionPolicy {
+    #[default]
+    Target,
+    Source,
+}
+
+/// What to do with recipes that use both ingredients, which would end up using the target
+/// twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Keep the quantity of the target.
+    KeepTarget,
+    /// Keep the quantity of the source.
+    KeepSource,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MergeIngredients {
+    /// The ingredient that's merged and deleted.
+    pub source: String,
+    /// The ingredient that's kept.
+    pub target: String,
+    #[serde(default)]
+    pub nutrition: NutritionPolicy,
+    /// Required if a recipe uses both ingredients, the merge is refused otherwise.
+    pub duplicates: Option<DuplicatePolicy>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MergeOutcome {
+    /// The recipes that used the source, now using the target.
+    pub recipes: Vec<String>,
+    /// The recipes that used both, resolved by the `duplicates` policy.
+    pub duplicates: Vec<String>,
+    /// The suggestions moved over to the target. Suggestions of users who already had one for
+    /// the target are deleted with the source.
+    pub suggestions_moved: u64,
+}
+
+/// Merges `source` into `target`: every recipe, suggestion and favorite of the source moves
+/// over to the target, then the source is deleted.
+///
+/// Returns the notifications for the owners of the affected recipes, already saved for them.
+/// Send them once the transaction is committed.
+pub async fn merge_ingredients(
+    conn: &mut PgConnection,
+    org: Org,
+    merge: &MergeIngredients,
+) -> Result<(MergeOutcome, Vec<Notification>), ApiError> {
+    // In a stable order, so concurrent merges of the same pair can't deadlock.
+    let locked = sqlx::query!(
+        r#"
+        SELECT id, name FROM ingredients
+        WHERE org_id = $1 AND (name = $2 OR name = $3)
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        *org,
+        merge.source,
+        merge.target
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let find = |name: &str| {
+        locked
+            .iter()
+            .find(|row| row.name.to_lowercase() == name.to_lowercase())
+            .map(|row| (row.id, row.name.clone()))
+            .ok_or(ApiError::NotFound)
+    };
+    let (source_id, source_name) = find(&merge.source)?;
+    let (target_id, target_name) = find(&merge.target)?;
+    if source_id == target_id {
+        return Err(ApiError::unprocessable_entity([(
+            "target",
+            "must be a different ingredient than the source",
+        )]));
+    }
+
+    let duplicates = sqlx::query!(
+        r#"
+        SELECT r.id, r.name FROM ingredients_to_recipes s
+        INNER JOIN ingredients_to_recipes t
+            ON t.recipe_id = s.recipe_id AND t.ingredient_id = $2
+        INNER JOIN recipes r ON r.id = s.recipe_id
+        WHERE s.ingredient_id = $1
+        ORDER BY r.name
+        "#,
+        source_id,
+        target_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let duplicate_ids: Vec<_> = duplicates.iter().map(|r| r.id).collect();
+
+    match (merge.duplicates, duplicates.is_empty()) {
+        (_, true) => {}
+        (None, false) => {
+            return Err(ApiError::unprocessable_entity(duplicates.iter().map(
+                |recipe| {
+                    (
+                        "duplicates",
+                        format!("{} uses both ingredients", recipe.name),
+                    )
+                },
+            )));
+        }
+        (Some(DuplicatePolicy::KeepSource), false) => {
+            sqlx::query!(
+                r#"
+                UPDATE ingredients_to_recipes t
+                SET quantity = s.quantity, quantity_unit = s.quantity_unit
+                FROM ingredients_to_recipes s
+                WHERE s.recipe_id = t.recipe_id AND s.ingredient_id = $1
+                  AND t.ingredient_id = $2 AND t.recipe_id = ANY($3)
+                "#,
+                source_id,
+                target_id,
+                &duplicate_ids
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        (Some(DuplicatePolicy::KeepTarget), false) => {}
+    }
+
+    // Read before the rows move, so the duplicates are included.
+    let affected = sqlx::query!(
+        r#"
+        SELECT r.id, r.name, r.creator_id FROM ingredients_to_recipes ir
+        INNER JOIN recipes r ON r.id = ir.recipe_id
+        WHERE ir.ingredient_id = $1
+        ORDER BY r.name
+        "#,
+        source_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM ingredients_to_recipes
+        WHERE ingredient_id = $1 AND recipe_id = ANY($2)
+        "#,
+        source_id,
+        &duplicate_ids
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "UPDATE ingredients_to_recipes SET ingredient_id = $2 WHERE ingredient_id = $1",
+        source_id,
+        target_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // A user may only have one suggestion and one favorite per ingredient. The rows that
+    // can't move are deleted along with the source.
+    let suggestions_moved = sqlx::query!(
+        r#"
+        UPDATE ingredient_suggestions s SET ingredient_id = $2
+        WHERE s.ingredient_id = $1 AND NOT EXISTS (
+            SELECT 1 FROM ingredient_suggestions t
+            WHERE t.ingredient_id = $2 AND t.user_id IS NOT DISTINCT FROM s.user_id
+        )
+        "#,
+        source_id,
+        target_id
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    sqlx::query!(
+        r#"
+        UPDATE favorite_ingredient f SET ingredient_id = $2
+        WHERE f.ingredient_id = $1 AND NOT EXISTS (
+            SELECT 1 FROM favorite_ingredient t
+            WHERE t.ingredient_id = $2 AND t.user_id = f.user_id
+        )
+        "#,
+        source_id,
+        target_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if merge.nutrition == NutritionPolicy::Source {
+        sqlx::query!(
+            r#"
+            UPDATE ingredients t SET (
+                category, calories_per_100g, protein, water, fat, sugar, carbohydrate, fiber,
+                caffeine, contains_alcohol
+            ) = (
+                s.category, s.calories_per_100g, s.protein, s.water, s.fat, s.sugar,
+                s.carbohydrate, s.fiber, s.caffeine, s.contains_alcohol
+            )
+            FROM ingredients s
+            WHERE t.id = $2 AND s.id = $1
+            "#,
+            source_id,
+            target_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    // The price and its currency only ever move together.
+    sqlx::query!(
+        r#"
+        UPDATE ingredients t SET
+            g_per_piece = COALESCE(t.g_per_piece, s.g_per_piece),
+            vegan = COALESCE(t.vegan, s.vegan),
+            vegetarian = COALESCE(t.vegetarian, s.vegetarian),
+            gluten_free = COALESCE(t.gluten_free, s.gluten_free),
+            contains_nuts = COALESCE(t.contains_nuts, s.contains_nuts),
+            allergens = COALESCE(t.allergens, s.allergens),
+            price_per_100g = COALESCE(t.price_per_100g, s.price_per_100g),
+            price_currency = CASE
+                WHEN t.price_per_100g IS NULL THEN s.price_currency
+                ELSE t.price_currency
+            END
+        FROM ingredients s
+        WHERE t.id = $2 AND s.id = $1
+        "#,
+        source_id,
+        target_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!("DELETE FROM ingredients WHERE id = $1", source_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let recipe_ids: Vec<_> = affected.iter().map(|r| r.id).collect();
+    refresh_recipe_nutrition(&mut *conn, &recipe_ids).await?;
+
+    let mut by_owner: BTreeMap<uuid::Uuid, Vec<String>> = BTreeMap::new();
+    for recipe in &affected {
+        by_owner
+            .entry(recipe.creator_id)
+            .or_default()
+            .push(recipe.name.clone());
+    }
+    let mut notifications = Vec::with_capacity(by_owner.len());
+    for (owner, recipes) in by_owner {
+        let notification = Notification::ingredient_merged(
+            owner,
+            source_name.clone(),
+            target_name.clone(),
+            recipes,
+        );
+        notifications.push(notification.save_for_recipient(&mut *conn).await?);
+    }
+
+    let outcome = MergeOutcome {
+        recipes: affected.into_iter().map(|r| r.name).collect(),
+        duplicates: duplicates.into_iter().map(|r| r.name).collect(),
+        suggestions_moved,
+    };
+    Ok((outcome, notifications))
+}
+
+/// Merges two ingredients that turned out to be the same, see [`merge_ingredients`].
+#[tracing::instrument(skip(tx, conn))]
+pub async fn merge(
+    State(AppState { tx, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Json(merge): Json<MergeIngredients>,
+) -> Result<Json<MergeOutcome>, ApiError> {
+    let mut db_tx = conn.begin().await?;
+    let (outcome, notifications) = merge_ingredients(&mut db_tx, org, &merge).await?;
+    db_tx.commit().await?;
+
+    tracing::info!(
+        source = %merge.source,
+        target = %merge.target,
+        recipes = outcome.recipes.len(),
+        "Merged ingredients"
+    );
+    for notification in notifications {
+        // Owners who aren't connected see the saved copy later.
+        let _ = tx.send(notification);
+    }
+
+    Ok(Json(outcome))
+}
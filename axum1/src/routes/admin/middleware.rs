@@ -5,7 +5,7 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{error::ApiError, state::AppState};
+use crate::{error::ApiError, personalized::mark_personalized, state::AppState};
 use tower_sessions::Session;
 
 #[derive(sqlx::FromRow, Serialize, Deserialize, Clone, Debug)]
@@ -23,6 +23,7 @@ where
     type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        mark_personalized(&parts.extensions);
         let session = Session::from_request_parts(parts, state)
             .await
             .expect("`SessionLayer` should be added");
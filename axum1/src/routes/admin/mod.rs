@@ -1,13 +1,76 @@
+pub mod broadcast;
+pub mod confirm_email;
+pub mod decline;
+pub mod export;
+pub mod meili;
+pub mod merge;
 mod middleware;
+mod reports;
+pub mod selfcheck;
 pub use middleware::AdminUser;
 
-use axum::{http::StatusCode, middleware::from_extractor_with_state, routing::get, Router};
+use std::time::Duration;
 
-use crate::{error::ApiError, extractors::DatabaseConnection, state::AppState};
+use axum::{
+    extract::FromRef,
+    http::StatusCode,
+    middleware::{from_extractor_with_state, from_fn_with_state},
+    routing::{get, post},
+    Router,
+};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, TrustedProxies},
+    rate_limit::{rate_limit, RateLimiter},
+    routes::{
+        auth::impersonation::{impersonate, impersonation_audit},
+        ingredient::suggestion::apply_all_suggestions,
+    },
+    state::AppState,
+};
 
 pub fn router(state: AppState) -> Router<AppState> {
+    let broadcast_limiter = RateLimiter::new(
+        state
+            .config
+            .borrow()
+            .application_settings
+            .broadcasts_per_hour(),
+        Duration::from_secs(60 * 60),
+    )
+    .trusting(TrustedProxies::from_ref(&state));
+    let email_confirmation_limiter = RateLimiter::new(
+        state
+            .config
+            .borrow()
+            .application_settings
+            .admin_email_confirmations_per_hour(),
+        Duration::from_secs(60 * 60),
+    )
+    .trusting(TrustedProxies::from_ref(&state));
+    let email_confirmations = Router::new()
+        .route(
+            "/users/:id/confirm_email",
+            post(confirm_email::confirm_email),
+        )
+        .route_layer(from_fn_with_state(email_confirmation_limiter, rate_limit));
+
     Router::new()
+        .route("/broadcast", post(broadcast::broadcast))
+        .route_layer(from_fn_with_state(broadcast_limiter, rate_limit))
         .route("/pg", get(pg_health))
+        .route("/meili", get(meili::meili))
+        .route("/selfcheck", get(selfcheck::selfcheck))
+        .route("/export/:file_name", get(export::export))
+        .route("/reports", get(reports::reports))
+        .route("/reports/:name/resolve", post(reports::resolve_reports))
+        .route("/ingredients/merge", post(merge::merge))
+        .route("/ingredients/:name/apply_all", post(apply_all_suggestions))
+        .route("/suggestions/decline", post(decline::decline_suggestions))
+        .route("/users/:id/impersonate", post(impersonate))
+        .route("/audit", get(impersonation_audit))
+        .merge(email_confirmations)
         .route_layer(from_extractor_with_state::<AdminUser, _>(state))
         .route("/health_check", get(|| async { StatusCode::OK }))
 }
@@ -0,0 +1,120 @@
+use axum::extract::{Path, Query};
+use sqlx::Acquire;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MeiliConnection},
+    org::Org,
+    pagination::{Paginated, Pagination},
+    search::{sync_recipe_document, RecipeSearchSimple},
+    time_range::TimeRange,
+};
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct ReportedRecipe {
+    name: String,
+    hidden: bool,
+    report_count: Option<i64>,
+    // Only the reasons are listed, reporters stay anonymous, even to moderators.
+    reasons: Option<Vec<String>>,
+}
+
+/// The moderation queue: reported recipes, most reported first. With `since`/`until`, only the
+/// reports filed in that range are counted.
+#[tracing::instrument(skip_all)]
+pub async fn reports(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    range: TimeRange,
+    pagination: Pagination,
+) -> Result<Paginated<ReportedRecipe>, ApiError> {
+    let reports = sqlx::query_as!(
+        ReportedRecipe,
+        r#"
+        SELECT r.name, r.hidden, COUNT(rp.id) AS report_count, ARRAY_AGG(rp.reason) AS reasons
+        FROM reports rp
+        INNER JOIN recipes r ON r.id = rp.recipe_id
+        WHERE r.org_id = $5
+          AND ($1::timestamptz IS NULL OR rp.created_at >= $1)
+          AND ($2::timestamptz IS NULL OR rp.created_at < $2)
+        GROUP BY r.id
+        ORDER BY report_count DESC, r.name
+        LIMIT $3 OFFSET $4
+        "#,
+        range.since,
+        range.until,
+        pagination.per_page,
+        pagination.offset(),
+        *org
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT rp.recipe_id) AS "count!"
+        FROM reports rp
+        INNER JOIN recipes r ON r.id = rp.recipe_id
+        WHERE r.org_id = $3
+          AND ($1::timestamptz IS NULL OR rp.created_at >= $1)
+          AND ($2::timestamptz IS NULL OR rp.created_at < $2)
+        "#,
+        range.since,
+        range.until,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(reports, pagination, total))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Resolution {
+    /// Whether the recipe should stay hidden. Otherwise the reports are dismissed.
+    hide: bool,
+}
+
+/// Closes the reports of a recipe, either hiding it for good or restoring it, in search too.
+#[tracing::instrument(skip(meili, conn))]
+pub async fn resolve_reports(
+    MeiliConnection(meili): MeiliConnection,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Path(name): Path<String>,
+    Query(resolution): Query<Resolution>,
+) -> Result<(), ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let recipe = sqlx::query!(
+        r#"
+        UPDATE recipes SET hidden = $1 WHERE name = $2 AND org_id = $3
+        RETURNING id, name, description, visibility = 'public' AS "public!"
+        "#,
+        resolution.hide,
+        name,
+        *org
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    sqlx::query!("DELETE FROM reports WHERE recipe_id = $1", recipe.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    let document = RecipeSearchSimple {
+        id: recipe.id,
+        name: recipe.name,
+        description: recipe.description,
+    };
+    tokio::spawn(sync_recipe_document(
+        meili,
+        document,
+        recipe.public && !resolution.hide,
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Round-trips through the services the app depends on, to catch the misconfigurations a
+//! connectivity check misses, like a revoked email token. Every check is undone or goes to a sink,
+//! so it's safe to run as often as needed.
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode, Json};
+use sqlx::{Acquire, PgPool};
+use tower_sessions::{
+    session::{Id, Record},
+    SessionStore,
+};
+
+use crate::{
+    email::{Email, EmailClient, Message},
+    state::AppState,
+};
+
+/// Don't let one unresponsive service hang the whole check.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The kind of the event the queue check enqueues. The relay never sees it, the check rolls it
+/// back.
+const SELFCHECK_KIND: &str = "selfcheck";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    /// Not configured, see `email_client.selfcheck_sink`.
+    Skipped,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub latency_ms: u64,
+    /// Why the check failed.
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn skipped() -> Self {
+        Self {
+            status: CheckStatus::Skipped,
+            latency_ms: 0,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SelfCheck {
+    /// Whether none of the checks failed.
+    pub ok: bool,
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+/// Runs one check with a timeout, measuring how long it took.
+pub async fn run_check<F>(check: F) -> CheckResult
+where
+    F: Future<Output = Result<(), anyhow::Error>>,
+{
+    let start = Instant::now();
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, check).await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+    let (status, error) = match outcome {
+        Ok(Ok(())) => (CheckStatus::Ok, None),
+        Ok(Err(e)) => (CheckStatus::Failed, Some(format!("{e:#}"))),
+        Err(_) => (CheckStatus::Failed, Some("timed out".to_owned())),
+    };
+    CheckResult {
+        status,
+        latency_ms,
+        error,
+    }
+}
+
+/// Enqueues a no-op event, claims it like the relay does and marks it sent, then rolls it all
+/// back.
+pub async fn check_queue(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+    let id = sqlx::query_scalar!(
+        "INSERT INTO events_outbox (kind, payload) VALUES ($1, '{}') RETURNING id",
+        SELFCHECK_KIND
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .context("Failed to enqueue")?;
+
+    let claimed = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM events_outbox
+        WHERE id = $1 AND sent_at IS NULL AND next_attempt_at <= NOW()
+        FOR UPDATE SKIP LOCKED
+        "#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .context("Failed to claim")?;
+    anyhow::ensure!(claimed.is_some(), "the enqueued event couldn't be claimed");
+
+    sqlx::query!("UPDATE events_outbox SET sent_at = NOW() WHERE id = $1", id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to mark as sent")?;
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+/// Sends a test email to the sink address, which accepts it without delivering it anywhere.
+pub async fn check_email(client: &EmailClient, sink: String) -> Result<(), anyhow::Error> {
+    let to = Email::parse(sink).map_err(|_| anyhow::anyhow!("the sink address is invalid"))?;
+    let message = Message::new(
+        to,
+        "Self-check",
+        "<p>This is a self-check, it needs no action.</p>",
+        "This is a self-check, it needs no action.",
+    );
+    client.send_message(message).await?;
+    Ok(())
+}
+
+/// Writes a short-lived session, reads it back and deletes it.
+pub async fn check_session_store(store: &dyn SessionStore) -> Result<(), anyhow::Error> {
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([(SELFCHECK_KIND.to_owned(), serde_json::json!(true))]),
+        expiry_date: time::OffsetDateTime::now_utc() + time::Duration::minutes(1),
+    };
+    store.create(&mut record).await.context("Failed to write")?;
+
+    let loaded = store.load(&record.id).await.context("Failed to read");
+    // Clean up even if reading failed.
+    store.delete(&record.id).await.context("Failed to delete")?;
+    anyhow::ensure!(
+        loaded?.is_some_and(|loaded| loaded.data == record.data),
+        "the written session couldn't be read back"
+    );
+    Ok(())
+}
+
+/// Runs every check concurrently. Responds with 503 if any of them failed, so it can be used as
+/// a deep readiness probe.
+#[tracing::instrument(skip_all)]
+pub async fn selfcheck(
+    State(AppState {
+        db_pool,
+        config,
+        email_client,
+        session_store,
+        ..
+    }): State<AppState>,
+) -> (StatusCode, Json<SelfCheck>) {
+    let sink = config.borrow().email_client.selfcheck_sink.clone();
+    let email = async {
+        match sink {
+            Some(sink) => run_check(check_email(&email_client, sink)).await,
+            None => CheckResult::skipped(),
+        }
+    };
+    let (queue, email, session_store) = tokio::join!(
+        run_check(check_queue(&db_pool)),
+        email,
+        run_check(check_session_store(session_store.as_ref())),
+    );
+
+    let checks = BTreeMap::from([
+        ("queue".to_owned(), queue),
+        ("email".to_owned(), email),
+        ("session_store".to_owned(), session_store),
+    ]);
+    let ok = checks.values().all(|c| c.status != CheckStatus::Failed);
+    for (name, check) in &checks {
+        if let Some(error) = &check.error {
+            tracing::warn!(check = %name, error.message = %error, "Self-check failed.");
+        }
+    }
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(SelfCheck { ok, checks }))
+}
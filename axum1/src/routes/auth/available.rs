@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use sqlx::PgConnection;
+use validator::Validate;
+
+use crate::{
+    error::ApiError, extractors::DatabaseConnection, org::Org, state::AppState, RE_USERNAME,
+};
+
+use super::timing::ResponseFloor;
+
+/// The same rules as registration, so anything reported as available can be registered.
+#[derive(Debug, serde::Deserialize, validator::Validate)]
+pub struct AvailabilityQuery {
+    #[validate(
+        length(min = 2, max = 40, message = "must be between 2 and 40 characters"),
+        regex(
+            path = *RE_USERNAME,
+            message = "can only contain letters, digits and . (period); periods cannot appear at start or end position, neither consecutively."
+        )
+    )]
+    pub name: Option<String>,
+    #[validate(email(message = "must be a valid email"))]
+    pub email: Option<String>,
+}
+
+/// Whether the asked values are free to register. Values that weren't asked about are omitted.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Availability {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<bool>,
+}
+
+/// Reports whether a name is free in the organization and an email is free at all, for live
+/// feedback during registration. Names and emails compare case-insensitively, like at
+/// registration. Rate limited, see `availability_checks_per_minute`.
+///
+/// The response time doesn't tell whether the lookup found anything, see [`ResponseFloor`].
+#[tracing::instrument(skip(config, conn))]
+pub async fn availability(
+    State(AppState { config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<Availability>, ApiError> {
+    query
+        .validate()
+        .map_err(ApiError::unprocessable_entity_from_validation_errors)?;
+    if query.name.is_none() && query.email.is_none() {
+        return Err(ApiError::unprocessable_entity([(
+            "query",
+            "either name or email is required",
+        )]));
+    }
+
+    let floor = ResponseFloor::start(config.borrow().security.constant_time_auth());
+    let availability = lookup_availability(
+        &mut conn,
+        org,
+        query.name.as_deref(),
+        query.email.as_deref(),
+    )
+    .await;
+    floor.wait().await;
+
+    Ok(Json(availability?))
+}
+
+/// Looks up both values in one query, even when only one is asked, so the work done doesn't
+/// depend on what was asked.
+pub async fn lookup_availability(
+    conn: &mut PgConnection,
+    org: Org,
+    name: Option<&str>,
+    email: Option<&str>,
+) -> Result<Availability, sqlx::Error> {
+    let taken = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM users WHERE name = $1 AND org_id = $2) AS "name_taken!",
+            EXISTS (SELECT 1 FROM users WHERE email = $3) AS "email_taken!"
+        "#,
+        name.unwrap_or_default(),
+        *org,
+        email.unwrap_or_default(),
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(Availability {
+        name: name.map(|_| !taken.name_taken),
+        email: email.map(|_| !taken.email_taken),
+    })
+}
@@ -0,0 +1,73 @@
+use axum::http::StatusCode;
+
+use crate::{
+    error::{ApiError, ResultExt},
+    extractors::{AuthUser, DatabaseConnection, Json},
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AvatarChoice {
+    /// The file name of one of the user's image uploads.
+    pub file_name: String,
+}
+
+/// Where the upload routes serve the avatar.
+pub fn avatar_url(user_id: uuid::Uuid, file_name: &str) -> String {
+    format!("/upload/{user_id}/{file_name}")
+}
+
+fn not_an_own_upload() -> ApiError {
+    ApiError::unprocessable_entity([("file_name", "is not one of your uploads")])
+}
+
+/// `PUT /me/avatar`. Only images may be avatars, going by the content type they were uploaded with.
+#[tracing::instrument(skip(conn, auth_user))]
+pub async fn set_avatar(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Json(choice): Json<AvatarChoice>,
+) -> Result<StatusCode, ApiError> {
+    let content_type = sqlx::query_scalar!(
+        "SELECT content_type FROM uploads WHERE uploader_id = $1 AND file_name = $2",
+        *auth_user,
+        choice.file_name
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(not_an_own_upload)?;
+
+    if !content_type.to_ascii_lowercase().starts_with("image/") {
+        return Err(ApiError::unprocessable_entity([(
+            "file_name",
+            "is not an image",
+        )]));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET avatar = $2 WHERE user_id = $1",
+        *auth_user,
+        choice.file_name
+    )
+    .execute(&mut *conn)
+    .await
+    // Deleted since it was looked up.
+    .on_constraint("users_avatar_fkey", |_| not_an_own_upload())?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DELETE /me/avatar`. The upload itself is kept.
+#[tracing::instrument(skip(conn, auth_user))]
+pub async fn remove_avatar(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE users SET avatar = NULL WHERE user_id = $1",
+        *auth_user
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,12 +1,12 @@
 use anyhow::Context;
 use axum::extract::Query;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use sqlx::{Acquire, Executor, PgExecutor, Postgres};
+use sqlx::{Acquire, Executor, PgConnection, PgExecutor, Postgres};
 
 use crate::{
     email::{Email, EmailClient},
     error::ApiError,
     extractors::DatabaseConnection,
+    token::hash_token,
 };
 
 // TODO: This is done through a queue, we might delete this
@@ -32,14 +32,6 @@ pub async fn _send_confirmation_email(
         .map_err(ApiError::Reqwest)
 }
 
-pub fn generate_confirmation_token() -> String {
-    let mut rng = thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
-}
-
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(confirmation_token, tx)
@@ -48,14 +40,16 @@ pub async fn store_token(
     tx: impl Executor<'_, Database = Postgres>,
     confirmation_token: &str,
     user_id: uuid::Uuid,
+    expiry_hours: i32,
 ) -> Result<(), ApiError> {
     sqlx::query!(
         r#"
-        INSERT INTO confirmation_tokens (confirmation_token, user_id)
-        VALUES ($1, $2)
+        INSERT INTO confirmation_tokens (confirmation_token, user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(hours => $3))
         "#,
-        confirmation_token,
-        user_id
+        hash_token(confirmation_token),
+        user_id,
+        expiry_hours
     )
     .execute(tx)
     .await?;
@@ -72,11 +66,13 @@ pub async fn enqueue_delivery_task(
         r#"
         INSERT INTO confirmation_delivery_queue (
             confirmation_id, 
-            user_email
-        ) VALUES ($1, $2)
+            user_email,
+            token_hash
+        ) VALUES ($1, $2, $3)
         "#,
         confirmation_id,
         user_email,
+        hash_token(&confirmation_id),
     )
     .execute(tx)
     .await?;
@@ -94,10 +90,13 @@ pub async fn confirm(
     DatabaseConnection(mut conn): DatabaseConnection,
 ) -> Result<(), ApiError> {
     let mut tx = conn.begin().await?;
-    let user_id = get_user_id_from_token(&mut *tx, &parameters.token)
+    let (user_id, expired) = get_user_id_from_token(&mut *tx, &parameters.token)
         .await
         .context("Failed to retrieve the user_id associated with the provided token.")?
         .ok_or(ApiError::BadRequest)?;
+    if expired {
+        return Err(ApiError::TokenExpired);
+    }
     confirm_subscriber(&mut *tx, user_id)
         .await
         .context("Failed to update the user status to `confirmed`.")?;
@@ -114,6 +113,25 @@ pub async fn confirm(
     Ok(())
 }
 
+/// Rejects logging in before the email is confirmed, if the deployment requires it.
+pub async fn ensure_login_allowed(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    require_confirmed_email: bool,
+) -> Result<(), ApiError> {
+    if !require_confirmed_email {
+        return Ok(());
+    }
+
+    let confirmed = sqlx::query_scalar!("SELECT confirmed FROM users WHERE user_id = $1", user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if !confirmed {
+        return Err(ApiError::EmailUnconfirmed);
+    }
+    Ok(())
+}
+
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(user_id, pool))]
 pub async fn confirm_subscriber<'c, E>(pool: E, user_id: uuid::Uuid) -> Result<(), ApiError>
 where
@@ -128,19 +146,24 @@ where
     Ok(())
 }
 
+/// Returns the user the token belongs to, and whether the token has already expired.
 #[tracing::instrument(name = "Get subscriber_id from token", skip(confirmation_token, pool))]
 pub async fn get_user_id_from_token<'c, E>(
     pool: E,
     confirmation_token: &str,
-) -> Result<Option<uuid::Uuid>, sqlx::Error>
+) -> Result<Option<(uuid::Uuid, bool)>, sqlx::Error>
 where
     E: PgExecutor<'c>,
 {
     let result = sqlx::query!(
-        r#"SELECT user_id FROM confirmation_tokens WHERE confirmation_token = $1"#,
-        confirmation_token,
+        r#"
+        SELECT user_id, expires_at < NOW() AS "expired!"
+        FROM confirmation_tokens
+        WHERE confirmation_token = $1
+        "#,
+        hash_token(confirmation_token),
     )
     .fetch_optional(pool)
     .await?;
-    Ok(result.map(|r| r.user_id))
+    Ok(result.map(|r| (r.user_id, r.expired)))
 }
@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, Json},
+    locale::Locale,
+    queue::digest::DigestFrequency,
+    state::AppState,
+    token::generate_token,
+};
+
+/// Users can follow at most this many topics.
+const MAX_TOPICS: usize = 20;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DigestPreferences {
+    pub frequency: DigestFrequency,
+    /// Ingredient or cuisine names, the user's favorite ingredients are always included.
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// An IANA time zone name, like `Europe/Budapest`.
+    #[serde(default = "default_time_zone")]
+    pub time_zone: String,
+}
+
+fn default_time_zone() -> String {
+    "UTC".to_owned()
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn digest_preferences(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+) -> Result<Json<DigestPreferences>, ApiError> {
+    let preferences = sqlx::query!(
+        "SELECT frequency, topics, time_zone FROM digest_preferences WHERE user_id = $1",
+        *auth_user
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // Digests are opt-in, so no preferences means they're off.
+    let preferences = match preferences {
+        Some(p) => DigestPreferences {
+            frequency: DigestFrequency::parse(&p.frequency).unwrap_or(DigestFrequency::Off),
+            topics: p.topics,
+            time_zone: p.time_zone,
+        },
+        None => DigestPreferences {
+            frequency: DigestFrequency::Off,
+            topics: Vec::new(),
+            time_zone: default_time_zone(),
+        },
+    };
+    Ok(Json(preferences))
+}
+
+#[tracing::instrument(skip(config, conn))]
+pub async fn update_digest_preferences(
+    State(AppState { config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Json(preferences): Json<DigestPreferences>,
+) -> Result<Json<DigestPreferences>, ApiError> {
+    let topics = normalize_topics(preferences.topics);
+    if topics.len() > MAX_TOPICS {
+        return Err(ApiError::unprocessable_entity([(
+            "topics",
+            "too many topics",
+        )]));
+    }
+
+    let mut tx = conn.begin().await?;
+
+    let known_time_zone = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1) AS "exists!""#,
+        preferences.time_zone
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if !known_time_zone {
+        return Err(ApiError::unprocessable_entity([(
+            "time_zone",
+            "unknown time zone",
+        )]));
+    }
+
+    let token = generate_token(&config.borrow().tokens);
+    // The unsubscribe token is kept on updates, so links in already sent emails keep working.
+    // The current week is marked as done on opt-in, the first digest covers the next full week.
+    sqlx::query!(
+        r#"
+        INSERT INTO digest_preferences (
+            user_id, frequency, topics, time_zone, unsubscribe_token, last_period_end
+        )
+        VALUES ($1, $2, $3, $4, $5, date_trunc('week', NOW() AT TIME ZONE $4) AT TIME ZONE $4)
+        ON CONFLICT (user_id) DO UPDATE
+        SET frequency = EXCLUDED.frequency,
+            topics = EXCLUDED.topics,
+            time_zone = EXCLUDED.time_zone
+        "#,
+        *auth_user,
+        preferences.frequency.as_str(),
+        &topics,
+        preferences.time_zone,
+        token
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(DigestPreferences {
+        frequency: preferences.frequency,
+        topics,
+        time_zone: preferences.time_zone,
+    }))
+}
+
+fn normalize_topics(topics: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for topic in topics {
+        let topic = topic.trim();
+        if !topic.is_empty() && !normalized.iter().any(|t| t.eq_ignore_ascii_case(topic)) {
+            normalized.push(topic.to_owned());
+        }
+    }
+    normalized
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeQuery {
+    token: String,
+    /// The language of the pages, the one the email was sent in.
+    lang: Option<String>,
+}
+
+impl UnsubscribeQuery {
+    fn locale(&self) -> Locale {
+        self.lang
+            .as_deref()
+            .and_then(Locale::parse)
+            .unwrap_or_default()
+    }
+}
+
+fn page(locale: Locale, title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html><html lang=\"{}\"><head><meta charset=\"utf-8\">\
+        <meta name=\"viewport\" content=\"width=device-width\">\
+        <title>{title}</title></head><body>{body}</body></html>",
+        locale.as_str()
+    ))
+}
+
+/// `GET /digest/unsubscribe`, where the link in digests leads. It only asks for a confirmation,
+/// as mail scanners and link previews follow links on their own.
+#[tracing::instrument(skip_all)]
+pub async fn confirm_unsubscribe(Query(query): Query<UnsubscribeQuery>) -> Html<String> {
+    // Without an `action`, the form posts to this same address, token included.
+    let (title, body) = match query.locale() {
+        Locale::En => (
+            "Unsubscribe",
+            "<form method=\"post\"><p>Stop receiving the weekly digest of new recipes?</p>\
+            <button type=\"submit\">Unsubscribe</button></form>",
+        ),
+        Locale::Hu => (
+            "Leiratkozás",
+            "<form method=\"post\"><p>Leiratkozol az új receptek heti összefoglalójáról?</p>\
+            <button type=\"submit\">Leiratkozás</button></form>",
+        ),
+    };
+    page(query.locale(), title, body)
+}
+
+/// `POST /digest/unsubscribe`. Turns the digest off, without logging in. The token is the proof
+/// of owning the address, so it's exempt from the CSRF check.
+#[tracing::instrument(skip_all)]
+pub async fn unsubscribe(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<Html<String>, ApiError> {
+    if !unsubscribe_by_token(&mut conn, &query.token).await? {
+        return Err(ApiError::NotFound);
+    }
+    Ok(match query.locale() {
+        Locale::En => page(
+            Locale::En,
+            "Unsubscribed",
+            "<p>You won't receive the weekly digest anymore.</p>",
+        ),
+        Locale::Hu => page(
+            Locale::Hu,
+            "Leiratkozva",
+            "<p>Többé nem küldünk heti összefoglalót.</p>",
+        ),
+    })
+}
+
+/// Whether the token belonged to someone. Unsubscribing twice is fine.
+pub async fn unsubscribe_by_token(
+    conn: &mut PgConnection,
+    token: &str,
+) -> Result<bool, sqlx::Error> {
+    let unsubscribed = sqlx::query!(
+        "UPDATE digest_preferences SET frequency = 'off' WHERE unsubscribe_token = $1",
+        token
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    Ok(unsubscribed > 0)
+}
@@ -0,0 +1,67 @@
+use axum::Json;
+use sqlx::PgConnection;
+use tower_sessions::Session;
+
+use crate::{
+    error::ApiError,
+    extractors::{GuestId, MaybeAuthUser},
+};
+
+#[derive(serde::Serialize)]
+pub(super) struct GuestSession {
+    guest_id: GuestId,
+}
+
+/// Starts an anonymous session, so guests can collect favorites before registering.
+///
+/// Calling it again returns the same guest id.
+#[tracing::instrument(skip_all)]
+pub(super) async fn create_guest_session(
+    maybe_auth_user: MaybeAuthUser,
+    session: Session,
+) -> Result<Json<GuestSession>, ApiError> {
+    if let Some(guest_id) = maybe_auth_user.guest_id() {
+        return Ok(Json(GuestSession { guest_id }));
+    }
+    if maybe_auth_user.into_inner().is_some() {
+        return Err(ApiError::BadRequest);
+    }
+
+    let guest_id = GuestId::new();
+    session.insert("guest_id", guest_id).await?;
+
+    Ok(Json(GuestSession { guest_id }))
+}
+
+/// Moves everything the guest collected to the user. Conflicts are resolved by taking the union
+/// of the two sides.
+///
+/// The caller is responsible for removing the `guest_id` from the session once the
+/// surrounding transaction is committed.
+#[tracing::instrument(skip(conn))]
+pub(super) async fn merge_guest_into_user(
+    conn: &mut PgConnection,
+    guest_id: GuestId,
+    user_id: uuid::Uuid,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO favorite_recipe (recipe_id, user_id)
+        SELECT recipe_id, $1 FROM guest_favorite_recipes WHERE guest_id = $2
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        *guest_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM guest_favorite_recipes WHERE guest_id = $1",
+        *guest_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
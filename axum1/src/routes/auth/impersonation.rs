@@ -0,0 +1,355 @@
+use axum::{
+    extract::{Path, Query, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::{Acquire, PgConnection};
+use tower_sessions::Session;
+use tracing::{field::display, Span};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection},
+    org::Org,
+    pagination::{Paginated, Pagination},
+    state::AppState,
+    time_range::TimeRange,
+};
+
+use super::sessions::{end_user_session, start_user_session};
+
+/// Stored in the session while an admin acts as another user, so the UI can show a banner and
+/// the admin can return to their own identity.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct ImpersonatedBy {
+    pub admin_id: uuid::Uuid,
+    /// The row in `impersonations` auditing this session.
+    pub impersonation_id: uuid::Uuid,
+}
+
+impl ImpersonatedBy {
+    pub const KEY: &'static str = "impersonated_by";
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ImpersonateQuery {
+    /// Required to impersonate another admin, as that hands out admin rights too.
+    #[serde(default)]
+    confirm_admin: bool,
+}
+
+/// Continues the current (admin) session as the given user. Must be behind the `AdminUser` guard.
+///
+/// Only users of the organization may be impersonated, and only super-admins may impersonate
+/// other super-admins or users of any organization.
+#[tracing::instrument(skip(session, conn))]
+pub async fn impersonate(
+    admin: AuthUser,
+    session: Session,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Path(user_id): Path<uuid::Uuid>,
+    Query(query): Query<ImpersonateQuery>,
+) -> Result<(), ApiError> {
+    if session
+        .get::<ImpersonatedBy>(ImpersonatedBy::KEY)
+        .await?
+        .is_some()
+    {
+        // Stop the current impersonation first, so there's always a single way back.
+        return Err(ApiError::Conflict);
+    }
+    if user_id == *admin {
+        return Err(ApiError::BadRequest);
+    }
+
+    let mut tx = conn.begin().await?;
+
+    let target = sqlx::query!(
+        r#"
+        SELECT t.org_id, t.is_admin, t.is_super_admin, a.is_super_admin AS actor_is_super_admin
+        FROM users t, users a
+        WHERE t.user_id = $1 AND a.user_id = $2
+        "#,
+        user_id,
+        *admin
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+    // Users of other organizations don't exist as far as their admins are concerned.
+    if target.org_id != *org && !target.actor_is_super_admin {
+        return Err(ApiError::NotFound);
+    }
+    if target.is_super_admin && !target.actor_is_super_admin {
+        return Err(ApiError::Forbidden);
+    }
+    if (target.is_admin || target.is_super_admin) && !query.confirm_admin {
+        return Err(ApiError::unprocessable_entity([(
+            "confirm_admin",
+            "must be set to impersonate another admin",
+        )]));
+    }
+
+    let impersonation_id = sqlx::query_scalar!(
+        "INSERT INTO impersonations (admin_id, user_id) VALUES ($1, $2) RETURNING id",
+        *admin,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // The admin's own session id is about to be replaced.
+    end_user_session(&session, &mut tx).await?;
+    tx.commit().await?;
+
+    session
+        .insert(
+            ImpersonatedBy::KEY,
+            ImpersonatedBy {
+                admin_id: *admin,
+                impersonation_id,
+            },
+        )
+        .await
+        .expect("impersonated_by is serializable");
+    start_user_session(&session, &mut conn, user_id).await?;
+
+    tracing::warn!(
+        admin_id = %*admin,
+        %user_id,
+        %impersonation_id,
+        "admin started impersonating a user"
+    );
+
+    Ok(())
+}
+
+/// Ends the impersonation, turning the session back into the admin's.
+#[tracing::instrument(skip_all)]
+pub async fn stop_impersonation(
+    _user: AuthUser,
+    session: Session,
+    DatabaseConnection(mut conn): DatabaseConnection,
+) -> Result<(), ApiError> {
+    let impersonated_by = session
+        .get::<ImpersonatedBy>(ImpersonatedBy::KEY)
+        .await?
+        .ok_or(ApiError::BadRequest)?;
+
+    let mut tx = conn.begin().await?;
+    end_impersonation(&mut tx, impersonated_by).await?;
+    end_user_session(&session, &mut tx).await?;
+    tx.commit().await?;
+
+    session
+        .remove::<ImpersonatedBy>(ImpersonatedBy::KEY)
+        .await?;
+    start_user_session(&session, &mut conn, impersonated_by.admin_id).await?;
+
+    Ok(())
+}
+
+/// Closes the audit record of the impersonation.
+pub(super) async fn end_impersonation(
+    conn: &mut PgConnection,
+    impersonated_by: ImpersonatedBy,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        "UPDATE impersonations SET ended_at = NOW() WHERE id = $1 AND ended_at IS NULL",
+        impersonated_by.impersonation_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::warn!(
+        admin_id = %impersonated_by.admin_id,
+        impersonation_id = %impersonated_by.impersonation_id,
+        "admin stopped impersonating a user"
+    );
+
+    Ok(())
+}
+
+/// Attributes what's done while impersonating to the admin: records their id on the request span
+/// as `impersonated_by`, and every request that may change something in `impersonated_requests`.
+pub async fn audit_impersonation(
+    State(AppState { db_pool, .. }): State<AppState>,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Response {
+    // A session that can't be read is the handler's problem, it reads the session too.
+    let Ok(Some(impersonated_by)) = session.get::<ImpersonatedBy>(ImpersonatedBy::KEY).await else {
+        return next.run(request).await;
+    };
+    Span::current().record("impersonated_by", display(impersonated_by.admin_id));
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let response = next.run(request).await;
+    if method.is_safe() {
+        return response;
+    }
+
+    let status = response.status().as_u16() as i16;
+    tracing::warn!(
+        admin_id = %impersonated_by.admin_id,
+        impersonation_id = %impersonated_by.impersonation_id,
+        %method,
+        %path,
+        status,
+        "admin made a request as the impersonated user"
+    );
+    // On the pool, so a sandboxed request is still audited.
+    if let Err(e) = sqlx::query!(
+        "INSERT INTO impersonated_requests (impersonation_id, method, path, status) VALUES ($1, $2, $3, $4)",
+        impersonated_by.impersonation_id,
+        method.as_str(),
+        path,
+        status
+    )
+    .execute(&db_pool)
+    .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to audit a request made while impersonating"
+        );
+    }
+    response
+}
+
+/// A request recorded by [`audit_impersonation`].
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct AuditedRequest {
+    /// The admin who made the request, unless they have been deleted since.
+    pub admin: Option<String>,
+    /// The user the admin was acting as.
+    pub user: String,
+    pub method: String,
+    pub path: String,
+    pub status: i16,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// `GET /admin/audit`: the requests made while impersonating users of the organization, the
+/// latest first. Must be behind the `AdminUser` guard. `since`/`until` scope it by the time of the
+/// request, see [`TimeRange`].
+#[tracing::instrument(skip(conn))]
+pub async fn impersonation_audit(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    range: TimeRange,
+    pagination: Pagination,
+) -> Result<Paginated<AuditedRequest>, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let requests = sqlx::query_as!(
+        AuditedRequest,
+        r#"
+        SELECT a.name AS "admin?", u.name AS "user!", r.method, r.path, r.status, r.created_at
+        FROM impersonated_requests r
+        INNER JOIN impersonations i ON i.id = r.impersonation_id
+        INNER JOIN users u ON u.user_id = i.user_id
+        LEFT JOIN users a ON a.user_id = i.admin_id
+        WHERE u.org_id = $1
+          AND ($2::timestamptz IS NULL OR r.created_at >= $2)
+          AND ($3::timestamptz IS NULL OR r.created_at < $3)
+        ORDER BY r.created_at DESC, r.id
+        LIMIT $4 OFFSET $5
+        "#,
+        *org,
+        range.since,
+        range.until,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM impersonated_requests r
+        INNER JOIN impersonations i ON i.id = r.impersonation_id
+        INNER JOIN users u ON u.user_id = i.user_id
+        WHERE u.org_id = $1
+          AND ($2::timestamptz IS NULL OR r.created_at >= $2)
+          AND ($3::timestamptz IS NULL OR r.created_at < $3)
+        "#,
+        *org,
+        range.since,
+        range.until
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Paginated::new(requests, pagination, total))
+}
+
+/// A request made on the user's account by support, as listed to the user.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct AccountActivity {
+    pub method: String,
+    pub path: String,
+    pub status: i16,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// `GET /me/activity`: the requests admins made while acting as the user, the latest first, so
+/// the user can tell them apart from their own. The admins aren't named. `since`/`until` scope it
+/// like [`impersonation_audit`].
+#[tracing::instrument(skip(conn))]
+pub async fn account_activity(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    range: TimeRange,
+    pagination: Pagination,
+) -> Result<Paginated<AccountActivity>, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let requests = sqlx::query_as!(
+        AccountActivity,
+        r#"
+        SELECT r.method, r.path, r.status, r.created_at
+        FROM impersonated_requests r
+        INNER JOIN impersonations i ON i.id = r.impersonation_id
+        WHERE i.user_id = $1
+          AND ($2::timestamptz IS NULL OR r.created_at >= $2)
+          AND ($3::timestamptz IS NULL OR r.created_at < $3)
+        ORDER BY r.created_at DESC, r.id
+        LIMIT $4 OFFSET $5
+        "#,
+        *auth_user,
+        range.since,
+        range.until,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM impersonated_requests r
+        INNER JOIN impersonations i ON i.id = r.impersonation_id
+        WHERE i.user_id = $1
+          AND ($2::timestamptz IS NULL OR r.created_at >= $2)
+          AND ($3::timestamptz IS NULL OR r.created_at < $3)
+        "#,
+        *auth_user,
+        range.since,
+        range.until
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Paginated::new(requests, pagination, total))
+}
@@ -1,94 +1,291 @@
 use anyhow::Context;
 use axum::{
-    extract::{Query, State},
-    routing::{get, post, put},
-    Form, Json, Router,
+    extract::{FromRef, Query, State},
+    http::HeaderMap,
+    middleware::from_fn_with_state,
+    routing::{delete, get, post, put},
+    Router,
 };
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::Acquire;
+use tokio::sync::broadcast;
 use tower_sessions::Session;
 use validator::Validate;
 
 use crate::{
-    email::Email,
+    breach::ensure_password_not_breached,
+    config::AuthSettings,
+    csrf::csrf_token,
+    email::{password_changed_message, password_reset_message, Email, EmailClient, EmailLinks},
     error::{ApiError, ResultExt},
-    extractors::{AuthUser, DatabaseConnection, MaybeAuthUser},
+    extractors::{
+        AuthUser, DatabaseConnection, Form, GuestId, Json, MaybeAuthUser, TrustedProxies,
+    },
+    locale::Locale,
+    org::Org,
+    rate_limit::{rate_limit, RateLimiter},
+    sse::{Notification, SecurityAlertReason},
     state::AppState,
+    token::{generate_token, hash_token},
+    upload::delete_upload,
+    utils::with_transaction,
     RE_USERNAME,
 };
 
-mod confirm;
+pub mod available;
+pub mod avatar;
+pub mod confirm;
+pub mod digest;
+mod guest;
+pub mod impersonation;
+pub mod notifications;
 mod oauth;
-mod password;
+pub mod password;
+pub mod recent;
+pub mod reset;
+pub mod sessions;
+pub mod timing;
+
+use guest::{create_guest_session, merge_guest_into_user};
+use impersonation::{account_activity, end_impersonation, stop_impersonation, ImpersonatedBy};
 
 use oauth::{discord_auth, discord_authorize, google_auth, google_authorize};
-use password::{compute_password_hash, validate_credentials};
+use password::{
+    compute_password_hash, ensure_password_strength, estimate_password_strength,
+    validate_credentials, PasswordStrength,
+};
 
-use self::confirm::{confirm, enqueue_delivery_task, generate_confirmation_token, store_token};
+use self::available::availability;
+use self::avatar::{avatar_url, remove_avatar, set_avatar};
+use self::confirm::{confirm, enqueue_delivery_task, ensure_login_allowed, store_token};
+use self::digest::{
+    confirm_unsubscribe, digest_preferences, unsubscribe, update_digest_preferences,
+};
+use self::notifications::{
+    mark_all_notifications_read, mark_notification_read, notifications, unread_count,
+};
+use self::recent::{clear_recently_viewed, recently_viewed, update_recently_viewed_settings};
+use self::reset::request_password_reset;
+use self::sessions::{end_user_session, revoke_user_sessions, start_user_session};
+
+pub fn router(state: AppState) -> Router<AppState> {
+    let password_strength_limiter = RateLimiter::per_minute(
+        state
+            .config
+            .borrow()
+            .application_settings
+            .password_strength_requests_per_minute(),
+    )
+    .trusting(TrustedProxies::from_ref(&state));
+    let rate_limited = Router::new()
+        .route("/auth/password_strength", post(password_strength))
+        .route_layer(from_fn_with_state(password_strength_limiter, rate_limit));
+    let availability_limiter = RateLimiter::per_minute(
+        state
+            .config
+            .borrow()
+            .application_settings
+            .availability_checks_per_minute(),
+    )
+    .trusting(TrustedProxies::from_ref(&state));
+    let availability_checks = Router::new()
+        .route("/auth/available", get(availability))
+        .route_layer(from_fn_with_state(availability_limiter, rate_limit));
 
-pub fn router() -> Router<AppState> {
     Router::new()
         .route("/me", get(me))
+        .route("/csrf", get(csrf_token))
+        .route(
+            "/me/recent",
+            get(recently_viewed).delete(clear_recently_viewed),
+        )
+        .route("/me/recent/settings", put(update_recently_viewed_settings))
+        .route("/me/notifications", get(notifications))
+        .route("/me/notifications/unread_count", get(unread_count))
+        .route(
+            "/me/notifications/read_all",
+            post(mark_all_notifications_read),
+        )
+        .route("/me/notifications/:id/read", post(mark_notification_read))
+        .route("/me/uploads/:file_name", delete(delete_upload))
+        .route("/me/avatar", put(set_avatar).delete(remove_avatar))
+        .route("/me/stop_impersonation", post(stop_impersonation))
+        .route("/me/activity", get(account_activity))
         .route("/auth", post(authorize))
         .route("/register", post(register))
         .route("/logout", get(logout))
+        .route("/guest", post(create_guest_session))
         .route("/update_password", put(update_password))
         .route("/confirm", get(confirm))
         .route("/forget_password_gen", post(forget_password_gen))
         .route("/forget_password", post(forget_password))
         .route("/is_token_valid", get(is_token_valid))
+        .route(
+            "/digest",
+            get(digest_preferences).put(update_digest_preferences),
+        )
+        .route(
+            "/digest/unsubscribe",
+            get(confirm_unsubscribe).post(unsubscribe),
+        )
         .route("/auth/discord_authorize", get(discord_authorize))
         .route("/auth/google_authorize", get(google_authorize))
         .route("/auth/discord", get(discord_auth))
         .route("/auth/google", get(google_auth))
+        .merge(rate_limited)
+        .merge(availability_checks)
 }
 
-#[derive(sqlx::FromRow, serde::Serialize, Debug)]
+#[derive(serde::Serialize, Debug)]
 struct UserDetails {
     name: String,
+    email: String,
+    is_admin: bool,
+    roles: Vec<&'static str>,
+    confirmed: bool,
+    avatar_url: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// The name of the admin acting as this user, if any.
+    impersonated_by: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MeQuery {
+    /// A comma separated list of the fields to return, e.g. `?fields=name,avatar_url`.
+    /// Every field is returned when omitted.
+    fields: Option<String>,
+}
+
+/// Masks the local part of an email address, keeping its first character, e.g. `j***@example.com`.
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{first}***@{domain}")
+        }
+        None => "***".to_owned(),
+    }
+}
+
+/// Rejects signing up with an email outside of `auth.allowed_email_domains`.
+fn ensure_email_domain_allowed(settings: &AuthSettings, email: &str) -> Result<(), ApiError> {
+    if settings.is_email_domain_allowed(email) {
+        return Ok(());
+    }
+    Err(ApiError::unprocessable_entity([(
+        "email",
+        "signing up with this email domain is not allowed",
+    )]))
 }
 
+#[tracing::instrument(skip(maybe_auth_user, session, conn))]
 async fn me(
     maybe_auth_user: MaybeAuthUser,
+    session: Session,
     DatabaseConnection(mut conn): DatabaseConnection,
-) -> Result<Json<Option<UserDetails>>, ApiError> {
-    if let Some(auth_user) = maybe_auth_user.into_inner() {
-        let name = sqlx::query_as!(
-            UserDetails,
-            r#"SELECT name FROM users WHERE user_id = $1"#,
-            *auth_user
-        )
-        .fetch_one(&mut *conn)
-        .await
-        .map_err(|_| ApiError::NotFound)?;
-        return Ok(Json(Some(name)));
+    Query(query): Query<MeQuery>,
+) -> Result<Json<Option<serde_json::Map<String, serde_json::Value>>>, ApiError> {
+    let Some(auth_user) = maybe_auth_user.into_inner() else {
+        return Ok(Json(None));
+    };
+
+    let user = sqlx::query!(
+        r#"
+        SELECT u.name, u.email, u.is_admin, u.confirmed, u.created_at, u.avatar
+        FROM users u
+        WHERE u.user_id = $1
+        "#,
+        *auth_user
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let impersonated_by = match session.get::<ImpersonatedBy>(ImpersonatedBy::KEY).await? {
+        Some(impersonated_by) => {
+            sqlx::query_scalar!(
+                "SELECT name FROM users WHERE user_id = $1",
+                impersonated_by.admin_id
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+        }
+        None => None,
+    };
+
+    let details = UserDetails {
+        name: user.name,
+        email: mask_email(&user.email),
+        is_admin: user.is_admin,
+        roles: if user.is_admin {
+            vec!["user", "admin"]
+        } else {
+            vec!["user"]
+        },
+        confirmed: user.confirmed,
+        avatar_url: user
+            .avatar
+            .map(|file_name| avatar_url(*auth_user, &file_name)),
+        created_at: user.created_at,
+        impersonated_by,
+    };
+
+    let serde_json::Value::Object(mut details) =
+        serde_json::to_value(details).context("Failed to serialize user details")?
+    else {
+        unreachable!("UserDetails serializes to a map")
+    };
+    if let Some(fields) = query.fields {
+        let fields: Vec<&str> = fields.split(',').map(str::trim).collect();
+        details.retain(|key, _| fields.contains(&key.as_str()));
     }
-    Ok(Json(None))
+
+    Ok(Json(Some(details)))
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct Credentials {
     email: String,
+    #[schemars(with = "String")]
     password: SecretString,
 }
 
 async fn authorize(
+    State(AppState { mut config, .. }): State<AppState>,
     session: Session,
-    conn: DatabaseConnection,
+    maybe_auth_user: MaybeAuthUser,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Form(credentials): Form<Credentials>,
 ) -> Result<(), ApiError> {
-    let user_id = validate_credentials(credentials, conn).await?;
-    // Rotate the session cookie on privilege level change.
-    // This is to prevent session-fixation attacks.
-    session.cycle_id().await?;
-    session
-        .insert("user_id", user_id)
-        .await
-        .expect("user_id is serializable");
+    let password_hashing = config.borrow().password_hashing.params();
+    let user_id = validate_credentials(credentials, &mut conn, password_hashing).await?;
+    org.ensure_member(&mut conn, user_id).await?;
+
+    let require_confirmed_email = config
+        .borrow_and_update()
+        .application_settings
+        .require_confirmed_email();
+    ensure_login_allowed(&mut conn, user_id, require_confirmed_email).await?;
+    if let Some(guest_id) = maybe_auth_user.guest_id() {
+        let mut tx = conn.begin().await?;
+        merge_guest_into_user(&mut tx, guest_id, user_id).await?;
+        tx.commit().await?;
+        session.remove::<GuestId>("guest_id").await?;
+    }
+    start_user_session(&session, &mut conn, user_id).await?;
     Ok(())
 }
 
-async fn logout(_user: AuthUser, session: Session) -> Result<(), ApiError> {
+async fn logout(
+    _user: AuthUser,
+    session: Session,
+    DatabaseConnection(mut conn): DatabaseConnection,
+) -> Result<(), ApiError> {
+    if let Some(impersonated_by) = session.get::<ImpersonatedBy>(ImpersonatedBy::KEY).await? {
+        end_impersonation(&mut conn, impersonated_by).await?;
+    }
+    end_user_session(&session, &mut conn).await?;
     session.delete().await?;
     Ok(())
 }
@@ -98,8 +295,11 @@ struct UserId {
     user_id: uuid::Uuid,
 }
 
-#[derive(serde::Deserialize, validator::Validate)]
+#[derive(serde::Deserialize, validator::Validate, schemars::JsonSchema)]
 pub struct Register {
+    // Given to schemars first, which then ignores the `regex` of `validate`, an expression it
+    // can't read.
+    #[schemars(regex = "RE_USERNAME")]
     #[validate(
         length(min = 2, max = 40, message = "must be between 2 and 40 characters"),
         regex(
@@ -110,12 +310,33 @@ pub struct Register {
     name: String,
     #[validate(email(message = "must be a valid email"))]
     email: String,
+    #[schemars(with = "String")]
     password: SecretString,
 }
 
-#[tracing::instrument(name = "Registering a new user", skip(form, conn))]
+#[tracing::instrument(
+    name = "Registering a new user",
+    skip(
+        form,
+        conn,
+        config,
+        breached_passwords,
+        session,
+        maybe_auth_user,
+        headers
+    )
+)]
 async fn register(
+    State(AppState {
+        mut config,
+        breached_passwords,
+        ..
+    }): State<AppState>,
+    session: Session,
+    maybe_auth_user: MaybeAuthUser,
+    headers: HeaderMap,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Form(form): Form<Register>,
 ) -> Result<(), ApiError> {
     form.validate()
@@ -127,228 +348,405 @@ async fn register(
         password,
     } = form;
 
-    let password_hash =
-        crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(password))
-            .await
-            .context("Failed to hash password")??;
-
-    let mut tx = conn.begin().await?;
-
-    let user_id = sqlx::query_as!(
-        UserId,
-        r#"
-        INSERT INTO users (name, email, password_hash)
-        VALUES ($1, $2, $3)
-        RETURNING user_id;
-        "#,
-        name,
-        email,
-        password_hash.expose_secret(),
-    )
-    .fetch_one(&mut *tx)
+    ensure_email_domain_allowed(&config.borrow().auth, &email)?;
+
+    let min_password_score = config
+        .borrow_and_update()
+        .application_settings
+        .min_password_score();
+    ensure_password_strength(
+        &password,
+        &[name.as_str(), email.as_str()],
+        min_password_score,
+    )?;
+    let breach_settings = config.borrow().auth.breached_passwords.clone();
+    ensure_password_not_breached(&breached_passwords, &breach_settings, &password).await?;
+
+    let password_hashing = config.borrow().password_hashing.params();
+    let password_hash = crate::utils::spawn_blocking_with_tracing(move || {
+        compute_password_hash(password, password_hashing)
+    })
     .await
-    .on_constraint("users_email_key", |_| {
-        ApiError::unprocessable_entity([("email", "email already taken")])
-    })?;
-
-    let token = generate_confirmation_token();
+    .context("Failed to hash password")??;
+
+    let locale = Locale::from_headers(&headers).unwrap_or_default();
+    let (token, expiry_hours) = {
+        let config = config.borrow();
+        (
+            generate_token(&config.tokens),
+            config.tokens.confirmation_expiry_hours(),
+        )
+    };
+    let guest_id = maybe_auth_user.guest_id();
+
+    with_transaction(&mut conn, move |tx| {
+        Box::pin(async move {
+            let user_id = sqlx::query_as!(
+                UserId,
+                r#"
+                INSERT INTO users (name, email, password_hash, locale, org_id)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING user_id;
+                "#,
+                name,
+                email,
+                password_hash.expose_secret(),
+                locale.as_str(),
+                *org,
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .on_constraint("users_email_key", |_| {
+                ApiError::unprocessable_entity([("email", "email already taken")])
+            })?;
 
-    store_token(&mut *tx, &token, user_id.user_id)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+            store_token(&mut *tx, &token, user_id.user_id, expiry_hours)
+                .await
+                .context("Failed to store the confirmation token for a new subscriber.")?;
 
-    enqueue_delivery_task(&mut *tx, token, email)
-        .await
-        .context("Failed to enqueue confirmation delivery task")?;
+            enqueue_delivery_task(&mut *tx, token, email)
+                .await
+                .context("Failed to enqueue confirmation delivery task")?;
+
+            if let Some(guest_id) = guest_id {
+                merge_guest_into_user(&mut *tx, guest_id, user_id.user_id).await?;
+            }
+            Ok::<_, ApiError>(())
+        })
+    })
+    .await?;
 
-    tx.commit().await?;
+    if guest_id.is_some() {
+        session.remove::<GuestId>("guest_id").await?;
+    }
 
     Ok(())
 }
 
 #[derive(serde::Deserialize)]
+struct PasswordStrengthCheck {
+    password: SecretString,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+/// Scores a candidate password for live feedback on the frontend. Nothing is persisted.
+async fn password_strength(
+    Form(form): Form<PasswordStrengthCheck>,
+) -> Result<Json<PasswordStrength>, ApiError> {
+    let user_inputs: Vec<&str> = [form.name.as_deref(), form.email.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(Json(estimate_password_strength(
+        &form.password,
+        &user_inputs,
+    )))
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 pub struct UpdatePassword {
     name: String,
+    #[schemars(with = "String")]
     password: SecretString,
 }
 
 async fn update_password(
+    State(AppState {
+        mut config,
+        session_store,
+        email_client,
+        tx: notifications,
+        breached_passwords,
+        ..
+    }): State<AppState>,
     user_id: AuthUser,
+    session: Session,
     DatabaseConnection(mut conn): DatabaseConnection,
     Form(form): Form<UpdatePassword>,
 ) -> Result<(), ApiError> {
     let UpdatePassword { name, password } = form;
-    let password_hash =
-        crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(password))
-            .await
-            .context("Failed to hash password")??;
+    let min_password_score = config
+        .borrow_and_update()
+        .application_settings
+        .min_password_score();
+    ensure_password_strength(&password, &[name.as_str()], min_password_score)?;
+    let breach_settings = config.borrow().auth.breached_passwords.clone();
+    ensure_password_not_breached(&breached_passwords, &breach_settings, &password).await?;
+    let password_hashing = config.borrow().password_hashing.params();
+    let password_hash = crate::utils::spawn_blocking_with_tracing(move || {
+        compute_password_hash(password, password_hashing)
+    })
+    .await
+    .context("Failed to hash password")??;
 
-    sqlx::query!(
+    let mut tx = conn.begin().await?;
+
+    let updated = sqlx::query!(
         r#"
         UPDATE users
         SET password_hash = $1
         WHERE name = $2 AND user_id = $3
+        RETURNING email, locale
         "#,
         password_hash.expose_secret(),
         name,
         *user_id,
     )
-    .execute(&mut *conn)
+    .fetch_optional(&mut *tx)
     .await
     .context("Failed to change user's password in the database.")?;
+
+    let Some(user) = updated else {
+        return Ok(());
+    };
+
+    // Kick out everyone else, who might have logged in with the old password.
+    revoke_user_sessions(&mut tx, &*session_store, *user_id, session.id()).await?;
+
+    let alert = Notification::security_alert(*user_id, SecurityAlertReason::PasswordChanged)
+        .save_for_recipient(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+
+    notify_password_changed(
+        &email_client,
+        &notifications,
+        alert,
+        user.email,
+        user.locale,
+    )
+    .await;
+
     Ok(())
 }
 
+/// Sends the (already saved) `SecurityAlert` to the user's open SSE streams and by email.
+/// The password is already changed at this point, so failures are only logged.
+async fn notify_password_changed(
+    email_client: &EmailClient,
+    notifications: &broadcast::Sender<Notification>,
+    alert: Notification,
+    email: String,
+    locale: Option<String>,
+) {
+    let _ = notifications.send(alert);
+
+    let locale = locale
+        .as_deref()
+        .and_then(Locale::parse)
+        .unwrap_or_default();
+    let outcome = match Email::parse(email) {
+        Ok(email) => email_client
+            .send_message(password_changed_message(email, locale))
+            .await
+            .map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = outcome {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send the password changed alert"
+        );
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct ForgetPassword {
     name: String,
     email: String,
 }
 
+/// Sends a password reset link, if the name and email belong to a user. The response is the
+/// same either way, and with `security.constant_time_auth` on, so is its timing: the email is
+/// sent in the background, see [`request_password_reset`].
 async fn forget_password_gen(
     DatabaseConnection(mut conn): DatabaseConnection,
-    State(AppState { email_client, .. }): State<AppState>,
+    State(AppState {
+        email_client,
+        mut config,
+        ..
+    }): State<AppState>,
     Form(form): Form<ForgetPassword>,
 ) -> Result<(), ApiError> {
     let ForgetPassword { name, email } = form;
-
-    let result = sqlx::query!(
-        r#"
-        SELECT user_id
-        FROM users
-        WHERE name = $1 AND email = $2
-        "#,
-        name,
-        email,
-    )
-    .fetch_optional(&mut *conn)
-    .await?;
-
-    if let Some(user_id) = result.map(|r| r.user_id) {
-        let token = uuid::Uuid::new_v4();
-
-        sqlx::query!(
-            r#"
-            INSERT INTO forget_password_tokens (token, user_id)
-            VALUES ($1, $2)
-            "#,
-            token,
-            user_id
+    let (tokens, constant_time, links) = {
+        let config = config.borrow_and_update();
+        (
+            config.tokens.clone(),
+            config.security.constant_time_auth(),
+            EmailLinks::from_settings(&config),
         )
-        .execute(&mut *conn)
-        .await?;
-
-        email_client
-            .send_mail(
-                Email::parse(email)?,
-                "Recipe App - Your password reset",
-                &format!(
-                    "Visit http://localhost:3001/forget_password?token={}",
-                    token
-                ),
-                &format!(
-                    "Visit http://localhost:3001/forget_password?token={}",
-                    token
-                ),
-            )
-            .await?;
+    };
+
+    let Some(reset) =
+        request_password_reset(&mut conn, &tokens, &name, &email, constant_time).await?
+    else {
+        return Ok(());
+    };
+    let message = password_reset_message(Email::parse(email)?, reset.locale, &links, &reset.token);
+    if !constant_time {
+        email_client.send_message(message).await?;
+        return Ok(());
     }
+    tokio::spawn(async move {
+        if let Err(e) = email_client.send_message(message).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the password reset email"
+            );
+        }
+    });
     Ok(())
 }
 
 #[derive(serde::Deserialize)]
 struct ForgetPasswordParameters {
-    token: uuid::Uuid,
+    token: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 pub struct ResetPassword {
+    #[schemars(with = "String")]
     password: SecretString,
 }
 
 #[derive(serde::Deserialize)]
 pub struct ResetDetails {
-    token: uuid::Uuid,
+    token: String,
     user_id: uuid::Uuid,
+    expired: bool,
 }
 
 async fn forget_password(
+    State(AppState {
+        mut config,
+        session_store,
+        email_client,
+        tx: notifications,
+        breached_passwords,
+        ..
+    }): State<AppState>,
     Query(params): Query<ForgetPasswordParameters>,
     DatabaseConnection(mut conn): DatabaseConnection,
     Form(form): Form<ResetPassword>,
 ) -> Result<(), ApiError> {
-    let mut tx = conn.begin().await?;
+    let min_password_score = config
+        .borrow_and_update()
+        .application_settings
+        .min_password_score();
+    ensure_password_strength(&form.password, &[], min_password_score)?;
+    let breach_settings = config.borrow().auth.breached_passwords.clone();
+    ensure_password_not_breached(&breached_passwords, &breach_settings, &form.password).await?;
+    let password_hashing = config.borrow().password_hashing.params();
+    let token = params.token;
+
+    let (user, alert) = with_transaction(&mut conn, move |tx| {
+        Box::pin(async move {
+            let result = sqlx::query_as!(
+                ResetDetails,
+                r#"
+                SELECT user_id, token, expires_at < NOW() AS "expired!"
+                FROM forget_password_tokens
+                WHERE token = $1
+                ORDER BY created_at DESC
+                LIMIT 1;
+                "#,
+                hash_token(&token),
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
 
-    // TODO: expiry is a little dumb this way, but let's just don't care about it for now.
-    let result = sqlx::query_as!(
-        ResetDetails,
-        r#"
-        SELECT user_id, token
-        FROM forget_password_tokens
-        WHERE created_at > current_timestamp - INTERVAL '2 days' AND token = $1
-        ORDER BY created_at DESC
-        LIMIT 1;
-        "#,
-        params.token,
-    )
-    .fetch_optional(&mut *tx)
+            if result.as_ref().is_some_and(|r| r.expired) {
+                return Err(ApiError::TokenExpired);
+            }
+            let Some(reset_details) = result else {
+                return Err(ApiError::BadRequest);
+            };
+
+            let password_hash = crate::utils::spawn_blocking_with_tracing(move || {
+                compute_password_hash(form.password, password_hashing)
+            })
+            .await
+            .context("Failed to hash password")??;
+
+            let user = sqlx::query!(
+                r#"
+                UPDATE users
+                SET password_hash = $1
+                WHERE user_id = $2
+                RETURNING email, locale
+                "#,
+                password_hash.expose_secret(),
+                reset_details.user_id,
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to change user's password in the database.")?;
+
+            // There's no current session in this flow, so every session is revoked.
+            revoke_user_sessions(&mut *tx, &*session_store, reset_details.user_id, None).await?;
+
+            sqlx::query!(
+                r#"
+                DELETE FROM forget_password_tokens
+                WHERE user_id = $1 AND token = $2
+                "#,
+                reset_details.user_id,
+                reset_details.token,
+            )
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete from forget_password_tokens.")?;
+
+            let alert = Notification::security_alert(
+                reset_details.user_id,
+                SecurityAlertReason::PasswordChanged,
+            )
+            .save_for_recipient(&mut *tx)
+            .await?;
+
+            Ok((user, alert))
+        })
+    })
     .await?;
 
-    if let Some(reset_details) = result {
-        let password_hash =
-            crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(form.password))
-                .await
-                .context("Failed to hash password")??;
-
-        sqlx::query!(
-            r#"
-            UPDATE users
-            SET password_hash = $1
-            WHERE user_id = $2
-            "#,
-            password_hash.expose_secret(),
-            reset_details.user_id,
-        )
-        .execute(&mut *tx)
-        .await
-        .context("Failed to change user's password in the database.")?;
-
-        sqlx::query!(
-            r#"
-            DELETE FROM forget_password_tokens
-            WHERE user_id = $1 AND token = $2
-            "#,
-            reset_details.user_id,
-            reset_details.token,
-        )
-        .execute(&mut *tx)
-        .await
-        .context("Failed to delete from forget_password_tokens.")?;
+    notify_password_changed(
+        &email_client,
+        &notifications,
+        alert,
+        user.email,
+        user.locale,
+    )
+    .await;
 
-        tx.commit().await?;
-        Ok(())
-    } else {
-        Err(ApiError::BadRequest)
-    }
+    Ok(())
 }
 
 async fn is_token_valid(
     Query(params): Query<ForgetPasswordParameters>,
     DatabaseConnection(mut conn): DatabaseConnection,
 ) -> Result<(), ApiError> {
-    sqlx::query!(
+    let token = sqlx::query!(
         r#"
-        SELECT token
+        SELECT expires_at < NOW() AS "expired!"
         FROM forget_password_tokens
-        WHERE created_at > current_timestamp - INTERVAL '2 days' AND token = $1
+        WHERE token = $1
         ORDER BY created_at DESC
         LIMIT 1;
         "#,
-        params.token,
+        hash_token(&params.token),
     )
     .fetch_optional(&mut *conn)
     .await?
     .ok_or(ApiError::NotFound)?;
 
+    if token.expired {
+        return Err(ApiError::TokenExpired);
+    }
+
     Ok(())
 }
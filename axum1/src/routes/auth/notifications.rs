@@ -0,0 +1,137 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Json,
+};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection},
+    pagination::{Paginated, Pagination},
+};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct SavedNotification {
+    pub id: uuid::Uuid,
+    /// The event name it was sent live with, like `security_alert`.
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub read: bool,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct NotificationsQuery {
+    #[serde(default)]
+    unread: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UnreadCount {
+    pub count: i64,
+}
+
+/// The notifications saved for the user, the latest first. With `unread=true`, only the unread
+/// ones.
+#[tracing::instrument(skip(conn))]
+pub async fn notifications(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Query(query): Query<NotificationsQuery>,
+    pagination: Pagination,
+) -> Result<Paginated<SavedNotification>, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let notifications = sqlx::query_as!(
+        SavedNotification,
+        r#"
+        SELECT id, kind, payload, created_at, read_at IS NOT NULL AS "read!"
+        FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        ORDER BY created_at DESC, id
+        LIMIT $3 OFFSET $4
+        "#,
+        *auth_user,
+        query.unread,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        "#,
+        *auth_user,
+        query.unread
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Paginated::new(notifications, pagination, total))
+}
+
+pub async fn count_unread(conn: &mut PgConnection, user_id: uuid::Uuid) -> Result<i64, ApiError> {
+    Ok(sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM notifications WHERE user_id = $1 AND read_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?)
+}
+
+/// The number of unread notifications, for the badge.
+#[tracing::instrument(skip(conn))]
+pub async fn unread_count(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+) -> Result<Json<UnreadCount>, ApiError> {
+    let count = count_unread(&mut conn, *auth_user).await?;
+    Ok(Json(UnreadCount { count }))
+}
+
+/// Marks a notification read. Marking it again keeps the time it was first read.
+#[tracing::instrument(skip(conn))]
+pub async fn mark_notification_read(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let marked = sqlx::query!(
+        "UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE user_id = $1 AND id = $2",
+        *auth_user,
+        id
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    if marked == 0 {
+        return Err(ApiError::NotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn mark_all_read(conn: &mut PgConnection, user_id: uuid::Uuid) -> Result<(), ApiError> {
+    sqlx::query!(
+        "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn mark_all_notifications_read(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+) -> Result<StatusCode, ApiError> {
+    mark_all_read(&mut conn, *auth_user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
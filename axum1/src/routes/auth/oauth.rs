@@ -1,5 +1,8 @@
 use anyhow::Context;
-use axum::{extract::Query, Extension, Json};
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
 use oauth2::{
     reqwest::async_http_client, AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier,
     Scope, StandardRevocableToken, TokenResponse,
@@ -7,10 +10,16 @@ use oauth2::{
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::Acquire;
 
-use super::{confirm::generate_confirmation_token, password::compute_password_hash};
+use super::{
+    ensure_email_domain_allowed, guest::merge_guest_into_user, password::compute_password_hash,
+    sessions::start_user_session,
+};
 use crate::{
+    config::{PasswordHashingSettings, TokenSettings},
     error::{ApiError, ResultExt},
-    extractors::DatabaseConnection,
+    extractors::{DatabaseConnection, GuestId},
+    state::AppState,
+    token::generate_token,
     utils::{DiscordOAuthClient, GoogleOAuthClient},
 };
 use tower_sessions::Session;
@@ -77,6 +86,7 @@ macro_rules! oauth_handlers_for_provider {
 
             #[tracing::instrument(skip_all)]
             pub(super) async fn [<$provider _authorize>](
+                State(AppState { config, .. }): State<AppState>,
                 Query(query): Query<AuthRequest>,
                session: Session,
                 Extension($client(oauth_client)): Extension<$client>,
@@ -134,11 +144,16 @@ macro_rules! oauth_handlers_for_provider {
                 let user_id = if let Some(u) = user {
                     u.user_id
                 } else {
+                    // Only new accounts, like for `register`.
+                    ensure_email_domain_allowed(&config.borrow().auth, &user_data.email)?;
+
                     // Assign a random strong password for the user.
-                    let random_pw = SecretString::from(generate_confirmation_token());
+                    let random_pw = SecretString::from(generate_token(&TokenSettings::default()));
 
+                    // Nobody logs in with it, the default cost will do.
+                    let params = PasswordHashingSettings::default().params();
                     let password_hash =
-                        crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(random_pw))
+                        crate::utils::spawn_blocking_with_tracing(move || compute_password_hash(random_pw, params))
                             .await
                             .context("Failed to hash password")??;
                     let user = sqlx::query!(
@@ -163,8 +178,18 @@ macro_rules! oauth_handlers_for_provider {
                     })?;
                     user.user_id
                 };
+
+                let guest_id = session.get::<GuestId>("guest_id").await?;
+                if let Some(guest_id) = guest_id {
+                    merge_guest_into_user(&mut *tx, guest_id, user_id).await?;
+                }
+
                 tx.commit().await?;
 
+                if guest_id.is_some() {
+                    session.remove::<GuestId>("guest_id").await?;
+                }
+
                 let token_to_revoke: StandardRevocableToken = match token.refresh_token() {
                     Some(token) => token.into(),
                     None => token.access_token().into(),
@@ -177,10 +202,7 @@ macro_rules! oauth_handlers_for_provider {
                     .await
                     .ok();
 
-                session.cycle_id().await?;
-                session
-                    .insert("user_id", user_id).await
-                    .expect("user_id is serializable");
+                start_user_session(&session, &mut *conn, user_id).await?;
 
                 Ok(())
             }
@@ -4,14 +4,21 @@ use argon2::{
     PasswordVerifier, Version,
 };
 use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgConnection;
+use zxcvbn::zxcvbn;
 
-use crate::{error::ApiError, extractors::DatabaseConnection};
+use crate::error::ApiError;
 
 use super::Credentials;
 
+/// Checks the email and password, and returns the user's id.
+///
+/// If the stored hash is weaker than `params`, it's replaced with one hashed with `params`. That
+/// only happens after a successful login, failing to upgrade the hash doesn't fail the login.
 pub async fn validate_credentials(
     credentials: Credentials,
-    DatabaseConnection(mut conn): DatabaseConnection,
+    conn: &mut PgConnection,
+    params: Params,
 ) -> Result<uuid::Uuid, ApiError> {
     let row: Option<_> = sqlx::query!(
         r#"
@@ -35,27 +42,118 @@ pub async fn validate_credentials(
         }
     };
 
-    crate::utils::spawn_blocking_with_tracing(move || {
+    let stored_hash = expected_password_hash.clone();
+    let upgraded_hash = crate::utils::spawn_blocking_with_tracing(move || {
         let expected_password_hash = PasswordHash::new(&expected_password_hash)?;
         Argon2::default().verify_password(
             credentials.password.expose_secret().as_bytes(),
             &expected_password_hash,
-        )
+        )?;
+        // Still on the blocking thread, hashing is just as slow as verifying.
+        if !needs_rehash(&expected_password_hash, &params) {
+            return Ok(None);
+        }
+        Ok(compute_password_hash(credentials.password, params)
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to upgrade a password hash"))
+            .ok())
     })
     .await
     .context("unexpected error happened during password hashing")?
-    .map_err(|_| ApiError::unprocessable_entity([("password", "password is wrong")]))?;
+    .map_err(|_: argon2::password_hash::Error| {
+        ApiError::unprocessable_entity([("password", "password is wrong")])
+    })?;
+
+    if let Some(upgraded_hash) = upgraded_hash {
+        // Unless the password was changed in the meantime.
+        let upgraded = sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE user_id = $2 AND password_hash = $3",
+            upgraded_hash.expose_secret(),
+            user_id,
+            stored_hash
+        )
+        .execute(&mut *conn)
+        .await;
+        match upgraded {
+            Ok(_) => tracing::info!(%user_id, "Upgraded the password hash"),
+            Err(e) => tracing::warn!(error = %e, %user_id, "Failed to upgrade a password hash"),
+        }
+    }
     Ok(user_id)
 }
 
-pub fn compute_password_hash(password: SecretString) -> Result<SecretString, anyhow::Error> {
+/// Whether `hash` isn't an Argon2id hash at least as costly as `params`.
+pub fn needs_rehash(hash: &PasswordHash<'_>, params: &Params) -> bool {
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    let Ok(current) = Params::try_from(hash) else {
+        return true;
+    };
+    current.m_cost() < params.m_cost()
+        || current.t_cost() < params.t_cost()
+        || current.p_cost() < params.p_cost()
+}
+
+pub fn compute_password_hash(
+    password: SecretString,
+    params: Params,
+) -> Result<SecretString, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(SecretString::from(password_hash))
 }
+
+#[derive(Debug, serde::Serialize)]
+pub struct PasswordStrength {
+    score: u8,
+    crack_time: String,
+    warning: Option<String>,
+    suggestions: Vec<String>,
+}
+
+/// Scores a password with `zxcvbn`. `user_inputs` are things like the user's name or email,
+/// that should be penalized when they appear in the password.
+pub fn estimate_password_strength(
+    password: &SecretString,
+    user_inputs: &[&str],
+) -> PasswordStrength {
+    let entropy = zxcvbn(password.expose_secret(), user_inputs);
+    let feedback = entropy.feedback();
+    PasswordStrength {
+        score: u8::from(entropy.score()),
+        crack_time: entropy
+            .crack_times()
+            .offline_slow_hashing_1e4_per_second()
+            .to_string(),
+        warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+        suggestions: feedback
+            .map(|f| f.suggestions().iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Rejects passwords scoring below `min_score` with a `422 Unprocessable Entity`,
+/// carrying the `zxcvbn` feedback.
+pub fn ensure_password_strength(
+    password: &SecretString,
+    user_inputs: &[&str],
+    min_score: u8,
+) -> Result<(), ApiError> {
+    let strength = estimate_password_strength(password, user_inputs);
+    if strength.score >= min_score {
+        return Ok(());
+    }
+    let mut errors = vec![(
+        "password",
+        format!(
+            "is too weak (score {} of required {min_score})",
+            strength.score
+        ),
+    )];
+    errors.extend(strength.warning.map(|w| ("password", w)));
+    errors.extend(strength.suggestions.into_iter().map(|s| ("password", s)));
+    Err(ApiError::unprocessable_entity(errors))
+}
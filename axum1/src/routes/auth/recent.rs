@@ -0,0 +1,117 @@
+use anyhow::Context;
+use axum::{extract::State, http::StatusCode};
+use sqlx::Acquire;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, Json},
+    org::Org,
+    state::AppState,
+};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RecentRecipe {
+    pub name: String,
+    pub slug: String,
+    pub description: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RecentlyViewedRecipes {
+    pub enabled: bool,
+    pub recipes: Vec<RecentRecipe>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RecentlyViewedSettings {
+    pub enabled: bool,
+}
+
+/// The recipes the user viewed recently, the latest first. Recipes deleted or hidden since are
+/// left out.
+#[tracing::instrument(skip(recently_viewed, conn))]
+pub async fn recently_viewed(
+    State(AppState {
+        recently_viewed, ..
+    }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+) -> Result<Json<RecentlyViewedRecipes>, ApiError> {
+    let slugs = recently_viewed
+        .list(*auth_user)
+        .await
+        .context("Failed to read the recently viewed recipes")?;
+
+    let mut tx = conn.begin().await?;
+
+    let enabled = sqlx::query_scalar!(
+        "SELECT track_recently_viewed FROM users WHERE user_id = $1",
+        *auth_user
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::Unauthorized)?;
+
+    let recipes = sqlx::query_as!(
+        RecentRecipe,
+        r#"
+        SELECT r.name, r.slug, r.description
+        FROM UNNEST($1::TEXT[]) WITH ORDINALITY AS viewed(slug, position)
+        INNER JOIN recipes r ON r.slug = viewed.slug
+        WHERE r.org_id = $2 AND (NOT r.hidden OR r.creator_id = $3)
+          AND (r.visibility <> 'private' OR r.creator_id = $3)
+        ORDER BY viewed.position
+        "#,
+        &slugs,
+        *org,
+        *auth_user
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(RecentlyViewedRecipes { enabled, recipes }))
+}
+
+/// Turns tracking on or off. Turning it off also forgets the history.
+#[tracing::instrument(skip(recently_viewed, conn))]
+pub async fn update_recently_viewed_settings(
+    State(AppState {
+        recently_viewed, ..
+    }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Json(settings): Json<RecentlyViewedSettings>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query!(
+        "UPDATE users SET track_recently_viewed = $1 WHERE user_id = $2",
+        settings.enabled,
+        *auth_user
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if !settings.enabled {
+        recently_viewed
+            .clear(*auth_user)
+            .await
+            .context("Failed to clear the recently viewed recipes")?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(skip(recently_viewed))]
+pub async fn clear_recently_viewed(
+    State(AppState {
+        recently_viewed, ..
+    }): State<AppState>,
+    auth_user: AuthUser,
+) -> Result<StatusCode, ApiError> {
+    recently_viewed
+        .clear(*auth_user)
+        .await
+        .context("Failed to clear the recently viewed recipes")?;
+    Ok(StatusCode::NO_CONTENT)
+}
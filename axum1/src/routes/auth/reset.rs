@@ -0,0 +1,61 @@
+use sqlx::PgConnection;
+
+use crate::{
+    config::TokenSettings,
+    error::ApiError,
+    locale::Locale,
+    token::{generate_token, hash_token},
+};
+
+use super::timing::ResponseFloor;
+
+/// A password reset token stored for a user, to be sent to them.
+#[derive(Debug)]
+pub struct PasswordReset {
+    pub token: String,
+    pub locale: Locale,
+}
+
+/// Stores a password reset token for the user with this name and email, if there's one.
+///
+/// It does the same work either way: the token is generated up front, and looking the user up
+/// and storing it is a single statement. With `constant_time` on, it also returns no sooner than
+/// a [`ResponseFloor`], so what's left doesn't show in the response time either.
+pub async fn request_password_reset(
+    conn: &mut PgConnection,
+    tokens: &TokenSettings,
+    name: &str,
+    email: &str,
+    constant_time: bool,
+) -> Result<Option<PasswordReset>, ApiError> {
+    let floor = ResponseFloor::start(constant_time);
+    let token = generate_token(tokens);
+
+    let stored = sqlx::query!(
+        r#"
+        WITH found AS (
+            SELECT user_id, locale FROM users WHERE name = $2 AND email = $3
+        ), stored AS (
+            INSERT INTO forget_password_tokens (token, user_id, expires_at)
+            SELECT $1, user_id, NOW() + make_interval(hours => $4) FROM found
+        )
+        SELECT locale FROM found
+        "#,
+        hash_token(&token),
+        name,
+        email,
+        tokens.password_reset_expiry_hours()
+    )
+    .fetch_optional(&mut *conn)
+    .await;
+    floor.wait().await;
+
+    Ok(stored?.map(|row| PasswordReset {
+        token,
+        locale: row
+            .locale
+            .as_deref()
+            .and_then(Locale::parse)
+            .unwrap_or_default(),
+    }))
+}
@@ -0,0 +1,90 @@
+use anyhow::Context;
+use sqlx::PgConnection;
+use tower_sessions::{session::Id, Session, SessionStore};
+
+use crate::{csrf::rotate_csrf_token, error::ApiError};
+
+/// Logs the user in and records the session in `user_sessions`, so it can be revoked later,
+/// e.g. when the password changes.
+pub(crate) async fn start_user_session(
+    session: &Session,
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+) -> Result<(), ApiError> {
+    // Rotate the session cookie on privilege level change.
+    // This is to prevent session-fixation attacks.
+    session.cycle_id().await?;
+    session
+        .insert("user_id", user_id)
+        .await
+        .expect("user_id is serializable");
+    rotate_csrf_token(session).await?;
+    // The new id is only assigned when the session is saved, which would otherwise happen
+    // after the response is sent.
+    session.save().await?;
+    let session_id = session.id().context("Saved session has no id")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        session_id.to_string(),
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Forgets the session, the caller is responsible for deleting it from the store.
+pub(crate) async fn end_user_session(
+    session: &Session,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    if let Some(session_id) = session.id() {
+        sqlx::query!(
+            "DELETE FROM user_sessions WHERE session_id = $1",
+            session_id.to_string()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Deletes every session of the user from the session store, except `keep`.
+/// Returns the number of revoked sessions.
+pub async fn revoke_user_sessions(
+    conn: &mut PgConnection,
+    store: &dyn SessionStore,
+    user_id: uuid::Uuid,
+    keep: Option<Id>,
+) -> Result<usize, ApiError> {
+    let session_ids = sqlx::query_scalar!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+        RETURNING session_id
+        "#,
+        user_id,
+        keep.map(|id| id.to_string())
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for session_id in &session_ids {
+        // Ids that fail to parse could not have been issued by the store in the first place.
+        let Ok(session_id) = session_id.parse::<Id>() else {
+            continue;
+        };
+        store
+            .delete(&session_id)
+            .await
+            .context("Failed to delete a session from the store")?;
+    }
+
+    Ok(session_ids.len())
+}
@@ -0,0 +1,34 @@
+//! Keeps the endpoints that look accounts up from telling by their response time whether one
+//! exists, see `security.constant_time_auth`. The lookups do the same work whatever they find, and
+//! the responses are held back until a randomized floor, which hides what's left, like a slow
+//! database round trip.
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::Instant;
+
+/// Every answer takes at least this long, plus a random jitter up to `MAX_JITTER`.
+pub const MIN_RESPONSE_TIME: Duration = Duration::from_millis(150);
+pub const MAX_JITTER: Duration = Duration::from_millis(100);
+
+/// The earliest a response may be sent, if the floor is enabled.
+#[derive(Debug)]
+pub struct ResponseFloor(Option<Instant>);
+
+impl ResponseFloor {
+    /// Starts counting when the request is received, before anything is looked up.
+    pub fn start(enabled: bool) -> Self {
+        Self(enabled.then(|| {
+            Instant::now()
+                + MIN_RESPONSE_TIME
+                + rand::thread_rng().gen_range(Duration::ZERO..=MAX_JITTER)
+        }))
+    }
+
+    /// Waits until the floor, whether the lookup succeeded or not.
+    pub async fn wait(self) {
+        if let Some(deadline) = self.0 {
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
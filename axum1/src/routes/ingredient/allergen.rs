@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+
+use axum::extract::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, Json},
+    org::Org,
+};
+
+/// The allergens that have to be declared on food labels in the EU.
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[sqlx(rename_all = "snake_case", type_name = "allergen")]
+#[serde(rename_all = "snake_case")]
+pub enum Allergen {
+    Gluten,
+    Crustaceans,
+    Eggs,
+    Fish,
+    Peanuts,
+    Soy,
+    Dairy,
+    Nuts,
+    Celery,
+    Mustard,
+    Sesame,
+    Sulphites,
+    Lupin,
+    Molluscs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllergenStatus {
+    /// Every ingredient has been checked, the recipe contains exactly the listed allergens.
+    Complete,
+    /// Some ingredients haven't been checked, so the recipe may contain allergens that aren't
+    /// listed.
+    Unverified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllergenProfile {
+    /// The allergens at least one of the ingredients is known to contain.
+    pub contains: BTreeSet<Allergen>,
+    pub status: AllergenStatus,
+}
+
+impl AllergenProfile {
+    /// Whether the recipe is known to be free of the allergen.
+    pub fn is_free_of(&self, allergen: Allergen) -> bool {
+        self.status == AllergenStatus::Complete && !self.contains.contains(&allergen)
+    }
+}
+
+/// The allergens of a recipe, from the allergens of its ingredients. `None` is an ingredient
+/// nobody has checked yet, while an empty list is one checked to contain none.
+///
+/// Any unchecked ingredient (or having no ingredients at all) leaves the profile unverified, we
+/// never claim a recipe is free of an allergen without knowing every ingredient.
+pub fn allergen_profile(ingredients: &[Option<Vec<Allergen>>]) -> AllergenProfile {
+    let mut status = if ingredients.is_empty() {
+        AllergenStatus::Unverified
+    } else {
+        AllergenStatus::Complete
+    };
+    let mut contains = BTreeSet::new();
+    for ingredient in ingredients {
+        match ingredient {
+            Some(allergens) => contains.extend(allergens.iter().copied()),
+            None => status = AllergenStatus::Unverified,
+        }
+    }
+    AllergenProfile { contains, status }
+}
+
+/// Sorted and without duplicates, so equal sets compare equal when suggestions are merged.
+pub fn normalized(mut allergens: Vec<Allergen>) -> Vec<Allergen> {
+    allergens.sort();
+    allergens.dedup();
+    allergens
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct IngredientAllergens {
+    /// Missing until somebody checks the ingredient. They can only be changed by moderators,
+    /// everyone else suggests changes.
+    pub allergens: Option<Vec<Allergen>>,
+}
+
+pub async fn get_allergens(
+    Path(name): Path<String>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+) -> Result<Json<IngredientAllergens>, ApiError> {
+    let allergens = sqlx::query_as!(
+        IngredientAllergens,
+        r#"
+        SELECT allergens AS "allergens: Vec<Allergen>" FROM ingredients
+        WHERE name = $1 AND org_id = $2
+        "#,
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    Ok(Json(allergens))
+}
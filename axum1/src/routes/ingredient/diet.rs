@@ -0,0 +1,155 @@
+use axum::extract::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, Form, Json},
+    org::Org,
+};
+
+/// The dietary attributes of an ingredient. `None` means it hasn't been checked yet.
+#[derive(sqlx::FromRow, Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DietaryFlags {
+    pub vegan: Option<bool>,
+    pub vegetarian: Option<bool>,
+    pub gluten_free: Option<bool>,
+    pub contains_nuts: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Diet {
+    Vegan,
+    Vegetarian,
+    GlutenFree,
+    NutFree,
+}
+
+impl Diet {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Vegan => "vegan",
+            Self::Vegetarian => "vegetarian",
+            Self::GlutenFree => "gluten_free",
+            Self::NutFree => "nut_free",
+        }
+    }
+
+    /// Whether a single ingredient fits the diet, if known.
+    fn allows(&self, flags: &DietaryFlags) -> Option<bool> {
+        match self {
+            Self::Vegan => flags.vegan,
+            Self::Vegetarian => flags.vegetarian,
+            Self::GlutenFree => flags.gluten_free,
+            Self::NutFree => flags.contains_nuts.map(|contains_nuts| !contains_nuts),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compliance {
+    Yes,
+    No,
+    /// Some ingredients haven't been checked, so we can't claim the recipe fits the diet.
+    Unverified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DietaryProfile {
+    pub vegan: Compliance,
+    pub vegetarian: Compliance,
+    pub gluten_free: Compliance,
+    pub nut_free: Compliance,
+}
+
+impl DietaryProfile {
+    pub fn get(&self, diet: Diet) -> Compliance {
+        match diet {
+            Diet::Vegan => self.vegan,
+            Diet::Vegetarian => self.vegetarian,
+            Diet::GlutenFree => self.gluten_free,
+            Diet::NutFree => self.nut_free,
+        }
+    }
+}
+
+/// The dietary profile of a recipe: it fits a diet only if every ingredient does.
+///
+/// A single ingredient that doesn't fit rules the diet out, otherwise any unchecked ingredient
+/// (or having no ingredients at all) leaves it unverified.
+pub fn dietary_profile(ingredients: &[DietaryFlags]) -> DietaryProfile {
+    let compliance = |diet: Diet| {
+        let mut compliance = if ingredients.is_empty() {
+            Compliance::Unverified
+        } else {
+            Compliance::Yes
+        };
+        for ingredient in ingredients {
+            match diet.allows(ingredient) {
+                Some(true) => {}
+                Some(false) => return Compliance::No,
+                None => compliance = Compliance::Unverified,
+            }
+        }
+        compliance
+    };
+
+    DietaryProfile {
+        vegan: compliance(Diet::Vegan),
+        vegetarian: compliance(Diet::Vegetarian),
+        gluten_free: compliance(Diet::GlutenFree),
+        nut_free: compliance(Diet::NutFree),
+    }
+}
+
+pub async fn get_dietary_flags(
+    Path(name): Path<String>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+) -> Result<Json<DietaryFlags>, ApiError> {
+    let flags = sqlx::query_as!(
+        DietaryFlags,
+        r#"
+        SELECT vegan, vegetarian, gluten_free, contains_nuts FROM ingredients
+        WHERE name = $1 AND org_id = $2
+        "#,
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    Ok(Json(flags))
+}
+
+/// Replaces the dietary flags of an ingredient, omitted flags become unknown again.
+pub async fn set_dietary_flags(
+    Path(name): Path<String>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Form(flags): Form<DietaryFlags>,
+) -> Result<Json<DietaryFlags>, ApiError> {
+    let flags = sqlx::query_as!(
+        DietaryFlags,
+        r#"
+        UPDATE ingredients
+        SET vegan = $1, vegetarian = $2, gluten_free = $3, contains_nuts = $4
+        WHERE name = $5 AND org_id = $6
+        RETURNING vegan, vegetarian, gluten_free, contains_nuts
+        "#,
+        flags.vegan,
+        flags.vegetarian,
+        flags.gluten_free,
+        flags.contains_nuts,
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    Ok(Json(flags))
+}
@@ -0,0 +1,266 @@
+use axum::extract::Path;
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::{ApiError, ResultExt},
+    extractors::{AuthUser, DatabaseConnection, Json},
+    org::Org,
+    pagination::{Paginated, Pagination},
+    routes::recipe::nutrition::{recipes_using_ingredient, refresh_recipe_nutrition},
+};
+
+use super::{allergen::Allergen, FoodCategory, Ingredient};
+
+/// The data of an ingredient as a revision recorded it. Everything a suggestion may change, and
+/// so everything a revert restores.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct IngredientSnapshot {
+    pub name: String,
+    pub category: Vec<FoodCategory>,
+    pub calories_per_100g: f32,
+    pub g_per_piece: Option<f32>,
+    pub protein: f32,
+    pub water: f32,
+    pub fat: f32,
+    pub sugar: f32,
+    pub carbohydrate: f32,
+    pub fiber: f32,
+    pub caffeine: f32,
+    pub contains_alcohol: bool,
+    pub price_per_100g: Option<f32>,
+    pub price_currency: Option<String>,
+    pub allergens: Option<Vec<Allergen>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngredientRevision {
+    /// Numbered from 1 per ingredient. The first one is the data before the first recorded change.
+    pub revision: i32,
+    pub ingredient: IngredientSnapshot,
+    /// The name of whoever applied the change, unless they have been deleted since.
+    pub applied_by: Option<String>,
+    /// The suggestions applied or merged by the change. Already deleted, they're only kept for
+    /// reference.
+    pub suggestion_ids: Vec<uuid::Uuid>,
+    /// For a revert, the revision it restored.
+    pub reverted_to: Option<i32>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Records the current data of the ingredient as its next revision. Run it in the transaction of
+/// the change, after the ingredient is locked or updated, so concurrent changes get their own
+/// numbers.
+pub(super) async fn record_revision(
+    conn: &mut PgConnection,
+    ingredient_id: uuid::Uuid,
+    applied_by: Option<uuid::Uuid>,
+    suggestion_ids: &[uuid::Uuid],
+    reverted_to: Option<i32>,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO ingredient_revisions (ingredient_id, revision, snapshot, applied_by, suggestion_ids, reverted_to)
+        SELECT
+            i.id,
+            COALESCE((SELECT MAX(revision) FROM ingredient_revisions WHERE ingredient_id = i.id), 0) + 1,
+            jsonb_build_object(
+                'name', i.name,
+                'category', COALESCE(i.category, '{}'),
+                'calories_per_100g', i.calories_per_100g,
+                'g_per_piece', i.g_per_piece,
+                'protein', i.protein,
+                'water', i.water,
+                'fat', i.fat,
+                'sugar', i.sugar,
+                'carbohydrate', i.carbohydrate,
+                'fiber', i.fiber,
+                'caffeine', i.caffeine,
+                'contains_alcohol', i.contains_alcohol,
+                'price_per_100g', i.price_per_100g,
+                'price_currency', i.price_currency,
+                'allergens', i.allergens
+            ),
+            $2, $3, $4
+        FROM ingredients i
+        WHERE i.id = $1
+        "#,
+        ingredient_id,
+        applied_by,
+        suggestion_ids,
+        reverted_to
+    )
+    .execute(&mut *conn)
+    .await
+    .on_constraint("ingredient_revisions_ingredient_id_revision_key", |_| {
+        ApiError::Conflict
+    })?;
+    Ok(())
+}
+
+/// Records the data of the ingredient before its first recorded change, so there's something to
+/// revert it to. Does nothing once it has a history.
+pub(super) async fn record_initial_revision(
+    conn: &mut PgConnection,
+    ingredient_id: uuid::Uuid,
+) -> Result<(), ApiError> {
+    let has_history = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM ingredient_revisions WHERE ingredient_id = $1) AS "exists!""#,
+        ingredient_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if !has_history {
+        record_revision(conn, ingredient_id, None, &[], None).await?;
+    }
+    Ok(())
+}
+
+/// The revisions of the ingredient, the latest first.
+pub async fn ingredient_history(
+    conn: &mut PgConnection,
+    org: Org,
+    name: &str,
+    pagination: Pagination,
+) -> Result<Paginated<IngredientRevision>, ApiError> {
+    let ingredient_id = sqlx::query_scalar!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2",
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let revisions = sqlx::query!(
+        r#"
+        SELECT
+            r.revision,
+            r.snapshot AS "snapshot: sqlx::types::Json<IngredientSnapshot>",
+            u.name AS "applied_by?",
+            r.suggestion_ids,
+            r.reverted_to,
+            r.created_at
+        FROM ingredient_revisions r
+        LEFT JOIN users u ON u.user_id = r.applied_by
+        WHERE r.ingredient_id = $1
+        ORDER BY r.revision DESC
+        LIMIT $2 OFFSET $3
+        "#,
+        ingredient_id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| IngredientRevision {
+        revision: row.revision,
+        ingredient: row.snapshot.0,
+        applied_by: row.applied_by,
+        suggestion_ids: row.suggestion_ids,
+        reverted_to: row.reverted_to,
+        created_at: row.created_at,
+    })
+    .collect();
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM ingredient_revisions WHERE ingredient_id = $1"#,
+        ingredient_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(revisions, pagination, total))
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn get_ingredient_history(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Path(name): Path<String>,
+    pagination: Pagination,
+) -> Result<Paginated<IngredientRevision>, ApiError> {
+    ingredient_history(&mut conn, org, &name, pagination).await
+}
+
+/// Restores the data of the ingredient from one of its revisions, recorded as a new revision, so
+/// nothing is lost, and refreshes the nutrition of the recipes using it.
+pub async fn revert_ingredient(
+    conn: &mut PgConnection,
+    org: Org,
+    moderator_id: uuid::Uuid,
+    name: &str,
+    revision: i32,
+) -> Result<Ingredient, ApiError> {
+    let ingredient_id = sqlx::query_scalar!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let ingredient = sqlx::query_as!(
+        Ingredient,
+        r#"
+        UPDATE ingredients i
+        SET name = s.name,
+            category = s.category,
+            calories_per_100g = s.calories_per_100g,
+            g_per_piece = s.g_per_piece,
+            protein = s.protein,
+            water = s.water,
+            fat = s.fat,
+            sugar = s.sugar,
+            carbohydrate = s.carbohydrate,
+            fiber = s.fiber,
+            caffeine = s.caffeine,
+            contains_alcohol = s.contains_alcohol,
+            price_per_100g = s.price_per_100g,
+            price_currency = s.price_currency,
+            allergens = s.allergens
+        FROM ingredient_revisions r, jsonb_populate_record(NULL::ingredients, r.snapshot) s
+        WHERE i.id = $1 AND r.ingredient_id = i.id AND r.revision = $2
+        RETURNING i.name, i.category as "category!: Vec<FoodCategory>", i.calories_per_100g,
+                  i.g_per_piece, i.protein, i.water, i.fat, i.sugar, i.carbohydrate, i.fiber,
+                  i.caffeine, i.contains_alcohol;
+        "#,
+        ingredient_id,
+        revision
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .on_constraint("ingredients_name_key", |_| ApiError::Conflict)?
+    .ok_or(ApiError::NotFound)?;
+
+    record_revision(
+        &mut *conn,
+        ingredient_id,
+        Some(moderator_id),
+        &[],
+        Some(revision),
+    )
+    .await?;
+    let affected_recipes = recipes_using_ingredient(&mut *conn, ingredient_id).await?;
+    refresh_recipe_nutrition(&mut *conn, &affected_recipes).await?;
+
+    Ok(ingredient)
+}
+
+/// `POST /i/:name/history/:revision/revert`. Must be behind the `AdminUser` guard, admins act as
+/// the moderators.
+#[tracing::instrument(skip(conn, moderator))]
+pub async fn revert_to_revision(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    moderator: AuthUser,
+    Path((name, revision)): Path<(String, i32)>,
+) -> Result<Json<Ingredient>, ApiError> {
+    let mut tx = conn.begin().await?;
+    let ingredient = revert_ingredient(&mut tx, org, *moderator, &name, revision).await?;
+    tx.commit().await?;
+
+    Ok(Json(ingredient))
+}
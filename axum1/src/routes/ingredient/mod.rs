@@ -1,26 +1,45 @@
 use axum::{
     extract::Path,
     middleware::from_extractor_with_state,
-    routing::{delete, get, post},
-    Json, Router,
+    routing::{delete, get, post, put},
+    Router,
 };
-// Because we need to deserialize a sequence from a form, we need `axum-extra`.
-// See: https://github.com/tokio-rs/axum/pull/1031
-use axum_extra::extract::Form;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::Connection;
 
 use crate::{
     error::ApiError,
-    extractors::{AuthUser, DatabaseConnection},
+    // `Form` wraps `axum-extra`'s, because we need to deserialize a sequence from a form.
+    // See: https://github.com/tokio-rs/axum/pull/1031
+    extractors::{AuthUser, DatabaseConnection, Form, Json},
+    org::Org,
+    pagination::{Paginated, Pagination},
+    routes::recipe::{
+        favorite::FavoriteState,
+        nutrition::{recipes_using_ingredient, refresh_recipe_nutrition},
+    },
     state::AppState,
 };
 
+pub mod allergen;
+pub mod diet;
+pub mod history;
+pub mod permissions;
+pub mod quota;
+pub mod resolve;
 pub mod suggestion;
 use suggestion::add_ingredient_suggestion;
 
+use self::allergen::{get_allergens, normalized, Allergen};
+use self::diet::{get_dietary_flags, set_dietary_flags};
+use self::history::{
+    get_ingredient_history, record_initial_revision, record_revision, revert_to_revision,
+};
+use self::permissions::{ensure_fields_editable, EditorRole};
 use self::suggestion::{
     apply_suggestion, decline_suggestion, get_ingredient_suggestion, get_ingredient_suggestions,
+    merge_suggestions,
 };
 
 use super::admin::AdminUser;
@@ -31,6 +50,9 @@ pub fn router(state: AppState) -> Router<AppState> {
         .route("/:name/suggestion/:id/decline", get(decline_suggestion))
         .route("/:name/suggestion/:id", get(get_ingredient_suggestion))
         .route("/:name/suggestions", get(get_ingredient_suggestions))
+        .route("/:name/suggestions/merge", post(merge_suggestions))
+        .route("/:name/history/:revision/revert", post(revert_to_revision))
+        .route("/:name/diet", put(set_dietary_flags))
         .route(
             "/:name",
             delete(delete_ingredient).patch(upgrade_ingredient),
@@ -40,14 +62,18 @@ pub fn router(state: AppState) -> Router<AppState> {
 
     Router::new()
         .route("/all", get(all_ingredients))
+        .route("/resolve", post(resolve::resolve_ingredients))
         .route("/category/:category", get(ingredients_by_category))
         .route("/:name", get(get_ingredient))
+        .route("/:name/diet", get(get_dietary_flags))
+        .route("/:name/allergens", get(get_allergens))
+        .route("/:name/history", get(get_ingredient_history))
         .route("/favorite/:name", post(make_favorite)) // TODO: swap route to `/:name/favorite` maybe for consistency?
         .route("/:name/suggestion", post(add_ingredient_suggestion))
         .merge(admin_services)
 }
 
-#[derive(sqlx::Type, Debug, Deserialize, Serialize, Clone)]
+#[derive(sqlx::Type, Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
 #[sqlx(rename_all = "snake_case", type_name = "food_category")]
 #[serde(rename_all = "snake_case")]
 pub enum FoodCategory {
@@ -69,7 +95,7 @@ pub enum FoodCategory {
     Uncategorized,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Ingredient {
     pub name: String,
     pub calories_per_100g: f32,
@@ -85,46 +111,98 @@ pub struct Ingredient {
     pub contains_alcohol: bool,
 }
 
+impl Ingredient {
+    /// See [`UpgradeIngredient::validate_nutrition`].
+    pub fn validate_nutrition(&self) -> Result<(), ApiError> {
+        UpgradeIngredient {
+            calories_per_100g: Some(self.calories_per_100g),
+            protein: Some(self.protein),
+            water: Some(self.water),
+            fat: Some(self.fat),
+            sugar: Some(self.sugar),
+            carbohydrate: Some(self.carbohydrate),
+            fiber: Some(self.fiber),
+            caffeine: Some(self.caffeine),
+            ..Default::default()
+        }
+        .validate_nutrition(None)
+    }
+}
+
+/// How much water, protein, fat and carbohydrate 100 grams may have together. A little over 100,
+/// as the values in nutrition tables are rounded.
+pub const MAX_MACROS_PER_100G: f32 = 105.0;
+
 async fn all_ingredients(
     DatabaseConnection(mut conn): DatabaseConnection,
-) -> Result<Json<Vec<Ingredient>>, ApiError> {
+    org: Org,
+    pagination: Pagination,
+) -> Result<Paginated<Ingredient>, ApiError> {
     let rows: Vec<_> = sqlx::query_as!(
         Ingredient,
         r#"
         SELECT name, calories_per_100g, category as "category: Vec<FoodCategory>", g_per_piece,
         protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol
-        FROM ingredients;
-        "#
+        FROM ingredients
+        WHERE org_id = $3
+        ORDER BY name
+        LIMIT $1 OFFSET $2;
+        "#,
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
-    Ok(Json(rows))
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM ingredients WHERE org_id = $1"#,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(Paginated::new(rows, pagination, total))
 }
 
 async fn ingredients_by_category(
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Path(category): Path<FoodCategory>,
-) -> Result<Json<Vec<Ingredient>>, ApiError> {
+    pagination: Pagination,
+) -> Result<Paginated<Ingredient>, ApiError> {
     let rows: Vec<_> = sqlx::query_as!(
         Ingredient,
         r#"
         SELECT name, calories_per_100g, category as "category: Vec<FoodCategory>", g_per_piece,
         protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol
         FROM ingredients
-        WHERE $1 = ANY (category);
+        WHERE $1 = ANY (category) AND org_id = $4
+        ORDER BY name
+        LIMIT $2 OFFSET $3;
         "#,
-        category as _
+        category.clone() as _,
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
-    Ok(Json(rows))
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM ingredients WHERE $1 = ANY (category) AND org_id = $2"#,
+        category as _,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(Paginated::new(rows, pagination, total))
 }
 
 async fn add_ingredient(
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     auth_user: AuthUser,
     Form(ingredient): Form<Ingredient>,
 ) -> Result<(), ApiError> {
+    ingredient.validate_nutrition()?;
     sqlx::query!(
         r#"
         INSERT INTO ingredients (
@@ -140,9 +218,10 @@ async fn add_ingredient(
             fiber,
             caffeine,
             contains_alcohol,
-            creator_id
+            creator_id,
+            org_id
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13);
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14);
         "#,
         ingredient.name,
         ingredient.category as _,
@@ -157,13 +236,14 @@ async fn add_ingredient(
         ingredient.caffeine,
         ingredient.contains_alcohol,
         *auth_user,
+        *org,
     )
     .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
 pub struct UpgradeIngredient {
     name: Option<String>,
     calories_per_100g: Option<f32>,
@@ -177,23 +257,150 @@ pub struct UpgradeIngredient {
     fiber: Option<f32>,
     caffeine: Option<f32>,
     contains_alcohol: Option<bool>,
+    /// The complete list, replacing the current one. An empty list means no allergens.
+    allergens: Option<Vec<Allergen>>,
+    /// `null`, or an empty value in a form, removes the price. Leaving it out keeps it as it is.
+    #[sqlx(skip)]
+    #[serde(default, deserialize_with = "explicit_null")]
+    price: Option<Option<IngredientPrice>>,
+}
+
+/// Tells a field set to `null` (`Some(None)`) apart from a missing one (`None`), for the fields
+/// that may be removed.
+fn explicit_null<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// The price of 100 grams of an ingredient.
+impl UpgradeIngredient {
+    /// Rejects nutrition values that can't be right, like negative calories or 900 grams of
+    /// protein in 100 grams. Every violation is reported at once, under the field's name, or
+    /// under `macros` if the macros add up to more than [`MAX_MACROS_PER_100G`].
+    ///
+    /// The fields left unset are taken from `current` for the sum, if there's one.
+    pub fn validate_nutrition(&self, current: Option<&Ingredient>) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        if self
+            .calories_per_100g
+            .is_some_and(|calories| !calories.is_finite() || calories < 0.0)
+        {
+            errors.push(("calories_per_100g", "must be a non-negative number"));
+        }
+        let grams = [
+            ("protein", self.protein),
+            ("water", self.water),
+            ("fat", self.fat),
+            ("sugar", self.sugar),
+            ("carbohydrate", self.carbohydrate),
+            ("fiber", self.fiber),
+            ("caffeine", self.caffeine),
+        ];
+        for (field, value) in grams {
+            if value.is_some_and(|g| !(0.0..=100.0).contains(&g)) {
+                errors.push((field, "must be between 0 and 100 grams per 100g"));
+            }
+        }
+
+        let macros = [
+            (self.water, current.map(|c| c.water)),
+            (self.protein, current.map(|c| c.protein)),
+            (self.fat, current.map(|c| c.fat)),
+            (self.carbohydrate, current.map(|c| c.carbohydrate)),
+        ];
+        let sum: f32 = macros
+            .into_iter()
+            .filter_map(|(value, current)| value.or(current))
+            .sum();
+        if sum > MAX_MACROS_PER_100G {
+            errors.push((
+                "macros",
+                "water, protein, fat and carbohydrate can't add up to more than 100g per 100g",
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::unprocessable_entity(errors))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct IngredientPrice {
+    pub amount_per_100g: f32,
+    /// An ISO 4217 currency code, like `EUR`.
+    pub currency: String,
+}
+
+impl IngredientPrice {
+    /// The amount and the currency are stored in separate columns, but always set together.
+    pub fn from_columns(amount_per_100g: Option<f32>, currency: Option<String>) -> Option<Self> {
+        Some(Self {
+            amount_per_100g: amount_per_100g?,
+            currency: currency?,
+        })
+    }
+
+    pub fn into_columns(price: Option<Self>) -> (Option<f32>, Option<String>) {
+        match price {
+            Some(price) => (Some(price.amount_per_100g), Some(price.currency)),
+            None => (None, None),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if !self.amount_per_100g.is_finite() || self.amount_per_100g < 0.0 {
+            return Err(ApiError::unprocessable_entity([(
+                "price",
+                "the amount must be a non-negative number",
+            )]));
+        }
+        if self.currency.len() != 3 || !self.currency.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(ApiError::unprocessable_entity([(
+                "price",
+                "the currency must be an ISO 4217 code, like EUR",
+            )]));
+        }
+        Ok(())
+    }
 }
 
 async fn upgrade_ingredient(
     Path(name): Path<String>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
     Form(ingredient): Form<UpgradeIngredient>,
 ) -> Result<Json<Ingredient>, ApiError> {
     let mut tx = conn.begin().await?;
-    let original = sqlx::query_as::<_, Ingredient>(
-        "SELECT name, category, calories_per_100g, g_per_piece,
-        protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol
-        FROM ingredients WHERE name = $1",
+    ensure_fields_editable(EditorRole::of(&mut tx, *auth_user).await?, &ingredient)?;
+    if let Some(Some(price)) = &ingredient.price {
+        price.validate()?;
+    }
+    // Locked until the revision is recorded, so concurrent changes get their own numbers.
+    let ingredient_id = sqlx::query_scalar!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+        name,
+        *org
     )
-    .bind(name.clone())
     .fetch_optional(&mut *tx)
     .await?
     .ok_or(ApiError::NotFound)?;
+    let original = sqlx::query_as::<_, Ingredient>(
+        "SELECT name, category, calories_per_100g, g_per_piece,
+        protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol
+        FROM ingredients WHERE id = $1",
+    )
+    .bind(ingredient_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    ingredient.validate_nutrition(Some(&original))?;
+    record_initial_revision(&mut tx, ingredient_id).await?;
 
     let row = sqlx::query_as!(
         Ingredient,
@@ -210,8 +417,11 @@ async fn upgrade_ingredient(
             carbohydrate = $9,
             fiber = $10,
             caffeine = $11,
-            contains_alcohol = $12
-        WHERE name = $13
+            contains_alcohol = $12,
+            price_per_100g = CASE WHEN $18 THEN $15 ELSE price_per_100g END,
+            price_currency = CASE WHEN $18 THEN $16 ELSE price_currency END,
+            allergens = COALESCE($17, allergens)
+        WHERE name = $13 AND org_id = $14
         RETURNING name, category as "category!: Vec<FoodCategory>", calories_per_100g, g_per_piece,
                   protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol;
         "#,
@@ -231,10 +441,26 @@ async fn upgrade_ingredient(
         ingredient
             .contains_alcohol
             .unwrap_or(original.contains_alcohol),
-        name
+        name,
+        *org,
+        ingredient
+            .price
+            .as_ref()
+            .and_then(|p| p.as_ref())
+            .map(|p| p.amount_per_100g),
+        ingredient
+            .price
+            .as_ref()
+            .and_then(|p| p.as_ref())
+            .map(|p| p.currency.as_str()),
+        ingredient.allergens.map(normalized) as _,
+        ingredient.price.is_some(),
     )
     .fetch_one(&mut *tx)
     .await?;
+    record_revision(&mut tx, ingredient_id, Some(*auth_user), &[], None).await?;
+    let affected_recipes = recipes_using_ingredient(&mut tx, ingredient_id).await?;
+    refresh_recipe_nutrition(&mut tx, &affected_recipes).await?;
 
     tx.commit().await?;
 
@@ -244,6 +470,7 @@ async fn upgrade_ingredient(
 async fn get_ingredient(
     Path(name): Path<String>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
 ) -> Result<Json<Ingredient>, ApiError> {
     let row = sqlx::query_as!(
         Ingredient,
@@ -251,9 +478,10 @@ async fn get_ingredient(
             SELECT name, calories_per_100g, category as "category: Vec<FoodCategory>", g_per_piece,
             protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol
             FROM ingredients
-            WHERE name = $1;
+            WHERE name = $1 AND org_id = $2;
             "#,
-        name
+        name,
+        *org
     )
     .fetch_optional(&mut *conn)
     .await?
@@ -265,20 +493,35 @@ async fn get_ingredient(
 async fn delete_ingredient(
     Path(name): Path<String>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
 ) -> Result<Json<Ingredient>, ApiError> {
+    let mut tx = conn.begin().await?;
+    let ingredient_id = sqlx::query_scalar!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+        name,
+        *org
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+    // Collected before the delete, which also removes the ingredient from the recipes.
+    let affected_recipes = recipes_using_ingredient(&mut tx, ingredient_id).await?;
+
     let row = sqlx::query_as!(
         Ingredient,
         r#"
         DELETE FROM ingredients
-        WHERE name = $1
+        WHERE id = $1
         RETURNING name, category as "category!: Vec<FoodCategory>", calories_per_100g, g_per_piece,
                   protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol;
         "#,
-        name
+        ingredient_id
     )
-    .fetch_optional(&mut *conn)
-    .await?
-    .ok_or(ApiError::NotFound)?;
+    .fetch_one(&mut *tx)
+    .await?;
+    refresh_recipe_nutrition(&mut tx, &affected_recipes).await?;
+
+    tx.commit().await?;
 
     Ok(Json(row))
 }
@@ -290,9 +533,10 @@ struct IngredientId {
 
 async fn make_favorite(
     auth_user: AuthUser,
+    org: Org,
     Path(name): Path<String>,
     DatabaseConnection(mut conn): DatabaseConnection,
-) -> Result<(), ApiError> {
+) -> Result<Json<FavoriteState>, ApiError> {
     // You can implement this either with a single query using Common Table Expressions (CTEs),
     // or multiple queries with a transaction.
     //
@@ -316,9 +560,10 @@ async fn make_favorite(
         r#"
         SELECT id
         FROM ingredients
-        WHERE name = $1
+        WHERE name = $1 AND org_id = $2
         "#,
-        name
+        name,
+        *org
     )
     .fetch_optional(&mut *tx)
     .await?
@@ -333,8 +578,19 @@ async fn make_favorite(
     .execute(&mut *tx)
     .await?;
 
+    // Return the resulting state, so the client doesn't need to ask for it separately.
+    let favorite_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM favorite_ingredient WHERE ingredient_id = $1"#,
+        ingredient.id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
     // Don't forget to commit to actually run those queries against the database.
     tx.commit().await?;
 
-    Ok(())
+    Ok(Json(FavoriteState {
+        favorited: true,
+        favorite_count,
+    }))
 }
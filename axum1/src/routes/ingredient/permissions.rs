@@ -0,0 +1,55 @@
+use sqlx::PgConnection;
+
+use crate::error::ApiError;
+
+use super::{suggestion::suggested_fields, UpgradeIngredient};
+
+/// Who is changing an ingredient. Admins act as the moderators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EditorRole {
+    User,
+    Moderator,
+}
+
+impl EditorRole {
+    pub async fn of(conn: &mut PgConnection, user_id: uuid::Uuid) -> Result<Self, ApiError> {
+        let user = sqlx::query!(
+            "SELECT is_admin, is_super_admin FROM users WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+        if user.is_admin || user.is_super_admin {
+            Ok(Self::Moderator)
+        } else {
+            Ok(Self::User)
+        }
+    }
+}
+
+/// The least privileged role that may change a field directly. Everyone else can only suggest.
+pub fn required_role(field: &str) -> EditorRole {
+    match field {
+        // A wrong value here misleads people avoiding alcohol or allergens, or filtering by
+        // category.
+        "category" | "contains_alcohol" | "allergens" => EditorRole::Moderator,
+        _ => EditorRole::User,
+    }
+}
+
+/// Rejects the change with `403` naming the first field the role may not change. Every field
+/// that is set counts as a change, even if it matches the current value.
+pub fn ensure_fields_editable(
+    role: EditorRole,
+    fields: &UpgradeIngredient,
+) -> Result<(), ApiError> {
+    match suggested_fields(fields)
+        .into_iter()
+        .find(|field| role < required_role(field))
+    {
+        Some(field) => Err(ApiError::FieldForbidden { field }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,71 @@
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponse, Response},
+};
+use sqlx::PgConnection;
+
+use crate::error::ApiError;
+
+pub const LIMIT_HEADER: &str = "x-ratelimit-limit";
+pub const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+/// What's left of a user's daily suggestion allowance. Responds with the allowance in the
+/// `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuggestionQuota {
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+/// Counts a suggestion against the user's allowance for the current UTC day, rejecting it with
+/// `429` once the allowance is used up.
+///
+/// Run it in the transaction inserting the suggestion, so a suggestion that fails anyway (like a
+/// duplicate) isn't counted.
+pub async fn consume_suggestion_quota(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    limit: u32,
+) -> Result<SuggestionQuota, ApiError> {
+    if limit == 0 {
+        return Err(ApiError::TooManyRequests);
+    }
+
+    sqlx::query!(
+        "DELETE FROM suggestion_counts WHERE user_id = $1 AND day < (NOW() AT TIME ZONE 'UTC')::DATE",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let count = sqlx::query_scalar!(
+        r#"
+        INSERT INTO suggestion_counts (user_id, day, count)
+        VALUES ($1, (NOW() AT TIME ZONE 'UTC')::DATE, 1)
+        ON CONFLICT (user_id, day) DO
+        UPDATE SET count = suggestion_counts.count + 1
+        WHERE suggestion_counts.count < $2
+        RETURNING count
+        "#,
+        user_id,
+        limit as i32
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::TooManyRequests)?;
+
+    Ok(SuggestionQuota {
+        limit,
+        remaining: limit.saturating_sub(count as u32),
+    })
+}
+
+impl IntoResponse for SuggestionQuota {
+    fn into_response(self) -> Response {
+        let mut response = ().into_response();
+        let headers = response.headers_mut();
+        headers.insert(LIMIT_HEADER, HeaderValue::from(self.limit));
+        headers.insert(REMAINING_HEADER, HeaderValue::from(self.remaining));
+        response
+    }
+}
@@ -0,0 +1,212 @@
+//! Resolves the ingredient names users type while composing a recipe to the ingredients of the
+//! organization. Names are compared slugified, like recipe slugs are generated, so `Jalapeño`,
+//! `jalapeno` and `JALAPENO` are the same name.
+use std::collections::{BTreeSet, HashMap};
+
+use axum::extract::State;
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, Json},
+    org::Org,
+    state::AppState,
+};
+
+/// The most similar ingredients offered for a name without an exact match.
+const MAX_CANDIDATES: i64 = 5;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ResolveRequest {
+    names: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MatchedIngredient {
+    pub id: uuid::Uuid,
+    /// The organization's spelling of the name.
+    pub name: String,
+    pub calories_per_100g: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Candidate {
+    pub id: uuid::Uuid,
+    pub name: String,
+    /// The trigram similarity of the names, from 0 to 1.
+    pub similarity: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "match", rename_all = "snake_case")]
+pub enum Resolution {
+    /// The same name, up to casing, accents and punctuation.
+    Exact { ingredient: MatchedIngredient },
+    /// Similar names, the most similar first. The user picks one, or creates the ingredient.
+    Fuzzy { candidates: Vec<Candidate> },
+    /// Nothing similar, the ingredient has to be created under this name.
+    New { name: String },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ResolvedName {
+    /// As it was sent.
+    pub name: String,
+    #[serde(flatten)]
+    pub resolution: Resolution,
+}
+
+/// The names without surrounding whitespace, blanks and duplicates, in their original order.
+pub fn parse_names(names: &[String]) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    names
+        .iter()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty() && seen.insert(*name))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The ingredients with the same name, by requested name. If the slugs of several ingredients
+/// match, the one spelled the same wins.
+pub async fn exact_matches(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+) -> Result<HashMap<String, MatchedIngredient>, ApiError> {
+    let matches = sqlx::query!(
+        r#"
+        SELECT DISTINCT ON (requested.name)
+            requested.name AS "requested!", i.id, i.name, i.calories_per_100g
+        FROM UNNEST($1::TEXT[]) AS requested(name)
+        INNER JOIN ingredients i
+            ON i.org_id = $2 AND slugify(i.name COLLATE "default") = slugify(requested.name)
+        ORDER BY requested.name, i.name = requested.name DESC, i.name
+        "#,
+        names,
+        *org
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let ingredient = MatchedIngredient {
+            id: row.id,
+            name: row.name,
+            calories_per_100g: row.calories_per_100g,
+        };
+        (row.requested, ingredient)
+    })
+    .collect();
+    Ok(matches)
+}
+
+/// The ingredients with a similar name, by requested name, the most similar first.
+pub async fn fuzzy_candidates(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+) -> Result<HashMap<String, Vec<Candidate>>, ApiError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT requested.name AS "requested!", c.id AS "id!", c.name AS "name!",
+            c.similarity AS "similarity!"
+        FROM UNNEST($1::TEXT[]) AS requested(name)
+        CROSS JOIN LATERAL (
+            SELECT i.id, i.name,
+                similarity(slugify(i.name COLLATE "default"), slugify(requested.name)) AS similarity
+            FROM ingredients i
+            WHERE i.org_id = $2
+              AND slugify(i.name COLLATE "default") % slugify(requested.name)
+            ORDER BY similarity DESC, i.name
+            LIMIT $3
+        ) c
+        ORDER BY requested.name, c.similarity DESC, c.name
+        "#,
+        names,
+        *org,
+        MAX_CANDIDATES
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut candidates: HashMap<String, Vec<Candidate>> = HashMap::new();
+    for row in rows {
+        candidates
+            .entry(row.requested)
+            .or_default()
+            .push(Candidate {
+                id: row.id,
+                name: row.name,
+                similarity: row.similarity,
+            });
+    }
+    Ok(candidates)
+}
+
+/// Resolves every name, in order. Only the names without an exact match are looked up fuzzily.
+pub async fn resolve_names(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+) -> Result<Vec<ResolvedName>, ApiError> {
+    let mut exact = exact_matches(conn, org, names).await?;
+    let unmatched: Vec<String> = names
+        .iter()
+        .filter(|name| !exact.contains_key(*name))
+        .cloned()
+        .collect();
+    let mut fuzzy = if unmatched.is_empty() {
+        HashMap::new()
+    } else {
+        fuzzy_candidates(conn, org, &unmatched).await?
+    };
+
+    Ok(names
+        .iter()
+        .map(|name| {
+            let resolution = if let Some(ingredient) = exact.remove(name) {
+                Resolution::Exact { ingredient }
+            } else if let Some(candidates) = fuzzy.remove(name) {
+                Resolution::Fuzzy { candidates }
+            } else {
+                Resolution::New { name: name.clone() }
+            };
+            ResolvedName {
+                name: name.clone(),
+                resolution,
+            }
+        })
+        .collect())
+}
+
+/// `POST /i/resolve` with `{"names": [...]}`, up to as many names as a recipe may have
+/// ingredients.
+#[tracing::instrument(skip(conn, config))]
+pub async fn resolve_ingredients(
+    State(AppState { config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Json(request): Json<ResolveRequest>,
+) -> Result<Json<Vec<ResolvedName>>, ApiError> {
+    let names = parse_names(&request.names);
+    let max_names = config
+        .borrow()
+        .application_settings
+        .max_recipe_ingredients();
+    if names.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            "must name at least one ingredient",
+        )]));
+    }
+    if names.len() > max_names {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            format!("at most {max_names} names can be resolved at once"),
+        )]));
+    }
+
+    let resolved = resolve_names(&mut conn, org, &names).await?;
+    Ok(Json(resolved))
+}
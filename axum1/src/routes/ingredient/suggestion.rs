@@ -1,15 +1,30 @@
+use std::collections::HashMap;
+
 use anyhow::Context;
-use axum::{extract::Path, Json};
-use sqlx::Acquire;
+use axum::extract::{Path, Query, State};
+use sqlx::{Acquire, PgConnection};
 
 use crate::{
     error::{ApiError, ResultExt},
-    extractors::{AuthUser, DatabaseConnection},
+    extractors::{AuthUser, DatabaseConnection, Json},
+    org::Org,
+    queue::outbox,
+    routes::recipe::nutrition::{recipes_using_ingredient, refresh_recipe_nutrition},
+    sse::Notification,
+    state::AppState,
+    time_range::TimeRange,
+    utils::with_transaction,
 };
 
-use super::{FoodCategory, UpgradeIngredient};
+use super::{
+    allergen::{normalized, Allergen},
+    history::{record_initial_revision, record_revision},
+    permissions::{ensure_fields_editable, EditorRole},
+    quota::{consume_suggestion_quota, SuggestionQuota},
+    FoodCategory, Ingredient, IngredientPrice, UpgradeIngredient,
+};
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct IngredientSuggestion {
     is_delete_vote: Option<bool>,
     update_ingredient: Option<UpgradeIngredient>,
@@ -21,63 +36,100 @@ impl IngredientSuggestion {
     }
 }
 
-#[tracing::instrument(skip(conn, auth_user))]
+#[tracing::instrument(skip(config, conn, auth_user))]
 pub async fn add_ingredient_suggestion(
+    State(AppState { mut config, .. }): State<AppState>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Path(name): Path<String>,
     auth_user: AuthUser,
     Json(ingredient_suggestion): Json<IngredientSuggestion>,
-) -> Result<(), ApiError> {
+) -> Result<SuggestionQuota, ApiError> {
     if ingredient_suggestion.is_irrelevant() {
         return Err(ApiError::BadRequest);
     }
 
     let update_ingredient = ingredient_suggestion.update_ingredient.unwrap_or_default();
-    sqlx::query!(
-        r#"
-        INSERT INTO ingredient_suggestions (
-            ingredient_id,
-            name,
-            category,
-            calories_per_100g,
-            g_per_piece,
-            protein,
-            water,
-            fat,
-            sugar,
-            carbohydrate,
-            fiber,
-            caffeine,
-            contains_alcohol,
-            user_id,
-            is_delete_vote
-        )
-        VALUES ((SELECT id FROM ingredients WHERE name = $1), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15);
-        "#,
-        name,
-        update_ingredient.name,
-        update_ingredient.category as _,
-        update_ingredient.calories_per_100g,
-        update_ingredient.g_per_piece.unwrap_or(None),
-        update_ingredient.protein,
-        update_ingredient.water,
-        update_ingredient.fat,
-        update_ingredient.sugar,
-        update_ingredient.carbohydrate,
-        update_ingredient.fiber,
-        update_ingredient.caffeine,
-        update_ingredient.contains_alcohol,
-        *auth_user,
-        ingredient_suggestion.is_delete_vote,
-    )
-    .execute(&mut *conn)
+    // Only the suggested values, the ones of the ingredient may change before it's applied.
+    update_ingredient.validate_nutrition(None)?;
+    if let Some(Some(price)) = &update_ingredient.price {
+        price.validate()?;
+    }
+    let clears_price = matches!(update_ingredient.price, Some(None));
+    let (price_per_100g, price_currency) =
+        IngredientPrice::into_columns(update_ingredient.price.flatten());
+
+    let max_suggestions_per_day = config
+        .borrow_and_update()
+        .application_settings
+        .max_suggestions_per_day();
+
+    with_transaction(&mut conn, move |tx| {
+        Box::pin(async move {
+            let quota =
+                consume_suggestion_quota(&mut *tx, *auth_user, max_suggestions_per_day).await?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO ingredient_suggestions (
+                    ingredient_id,
+                    name,
+                    category,
+                    calories_per_100g,
+                    g_per_piece,
+                    protein,
+                    water,
+                    fat,
+                    sugar,
+                    carbohydrate,
+                    fiber,
+                    caffeine,
+                    contains_alcohol,
+                    user_id,
+                    is_delete_vote,
+                    price_per_100g,
+                    price_currency,
+                    allergens,
+                    clears_price
+                )
+                VALUES ((SELECT id FROM ingredients WHERE name = $1 AND org_id = $16), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $17, $18, $19, $20);
+                "#,
+                name,
+                update_ingredient.name,
+                update_ingredient.category as _,
+                update_ingredient.calories_per_100g,
+                update_ingredient.g_per_piece.unwrap_or(None),
+                update_ingredient.protein,
+                update_ingredient.water,
+                update_ingredient.fat,
+                update_ingredient.sugar,
+                update_ingredient.carbohydrate,
+                update_ingredient.fiber,
+                update_ingredient.caffeine,
+                update_ingredient.contains_alcohol,
+                *auth_user,
+                ingredient_suggestion.is_delete_vote,
+                *org,
+                price_per_100g,
+                price_currency,
+                update_ingredient.allergens.map(normalized) as _,
+                clears_price,
+            )
+            .execute(&mut *tx)
+            .await
+            .on_constraint("ingredient_suggestions_ingredient_id_user_id_key", |_| {
+                ApiError::Conflict
+            })?;
+
+            Ok::<_, ApiError>(quota)
+        })
+    })
     .await
-    .on_constraint("ingredient_suggestions_ingredient_id_user_id_key", |_| ApiError::Conflict)
-    ?;
-    Ok(())
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+#[derive(
+    Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow, schemars::JsonSchema,
+)]
 pub struct SuggestedIngredient {
     id: uuid::Uuid,
     name: Option<String>,
@@ -92,6 +144,9 @@ pub struct SuggestedIngredient {
     fiber: Option<f32>,
     caffeine: Option<f32>,
     contains_alcohol: Option<bool>,
+    price_per_100g: Option<f32>,
+    price_currency: Option<String>,
+    allergens: Option<Vec<Allergen>>,
     is_delete_vote: Option<bool>,
     suggester: String,
 }
@@ -99,7 +154,9 @@ pub struct SuggestedIngredient {
 #[tracing::instrument(skip(conn))]
 pub async fn get_ingredient_suggestions(
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Path(name): Path<String>,
+    range: TimeRange,
 ) -> Result<Json<Vec<SuggestedIngredient>>, ApiError> {
     let suggestions: Vec<_> = sqlx::query_as!(
         SuggestedIngredient,
@@ -118,14 +175,23 @@ pub async fn get_ingredient_suggestions(
             COALESCE(igs.fiber, i.fiber) AS fiber,
             COALESCE(igs.caffeine, i.caffeine) AS caffeine,
             COALESCE(igs.contains_alcohol, i.contains_alcohol) AS contains_alcohol,
+            CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_per_100g, i.price_per_100g) END AS price_per_100g,
+            CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_currency, i.price_currency) END AS price_currency,
+            COALESCE(igs.allergens, i.allergens) AS "allergens: Vec<Allergen>",
             u.name as suggester,
             is_delete_vote
             FROM ingredient_suggestions igs 
         INNER JOIN ingredients i ON igs.ingredient_id = i.id
         INNER JOIN users u ON u.user_id = igs.user_id
-        WHERE ingredient_id = (SELECT id FROM ingredients WHERE name = $1)
+        WHERE ingredient_id = (SELECT id FROM ingredients WHERE name = $1 AND org_id = $4)
+          AND ($2::timestamptz IS NULL OR igs.created_at >= $2)
+          AND ($3::timestamptz IS NULL OR igs.created_at < $3)
+        ORDER BY igs.created_at
         "#,
-        name
+        name,
+        range.since,
+        range.until,
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
@@ -146,12 +212,16 @@ pub struct Suggestion {
     fiber: Option<f32>,
     caffeine: Option<f32>,
     contains_alcohol: Option<bool>,
+    price_per_100g: Option<f32>,
+    price_currency: Option<String>,
+    allergens: Option<Vec<Allergen>>,
     is_delete_vote: Option<bool>,
 }
 
 #[tracing::instrument(skip(conn))]
 pub async fn get_ingredient_suggestion(
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     Path((name, id)): Path<(String, uuid::Uuid)>,
 ) -> Result<Json<Suggestion>, ApiError> {
     let suggestion = sqlx::query_as!(
@@ -170,13 +240,17 @@ pub async fn get_ingredient_suggestion(
             COALESCE(igs.fiber, i.fiber) AS fiber,
             COALESCE(igs.caffeine, i.caffeine) AS caffeine,
             COALESCE(igs.contains_alcohol, i.contains_alcohol) AS contains_alcohol,
+            CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_per_100g, i.price_per_100g) END AS price_per_100g,
+            CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_currency, i.price_currency) END AS price_currency,
+            COALESCE(igs.allergens, i.allergens) AS "allergens: Vec<Allergen>",
             is_delete_vote
         FROM ingredient_suggestions igs
         INNER JOIN ingredients i ON igs.ingredient_id = i.id
-        WHERE i.name = $1 AND igs.id = $2;
+        WHERE i.name = $1 AND i.org_id = $3 AND igs.id = $2;
         "#,
         name,
-        id
+        id,
+        *org
     )
     .fetch_optional(&mut *conn)
     .await?
@@ -184,30 +258,78 @@ pub async fn get_ingredient_suggestion(
     Ok(Json(suggestion))
 }
 
-#[tracing::instrument(skip(conn, id))]
-pub async fn apply_suggestion(
-    DatabaseConnection(mut conn): DatabaseConnection,
-    Path((name, id)): Path<(String, uuid::Uuid)>,
+/// Applies the suggestion to the ingredient, recording a revision, or deletes the ingredient for a
+/// delete vote. Either way, the nutrition of the recipes using it is refreshed.
+///
+/// The author is notified through the outbox, so run it in a transaction and relay the
+/// notifications once it's committed.
+pub async fn apply_ingredient_suggestion(
+    conn: &mut PgConnection,
+    org: Org,
+    user_id: uuid::Uuid,
+    name: &str,
+    id: uuid::Uuid,
 ) -> Result<(), ApiError> {
-    let mut tx = conn.begin().await?;
+    let role = EditorRole::of(&mut *conn, user_id).await?;
 
     let suggestion_row = sqlx::query!(
-        r#"SELECT is_delete_vote FROM ingredient_suggestions WHERE id = $1"#,
+        r#"SELECT is_delete_vote, ingredient_id FROM ingredient_suggestions WHERE id = $1"#,
         id
     )
-    .fetch_optional(&mut *tx)
+    .fetch_optional(&mut *conn)
     .await?
     .ok_or(ApiError::NotFound)?;
 
+    if let (Some(ingredient_id), false) = (
+        suggestion_row.ingredient_id,
+        suggestion_row.is_delete_vote.unwrap_or(false),
+    ) {
+        for suggestion in lock_pending_suggestions(&mut *conn, ingredient_id, Some(&[id])).await? {
+            ensure_fields_editable(role, &suggestion.fields)?;
+        }
+    }
+
+    // Collected before the change, because deleting the ingredient also removes it from the
+    // recipes.
+    let affected_recipes = match suggestion_row.ingredient_id {
+        Some(ingredient_id) => recipes_using_ingredient(&mut *conn, ingredient_id).await?,
+        None => Vec::new(),
+    };
+
+    // Before the change, deleting the ingredient also deletes its suggestions.
+    notify_suggestion_authors(
+        &mut *conn,
+        &[id],
+        name,
+        suggestion_row.is_delete_vote.unwrap_or(false),
+    )
+    .await?;
+
     if suggestion_row.is_delete_vote.unwrap_or(false) {
-        sqlx::query!(r#"DELETE FROM ingredients WHERE name = $1"#, name)
-            .execute(&mut *tx)
-            .await
-            .context("failed to delete from ingredients")?;
+        sqlx::query!(
+            r#"DELETE FROM ingredients WHERE name = $1 AND org_id = $2"#,
+            name,
+            *org
+        )
+        .execute(&mut *conn)
+        .await
+        .context("failed to delete from ingredients")?;
     } else {
+        // Locked until the revision is recorded, so concurrent changes get their own numbers.
+        let ingredient_id = sqlx::query_scalar!(
+            "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+            name,
+            *org
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some(ingredient_id) = ingredient_id {
+            record_initial_revision(&mut *conn, ingredient_id).await?;
+        }
+
         sqlx::query!(
             r#"
-        UPDATE ingredients i 
+            UPDATE ingredients i
             SET
                 name = COALESCE(igs.name, i.name),
                 category = COALESCE(igs.category, i.category),
@@ -220,17 +342,25 @@ pub async fn apply_suggestion(
                 carbohydrate = COALESCE(igs.carbohydrate, i.carbohydrate),
                 fiber = COALESCE(igs.fiber, i.fiber),
                 caffeine = COALESCE(igs.caffeine, i.caffeine),
-                contains_alcohol = COALESCE(igs.contains_alcohol, i.contains_alcohol)
-        FROM ingredient_suggestions igs
-        WHERE i.name = $1 AND igs.id = $2
-        "#,
+                contains_alcohol = COALESCE(igs.contains_alcohol, i.contains_alcohol),
+                price_per_100g = CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_per_100g, i.price_per_100g) END,
+                price_currency = CASE WHEN igs.clears_price THEN NULL ELSE COALESCE(igs.price_currency, i.price_currency) END,
+                allergens = COALESCE(igs.allergens, i.allergens)
+            FROM ingredient_suggestions igs
+            WHERE i.name = $1 AND i.org_id = $3 AND igs.id = $2
+            "#,
             name,
-            id
+            id,
+            *org
         )
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .on_constraint("ingredients_name_key", |_| ApiError::Conflict)?;
 
+        if let Some(ingredient_id) = ingredient_id {
+            record_revision(&mut *conn, ingredient_id, Some(user_id), &[id], None).await?;
+        }
+
         sqlx::query!(
             r#"
             DELETE FROM ingredient_suggestions
@@ -238,11 +368,29 @@ pub async fn apply_suggestion(
             "#,
             id
         )
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await
         .context("failed to delete from suggestions table")?;
     }
-    tx.commit().await?;
+
+    refresh_recipe_nutrition(&mut *conn, &affected_recipes).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(conn, id, channel, auth_user))]
+pub async fn apply_suggestion(
+    State(AppState { tx: channel, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    Path((name, id)): Path<(String, uuid::Uuid)>,
+) -> Result<(), ApiError> {
+    with_transaction(&mut conn, move |tx| {
+        Box::pin(async move { apply_ingredient_suggestion(tx, org, *auth_user, &name, id).await })
+    })
+    .await?;
+
+    outbox::relay_now(&mut conn, &channel).await;
 
     Ok(())
 }
@@ -265,3 +413,493 @@ pub async fn decline_suggestion(
 
     Ok(())
 }
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MergeSuggestions {
+    ids: Vec<uuid::Uuid>,
+    /// Resolves conflicts by naming which suggestion wins for a given field.
+    #[serde(default)]
+    overrides: HashMap<String, uuid::Uuid>,
+}
+
+/// Combines the non-null fields of several suggestions.
+///
+/// If more than one suggestion sets a field to different values, that's a conflict, unless
+/// `overrides` names the suggestion that wins for that field. On failure, the names of the
+/// conflicting fields are returned.
+pub fn merge_suggested_fields(
+    suggestions: &[(uuid::Uuid, UpgradeIngredient)],
+    overrides: &HashMap<String, uuid::Uuid>,
+) -> Result<UpgradeIngredient, Vec<&'static str>> {
+    let mut merged = UpgradeIngredient::default();
+    let mut conflicts = Vec::new();
+
+    macro_rules! merge_fields {
+        ($($field:ident),* $(,)?) => {
+            $(
+                let candidates: Vec<_> = suggestions
+                    .iter()
+                    .filter_map(|(id, s)| s.$field.clone().map(|value| (*id, value)))
+                    .collect();
+                merged.$field = match overrides.get(stringify!($field)) {
+                    Some(winner) => {
+                        let value = candidates
+                            .iter()
+                            .find(|(id, _)| id == winner)
+                            .map(|(_, value)| value.clone());
+                        // The winner must actually suggest a value for this field.
+                        if value.is_none() {
+                            conflicts.push(stringify!($field));
+                        }
+                        value
+                    }
+                    None => match candidates.split_first() {
+                        None => None,
+                        Some(((_, first), rest)) if rest.iter().all(|(_, value)| value == first) => {
+                            Some(first.clone())
+                        }
+                        Some(_) => {
+                            conflicts.push(stringify!($field));
+                            None
+                        }
+                    },
+                };
+            )*
+        };
+    }
+
+    merge_fields!(
+        name,
+        calories_per_100g,
+        category,
+        g_per_piece,
+        protein,
+        water,
+        fat,
+        sugar,
+        carbohydrate,
+        fiber,
+        caffeine,
+        contains_alcohol,
+        allergens,
+        price,
+    );
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+#[tracing::instrument(skip(conn, auth_user))]
+pub async fn merge_suggestions(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    Path(name): Path<String>,
+    Json(merge): Json<MergeSuggestions>,
+) -> Result<Json<Ingredient>, ApiError> {
+    let MergeSuggestions { mut ids, overrides } = merge;
+    ids.sort_unstable();
+    ids.dedup();
+
+    if ids.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "ids",
+            "at least one suggestion is required",
+        )]));
+    }
+
+    let mut tx = conn.begin().await?;
+
+    // Lock the ingredient and the suggestions, so concurrent merges or applies
+    // of the same suggestions can't interleave with this one.
+    let ingredient = sqlx::query!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+        name,
+        *org
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let pending = lock_pending_suggestions(&mut tx, ingredient.id, Some(&ids)).await?;
+
+    if pending.len() != ids.len() {
+        return Err(ApiError::NotFound);
+    }
+
+    if pending.iter().any(|s| s.is_delete_vote) {
+        return Err(ApiError::unprocessable_entity([(
+            "ids",
+            "delete votes cannot be merged",
+        )]));
+    }
+
+    let suggestions: Vec<_> = pending.into_iter().map(|s| (s.id, s.fields)).collect();
+
+    let merged = merge_suggested_fields(&suggestions, &overrides).map_err(|fields| {
+        ApiError::unprocessable_entity(
+            fields
+                .into_iter()
+                .map(|field| (field, "suggestions have conflicting values")),
+        )
+    })?;
+    ensure_fields_editable(EditorRole::of(&mut tx, *auth_user).await?, &merged)?;
+
+    record_initial_revision(&mut tx, ingredient.id).await?;
+    let row = update_ingredient_fields(&mut tx, ingredient.id, &merged).await?;
+    record_revision(&mut tx, ingredient.id, Some(*auth_user), &ids, None).await?;
+    let affected_recipes = recipes_using_ingredient(&mut tx, ingredient.id).await?;
+    refresh_recipe_nutrition(&mut tx, &affected_recipes).await?;
+
+    sqlx::query!(
+        "DELETE FROM ingredient_suggestions WHERE id = ANY($1)",
+        &ids
+    )
+    .execute(&mut *tx)
+    .await
+    .context("failed to delete from suggestions table")?;
+
+    tx.commit().await?;
+
+    Ok(Json(row))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ApplyAllQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SkippedSuggestion {
+    id: uuid::Uuid,
+    reason: &'static str,
+    /// The fields this suggestion disagrees with other suggestions about.
+    conflicts: Vec<&'static str>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ApplyAllReport {
+    dry_run: bool,
+    applied: Vec<uuid::Uuid>,
+    skipped: Vec<SkippedSuggestion>,
+    /// The combined changes of the applied suggestions.
+    merged: UpgradeIngredient,
+    /// The ingredient after the changes, only when they were actually applied.
+    ingredient: Option<Ingredient>,
+}
+
+/// The names of the fields a suggestion sets.
+pub(super) fn suggested_fields(suggestion: &UpgradeIngredient) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    macro_rules! collect_fields {
+        ($($field:ident),* $(,)?) => {
+            $(
+                if suggestion.$field.is_some() {
+                    fields.push(stringify!($field));
+                }
+            )*
+        };
+    }
+    collect_fields!(
+        name,
+        calories_per_100g,
+        category,
+        g_per_piece,
+        protein,
+        water,
+        fat,
+        sugar,
+        carbohydrate,
+        fiber,
+        caffeine,
+        contains_alcohol,
+        allergens,
+        price,
+    );
+    fields
+}
+
+/// Suggestions by id, with the fields they conflict on.
+pub type ConflictingSuggestions = Vec<(uuid::Uuid, Vec<&'static str>)>;
+
+/// Separates the suggestions that can be applied together from the ones setting a field to a value
+/// some other suggestion disagrees with. The latter are returned with their conflicting fields.
+pub fn split_conflicting_suggestions(
+    suggestions: Vec<(uuid::Uuid, UpgradeIngredient)>,
+) -> (Vec<(uuid::Uuid, UpgradeIngredient)>, ConflictingSuggestions) {
+    let conflicts = merge_suggested_fields(&suggestions, &HashMap::new())
+        .err()
+        .unwrap_or_default();
+
+    let mut applicable = Vec::new();
+    let mut conflicting = Vec::new();
+    for (id, fields) in suggestions {
+        let own_conflicts: Vec<_> = suggested_fields(&fields)
+            .into_iter()
+            .filter(|field| conflicts.contains(field))
+            .collect();
+        if own_conflicts.is_empty() {
+            applicable.push((id, fields));
+        } else {
+            conflicting.push((id, own_conflicts));
+        }
+    }
+    (applicable, conflicting)
+}
+
+/// Applies every pending suggestion of an ingredient that doesn't conflict with another one.
+///
+/// Suggestions setting a field to a value some other suggestion disagrees with are skipped and
+/// left pending, so they can be resolved with `merge_suggestions`. Delete votes take priority:
+/// while any is pending, nothing is applied, since accepting it would throw the edits away anyway.
+/// With `dry_run`, only the report is computed and nothing is changed.
+#[tracing::instrument(skip(conn, channel, auth_user))]
+pub async fn apply_all_suggestions(
+    State(AppState { tx: channel, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    Path(name): Path<String>,
+    Query(query): Query<ApplyAllQuery>,
+) -> Result<Json<ApplyAllReport>, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let ingredient = sqlx::query!(
+        "SELECT id FROM ingredients WHERE name = $1 AND org_id = $2 FOR UPDATE",
+        name,
+        *org
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let pending = lock_pending_suggestions(&mut tx, ingredient.id, None).await?;
+
+    let mut report = ApplyAllReport {
+        dry_run: query.dry_run,
+        applied: Vec::new(),
+        skipped: Vec::new(),
+        merged: UpgradeIngredient::default(),
+        ingredient: None,
+    };
+
+    if pending.iter().any(|s| s.is_delete_vote) {
+        report.skipped = pending
+            .into_iter()
+            .map(|s| SkippedSuggestion {
+                id: s.id,
+                reason: if s.is_delete_vote {
+                    "delete votes must be reviewed one by one"
+                } else {
+                    "the ingredient has pending delete votes"
+                },
+                conflicts: Vec::new(),
+            })
+            .collect();
+        return Ok(Json(report));
+    }
+
+    let suggestions: Vec<_> = pending.into_iter().map(|s| (s.id, s.fields)).collect();
+    let (applicable, conflicting) = split_conflicting_suggestions(suggestions);
+
+    report.skipped = conflicting
+        .into_iter()
+        .map(|(id, conflicts)| SkippedSuggestion {
+            id,
+            reason: "conflicts with other suggestions",
+            conflicts,
+        })
+        .collect();
+    report.applied = applicable.iter().map(|(id, _)| *id).collect();
+    // None of the remaining suggestions touch a conflicting field, so this can't fail.
+    report.merged = merge_suggested_fields(&applicable, &HashMap::new()).unwrap_or_default();
+    ensure_fields_editable(EditorRole::of(&mut tx, *auth_user).await?, &report.merged)?;
+
+    if query.dry_run || applicable.is_empty() {
+        return Ok(Json(report));
+    }
+
+    record_initial_revision(&mut tx, ingredient.id).await?;
+    report.ingredient =
+        Some(update_ingredient_fields(&mut tx, ingredient.id, &report.merged).await?);
+    record_revision(
+        &mut tx,
+        ingredient.id,
+        Some(*auth_user),
+        &report.applied,
+        None,
+    )
+    .await?;
+    let affected_recipes = recipes_using_ingredient(&mut tx, ingredient.id).await?;
+    refresh_recipe_nutrition(&mut tx, &affected_recipes).await?;
+
+    notify_suggestion_authors(&mut tx, &report.applied, &name, false).await?;
+
+    sqlx::query!(
+        "DELETE FROM ingredient_suggestions WHERE id = ANY($1)",
+        &report.applied
+    )
+    .execute(&mut *tx)
+    .await
+    .context("failed to delete from suggestions table")?;
+
+    tx.commit().await?;
+
+    outbox::relay_now(&mut conn, &channel).await;
+
+    Ok(Json(report))
+}
+
+/// Lets the authors of the applied suggestions know, through the outbox. Must be called before
+/// the suggestions are deleted.
+async fn notify_suggestion_authors(
+    conn: &mut PgConnection,
+    suggestion_ids: &[uuid::Uuid],
+    ingredient: &str,
+    is_delete_vote: bool,
+) -> Result<(), ApiError> {
+    let authors = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT user_id AS "user_id!" FROM ingredient_suggestions
+        WHERE id = ANY($1) AND user_id IS NOT NULL
+        "#,
+        suggestion_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for user_id in authors {
+        let notification =
+            Notification::suggestion_applied(user_id, ingredient.to_owned(), is_delete_vote)
+                .save_for_recipient(&mut *conn)
+                .await?;
+        outbox::enqueue(&mut *conn, &notification).await?;
+    }
+    Ok(())
+}
+
+/// A pending suggestion, as needed for merging.
+struct PendingSuggestion {
+    id: uuid::Uuid,
+    fields: UpgradeIngredient,
+    is_delete_vote: bool,
+}
+
+/// Fetches and locks the pending suggestions of an ingredient, optionally only the given ones.
+async fn lock_pending_suggestions(
+    conn: &mut PgConnection,
+    ingredient_id: uuid::Uuid,
+    ids: Option<&[uuid::Uuid]>,
+) -> Result<Vec<PendingSuggestion>, ApiError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            id, name, category as "category: Vec<FoodCategory>", calories_per_100g, g_per_piece,
+            protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol,
+            price_per_100g, price_currency, clears_price, allergens as "allergens: Vec<Allergen>",
+            is_delete_vote
+        FROM ingredient_suggestions
+        WHERE ingredient_id = $1 AND ($2::uuid[] IS NULL OR id = ANY($2))
+        ORDER BY created_at
+        FOR UPDATE
+        "#,
+        ingredient_id,
+        ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| PendingSuggestion {
+            id: r.id,
+            fields: UpgradeIngredient {
+                name: r.name,
+                calories_per_100g: r.calories_per_100g,
+                category: r.category,
+                // A missing `g_per_piece` in a suggestion means "leave it as it is".
+                g_per_piece: r.g_per_piece.map(Some),
+                protein: r.protein,
+                water: r.water,
+                fat: r.fat,
+                sugar: r.sugar,
+                carbohydrate: r.carbohydrate,
+                fiber: r.fiber,
+                caffeine: r.caffeine,
+                contains_alcohol: r.contains_alcohol,
+                allergens: r.allergens,
+                price: if r.clears_price {
+                    Some(None)
+                } else {
+                    IngredientPrice::from_columns(r.price_per_100g, r.price_currency).map(Some)
+                },
+            },
+            is_delete_vote: r.is_delete_vote.unwrap_or(false),
+        })
+        .collect())
+}
+
+/// Overwrites the fields of the ingredient that are set in `fields`, removing the price if it's
+/// set to `null`.
+async fn update_ingredient_fields(
+    conn: &mut PgConnection,
+    ingredient_id: uuid::Uuid,
+    fields: &UpgradeIngredient,
+) -> Result<Ingredient, ApiError> {
+    sqlx::query_as!(
+        Ingredient,
+        r#"
+        UPDATE ingredients
+        SET name = COALESCE($1, name),
+            calories_per_100g = COALESCE($2, calories_per_100g),
+            category = COALESCE($3, category),
+            g_per_piece = COALESCE($4, g_per_piece),
+            protein = COALESCE($5, protein),
+            water = COALESCE($6, water),
+            fat = COALESCE($7, fat),
+            sugar = COALESCE($8, sugar),
+            carbohydrate = COALESCE($9, carbohydrate),
+            fiber = COALESCE($10, fiber),
+            caffeine = COALESCE($11, caffeine),
+            contains_alcohol = COALESCE($12, contains_alcohol),
+            price_per_100g = CASE WHEN $17 THEN $14 ELSE price_per_100g END,
+            price_currency = CASE WHEN $17 THEN $15 ELSE price_currency END,
+            allergens = COALESCE($16, allergens)
+        WHERE id = $13
+        RETURNING name, category as "category!: Vec<FoodCategory>", calories_per_100g, g_per_piece,
+                  protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol;
+        "#,
+        fields.name,
+        fields.calories_per_100g,
+        fields.category.clone() as _,
+        fields.g_per_piece.flatten(),
+        fields.protein,
+        fields.water,
+        fields.fat,
+        fields.sugar,
+        fields.carbohydrate,
+        fields.fiber,
+        fields.caffeine,
+        fields.contains_alcohol,
+        ingredient_id,
+        fields
+            .price
+            .as_ref()
+            .and_then(|p| p.as_ref())
+            .map(|p| p.amount_per_100g),
+        fields
+            .price
+            .as_ref()
+            .and_then(|p| p.as_ref())
+            .map(|p| p.currency.as_str()),
+        fields.allergens.clone() as _,
+        fields.price.is_some(),
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .on_constraint("ingredients_name_key", |_| ApiError::Conflict)
+}
@@ -0,0 +1,105 @@
+//! Redirects the non-canonical addresses of recipes to the canonical one with `301 Moved
+//! Permanently`, so every link to a recipe ends up at the same URL: `/r/Tomato%20Soup/`,
+//! `/r/TOMATO-SOUP` and former slugs all lead to `/r/tomato-soup`. See `canonical_recipe_urls`.
+//!
+//! Only `GET` and `HEAD` requests are redirected, clients don't repeat other methods after a
+//! `301`. Those keep working with any slug [`RecipeName`](super::slug::RecipeName) resolves.
+use std::collections::HashMap;
+
+use axum::{
+    extract::{OriginalUri, Path, Request, State},
+    http::{header::LOCATION, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    RequestExt,
+};
+use percent_encoding::percent_decode_str;
+
+use crate::{error::ApiError, org::Org, state::AppState};
+
+use super::slug::canonical_recipe_slug;
+
+fn redirects(state: &AppState, method: &Method) -> bool {
+    (method == Method::GET || method == Method::HEAD)
+        && state
+            .config
+            .borrow()
+            .application_settings
+            .canonical_recipe_urls()
+}
+
+/// `path` with its first segment replaced by `slug`.
+fn with_slug(path: &str, slug: &str) -> String {
+    let rest = path
+        .strip_prefix('/')
+        .and_then(|path| path.find('/').map(|end| &path[end..]))
+        .unwrap_or("");
+    format!("/{slug}{rest}")
+}
+
+/// Redirects to `canonical_path`, which replaces `path` within the recipe router. The prefix the
+/// router is nested under and the query are kept.
+fn moved_permanently(original: &Uri, path: &str, canonical_path: &str) -> Response {
+    let prefix = original.path().strip_suffix(path).unwrap_or_default();
+    let location = match original.query() {
+        Some(query) => format!("{prefix}{canonical_path}?{query}"),
+        None => format!("{prefix}{canonical_path}"),
+    };
+    (StatusCode::MOVED_PERMANENTLY, [(LOCATION, location)]).into_response()
+}
+
+/// Redirects the requests of the `/:slug` routes to the current slug of the recipe, in its
+/// canonical casing. Unknown slugs are left to the handler to reject.
+pub async fn redirect_to_canonical_slug(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    OriginalUri(original): OriginalUri,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(slug) = params
+        .get("slug")
+        .filter(|_| redirects(&state, request.method()))
+    else {
+        return next.run(request).await;
+    };
+    let org = match request.extract_parts_with_state::<Org, _>(&state).await {
+        Ok(org) => org,
+        Err(e) => return e.into_response(),
+    };
+    match canonical_recipe_slug(&state.db_pool, org, slug).await {
+        Ok(Some(canonical)) if canonical != *slug => {
+            let canonical_path = with_slug(request.uri().path(), &canonical);
+            moved_permanently(&original, request.uri().path(), &canonical_path)
+        }
+        Ok(_) => next.run(request).await,
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
+
+/// The fallback of the recipe router. Redirects paths with a trailing slash to the path without
+/// it, and to the canonical slug at once if the path starts with one, so it's a single redirect.
+/// Anything else is `404 Not Found`.
+pub async fn trailing_slash(
+    State(state): State<AppState>,
+    OriginalUri(original): OriginalUri,
+    mut request: Request,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.len() == path.len() || trimmed.is_empty() || !redirects(&state, request.method()) {
+        return ApiError::NotFound.into_response();
+    }
+    let org = match request.extract_parts_with_state::<Org, _>(&state).await {
+        Ok(org) => org,
+        Err(e) => return e.into_response(),
+    };
+
+    let segment = trimmed[1..].split('/').next().unwrap_or_default();
+    let slug = percent_decode_str(segment).decode_utf8_lossy();
+    match canonical_recipe_slug(&state.db_pool, org, &slug).await {
+        Ok(Some(canonical)) => moved_permanently(&original, &path, &with_slug(trimmed, &canonical)),
+        Ok(None) => moved_permanently(&original, &path, trimmed),
+        Err(e) => ApiError::from(e).into_response(),
+    }
+}
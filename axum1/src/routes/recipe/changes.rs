@@ -0,0 +1,188 @@
+//! Lets clients keep a local copy of the recipes up to date by fetching only what changed since
+//! they last synced, instead of every recipe again.
+use std::{fmt, str::FromStr};
+
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    state::AppState,
+};
+
+/// Where a client is in the feed: the last change it has seen. Changes are ordered by when they
+/// happened, then by recipe id, so recipes changed at the same time aren't skipped between pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub changed_at: DateTime<Utc>,
+    pub id: uuid::Uuid,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.changed_at.timestamp_micros(), self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (micros, id) = s.split_once('.').ok_or(())?;
+        let micros = micros.parse().map_err(|_| ())?;
+        Ok(Self {
+            changed_at: DateTime::from_timestamp_micros(micros).ok_or(())?,
+            id: id.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ChangesQuery {
+    /// The `cursor` of the previous response. Without it, every recipe is listed as created.
+    since: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChangedRecipe {
+    pub id: uuid::Uuid,
+    /// To fetch the recipe with.
+    pub slug: String,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Changes {
+    pub created: Vec<ChangedRecipe>,
+    /// Recipes the client may not have yet are listed here too when they become visible to it
+    /// again, like a hidden recipe that was restored.
+    pub updated: Vec<ChangedRecipe>,
+    /// Deleted recipes, and the ones the user can no longer see.
+    pub deleted: Vec<uuid::Uuid>,
+    /// To pass as `since` next time. Missing if there's nothing to sync yet.
+    pub cursor: Option<String>,
+    /// Whether there are more changes than fit in this response. Ask again with `cursor` right
+    /// away.
+    pub has_more: bool,
+    /// The cursor is older than the deleted recipes are remembered. The client has to drop its
+    /// copy and sync again without `since`.
+    pub full_resync: bool,
+}
+
+/// The changes to the recipes the user can see in the listings, after `since`, at most `limit` of
+/// them.
+pub async fn fetch_changes(
+    conn: &mut PgConnection,
+    org: Org,
+    viewer: Option<uuid::Uuid>,
+    since: Option<Cursor>,
+    limit: usize,
+) -> Result<Changes, ApiError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id AS "id!", slug, changed_at AS "changed_at!", created_at, visible AS "visible!"
+        FROM (
+            SELECT r.id, r.slug, COALESCE(r.updated_at, r.created_at) AS changed_at, r.created_at,
+                NOT r.hidden AND (r.visibility = 'public' OR COALESCE(r.creator_id = $2, FALSE) OR EXISTS (
+                    SELECT 1 FROM users u
+                    WHERE u.user_id = $2 AND (u.is_admin OR u.is_super_admin)
+                )) AS visible
+            FROM recipes r
+            WHERE r.org_id = $1
+            UNION ALL
+            SELECT t.recipe_id, NULL, t.deleted_at, NULL, FALSE
+            FROM recipe_tombstones t
+            WHERE t.org_id = $1
+        ) changes
+        WHERE CASE
+            WHEN $3::timestamptz IS NULL THEN visible
+            ELSE (changed_at, id) > ($3, $4::uuid)
+        END
+        ORDER BY changed_at, id
+        LIMIT $5
+        "#,
+        *org,
+        viewer,
+        since.map(|cursor| cursor.changed_at),
+        since.map(|cursor| cursor.id),
+        limit as i64 + 1
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let has_more = rows.len() > limit;
+    let mut changes = Changes {
+        has_more,
+        cursor: since.map(|cursor| cursor.to_string()),
+        ..Default::default()
+    };
+    for row in rows.into_iter().take(limit) {
+        changes.cursor = Some(
+            Cursor {
+                changed_at: row.changed_at,
+                id: row.id,
+            }
+            .to_string(),
+        );
+        let is_new = match (since, row.created_at) {
+            (Some(since), Some(created_at)) => created_at > since.changed_at,
+            (None, _) => true,
+            // Tombstones.
+            (Some(_), None) => false,
+        };
+        match (row.slug, row.visible) {
+            (Some(slug), true) => {
+                let recipe = ChangedRecipe { id: row.id, slug };
+                if is_new {
+                    changes.created.push(recipe);
+                } else {
+                    changes.updated.push(recipe);
+                }
+            }
+            // The client never had it, like someone else's new draft.
+            _ if is_new => {}
+            _ => changes.deleted.push(row.id),
+        }
+    }
+    Ok(changes)
+}
+
+/// `GET /r/changes?since=<cursor>`, to keep a copy of the recipes in sync.
+#[tracing::instrument(skip(conn, config, maybe_auth_user))]
+pub async fn recipe_changes(
+    State(AppState { config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<Changes>, ApiError> {
+    let since = query
+        .since
+        .map(|since| since.parse::<Cursor>())
+        .transpose()
+        .map_err(|_| ApiError::unprocessable_entity([("since", "isn't a valid cursor")]))?;
+    let (limit, tombstones_days) = {
+        let config = config.borrow();
+        (
+            config.application_settings.max_sync_changes(),
+            config.worker.retention.tombstones_days(),
+        )
+    };
+
+    // Tombstones are only purged when a retention is set, the cursor can't outlive them otherwise.
+    if let Some(since) = since {
+        let remembered_since = Utc::now() - chrono::Duration::days(i64::from(tombstones_days));
+        if tombstones_days > 0 && since.changed_at < remembered_since {
+            return Ok(Json(Changes {
+                full_resync: true,
+                ..Default::default()
+            }));
+        }
+    }
+
+    let viewer = maybe_auth_user.into_inner().as_deref().copied();
+    let changes = fetch_changes(&mut conn, org, viewer, since, limit).await?;
+    Ok(Json(changes))
+}
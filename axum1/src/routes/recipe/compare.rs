@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use axum::extract::{Json, Query, State};
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    routes::ingredient::IngredientPrice,
+    state::AppState,
+};
+
+use super::{
+    cost::{estimate_cost, CostItem, RecipeCost},
+    detail::{fetch_recipes_detailed, Sections},
+    nutrition::NutritionSummary,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CompareQuery {
+    /// Comma separated recipe names.
+    names: String,
+    /// For the cost per serving, see [`super::cost::CostQuery`]. Defaults to 1.
+    servings: Option<u32>,
+}
+
+/// Recipes side by side, in the order they were asked for.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Comparison {
+    pub recipes: Vec<ComparedRecipe>,
+    pub ingredients: IngredientOverlap,
+    /// The names that aren't recipes the user can see.
+    pub not_found: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ComparedRecipe {
+    pub name: String,
+    pub slug: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    pub total_time: i32,
+    /// Missing until the nutrition of a new recipe is first computed.
+    pub nutrition: Option<NutritionSummary>,
+    pub cost: RecipeCost,
+    /// How many users have favorited the recipe. Recipes aren't rated, this is how they compare
+    /// in popularity.
+    pub favorites: i64,
+}
+
+/// Which ingredients the compared recipes have in common.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct IngredientOverlap {
+    /// In every recipe.
+    pub shared: Vec<String>,
+    /// In more than one recipe, but not in all of them, with the recipes they're in.
+    pub partially_shared: BTreeMap<String, Vec<String>>,
+    /// The ingredients only one recipe has, by recipe. Every recipe is listed, even without any.
+    pub unique: BTreeMap<String, Vec<String>>,
+}
+
+/// Sorts the ingredients of the recipes by how many of the recipes have them.
+pub fn ingredient_overlap(recipes: &[(String, Vec<String>)]) -> IngredientOverlap {
+    let mut recipes_by_ingredient: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (recipe, ingredients) in recipes {
+        for ingredient in ingredients {
+            recipes_by_ingredient
+                .entry(ingredient.as_str())
+                .or_default()
+                .insert(recipe.as_str());
+        }
+    }
+
+    let mut overlap = IngredientOverlap {
+        unique: recipes
+            .iter()
+            .map(|(recipe, _)| (recipe.clone(), Vec::new()))
+            .collect(),
+        ..Default::default()
+    };
+    for (ingredient, in_recipes) in recipes_by_ingredient {
+        if in_recipes.len() == recipes.len() {
+            overlap.shared.push(ingredient.to_owned());
+        } else if in_recipes.len() == 1 {
+            let recipe = in_recipes.first().expect("it's in one recipe");
+            overlap
+                .unique
+                .get_mut(*recipe)
+                .expect("every recipe is listed")
+                .push(ingredient.to_owned());
+        } else {
+            overlap.partially_shared.insert(
+                ingredient.to_owned(),
+                in_recipes.into_iter().map(str::to_owned).collect(),
+            );
+        }
+    }
+    overlap
+}
+
+/// The names in `?names=`, without blanks and duplicates, in their original order.
+pub fn parse_names(names: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && seen.insert(*name))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Compares the recipes the user can see. The others are reported in
+/// [`Comparison::not_found`], they don't fail the comparison.
+pub async fn compare(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+    viewer: Option<uuid::Uuid>,
+    servings: u32,
+) -> Result<Comparison, ApiError> {
+    let mut found = fetch_recipes_detailed(conn, org, names, viewer, Sections::ALL).await?;
+    let found_names: Vec<_> = found.values().map(|recipe| recipe.name.clone()).collect();
+
+    let mut cost_items: HashMap<String, Vec<CostItem>> = HashMap::new();
+    let rows = sqlx::query!(
+        r#"
+        SELECT r.name AS recipe, i.name, ir.quantity, ir.quantity_unit, i.price_per_100g,
+            i.price_currency
+        FROM recipes r
+        INNER JOIN ingredients_to_recipes ir ON ir.recipe_id = r.id
+        INNER JOIN ingredients i ON i.id = ir.ingredient_id
+        WHERE r.name = ANY($1) AND r.org_id = $2
+        ORDER BY i.name
+        "#,
+        &found_names,
+        *org
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in rows {
+        cost_items.entry(row.recipe).or_default().push(CostItem {
+            name: row.name,
+            quantity: row.quantity,
+            quantity_unit: row.quantity_unit,
+            price: IngredientPrice::from_columns(row.price_per_100g, row.price_currency),
+        });
+    }
+
+    let favorites: HashMap<String, i64> = sqlx::query!(
+        r#"
+        SELECT r.name, COUNT(fr.recipe_id) AS "count!"
+        FROM recipes r
+        LEFT JOIN favorite_recipe fr ON fr.recipe_id = r.id
+        WHERE r.name = ANY($1) AND r.org_id = $2
+        GROUP BY r.name
+        "#,
+        &found_names,
+        *org
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| (row.name, row.count))
+    .collect();
+
+    let mut recipes = Vec::with_capacity(found.len());
+    let mut ingredients = Vec::with_capacity(found.len());
+    let mut not_found = Vec::new();
+    for name in names {
+        let Some(recipe) = found.remove(name) else {
+            not_found.push(name.clone());
+            continue;
+        };
+        let items = cost_items.remove(&recipe.name).unwrap_or_default();
+        ingredients.push((
+            recipe.name.clone(),
+            recipe
+                .ingredients
+                .unwrap_or_default()
+                .into_iter()
+                .map(|ingredient| ingredient.name)
+                .collect(),
+        ));
+        recipes.push(ComparedRecipe {
+            favorites: favorites.get(&recipe.name).copied().unwrap_or(0),
+            cost: estimate_cost(&items, servings),
+            name: recipe.name,
+            slug: recipe.slug,
+            prep_time: recipe.prep_time,
+            cook_time: recipe.cook_time,
+            total_time: recipe.prep_time + recipe.cook_time,
+            nutrition: recipe.nutrition,
+        });
+    }
+
+    Ok(Comparison {
+        recipes,
+        ingredients: ingredient_overlap(&ingredients),
+        not_found,
+    })
+}
+
+/// `GET /r/compare?names=a,b,c`, up to `application_settings.max_compared_recipes` recipes.
+#[tracing::instrument(skip(conn, config, maybe_auth_user))]
+pub async fn compare_recipes(
+    State(AppState { config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Query(query): Query<CompareQuery>,
+) -> Result<Json<Comparison>, ApiError> {
+    let names = parse_names(&query.names);
+    let max_compared = config.borrow().application_settings.max_compared_recipes();
+    if names.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            "must name at least one recipe",
+        )]));
+    }
+    if names.len() > max_compared {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            format!("at most {max_compared} recipes can be compared at once"),
+        )]));
+    }
+
+    let viewer = maybe_auth_user.into_inner().as_deref().copied();
+    let comparison = compare(&mut conn, org, &names, viewer, query.servings.unwrap_or(1)).await?;
+    Ok(Json(comparison))
+}
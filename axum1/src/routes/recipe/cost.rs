@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Json, Query};
+use sqlx::Acquire;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    routes::ingredient::IngredientPrice,
+};
+
+use super::{helpers::QuantityUnit, slug::RecipeName};
+
+/// An ingredient of a recipe, as needed for the cost estimate.
+#[derive(Debug, Clone)]
+pub struct CostItem {
+    pub name: String,
+    pub quantity: String,
+    pub quantity_unit: String,
+    pub price: Option<IngredientPrice>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CurrencyCost {
+    pub total: f64,
+    pub per_serving: f64,
+}
+
+/// The estimated cost of a recipe.
+///
+/// Amounts in different currencies are never added up, there's a separate total for each
+/// currency the ingredients are priced in.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct RecipeCost {
+    pub servings: u32,
+    pub totals: BTreeMap<String, CurrencyCost>,
+    /// Ingredients without a price, so the totals are lower than the real cost.
+    pub missing_prices: Vec<String>,
+    /// Ingredients with a non-numeric quantity (like "a pinch"), left out of the totals.
+    pub unknown_quantities: Vec<String>,
+}
+
+/// Sums the cost of every ingredient, by currency.
+pub fn estimate_cost(items: &[CostItem], servings: u32) -> RecipeCost {
+    let servings = servings.max(1);
+    let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+    let mut missing_prices = Vec::new();
+    let mut unknown_quantities = Vec::new();
+
+    for item in items {
+        let Some(price) = &item.price else {
+            missing_prices.push(item.name.clone());
+            continue;
+        };
+        let Ok(quantity) = item.quantity.trim().parse::<f64>() else {
+            unknown_quantities.push(item.name.clone());
+            continue;
+        };
+        // Same conversion as the nutrition summary, so the two stay consistent.
+        let multiplier = QuantityUnit::try_from(item.quantity_unit.as_str())
+            .unwrap_or_default()
+            .get_multiplier_for_g() as f64;
+        *totals.entry(price.currency.clone()).or_default() +=
+            price.amount_per_100g as f64 * multiplier * quantity / 100.0;
+    }
+
+    RecipeCost {
+        servings,
+        totals: totals
+            .into_iter()
+            .map(|(currency, total)| {
+                let cost = CurrencyCost {
+                    total,
+                    per_serving: total / servings as f64,
+                };
+                (currency, cost)
+            })
+            .collect(),
+        missing_prices,
+        unknown_quantities,
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CostQuery {
+    /// Recipes don't record how many they serve, so the client tells. Defaults to 1.
+    servings: Option<u32>,
+}
+
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+pub async fn recipe_cost(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    RecipeName(name): RecipeName,
+    Query(query): Query<CostQuery>,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<Json<RecipeCost>, ApiError> {
+    let user_id = maybe_auth_user.into_inner().as_deref().copied();
+    let mut tx = conn.begin().await?;
+
+    let recipe_id = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM recipes
+        WHERE name = $1 AND org_id = $2 AND (NOT hidden OR creator_id = $3)
+          AND (
+            visibility <> 'private'
+            OR creator_id = $3
+            OR EXISTS (
+                SELECT 1 FROM users u
+                WHERE u.user_id = $3 AND (u.is_admin OR u.is_super_admin)
+            )
+          )
+        "#,
+        name,
+        *org,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let items: Vec<_> = sqlx::query!(
+        r#"
+        SELECT i.name, ir.quantity, ir.quantity_unit, i.price_per_100g, i.price_currency
+        FROM ingredients_to_recipes ir
+        INNER JOIN ingredients i ON i.id = ir.ingredient_id
+        WHERE ir.recipe_id = $1
+        ORDER BY i.name
+        "#,
+        recipe_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|r| CostItem {
+        name: r.name,
+        quantity: r.quantity,
+        quantity_unit: r.quantity_unit,
+        price: IngredientPrice::from_columns(r.price_per_100g, r.price_currency),
+    })
+    .collect();
+
+    tx.commit().await?;
+
+    Ok(Json(estimate_cost(&items, query.servings.unwrap_or(1))))
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use axum::http::{header::VARY, HeaderMap, HeaderValue};
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    locale::{Locale, Unit},
+    org::Org,
+    routes::ingredient::{
+        allergen::{allergen_profile, Allergen, AllergenProfile},
+        diet::{dietary_profile, DietaryFlags, DietaryProfile},
+    },
+};
+
+use super::{
+    helpers::{DifficultyLevel, QuantityUnit, TypeByTime},
+    nutrition::NutritionSummary,
+    DetailedIngredient,
+};
+
+/// The optional, more expensive parts of a recipe. The rest is always included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sections {
+    /// The ingredients, along with the total calories, the dietary profile and the allergens
+    /// computed from them.
+    pub ingredients: bool,
+    /// The cached nutrition summary.
+    pub nutrition: bool,
+}
+
+impl Default for Sections {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl Sections {
+    pub const ALL: Self = Self {
+        ingredients: true,
+        nutrition: true,
+    };
+
+    /// Parses a comma separated list of section names, like `ingredients,nutrition`. An empty
+    /// list leaves out every optional section.
+    pub fn parse(include: &str) -> Result<Self, ApiError> {
+        let mut sections = Self {
+            ingredients: false,
+            nutrition: false,
+        };
+        for section in include.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match section {
+                "ingredients" => sections.ingredients = true,
+                "nutrition" => sections.nutrition = true,
+                _ => {
+                    return Err(ApiError::unprocessable_entity([(
+                        "include",
+                        format!(
+                            "unknown section `{section}`, expected `ingredients` or `nutrition`"
+                        ),
+                    )]))
+                }
+            }
+        }
+        Ok(sections)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DetailQuery {
+    /// See [`Sections::parse`]. Everything is included by default.
+    include: Option<String>,
+    /// Also return the numbers formatted for display, see [`FormattedNumbers`]. Off by default.
+    formatted: Option<bool>,
+}
+
+impl DetailQuery {
+    pub fn sections(&self) -> Result<Sections, ApiError> {
+        self.include
+            .as_deref()
+            .map_or(Ok(Sections::ALL), Sections::parse)
+    }
+
+    /// The locale to format the numbers in, if they're asked for formatted: the client's
+    /// preferred supported language, or the configured default.
+    pub fn locale(&self, headers: &HeaderMap, default: Locale) -> Option<Locale> {
+        self.formatted
+            .unwrap_or(false)
+            .then(|| Locale::from_headers(headers).unwrap_or(default))
+    }
+}
+
+/// `Vary: Accept-Language` for formatted responses, which depend on it.
+pub fn vary_on_locale(locale: Option<Locale>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if locale.is_some() {
+        headers.insert(VARY, HeaderValue::from_static("Accept-Language"));
+    }
+    headers
+}
+
+/// Everything about a recipe the frontend shows on its page. The sections that were left out
+/// are missing from the response.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RecipeDetailedWithFav {
+    pub name: String,
+    pub slug: String,
+    pub description: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    pub difficulty: DifficultyLevel,
+    /// In the order they should be followed.
+    pub steps: Vec<String>,
+    pub cuisine: String,
+    pub meal_type: TypeByTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingredients: Option<Vec<DetailedIngredient>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_calories: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dietary: Option<DietaryProfile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allergens: Option<AllergenProfile>,
+    /// Missing until the nutrition of a new recipe is first computed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nutrition: Option<NutritionSummary>,
+    pub favorited: bool,
+    pub is_author: bool,
+    /// Only with `?formatted=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<FormattedNumbers>,
+}
+
+/// The numbers of a recipe formatted for display in a locale, like `1,5 kg` in Hungarian. The
+/// raw numbers are still returned next to them, for clients that format them on their own.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FormattedNumbers {
+    pub locale: String,
+    /// In the order of the ingredients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingredients: Option<Vec<FormattedIngredient>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_calories: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nutrition: Option<FormattedNutrition>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FormattedIngredient {
+    pub name: String,
+    /// Quantities that aren't numbers are returned as they are.
+    pub quantity: String,
+    pub calories_per_100g: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FormattedNutrition {
+    pub calories: String,
+    pub protein: String,
+    pub fat: String,
+    pub carbohydrate: String,
+    pub sugar: String,
+    pub fiber: String,
+}
+
+impl RecipeDetailedWithFav {
+    /// Fills in [`Self::formatted`] for the sections that were included.
+    pub fn format_numbers(&mut self, locale: Locale) {
+        let calories = |value: f32| locale.format_amount(value.into(), 0, Some(Unit::Kilocalorie));
+        let grams = |value: f32| locale.format_amount(value.into(), 1, Some(Unit::Gram));
+
+        self.formatted = Some(FormattedNumbers {
+            locale: locale.as_str().to_owned(),
+            ingredients: self.ingredients.as_ref().map(|ingredients| {
+                ingredients
+                    .iter()
+                    .map(|ingredient| FormattedIngredient {
+                        name: ingredient.name.clone(),
+                        quantity: format_quantity(
+                            locale,
+                            &ingredient.quantity,
+                            &ingredient.quantity_unit,
+                        ),
+                        calories_per_100g: calories(ingredient.calories_per_100g),
+                    })
+                    .collect()
+            }),
+            full_calories: self.full_calories.map(calories),
+            nutrition: self.nutrition.map(|n| FormattedNutrition {
+                calories: calories(n.calories),
+                protein: grams(n.protein),
+                fat: grams(n.fat),
+                carbohydrate: grams(n.carbohydrate),
+                sugar: grams(n.sugar),
+                fiber: grams(n.fiber),
+            }),
+        });
+    }
+}
+
+fn format_quantity(locale: Locale, quantity: &str, unit: &str) -> String {
+    match quantity.trim().replace(',', ".").parse::<f64>() {
+        Ok(value) => locale.format_amount(value, 2, Unit::parse(unit)),
+        Err(_) => format!("{quantity} {unit}").trim().to_owned(),
+    }
+}
+
+struct RecipeRow {
+    requested_name: String,
+    id: uuid::Uuid,
+    name: String,
+    slug: String,
+    description: String,
+    prep_time: i32,
+    cook_time: i32,
+    difficulty: DifficultyLevel,
+    steps: Vec<String>,
+    cuisine: String,
+    meal_type: TypeByTime,
+    calories: Option<f32>,
+    protein: Option<f32>,
+    fat: Option<f32>,
+    carbohydrate: Option<f32>,
+    sugar: Option<f32>,
+    fiber: Option<f32>,
+    favorited: bool,
+    is_author: bool,
+}
+
+struct IngredientRow {
+    recipe_id: uuid::Uuid,
+    name: String,
+    quantity: String,
+    quantity_unit: String,
+    calories_per_100g: f32,
+    vegan: Option<bool>,
+    vegetarian: Option<bool>,
+    gluten_free: Option<bool>,
+    contains_nuts: Option<bool>,
+    allergens: Option<Vec<Allergen>>,
+}
+
+/// Fetches a single recipe, see [`fetch_recipes_detailed`].
+pub async fn fetch_recipe_detailed(
+    conn: &mut PgConnection,
+    org: Org,
+    name: &str,
+    maybe_user_id: Option<uuid::Uuid>,
+    sections: Sections,
+) -> Result<Option<RecipeDetailedWithFav>, ApiError> {
+    let mut recipes =
+        fetch_recipes_detailed(conn, org, &[name.to_owned()], maybe_user_id, sections).await?;
+    Ok(recipes.remove(name))
+}
+
+/// Fetches the recipes with the requested sections and the favorite/author flags for the given
+/// user, keyed by the names they were requested with. Recipes that don't exist in the
+/// organization, or are hidden for moderation and not authored by the user, are left out. So are
+/// private recipes, unless the user is their author or an admin. Unlisted ones are included,
+/// asking for one by name is how they're meant to be found.
+///
+/// It takes two queries however many recipes are requested, and only one without ingredients.
+pub async fn fetch_recipes_detailed(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+    maybe_user_id: Option<uuid::Uuid>,
+    sections: Sections,
+) -> Result<HashMap<String, RecipeDetailedWithFav>, ApiError> {
+    let rows = sqlx::query_as!(
+        RecipeRow,
+        r#"
+        SELECT requested.name AS "requested_name!", r.id, r.name, r.slug, r.description,
+            r.prep_time, r.cook_time, r.difficulty AS "difficulty: DifficultyLevel", r.steps,
+            c.name AS cuisine, r.meal_type AS "meal_type: TypeByTime",
+            n.calories AS "calories?", n.protein AS "protein?", n.fat AS "fat?",
+            n.carbohydrate AS "carbohydrate?", n.sugar AS "sugar?", n.fiber AS "fiber?",
+            EXISTS (
+                SELECT 1 FROM favorite_recipe fr WHERE fr.recipe_id = r.id AND fr.user_id = $3
+            ) AS "favorited!",
+            COALESCE(r.creator_id = $3, FALSE) AS "is_author!"
+        FROM UNNEST($1::TEXT[]) AS requested(name)
+        INNER JOIN recipes r ON r.name = requested.name AND r.org_id = $2
+        INNER JOIN cuisines c ON c.id = r.cuisine_id
+        LEFT JOIN recipe_nutrition n ON n.recipe_id = r.id AND $4
+        WHERE (NOT r.hidden OR r.creator_id = $3)
+          AND (
+            r.visibility <> 'private'
+            OR r.creator_id = $3
+            OR EXISTS (
+                SELECT 1 FROM users u
+                WHERE u.user_id = $3 AND (u.is_admin OR u.is_super_admin)
+            )
+          )
+        "#,
+        names,
+        *org,
+        maybe_user_id,
+        sections.nutrition
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .context("Failed to query recipes")?;
+
+    let mut ingredients: HashMap<uuid::Uuid, Vec<IngredientRow>> = HashMap::new();
+    if sections.ingredients && !rows.is_empty() {
+        let recipe_ids: Vec<_> = rows.iter().map(|row| row.id).collect();
+        let ingredient_rows = sqlx::query_as!(
+            IngredientRow,
+            r#"
+            SELECT ir.recipe_id, i.name, ir.quantity, ir.quantity_unit, i.calories_per_100g,
+                i.vegan, i.vegetarian, i.gluten_free, i.contains_nuts,
+                i.allergens AS "allergens: Vec<Allergen>"
+            FROM ingredients_to_recipes ir
+            INNER JOIN ingredients i ON i.id = ir.ingredient_id
+            WHERE ir.recipe_id = ANY($1)
+            ORDER BY i.name
+            "#,
+            &recipe_ids
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .context("Failed to query recipe ingredients")?;
+        for row in ingredient_rows {
+            ingredients.entry(row.recipe_id).or_default().push(row);
+        }
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let recipe_ingredients = sections.ingredients.then(|| {
+                ingredients
+                    .get(&row.id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+            });
+            let nutrition = match (
+                row.calories,
+                row.protein,
+                row.fat,
+                row.carbohydrate,
+                row.sugar,
+                row.fiber,
+            ) {
+                (
+                    Some(calories),
+                    Some(protein),
+                    Some(fat),
+                    Some(carbohydrate),
+                    Some(sugar),
+                    Some(fiber),
+                ) => Some(NutritionSummary {
+                    calories,
+                    protein,
+                    fat,
+                    carbohydrate,
+                    sugar,
+                    fiber,
+                }),
+                _ => None,
+            };
+            let recipe = RecipeDetailedWithFav {
+                name: row.name,
+                slug: row.slug,
+                description: row.description,
+                prep_time: row.prep_time,
+                cook_time: row.cook_time,
+                difficulty: row.difficulty,
+                steps: row.steps,
+                cuisine: row.cuisine,
+                meal_type: row.meal_type,
+                ingredients: recipe_ingredients.map(|rows| {
+                    rows.iter()
+                        .map(|i| DetailedIngredient {
+                            name: i.name.clone(),
+                            quantity: i.quantity.clone(),
+                            quantity_unit: i.quantity_unit.clone(),
+                            calories_per_100g: i.calories_per_100g,
+                        })
+                        .collect()
+                }),
+                full_calories: recipe_ingredients.map(full_calories),
+                dietary: recipe_ingredients.map(|rows| {
+                    let flags: Vec<_> = rows
+                        .iter()
+                        .map(|i| DietaryFlags {
+                            vegan: i.vegan,
+                            vegetarian: i.vegetarian,
+                            gluten_free: i.gluten_free,
+                            contains_nuts: i.contains_nuts,
+                        })
+                        .collect();
+                    dietary_profile(&flags)
+                }),
+                allergens: recipe_ingredients.map(|rows| {
+                    let allergens: Vec<_> = rows.iter().map(|i| i.allergens.clone()).collect();
+                    allergen_profile(&allergens)
+                }),
+                nutrition,
+                favorited: row.favorited,
+                is_author: row.is_author,
+                formatted: None,
+            };
+            (row.requested_name, recipe)
+        })
+        .collect())
+}
+
+fn full_calories(ingredients: &[IngredientRow]) -> f32 {
+    ingredients.iter().fold(0.0, |acc, ingredient| {
+        let multiplier = QuantityUnit::try_from(ingredient.quantity_unit.as_str())
+            .unwrap_or_default()
+            .get_multiplier_for_g();
+        acc + (ingredient.calories_per_100g * multiplier * ingredient.quantity.parse::<f32>().unwrap_or(0.0) // We ignore non-numeric quantities
+            / 100.0)
+    })
+}
@@ -1,15 +1,52 @@
 use axum::{
     async_trait,
-    extract::{FromRef, FromRequestParts, Path},
+    extract::{FromRef, FromRequestParts},
     http::request::Parts,
 };
 use tower_sessions::Session;
 
-use crate::{error::ApiError, extractors::DatabaseConnection, state::AppState};
+use crate::{
+    error::ApiError,
+    extractors::{
+        AuthUser, DatabaseConnection, DbConnection, MaybeAuthUser, ReadDatabaseConnection,
+    },
+    org::Org,
+    state::AppState,
+};
+
+use super::slug::RecipeName;
 
 #[derive(Debug)]
 pub struct RecipeCreator(uuid::Uuid);
 
+/// The connection a recipe is read on: the primary for users, who may be reading what they've
+/// just written, the replica for anyone else. Only one of them is acquired.
+pub enum RecipeReadConnection {
+    User(AuthUser, DbConnection),
+    Anonymous(DbConnection),
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RecipeReadConnection
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let maybe_auth_user = MaybeAuthUser::from_request_parts(parts, state).await?;
+        if let Some(user) = maybe_auth_user.into_inner() {
+            let DatabaseConnection(conn) =
+                DatabaseConnection::from_request_parts(parts, state).await?;
+            return Ok(Self::User(user, conn));
+        }
+        let ReadDatabaseConnection(conn) =
+            ReadDatabaseConnection::from_request_parts(parts, state).await?;
+        Ok(Self::Anonymous(conn))
+    }
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for RecipeCreator
 where
@@ -23,25 +60,25 @@ where
             .await
             .expect("`SessionLayer` should be added");
 
-        let Path(recipe_name) = Path::<String>::from_request_parts(parts, state)
-            .await
-            .expect("Recipe name is missing from the URL");
+        let RecipeName(recipe_name) = RecipeName::from_request_parts(parts, state).await?;
 
         let user_id = session
             .get::<uuid::Uuid>("user_id")
             .await?
             .ok_or(ApiError::Forbidden)?;
 
-        let DatabaseConnection(mut conn) = DatabaseConnection::from_request_parts(parts, state)
-            .await
-            .expect("Database extension is missing");
+        let org = Org::from_request_parts(parts, state).await?;
+
+        // Not a `DatabaseConnection`, the handler holds that one in a sandboxed request.
+        let AppState { db_pool, .. } = AppState::from_ref(state);
 
         sqlx::query!(
-            "SELECT 1 AS _e FROM recipes WHERE creator_id = $1 AND name = $2",
+            "SELECT 1 AS _e FROM recipes WHERE creator_id = $1 AND name = $2 AND org_id = $3",
             user_id,
-            recipe_name
+            recipe_name,
+            *org
         )
-        .fetch_optional(&mut *conn)
+        .fetch_optional(&db_pool)
         .await?
         .ok_or(ApiError::Forbidden)?;
 
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, HashSet};
+
+use axum::{extract::State, Json};
+use sqlx::{Acquire, PgConnection};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    state::AppState,
+};
+
+use super::slug::RecipeName;
+
+/// Whoever marks a recipe as favorite: a registered user or a guest session.
+#[derive(Debug, Clone, Copy)]
+pub enum Favoriter {
+    User(uuid::Uuid),
+    Guest(uuid::Uuid),
+}
+
+impl TryFrom<MaybeAuthUser> for Favoriter {
+    type Error = ApiError;
+
+    fn try_from(maybe_auth_user: MaybeAuthUser) -> Result<Self, Self::Error> {
+        let guest_id = maybe_auth_user.guest_id();
+        match (maybe_auth_user.into_inner(), guest_id) {
+            (Some(auth_user), _) => Ok(Self::User(*auth_user)),
+            (None, Some(guest_id)) => Ok(Self::Guest(*guest_id)),
+            (None, None) => Err(ApiError::Unauthorized),
+        }
+    }
+}
+
+impl Favoriter {
+    /// The user to check the visibility of recipes for. Guests only see what anonymous visitors do.
+    fn viewer(self) -> Option<uuid::Uuid> {
+        match self {
+            Self::User(user_id) => Some(user_id),
+            Self::Guest(_) => None,
+        }
+    }
+}
+
+/// The id of the recipe, if the viewer may open it: the same rules as the detail page, so hidden
+/// and private recipes of others can't be favorited or reported by name.
+pub(super) async fn visible_recipe_id(
+    conn: &mut PgConnection,
+    org: Org,
+    viewer: Option<uuid::Uuid>,
+    name: &str,
+) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM recipes
+        WHERE name = $1 AND org_id = $2 AND (NOT hidden OR creator_id = $3)
+          AND (
+            visibility <> 'private'
+            OR creator_id = $3
+            OR EXISTS (
+                SELECT 1 FROM users u
+                WHERE u.user_id = $3 AND (u.is_admin OR u.is_super_admin)
+            )
+          )
+        "#,
+        name,
+        *org,
+        viewer
+    )
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+/// The state after a favorite was set or cleared, so clients don't need another request
+/// to render it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FavoriteState {
+    pub favorited: bool,
+    /// The number of registered users having this as a favorite. Guests are only counted
+    /// once they register.
+    pub favorite_count: i64,
+}
+
+/// Marks (or unmarks) the recipe as a favorite. Repeating the call is a no-op, the primary keys
+/// make sure concurrent calls can't store it twice.
+pub async fn set_favorite_recipe(
+    conn: &mut PgConnection,
+    org: Org,
+    favoriter: Favoriter,
+    name: &str,
+    favorited: bool,
+) -> Result<FavoriteState, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let recipe_id = visible_recipe_id(&mut tx, org, favoriter.viewer(), name)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    match (favoriter, favorited) {
+        (Favoriter::User(user_id), true) => {
+            sqlx::query!(
+                "INSERT INTO favorite_recipe (recipe_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                recipe_id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        (Favoriter::User(user_id), false) => {
+            sqlx::query!(
+                "DELETE FROM favorite_recipe WHERE recipe_id = $1 AND user_id = $2",
+                recipe_id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        (Favoriter::Guest(guest_id), true) => {
+            sqlx::query!(
+                "INSERT INTO guest_favorite_recipes (guest_id, recipe_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                guest_id,
+                recipe_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        (Favoriter::Guest(guest_id), false) => {
+            sqlx::query!(
+                "DELETE FROM guest_favorite_recipes WHERE guest_id = $1 AND recipe_id = $2",
+                guest_id,
+                recipe_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    let favorite_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM favorite_recipe WHERE recipe_id = $1"#,
+        recipe_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(FavoriteState {
+        favorited,
+        favorite_count,
+    })
+}
+
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+pub async fn favorite_recipe(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    RecipeName(name): RecipeName,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<Json<FavoriteState>, ApiError> {
+    let favoriter = Favoriter::try_from(maybe_auth_user)?;
+    let state = set_favorite_recipe(&mut conn, org, favoriter, &name, true).await?;
+    Ok(Json(state))
+}
+
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+pub async fn unfavorite_recipe(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    RecipeName(name): RecipeName,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<Json<FavoriteState>, ApiError> {
+    let favoriter = Favoriter::try_from(maybe_auth_user)?;
+    let state = set_favorite_recipe(&mut conn, org, favoriter, &name, false).await?;
+    Ok(Json(state))
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BulkFavorites {
+    names: Vec<String>,
+}
+
+/// What happened to a single recipe of a bulk favorite request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkFavoriteOutcome {
+    Added,
+    AlreadyPresent,
+    Removed,
+    NotPresent,
+    NotFound,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BulkFavoriteResponse {
+    pub results: BTreeMap<String, BulkFavoriteOutcome>,
+}
+
+/// Marks (or unmarks) many recipes as favorites in a single transaction. Like
+/// `set_favorite_recipe`, it's idempotent: recipes that are already in the requested state are
+/// reported, not rejected.
+pub async fn set_favorite_recipes(
+    conn: &mut PgConnection,
+    org: Org,
+    favoriter: Favoriter,
+    names: &[String],
+    favorited: bool,
+) -> Result<BTreeMap<String, BulkFavoriteOutcome>, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    // Recipe names compare case-insensitively, so results are keyed by the name as requested,
+    // not as stored, for clients to find every name they sent.
+    let recipes = sqlx::query!(
+        r#"
+        SELECT requested.name AS "requested!", r.id
+        FROM UNNEST($1::TEXT[]) AS requested(name)
+        INNER JOIN recipes r ON r.name = requested.name AND r.org_id = $2
+        WHERE (NOT r.hidden OR r.creator_id = $3)
+          AND (
+            r.visibility <> 'private'
+            OR r.creator_id = $3
+            OR EXISTS (
+                SELECT 1 FROM users u
+                WHERE u.user_id = $3 AND (u.is_admin OR u.is_super_admin)
+            )
+          )
+        "#,
+        names,
+        *org,
+        favoriter.viewer()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut results: BTreeMap<String, BulkFavoriteOutcome> = names
+        .iter()
+        .map(|name| (name.clone(), BulkFavoriteOutcome::NotFound))
+        .collect();
+
+    for recipe in recipes {
+        let result = match (favoriter, favorited) {
+            (Favoriter::User(user_id), true) => sqlx::query!(
+                "INSERT INTO favorite_recipe (recipe_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                recipe.id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?,
+            (Favoriter::User(user_id), false) => sqlx::query!(
+                "DELETE FROM favorite_recipe WHERE recipe_id = $1 AND user_id = $2",
+                recipe.id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?,
+            (Favoriter::Guest(guest_id), true) => sqlx::query!(
+                "INSERT INTO guest_favorite_recipes (guest_id, recipe_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                guest_id,
+                recipe.id
+            )
+            .execute(&mut *tx)
+            .await?,
+            (Favoriter::Guest(guest_id), false) => sqlx::query!(
+                "DELETE FROM guest_favorite_recipes WHERE guest_id = $1 AND recipe_id = $2",
+                guest_id,
+                recipe.id
+            )
+            .execute(&mut *tx)
+            .await?,
+        };
+
+        let outcome = match (favorited, result.rows_affected() > 0) {
+            (true, true) => BulkFavoriteOutcome::Added,
+            (true, false) => BulkFavoriteOutcome::AlreadyPresent,
+            (false, true) => BulkFavoriteOutcome::Removed,
+            (false, false) => BulkFavoriteOutcome::NotPresent,
+        };
+        results.insert(recipe.requested, outcome);
+    }
+
+    tx.commit().await?;
+
+    Ok(results)
+}
+
+/// Deduplicates the names of a bulk request case-insensitively, like recipe names compare,
+/// keeping the first spelling, and enforces the configured batch size.
+fn bulk_names(state: &AppState, mut names: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let max_batch_size = state
+        .config
+        .borrow()
+        .application_settings
+        .max_recipe_batch_size();
+
+    let mut seen = HashSet::new();
+    names.retain(|name| seen.insert(name.to_lowercase()));
+    if names.len() > max_batch_size {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            format!("at most {max_batch_size} recipes can be changed at once"),
+        )]));
+    }
+    Ok(names)
+}
+
+#[tracing::instrument(skip(state, conn, maybe_auth_user))]
+pub async fn favorite_recipes(
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Json(bulk): Json<BulkFavorites>,
+) -> Result<Json<BulkFavoriteResponse>, ApiError> {
+    let names = bulk_names(&state, bulk.names)?;
+    let favoriter = Favoriter::try_from(maybe_auth_user)?;
+    let results = set_favorite_recipes(&mut conn, org, favoriter, &names, true).await?;
+    Ok(Json(BulkFavoriteResponse { results }))
+}
+
+#[tracing::instrument(skip(state, conn, maybe_auth_user))]
+pub async fn unfavorite_recipes(
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Json(bulk): Json<BulkFavorites>,
+) -> Result<Json<BulkFavoriteResponse>, ApiError> {
+    let names = bulk_names(&state, bulk.names)?;
+    let favoriter = Favoriter::try_from(maybe_auth_user)?;
+    let results = set_favorite_recipes(&mut conn, org, favoriter, &names, false).await?;
+    Ok(Json(BulkFavoriteResponse { results }))
+}
@@ -0,0 +1,610 @@
+//! Importing recipes from other sites, through the `schema.org/Recipe` JSON-LD most recipe pages
+//! embed for search engines.
+//!
+//! The page is fetched on behalf of the user, so the fetch is restricted to public addresses: every
+//! address a host resolves to is checked, the connection is pinned to the checked addresses so a
+//! second lookup can't point elsewhere, and redirects are followed by hand to check them the same
+//! way.
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{Json, State},
+    http::header::{ACCEPT, LOCATION},
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use sqlx::PgConnection;
+
+use crate::{
+    config::RecipeImportSettings,
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection},
+    org::Org,
+    routes::ingredient::resolve::exact_matches,
+    state::AppState,
+};
+
+use super::helpers::{DifficultyLevel, TypeByTime};
+
+/// Addresses that aren't reachable from the internet, or belong to the server's own network.
+static NON_PUBLIC_NETWORKS: Lazy<Vec<ipnet::IpNet>> = Lazy::new(|| {
+    [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.0.2.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "198.51.100.0/24",
+        "203.0.113.0/24",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        // The unspecified and loopback addresses, and the deprecated IPv4-compatible ones.
+        "::/96",
+        "64:ff9b::/96",
+        "100::/64",
+        "2001:db8::/32",
+        // 6to4, with an IPv4 address embedded.
+        "2002::/16",
+        "fc00::/7",
+        "fe80::/10",
+        "ff00::/8",
+    ]
+    .into_iter()
+    .map(|network| network.parse().unwrap())
+    .collect()
+});
+
+static JSON_LD_SCRIPT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<script[^>]*type\s*=\s*["']?application/ld\+json["']?[^>]*>(.*?)</script>"#)
+        .unwrap()
+});
+
+static HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+
+static ISO_DURATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^P(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+(?:\.\d+)?)S)?)?$").unwrap()
+});
+
+static LEADING_QUANTITY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d+\s+\d+/\d+|\d+/\d+|\d+(?:[.,]\d+)?|[½⅓⅔¼¾⅛])\s*(.*)$").unwrap());
+
+/// Units the ingredient lines are commonly written with, but that can't be converted to grams.
+/// They're kept in the quantity, for the user to convert while reviewing.
+const OTHER_UNITS: &[&str] = &[
+    "cup",
+    "cups",
+    "tbsp",
+    "tablespoon",
+    "tablespoons",
+    "tsp",
+    "teaspoon",
+    "teaspoons",
+    "oz",
+    "ounce",
+    "ounces",
+    "lb",
+    "lbs",
+    "pound",
+    "pounds",
+    "ml",
+    "l",
+    "liter",
+    "liters",
+    "litre",
+    "litres",
+    "pinch",
+    "clove",
+    "cloves",
+    "can",
+    "cans",
+    "slice",
+    "slices",
+];
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ImportUrl {
+    url: String,
+}
+
+/// A recipe as it would be saved with `POST /r`, guessed from another site's page. Nothing is
+/// saved, the user reviews and fixes it first.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecipeDraft {
+    pub name: String,
+    pub description: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    /// `schema.org` has no difficulty, so it's always `medium`.
+    pub difficulty: DifficultyLevel,
+    pub steps: Vec<String>,
+    /// `Unspecified` unless the page names a known cuisine.
+    pub cuisine: String,
+    pub meal_type: TypeByTime,
+    pub ingredients: Vec<DraftIngredient>,
+    /// The page the recipe was imported from, after redirects.
+    pub source_url: String,
+    /// The recipe's image on the other site. It has to be uploaded to be used.
+    pub image_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DraftIngredient {
+    pub name: String,
+    /// Non-metric quantities keep their unit, like `1 cup`.
+    pub quantity: String,
+    /// `g`, `mg`, `kg` or empty.
+    pub quantity_unit: String,
+    pub calories_per_100g: f32,
+    /// The line of the page this was parsed from.
+    pub original: String,
+    /// Not an ingredient of the organization yet, it has to be created before saving the recipe.
+    pub needs_creation: bool,
+}
+
+/// Fetches a recipe page and returns what could be read from it as a draft.
+#[tracing::instrument(skip(config, conn, _auth_user))]
+pub async fn import_recipe_from_url(
+    State(AppState { config, .. }): State<AppState>,
+    _auth_user: AuthUser,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    Json(ImportUrl { url }): Json<ImportUrl>,
+) -> Result<Json<RecipeDraft>, ApiError> {
+    let settings = config.borrow().recipe_import.clone();
+    let (source_url, page) = fetch_page(&settings, &url).await?;
+    let recipe = find_recipe_json_ld(&page).ok_or_else(|| {
+        ApiError::unprocessable_entity([("url", "has no schema.org recipe on the page")])
+    })?;
+
+    let mut draft = draft_from_json_ld(&recipe, source_url.as_str());
+    match_draft(&mut conn, org, &mut draft).await?;
+    Ok(Json(draft))
+}
+
+/// Swaps the ingredient names for the organization's spelling of them, flagging the rest for
+/// creation, and falls back to `Unspecified` for unknown cuisines.
+///
+/// Names are matched like `POST /i/resolve` matches them exactly, see [`exact_matches`].
+pub async fn match_draft(
+    conn: &mut PgConnection,
+    org: Org,
+    draft: &mut RecipeDraft,
+) -> Result<(), ApiError> {
+    let names: Vec<String> = draft.ingredients.iter().map(|i| i.name.clone()).collect();
+    let known = exact_matches(&mut *conn, org, &names).await?;
+
+    for ingredient in &mut draft.ingredients {
+        match known.get(&ingredient.name) {
+            Some(known) => {
+                ingredient.name.clone_from(&known.name);
+                ingredient.calories_per_100g = known.calories_per_100g;
+                ingredient.needs_creation = false;
+            }
+            None => ingredient.needs_creation = true,
+        }
+    }
+
+    draft.cuisine = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(
+            (SELECT name FROM cuisines WHERE name = $1),
+            'Unspecified'
+        ) AS "name!"
+        "#,
+        draft.cuisine
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+fn unfetchable(reason: impl std::fmt::Display) -> ApiError {
+    ApiError::unprocessable_entity([("url", format!("couldn't be fetched: {reason}"))])
+}
+
+/// Fetches the page, returning where it ended up after redirects and its text.
+async fn fetch_page(settings: &RecipeImportSettings, url: &str) -> Result<(Url, String), ApiError> {
+    let deadline = tokio::time::Instant::now() + settings.timeout();
+    let mut url = parse_url(url)?;
+
+    for _ in 0..=settings.max_redirects() {
+        let addresses = resolve_public(&url).await?;
+        // A proxy from the environment would resolve the host itself, past the checked addresses.
+        let mut client = reqwest::Client::builder()
+            .no_proxy()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("axum1-recipe-import/", env!("CARGO_PKG_VERSION")));
+        if let Some(domain) = url.domain() {
+            client = client.resolve_to_addrs(domain, &addresses);
+        }
+        let client = client.build()?;
+
+        let mut response = tokio::time::timeout_at(
+            deadline,
+            client.get(url.clone()).header(ACCEPT, "text/html").send(),
+        )
+        .await
+        .map_err(|_| unfetchable("timed out"))?
+        .map_err(|e| {
+            tracing::debug!(error.message = %e, "Failed to fetch a recipe page");
+            unfetchable("the site didn't answer")
+        })?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| unfetchable("redirected nowhere"))?;
+            let next = url
+                .join(location)
+                .map_err(|_| unfetchable("redirected to an invalid URL"))?;
+            url = parse_url(next.as_str())?;
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(unfetchable(format!(
+                "the site answered with {}",
+                response.status()
+            )));
+        }
+
+        let max_bytes = settings.max_page_bytes();
+        let too_large = || unfetchable(format!("the page is larger than {max_bytes} bytes"));
+        if response
+            .content_length()
+            .is_some_and(|length| length > max_bytes as u64)
+        {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = tokio::time::timeout_at(deadline, response.chunk())
+            .await
+            .map_err(|_| unfetchable("timed out"))?
+            .map_err(|_| unfetchable("the download was interrupted"))?
+        {
+            if body.len() + chunk.len() > max_bytes {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        return Ok((url, String::from_utf8_lossy(&body).into_owned()));
+    }
+
+    Err(unfetchable("too many redirects"))
+}
+
+/// Only `http` and `https` URLs without credentials are fetched.
+fn parse_url(url: &str) -> Result<Url, ApiError> {
+    let invalid = || ApiError::unprocessable_entity([("url", "is not a valid http(s) URL")]);
+    let url = Url::parse(url.trim()).map_err(|_| invalid())?;
+    if !matches!(url.scheme(), "http" | "https")
+        || url.host().is_none()
+        || !url.username().is_empty()
+        || url.password().is_some()
+    {
+        return Err(invalid());
+    }
+    Ok(url)
+}
+
+/// The addresses of the URL's host, if every one of them is public.
+async fn resolve_public(url: &Url) -> Result<Vec<SocketAddr>, ApiError> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host = url.host_str().unwrap_or_default();
+    // IPv6 hosts are bracketed.
+    let addresses: Vec<SocketAddr> = match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| unfetchable("the host doesn't exist"))?
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return Err(unfetchable("the host doesn't exist"));
+    }
+    if !addresses.iter().all(|address| is_public_ip(address.ip())) {
+        return Err(ApiError::unprocessable_entity([(
+            "url",
+            "points to a private network address",
+        )]));
+    }
+    Ok(addresses)
+}
+
+/// Whether the address is reachable from the internet. IPv4 addresses mapped to IPv6 are judged
+/// as IPv4.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    };
+    !NON_PUBLIC_NETWORKS
+        .iter()
+        .any(|network| network.contains(&ip))
+}
+
+/// The first `schema.org/Recipe` in the JSON-LD scripts of the page, looking into arrays and
+/// `@graph`s.
+pub fn find_recipe_json_ld(html: &str) -> Option<Value> {
+    JSON_LD_SCRIPT
+        .captures_iter(html)
+        .filter_map(|script| serde_json::from_str::<Value>(script[1].trim()).ok())
+        .find_map(|value| find_recipe_node(&value).cloned())
+}
+
+fn find_recipe_node(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_recipe_node),
+        Value::Object(object) => {
+            let is_recipe = strings(object.get("@type")).iter().any(|t| {
+                let t = t.rsplit(['/', ':']).next().unwrap_or_default();
+                t.eq_ignore_ascii_case("Recipe")
+            });
+            if is_recipe {
+                Some(value)
+            } else {
+                object.get("@graph").and_then(find_recipe_node)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Maps a `schema.org/Recipe` to a draft. The ingredients are all flagged for creation until
+/// they're matched with [`match_draft`].
+pub fn draft_from_json_ld(recipe: &Value, source_url: &str) -> RecipeDraft {
+    let text = |key: &str| {
+        strings(recipe.get(key))
+            .into_iter()
+            .map(|s| plain_text(&s))
+            .find(|s| !s.is_empty())
+    };
+
+    let name = recipe_name(&text("name").unwrap_or_default());
+    let description = text("description")
+        .map(|d| truncate(&d, 250))
+        .filter(|d| d.chars().count() >= 2)
+        .unwrap_or_else(|| name.clone());
+
+    let minutes = |key: &str| {
+        recipe
+            .get(key)
+            .and_then(Value::as_str)
+            .and_then(parse_duration_minutes)
+    };
+    let prep_time = minutes("prepTime").unwrap_or(0);
+    let cook_time = minutes("cookTime")
+        .or_else(|| minutes("totalTime").map(|total| (total - prep_time).max(0)))
+        .unwrap_or(0);
+
+    let meal_type = strings(recipe.get("recipeCategory"))
+        .iter()
+        .find_map(|category| {
+            let category = category.to_lowercase();
+            if category.contains("breakfast") {
+                Some(TypeByTime::Breakfast)
+            } else if category.contains("lunch") {
+                Some(TypeByTime::Lunch)
+            } else if category.contains("dinner") {
+                Some(TypeByTime::Dinner)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(TypeByTime::Other);
+
+    let mut steps = Vec::new();
+    collect_steps(recipe.get("recipeInstructions"), &mut steps);
+
+    let ingredients = strings(
+        recipe
+            .get("recipeIngredient")
+            .or_else(|| recipe.get("ingredients")),
+    )
+    .iter()
+    .map(|line| plain_text(line))
+    .filter(|line| !line.is_empty())
+    .map(|line| parse_ingredient_line(&line))
+    .collect();
+
+    RecipeDraft {
+        name,
+        description,
+        prep_time,
+        cook_time,
+        difficulty: DifficultyLevel::Medium,
+        steps,
+        cuisine: text("recipeCuisine").unwrap_or_else(|| "Unspecified".into()),
+        meal_type,
+        ingredients,
+        source_url: source_url.to_owned(),
+        image_url: image_url(recipe.get("image")),
+    }
+}
+
+/// The steps of `recipeInstructions`, which may be text, a list of texts, `HowToStep`s, or
+/// `HowToSection`s of those.
+fn collect_steps(value: Option<&Value>, steps: &mut Vec<String>) {
+    match value {
+        Some(Value::String(text)) => steps.extend(
+            plain_text(&text.replace("<br>", "\n"))
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        ),
+        Some(Value::Array(items)) => items
+            .iter()
+            .for_each(|item| collect_steps(Some(item), steps)),
+        Some(Value::Object(object)) => {
+            if let Some(items) = object.get("itemListElement") {
+                collect_steps(Some(items), steps);
+            } else {
+                collect_steps(object.get("text").or_else(|| object.get("name")), steps);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Splits a line like `200 g flour, sifted` into its quantity, unit and ingredient name.
+pub fn parse_ingredient_line(line: &str) -> DraftIngredient {
+    let mut quantity = String::new();
+    let mut quantity_unit = String::new();
+    let mut rest = line.trim();
+
+    if let Some(captures) = LEADING_QUANTITY.captures(rest) {
+        quantity = parse_quantity(&captures[1]);
+        rest = captures.get(2).map_or("", |m| m.as_str());
+
+        let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let unit = word.trim_end_matches('.').to_lowercase();
+        match unit.as_str() {
+            "g" | "gram" | "grams" => quantity_unit = "g".into(),
+            "kg" | "kilogram" | "kilograms" => quantity_unit = "kg".into(),
+            "mg" | "milligram" | "milligrams" => quantity_unit = "mg".into(),
+            other if OTHER_UNITS.contains(&other) => quantity = format!("{quantity} {other}"),
+            _ => {}
+        }
+        if !quantity_unit.is_empty() || OTHER_UNITS.contains(&unit.as_str()) {
+            rest = after.trim_start();
+        }
+    }
+
+    let rest = rest.strip_prefix("of ").unwrap_or(rest);
+    let name = rest
+        .split([',', '('])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    DraftIngredient {
+        name,
+        quantity,
+        quantity_unit,
+        calories_per_100g: 0.0,
+        original: line.trim().to_owned(),
+        needs_creation: true,
+    }
+}
+
+/// Turns `1 1/2`, `3/4`, `1,5` or `½` into a decimal number.
+fn parse_quantity(quantity: &str) -> String {
+    let fraction = |f: &str| {
+        let (numerator, denominator) = f.split_once('/')?;
+        let denominator: f32 = denominator.trim().parse().ok()?;
+        (denominator != 0.0).then_some(numerator.trim().parse::<f32>().ok()? / denominator)
+    };
+    let value = match quantity {
+        "½" => Some(0.5),
+        "⅓" => Some(1.0 / 3.0),
+        "⅔" => Some(2.0 / 3.0),
+        "¼" => Some(0.25),
+        "¾" => Some(0.75),
+        "⅛" => Some(0.125),
+        q => match q.split_once(char::is_whitespace) {
+            Some((whole, f)) => whole
+                .parse::<f32>()
+                .ok()
+                .zip(fraction(f))
+                .map(|(w, f)| w + f),
+            None if q.contains('/') => fraction(q),
+            None => q.replace(',', ".").parse().ok(),
+        },
+    };
+    match value {
+        Some(value) => {
+            let rounded = format!("{value:.2}");
+            rounded
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_owned()
+        }
+        None => quantity.to_owned(),
+    }
+}
+
+/// Minutes of an ISO 8601 duration like `PT1H30M`.
+pub fn parse_duration_minutes(duration: &str) -> Option<i32> {
+    let captures = ISO_DURATION.captures(duration.trim())?;
+    let part = |i: usize| {
+        captures
+            .get(i)
+            .map_or(Some(0.0), |m| m.as_str().parse::<f64>().ok())
+    };
+    let minutes = part(1)? * 24.0 * 60.0 + part(2)? * 60.0 + part(3)? + part(4)? / 60.0;
+    Some(minutes.round().min(i32::MAX as f64) as i32)
+}
+
+/// Our recipe names only have letters, digits, spaces and dashes.
+fn recipe_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    truncate(cleaned.trim_start_matches('-').trim(), 250)
+}
+
+fn image_url(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(url) => Some(url.clone()),
+        Value::Array(items) => items.iter().find_map(|item| image_url(Some(item))),
+        Value::Object(object) => image_url(object.get("url").or_else(|| object.get("contentUrl"))),
+        _ => None,
+    }
+}
+
+/// A string, or the strings of an array.
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Strips HTML tags and decodes the common entities.
+fn plain_text(text: &str) -> String {
+    HTML_TAG
+        .replace_all(text, "")
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .trim()
+        .to_owned()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars()
+        .take(max_chars)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
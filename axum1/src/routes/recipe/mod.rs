@@ -1,62 +1,130 @@
+use std::{collections::HashMap, sync::Arc};
+
 use anyhow::Context;
 use axum::{
-    extract::{Json, Path, Query, State},
-    http::StatusCode,
-    routing::{get, post},
+    extract::{FromRef, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware::from_fn_with_state,
+    routing::{get, post, put},
     Router,
 };
 use axum_extra::extract::Form;
-use sqlx::{types::BigDecimal, Acquire};
+use sqlx::{types::BigDecimal, Acquire, PgConnection};
 use validator::Validate;
 
 use crate::{
     error::{ApiError, ResultExt},
-    extractors::{AuthUser, DatabaseConnection, MaybeAuthUser},
+    extractors::{
+        AuthUser, DatabaseConnection, DbConnection, GuestId, MaybeAuthUser, ReadDatabaseConnection,
+        TrustedProxies,
+    },
+    org::Org,
+    pagination::{Paginated, Pagination},
+    queue::outbox,
+    rate_limit::{rate_limit, RateLimiter},
+    search::RecipeSearchSimple,
     sse::Notification,
     state::AppState,
+    utils::SingleFlight,
     RE_RECIPE,
 };
 
-mod helpers;
+pub mod helpers;
 use helpers::{DifficultyLevel, TypeByTime};
 
-use self::{extractors::RecipeCreator, helpers::QuantityUnit};
+use self::{
+    detail::{
+        fetch_recipe_detailed, fetch_recipes_detailed, vary_on_locale, DetailQuery,
+        RecipeDetailedWithFav, Sections,
+    },
+    extractors::{RecipeCreator, RecipeReadConnection},
+    nutrition::refresh_recipe_nutrition,
+    query::{RecipeQuery, SearchFilters},
+    references::resolve_ingredient_ids,
+    size::RecipeSizeLimits,
+    slug::{suggest_recipe_name, RecipeName},
+    visibility::Visibility,
+};
 
+pub mod canonical;
+pub mod changes;
+pub mod compare;
+pub mod cost;
+pub mod detail;
 mod extractors;
+pub mod favorite;
+pub mod import;
+pub mod nutrition;
+pub mod pantry;
+pub mod pdf;
+pub mod presence;
+pub mod query;
+pub mod references;
+mod report;
+pub mod size;
+pub mod slug;
+pub mod visibility;
+
+pub fn router(state: AppState) -> Router<AppState> {
+    // Each import fetches a page from another site on our behalf.
+    let import_limiter =
+        RateLimiter::per_minute(state.config.borrow().recipe_import.requests_per_minute())
+            .trusting(TrustedProxies::from_ref(&state));
+    let imports = Router::new()
+        .route("/import_url", post(import::import_recipe_from_url))
+        .route_layer(from_fn_with_state(import_limiter, rate_limit));
 
-pub fn router() -> Router<AppState> {
     let action_router = Router::new()
         .route("/my-recipes", get(my_recipes))
         .route("/favorites", get(my_favorite_recipes))
         .route("/popular", get(most_popular_recipes))
-        .route("/hot", get(hot_recipes));
+        .route("/hot", get(hot_recipes))
+        .route("/search", get(search_recipes));
 
-    Router::new()
-        .route("/", post(insert_full_recipe))
-        .route("/:name", get(get_recipe_with_ingredients))
-        .route("/:name/favorite", post(toggle_favorite_recipe))
+    let recipe_router = Router::new()
+        .route("/:slug", get(get_recipe_with_ingredients))
+        .route(
+            "/:slug/favorite",
+            post(toggle_favorite_recipe)
+                .put(favorite::favorite_recipe)
+                .delete(favorite::unfavorite_recipe),
+        )
+        .route("/:slug/name", put(rename_recipe))
+        .route("/:slug/visibility", put(visibility::set_visibility))
+        .route("/:slug/report", post(report::report_recipe))
+        .route("/:slug/pdf", get(pdf::recipe_pdf))
+        .route("/:slug/cost", get(cost::recipe_cost))
+        .route(
+            "/:slug/presence",
+            get(presence::list_presence)
+                .put(presence::heartbeat)
+                .delete(presence::leave),
+        )
         .route(
-            "/:name/ingredient",
+            "/:slug/ingredient",
             post(add_or_update_ingredient_to_recipe).delete(delete_ingredient_from_recipe),
         )
+        .route_layer(from_fn_with_state(
+            state,
+            canonical::redirect_to_canonical_slug,
+        ));
+
+    Router::new()
+        .route("/", get(list_recipes).post(insert_full_recipe))
+        .route("/batch", post(get_recipes_batch))
+        .route("/changes", get(changes::recipe_changes))
+        .route("/compare", get(compare::compare_recipes))
+        .route("/pantry_match", post(pantry::pantry_match))
+        .route(
+            "/favorites",
+            put(favorite::favorite_recipes).delete(favorite::unfavorite_recipes),
+        )
+        .merge(imports)
+        .merge(recipe_router)
         .nest("/action", action_router)
+        .fallback(canonical::trailing_slash)
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
-struct RecipeDetailedWithFav {
-    name: String,
-    description: String,
-    prep_time: i32,
-    cook_time: i32,
-    difficulty: DifficultyLevel,
-    steps: Vec<String>,
-    cuisine: String,
-    meal_type: TypeByTime,
-    ingredients: Vec<DetailedIngredient>,
-    full_calories: f32,
-    favorited: bool,
-    is_author: bool,
-}
 #[derive(
     Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow, validator::Validate,
 )]
@@ -86,126 +154,180 @@ struct RecipeWithIngredients {
     cuisine: String,
     meal_type: TypeByTime,
     ingredients: Vec<DetailedIngredient>,
+    /// The file name of one of the author's uploads.
+    #[serde(default)]
+    image: Option<String>,
+    /// Private unless set.
+    #[serde(default)]
+    visibility: Visibility,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
-struct DetailedIngredient {
+pub struct DetailedIngredient {
+    pub name: String,
+    pub quantity: String,
+    pub quantity_unit: String,
+    pub calories_per_100g: f32,
+}
+
+/// What an anonymous read of a recipe depends on. The reads with the same key share a result.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecipeReadKey {
+    org_id: uuid::Uuid,
     name: String,
-    quantity: String,
-    quantity_unit: String,
-    calories_per_100g: f32,
+    sections: Sections,
 }
 
-#[tracing::instrument(skip(conn, maybe_auth_user))]
-async fn get_recipe_with_ingredients(
-    DatabaseConnection(mut conn): DatabaseConnection,
-    Path(name): Path<String>,
-    maybe_auth_user: MaybeAuthUser,
-) -> Result<Json<RecipeDetailedWithFav>, ApiError> {
-    let mut tx = conn.begin().await?;
+pub type SharedRecipeRead = Result<Option<RecipeDetailedWithFav>, Arc<ApiError>>;
 
-    // A little bit clunky, but better be safe than overly smart.
-    let recipe = sqlx::query_as!(
-        RecipeFull,
-        r#"
-        SELECT r.name, description, prep_time, cook_time, difficulty as "difficulty: DifficultyLevel",
-        steps, c.name as cuisine, meal_type as "meal_type: TypeByTime"
-        FROM recipes r
-        INNER JOIN cuisines c ON c.id = r.cuisine_id
-        WHERE r.name = $1
-        "#,
-        name
-    )
-    .fetch_optional(&mut *tx)
-    .await
-    .context("Failed to query recipe")?
-    .ok_or(ApiError::NotFound)?;
+/// Anonymous reads of the same recipe that arrive while one is already in flight share its
+/// result instead of each running the same queries, which matters for recipes that suddenly
+/// become popular. Only `GET /r/:slug` opts into it.
+pub type RecipeReads = SingleFlight<RecipeReadKey, SharedRecipeRead>;
 
-    let ingredients: Vec<DetailedIngredient> = sqlx::query_as!(
-        DetailedIngredient,
-        r#"
-        SELECT i.name, i.calories_per_100g, ir.quantity, ir.quantity_unit FROM recipes r
-        INNER JOIN ingredients_to_recipes ir
-        ON r.id = ir.recipe_id
-        INNER JOIN ingredients i
-        ON i.id = ir.ingredient_id
-        WHERE r.name = $1;
-        "#,
-        name
-    )
-    .fetch_all(&mut *tx)
-    .await
-    .context("Failed to query recipe ingredients")?;
-
-    let full_calories = ingredients.iter().fold(0.0, |acc, ingredient| {
-        let multiplier = QuantityUnit::try_from(ingredient.quantity_unit.as_str())
-            .unwrap_or_default()
-            .get_multiplier_for_g();
-        acc + (ingredient.calories_per_100g * multiplier * ingredient.quantity.parse::<f32>().unwrap_or(0.0) // We ignore non-numeric quantities
-            / 100.0)
-    });
-
-    let (favorited, is_author) = if let Some(user_id) = maybe_auth_user.into_inner() {
-        let favorited = sqlx::query!(
-            r#"
-        SELECT 1 as _e FROM favorite_recipe
-        WHERE user_id = $1 AND recipe_id = (SELECT id FROM recipes WHERE name = $2)"#,
-            *user_id,
-            recipe.name
-        )
-        .fetch_optional(&mut *tx)
-        .await
-        .context("failed to query for favorite recipes")?
-        .is_some();
+/// Reads the recipe the way anyone who isn't logged in sees it. It owns the connection, so it can
+/// be shared with the other readers, see [`RecipeReads`].
+async fn read_recipe_anonymously(
+    mut conn: DbConnection,
+    org: Org,
+    name: String,
+    sections: Sections,
+) -> Result<Option<RecipeDetailedWithFav>, ApiError> {
+    let mut tx = conn.begin().await?;
+    let recipe = fetch_recipe_detailed(&mut tx, org, &name, None, sections).await?;
+    tx.commit().await?;
+    Ok(recipe)
+}
 
-        let is_author = sqlx::query!(
-            "SELECT 1 as _e FROM recipes WHERE name = $1 AND creator_id = $2",
-            name,
-            *user_id
-        )
-        .fetch_optional(&mut *tx)
-        .await?
-        .is_some();
+/// The full recipe in a single request, see [`RecipeDetailedWithFav`]. `?include=` picks the
+/// optional sections, see [`detail::Sections`], and `?formatted=true` adds the numbers formatted
+/// in the client's language.
+#[tracing::instrument(skip(db_pool, recently_viewed, recipe_reads, config, headers, conn))]
+async fn get_recipe_with_ingredients(
+    State(AppState {
+        db_pool,
+        recently_viewed,
+        recipe_reads,
+        config,
+        ..
+    }): State<AppState>,
+    org: Org,
+    RecipeName(name): RecipeName,
+    Query(query): Query<DetailQuery>,
+    headers: HeaderMap,
+    conn: RecipeReadConnection,
+) -> Result<(HeaderMap, Json<RecipeDetailedWithFav>), ApiError> {
+    let sections = query.sections()?;
+    let default_locale = config.borrow().application_settings.default_locale();
+    let locale = query.locale(&headers, default_locale);
+
+    // Logged in users get their own favorite/author flags (and may see their own hidden recipes),
+    // so their responses can't be shared.
+    let conn = match conn {
+        RecipeReadConnection::User(user, mut conn) => {
+            let mut tx = conn.begin().await?;
+            let mut recipe = fetch_recipe_detailed(&mut tx, org, &name, Some(*user), sections)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            tx.commit().await?;
+            // Off the request path, viewing a recipe shouldn't wait for (or fail because of) Redis.
+            tokio::spawn(recently_viewed.record_if_enabled(db_pool, *user, recipe.slug.clone()));
+            if let Some(locale) = locale {
+                recipe.format_numbers(locale);
+            }
+            return Ok((vary_on_locale(locale), Json(recipe)));
+        }
+        RecipeReadConnection::Anonymous(conn) => conn,
+    };
 
-        (favorited, is_author)
+    // A sandboxed request reads its own uncommitted writes, nobody else may see them.
+    let recipe = if matches!(conn, DbConnection::Sandboxed(_)) {
+        read_recipe_anonymously(conn, org, name, sections).await
     } else {
-        (false, false)
+        // Only the first reader's connection is used, the others are given back right away.
+        let key = RecipeReadKey {
+            org_id: *org,
+            name: name.clone(),
+            sections,
+        };
+        recipe_reads
+            .try_run(key, read_recipe_anonymously(conn, org, name, sections))
+            .await
     };
+    let mut recipe = recipe?.ok_or(ApiError::NotFound)?;
 
-    tx.commit().await?;
+    // Formatted after the shared read, so clients with different languages can share it.
+    if let Some(locale) = locale {
+        recipe.format_numbers(locale);
+    }
+    Ok((vary_on_locale(locale), Json(recipe)))
+}
 
-    Ok(Json(RecipeDetailedWithFav {
-        ingredients,
-        name: recipe.name,
-        description: recipe.description,
-        prep_time: recipe.prep_time,
-        cook_time: recipe.cook_time,
-        difficulty: recipe.difficulty,
-        steps: recipe.steps,
-        cuisine: recipe.cuisine,
-        meal_type: recipe.meal_type,
-        full_calories,
-        favorited,
-        is_author,
-    }))
+#[derive(Debug, Clone, serde::Deserialize)]
+struct BatchRecipeQuery {
+    names: Vec<String>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
-struct Recipe {
-    name: String,
-    description: String,
+#[derive(Debug, Clone, serde::Serialize, Default)]
+struct BatchRecipeResponse {
+    recipes: HashMap<String, RecipeDetailedWithFav>,
+    not_found: Vec<String>,
+}
+
+/// The same as `GET /r/:slug` for many recipes at once, by name.
+#[tracing::instrument(skip(conn, maybe_auth_user, config, headers))]
+async fn get_recipes_batch(
+    State(AppState { mut config, .. }): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Query(detail_query): Query<DetailQuery>,
+    headers: HeaderMap,
+    Json(query): Json<BatchRecipeQuery>,
+) -> Result<(HeaderMap, Json<BatchRecipeResponse>), ApiError> {
+    let sections = detail_query.sections()?;
+    let (max_batch_size, default_locale) = {
+        let config = config.borrow_and_update();
+        (
+            config.application_settings.max_recipe_batch_size(),
+            config.application_settings.default_locale(),
+        )
+    };
+    let locale = detail_query.locale(&headers, default_locale);
+
+    let mut names = query.names;
+    names.sort_unstable();
+    names.dedup();
+
+    if names.len() > max_batch_size {
+        return Err(ApiError::unprocessable_entity([(
+            "names",
+            format!("at most {max_batch_size} recipes can be requested at once"),
+        )]));
+    }
+
+    let user_id = maybe_auth_user.into_inner().as_deref().copied();
+    let mut recipes = fetch_recipes_detailed(&mut conn, org, &names, user_id, sections).await?;
+    if let Some(locale) = locale {
+        recipes
+            .values_mut()
+            .for_each(|recipe| recipe.format_numbers(locale));
+    }
+    let not_found = names
+        .into_iter()
+        .filter(|name| !recipes.contains_key(name))
+        .collect();
+
+    Ok((
+        vary_on_locale(locale),
+        Json(BatchRecipeResponse { recipes, not_found }),
+    ))
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
-struct RecipeFull {
+struct Recipe {
     name: String,
     description: String,
-    prep_time: i32,
-    cook_time: i32,
-    difficulty: DifficultyLevel,
-    steps: Vec<String>,
-    cuisine: String,
-    meal_type: TypeByTime,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, validator::Validate)]
@@ -217,11 +339,13 @@ struct InsertIngredient {
     quantity_unit: String,
 }
 
-#[tracing::instrument(skip(conn))]
+#[tracing::instrument(skip(conn, config))]
 async fn add_or_update_ingredient_to_recipe(
+    State(AppState { config, .. }): State<AppState>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     _creator: RecipeCreator,
-    Path(name): Path<String>,
+    RecipeName(name): RecipeName,
     Form(ingredient): Form<InsertIngredient>,
 ) -> Result<(), ApiError> {
     ingredient
@@ -230,12 +354,15 @@ async fn add_or_update_ingredient_to_recipe(
 
     let mut tx = conn.begin().await?;
 
+    let ingredient_ids =
+        resolve_ingredient_ids(&mut tx, org, std::slice::from_ref(&ingredient.name)).await?;
+
     sqlx::query!(
         r#"
         INSERT INTO ingredients_to_recipes (ingredient_id, recipe_id, quantity, quantity_unit)
         VALUES (
-            (SELECT id FROM ingredients WHERE name = $1),
-            (SELECT id FROM recipes WHERE name = $2),
+            $1,
+            (SELECT id FROM recipes WHERE name = $2 AND org_id = $5),
             $3,
             $4
         ) ON CONFLICT (ingredient_id, recipe_id) DO
@@ -243,19 +370,55 @@ async fn add_or_update_ingredient_to_recipe(
             quantity = EXCLUDED.quantity,
             quantity_unit = EXCLUDED.quantity_unit;
         "#,
-        ingredient.name,
+        ingredient_ids[&ingredient.name],
         name,
         ingredient.quantity,
-        ingredient.quantity_unit
+        ingredient.quantity_unit,
+        *org
     )
     .execute(&mut *tx)
     .await
     .map_err(|_| ApiError::BadRequest)?;
 
+    // Counted after the insert, so updating an ingredient of a full recipe is still allowed.
+    let ingredient_count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM ingredients_to_recipes
+        WHERE recipe_id = (SELECT id FROM recipes WHERE name = $1 AND org_id = $2)
+        "#,
+        name,
+        *org
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let limits = RecipeSizeLimits::from_settings(&config.borrow().application_settings);
+    limits.check(ingredient_count as usize, 0)?;
+
+    refresh_nutrition_by_name(&mut tx, org, &name).await?;
+
     tx.commit().await?;
     Ok(())
 }
 
+/// Keeps the cached nutrition summary of a recipe in sync after its ingredients change.
+async fn refresh_nutrition_by_name(
+    conn: &mut PgConnection,
+    org: Org,
+    name: &str,
+) -> Result<(), ApiError> {
+    let recipe_id = sqlx::query_scalar!(
+        "SELECT id FROM recipes WHERE name = $1 AND org_id = $2",
+        name,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    refresh_recipe_nutrition(&mut *conn, &[recipe_id])
+        .await
+        .context("Failed to refresh recipe nutrition")?;
+    Ok(())
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 struct NamedIngredient {
     name: String,
@@ -264,7 +427,8 @@ struct NamedIngredient {
 #[tracing::instrument(skip(conn))]
 async fn delete_ingredient_from_recipe(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Path(name): Path<String>,
+    org: Org,
+    RecipeName(name): RecipeName,
     _creator: RecipeCreator,
     Form(ingredient): Form<NamedIngredient>,
 ) -> Result<(), ApiError> {
@@ -273,33 +437,45 @@ async fn delete_ingredient_from_recipe(
     sqlx::query!(
         r#"
         DELETE FROM ingredients_to_recipes
-        WHERE recipe_id = (SELECT id FROM recipes WHERE name = $1)
-        AND ingredient_id = (SELECT id from ingredients WHERE name = $2)
+        WHERE recipe_id = (SELECT id FROM recipes WHERE name = $1 AND org_id = $3)
+        AND ingredient_id = (SELECT id from ingredients WHERE name = $2 AND org_id = $3)
         "#,
         name,
-        ingredient.name
+        ingredient.name,
+        *org
     )
     .execute(&mut *tx)
     .await
     .context("Failed to delete from ingredients_to_recipes")?;
 
+    refresh_nutrition_by_name(&mut tx, org, &name).await?;
+
     tx.commit().await?;
 
     Ok(())
 }
 
-#[tracing::instrument(skip(conn, auth_user))]
+#[tracing::instrument(skip(conn, auth_user, config))]
 async fn insert_full_recipe(
-    State(AppState { tx: channel, .. }): State<AppState>,
+    State(AppState {
+        tx: channel,
+        config,
+        ..
+    }): State<AppState>,
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     // We want to accept Json input here instead of Form, because the structure
     // of `RecipeWithIngredients` is too complicated to handle with a form.
     auth_user: AuthUser,
     Json(recipe_with_ingredients): Json<RecipeWithIngredients>,
-) -> Result<(), ApiError> {
+) -> Result<Json<CreatedRecipe>, ApiError> {
     recipe_with_ingredients
         .validate()
         .map_err(ApiError::unprocessable_entity_from_validation_errors)?;
+    RecipeSizeLimits::from_settings(&config.borrow().application_settings).check(
+        recipe_with_ingredients.ingredients.len(),
+        recipe_with_ingredients.steps.len(),
+    )?;
 
     let mut tx = conn.begin().await?;
 
@@ -313,6 +489,8 @@ async fn insert_full_recipe(
         cuisine,
         meal_type,
         ingredients,
+        image,
+        visibility,
     } = recipe_with_ingredients;
 
     let recipe = sqlx::query!(
@@ -326,10 +504,13 @@ async fn insert_full_recipe(
             "difficulty",
             "steps",
             "cuisine_id",
-            "meal_type"
+            "meal_type",
+            "org_id",
+            "image",
+            "visibility"
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, (SELECT id FROM cuisines WHERE name = $8), $9)
-        RETURNING id;
+        VALUES ($1, $2, $3, $4, $5, $6, $7, (SELECT id FROM cuisines WHERE name = $8), $9, $10, $11, $12)
+        RETURNING id, slug;
         "#,
         name,
         description,
@@ -340,52 +521,122 @@ async fn insert_full_recipe(
         &steps,
         cuisine,
         meal_type as _,
+        *org,
+        image,
+        visibility as _,
     )
     .fetch_one(&mut *tx)
     .await
     .on_code("23502", |_| {
         ApiError::unprocessable_entity([("cuisine", "does not exist")])
     })
-    .on_constraint("recipes_name_key", |_| ApiError::Conflict)?;
+    .on_constraint("recipes_image_fkey", |_| {
+        ApiError::unprocessable_entity([("image", "is not one of your uploads")])
+    })
+    // Another recipe with the same slug was created concurrently, trying again gets the next one.
+    .on_constraint("recipes_slug_key", |_| ApiError::Conflict);
+    let recipe = match recipe {
+        Err(ApiError::Sqlx(sqlx::Error::Database(dbe)))
+            if dbe.constraint() == Some("recipes_name_key") =>
+        {
+            // The failed insert aborted the transaction, so the suggestion is looked up outside it.
+            tx.rollback().await?;
+            let suggestion = suggest_recipe_name(&mut *conn, org, &name).await?;
+            return Err(ApiError::RecipeNameTaken { suggestion });
+        }
+        recipe => recipe?,
+    };
+
+    // Every reference is checked before any is inserted, so all unknown names are reported at once.
+    let names: Vec<_> = ingredients.iter().map(|i| i.name.clone()).collect();
+    let ingredient_ids = resolve_ingredient_ids(&mut tx, org, &names).await?;
 
     for ingredient in ingredients {
         sqlx::query!(
             r#"
         INSERT INTO ingredients_to_recipes (ingredient_id, recipe_id, quantity, quantity_unit)
-        VALUES (
-            (SELECT id FROM ingredients WHERE name = $1),
-            $2,
-            $3,
-            $4
-        ) ON CONFLICT (ingredient_id, recipe_id) DO
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (ingredient_id, recipe_id) DO
         UPDATE SET
             quantity = EXCLUDED.quantity,
             quantity_unit = EXCLUDED.quantity_unit;
         "#,
-            ingredient.name,
+            ingredient_ids[&ingredient.name],
             recipe.id,
             ingredient.quantity,
-            ingredient.quantity_unit
+            ingredient.quantity_unit,
         )
         .execute(&mut *tx)
+        .await?;
+    }
+
+    refresh_recipe_nutrition(&mut tx, &[recipe.id])
         .await
-        .map_err(|_| {
-            ApiError::unprocessable_entity([(
-                "ingredient-name",
-                format!("{} is not an ingredient", ingredient.name),
-            )])
-        })?;
+        .context("Failed to compute recipe nutrition")?;
+
+    // Everyone is told about new recipes, but only the ones they can find.
+    if visibility == Visibility::Public {
+        outbox::enqueue(&mut tx, &Notification::new_recipe(name)).await?;
     }
 
     tx.commit().await?;
 
-    channel.send(Notification::new_recipe(name)).unwrap();
-    Ok(())
+    outbox::relay_now(&mut conn, &channel).await;
+    Ok(Json(CreatedRecipe { slug: recipe.slug }))
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct CreatedRecipe {
+    /// Where the recipe can be found, it never changes.
+    slug: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, validator::Validate)]
+struct Rename {
+    #[validate(
+        length(
+            min = 2,
+            max = 250,
+            message = "should be at least 2 characters, but no more than 250"
+        ),
+        regex(
+            path = *RE_RECIPE,
+            message = "only letters, digits, and non-leading and non-trailing dashes are allowed"
+        )
+    )]
+    name: String,
+}
+
+/// Changes the display name of a recipe. Its slug stays the same, so links to it keep working.
+#[tracing::instrument(skip(conn))]
+async fn rename_recipe(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    _creator: RecipeCreator,
+    RecipeName(name): RecipeName,
+    Json(rename): Json<Rename>,
+) -> Result<StatusCode, ApiError> {
+    rename
+        .validate()
+        .map_err(ApiError::unprocessable_entity_from_validation_errors)?;
+
+    sqlx::query!(
+        "UPDATE recipes SET name = $1 WHERE name = $2 AND org_id = $3",
+        rename.name,
+        name,
+        *org
+    )
+    .execute(&mut *conn)
+    .await
+    .on_constraint("recipes_name_key", |_| ApiError::Conflict)?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
 struct RecipeWithIngredientCount {
     name: String,
+    slug: String,
     description: String,
     ingredient_count: Option<i64>,
 }
@@ -393,38 +644,91 @@ struct RecipeWithIngredientCount {
 #[tracing::instrument(skip_all)]
 async fn my_recipes(
     DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
     auth_user: AuthUser,
-) -> Result<Json<Vec<RecipeWithIngredientCount>>, ApiError> {
+    pagination: Pagination,
+) -> Result<Paginated<RecipeWithIngredientCount>, ApiError> {
     let results = sqlx::query_as!(
         RecipeWithIngredientCount,
         r#"
         SELECT DISTINCT r.name,
+                r.slug,
                 r.description,
                 COUNT(ir.recipe_id) OVER (PARTITION BY r.id) AS ingredient_count
         FROM recipes r
         LEFT JOIN ingredients_to_recipes ir ON ir.recipe_id = r.id
-        WHERE creator_id = $1;
+        WHERE creator_id = $1 AND r.org_id = $4
+        ORDER BY r.name
+        LIMIT $2 OFFSET $3;
         "#,
-        *auth_user
+        *auth_user,
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
 
-    Ok(Json(results))
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM recipes WHERE creator_id = $1 AND org_id = $2"#,
+        *auth_user,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(results, pagination, total))
+}
+
+/// Lists the recipes matching the filters, see [`query::RecipeFilters`].
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+async fn list_recipes(
+    ReadDatabaseConnection(mut conn): ReadDatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    SearchFilters(filters): SearchFilters,
+    pagination: Pagination,
+) -> Result<Paginated<RecipeWithIngredientCount>, ApiError> {
+    let viewer = maybe_auth_user.into_inner().as_deref().copied();
+    let query = RecipeQuery::new(org, &filters).viewer(viewer);
+    let results = query
+        .fetch_page(
+            &mut conn,
+            r#"
+            r.name, r.slug, r.description,
+            (SELECT COUNT(*) FROM ingredients_to_recipes ir WHERE ir.recipe_id = r.id)
+                AS ingredient_count
+            "#,
+            pagination,
+        )
+        .await?;
+    let total = query.fetch_count(&mut conn).await?;
+
+    Ok(Paginated::new(results, pagination, total))
 }
 
-#[tracing::instrument(skip(conn, auth_user))]
+#[tracing::instrument(skip(conn, maybe_auth_user))]
 async fn toggle_favorite_recipe(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Path(name): Path<String>,
-    auth_user: AuthUser,
+    org: Org,
+    RecipeName(name): RecipeName,
+    maybe_auth_user: MaybeAuthUser,
 ) -> Result<StatusCode, ApiError> {
+    let guest_id = maybe_auth_user.guest_id();
+    let Some(auth_user) = maybe_auth_user.into_inner() else {
+        let guest_id = guest_id.ok_or(ApiError::Unauthorized)?;
+        return toggle_guest_favorite_recipe(&mut conn, org, guest_id, &name).await;
+    };
+
+    let recipe_id = favorite::visible_recipe_id(&mut conn, org, Some(*auth_user), &name)
+        .await?
+        .ok_or(ApiError::BadRequest)?;
     let result = sqlx::query!(
         // This is a helper function written in the `create_favorite_recipe` migration.
         // It helps to easily manage a 'toggle' functionality for marking favorites.
-        "SELECT toggle_favorite_recipe($1, (SELECT id FROM recipes WHERE name = $2))",
+        "SELECT toggle_favorite_recipe($1, $2)",
         *auth_user,
-        name,
+        recipe_id,
     )
     .fetch_one(&mut *conn)
     .await
@@ -437,83 +741,235 @@ async fn toggle_favorite_recipe(
     }
 }
 
+/// The same as `toggle_favorite_recipe`, but for guest sessions.
+async fn toggle_guest_favorite_recipe(
+    conn: &mut PgConnection,
+    org: Org,
+    guest_id: GuestId,
+    name: &str,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let recipe_id = favorite::visible_recipe_id(&mut tx, org, None, name)
+        .await?
+        .ok_or(ApiError::BadRequest)?;
+
+    let removed = sqlx::query!(
+        "DELETE FROM guest_favorite_recipes WHERE guest_id = $1 AND recipe_id = $2",
+        *guest_id,
+        recipe_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let status = if removed > 0 {
+        StatusCode::OK
+    } else {
+        sqlx::query!(
+            "INSERT INTO guest_favorite_recipes (guest_id, recipe_id) VALUES ($1, $2)",
+            *guest_id,
+            recipe_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        StatusCode::CREATED
+    };
+
+    tx.commit().await?;
+
+    Ok(status)
+}
+
 #[tracing::instrument(skip_all)]
 async fn my_favorite_recipes(
     DatabaseConnection(mut conn): DatabaseConnection,
-    auth_user: AuthUser,
-) -> Result<Json<Vec<RecipeWithIngredientCount>>, ApiError> {
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    pagination: Pagination,
+) -> Result<Paginated<RecipeWithIngredientCount>, ApiError> {
+    let guest_id = maybe_auth_user.guest_id();
+    let Some(auth_user) = maybe_auth_user.into_inner() else {
+        let guest_id = guest_id.ok_or(ApiError::Unauthorized)?;
+        let results = sqlx::query_as!(
+            RecipeWithIngredientCount,
+            r#"
+            SELECT DISTINCT r.name,
+                    r.slug,
+                    r.description,
+                    COUNT(ir.recipe_id) OVER (PARTITION BY r.id) AS ingredient_count
+            FROM recipes r
+            LEFT JOIN ingredients_to_recipes ir ON ir.recipe_id = r.id
+            INNER JOIN guest_favorite_recipes gfr ON gfr.recipe_id = r.id AND gfr.guest_id = $1
+            WHERE r.org_id = $4 AND r.visibility <> 'private' AND NOT r.hidden
+            ORDER BY r.name
+            LIMIT $2 OFFSET $3;
+            "#,
+            *guest_id,
+            pagination.per_page,
+            pagination.offset(),
+            *org
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM guest_favorite_recipes gfr
+            INNER JOIN recipes r ON r.id = gfr.recipe_id
+            WHERE gfr.guest_id = $1 AND r.org_id = $2 AND r.visibility <> 'private'
+                AND NOT r.hidden
+            "#,
+            *guest_id,
+            *org
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        return Ok(Paginated::new(results, pagination, total));
+    };
+
     let results = sqlx::query_as!(
         RecipeWithIngredientCount,
         r#"
         SELECT DISTINCT r.name,
+                r.slug,
                 r.description,
                 COUNT(ir.recipe_id) OVER (PARTITION BY r.id) AS ingredient_count
         FROM recipes r
         LEFT JOIN ingredients_to_recipes ir ON ir.recipe_id = r.id
-        INNER JOIN favorite_recipe fr ON fr.recipe_id = r.id AND fr.user_id = $1;
+        INNER JOIN favorite_recipe fr ON fr.recipe_id = r.id AND fr.user_id = $1
+        WHERE r.org_id = $4 AND (r.visibility <> 'private' OR r.creator_id = $1)
+            AND (NOT r.hidden OR r.creator_id = $1)
+        ORDER BY r.name
+        LIMIT $2 OFFSET $3;
         "#,
-        *auth_user
+        *auth_user,
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
 
-    Ok(Json(results))
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM favorite_recipe fr
+        INNER JOIN recipes r ON r.id = fr.recipe_id
+        WHERE fr.user_id = $1 AND r.org_id = $2
+            AND (r.visibility <> 'private' OR r.creator_id = $1)
+            AND (NOT r.hidden OR r.creator_id = $1)
+        "#,
+        *auth_user,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(results, pagination, total))
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
 struct RecipeWithFavoriteCount {
     name: String,
+    slug: String,
     count: Option<i64>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct LimitedQuery {
-    limit: Option<i64>,
-}
-
 #[tracing::instrument(skip(conn))]
 async fn most_popular_recipes(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Query(query): Query<LimitedQuery>,
-) -> Result<Json<Vec<RecipeWithFavoriteCount>>, ApiError> {
-    let limit = query.limit.filter(|&limit| limit >= 0).unwrap_or(10);
+    org: Org,
+    pagination: Pagination,
+) -> Result<Paginated<RecipeWithFavoriteCount>, ApiError> {
     let results = sqlx::query_as!(
         RecipeWithFavoriteCount,
         r#"
-        SELECT r.name, COUNT(fr.recipe_id) FROM recipes r
+        SELECT r.name, r.slug, COUNT(fr.recipe_id) FROM recipes r
         INNER JOIN favorite_recipe fr ON r.id = fr.recipe_id
-        GROUP BY r.name
-        ORDER BY count DESC
-        LIMIT $1;
+        WHERE NOT r.hidden AND r.visibility = 'public' AND r.org_id = $3
+        GROUP BY r.id
+        ORDER BY count DESC, r.name
+        LIMIT $1 OFFSET $2;
         "#,
-        limit
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
 
-    Ok(Json(results))
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT r.id) AS "count!" FROM recipes r
+        INNER JOIN favorite_recipe fr ON r.id = fr.recipe_id
+        WHERE NOT r.hidden AND r.visibility = 'public' AND r.org_id = $1
+        "#,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(results, pagination, total))
 }
 
 #[tracing::instrument(skip(conn))]
 async fn hot_recipes(
     DatabaseConnection(mut conn): DatabaseConnection,
-    Query(query): Query<LimitedQuery>,
-) -> Result<Json<Vec<RecipeWithFavoriteCount>>, ApiError> {
-    let limit = query.limit.filter(|&limit| limit >= 0).unwrap_or(10);
+    org: Org,
+    pagination: Pagination,
+) -> Result<Paginated<RecipeWithFavoriteCount>, ApiError> {
     let results = sqlx::query_as!(
         RecipeWithFavoriteCount,
         r#"
-        SELECT r.name, COUNT(fr.recipe_id) FROM favorite_recipe fr
+        SELECT r.name, r.slug, COUNT(fr.recipe_id) FROM favorite_recipe fr
         INNER JOIN recipes r ON r.id = fr.recipe_id
-        WHERE fr.created_at > current_timestamp - INTERVAL '14 days'
-        GROUP BY r.name
-        ORDER BY count DESC
-        LIMIT $1
+        WHERE fr.created_at > current_timestamp - INTERVAL '14 days' AND NOT r.hidden
+            AND r.visibility = 'public' AND r.org_id = $3
+        GROUP BY r.id
+        ORDER BY count DESC, r.name
+        LIMIT $1 OFFSET $2
         "#,
-        limit
+        pagination.per_page,
+        pagination.offset(),
+        *org
     )
     .fetch_all(&mut *conn)
     .await?;
 
-    Ok(Json(results))
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT r.id) AS "count!" FROM favorite_recipe fr
+        INNER JOIN recipes r ON r.id = fr.recipe_id
+        WHERE fr.created_at > current_timestamp - INTERVAL '14 days' AND NOT r.hidden
+            AND r.visibility = 'public' AND r.org_id = $1
+        "#,
+        *org
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(Paginated::new(results, pagination, total))
+}
+
+/// Searches the recipes in Postgres. Takes the same filters as the listing, but `q` is required.
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+async fn search_recipes(
+    ReadDatabaseConnection(mut conn): ReadDatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    SearchFilters(filters): SearchFilters,
+    pagination: Pagination,
+) -> Result<Paginated<RecipeSearchSimple>, ApiError> {
+    if filters.q.as_deref().unwrap_or_default().trim().is_empty() {
+        return Err(ApiError::unprocessable_entity([("q", "must not be empty")]));
+    }
+    let viewer = maybe_auth_user.into_inner().as_deref().copied();
+    let query = RecipeQuery::new(org, &filters).viewer(viewer);
+    let results = query
+        .fetch_page(&mut conn, "r.id, r.name, r.description", pagination)
+        .await?;
+    let total = query.fetch_count(&mut conn).await?;
+
+    Ok(Paginated::new(results, pagination, total))
 }
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use sqlx::{PgConnection, PgPool};
+
+use crate::queue::set_local_statement_timeout;
+
+use super::helpers::QuantityUnit;
+
+/// How many recipes are recomputed in a single transaction.
+const BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Nutrition {
+    calories: f32,
+    protein: f32,
+    fat: f32,
+    carbohydrate: f32,
+    sugar: f32,
+    fiber: f32,
+}
+
+/// The cached nutrition of a whole recipe, as served to clients. Every value is in grams, except
+/// for the calories.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct NutritionSummary {
+    pub calories: f32,
+    pub protein: f32,
+    pub fat: f32,
+    pub carbohydrate: f32,
+    pub sugar: f32,
+    pub fiber: f32,
+}
+
+/// Recomputes the cached nutrition of every recipe. Returns the number of recipes updated.
+#[tracing::instrument(skip_all)]
+pub async fn recompute_all_recipe_nutrition(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let recipe_ids = sqlx::query_scalar!("SELECT id FROM recipes ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    recompute_recipe_nutrition(pool, &recipe_ids).await
+}
+
+/// Recomputes the cached nutrition of the given recipes in batches.
+/// Returns the number of recipes updated.
+///
+/// This only ever overwrites the cache with values derived from the current ingredients,
+/// so it's safe to run repeatedly.
+#[tracing::instrument(skip_all, fields(recipes = recipe_ids.len()))]
+pub async fn recompute_recipe_nutrition(
+    pool: &PgPool,
+    recipe_ids: &[uuid::Uuid],
+) -> Result<u64, anyhow::Error> {
+    let mut updated = 0;
+    for batch in recipe_ids.chunks(BATCH_SIZE) {
+        let mut tx = pool.begin().await?;
+        // This may run on a large dataset, don't let the per-statement limit cut it off.
+        set_local_statement_timeout(&mut tx, None).await?;
+        updated += refresh_recipe_nutrition(&mut tx, batch).await?;
+        tx.commit().await?;
+    }
+    tracing::info!("recomputed nutrition of {updated} recipes");
+    Ok(updated)
+}
+
+/// The recipes that use the given ingredient.
+pub async fn recipes_using_ingredient(
+    conn: &mut PgConnection,
+    ingredient_id: uuid::Uuid,
+) -> Result<Vec<uuid::Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT recipe_id FROM ingredients_to_recipes WHERE ingredient_id = $1",
+        ingredient_id
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Recomputes and stores the nutrition summary of the given recipes.
+pub async fn refresh_recipe_nutrition(
+    conn: &mut PgConnection,
+    recipe_ids: &[uuid::Uuid],
+) -> Result<u64, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT ir.recipe_id, ir.quantity, ir.quantity_unit, i.calories_per_100g,
+               i.protein, i.fat, i.carbohydrate, i.sugar, i.fiber
+        FROM ingredients_to_recipes ir
+        INNER JOIN ingredients i ON i.id = ir.ingredient_id
+        WHERE ir.recipe_id = ANY($1)
+        "#,
+        recipe_ids
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Recipes without ingredients still get a (zeroed) summary.
+    let mut totals: HashMap<uuid::Uuid, Nutrition> = recipe_ids
+        .iter()
+        .map(|id| (*id, Nutrition::default()))
+        .collect();
+
+    for row in rows {
+        let multiplier = QuantityUnit::try_from(row.quantity_unit.as_str())
+            .unwrap_or_default()
+            .get_multiplier_for_g();
+        // Same as the calculation of `full_calories`, we ignore non-numeric quantities.
+        let ratio = multiplier * row.quantity.parse::<f32>().unwrap_or(0.0) / 100.0;
+        let total = totals.entry(row.recipe_id).or_default();
+        total.calories += row.calories_per_100g * ratio;
+        total.protein += row.protein * ratio;
+        total.fat += row.fat * ratio;
+        total.carbohydrate += row.carbohydrate * ratio;
+        total.sugar += row.sugar * ratio;
+        total.fiber += row.fiber * ratio;
+    }
+
+    let (ids, nutrition): (Vec<_>, Vec<_>) = totals.into_iter().unzip();
+    let column = |f: fn(&Nutrition) -> f32| nutrition.iter().map(f).collect::<Vec<_>>();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO recipe_nutrition (recipe_id, calories, protein, fat, carbohydrate, sugar, fiber)
+        SELECT * FROM UNNEST($1::uuid[], $2::real[], $3::real[], $4::real[], $5::real[], $6::real[], $7::real[])
+            AS n (recipe_id, calories, protein, fat, carbohydrate, sugar, fiber)
+        -- The recipe might have been deleted in the meantime.
+        WHERE EXISTS (SELECT 1 FROM recipes r WHERE r.id = n.recipe_id)
+        ON CONFLICT (recipe_id) DO UPDATE SET
+            calories = EXCLUDED.calories,
+            protein = EXCLUDED.protein,
+            fat = EXCLUDED.fat,
+            carbohydrate = EXCLUDED.carbohydrate,
+            sugar = EXCLUDED.sugar,
+            fiber = EXCLUDED.fiber
+        "#,
+        &ids,
+        &column(|n| n.calories),
+        &column(|n| n.protein),
+        &column(|n| n.fat),
+        &column(|n| n.carbohydrate),
+        &column(|n| n.sugar),
+        &column(|n| n.fiber),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
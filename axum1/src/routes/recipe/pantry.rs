@@ -0,0 +1,153 @@
+//! Finds the recipes a user can make with the ingredients they have.
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    extractors::{Json, MaybeAuthUser, ReadDatabaseConnection},
+    org::Org,
+    routes::ingredient::resolve::{exact_matches, parse_names},
+};
+
+/// The most ingredients a pantry may have.
+const MAX_PANTRY_SIZE: usize = 500;
+
+/// The most recipes returned, the best matches first.
+const MAX_MATCHES: i64 = 50;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PantryMatchRequest {
+    /// The ingredients the user has, as typed. They're matched like `POST /i/resolve` matches
+    /// names, but only exact matches count.
+    pub ingredients: Vec<String>,
+    /// Recipes missing more ingredients than this are left out. Defaults to 0, only the recipes
+    /// that can be made right away.
+    #[serde(default)]
+    pub max_missing: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PantryMatch {
+    pub recipes: Vec<MatchedRecipe>,
+    /// The names that aren't ingredients of the organization, `POST /i/resolve` offers similar
+    /// ones.
+    pub unknown: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MatchedRecipe {
+    pub name: String,
+    pub slug: String,
+    /// The fraction of the recipe's ingredients the user has, from 0 to 1.
+    pub coverage: f64,
+    /// The ingredients the user doesn't have, by name.
+    pub missing: Vec<String>,
+}
+
+/// The recipes using at least one of `ingredient_ids`, and missing at most `max_missing` of
+/// their ingredients, ranked by coverage. Ties go to the recipe missing fewer ingredients.
+///
+/// Only the recipes with an owned ingredient are considered, found through the ingredients'
+/// index, so it doesn't scan every recipe.
+pub async fn match_pantry(
+    conn: &mut PgConnection,
+    org: Org,
+    viewer: Option<uuid::Uuid>,
+    ingredient_ids: &[uuid::Uuid],
+    max_missing: u32,
+) -> Result<Vec<MatchedRecipe>, ApiError> {
+    let rows = sqlx::query!(
+        r#"
+        WITH candidates AS (
+            SELECT DISTINCT recipe_id FROM ingredients_to_recipes
+            WHERE ingredient_id = ANY($2)
+        )
+        SELECT r.name, r.slug,
+            COUNT(*) AS "required!",
+            COUNT(*) FILTER (WHERE ir.ingredient_id = ANY($2)) AS "owned!",
+            COALESCE(
+                ARRAY_AGG(i.name ORDER BY i.name) FILTER (WHERE NOT ir.ingredient_id = ANY($2)),
+                '{}'
+            ) AS "missing!"
+        FROM candidates c
+        INNER JOIN recipes r ON r.id = c.recipe_id
+        INNER JOIN ingredients_to_recipes ir ON ir.recipe_id = r.id
+        INNER JOIN ingredients i ON i.id = ir.ingredient_id
+        WHERE r.org_id = $1 AND NOT r.hidden
+            AND (r.visibility = 'public' OR r.creator_id = $3)
+        GROUP BY r.id
+        HAVING COUNT(*) FILTER (WHERE NOT ir.ingredient_id = ANY($2)) <= $4
+        ORDER BY
+            COUNT(*) FILTER (WHERE ir.ingredient_id = ANY($2))::FLOAT8 / COUNT(*) DESC,
+            COUNT(*) FILTER (WHERE NOT ir.ingredient_id = ANY($2)),
+            r.name
+        LIMIT $5
+        "#,
+        *org,
+        ingredient_ids,
+        viewer,
+        i64::from(max_missing),
+        MAX_MATCHES
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| MatchedRecipe {
+            name: row.name,
+            slug: row.slug,
+            coverage: row.owned as f64 / row.required as f64,
+            missing: row.missing,
+        })
+        .collect())
+}
+
+/// Resolves the pantry's names and matches the recipes against them, see [`match_pantry`].
+pub async fn pantry_match_recipes(
+    conn: &mut PgConnection,
+    org: Org,
+    viewer: Option<uuid::Uuid>,
+    request: &PantryMatchRequest,
+) -> Result<PantryMatch, ApiError> {
+    let names = parse_names(&request.ingredients);
+    if names.is_empty() {
+        return Err(ApiError::unprocessable_entity([(
+            "ingredients",
+            "must name at least one ingredient",
+        )]));
+    }
+    if names.len() > MAX_PANTRY_SIZE {
+        return Err(ApiError::unprocessable_entity([(
+            "ingredients",
+            format!("at most {MAX_PANTRY_SIZE} ingredients can be matched at once"),
+        )]));
+    }
+
+    let owned = exact_matches(&mut *conn, org, &names).await?;
+    let unknown = names
+        .iter()
+        .filter(|name| !owned.contains_key(*name))
+        .cloned()
+        .collect();
+    let ingredient_ids: Vec<_> = owned.values().map(|ingredient| ingredient.id).collect();
+
+    let recipes = if ingredient_ids.is_empty() {
+        Vec::new()
+    } else {
+        match_pantry(conn, org, viewer, &ingredient_ids, request.max_missing).await?
+    };
+    Ok(PantryMatch { recipes, unknown })
+}
+
+/// `POST /r/pantry_match` with `{"ingredients": [...], "max_missing": 2}`.
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+pub async fn pantry_match(
+    ReadDatabaseConnection(mut conn): ReadDatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    Json(request): Json<PantryMatchRequest>,
+) -> Result<Json<PantryMatch>, ApiError> {
+    let viewer = maybe_auth_user.into_inner().as_deref().copied();
+    let pantry = pantry_match_recipes(&mut conn, org, viewer, &request).await?;
+    Ok(Json(pantry))
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::Context;
+use axum::{
+    body::Bytes,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+};
+use once_cell::sync::Lazy;
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference};
+use sqlx::Acquire;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    utils::spawn_blocking_cancellable,
+};
+
+use super::{
+    detail::{fetch_recipe_detailed, Sections},
+    nutrition::NutritionSummary,
+    slug::RecipeName,
+};
+
+/// Everything that ends up on the printed page.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PrintableRecipe {
+    pub name: String,
+    pub description: String,
+    pub cuisine: String,
+    pub prep_time: i32,
+    pub cook_time: i32,
+    /// Name, quantity and unit.
+    pub ingredients: Vec<(String, String, String)>,
+    pub steps: Vec<String>,
+    pub nutrition: Option<NutritionSummary>,
+}
+
+impl PrintableRecipe {
+    /// Changes whenever anything printed changes, so it's used as the cache key.
+    fn version(&self) -> anyhow::Result<blake3::Hash> {
+        let serialized = serde_json::to_vec(self).context("Failed to serialize recipe")?;
+        Ok(blake3::hash(&serialized))
+    }
+}
+
+/// The maximum number of recipes with a cached PDF.
+const MAX_CACHED_PDFS: usize = 256;
+
+/// A rendered PDF, with the version of the recipe it was rendered from.
+type CachedPdf = (blake3::Hash, Bytes);
+
+/// The last rendered version of each recipe, by organization and name. Older versions are simply
+/// overwritten.
+static PDF_CACHE: Lazy<Mutex<HashMap<(uuid::Uuid, String), CachedPdf>>> =
+    Lazy::new(Default::default);
+
+#[tracing::instrument(skip(conn, maybe_auth_user))]
+pub async fn recipe_pdf(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    RecipeName(name): RecipeName,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut tx = conn.begin().await?;
+
+    let user_id = maybe_auth_user.into_inner().as_deref().copied();
+    let recipe = fetch_recipe_detailed(&mut tx, org, &name, user_id, Sections::ALL)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    tx.commit().await?;
+
+    let printable = PrintableRecipe {
+        name: recipe.name,
+        description: recipe.description,
+        cuisine: recipe.cuisine,
+        prep_time: recipe.prep_time,
+        cook_time: recipe.cook_time,
+        ingredients: recipe
+            .ingredients
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| (i.name, i.quantity, i.quantity_unit))
+            .collect(),
+        steps: recipe.steps,
+        nutrition: recipe.nutrition,
+    };
+    let version = printable.version()?;
+    let file_name = pdf_file_name(&printable.name);
+    let cache_key = (*org, printable.name.clone());
+
+    let cached = PDF_CACHE
+        .lock()
+        .unwrap()
+        .get(&cache_key)
+        .filter(|(cached_version, _)| *cached_version == version)
+        .map(|(_, pdf)| pdf.clone());
+
+    let pdf = match cached {
+        Some(pdf) => pdf,
+        None => {
+            // Stops laying out the pages if the client disconnects in the meantime.
+            let pdf =
+                spawn_blocking_cancellable(move |cancel| render_recipe_pdf(&printable, &cancel))
+                    .await
+                    .context("Failed to render recipe PDF")??;
+            let pdf = Bytes::from(pdf);
+
+            let mut cache = PDF_CACHE.lock().unwrap();
+            if cache.len() >= MAX_CACHED_PDFS && !cache.contains_key(&cache_key) {
+                // Not worth an LRU, any entry will do.
+                if let Some(evicted) = cache.keys().next().cloned() {
+                    cache.remove(&evicted);
+                }
+            }
+            cache.insert(cache_key, (version, pdf.clone()));
+            pdf
+        }
+    };
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/pdf".to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("inline; filename=\"{file_name}\""),
+            ),
+        ],
+        pdf,
+    ))
+}
+
+/// Recipe names may contain any letter, but header values should stick to ASCII.
+fn pdf_file_name(recipe_name: &str) -> String {
+    let stem: String = recipe_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{stem}.pdf")
+}
+
+const PAGE_WIDTH: Mm = Mm(210.0);
+const PAGE_HEIGHT: Mm = Mm(297.0);
+const MARGIN: f32 = 20.0;
+/// Roughly what fits between the margins in the body font size.
+const WRAP_AT: usize = 90;
+
+/// Writes lines top to bottom, starting new pages as needed.
+struct Writer {
+    doc: PdfDocumentReference,
+    layer: printpdf::PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+}
+
+impl Writer {
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        // Line height of 1.4em, converted from points to millimeters.
+        let height = size * 1.4 * 0.3528;
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "Layer 1");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT.0 - MARGIN;
+        }
+        self.y -= height;
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer
+            .use_text(text, size, Mm(MARGIN), Mm(self.y), font);
+    }
+
+    fn paragraph(&mut self, text: &str, size: f32) {
+        for line in wrap(text, WRAP_AT) {
+            self.line(&line, size, false);
+        }
+    }
+
+    fn gap(&mut self) {
+        self.y -= 4.0;
+    }
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Lays out the recipe on A4 pages. This is CPU-bound, call it on a blocking thread.
+///
+/// Gives up between sections once `cancel` is cancelled.
+pub fn render_recipe_pdf(
+    recipe: &PrintableRecipe,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<u8>> {
+    let check_cancelled = || {
+        anyhow::ensure!(
+            !cancel.is_cancelled(),
+            "Rendering the recipe PDF was cancelled"
+        );
+        Ok(())
+    };
+    let (doc, page, layer) = PdfDocument::new(&recipe.name, PAGE_WIDTH, PAGE_HEIGHT, "Layer 1");
+    let regular = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .context("Failed to add font")?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .context("Failed to add font")?;
+    let layer = doc.get_page(page).get_layer(layer);
+    let mut writer = Writer {
+        doc,
+        layer,
+        regular,
+        bold,
+        y: PAGE_HEIGHT.0 - MARGIN,
+    };
+
+    writer.line(&recipe.name, 22.0, true);
+    writer.line(
+        &format!(
+            "{} | prep {} min | cook {} min",
+            recipe.cuisine, recipe.prep_time, recipe.cook_time
+        ),
+        10.0,
+        false,
+    );
+    writer.gap();
+    writer.paragraph(&recipe.description, 11.0);
+
+    check_cancelled()?;
+    writer.gap();
+    writer.line("Ingredients", 14.0, true);
+    for (name, quantity, unit) in &recipe.ingredients {
+        writer.paragraph(&format!("- {quantity} {unit} {name}"), 11.0);
+    }
+
+    check_cancelled()?;
+    writer.gap();
+    writer.line("Steps", 14.0, true);
+    for (i, step) in recipe.steps.iter().enumerate() {
+        check_cancelled()?;
+        writer.paragraph(&format!("{}. {step}", i + 1), 11.0);
+    }
+
+    if let Some(nutrition) = &recipe.nutrition {
+        writer.gap();
+        writer.line("Nutrition", 14.0, true);
+        writer.line(&format!("{:.0} kcal", nutrition.calories), 11.0, false);
+        writer.line(
+            &format!(
+                "protein {:.1} g | fat {:.1} g | carbs {:.1} g | sugar {:.1} g | fiber {:.1} g",
+                nutrition.protein,
+                nutrition.fat,
+                nutrition.carbohydrate,
+                nutrition.sugar,
+                nutrition.fiber
+            ),
+            10.0,
+            false,
+        );
+    }
+
+    // Saving compresses the pages, the most expensive part.
+    check_cancelled()?;
+    writer
+        .doc
+        .save_to_bytes()
+        .context("Failed to write recipe PDF")
+}
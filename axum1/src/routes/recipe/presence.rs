@@ -0,0 +1,164 @@
+use anyhow::Context;
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use sqlx::PgConnection;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, MaybeAuthUser},
+    org::Org,
+    sse::{Notification, PresenceStatus},
+    state::AppState,
+};
+
+use super::slug::RecipeName;
+
+struct PresenceRecipe {
+    id: uuid::Uuid,
+    slug: String,
+}
+
+/// The recipe, if the user can see it.
+async fn find_recipe(
+    conn: &mut PgConnection,
+    org: Org,
+    name: &str,
+    user_id: Option<uuid::Uuid>,
+) -> Result<PresenceRecipe, ApiError> {
+    sqlx::query_as!(
+        PresenceRecipe,
+        r#"
+        SELECT id, slug FROM recipes
+        WHERE org_id = $1 AND name = $2 AND NOT hidden
+          AND (visibility <> 'private' OR creator_id = $3)
+        "#,
+        *org,
+        name,
+        user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)
+}
+
+async fn display_names(
+    conn: &mut PgConnection,
+    user_ids: &[uuid::Uuid],
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT name FROM users WHERE user_id = ANY($1) ORDER BY name",
+        user_ids
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// Drops the viewers who stopped sending heartbeats and announces that they left. Returns the
+/// ones still present.
+async fn present_users(
+    state: &AppState,
+    conn: &mut PgConnection,
+    recipe: &PresenceRecipe,
+) -> Result<Vec<uuid::Uuid>, ApiError> {
+    let (present, expired) = state
+        .presence
+        .list(recipe.id)
+        .await
+        .context("Failed to read the presence of a recipe")?;
+    if !expired.is_empty() {
+        for name in display_names(conn, &expired).await? {
+            let _ = state.tx.send(Notification::presence(
+                recipe.slug.clone(),
+                name,
+                PresenceStatus::Left,
+            ));
+        }
+    }
+    Ok(present)
+}
+
+/// The display names of the users looking at the recipe right now.
+#[tracing::instrument(skip(state, conn, maybe_auth_user))]
+pub async fn list_presence(
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    maybe_auth_user: MaybeAuthUser,
+    RecipeName(name): RecipeName,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let user_id = maybe_auth_user.into_inner().as_deref().copied();
+    let recipe = find_recipe(&mut conn, org, &name, user_id).await?;
+    let present = present_users(&state, &mut conn, &recipe).await?;
+    Ok(Json(display_names(&mut conn, &present).await?))
+}
+
+/// Marks the user present on the recipe, announcing them if they just joined. Clients send this
+/// periodically, more often than `presence_timeout_seconds`, while the recipe is open.
+#[tracing::instrument(skip(state, conn))]
+pub async fn heartbeat(
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    RecipeName(name): RecipeName,
+) -> Result<StatusCode, ApiError> {
+    let recipe = find_recipe(&mut conn, org, &name, Some(*auth_user)).await?;
+    let joined = state
+        .presence
+        .heartbeat(recipe.id, *auth_user)
+        .await
+        .context("Failed to record a presence heartbeat")?;
+    if joined {
+        announce(
+            &state,
+            &mut conn,
+            &recipe,
+            *auth_user,
+            PresenceStatus::Joined,
+        )
+        .await?;
+    }
+    // Every viewer sends heartbeats, so the ones who went away are noticed soon after they
+    // time out.
+    present_users(&state, &mut conn, &recipe).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Marks the user gone from the recipe, without waiting for the timeout.
+#[tracing::instrument(skip(state, conn))]
+pub async fn leave(
+    State(state): State<AppState>,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    RecipeName(name): RecipeName,
+) -> Result<StatusCode, ApiError> {
+    let recipe = find_recipe(&mut conn, org, &name, Some(*auth_user)).await?;
+    let left = state
+        .presence
+        .leave(recipe.id, *auth_user)
+        .await
+        .context("Failed to remove a presence")?;
+    if left {
+        announce(&state, &mut conn, &recipe, *auth_user, PresenceStatus::Left).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn announce(
+    state: &AppState,
+    conn: &mut PgConnection,
+    recipe: &PresenceRecipe,
+    user_id: uuid::Uuid,
+    status: PresenceStatus,
+) -> Result<(), ApiError> {
+    for name in display_names(conn, &[user_id]).await? {
+        // Nobody may be listening.
+        let _ = state
+            .tx
+            .send(Notification::presence(recipe.slug.clone(), name, status));
+    }
+    Ok(())
+}
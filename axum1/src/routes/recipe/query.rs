@@ -0,0 +1,262 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::request::Parts,
+};
+use sqlx::{postgres::PgRow, FromRow, PgConnection, Postgres, QueryBuilder};
+
+use crate::{
+    config::SearchSettings,
+    error::ApiError,
+    org::Org,
+    pagination::Pagination,
+    routes::ingredient::{allergen::Allergen, diet::Diet},
+    state::AppState,
+};
+
+use super::helpers::TypeByTime;
+
+/// The filters of the recipe listings, from the query string. They're all optional and combine
+/// with `AND`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct RecipeFilters {
+    /// Matches the name or the description, by trigram similarity. Results are ranked by it.
+    pub q: Option<String>,
+    /// Only recipes that surely fit the diet, see [`dietary_profile`].
+    ///
+    /// [`dietary_profile`]: crate::routes::ingredient::diet::dietary_profile
+    pub diet: Option<Diet>,
+    /// Only recipes that are surely free of the allergen, see [`allergen_profile`].
+    ///
+    /// [`allergen_profile`]: crate::routes::ingredient::allergen::allergen_profile
+    pub exclude_allergen: Option<Allergen>,
+    /// The most the preparation and the cooking may take together, in minutes.
+    pub max_minutes: Option<i32>,
+    pub meal_type: Option<TypeByTime>,
+    /// The name of the cuisine.
+    pub cuisine: Option<String>,
+}
+
+impl FromRef<AppState> for SearchSettings {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.borrow().search.clone()
+    }
+}
+
+/// The [`RecipeFilters`] of a listing or search request, within the configured limits.
+///
+/// The number of query parameters is checked before they're deserialized, and the length of `q`
+/// before it reaches the database, so pathological requests never cost more than parsing.
+#[derive(Debug, Clone)]
+pub struct SearchFilters(pub RecipeFilters);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SearchFilters
+where
+    S: Send + Sync,
+    SearchSettings: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = SearchSettings::from_ref(state);
+        let values = parts.uri.query().map_or(0, |query| {
+            query.split('&').filter(|p| !p.is_empty()).count()
+        });
+        if values > settings.max_filter_values() {
+            return Err(ApiError::SearchLimitExceeded {
+                key: "filters",
+                reason: format!(
+                    "at most {} query parameters are allowed",
+                    settings.max_filter_values()
+                ),
+            });
+        }
+
+        let Query(filters) = Query::<RecipeFilters>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::BadRequest)?;
+        if filters
+            .q
+            .as_ref()
+            .is_some_and(|q| q.chars().count() > settings.max_query_length())
+        {
+            return Err(ApiError::SearchLimitExceeded {
+                key: "q",
+                reason: format!(
+                    "must be at most {} characters long",
+                    settings.max_query_length()
+                ),
+            });
+        }
+
+        Ok(Self(filters))
+    }
+}
+
+/// Builds the queries of a recipe listing, so the page and the total are always counted with the
+/// same filters.
+///
+/// Only visible recipes of the organization are listed: the public ones, and for a signed-in
+/// viewer their own. Admins see every recipe. Every value is bound as a parameter, only fixed SQL
+/// is pushed to the query.
+#[derive(Debug, Clone)]
+pub struct RecipeQuery<'a> {
+    org: Org,
+    filters: &'a RecipeFilters,
+    viewer: Option<uuid::Uuid>,
+}
+
+impl<'a> RecipeQuery<'a> {
+    /// Lists what anyone can see, see [`Self::viewer`] for more.
+    pub fn new(org: Org, filters: &'a RecipeFilters) -> Self {
+        Self {
+            org,
+            filters,
+            viewer: None,
+        }
+    }
+
+    /// Also lists the recipes the signed-in user can see, but others can't.
+    pub fn viewer(mut self, user_id: Option<uuid::Uuid>) -> Self {
+        self.viewer = user_id;
+        self
+    }
+
+    /// A page of the matching recipes. `columns` is the select list, over `recipes r`.
+    pub fn select(&self, columns: &str, pagination: Pagination) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT ");
+        query.push(columns).push(" FROM recipes r");
+        self.push_filters(&mut query);
+
+        query.push(" ORDER BY ");
+        if let Some(q) = self.search() {
+            query
+                .push("similarity(r.name, ")
+                .push_bind(q.to_owned())
+                .push(") DESC, word_similarity(")
+                .push_bind(q.to_owned())
+                .push(", r.description) DESC, ");
+        }
+        query
+            .push("r.name LIMIT ")
+            .push_bind(pagination.per_page)
+            .push(" OFFSET ")
+            .push_bind(pagination.offset());
+        query
+    }
+
+    /// The number of matching recipes, on all pages.
+    pub fn count(&self) -> QueryBuilder<'static, Postgres> {
+        let mut query = QueryBuilder::new("SELECT COUNT(*) FROM recipes r");
+        self.push_filters(&mut query);
+        query
+    }
+
+    pub async fn fetch_page<T>(
+        &self,
+        conn: &mut PgConnection,
+        columns: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<T>, sqlx::Error>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+    {
+        self.select(columns, pagination)
+            .build_query_as()
+            .fetch_all(conn)
+            .await
+    }
+
+    pub async fn fetch_count(&self, conn: &mut PgConnection) -> Result<i64, sqlx::Error> {
+        self.count().build_query_scalar().fetch_one(conn).await
+    }
+
+    fn search(&self) -> Option<&str> {
+        self.filters
+            .q
+            .as_deref()
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+    }
+
+    fn push_filters(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        query
+            .push(" WHERE NOT r.hidden AND r.org_id = ")
+            .push_bind(*self.org);
+
+        match self.viewer {
+            Some(user_id) => {
+                query
+                    .push(" AND (r.visibility = 'public' OR r.creator_id = ")
+                    .push_bind(user_id)
+                    .push(" OR EXISTS (SELECT 1 FROM users u WHERE u.user_id = ")
+                    .push_bind(user_id)
+                    .push(" AND (u.is_admin OR u.is_super_admin)))");
+            }
+            None => {
+                query.push(" AND r.visibility = 'public'");
+            }
+        }
+
+        if let Some(q) = self.search() {
+            query
+                .push(" AND (")
+                .push_bind(q.to_owned())
+                .push(" <% r.name OR ")
+                .push_bind(q.to_owned())
+                .push(" <% r.description)");
+        }
+        if let Some(diet) = self.filters.diet {
+            // This must agree with `dietary_profile`: a recipe only fits a diet if it has
+            // ingredients, and all of them are known to fit it. `IS NOT TRUE` treats the unknown
+            // (NULL) flags as not fitting.
+            query.push(
+                " AND EXISTS (SELECT 1 FROM ingredients_to_recipes ir WHERE ir.recipe_id = r.id) \
+                 AND NOT EXISTS (\
+                    SELECT 1 FROM ingredients_to_recipes ir \
+                    INNER JOIN ingredients i ON i.id = ir.ingredient_id \
+                    WHERE ir.recipe_id = r.id AND (",
+            );
+            query.push(match diet {
+                Diet::Vegan => "i.vegan",
+                Diet::Vegetarian => "i.vegetarian",
+                Diet::GlutenFree => "i.gluten_free",
+                Diet::NutFree => "NOT i.contains_nuts",
+            });
+            query.push(") IS NOT TRUE)");
+        }
+        if let Some(allergen) = self.filters.exclude_allergen {
+            // This must agree with `AllergenProfile::is_free_of`: unchecked (NULL) ingredients
+            // may contain anything, so they rule the recipe out too.
+            query
+                .push(
+                    " AND EXISTS (SELECT 1 FROM ingredients_to_recipes ir WHERE ir.recipe_id = r.id) \
+                     AND NOT EXISTS (\
+                        SELECT 1 FROM ingredients_to_recipes ir \
+                        INNER JOIN ingredients i ON i.id = ir.ingredient_id \
+                        WHERE ir.recipe_id = r.id AND (i.allergens IS NULL OR ",
+                )
+                .push_bind(allergen)
+                .push(" = ANY(i.allergens)))");
+        }
+        if let Some(max_minutes) = self.filters.max_minutes {
+            query
+                .push(" AND r.prep_time + r.cook_time <= ")
+                .push_bind(max_minutes);
+        }
+        if let Some(meal_type) = &self.filters.meal_type {
+            query
+                .push(" AND r.meal_type = ")
+                .push_bind(meal_type.clone());
+        }
+        if let Some(cuisine) = &self.filters.cuisine {
+            query
+                .push(
+                    " AND EXISTS (SELECT 1 FROM cuisines c WHERE c.id = r.cuisine_id AND c.name = ",
+                )
+                .push_bind(cuisine.clone())
+                .push(")");
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use sqlx::PgConnection;
+
+use crate::{error::ApiError, org::Org};
+
+/// Looks up the ids of the ingredients a recipe refers to by name, rejecting the request with
+/// `422` listing every name that isn't an ingredient of the org.
+///
+/// The found ingredients are locked until the transaction ends, so they can't be deleted between
+/// the check and inserting the references.
+pub async fn resolve_ingredient_ids(
+    conn: &mut PgConnection,
+    org: Org,
+    names: &[String],
+) -> Result<HashMap<String, uuid::Uuid>, ApiError> {
+    let rows = sqlx::query!(
+        r#"
+        WITH known AS (
+            SELECT id, name FROM ingredients
+            WHERE org_id = $2 AND name = ANY($1)
+            FOR SHARE
+        )
+        SELECT requested.name AS "name!", known.id AS "id?"
+        FROM UNNEST($1::TEXT[]) AS requested(name)
+        LEFT JOIN known ON known.name = requested.name
+        "#,
+        names,
+        *org
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut ids = HashMap::with_capacity(rows.len());
+    let mut unknown = Vec::new();
+    for row in rows {
+        match row.id {
+            Some(id) => {
+                ids.insert(row.name, id);
+            }
+            None => unknown.push(row.name),
+        }
+    }
+
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        unknown.dedup();
+        return Err(ApiError::unprocessable_entity(unknown.into_iter().map(
+            |name| ("ingredient-name", format!("{name} is not an ingredient")),
+        )));
+    }
+    Ok(ids)
+}
@@ -0,0 +1,94 @@
+use axum::{extract::State, http::StatusCode};
+use axum_extra::extract::Form;
+use sqlx::Acquire;
+use validator::Validate;
+
+use crate::{
+    error::ApiError,
+    extractors::{AuthUser, DatabaseConnection, MeiliConnection},
+    org::Org,
+    search::{sync_recipe_document, RecipeSearchSimple},
+    state::AppState,
+};
+
+use super::{favorite::visible_recipe_id, slug::RecipeName};
+
+#[derive(Debug, serde::Deserialize, validator::Validate)]
+pub struct Report {
+    #[validate(length(
+        min = 1,
+        max = 1000,
+        message = "should be at least 1 character, but no more than 1000"
+    ))]
+    reason: String,
+}
+
+/// Flags a recipe as inappropriate. Reporting the same recipe again is a no-op.
+/// Once enough distinct users report a recipe, it's hidden until a moderator reviews it.
+#[tracing::instrument(skip(conn, config, meili, auth_user))]
+pub async fn report_recipe(
+    State(AppState { mut config, .. }): State<AppState>,
+    MeiliConnection(meili): MeiliConnection,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    auth_user: AuthUser,
+    RecipeName(name): RecipeName,
+    Form(report): Form<Report>,
+) -> Result<StatusCode, ApiError> {
+    report
+        .validate()
+        .map_err(ApiError::unprocessable_entity_from_validation_errors)?;
+
+    let threshold = config
+        .borrow_and_update()
+        .application_settings
+        .report_hide_threshold();
+
+    let mut tx = conn.begin().await?;
+
+    // Recipes the reporter can't open don't exist as far as they're concerned.
+    let recipe_id = visible_recipe_id(&mut tx, org, Some(*auth_user), &name)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO reports (recipe_id, reporter_id, reason)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (recipe_id, reporter_id) DO NOTHING
+        "#,
+        recipe_id,
+        *auth_user,
+        report.reason
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Ok(StatusCode::OK);
+    }
+
+    let hidden = sqlx::query_as!(
+        RecipeSearchSimple,
+        r#"
+        UPDATE recipes SET hidden = TRUE
+        WHERE id = $1 AND NOT hidden
+            AND (SELECT COUNT(*) FROM reports WHERE recipe_id = $1) >= $2
+        RETURNING id, name, description
+        "#,
+        recipe_id,
+        threshold
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    // The frontend searches the index directly, so it has to go from there too.
+    if let Some(recipe) = hidden {
+        tokio::spawn(sync_recipe_document(meili, recipe, false));
+    }
+
+    Ok(StatusCode::CREATED)
+}
@@ -0,0 +1,41 @@
+use crate::{config::ApplicationSettings, error::ApiError};
+
+/// The most ingredients and steps a recipe may have, so a single recipe can't make computing the
+/// nutrition or rendering the PDF arbitrarily slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecipeSizeLimits {
+    pub max_ingredients: usize,
+    pub max_steps: usize,
+}
+
+impl RecipeSizeLimits {
+    pub fn from_settings(settings: &ApplicationSettings) -> Self {
+        Self {
+            max_ingredients: settings.max_recipe_ingredients(),
+            max_steps: settings.max_recipe_steps(),
+        }
+    }
+
+    /// Rejects a recipe over the limits, naming every limit that was exceeded.
+    pub fn check(&self, ingredients: usize, steps: usize) -> Result<(), ApiError> {
+        let mut errors = Vec::new();
+        if ingredients > self.max_ingredients {
+            errors.push((
+                "ingredients",
+                format!("at most {} ingredients are allowed", self.max_ingredients),
+            ));
+        }
+        if steps > self.max_steps {
+            errors.push((
+                "steps",
+                format!("at most {} steps are allowed", self.max_steps),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::unprocessable_entity(errors))
+        }
+    }
+}
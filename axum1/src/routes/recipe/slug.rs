@@ -0,0 +1,123 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::request::Parts,
+};
+use sqlx::PgExecutor;
+
+use crate::{error::ApiError, org::Org, state::AppState};
+
+/// The recipe a slug leads to.
+struct SlugTarget {
+    name: String,
+    slug: String,
+}
+
+/// Finds the recipe with this slug, or a former one from `recipe_slug_history`. The current slug
+/// of a recipe wins over a former slug of another one. Failing both, `slug` is slugified, so
+/// e.g. `Tomato Soup` finds `tomato-soup`.
+async fn find_recipe_by_slug<'c>(
+    conn: impl PgExecutor<'c>,
+    org: Org,
+    slug: &str,
+) -> Result<Option<SlugTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        SlugTarget,
+        r#"
+        SELECT name AS "name!", slug AS "slug!" FROM (
+            SELECT r.name, r.slug, 0 AS priority FROM recipes r
+            WHERE r.org_id = $1 AND r.slug = $2
+            UNION ALL
+            SELECT r.name, r.slug, 1 AS priority FROM recipe_slug_history h
+            INNER JOIN recipes r ON r.id = h.recipe_id
+            WHERE h.org_id = $1 AND h.slug = $2
+            UNION ALL
+            SELECT r.name, r.slug, 2 AS priority FROM recipes r
+            WHERE r.org_id = $1 AND r.slug = slugify($2)
+        ) found
+        ORDER BY priority
+        LIMIT 1
+        "#,
+        *org,
+        slug
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Finds the current name of the recipe with this slug, see [`find_recipe_by_slug`].
+pub async fn resolve_recipe_slug<'c>(
+    conn: impl PgExecutor<'c>,
+    org: Org,
+    slug: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(find_recipe_by_slug(conn, org, slug)
+        .await?
+        .map(|target| target.name))
+}
+
+/// Finds the current slug of the recipe with this slug, in its canonical casing. See
+/// [`find_recipe_by_slug`].
+pub async fn canonical_recipe_slug<'c>(
+    conn: impl PgExecutor<'c>,
+    org: Org,
+    slug: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(find_recipe_by_slug(conn, org, slug)
+        .await?
+        .map(|target| target.slug))
+}
+
+/// The first free name like `name 2`, numbered the same way as `unique_recipe_slug` numbers
+/// slugs, to offer when `name` is taken.
+pub async fn suggest_recipe_name<'c>(
+    conn: impl PgExecutor<'c>,
+    org: Org,
+    name: &str,
+) -> Result<String, sqlx::Error> {
+    // With `n` recipes in the organization, one of the first `n + 1` suffixes is free.
+    sqlx::query_scalar!(
+        r#"
+        SELECT $2 || ' ' || suffix AS "name!"
+        FROM generate_series(2, (SELECT COUNT(*) FROM recipes WHERE org_id = $1) + 2) suffix
+        WHERE NOT EXISTS (
+            SELECT 1 FROM recipes WHERE org_id = $1 AND name = $2 || ' ' || suffix
+        )
+        ORDER BY suffix
+        LIMIT 1
+        "#,
+        *org,
+        name
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// The recipe addressed by the `:slug` path segment, resolved to its current name. Rejects
+/// unknown slugs with `404`.
+///
+/// Handlers keep working with the name, which is just as unique within the organization.
+#[derive(Debug, Clone)]
+pub struct RecipeName(pub String);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RecipeName
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(slug) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::NotFound)?;
+        let org = Org::from_request_parts(parts, state).await?;
+        let AppState { db_pool, .. } = AppState::from_ref(state);
+
+        resolve_recipe_slug(&db_pool, org, &slug)
+            .await?
+            .map(Self)
+            .ok_or(ApiError::NotFound)
+    }
+}
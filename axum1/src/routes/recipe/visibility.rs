@@ -0,0 +1,70 @@
+use axum::{extract::Json, http::StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ApiError,
+    extractors::{DatabaseConnection, MeiliConnection},
+    org::Org,
+    search::{sync_recipe_document, RecipeSearchSimple},
+};
+
+use super::{extractors::RecipeCreator, slug::RecipeName};
+
+/// Who can see a recipe. Its author and the admins always can.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[sqlx(rename_all = "snake_case", type_name = "recipe_visibility")]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Nobody else, like a draft. New recipes start like this.
+    #[default]
+    Private,
+    /// Anyone who knows its slug or name, but it's left out of the listings and the search.
+    Unlisted,
+    /// Everyone.
+    Public,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetVisibility {
+    visibility: Visibility,
+}
+
+/// Changes who can see the recipe. Only its author may.
+#[tracing::instrument(skip(meili, conn))]
+pub async fn set_visibility(
+    MeiliConnection(meili): MeiliConnection,
+    DatabaseConnection(mut conn): DatabaseConnection,
+    org: Org,
+    _creator: RecipeCreator,
+    RecipeName(name): RecipeName,
+    Json(body): Json<SetVisibility>,
+) -> Result<StatusCode, ApiError> {
+    let recipe = sqlx::query!(
+        r#"
+        UPDATE recipes SET visibility = $1
+        WHERE name = $2 AND org_id = $3
+        RETURNING id, name, description, hidden
+        "#,
+        body.visibility as _,
+        name,
+        *org
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    // Off the request path, the periodic indexing catches up if MeiliSearch can't be reached now.
+    // A recipe hidden by moderators stays out of search until they restore it.
+    let document = RecipeSearchSimple {
+        id: recipe.id,
+        name: recipe.name,
+        description: recipe.description,
+    };
+    tokio::spawn(sync_recipe_document(
+        meili,
+        document,
+        body.visibility == Visibility::Public && !recipe.hidden,
+    ));
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,91 @@
+//! Sandboxed requests, for demos and for exercising write endpoints against a shared
+//! environment without keeping the changes.
+//!
+//! A request with `X-Sandbox: true` runs in a database transaction that's rolled back once the
+//! response is ready. Handlers get that transaction from [`DatabaseConnection`], and their own
+//! transactions become savepoints in it.
+//!
+//! Only enabled with `sandbox_mode`, and it's not meant for production:
+//! - Only what goes through [`DatabaseConnection`] is undone. Queries on the pool itself (e.g.
+//!   resolving the organization or the recipe slug), Redis (sessions, recently viewed recipes),
+//!   uploaded files, search indexing, emails and notifications sent to other users all happen
+//!   for real.
+//! - Every sandboxed request holds on to a connection until it's done, so a few of them can use
+//!   up the small pool.
+//!
+//! [`DatabaseConnection`]: crate::extractors::DatabaseConnection
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use sqlx::{Postgres, Transaction};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::{error::ApiError, state::AppState};
+
+pub const SANDBOX_HEADER: &str = "x-sandbox";
+
+/// The transaction of a sandboxed request, in the request extensions.
+#[derive(Clone)]
+pub struct SandboxTransaction(Arc<Mutex<Transaction<'static, Postgres>>>);
+
+impl SandboxTransaction {
+    /// There's only one transaction per request, so a second extractor asking for it while the
+    /// first still holds it is a bug. Waiting would deadlock, so this fails instead.
+    pub fn lock(&self) -> Result<OwnedMutexGuard<Transaction<'static, Postgres>>, ApiError> {
+        Arc::clone(&self.0)
+            .try_lock_owned()
+            .map_err(|_| anyhow::anyhow!("The sandbox transaction is already in use").into())
+    }
+}
+
+/// Runs requests with `X-Sandbox: true` in a transaction that's rolled back at the end, and
+/// marks their response with `X-Sandbox: rolled-back`.
+///
+/// If the sandbox isn't enabled, such requests are rejected with `400 Bad Request`, rather than
+/// making changes the client expects to be undone.
+pub async fn sandbox(
+    State(AppState {
+        db_pool, config, ..
+    }): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let requested = request
+        .headers()
+        .get(SANDBOX_HEADER)
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"true"));
+    if !requested {
+        return Ok(next.run(request).await);
+    }
+    if !config.borrow().application_settings.sandbox_mode() {
+        return Err(ApiError::BadRequest);
+    }
+
+    let tx = Arc::new(Mutex::new(db_pool.begin().await?));
+    request
+        .extensions_mut()
+        .insert(SandboxTransaction(Arc::clone(&tx)));
+
+    let mut response = next.run(request).await;
+
+    // A task spawned by the handler may still hold it, then it's rolled back when dropped.
+    if let Ok(tx) = Arc::try_unwrap(tx) {
+        if let Err(e) = tx.into_inner().rollback().await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to roll back a sandboxed request."
+            );
+        }
+    }
+    response
+        .headers_mut()
+        .insert(SANDBOX_HEADER, HeaderValue::from_static("rolled-back"));
+    Ok(response)
+}
@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::routes::{
+    auth::{Credentials, Register, ResetPassword, UpdatePassword},
+    ingredient::{
+        suggestion::{IngredientSuggestion, SuggestedIngredient},
+        FoodCategory, Ingredient, UpgradeIngredient,
+    },
+};
+
+/// The JSON Schemas of the public request and response types, keyed by the type name.
+pub fn schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        ("Credentials", schema_for!(Credentials)),
+        ("Register", schema_for!(Register)),
+        ("UpdatePassword", schema_for!(UpdatePassword)),
+        ("ResetPassword", schema_for!(ResetPassword)),
+        ("FoodCategory", schema_for!(FoodCategory)),
+        ("Ingredient", schema_for!(Ingredient)),
+        ("UpgradeIngredient", schema_for!(UpgradeIngredient)),
+        ("IngredientSuggestion", schema_for!(IngredientSuggestion)),
+        ("SuggestedIngredient", schema_for!(SuggestedIngredient)),
+    ]
+}
+
+/// Writes every schema to `<out_dir>/<TypeName>.json`, creating `out_dir` if needed.
+/// Returns the paths of the written files.
+pub fn dump_schemas(out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(out_dir)?;
+    schemas()
+        .into_iter()
+        .map(|(name, schema)| {
+            let path = out_dir.join(format!("{name}.json"));
+            std::fs::write(&path, serde_json::to_string_pretty(&schema)?)?;
+            Ok(path)
+        })
+        .collect()
+}
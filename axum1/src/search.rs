@@ -1,7 +1,48 @@
+use std::{sync::Mutex, time::Instant};
+
+use chrono::{DateTime, Utc};
 use meilisearch_sdk::client::Client;
-use sqlx::{Pool, Postgres};
+use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
+use sqlx::{PgConnection, Pool, Postgres};
+use tracing::Instrument;
+
+use crate::{
+    config::{MeiliConfig, Settings},
+    org::Org,
+    pagination::Pagination,
+    queue::get_connection_pool,
+    routes::{
+        ingredient::FoodCategory,
+        recipe::query::{RecipeFilters, RecipeQuery},
+    },
+};
+
+pub fn meili_client(meili: &MeiliConfig) -> anyhow::Result<Client> {
+    Ok(Client::new(
+        &meili.url,
+        Some(meili.master_key.expose_secret()),
+    )?)
+}
+
+/// The outcome of the recent indexing runs in this process.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct IndexingStatus {
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_error: Option<IndexingError>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IndexingError {
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
 
-use crate::{config::Settings, queue::get_connection_pool, routes::ingredient::FoodCategory};
+static INDEXING_STATUS: Lazy<Mutex<IndexingStatus>> = Lazy::new(Default::default);
+
+pub fn indexing_status() -> IndexingStatus {
+    INDEXING_STATUS.lock().unwrap().clone()
+}
 
 pub async fn run_meili_indexer_until_stopped(
     mut config: tokio::sync::watch::Receiver<Settings>,
@@ -9,7 +50,7 @@ pub async fn run_meili_indexer_until_stopped(
     let Settings {
         database, meili, ..
     } = config.borrow_and_update().clone();
-    let meili_client = Client::new(meili.url, Some(meili.master_key))?;
+    let meili_client = meili_client(&meili)?;
     let pool = get_connection_pool(&database);
     // TODO: These defaults are hidden here, maybe there's a better place for them?
     let retry_seconds = meili.retry_seconds.unwrap_or(60);
@@ -18,11 +59,31 @@ pub async fn run_meili_indexer_until_stopped(
     let mut current_retries = 0;
     // I don't really know whether this is a good idea yet. Maybe the whole MeiliSearch indexing should be its own crate.
     loop {
-        match run_meili_indexer(&pool, &meili_client).await {
+        let span = tracing::info_span!(
+            "meili_indexing",
+            task.attempt = current_retries + 1,
+            task.duration_ms = tracing::field::Empty,
+        );
+        let started = Instant::now();
+        let outcome = run_meili_indexer(&pool, &meili_client)
+            .instrument(span.clone())
+            .await;
+        let elapsed = started.elapsed();
+        span.record("task.duration_ms", elapsed.as_millis() as u64);
+        metrics::histogram!("meili_indexing_duration_seconds").record(elapsed.as_secs_f64());
+
+        match outcome {
             Ok(_) => {
+                metrics::counter!("meili_indexing_runs_total").increment(1);
+                INDEXING_STATUS.lock().unwrap().last_success_at = Some(Utc::now());
                 tokio::time::sleep(std::time::Duration::from_secs(indexing_interval_seconds)).await;
             }
             Err(e) => {
+                metrics::counter!("meili_indexing_failures_total").increment(1);
+                INDEXING_STATUS.lock().unwrap().last_error = Some(IndexingError {
+                    message: format!("{e:#}"),
+                    at: Utc::now(),
+                });
                 if max_retries > current_retries {
                     current_retries += 1;
                     let left = max_retries - current_retries;
@@ -48,9 +109,43 @@ pub async fn run_meili_indexer(
     meili_indexing_task(meili_client, &ingredient_records, "ingredients").await?;
     meili_indexing_task(meili_client, &cuisine_records, "cuisines").await?;
     meili_indexing_task(meili_client, &recipe_records, "recipes").await?;
+    // Documents are only ever added above, the recipes that aren't public anymore, or were hidden
+    // by moderators, are removed.
+    let non_public_ids = get_non_public_recipe_ids(pool).await?;
+    if !non_public_ids.is_empty() {
+        meili_client
+            .index("recipes")
+            .delete_documents(&non_public_ids)
+            .await?
+            .wait_for_completion(meili_client, None, None)
+            .await?;
+    }
     Ok(())
 }
 
+/// Adds the recipe to the `recipes` index if it's public, removes it otherwise. Recipes hidden by
+/// moderators count as not public. The periodic indexing does the same for every recipe, this
+/// applies a change to one of them right away.
+pub async fn sync_recipe_document(client: Client, recipe: RecipeSearchSimple, public: bool) {
+    let synced = async {
+        let index = client.index("recipes");
+        if public {
+            index.add_documents(&[&recipe], None).await?;
+        } else {
+            index.delete_document(recipe.id).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    if let Err(e) = synced.await {
+        tracing::warn!(
+            error.message = %e,
+            recipe_id = %recipe.id,
+            "Failed to update the recipe in the search index, the next indexing run will retry"
+        );
+    }
+}
+
+#[tracing::instrument(skip(client, records), fields(documents = records.len()))]
 async fn meili_indexing_task<T: serde::Serialize + Sync + Send>(
     client: &Client,
     records: &[T],
@@ -83,12 +178,13 @@ async fn get_ingredient_records(pool: &Pool<Postgres>) -> anyhow::Result<Vec<Ing
     Ok(records)
 }
 
-async fn get_recipe_records(pool: &Pool<Postgres>) -> anyhow::Result<Vec<RecipeSearchSimple>> {
+/// The recipes to index, the public ones that aren't hidden by moderators.
+pub async fn get_recipe_records(pool: &Pool<Postgres>) -> anyhow::Result<Vec<RecipeSearchSimple>> {
     let mut tx = pool.begin().await?;
     let records = sqlx::query_as!(
         RecipeSearchSimple,
         r#"
-        SELECT id, name, description FROM recipes
+        SELECT id, name, description FROM recipes WHERE visibility = 'public' AND NOT hidden
         "#
     )
     .fetch_all(&mut *tx)
@@ -97,6 +193,15 @@ async fn get_recipe_records(pool: &Pool<Postgres>) -> anyhow::Result<Vec<RecipeS
     Ok(records)
 }
 
+/// The recipes to remove from the index, see [`get_recipe_records`].
+pub async fn get_non_public_recipe_ids(pool: &Pool<Postgres>) -> anyhow::Result<Vec<uuid::Uuid>> {
+    Ok(
+        sqlx::query_scalar!("SELECT id FROM recipes WHERE visibility <> 'public' OR hidden")
+            .fetch_all(pool)
+            .await?,
+    )
+}
+
 async fn get_cuisine_records(pool: &Pool<Postgres>) -> anyhow::Result<Vec<Cuisine>> {
     let mut tx = pool.begin().await?;
     let records = sqlx::query_as!(
@@ -117,11 +222,44 @@ struct Cuisine {
     id: uuid::Uuid,
 }
 
-#[derive(serde::Serialize, sqlx::FromRow)]
-struct RecipeSearchSimple {
-    id: uuid::Uuid,
-    name: String,
-    description: String,
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct RecipeSearchSimple {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub description: String,
+}
+
+/// Searches recipes in Postgres, for when MeiliSearch is unavailable. The results have the same
+/// shape as the documents in the `recipes` index, ordered by their trigram similarity to `query`.
+#[tracing::instrument(skip(conn))]
+pub async fn search_recipes_pg(
+    conn: &mut PgConnection,
+    org: Org,
+    query: &str,
+    pagination: Pagination,
+) -> anyhow::Result<Vec<RecipeSearchSimple>> {
+    let filters = RecipeFilters {
+        q: Some(query.to_owned()),
+        ..Default::default()
+    };
+    let records = RecipeQuery::new(org, &filters)
+        .fetch_page(conn, "r.id, r.name, r.description", pagination)
+        .await?;
+    Ok(records)
+}
+
+/// The number of recipes `search_recipes_pg` finds on all pages.
+#[tracing::instrument(skip(conn))]
+pub async fn count_recipes_pg(
+    conn: &mut PgConnection,
+    org: Org,
+    query: &str,
+) -> anyhow::Result<i64> {
+    let filters = RecipeFilters {
+        q: Some(query.to_owned()),
+        ..Default::default()
+    };
+    Ok(RecipeQuery::new(org, &filters).fetch_count(conn).await?)
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -0,0 +1,81 @@
+//! Keeps cookies off plain HTTP, see `security.require_secure_cookies`.
+//!
+//! The app never terminates TLS itself, so a request only counts as HTTPS if a trusted proxy says
+//! so in `X-Forwarded-Proto`. A browser drops a `Secure` cookie set over plain HTTP anyway, but a
+//! misconfigured proxy would go unnoticed, so the response is refused instead.
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRef, Request, State},
+    http::{header::SET_COOKIE, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{error::ApiError, extractors::TrustedProxies, state::AppState};
+
+pub const FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// The state of [`secure_cookies_only`].
+#[derive(Clone)]
+pub struct SecureCookies {
+    pub required: bool,
+    pub trusted_proxies: TrustedProxies,
+}
+
+impl FromRef<AppState> for SecureCookies {
+    fn from_ref(state: &AppState) -> Self {
+        let config = state.config.borrow();
+        Self {
+            required: config.security.require_secure_cookies(),
+            trusted_proxies: TrustedProxies(config.application_settings.trusted_proxies.clone()),
+        }
+    }
+}
+
+/// Whether the client reached us over HTTPS, as told by the proxy in front of the app. The
+/// closest proxy to the client is the first one in `X-Forwarded-Proto`.
+pub fn is_https(peer: IpAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> bool {
+    if !trusted.contains(&peer) {
+        return false;
+    }
+    headers
+        .get(FORWARDED_PROTO)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+/// Replaces the responses setting cookies over plain HTTP with `403 Forbidden`, if secure cookies
+/// are required. Must wrap the session layer, so it sees the session cookie.
+///
+/// The request is handled first, so e.g. a login still creates a session, but its cookie never
+/// reaches the client.
+pub async fn secure_cookies_only(
+    State(secure_cookies): State<SecureCookies>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !secure_cookies.required {
+        return next.run(request).await;
+    }
+
+    let peer = connect_info
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let https = is_https(peer, request.headers(), &secure_cookies.trusted_proxies);
+    let path = request.uri().path().to_owned();
+
+    let response = next.run(request).await;
+    if https || !response.headers().contains_key(SET_COOKIE) {
+        return response;
+    }
+
+    tracing::error!(
+        %peer,
+        %path,
+        "Refused to set a cookie over plain HTTP. Is the proxy sending `X-Forwarded-Proto`, and is it in `trusted_proxies`?"
+    );
+    ApiError::HttpsRequired.into_response()
+}
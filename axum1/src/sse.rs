@@ -1,39 +1,216 @@
-use std::convert::Infallible;
+use std::{collections::HashSet, convert::Infallible, sync::Arc, time::Duration};
 
 use axum::{
     extract::State,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
     response::{
         sse::{Event, KeepAlive},
-        Sse,
+        IntoResponse, Sse,
     },
 };
 use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::{state::AppState, utils::shutdown_signal};
+use crate::{
+    config::ApplicationSettings, connections::ConnectionGuard, error::ApiError,
+    extractors::MaybeAuthUser, state::AppState, utils::shutdown_signal,
+};
+
+/// The most unread notifications replayed to a user when they connect.
+pub const MAX_REPLAYED: i64 = 50;
+
+/// How long reading the unread notifications may hold up the live ones.
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// Streams the notifications visible to the user.
+///
+/// A signed-in user first gets their unread saved notifications, the latest first (see
+/// [`unread_backlog`]), so nothing sent while they were disconnected is missed. The live ones
+/// follow.
+///
+/// Counts toward the connection limits of the user or guest, `429` over them.
 #[tracing::instrument(skip_all)]
 pub async fn sse_handler(
-    State(AppState { tx: chan, .. }): State<AppState>,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    State(AppState {
+        tx: chan,
+        db_pool,
+        config,
+        connections,
+        ..
+    }): State<AppState>,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let guest_id = maybe_auth_user.guest_id().map(|guest_id| *guest_id);
+    let user_id = maybe_auth_user.into_inner().map(|user| *user);
+    let limits = config.borrow().application_settings.connection_limits();
+    let guard = connections
+        .try_open(user_id.or(guest_id), limits)
+        .ok_or(ApiError::TooManyRequests)?;
     // Create an internal channel which transmits all traffic that's coming from our `chan`.
     let (mut tx, rx) = futures::channel::mpsc::channel::<Result<Event, Infallible>>(16);
 
+    // Subscribe before reading the backlog, so nothing saved in between is missed.
     let mut sub = chan.subscribe();
     tokio::spawn(async move {
         use futures::SinkExt;
 
-        while let Ok(m) = sub.recv().await {
+        let backlog = match user_id {
+            Some(user_id) => replay_backlog(&db_pool, user_id).await,
+            None => Vec::new(),
+        };
+        let replayed: HashSet<_> = backlog.iter().map(|n| n.notification_id).collect();
+        for notification in backlog {
+            let event = Event::default()
+                .event(notification.kind.as_str())
+                .id(notification.notification_id.to_string())
+                .json_data(notification.data())
+                .unwrap();
+            if tx.send(Ok(event)).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let m = match sub.recv().await {
+                Ok(m) => m,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::trace!("SSE client lagged behind by {skipped} notifications");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if !m.is_visible_to(user_id) {
+                continue;
+            }
+            // Saved after we subscribed, but before the backlog was read.
+            if m.notification_id().is_some_and(|id| replayed.contains(&id)) {
+                continue;
+            }
             if let Err(send_error) = tx
                 .send(Ok(Event::default().event(m.name()).json_data(m).unwrap()))
                 .await
             {
                 tracing::trace!("Broadcasting error: {:?}", send_error);
+                // The stream was dropped, the client is gone.
+                break;
             }
         }
     });
 
-    Sse::new(or_until_shutdown(rx)).keep_alive(KeepAlive::default())
+    let keep_alive = keep_alive(&config.borrow().application_settings);
+    let sse = Sse::new(holding(guard, or_until_shutdown(rx)));
+    Ok(match keep_alive {
+        Some(keep_alive) => sse.keep_alive(keep_alive),
+        None => sse,
+    })
+}
+
+/// Keeps the connection counted for as long as the stream lives. The response body owns it, so
+/// it's dropped however the stream ends, with the client disconnecting or the server shutting
+/// down.
+fn holding<S>(guard: ConnectionGuard, stream: S) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    async_stream::stream! {
+        let _guard = guard;
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            yield item;
+        }
+    }
+}
+
+/// The keep-alive comments of the SSE streams, if they aren't disabled. Proxies drop streams
+/// that stay quiet for longer than their idle timeout.
+fn keep_alive(settings: &ApplicationSettings) -> Option<KeepAlive> {
+    let interval = settings.sse_keep_alive_interval()?;
+    let keep_alive = KeepAlive::new().interval(interval);
+    Some(match &settings.sse_keep_alive_text {
+        Some(text) => keep_alive.text(text.as_str()),
+        None => keep_alive,
+    })
+}
+
+/// A saved notification sent again when its recipient connects, see [`unread_backlog`].
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ReplayedNotification {
+    pub notification_id: uuid::Uuid,
+    /// The event name it was sent live with.
+    pub kind: String,
+    pub payload: serde_json::Value,
+}
+
+impl ReplayedNotification {
+    /// What's sent: the notification as it was sent live, along with its id and
+    /// `"replayed": true`, so clients can tell it apart and mark it read.
+    pub fn data(&self) -> serde_json::Value {
+        let mut data = self.payload.clone();
+        if let Some(fields) = data.as_object_mut() {
+            fields.insert(
+                "notification_id".to_owned(),
+                self.notification_id.to_string().into(),
+            );
+            fields.insert("replayed".to_owned(), true.into());
+        }
+        data
+    }
+}
+
+/// The unread notifications saved for the user, the latest first, at most [`MAX_REPLAYED`].
+pub async fn unread_backlog(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+) -> Result<Vec<ReplayedNotification>, sqlx::Error> {
+    sqlx::query_as!(
+        ReplayedNotification,
+        r#"
+        SELECT id AS notification_id, kind, payload
+        FROM notifications
+        WHERE user_id = $1 AND read_at IS NULL
+        ORDER BY created_at DESC, id
+        LIMIT $2
+        "#,
+        user_id,
+        MAX_REPLAYED
+    )
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// The backlog, or nothing if it can't be read in time. The live notifications are more important
+/// than the replayed ones, and those can still be fetched from `GET /me/notifications`.
+async fn replay_backlog(db_pool: &PgPool, user_id: uuid::Uuid) -> Vec<ReplayedNotification> {
+    let backlog = async {
+        let mut conn = db_pool.acquire().await?;
+        unread_backlog(&mut conn, user_id).await
+    };
+    match tokio::time::timeout(REPLAY_TIMEOUT, backlog).await {
+        Ok(Ok(backlog)) => backlog,
+        Ok(Err(e)) => {
+            tracing::warn!(error.message = %e, %user_id, "Failed to read the unread notifications");
+            Vec::new()
+        }
+        Err(_) => {
+            tracing::warn!(%user_id, "Timed out reading the unread notifications");
+            Vec::new()
+        }
+    }
+}
+
+/// Answers `HEAD` like `sse_handler` would, without subscribing to the notifications.
+///
+/// Axum would run the `GET` handler and drop the body, which would start the stream for nothing.
+pub async fn sse_head() -> impl IntoResponse {
+    (
+        [
+            (CONTENT_TYPE, "text/event-stream"),
+            (CACHE_CONTROL, "no-cache"),
+        ],
+        (),
+    )
 }
 
 /// Run a stream until it completes or we receive the shutdown signal.
@@ -66,6 +243,12 @@ where
 #[serde(untagged)]
 pub enum Notification {
     NewRecipe(NewRecipe),
+    SecurityAlert(SecurityAlert),
+    SystemAnnouncement(SystemAnnouncement),
+    IngredientMerged(IngredientMerged),
+    Presence(PresenceChanged),
+    SuggestionApplied(SuggestionApplied),
+    SuggestionDeclined(SuggestionDeclined),
 }
 
 impl Notification {
@@ -73,9 +256,194 @@ impl Notification {
         Self::NewRecipe(NewRecipe { name })
     }
 
+    pub fn security_alert(user_id: uuid::Uuid, reason: SecurityAlertReason) -> Self {
+        Self::SecurityAlert(SecurityAlert {
+            user_id,
+            reason,
+            notification_id: None,
+        })
+    }
+
+    pub fn ingredient_merged(
+        user_id: uuid::Uuid,
+        source: String,
+        target: String,
+        recipes: Vec<String>,
+    ) -> Self {
+        Self::IngredientMerged(IngredientMerged {
+            user_id,
+            source,
+            target,
+            recipes,
+            notification_id: None,
+        })
+    }
+
+    pub fn suggestion_applied(
+        user_id: uuid::Uuid,
+        ingredient: String,
+        is_delete_vote: bool,
+    ) -> Self {
+        Self::SuggestionApplied(SuggestionApplied {
+            user_id,
+            ingredient,
+            is_delete_vote,
+            notification_id: None,
+        })
+    }
+
+    pub fn suggestion_declined(
+        user_id: uuid::Uuid,
+        ingredient: Option<String>,
+        reason: Option<String>,
+    ) -> Self {
+        Self::SuggestionDeclined(SuggestionDeclined {
+            user_id,
+            ingredient,
+            reason,
+            notification_id: None,
+        })
+    }
+
+    pub fn presence(recipe: String, name: String, status: PresenceStatus) -> Self {
+        Self::Presence(PresenceChanged {
+            recipe,
+            name,
+            status,
+        })
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             Self::NewRecipe(_) => "new_recipe",
+            Self::SecurityAlert(_) => "security_alert",
+            Self::SystemAnnouncement(_) => "system_announcement",
+            Self::IngredientMerged(_) => "ingredient_merged",
+            Self::Presence(_) => "presence",
+            Self::SuggestionApplied(_) => "suggestion_applied",
+            Self::SuggestionDeclined(_) => "suggestion_declined",
+        }
+    }
+
+    /// The only user who should receive the notification, or `None` if it's not for a single user.
+    pub fn recipient(&self) -> Option<uuid::Uuid> {
+        match self {
+            Self::NewRecipe(_) | Self::SystemAnnouncement(_) | Self::Presence(_) => None,
+            Self::SecurityAlert(alert) => Some(alert.user_id),
+            Self::IngredientMerged(merged) => Some(merged.user_id),
+            Self::SuggestionApplied(applied) => Some(applied.user_id),
+            Self::SuggestionDeclined(declined) => Some(declined.user_id),
+        }
+    }
+
+    /// Saves a copy for the recipient, so they see it in `GET /me/notifications` even if they're
+    /// not connected right now. The id of the copy is added to what's sent live, so clients can
+    /// mark it read.
+    ///
+    /// Save it in the transaction that caused it, and only send it after that's committed.
+    pub async fn save_for_recipient(
+        mut self,
+        conn: &mut PgConnection,
+    ) -> Result<Self, sqlx::Error> {
+        let Some(user_id) = self.recipient() else {
+            return Ok(self);
+        };
+        let payload = serde_json::to_value(&self).expect("notifications are serializable");
+        let id = sqlx::query_scalar!(
+            "INSERT INTO notifications (user_id, kind, payload) VALUES ($1, $2, $3) RETURNING id",
+            user_id,
+            self.name(),
+            payload
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        match &mut self {
+            Self::SecurityAlert(alert) => alert.notification_id = Some(id),
+            Self::IngredientMerged(merged) => merged.notification_id = Some(id),
+            Self::SuggestionApplied(applied) => applied.notification_id = Some(id),
+            Self::SuggestionDeclined(declined) => declined.notification_id = Some(id),
+            Self::NewRecipe(_) | Self::SystemAnnouncement(_) | Self::Presence(_) => {}
+        }
+        Ok(self)
+    }
+
+    /// Reads back a notification saved with its event name and recipient, like the ones in the
+    /// outbox. The payload alone is ambiguous, and doesn't have the recipient.
+    ///
+    /// Announcements can't be restored, their recipients are never saved along with them.
+    pub fn from_stored(
+        kind: &str,
+        recipient: Option<uuid::Uuid>,
+        payload: serde_json::Value,
+    ) -> Result<Self, anyhow::Error> {
+        let recipient = || recipient.ok_or_else(|| anyhow::anyhow!("{kind} without a recipient"));
+        let notification = match kind {
+            "new_recipe" => Self::NewRecipe(serde_json::from_value(payload)?),
+            "security_alert" => Self::SecurityAlert(SecurityAlert {
+                user_id: recipient()?,
+                ..serde_json::from_value(payload)?
+            }),
+            "ingredient_merged" => Self::IngredientMerged(IngredientMerged {
+                user_id: recipient()?,
+                ..serde_json::from_value(payload)?
+            }),
+            "presence" => Self::Presence(serde_json::from_value(payload)?),
+            "suggestion_applied" => Self::SuggestionApplied(SuggestionApplied {
+                user_id: recipient()?,
+                ..serde_json::from_value(payload)?
+            }),
+            "suggestion_declined" => Self::SuggestionDeclined(SuggestionDeclined {
+                user_id: recipient()?,
+                ..serde_json::from_value(payload)?
+            }),
+            _ => anyhow::bail!("notifications of kind {kind} can't be restored"),
+        };
+        Ok(notification)
+    }
+
+    /// Saves a copy for each of `user_ids`, like [`Self::save_for_recipient`] does for a single
+    /// recipient.
+    pub async fn save_for_users(
+        &self,
+        conn: &mut PgConnection,
+        user_ids: &[uuid::Uuid],
+    ) -> Result<(), sqlx::Error> {
+        let payload = serde_json::to_value(self).expect("notifications are serializable");
+        sqlx::query!(
+            r#"
+            INSERT INTO notifications (user_id, kind, payload)
+            SELECT UNNEST($1::UUID[]), $2, $3
+            "#,
+            user_ids,
+            self.name(),
+            payload
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// The id of the saved copy, see [`Self::save_for_recipient`].
+    pub fn notification_id(&self) -> Option<uuid::Uuid> {
+        match self {
+            Self::SecurityAlert(alert) => alert.notification_id,
+            Self::IngredientMerged(merged) => merged.notification_id,
+            Self::SuggestionApplied(applied) => applied.notification_id,
+            Self::SuggestionDeclined(declined) => declined.notification_id,
+            Self::NewRecipe(_) | Self::SystemAnnouncement(_) | Self::Presence(_) => None,
+        }
+    }
+
+    /// Notifications meant for a single user, or for a group of them, are not broadcast to
+    /// everyone else.
+    pub fn is_visible_to(&self, user_id: Option<uuid::Uuid>) -> bool {
+        if let Self::SystemAnnouncement(announcement) = self {
+            return user_id.is_some_and(|user_id| announcement.recipients.contains(&user_id));
+        }
+        match self.recipient() {
+            Some(recipient) => Some(recipient) == user_id,
+            None => true,
         }
     }
 }
@@ -84,3 +452,93 @@ impl Notification {
 pub struct NewRecipe {
     pub name: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityAlert {
+    #[serde(skip)]
+    pub user_id: uuid::Uuid,
+    pub reason: SecurityAlertReason,
+    /// The saved copy of the alert, see [`Notification::save_for_recipient`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_id: Option<uuid::Uuid>,
+}
+
+/// A message from the admins, like a maintenance notice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemAnnouncement {
+    pub id: uuid::Uuid,
+    pub title: String,
+    pub body: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// The users it's addressed to, the announcement is only sent to the connected ones.
+    #[serde(skip)]
+    pub recipients: Arc<HashSet<uuid::Uuid>>,
+}
+
+/// An ingredient used by some of the recipient's recipes was merged into another one by a
+/// moderator, so the recipes now use `target` instead of `source`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngredientMerged {
+    #[serde(skip)]
+    pub user_id: uuid::Uuid,
+    pub source: String,
+    pub target: String,
+    /// The names of the recipient's recipes that used `source`.
+    pub recipes: Vec<String>,
+    /// The saved copy, see [`Notification::save_for_recipient`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_id: Option<uuid::Uuid>,
+}
+
+/// A suggestion of the recipient was accepted by a moderator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestionApplied {
+    #[serde(skip)]
+    pub user_id: uuid::Uuid,
+    /// The name of the ingredient, as it was before the change.
+    pub ingredient: String,
+    /// Whether the ingredient was deleted, instead of updated.
+    pub is_delete_vote: bool,
+    /// The saved copy, see [`Notification::save_for_recipient`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_id: Option<uuid::Uuid>,
+}
+
+/// A suggestion of the recipient was declined by a moderator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestionDeclined {
+    #[serde(skip)]
+    pub user_id: uuid::Uuid,
+    /// The name of the ingredient, if the suggestion was for one that exists.
+    pub ingredient: Option<String>,
+    /// Why, if the moderator said.
+    pub reason: Option<String>,
+    /// The saved copy, see [`Notification::save_for_recipient`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_id: Option<uuid::Uuid>,
+}
+
+/// Someone started or stopped looking at a recipe, see [`crate::presence`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceChanged {
+    /// The slug of the recipe.
+    pub recipe: String,
+    /// The display name of the user.
+    pub name: String,
+    pub status: PresenceStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Joined,
+    Left,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityAlertReason {
+    PasswordChanged,
+    /// An admin confirmed the email address, without the confirmation link.
+    EmailConfirmedByAdmin,
+}
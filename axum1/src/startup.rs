@@ -1,27 +1,66 @@
 use crate::{
+    allow::answer_options,
+    breach::BreachedPasswords,
     config::Settings,
+    connections::Connections,
+    cors::cors_layer,
+    csrf::csrf_protect,
     email::EmailClient,
-    routes::{admin, auth, ingredient, recipe},
-    sse::{sse_handler, Notification},
+    error::{problem_details, ApiError},
+    extractors::{SessionFallback, StrictBodies},
+    health::Startup,
+    load_shed::{shed_load, ConcurrencyLimit},
+    pagination::pagination_links,
+    personalized::vary_personalized,
+    presence::Presence,
+    queue::{outbox, with_statement_timeout},
+    recent::RecentlyViewed,
+    routes::auth::impersonation::audit_impersonation,
+    sandbox::sandbox,
+    search::meili_client,
+    secure_cookies::{secure_cookies_only, SecureCookies},
+    sse::{sse_handler, sse_head, Notification},
     state::AppState,
-    upload,
+    telemetry::{make_span, statement_budget},
     utils::{oauth_client_discord, oauth_client_google, shutdown_signal},
+    versioning::{api_router, negotiate_version, ApiVersion},
+    ws::ws_handler,
 };
 use anyhow::Context;
 use axum::{
-    http::HeaderValue,
-    routing::{get, get_service},
+    extract::FromRef,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{get, get_service, MethodRouter},
     Extension, Router,
 };
 use axum_prometheus::PrometheusMetricLayerBuilder;
 use sqlx::postgres::PgPoolOptions;
-use std::{net::SocketAddr, sync::Arc};
+use std::{future::IntoFuture, net::SocketAddr, sync::Arc};
 use time::Duration;
 use tokio::net::TcpListener;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use tower::Layer;
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use tower_sessions::{Expiry, SessionManagerLayer};
 use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
 
+/// The connections of the primary, and of the replica if there's one.
+const DB_POOL_SIZE: u32 = 5;
+
+/// The files in `static`, for whatever the API doesn't route. Other methods than `GET` and `HEAD`
+/// are a `404`, like a missing file, and not a `405` that `answer_options` would take for a route.
+pub fn static_files() -> MethodRouter {
+    get_service(ServeDir::new("static")).fallback(|| async { ApiError::NotFound })
+}
+
+/// Wraps the application in the middleware that must see all of it: `negotiate_version` rewrites
+/// the path before routing, and `answer_options` needs the `Allow` header, which the router only
+/// adds to the final response.
+pub fn outermost(app: Router) -> Router {
+    Router::new()
+        .fallback_service(from_fn(negotiate_version).layer(app))
+        .layer(from_fn(answer_options))
+}
+
 pub async fn application(
     dynamic_cfg: tokio::sync::watch::Receiver<Settings>,
 ) -> Result<(), anyhow::Error> {
@@ -34,20 +73,48 @@ pub async fn application(
         config.application_settings.port,
     ));
 
+    // Bound before the migrations run, so the liveness probe passes while they do. See
+    // `crate::health`.
+    let listener = TcpListener::bind(&addr)
+        .await
+        .context("Failed to bind the listener")?;
+    let startup = Startup::default();
+    let server = tokio::spawn(
+        axum::serve(
+            listener,
+            startup
+                .router()
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .into_future(),
+    );
+    tracing::debug!(%addr, "listening, starting up");
+
     let discord_oauth_client = oauth_client_discord(&config);
     let google_oauth_client = oauth_client_google(&config);
 
-    let db_conn_str = config.database.connection_string();
-
-    let db_pool = PgPoolOptions::new()
-        .max_connections(5)
+    let db_pool = with_statement_timeout(PgPoolOptions::new(), config.database.statement_timeout())
+        .max_connections(DB_POOL_SIZE)
         .acquire_timeout(std::time::Duration::from_secs(3))
-        .connect(&db_conn_str)
+        .connect_with(config.database.with_db())
         .await
         .context("failed to connect to database")?;
 
     sqlx::migrate!().run(&db_pool).await?;
 
+    let replica_pool = match config.database.replica_with_db() {
+        Some(options) => Some(
+            with_statement_timeout(PgPoolOptions::new(), config.database.statement_timeout())
+                .max_connections(DB_POOL_SIZE)
+                .acquire_timeout(std::time::Duration::from_secs(3))
+                .connect_with(options)
+                .await
+                .context("failed to connect to the read replica")?,
+        ),
+        None => None,
+    };
+
     let redis_url = config.redis.connection_string();
 
     let pool = RedisPool::new(
@@ -62,15 +129,33 @@ pub async fn application(
     pool.wait_for_connect().await?;
     tracing::debug!("redis connected.");
 
+    let recently_viewed = RecentlyViewed::new(
+        pool.clone(),
+        config.application_settings.recently_viewed_limit(),
+    );
+    let presence = Presence::new(pool.clone(), config.application_settings.presence_timeout());
+    let breached_passwords = BreachedPasswords::new(pool.clone());
+    // The store encodes the session records with MessagePack itself, they're never JSON in Redis.
     let session_store = RedisStore::new(pool);
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(
-            std::env::var("APP_ENVIRONMENT").unwrap_or_else(|_| String::from("local"))
-                == "production",
-        )
+    let session_settings = config.session.clone();
+    let mut session_layer = SessionManagerLayer::new(session_store.clone())
+        .with_secure(config.security.secure_session_cookie(&session_settings)?)
         .with_expiry(Expiry::OnInactivity(Duration::minutes(10)));
+    if let Some(name) = session_settings.cookie_name {
+        session_layer = session_layer.with_name(name);
+    }
+    if let Some(same_site) = session_settings.same_site {
+        session_layer = session_layer.with_same_site(same_site.into());
+    }
+    if let Some(path) = session_settings.path {
+        session_layer = session_layer.with_path(path);
+    }
+    if let Some(domain) = session_settings.domain {
+        session_layer = session_layer.with_domain(domain);
+    }
 
     let email_client = EmailClient::from_config(config.email_client);
+    let meili_client = meili_client(&config.meili)?;
 
     let (metric_layer, metric_handle) = PrometheusMetricLayerBuilder::new()
         .with_ignore_pattern("/admin")
@@ -81,43 +166,96 @@ pub async fn application(
     let tx = Arc::new(tx);
     let rx = Arc::new(rx);
 
+    let (stop_relay, relay_stopped) = tokio::sync::watch::channel(false);
+    tokio::spawn(outbox::relay_loop(
+        db_pool.clone(),
+        (*tx).clone(),
+        config.worker.outbox_relay_interval(),
+        relay_stopped,
+    ));
+
     let app_state = AppState {
         db_pool,
+        replica_pool,
         config: dynamic_cfg,
         email_client,
+        meili_client,
         tx,
         rx,
+        session_store: Arc::new(session_store),
+        recently_viewed,
+        presence,
+        breached_passwords,
+        connections: Connections::new(),
+        recipe_reads: Default::default(),
     };
 
-    let app = Router::<AppState>::new()
+    let mut app = Router::<AppState>::new()
         .route("/metrics", get(|| async move { metric_handle.render() }))
-        .route("/sse", get(sse_handler))
-        .nest("/i", ingredient::router(app_state.clone()))
-        .nest("/r", recipe::router())
-        .nest("/", auth::router())
-        .nest("/admin", admin::router(app_state.clone()))
-        .nest("/upload", upload::router(app_state.clone()))
-        .fallback_service(get_service(ServeDir::new("static")))
+        .route("/sse", get(sse_handler).head(sse_head))
+        .route("/ws", get(ws_handler));
+    // The default version is served without a prefix too, like before versioning.
+    let default_version = config.application_settings.default_api_version();
+    for version in ApiVersion::ALL {
+        let api = api_router(version, &app_state);
+        if version == default_version {
+            app = app.merge(api.clone());
+        }
+        app = app.nest(&version.prefix(), api);
+    }
+
+    let app = app
+        .fallback_service(static_files())
+        .layer(from_fn(pagination_links))
+        .layer(from_fn(vary_personalized))
+        .layer(from_fn_with_state(
+            config.application_settings.csrf_protection(),
+            csrf_protect,
+        ))
+        .layer(from_fn_with_state(app_state.clone(), sandbox))
+        .layer(from_fn_with_state(app_state.clone(), statement_budget))
+        .layer(from_fn_with_state(app_state.clone(), audit_impersonation))
         .layer(
             tower::ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
+                .layer(TraceLayer::new_for_http().make_span_with(make_span))
                 .layer(metric_layer)
+                .layer(from_fn_with_state(
+                    ConcurrencyLimit::new(
+                        config
+                            .application_settings
+                            .max_concurrent_requests(DB_POOL_SIZE),
+                    ),
+                    shed_load,
+                ))
+                .layer(from_fn_with_state(
+                    config.application_settings.problem_json.unwrap_or(false),
+                    problem_details,
+                ))
+                .layer(Extension(StrictBodies(
+                    config.application_settings.strict_request_bodies(),
+                )))
+                .layer(Extension(SessionFallback(
+                    config.session.anonymous_on_store_error(),
+                )))
                 .layer(Extension(discord_oauth_client))
                 .layer(Extension(google_oauth_client))
-                .layer(
-                    CorsLayer::very_permissive()
-                        .allow_origin(config.frontend_url.parse::<HeaderValue>().unwrap())
-                        .allow_credentials(true),
-                )
+                .layer(cors_layer(&config.cors, &config.frontend_url))
+                .layer(from_fn_with_state(
+                    SecureCookies::from_ref(&app_state),
+                    secure_cookies_only,
+                ))
                 .layer(session_layer),
         )
         .with_state(app_state);
+    let app = outermost(app);
 
-    let listener = TcpListener::bind(&addr).await.unwrap();
+    startup.serve_app(app);
+    tracing::debug!(%addr, "ready");
 
-    tracing::debug!(%addr, "listening");
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    let served = server
         .await
-        .context("Failed to start server")
+        .context("The server task panicked")?
+        .context("Failed to start server");
+    let _ = stop_relay.send(true);
+    served
 }
@@ -1,18 +1,41 @@
 use std::sync::Arc;
 
+use meilisearch_sdk::client::Client;
 use sqlx::PgPool;
-use tokio::sync::{
-    broadcast,
-    watch,
-};
+use tokio::sync::{broadcast, watch};
+use tower_sessions::SessionStore;
 
-use crate::{config::Settings, email::EmailClient, sse::Notification};
+use crate::{
+    breach::BreachedPasswords, config::Settings, connections::Connections, email::EmailClient,
+    presence::Presence, recent::RecentlyViewed, routes::recipe::RecipeReads, sse::Notification,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: PgPool,
+    /// The pool of `database.replica`, if it's configured.
+    pub replica_pool: Option<PgPool>,
     pub config: watch::Receiver<Settings>,
     pub tx: Arc<broadcast::Sender<Notification>>,
     pub rx: Arc<broadcast::Receiver<Notification>>,
     pub email_client: EmailClient,
+    /// Built once from the `meili` config, changing it takes a restart.
+    pub meili_client: Client,
+    /// The store behind the session layer, for revoking sessions other than the current one.
+    pub session_store: Arc<dyn SessionStore>,
+    pub recently_viewed: RecentlyViewed,
+    pub presence: Presence,
+    pub breached_passwords: BreachedPasswords,
+    /// The SSE streams and WebSockets open on this instance.
+    pub connections: Connections,
+    /// The anonymous recipe reads in flight on this instance.
+    pub recipe_reads: Arc<RecipeReads>,
+}
+
+impl AppState {
+    /// The replica, or the primary without one. It may lag behind the primary, see
+    /// [`crate::extractors::ReadDatabaseConnection`].
+    pub fn read_pool(&self) -> &PgPool {
+        self.replica_pool.as_ref().unwrap_or(&self.db_pool)
+    }
 }
@@ -0,0 +1,155 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use axum::{
+    extract::{MatchedPath, Request as AxumRequest, State},
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{propagation::Extractor, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Sampler, Tracer, TracerProvider},
+    Resource,
+};
+use tracing::{Event, Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{config::TelemetrySettings, state::AppState};
+
+/// The target SQLx logs every executed statement under, see `DatabaseSettings::with_db`.
+pub const STATEMENT_TARGET: &str = "sqlx::query";
+
+/// Sets up exporting spans over OTLP, if it's enabled.
+///
+/// Returns the provider, to be shut down before exiting so the last spans are flushed, and the
+/// tracer to build the `tracing_opentelemetry` layer with.
+pub fn init_tracer(
+    settings: &TelemetrySettings,
+) -> anyhow::Result<Option<(TracerProvider, Tracer)>> {
+    let Some(endpoint) = &settings.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        // Follow the caller's decision, so a trace isn't cut in half at this service.
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            settings.sample_ratio(),
+        ))))
+        .with_resource(Resource::new([
+            KeyValue::new("service.name", settings.service_name().to_owned()),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Ok(Some((provider, tracer)))
+}
+
+/// The span of a request, like `TraceLayer`'s default, continuing the trace in the `traceparent`
+/// header of the request.
+///
+/// Without OpenTelemetry the global propagator is a no-op, and this is the same as the default.
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    let span = tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        db.statements = tracing::field::Empty,
+        impersonated_by = tracing::field::Empty,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+    span
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+tokio::task_local! {
+    static STATEMENTS: AtomicUsize;
+}
+
+/// Counts the statements SQLx executes within [`count_statements`], by the events it logs for
+/// them. It has to see the `sqlx::query` events at `trace`, even if they aren't printed, so it
+/// needs its own filter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatementCounterLayer;
+
+impl<S: Subscriber> Layer<S> for StatementCounterLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() == STATEMENT_TARGET {
+            let _ = STATEMENTS.try_with(|count| count.fetch_add(1, Ordering::Relaxed));
+        }
+    }
+}
+
+/// Runs `future`, counting the statements it executes. Statements of the tasks it spawns aren't
+/// counted.
+pub async fn count_statements<F: Future>(future: F) -> (F::Output, usize) {
+    STATEMENTS
+        .scope(AtomicUsize::new(0), async {
+            let output = future.await;
+            (
+                output,
+                STATEMENTS.with(|count| count.load(Ordering::Relaxed)),
+            )
+        })
+        .await
+}
+
+/// Records how many statements a request executed on its span and in the
+/// `db_statements_per_request` histogram, and warns about the ones over
+/// `database.statements_per_request_warning`, which are probably running a query per row.
+pub async fn statement_budget(
+    State(AppState { config, .. }): State<AppState>,
+    request: AxumRequest,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+    let (response, statements) = count_statements(next.run(request)).await;
+
+    Span::current().record("db.statements", statements);
+    metrics::histogram!("db_statements_per_request", "route" => route.clone())
+        .record(statements as f64);
+    let threshold = config.borrow().database.statements_per_request_warning();
+    if statements > threshold {
+        metrics::counter!("db_statement_budget_exceeded_total", "route" => route.clone())
+            .increment(1);
+        tracing::warn!(
+            %route,
+            statements,
+            threshold,
+            "The request executed more statements than expected, is it querying per row?"
+        );
+    }
+    response
+}
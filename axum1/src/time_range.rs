@@ -0,0 +1,75 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+
+use crate::error::ApiError;
+
+#[derive(serde::Deserialize)]
+struct TimeRangeQuery {
+    since: Option<String>,
+    until: Option<String>,
+}
+
+/// Scopes a time-ordered listing with the optional `since` and `until` RFC 3339 query parameters.
+///
+/// `since` is inclusive and `until` is exclusive, so consecutive ranges never overlap. Both may be
+/// combined with [`Pagination`](crate::pagination::Pagination), which pages within the range.
+/// A `+` in an offset has to be percent-encoded (`%2B`), otherwise it's decoded as a space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    /// Rejects malformed timestamps and ranges ending before they start with `422`.
+    pub fn parse(since: Option<&str>, until: Option<&str>) -> Result<Self, ApiError> {
+        let mut errors = Vec::new();
+        let mut parse = |field: &'static str, value: Option<&str>| {
+            value.and_then(|value| match DateTime::parse_from_rfc3339(value) {
+                Ok(timestamp) => Some(timestamp.with_timezone(&Utc)),
+                Err(_) => {
+                    errors.push((field, "should be an RFC 3339 timestamp"));
+                    None
+                }
+            })
+        };
+        let range = Self {
+            since: parse("since", since),
+            until: parse("until", until),
+        };
+
+        if !errors.is_empty() {
+            return Err(ApiError::unprocessable_entity(errors));
+        }
+        if let (Some(since), Some(until)) = (range.since, range.until) {
+            if since > until {
+                return Err(ApiError::unprocessable_entity([(
+                    "since",
+                    "should not be later than `until`",
+                )]));
+            }
+        }
+
+        Ok(range)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TimeRange
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<TimeRangeQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::BadRequest)?;
+
+        Self::parse(query.since.as_deref(), query.until.as_deref())
+    }
+}
@@ -0,0 +1,39 @@
+//! Generation and verification of the one-time tokens we send out in emails,
+//! like registration confirmation and password reset tokens.
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::config::{TokenEncoding, TokenSettings};
+
+/// Generates a random token with the configured amount of entropy, encoded in a URL-safe way.
+pub fn generate_token(settings: &TokenSettings) -> String {
+    let mut bytes = vec![0u8; settings.bytes()];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    match settings.encoding() {
+        TokenEncoding::Base64Url => URL_SAFE_NO_PAD.encode(bytes),
+        TokenEncoding::Hex => bytes.iter().map(|b| format!("{b:02x}")).collect(),
+    }
+}
+
+/// The form a token is stored and looked up in: the hex encoded SHA-256 digest of it.
+///
+/// Someone reading the tables can't use the tokens, and looking one up by its digest doesn't
+/// compare the secret itself in variable time. It's the same as
+/// `encode(sha256(convert_to(token, 'UTF8')), 'hex')` in Postgres.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Compares a token kept in plain, like the CSRF token of a session, with the one provided by the
+/// client in constant time.
+///
+/// Both sides are hashed first, so the comparison doesn't leak the length of the tokens either.
+/// `blake3::Hash` implements `PartialEq` in constant time.
+pub fn verify_token(expected: &str, provided: &str) -> bool {
+    blake3::hash(expected.as_bytes()) == blake3::hash(provided.as_bytes())
+}
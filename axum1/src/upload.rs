@@ -1,52 +1,116 @@
 use anyhow::Context;
+use async_compression::tokio::{bufread::ZstdDecoder, write::ZstdEncoder};
 use axum::{
     body::Body,
     body::Bytes,
-    extract::{DefaultBodyLimit, Multipart, Path},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{
+        header::{CONTENT_SECURITY_POLICY, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+        HeaderMap, StatusCode,
+    },
     middleware::from_extractor_with_state,
-    routing::post,
+    response::{IntoResponse, Response},
+    routing::{get, post},
     BoxError, Router,
 };
 use futures::{Stream, TryStreamExt};
-use sqlx::{Acquire, PgExecutor};
+use sqlx::{Acquire, PgConnection, PgExecutor};
 use std::io::{self, ErrorKind};
-use tokio::{fs::File, io::BufWriter};
-use tokio_util::io::StreamReader;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufReader, BufWriter},
+};
+use tokio_util::io::{ReaderStream, StreamReader};
 use tower_http::limit::RequestBodyLimitLayer;
 
 use crate::{
-    error::ApiError,
-    extractors::{DatabaseConnection, Uploader},
+    error::{ApiError, ResultExt},
+    extractors::{AuthUser, DatabaseConnection, Uploader},
     routes::admin::AdminUser,
     state::AppState,
 };
 
 pub const UPLOADS_DIRECTORY: &str = "uploads";
 
+/// Used when the client doesn't tell what it uploads.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The only encoding files are compressed with at rest.
+const ZSTD_ENCODING: &str = "zstd";
+
 pub fn router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/:file_name", post(save_request_body))
         .route_layer(from_extractor_with_state::<AdminUser, _>(state))
         .route("/", post(accept_form))
+        .route("/:user_id/:file_name", get(fetch_upload))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(25 * 1024 * 1024)) // 25mb
 }
 
+/// Whether files of this content type are compressed at rest. `compressed_types` may name exact
+/// types (`application/json`) or every subtype of a type (`text/*`), parameters like the charset
+/// are ignored.
+pub fn should_compress(content_type: &str, compressed_types: &[String]) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let Some((main_type, _)) = essence.split_once('/') else {
+        return false;
+    };
+
+    compressed_types.iter().any(|compressed| {
+        let compressed = compressed.trim().to_ascii_lowercase();
+        match compressed.strip_suffix("/*") {
+            Some(compressed_main_type) => compressed_main_type == main_type,
+            None => compressed == essence,
+        }
+    })
+}
+
 pub async fn save_request_body(
+    State(AppState { mut config, .. }): State<AppState>,
     DatabaseConnection(mut conn): DatabaseConnection,
     Path(file_name): Path<String>,
     uploader: Uploader,
+    headers: HeaderMap,
     body: Body,
 ) -> Result<(), ApiError> {
-    stream_to_file(&file_name, uploader.id, body.into_data_stream(), &mut *conn).await
+    let compressed_types = config
+        .borrow_and_update()
+        .application_settings
+        .compressed_upload_types();
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or(DEFAULT_CONTENT_TYPE);
+
+    stream_to_file(
+        &file_name,
+        uploader.id,
+        content_type,
+        &compressed_types,
+        body.into_data_stream(),
+        &mut *conn,
+    )
+    .await
 }
 
 // Handler that accepts a multipart form upload and streams each field to a file.
 pub async fn accept_form(
+    State(AppState { mut config, .. }): State<AppState>,
     uploader: Uploader,
     DatabaseConnection(mut conn): DatabaseConnection,
     mut multipart: Multipart,
 ) -> Result<(), ApiError> {
+    let compressed_types = config
+        .borrow_and_update()
+        .application_settings
+        .compressed_upload_types();
+
     let mut tx = conn.begin().await?;
     while let Some(field) = multipart
         .next_field()
@@ -57,17 +121,163 @@ pub async fn accept_form(
             continue;
         };
 
-        stream_to_file(&file_name, uploader.id, field, &mut *tx).await?;
+        let content_type = field
+            .content_type()
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+            .to_owned();
+
+        stream_to_file(
+            &file_name,
+            uploader.id,
+            &content_type,
+            &compressed_types,
+            field,
+            &mut *tx,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Serves an upload with the content type it was uploaded with, decompressing it if it's
+/// compressed at rest.
+pub async fn fetch_upload(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    Path((user_id, file_name)): Path<(uuid::Uuid, String)>,
+) -> Result<Response, ApiError> {
+    if !path_is_valid(&file_name) {
+        return Err(ApiError::BadRequest);
+    }
+
+    let upload = sqlx::query!(
+        "SELECT content_type, content_encoding FROM uploads WHERE uploader_id = $1 AND file_name = $2",
+        user_id,
+        file_name
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let file_path = std::path::Path::new(UPLOADS_DIRECTORY)
+        .join(user_id.to_string())
+        .join(&file_name);
+    let file = File::open(file_path)
+        .await
+        .map_err(|_| ApiError::NotFound)?;
+
+    let body = match upload.content_encoding.as_deref() {
+        Some(ZSTD_ENCODING) => {
+            Body::from_stream(ReaderStream::new(ZstdDecoder::new(BufReader::new(file))))
+        }
+        _ => Body::from_stream(ReaderStream::new(file)),
+    };
+
+    // The content type is whatever the uploader claimed, so browsers mustn't run it as a page of
+    // this origin.
+    let headers = [
+        (CONTENT_TYPE, upload.content_type),
+        (X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        (CONTENT_SECURITY_POLICY, "sandbox".to_owned()),
+    ];
+    Ok((headers, body).into_response())
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct DeleteUploadQuery {
+    /// Removes the upload from the recipes using it as their image and from the avatar, instead of
+    /// refusing to delete it.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Deletes an upload of the user. Its bytes no longer count towards the daily upload limit.
+#[tracing::instrument(skip(conn, auth_user))]
+pub async fn delete_upload(
+    DatabaseConnection(mut conn): DatabaseConnection,
+    auth_user: AuthUser,
+    Path(file_name): Path<String>,
+    Query(query): Query<DeleteUploadQuery>,
+) -> Result<StatusCode, ApiError> {
+    if !path_is_valid(&file_name) {
+        return Err(ApiError::BadRequest);
     }
 
+    let mut tx = conn.begin().await?;
+    delete_owned_upload(&mut tx, *auth_user, &file_name, query.force).await?;
     tx.commit().await?;
 
+    // The row is gone, so the worst a failure here does is leave an unreachable file behind.
+    let file_path = std::path::Path::new(UPLOADS_DIRECTORY)
+        .join(auth_user.to_string())
+        .join(&file_name);
+    if let Err(e) = tokio::fs::remove_file(&file_path).await {
+        if e.kind() != ErrorKind::NotFound {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to remove a deleted upload from the disk"
+            );
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Deletes the row of an upload, which is what the upload limit is counted from. Only the
+/// uploader may delete it, other users get a `404`, like for an upload that doesn't exist.
+///
+/// An upload used as a recipe image or as the avatar of the user is a `409`, unless `force`
+/// removes it from those first.
+pub async fn delete_owned_upload(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    file_name: &str,
+    force: bool,
+) -> Result<(), ApiError> {
+    if force {
+        sqlx::query!(
+            "UPDATE recipes SET image = NULL WHERE creator_id = $1 AND image = $2",
+            user_id,
+            file_name
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            "UPDATE users SET avatar = NULL WHERE user_id = $1 AND avatar = $2",
+            user_id,
+            file_name
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let deleted = sqlx::query!(
+        "DELETE FROM uploads WHERE uploader_id = $1 AND file_name = $2",
+        user_id,
+        file_name
+    )
+    .execute(&mut *conn)
+    .await
+    .on_constraint("recipes_image_fkey", |_| ApiError::Conflict)
+    .on_constraint("users_avatar_fkey", |_| ApiError::Conflict)?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(ApiError::NotFound);
+    }
     Ok(())
 }
 
+/// Streams the upload to its file, compressing it if its content type is one of
+/// `compressed_types`. The quota is accounted with the original size, however it's stored.
 async fn stream_to_file<'c, T, S, E, P>(
     path: P,
     user_id: uuid::Uuid,
+    content_type: &str,
+    compressed_types: &[String],
     stream: S,
     tx: T,
 ) -> Result<(), ApiError>
@@ -96,14 +306,37 @@ where
             );
         }
         let file_path = user_dir.join(path);
-        let mut file = BufWriter::new(File::create(file_path.clone()).await?);
+        let file = BufWriter::new(File::create(file_path.clone()).await?);
 
         // Copy the body into the file.
-        let bytes_copied = tokio::io::copy(&mut body_reader, &mut file).await?;
-        tracing::info!("written {bytes_copied} bytes");
+        let compress = should_compress(content_type, compressed_types);
+        let bytes_copied = if compress {
+            let mut encoder = ZstdEncoder::new(file);
+            let bytes_copied = tokio::io::copy(&mut body_reader, &mut encoder).await?;
+            // Finishes the zstd frame, and flushes the file.
+            encoder.shutdown().await?;
+            bytes_copied
+        } else {
+            let mut file = file;
+            let bytes_copied = tokio::io::copy(&mut body_reader, &mut file).await?;
+            file.flush().await?;
+            bytes_copied
+        };
+        let stored_bytes = tokio::fs::metadata(&file_path).await?.len();
+        tracing::info!("written {bytes_copied} bytes, stored as {stored_bytes} bytes");
 
+        // Uploading a file again replaces it, so its row has to describe the new content.
         sqlx::query!(
-            "INSERT INTO uploads (uploader_id, bytes, file_name) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            r#"
+            INSERT INTO uploads (uploader_id, bytes, file_name, stored_bytes, content_type, content_encoding)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (uploader_id, file_name) DO
+            UPDATE SET
+                bytes = EXCLUDED.bytes,
+                stored_bytes = EXCLUDED.stored_bytes,
+                content_type = EXCLUDED.content_type,
+                content_encoding = EXCLUDED.content_encoding
+            "#,
             user_id,
             bytes_copied as f32,
             file_path
@@ -111,7 +344,10 @@ where
                 .file_name()
                 .expect("only valid files can be uploaded")
                 .to_str()
-                .expect("only valid files can be uploaded")
+                .expect("only valid files can be uploaded"),
+            stored_bytes as i32,
+            content_type,
+            compress.then_some(ZSTD_ENCODING)
         )
         .execute(tx)
         .await
@@ -1,11 +1,26 @@
+use futures::{
+    future::{BoxFuture, WeakShared},
+    Future, FutureExt,
+};
 use oauth2::{
     basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, RevocationUrl, TokenUrl,
 };
+use secrecy::ExposeSecret;
+use sqlx::{Acquire, PgConnection, Postgres};
 use tokio::task::{JoinError, JoinHandle};
+use tokio_util::sync::CancellationToken;
 
-use std::fmt::{Debug, Display};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use crate::config::Settings;
+use crate::{config::Settings, error::ApiError};
 
 /// To play nicely with tokio, we must offload our CPU-intensive task to a
 /// separate threadpool using `tokio::task::spawn_blocking`. Those threads
@@ -23,6 +38,62 @@ where
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
 
+/// Like [`spawn_blocking_with_tracing`], for long work that's no longer needed once the caller
+/// gives up on it.
+///
+/// A blocking thread can't be aborted, so `f` gets a token that's cancelled when the returned
+/// future is dropped, like when the client of the request disconnects. `f` should check it between
+/// chunks of work and return early once it's cancelled.
+pub async fn spawn_blocking_cancellable<F, R>(f: F) -> Result<R, JoinError>
+where
+    F: FnOnce(CancellationToken) -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let token = CancellationToken::new();
+    let _cancel_on_drop = token.clone().drop_guard();
+    spawn_blocking_with_tracing(move || f(token)).await
+}
+
+/// Runs `f` in a transaction, committing it if `f` succeeds and rolling it back if it fails.
+///
+/// The closure borrows the transaction, so it has to box its future:
+///
+/// ```ignore
+/// with_transaction(&mut conn, move |tx| {
+///     Box::pin(async move {
+///         sqlx::query!("...").execute(&mut *tx).await?;
+///         Ok(())
+///     })
+/// })
+/// .await?;
+/// ```
+///
+/// Returning early from `f` with `?` rolls back too, nothing is left to dropping the transaction.
+pub async fn with_transaction<'a, A, F, T, E>(conn: A, f: F) -> Result<T, ApiError>
+where
+    A: Acquire<'a, Database = Postgres>,
+    F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, Result<T, E>>,
+    E: Into<ApiError>,
+{
+    let mut tx = conn.begin().await?;
+    match f(&mut tx).await {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = tx.rollback().await {
+                tracing::warn!(
+                    error.cause_chain = ?rollback_error,
+                    error.message = %rollback_error,
+                    "Failed to roll back a transaction"
+                );
+            }
+            Err(e.into())
+        }
+    }
+}
+
 pub fn init_tracing_panic_hook() {
     let next_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
@@ -90,7 +161,12 @@ pub async fn shutdown_signal() {
 
 pub fn oauth_client_discord(config: &Settings) -> DiscordOAuthClient {
     let client_id = config.oauth.discord.client_id.clone();
-    let client_secret = config.oauth.discord.client_secret.clone();
+    let client_secret = config
+        .oauth
+        .discord
+        .client_secret
+        .expose_secret()
+        .to_owned();
     let redirect_url = config.oauth.discord.redirect_url.clone();
     let auth_url = config.oauth.discord.auth_url.clone();
     let token_url = config.oauth.discord.token_url.clone();
@@ -112,7 +188,7 @@ pub fn oauth_client_discord(config: &Settings) -> DiscordOAuthClient {
 
 pub fn oauth_client_google(config: &Settings) -> GoogleOAuthClient {
     let client_id = config.oauth.google.client_id.clone();
-    let client_secret = config.oauth.google.client_secret.clone();
+    let client_secret = config.oauth.google.client_secret.expose_secret().to_owned();
     let redirect_url = config.oauth.google.redirect_url.clone();
     let auth_url = config.oauth.google.auth_url.clone();
     let token_url = config.oauth.google.token_url.clone();
@@ -137,3 +213,113 @@ pub struct GoogleOAuthClient(pub BasicClient);
 
 #[derive(Clone, Debug)]
 pub struct DiscordOAuthClient(pub BasicClient);
+
+/// The computations in flight, by key. Only the waiters keep a computation alive, so it's
+/// dropped, and its key forgotten, once every one of them gave up.
+type InFlight<K, V> = Arc<Mutex<HashMap<K, (u64, WeakShared<BoxFuture<'static, V>>)>>>;
+
+/// Coalesces identical concurrent computations: while one is running for a key, later callers
+/// with the same key wait for its result instead of starting their own.
+///
+/// Nothing is cached, the key is forgotten as soon as the computation finishes, panics or is
+/// dropped by every waiter. The key must capture everything the result depends on (e.g. the
+/// route, the path and who's asking), because everyone waiting on the same key gets the same
+/// result.
+///
+/// It's a helper for the handlers that opt into it, not a layer: a handler knows which of its
+/// reads can be shared and what they depend on. Keep it in the `AppState`, like
+/// [`RecipeReads`](crate::routes::recipe::RecipeReads).
+pub struct SingleFlight<K, V> {
+    in_flight: InFlight<K, V>,
+    next_id: AtomicU64,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Forgets the key of a computation when it's dropped, however it ends. The id tells it apart
+/// from a later computation of the same key, which isn't forgotten.
+struct ForgetOnDrop<K: Eq + Hash, V> {
+    in_flight: InFlight<K, V>,
+    key: K,
+    id: u64,
+}
+
+impl<K: Eq + Hash, V> Drop for ForgetOnDrop<K, V> {
+    fn drop(&mut self) {
+        // Not panicking again if a panicking waiter poisoned the lock.
+        let Ok(mut in_flight) = self.in_flight.lock() else {
+            return;
+        };
+        if matches!(in_flight.get(&self.key), Some((id, _)) if *id == self.id) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub async fn run<F>(&self, key: K, computation: F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let flight = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key).and_then(|(_, flight)| flight.upgrade()) {
+                Some(flight) => {
+                    // Whatever the computation holds (e.g. a connection) is given back while
+                    // waiting for the other one.
+                    drop(computation);
+                    flight
+                }
+                None => {
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    let guard = ForgetOnDrop {
+                        in_flight: Arc::clone(&self.in_flight),
+                        key: key.clone(),
+                        id,
+                    };
+                    let flight = async move {
+                        let _guard = guard;
+                        computation.await
+                    }
+                    .boxed()
+                    .shared();
+                    let weak = flight
+                        .downgrade()
+                        .expect("the flight hasn't been polled yet");
+                    in_flight.insert(key, (id, weak));
+                    flight
+                }
+            }
+        };
+        flight.await
+    }
+}
+
+impl<K, T> SingleFlight<K, Result<T, Arc<ApiError>>>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    T: Clone + Send + Sync + 'static,
+{
+    /// Like [`SingleFlight::run`], for computations failing with an `ApiError`. Every waiter gets
+    /// the error of the computation: the last one to see it the original, the others a copy, see
+    /// [`ApiError::shared_copy`].
+    pub async fn try_run<F>(&self, key: K, computation: F) -> Result<T, ApiError>
+    where
+        F: Future<Output = Result<T, ApiError>> + Send + 'static,
+    {
+        self.run(key, async move { computation.await.map_err(Arc::new) })
+            .await
+            .map_err(|e| Arc::try_unwrap(e).unwrap_or_else(|e| e.shared_copy()))
+    }
+}
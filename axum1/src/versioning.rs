@@ -0,0 +1,140 @@
+//! Every version of the API is mounted under its own prefix, like `/v1/r`. The unprefixed paths
+//! serve `application_settings.default_api_version`, unless the client asks for a version with
+//! `Accept: application/vnd.recipes.v1+json`.
+use std::{fmt, str::FromStr};
+
+use axum::{
+    extract::Request,
+    http::{header::ACCEPT, HeaderMap, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde::Deserialize;
+
+use crate::{
+    routes::{admin, auth, ingredient, recipe},
+    state::AppState,
+    upload,
+};
+
+const MEDIA_TYPE_PREFIX: &str = "application/vnd.recipes.";
+const MEDIA_TYPE_SUFFIX: &str = "+json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiVersion {
+    #[default]
+    V1,
+}
+
+impl ApiVersion {
+    /// Every version that's served, side by side.
+    pub const ALL: [Self; 1] = [Self::V1];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+        }
+    }
+
+    /// The path the version is mounted under.
+    pub fn prefix(self) -> String {
+        format!("/{}", self.name())
+    }
+
+    /// The version named in an `Accept` header, like `application/vnd.recipes.v1+json`. Other
+    /// media types are skipped, `Err` is a vendor media type of a version that doesn't exist.
+    pub fn from_accept(headers: &HeaderMap) -> Result<Option<Self>, String> {
+        let media_types = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|media_type| media_type.split(';').next().unwrap_or_default().trim());
+        for media_type in media_types {
+            let Some(version) = media_type
+                .strip_prefix(MEDIA_TYPE_PREFIX)
+                .and_then(|rest| rest.strip_suffix(MEDIA_TYPE_SUFFIX))
+            else {
+                continue;
+            };
+            return version.parse().map(Some).map_err(|_| version.to_owned());
+        }
+        Ok(None)
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|version| version.name() == s)
+            .ok_or(())
+    }
+}
+
+/// The routes of one version of the API.
+///
+/// The versions share the routers of the modules that didn't change between them. A `V2` that
+/// changes the recipes would only swap in its own recipe router here, and keep the rest.
+pub fn api_router(version: ApiVersion, state: &AppState) -> Router<AppState> {
+    let recipes = match version {
+        ApiVersion::V1 => recipe::router(state.clone()),
+    };
+    Router::new()
+        .nest("/i", ingredient::router(state.clone()))
+        .nest("/r", recipes)
+        .nest("/", auth::router(state.clone()))
+        .nest("/admin", admin::router(state.clone()))
+        .nest("/upload", upload::router(state.clone()))
+}
+
+/// Routes requests asking for a version with their `Accept` header to that version, by adding its
+/// prefix to the path. Prefixed paths are left alone, the prefix wins over the header.
+///
+/// It has to run before routing, so it wraps the whole application.
+pub async fn negotiate_version(mut request: Request, next: Next) -> Response {
+    let version = match ApiVersion::from_accept(request.headers()) {
+        Ok(Some(version)) => version,
+        Ok(None) => return next.run(request).await,
+        Err(unknown) => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                format!("API version `{unknown}` doesn't exist"),
+            )
+                .into_response()
+        }
+    };
+
+    let path = request.uri().path();
+    let prefixed = ApiVersion::ALL.into_iter().any(|version| {
+        path.strip_prefix(&version.prefix())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    });
+    if !prefixed {
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map_or(path, |path_and_query| path_and_query.as_str());
+        let rewritten = format!("{}{path_and_query}", version.prefix());
+        let mut parts = request.uri().clone().into_parts();
+        match rewritten.parse() {
+            Ok(path_and_query) => parts.path_and_query = Some(path_and_query),
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        }
+        match Uri::from_parts(parts) {
+            Ok(uri) => *request.uri_mut() = uri,
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        }
+    }
+    next.run(request).await
+}
@@ -0,0 +1,99 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    error::ApiError, extractors::MaybeAuthUser, sse::Notification, state::AppState,
+    utils::shutdown_signal,
+};
+
+/// The same live notifications as `sse_handler`, over a WebSocket. The unread ones aren't replayed.
+///
+/// Every message is a JSON text frame like `{"event": "new_recipe", "data": {...}}`.
+/// The session cookie authenticates the upgrade request, just like for SSE, and the socket counts
+/// toward the same connection limits.
+#[tracing::instrument(skip_all)]
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(AppState {
+        tx: chan,
+        config,
+        connections,
+        ..
+    }): State<AppState>,
+    maybe_auth_user: MaybeAuthUser,
+) -> Result<Response, ApiError> {
+    let guest_id = maybe_auth_user.guest_id().map(|guest_id| *guest_id);
+    let user_id = maybe_auth_user.into_inner().map(|user| *user);
+    let limits = config.borrow().application_settings.connection_limits();
+    let guard = connections
+        .try_open(user_id.or(guest_id), limits)
+        .ok_or(ApiError::TooManyRequests)?;
+    // Subscribe before the upgrade, so nothing sent in between is missed.
+    let sub = chan.subscribe();
+    Ok(ws.on_upgrade(move |socket| async move {
+        // Held until the socket is done with, or dropped with the callback if the upgrade fails.
+        let _guard = guard;
+        forward_notifications(socket, sub, user_id).await
+    }))
+}
+
+#[derive(serde::Serialize)]
+struct Envelope<'a> {
+    event: &'static str,
+    data: &'a Notification,
+}
+
+async fn forward_notifications(
+    mut socket: WebSocket,
+    mut sub: broadcast::Receiver<Notification>,
+    user_id: Option<uuid::Uuid>,
+) {
+    let shutdown = shutdown_signal();
+    futures::pin_mut!(shutdown);
+
+    loop {
+        tokio::select! {
+            notification = sub.recv() => {
+                let m = match notification {
+                    Ok(m) => m,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::trace!("WebSocket client lagged behind by {skipped} notifications");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !m.is_visible_to(user_id) {
+                    continue;
+                }
+                let payload = serde_json::to_string(&Envelope { event: m.name(), data: &m })
+                    .expect("notifications are serializable");
+                if let Err(send_error) = socket.send(Message::Text(payload)).await {
+                    tracing::trace!("Broadcasting error: {:?}", send_error);
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                // Protocol level pings are answered with pongs by the WebSocket implementation.
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                // An application level keepalive, for clients that can't send protocol pings.
+                Some(Ok(Message::Text(text))) if text == "ping" => {
+                    if socket.send(Message::Text("pong".to_owned())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Text(_) | Message::Binary(_))) => {}
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
+            _ = &mut shutdown => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use axum1::{
+    error::ApiError,
+    org::Org,
+    routes::admin::confirm_email::confirm_user_email,
+    sse::{Notification, SecurityAlertReason},
+};
+use sqlx::PgPool;
+
+async fn count(pool: &PgPool, table: &str, user_id: uuid::Uuid) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table} WHERE user_id = $1"))
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn unconfirmed_users_are_confirmed_and_audited(pool: PgPool) {
+    let admin = common::confirmed_user(&pool, "admin").await;
+    let bounced = common::user(&pool, "bounced").await;
+    sqlx::query(
+        "INSERT INTO confirmation_tokens (confirmation_token, user_id, expires_at) VALUES ('token', $1, NOW() + INTERVAL '1 day')",
+    )
+    .bind(bounced)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let mut conn = pool.acquire().await.unwrap();
+    let alert = confirm_user_email(&mut conn, Org::DEFAULT, admin, bounced)
+        .await
+        .unwrap()
+        .expect("the user wasn't confirmed yet");
+
+    assert!(matches!(
+        alert,
+        Notification::SecurityAlert(ref alert)
+            if alert.user_id == bounced
+                && matches!(alert.reason, SecurityAlertReason::EmailConfirmedByAdmin)
+                && alert.notification_id.is_some()
+    ));
+    let confirmed: bool = sqlx::query_scalar("SELECT confirmed FROM users WHERE user_id = $1")
+        .bind(bounced)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(confirmed);
+    assert_eq!(count(&pool, "confirmation_tokens", bounced).await, 0);
+    let audited_by: uuid::Uuid =
+        sqlx::query_scalar("SELECT admin_id FROM admin_email_confirmations WHERE user_id = $1")
+            .bind(bounced)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(audited_by, admin);
+}
+
+#[sqlx::test]
+async fn already_confirmed_users_are_left_alone(pool: PgPool) {
+    let admin = common::confirmed_user(&pool, "admin").await;
+    let cook = common::confirmed_user(&pool, "cook").await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let alert = confirm_user_email(&mut conn, Org::DEFAULT, admin, cook)
+        .await
+        .unwrap();
+
+    assert!(alert.is_none());
+    assert_eq!(count(&pool, "admin_email_confirmations", cook).await, 0);
+    assert_eq!(count(&pool, "notifications", cook).await, 0);
+}
+
+#[sqlx::test]
+async fn unknown_users_are_not_found(pool: PgPool) {
+    let admin = common::confirmed_user(&pool, "admin").await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let result = confirm_user_email(&mut conn, Org::DEFAULT, admin, uuid::Uuid::new_v4()).await;
+
+    assert!(matches!(result, Err(ApiError::NotFound)));
+}
+
+/// Moves the user into a new organization.
+async fn move_to_other_org(pool: &PgPool, user_id: uuid::Uuid) {
+    sqlx::query(
+        r#"
+        WITH org AS (INSERT INTO organizations (slug, name) VALUES ('other', 'Other') RETURNING id)
+        UPDATE users SET org_id = (SELECT id FROM org) WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[sqlx::test]
+async fn users_of_other_organizations_are_not_found(pool: PgPool) {
+    let admin = common::confirmed_user(&pool, "admin").await;
+    let stranger = common::user(&pool, "stranger").await;
+    move_to_other_org(&pool, stranger).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let result = confirm_user_email(&mut conn, Org::DEFAULT, admin, stranger).await;
+
+    assert!(matches!(result, Err(ApiError::NotFound)));
+    assert_eq!(count(&pool, "admin_email_confirmations", stranger).await, 0);
+}
+
+#[sqlx::test]
+async fn super_admins_confirm_users_of_any_organization(pool: PgPool) {
+    let super_admin = common::confirmed_user(&pool, "super_admin").await;
+    sqlx::query("UPDATE users SET is_super_admin = TRUE WHERE user_id = $1")
+        .bind(super_admin)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let stranger = common::user(&pool, "stranger").await;
+    move_to_other_org(&pool, stranger).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let alert = confirm_user_email(&mut conn, Org::DEFAULT, super_admin, stranger)
+        .await
+        .unwrap();
+
+    assert!(alert.is_some());
+    assert_eq!(count(&pool, "admin_email_confirmations", stranger).await, 1);
+}
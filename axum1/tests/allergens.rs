@@ -0,0 +1,109 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    pagination::Pagination,
+    routes::{
+        ingredient::allergen::{allergen_profile, normalized, Allergen, AllergenStatus},
+        recipe::query::{RecipeFilters, RecipeQuery},
+    },
+};
+use sqlx::PgPool;
+
+#[test]
+fn checked_ingredients_give_the_complete_list() {
+    let profile = allergen_profile(&[
+        Some(vec![Allergen::Gluten, Allergen::Eggs]),
+        Some(vec![]),
+        Some(vec![Allergen::Dairy, Allergen::Eggs]),
+    ]);
+
+    assert_eq!(profile.status, AllergenStatus::Complete);
+    assert_eq!(
+        profile.contains.into_iter().collect::<Vec<_>>(),
+        [Allergen::Gluten, Allergen::Eggs, Allergen::Dairy]
+    );
+}
+
+#[test]
+fn unchecked_ingredients_leave_the_allergens_unverified() {
+    let profile = allergen_profile(&[Some(vec![Allergen::Dairy]), None]);
+
+    assert_eq!(profile.status, AllergenStatus::Unverified);
+    // The known allergens are still listed.
+    assert!(profile.contains.contains(&Allergen::Dairy));
+    assert!(!profile.is_free_of(Allergen::Nuts));
+    assert!(!profile.is_free_of(Allergen::Dairy));
+
+    let checked = allergen_profile(&[Some(vec![Allergen::Dairy]), Some(vec![])]);
+    assert!(checked.is_free_of(Allergen::Nuts));
+}
+
+#[test]
+fn recipe_without_ingredients_is_unverified() {
+    let profile = allergen_profile(&[]);
+
+    assert_eq!(profile.status, AllergenStatus::Unverified);
+    assert!(!profile.is_free_of(Allergen::Nuts));
+}
+
+#[test]
+fn the_same_allergens_in_any_order_are_equal() {
+    assert_eq!(
+        normalized(vec![Allergen::Nuts, Allergen::Dairy, Allergen::Nuts]),
+        normalized(vec![Allergen::Dairy, Allergen::Nuts])
+    );
+}
+
+#[derive(sqlx::FromRow)]
+struct Name {
+    name: String,
+}
+
+/// A recipe with one ingredient per entry, each with the given allergens.
+async fn seed_recipe(pool: &PgPool, user_id: uuid::Uuid, name: &str, ingredients: &[Option<&str>]) {
+    let recipe_id = common::recipe(pool, user_id, name).await;
+    for (i, allergens) in ingredients.iter().enumerate() {
+        let ingredient = format!("{name} ingredient {i}");
+        let ingredient_id = common::add_ingredient(pool, recipe_id, &ingredient, "100").await;
+        sqlx::query("UPDATE ingredients SET allergens = $2::allergen[] WHERE id = $1")
+            .bind(ingredient_id)
+            .bind(allergens)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+#[sqlx::test]
+async fn only_recipes_known_to_be_free_of_the_allergen_are_listed(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    seed_recipe(&pool, user_id, "bean soup", &[Some("{}"), Some("{celery}")]).await;
+    seed_recipe(&pool, user_id, "pesto", &[Some("{}"), Some("{nuts,dairy}")]).await;
+    seed_recipe(&pool, user_id, "porridge", &[Some("{gluten}"), None]).await;
+    seed_recipe(&pool, user_id, "water", &[]).await;
+
+    let filters = RecipeFilters {
+        exclude_allergen: Some(Allergen::Nuts),
+        ..Default::default()
+    };
+    let query = RecipeQuery::new(Org::DEFAULT, &filters);
+    let mut conn = pool.acquire().await.unwrap();
+    let names: Vec<Name> = query
+        .fetch_page(
+            &mut conn,
+            "r.name",
+            Pagination {
+                page: 1,
+                per_page: 10,
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        names.into_iter().map(|n| n.name).collect::<Vec<_>>(),
+        ["bean soup"]
+    );
+    assert_eq!(query.fetch_count(&mut conn).await.unwrap(), 1);
+}
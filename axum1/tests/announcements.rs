@@ -0,0 +1,100 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::admin::broadcast::{create_announcement, Audience, Broadcast},
+    sse::Notification,
+};
+use sqlx::PgPool;
+
+fn broadcast(audience: Audience, persist: bool) -> Broadcast {
+    Broadcast {
+        title: "Maintenance".to_owned(),
+        body: "We'll be down for an hour tonight.".to_owned(),
+        audience,
+        persist,
+    }
+}
+
+async fn saved_for(pool: &PgPool, user_id: uuid::Uuid) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn announcements_reach_only_their_audience(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    let member = common::user(&pool, "member").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let announcement = create_announcement(
+        &mut conn,
+        Org::DEFAULT,
+        admin,
+        &broadcast(Audience::Admins, true),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(saved_for(&pool, admin).await, 1);
+    assert_eq!(saved_for(&pool, member).await, 0);
+
+    let notification = Notification::SystemAnnouncement(announcement);
+    assert!(notification.is_visible_to(Some(admin)));
+    assert!(!notification.is_visible_to(Some(member)));
+    assert!(!notification.is_visible_to(None));
+}
+
+#[sqlx::test]
+async fn unsaved_announcements_are_still_audited(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    let member = common::user(&pool, "member").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let announcement = create_announcement(
+        &mut conn,
+        Org::DEFAULT,
+        admin,
+        &broadcast(Audience::All, false),
+    )
+    .await
+    .unwrap();
+
+    assert!(announcement.recipients.contains(&member));
+    assert_eq!(saved_for(&pool, member).await, 0);
+    let author: Option<uuid::Uuid> =
+        sqlx::query_scalar("SELECT author_id FROM announcements WHERE id = $1")
+            .bind(announcement.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(author, Some(admin));
+}
+
+#[sqlx::test]
+async fn saved_announcements_start_unread(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    let member = common::user(&pool, "member").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    create_announcement(
+        &mut conn,
+        Org::DEFAULT,
+        admin,
+        &broadcast(Audience::All, true),
+    )
+    .await
+    .unwrap();
+
+    let unread: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+    )
+    .bind(member)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(unread, 1);
+}
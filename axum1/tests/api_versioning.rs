@@ -0,0 +1,97 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::MatchedPath,
+    http::{header::ACCEPT, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::from_fn,
+    routing::get,
+    Router,
+};
+use axum1::versioning::{negotiate_version, ApiVersion};
+use tower::{Layer, ServiceExt};
+
+/// Like the application: every version under its prefix, the default one without a prefix too.
+fn app() -> Router {
+    let mut app = Router::new();
+    for version in ApiVersion::ALL {
+        let api = Router::new().route(
+            "/r/pancake",
+            get(move |path: MatchedPath| async move { format!("{version} {}", path.as_str()) }),
+        );
+        if version == ApiVersion::default() {
+            app = app.merge(api.clone());
+        }
+        app = app.nest(&version.prefix(), api);
+    }
+    Router::new().fallback_service(from_fn(negotiate_version).layer(app))
+}
+
+async fn get_with(uri: &str, accept: Option<&str>) -> (StatusCode, String) {
+    let mut request = Request::get(uri);
+    if let Some(accept) = accept {
+        request = request.header(ACCEPT, accept);
+    }
+    let response = app()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn prefixed_paths_are_served_by_their_version() {
+    let (status, body) = get_with("/v1/r/pancake", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "v1 /v1/r/pancake");
+}
+
+#[tokio::test]
+async fn unprefixed_paths_are_served_by_the_default_version() {
+    let (status, body) = get_with("/r/pancake", Some("application/json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "v1 /r/pancake");
+}
+
+#[tokio::test]
+async fn the_accept_header_selects_the_version() {
+    let (status, body) = get_with(
+        "/r/pancake?servings=2",
+        Some("application/problem+json, application/vnd.recipes.v1+json; q=0.9"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "v1 /v1/r/pancake");
+
+    // The prefix wins.
+    let (status, body) = get_with("/v1/r/pancake", Some("application/vnd.recipes.v1+json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "v1 /v1/r/pancake");
+}
+
+#[tokio::test]
+async fn unknown_versions_are_not_acceptable() {
+    let (status, _) = get_with("/r/pancake", Some("application/vnd.recipes.v9+json")).await;
+    assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+}
+
+#[test]
+fn versions_are_read_from_the_accept_header() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(ApiVersion::from_accept(&headers), Ok(None));
+
+    headers.insert(ACCEPT, HeaderValue::from_static("text/html, */*"));
+    assert_eq!(ApiVersion::from_accept(&headers), Ok(None));
+
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/vnd.recipes.v1+json"),
+    );
+    assert_eq!(ApiVersion::from_accept(&headers), Ok(Some(ApiVersion::V1)));
+
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/vnd.recipes.beta+json"),
+    );
+    assert_eq!(ApiVersion::from_accept(&headers), Err("beta".to_owned()));
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::auth::available::{lookup_availability, AvailabilityQuery},
+};
+use sqlx::PgPool;
+use validator::Validate;
+
+#[sqlx::test]
+async fn taken_values_are_reported_case_insensitively(pool: PgPool) {
+    common::user(&pool, "Béla").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let taken = lookup_availability(
+        &mut conn,
+        Org::DEFAULT,
+        Some("béla"),
+        Some("BÉLA@example.com"),
+    )
+    .await
+    .unwrap();
+    assert_eq!((taken.name, taken.email), (Some(false), Some(false)));
+
+    let free = lookup_availability(&mut conn, Org::DEFAULT, Some("Jenő"), None)
+        .await
+        .unwrap();
+    assert_eq!((free.name, free.email), (Some(true), None));
+}
+
+#[sqlx::test]
+async fn names_are_only_taken_within_the_organization(pool: PgPool) {
+    common::user(&pool, "Béla").await;
+    sqlx::query(
+        r#"
+        WITH org AS (INSERT INTO organizations (slug, name) VALUES ('other', 'Other') RETURNING id)
+        UPDATE users SET org_id = (SELECT id FROM org) WHERE name = 'Béla'
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let availability = lookup_availability(
+        &mut conn,
+        Org::DEFAULT,
+        Some("Béla"),
+        Some("Béla@example.com"),
+    )
+    .await
+    .unwrap();
+
+    // Emails are unique across every organization.
+    assert_eq!(
+        (availability.name, availability.email),
+        (Some(true), Some(false))
+    );
+}
+
+#[test]
+fn malformed_values_are_rejected() {
+    for (name, email) in [
+        (Some(".béla"), None),
+        (Some("b"), None),
+        (None, Some("not an email")),
+    ] {
+        let query = AvailabilityQuery {
+            name: name.map(str::to_owned),
+            email: email.map(str::to_owned),
+        };
+        assert!(query.validate().is_err(), "{name:?} {email:?}");
+    }
+
+    let query = AvailabilityQuery {
+        name: Some("béla.kovács".to_owned()),
+        email: Some("bela@example.com".to_owned()),
+    };
+    assert!(query.validate().is_ok());
+}
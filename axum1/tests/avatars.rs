@@ -0,0 +1,94 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Method, Request, StatusCode,
+    },
+    response::Response,
+    Router,
+};
+use axum1::routes::auth;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+async fn upload(pool: &PgPool, user_id: uuid::Uuid, file_name: &str, content_type: &str) {
+    sqlx::query(
+        "INSERT INTO uploads (uploader_id, file_name, bytes, stored_bytes, content_type) VALUES ($1, $2, 100, 100, $3)",
+    )
+    .bind(user_id)
+    .bind(file_name)
+    .bind(content_type)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn send(app: &Router, method: Method, uri: &str, cookie: &str, body: Body) -> Response {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(COOKIE, cookie)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap()
+}
+
+async fn choose(app: &Router, cookie: &str, file_name: &str) -> StatusCode {
+    let body = Body::from(json!({ "file_name": file_name }).to_string());
+    send(app, Method::PUT, "/me/avatar", cookie, body)
+        .await
+        .status()
+}
+
+async fn avatar_url(app: &Router, cookie: &str) -> serde_json::Value {
+    let response = send(app, Method::GET, "/me", cookie, Body::empty()).await;
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let me: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    me["avatar_url"].clone()
+}
+
+#[sqlx::test]
+async fn only_own_images_are_avatars(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    let bob = common::user(&pool, "bob").await;
+    upload(&pool, alice, "alice.png", "image/png").await;
+    upload(&pool, alice, "notes.pdf", "application/pdf").await;
+    upload(&pool, bob, "bob.png", "image/png").await;
+    let store = MemoryStore::default();
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .merge(auth::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+    let cookie = common::logged_in(&store, alice).await;
+
+    // Uploading alone doesn't make an avatar.
+    assert_eq!(avatar_url(&app, &cookie).await, serde_json::Value::Null);
+
+    assert_eq!(
+        choose(&app, &cookie, "notes.pdf").await,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        choose(&app, &cookie, "bob.png").await,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        choose(&app, &cookie, "alice.png").await,
+        StatusCode::NO_CONTENT
+    );
+    // The route serving uploads, `/upload/:user_id/:file_name`.
+    assert_eq!(
+        avatar_url(&app, &cookie).await,
+        format!("/upload/{alice}/alice.png")
+    );
+
+    let response = send(&app, Method::DELETE, "/me/avatar", &cookie, Body::empty()).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(avatar_url(&app, &cookie).await, serde_json::Value::Null);
+}
@@ -0,0 +1,157 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    routing::post,
+    Extension, Router,
+};
+use axum1::extractors::{Form, Json, StrictBodies};
+use tower::ServiceExt;
+
+#[derive(Debug, serde::Deserialize)]
+#[allow(dead_code)]
+struct Payload {
+    name: String,
+    amount: u32,
+}
+
+fn app() -> Router {
+    Router::new()
+        .route("/json", post(|Json(_): Json<Payload>| async {}))
+        .route("/form", post(|Form(_): Form<Payload>| async {}))
+}
+
+async fn send(
+    uri: &str,
+    content_type: &str,
+    body: &'static str,
+) -> (StatusCode, serde_json::Value) {
+    send_to(app(), uri, content_type, body).await
+}
+
+async fn send_to(
+    app: Router,
+    uri: &str,
+    content_type: &str,
+    body: &'static str,
+) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .oneshot(
+            Request::post(uri)
+                .header(CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn valid_bodies_are_accepted() {
+    let (status, _) = send("/json", "application/json", r#"{"name":"salt","amount":1}"#).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        "/form",
+        "application/x-www-form-urlencoded",
+        "name=salt&amount=1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn truncated_json_is_unprocessable() {
+    let (status, body) = send("/json", "application/json", r#"{"name":"sa"#).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["body"][0].as_str().unwrap().contains("EOF"));
+}
+
+#[tokio::test]
+async fn missing_json_fields_are_named() {
+    let (status, body) = send("/json", "application/json", r#"{"name":"salt"}"#).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["body"][0]
+        .as_str()
+        .unwrap()
+        .contains("missing field `amount`"));
+}
+
+#[tokio::test]
+async fn json_without_content_type_is_unprocessable() {
+    let (status, body) = send("/json", "text/plain", r#"{"name":"salt","amount":1}"#).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["body"].is_array());
+}
+
+#[tokio::test]
+async fn malformed_forms_are_unprocessable() {
+    let (status, body) = send("/form", "application/x-www-form-urlencoded", "name=salt").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["body"][0]
+        .as_str()
+        .unwrap()
+        .contains("missing field `amount`"));
+
+    let (status, _) = send(
+        "/form",
+        "application/x-www-form-urlencoded",
+        "name=salt&amount=many",
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn unknown_fields_are_ignored_unless_strict() {
+    let json = r#"{"name":"salt","amount":1,"nmae":"pepper"}"#;
+    let form = "name=salt&amount=1&nmae=pepper";
+
+    for app in [app(), app().layer(Extension(StrictBodies(false)))] {
+        let (status, _) = send_to(app.clone(), "/json", "application/json", json).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_to(app, "/form", "application/x-www-form-urlencoded", form).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let strict = app().layer(Extension(StrictBodies(true)));
+    let (status, body) = send_to(strict.clone(), "/json", "application/json", json).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"]["nmae"][0], "is not a known field");
+
+    let (status, body) = send_to(
+        strict.clone(),
+        "/form",
+        "application/x-www-form-urlencoded",
+        form,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"]["nmae"][0], "is not a known field");
+}
+
+#[tokio::test]
+async fn strict_bodies_are_still_validated() {
+    let strict = app().layer(Extension(StrictBodies(true)));
+
+    let (status, _) = send_to(
+        strict.clone(),
+        "/form",
+        "application/x-www-form-urlencoded",
+        "name=salt&amount=1",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send_to(strict, "/json", "application/json", r#"{"name":"salt"}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["body"][0]
+        .as_str()
+        .unwrap()
+        .contains("missing field `amount`"));
+}
@@ -0,0 +1,50 @@
+use axum1::{
+    breach::{ensure_password_not_breached, hash_parts, range_contains, BreachedPasswords},
+    config::BreachedPasswordSettings,
+};
+use secrecy::SecretString;
+use tower_sessions_redis_store::fred::prelude::*;
+
+#[test]
+fn only_the_prefix_of_the_hash_is_sent() {
+    let (prefix, suffix) = hash_parts(&SecretString::from("password"));
+
+    assert_eq!(prefix, "5BAA6");
+    assert_eq!(suffix, "1E4C9B93F3F0682250B6CF8331B7EE68FD8");
+}
+
+#[test]
+fn padding_entries_dont_count_as_breached() {
+    let range = "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n\
+                 1E4C9B93F3F0682250B6CF8331B7EE68FD8:0\r\n\
+                 1e4c9b93f3f0682250b6cf8331b7ee68fd9:10434004\r\n";
+
+    assert!(range_contains(range, "0018A45C4D1DEF81644B54AB7F969B88D65"));
+    assert!(!range_contains(
+        range,
+        "1E4C9B93F3F0682250B6CF8331B7EE68FD8"
+    ));
+    assert!(range_contains(range, "1E4C9B93F3F0682250B6CF8331B7EE68FD9"));
+    assert!(!range_contains(
+        range,
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"
+    ));
+}
+
+#[tokio::test]
+async fn nothing_is_checked_when_disabled() {
+    // Never connected, a disabled check doesn't touch Redis or the API.
+    let redis = RedisPool::new(RedisConfig::default(), None, None, None, 1).unwrap();
+    let settings = BreachedPasswordSettings {
+        api_url: Some("http://127.0.0.1:9".into()),
+        ..Default::default()
+    };
+
+    ensure_password_not_breached(
+        &BreachedPasswords::new(redis),
+        &settings,
+        &SecretString::from("password"),
+    )
+    .await
+    .unwrap();
+}
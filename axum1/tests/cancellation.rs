@@ -0,0 +1,78 @@
+mod common;
+
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use axum1::{
+    org::Org,
+    routes::admin::export::{export_stream, ExportTable},
+    time_range::TimeRange,
+    utils::spawn_blocking_cancellable,
+};
+use futures::StreamExt;
+use sqlx::{postgres::PgPoolOptions, PgPool};
+
+#[sqlx::test]
+async fn a_dropped_export_releases_its_connection_and_cursor(pool: PgPool) {
+    for i in 0..3 {
+        common::user(&pool, &format!("cook{i}")).await;
+    }
+    // A single connection, so the one the export used is the one checked afterwards.
+    let single = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(Duration::from_secs(2))
+        .connect_with((*pool.connect_options()).clone())
+        .await
+        .unwrap();
+
+    let mut export = Box::pin(export_stream(
+        single.clone(),
+        Org::DEFAULT,
+        ExportTable::Users,
+        TimeRange::default(),
+    ));
+    // The client reads the first line, then disconnects.
+    export.next().await.unwrap().unwrap();
+    drop(export);
+
+    let mut conn = single
+        .acquire()
+        .await
+        .expect("the export should have released its connection");
+    // The portal of this very query is listed too.
+    let open_cursors: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM pg_cursors WHERE name = 'export_cursor'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+    assert_eq!(open_cursors, 0);
+}
+
+#[tokio::test]
+async fn dropping_blocking_work_cancels_it() {
+    let (started_tx, started) = mpsc::channel();
+    let (stopped_tx, stopped) = mpsc::channel();
+    let work = tokio::spawn(spawn_blocking_cancellable(move |cancel| {
+        started_tx.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !cancel.is_cancelled() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        stopped_tx.send(cancel.is_cancelled()).unwrap();
+    }));
+    tokio::task::spawn_blocking(move || started.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Like a disconnecting client, gives up on the work while it's running.
+    work.abort();
+
+    let cancelled =
+        tokio::task::spawn_blocking(move || stopped.recv_timeout(Duration::from_secs(2)))
+            .await
+            .unwrap();
+    assert_eq!(cancelled, Ok(true));
+}
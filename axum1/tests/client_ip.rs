@@ -0,0 +1,122 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::connect_info::MockConnectInfo,
+    http::{HeaderMap, HeaderValue, Request},
+    routing::get,
+    Router,
+};
+use axum1::extractors::{ClientIp, TrustedProxies};
+use tower::ServiceExt;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn load_balancers() -> TrustedProxies {
+    TrustedProxies(vec!["10.0.0.0/8".parse().unwrap()])
+}
+
+fn forwarded(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.append(*name, HeaderValue::from_static(value));
+    }
+    headers
+}
+
+#[test]
+fn headers_are_ignored_without_trusted_proxies() {
+    let headers = forwarded(&[
+        ("x-forwarded-for", "203.0.113.7"),
+        ("x-real-ip", "203.0.113.8"),
+    ]);
+
+    let client = ClientIp::resolve(ip("10.0.0.1"), &headers, &TrustedProxies::default());
+    assert_eq!(client, ClientIp(ip("10.0.0.1")));
+}
+
+#[test]
+fn headers_are_ignored_from_untrusted_peers() {
+    let headers = forwarded(&[("x-forwarded-for", "203.0.113.7")]);
+
+    let client = ClientIp::resolve(ip("198.51.100.1"), &headers, &load_balancers());
+    assert_eq!(client, ClientIp(ip("198.51.100.1")));
+}
+
+#[test]
+fn rightmost_untrusted_hop_is_the_client() {
+    // The client prepended a forged address, our proxies appended the rest.
+    let headers = forwarded(&[("x-forwarded-for", "192.0.2.1, 203.0.113.7, 10.1.2.3")]);
+
+    let client = ClientIp::resolve(ip("10.0.0.1"), &headers, &load_balancers());
+    assert_eq!(client, ClientIp(ip("203.0.113.7")));
+}
+
+#[test]
+fn repeated_forwarded_for_headers_are_combined() {
+    let headers = forwarded(&[
+        ("x-forwarded-for", "203.0.113.7"),
+        ("x-forwarded-for", "10.1.2.3"),
+    ]);
+
+    let client = ClientIp::resolve(ip("10.0.0.1"), &headers, &load_balancers());
+    assert_eq!(client, ClientIp(ip("203.0.113.7")));
+}
+
+#[test]
+fn real_ip_is_used_without_forwarded_for() {
+    let headers = forwarded(&[("x-real-ip", "2001:db8::1")]);
+
+    let client = ClientIp::resolve(ip("10.0.0.1"), &headers, &load_balancers());
+    assert_eq!(client, ClientIp(ip("2001:db8::1")));
+}
+
+#[test]
+fn garbage_hops_fall_back_to_the_peer() {
+    let headers = forwarded(&[("x-forwarded-for", "203.0.113.7, unknown")]);
+
+    let client = ClientIp::resolve(ip("10.0.0.1"), &headers, &load_balancers());
+    assert_eq!(client, ClientIp(ip("10.0.0.1")));
+}
+
+async fn extracted_ip(peer: &str, trusted: TrustedProxies) -> String {
+    let peer: SocketAddr = format!("{peer}:4321").parse().unwrap();
+    let app = Router::new()
+        .route(
+            "/",
+            get(|ClientIp(ip): ClientIp| async move { ip.to_string() }),
+        )
+        .with_state(trusted)
+        .layer(MockConnectInfo(peer));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("x-forwarded-for", "203.0.113.7")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn extractor_uses_the_socket_address_by_default() {
+    assert_eq!(
+        extracted_ip("10.0.0.1", TrustedProxies::default()).await,
+        "10.0.0.1"
+    );
+}
+
+#[tokio::test]
+async fn extractor_honors_trusted_proxies() {
+    assert_eq!(
+        extracted_ip("10.0.0.1", load_balancers()).await,
+        "203.0.113.7"
+    );
+}
@@ -0,0 +1,222 @@
+//! Fixtures shared by the integration tests. Each test binary only uses some of them.
+#![allow(dead_code)]
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum1::{
+    breach::BreachedPasswords, config::Settings, connections::Connections, email::EmailClient,
+    presence::Presence, recent::RecentlyViewed, state::AppState,
+};
+use meilisearch_sdk::client::Client;
+use secrecy::SecretString;
+use serde_json::json;
+use sqlx::PgPool;
+use tower_sessions::{
+    session::{Id, Record},
+    MemoryStore, SessionStore,
+};
+use tower_sessions_redis_store::fred::prelude::*;
+
+/// Settings pointing at nothing that's running, with `overrides` merged into them key by key,
+/// e.g. `json!({ "application_settings": { "sandbox_mode": true } })`.
+pub fn settings(overrides: serde_json::Value) -> Settings {
+    let oauth = json!({
+        "client_id": "client-id",
+        "client_secret": "secret",
+        "auth_url": "https://example.com/auth",
+        "token_url": "https://example.com/token",
+        "redirect_url": "https://example.com/redirect",
+        "revocation_url": "https://example.com/revoke",
+    });
+    let mut settings = json!({
+        "database": {
+            "username": "postgres",
+            "password": "password",
+            "port": 5432,
+            "host": "127.0.0.1",
+            "database_name": "hummus",
+            "require_ssl": false,
+        },
+        "redis": { "host": "127.0.0.1", "port": 6379, "secret_key": "secret" },
+        "application_settings": {
+            "port": 3000,
+            "host": [127, 0, 0, 1],
+            "daily_upload_limit_bytes": 1024,
+        },
+        "frontend_url": "http://localhost:3001",
+        "email_client": {
+            "base_url": "http://localhost:9",
+            "sender_email": "recipes@example.com",
+            "authorization_token": "token",
+            "timeout_milliseconds": 1000,
+        },
+        "meili": { "url": "http://localhost:7700", "master_key": "key" },
+        "oauth": { "discord": oauth.clone(), "google": oauth },
+    });
+    merge(&mut settings, overrides);
+    serde_json::from_value(settings).unwrap()
+}
+
+fn merge(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// The state of the application with `settings`. Nothing here connects to Redis, Meilisearch or
+/// the email API, sessions are kept in memory.
+pub fn state(pool: PgPool, settings: Settings) -> AppState {
+    let (_config_tx, config) = tokio::sync::watch::channel(settings);
+    let (tx, rx) = tokio::sync::broadcast::channel(1);
+    let redis = RedisPool::new(RedisConfig::default(), None, None, None, 1).unwrap();
+    AppState {
+        db_pool: pool,
+        replica_pool: None,
+        config,
+        tx: Arc::new(tx),
+        rx: Arc::new(rx),
+        email_client: EmailClient::new(
+            "http://127.0.0.1:9".into(),
+            "recipes@example.com".into(),
+            SecretString::from("token"),
+            Duration::from_millis(200),
+        ),
+        meili_client: Client::new("http://127.0.0.1:9", Some("key")).unwrap(),
+        session_store: Arc::new(MemoryStore::default()),
+        recently_viewed: RecentlyViewed::new(redis.clone(), 1),
+        presence: Presence::new(redis.clone(), Duration::from_secs(30)),
+        breached_passwords: BreachedPasswords::new(redis),
+        connections: Connections::new(),
+        recipe_reads: Default::default(),
+    }
+}
+
+/// The `Cookie` header of a session in `store` logged in as the user, for a `SessionManagerLayer`
+/// on the same store.
+pub async fn logged_in(store: &MemoryStore, user_id: uuid::Uuid) -> String {
+    session(store, "user_id", user_id).await
+}
+
+/// Like [`logged_in`], for a guest session with the guest id.
+pub async fn guest(store: &MemoryStore, guest_id: uuid::Uuid) -> String {
+    session(store, "guest_id", guest_id).await
+}
+
+async fn session(store: &MemoryStore, key: &str, id: uuid::Uuid) -> String {
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([(key.to_owned(), json!(id))]),
+        expiry_date: time::OffsetDateTime::now_utc() + time::Duration::minutes(10),
+    };
+    store.create(&mut record).await.unwrap();
+    format!("id={}", record.id)
+}
+
+/// A user called `name`, at `<name>@example.com`, without a password.
+pub async fn user(pool: &PgPool, name: &str) -> uuid::Uuid {
+    sqlx::query_scalar(
+        "INSERT INTO users (name, email, password_hash) VALUES ($1, $1 || '@example.com', '') RETURNING user_id",
+    )
+    .bind(name)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+/// A [`user`] who has confirmed their email.
+pub async fn confirmed_user(pool: &PgPool, name: &str) -> uuid::Uuid {
+    let user_id = user(pool, name).await;
+    sqlx::query("UPDATE users SET confirmed = TRUE WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    user_id
+}
+
+/// A [`user`] who is an admin.
+pub async fn admin(pool: &PgPool, name: &str) -> uuid::Uuid {
+    let user_id = user(pool, name).await;
+    sqlx::query("UPDATE users SET is_admin = TRUE WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    user_id
+}
+
+/// Inserts a recipe without ingredients. Fails like the insert does, e.g. on a taken name.
+pub async fn try_recipe(
+    pool: &PgPool,
+    creator_id: uuid::Uuid,
+    name: &str,
+    visibility: &str,
+) -> Result<uuid::Uuid, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO recipes (name, description, creator_id, prep_time, cook_time, difficulty, steps, cuisine_id, meal_type, visibility)
+        VALUES ($1, 'A recipe', $2, 10, 20, 'easy', '{}', (SELECT id FROM cuisines WHERE name = 'Unspecified'), 'lunch', $3::recipe_visibility)
+        RETURNING id
+        "#,
+    )
+    .bind(name)
+    .bind(creator_id)
+    .bind(visibility)
+    .fetch_one(pool)
+    .await
+}
+
+/// A public recipe without ingredients.
+pub async fn recipe(pool: &PgPool, creator_id: uuid::Uuid, name: &str) -> uuid::Uuid {
+    try_recipe(pool, creator_id, name, "public").await.unwrap()
+}
+
+/// An ingredient with 100 calories and `protein`, nothing else.
+pub async fn ingredient(pool: &PgPool, name: &str, protein: f32) -> uuid::Uuid {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO ingredients (name, original_name, calories_per_100g, protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol)
+        VALUES ($1, $1, 100, $2, 0, 0, 0, 0, 0, 0, FALSE)
+        RETURNING id
+        "#,
+    )
+    .bind(name)
+    .bind(protein)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+/// Adds `quantity` grams of the ingredient to the recipe, creating it as an [`ingredient`] without
+/// protein unless there's one by that name. Returns the ingredient.
+pub async fn add_ingredient(
+    pool: &PgPool,
+    recipe_id: uuid::Uuid,
+    name: &str,
+    quantity: &str,
+) -> uuid::Uuid {
+    sqlx::query_scalar(
+        r#"
+        WITH ingredient AS (
+            INSERT INTO ingredients (name, original_name, calories_per_100g, protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol)
+            VALUES ($1, $1, 100, 0, 0, 0, 0, 0, 0, 0, FALSE)
+            ON CONFLICT (org_id, name) DO UPDATE SET name = EXCLUDED.name
+            RETURNING id
+        )
+        INSERT INTO ingredients_to_recipes (recipe_id, ingredient_id, quantity, quantity_unit)
+        SELECT $2, id, $3, 'g' FROM ingredient
+        RETURNING ingredient_id
+        "#,
+    )
+    .bind(name)
+    .bind(recipe_id)
+    .bind(quantity)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
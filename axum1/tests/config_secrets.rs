@@ -0,0 +1,48 @@
+mod common;
+
+use axum1::config::Settings;
+use serde_json::json;
+
+const SECRETS: [&str; 8] = [
+    "db-password-123",
+    "redis-password-123",
+    "redis-secret-key-123",
+    "sentry-dsn-123",
+    "postmark-token-123",
+    "meili-master-key-123",
+    "discord-secret-123",
+    "google-secret-123",
+];
+
+fn settings() -> Settings {
+    common::settings(json!({
+        "database": { "password": SECRETS[0] },
+        "redis": { "password": SECRETS[1], "secret_key": SECRETS[2] },
+        "sentry_dsn": SECRETS[3],
+        "email_client": { "authorization_token": SECRETS[4] },
+        "meili": { "master_key": SECRETS[5] },
+        "oauth": {
+            "discord": { "client_secret": SECRETS[6] },
+            "google": { "client_secret": SECRETS[7] },
+        },
+    }))
+}
+
+#[test]
+fn debug_output_redacts_every_secret() {
+    let debug = format!("{:?}", settings());
+
+    for secret in SECRETS {
+        assert!(!debug.contains(secret), "{secret} leaked into {debug}");
+    }
+    // Everything else is still there to debug with.
+    assert!(debug.contains("hummus"));
+}
+
+#[test]
+fn connection_strings_expose_the_passwords() {
+    let settings = settings();
+
+    assert!(settings.database.connection_string().contains(SECRETS[0]));
+    assert!(settings.redis.connection_string().contains(SECRETS[1]));
+}
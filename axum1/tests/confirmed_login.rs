@@ -0,0 +1,49 @@
+mod common;
+
+use axum::response::IntoResponse;
+use axum1::{error::ApiError, routes::auth::confirm::ensure_login_allowed};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn unconfirmed_users_may_log_in_by_default(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    ensure_login_allowed(&mut conn, user_id, false)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn confirmed_users_may_log_in_by_default(pool: PgPool) {
+    let user_id = common::confirmed_user(&pool, "cook").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    ensure_login_allowed(&mut conn, user_id, false)
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn unconfirmed_users_are_rejected_when_required(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let error = ensure_login_allowed(&mut conn, user_id, true)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, ApiError::EmailUnconfirmed));
+    assert_eq!(error.code(), "email_unconfirmed");
+    assert_eq!(error.into_response().status(), 403);
+}
+
+#[sqlx::test]
+async fn confirmed_users_may_log_in_when_required(pool: PgPool) {
+    let user_id = common::confirmed_user(&pool, "cook").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    ensure_login_allowed(&mut conn, user_id, true)
+        .await
+        .unwrap();
+}
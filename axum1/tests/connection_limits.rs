@@ -0,0 +1,89 @@
+use axum1::connections::{ConnectionLimits, Connections};
+
+const LIMITS: ConnectionLimits = ConnectionLimits {
+    per_user: Some(2),
+    total: Some(3),
+};
+
+#[test]
+fn connections_over_the_per_user_limit_are_refused() {
+    let connections = Connections::new();
+    let user_id = uuid::Uuid::new_v4();
+
+    let first = connections.try_open(Some(user_id), LIMITS).unwrap();
+    let second = connections.try_open(Some(user_id), LIMITS).unwrap();
+    assert!(connections.try_open(Some(user_id), LIMITS).is_none());
+    // Someone else still gets in.
+    let other = connections.try_open(Some(uuid::Uuid::new_v4()), LIMITS);
+    assert!(other.is_some());
+
+    drop(first);
+    assert_eq!(connections.open_by(user_id), 1);
+    let third = connections.try_open(Some(user_id), LIMITS);
+    assert!(third.is_some());
+    drop((second, third, other));
+    assert_eq!(connections.open_by(user_id), 0);
+    assert_eq!(connections.open(), 0);
+}
+
+#[test]
+fn connections_over_the_total_limit_are_refused() {
+    let connections = Connections::new();
+
+    let open: Vec<_> = (0..3)
+        .map(|_| connections.try_open(None, LIMITS).unwrap())
+        .collect();
+    assert!(connections.try_open(None, LIMITS).is_none());
+    assert!(connections
+        .try_open(Some(uuid::Uuid::new_v4()), LIMITS)
+        .is_none());
+
+    drop(open);
+    assert!(connections.try_open(None, LIMITS).is_some());
+}
+
+#[test]
+fn refused_connections_are_not_counted() {
+    let connections = Connections::new();
+    let user_id = uuid::Uuid::new_v4();
+    let _open: Vec<_> = (0..2)
+        .map(|_| connections.try_open(Some(user_id), LIMITS).unwrap())
+        .collect();
+
+    for _ in 0..10 {
+        assert!(connections.try_open(Some(user_id), LIMITS).is_none());
+    }
+    assert_eq!(connections.open_by(user_id), 2);
+    assert_eq!(connections.open(), 2);
+}
+
+#[test]
+fn no_limits_let_everything_in() {
+    let connections = Connections::new();
+    let user_id = uuid::Uuid::new_v4();
+
+    let _open: Vec<_> = (0..100)
+        .map(|_| {
+            connections
+                .try_open(Some(user_id), ConnectionLimits::default())
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(connections.open_by(user_id), 100);
+}
+
+/// A task owning a guard that's aborted mid-stream, like a client that vanished.
+#[tokio::test]
+async fn aborted_connections_free_their_slot() {
+    let connections = Connections::new();
+    let user_id = uuid::Uuid::new_v4();
+    let guard = connections.try_open(Some(user_id), LIMITS).unwrap();
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        std::future::pending::<()>().await;
+    });
+
+    task.abort();
+    assert!(task.await.unwrap_err().is_cancelled());
+    assert_eq!(connections.open_by(user_id), 0);
+}
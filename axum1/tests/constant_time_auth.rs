@@ -0,0 +1,90 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum1::{
+    config::TokenSettings,
+    routes::auth::{
+        reset::request_password_reset,
+        timing::{ResponseFloor, MAX_JITTER, MIN_RESPONSE_TIME},
+    },
+    telemetry::{count_statements, StatementCounterLayer},
+};
+use sqlx::PgPool;
+use tracing_subscriber::layer::SubscriberExt;
+
+async fn stored_tokens(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM forget_password_tokens")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn resets_for_existing_and_missing_users_execute_the_same_statements(pool: PgPool) {
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(StatementCounterLayer),
+    );
+    common::user(&pool, "cook").await;
+    let tokens = TokenSettings::default();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let (existing, for_existing) = count_statements(request_password_reset(
+        &mut conn,
+        &tokens,
+        "cook",
+        "cook@example.com",
+        false,
+    ))
+    .await;
+    let (missing, for_missing) = count_statements(request_password_reset(
+        &mut conn,
+        &tokens,
+        "nobody",
+        "nobody@example.com",
+        false,
+    ))
+    .await;
+
+    assert!(existing.unwrap().is_some());
+    assert!(missing.unwrap().is_none());
+    assert_eq!(stored_tokens(&pool).await, 1);
+    assert!(for_existing > 0, "the statements weren't counted");
+    assert_eq!(for_existing, for_missing);
+}
+
+/// Coarse on purpose, it only has to tell a padded response from an unpadded one.
+#[sqlx::test]
+async fn resets_for_existing_and_missing_users_take_comparable_time(pool: PgPool) {
+    common::user(&pool, "cook").await;
+    let tokens = TokenSettings::default();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let started = Instant::now();
+    request_password_reset(&mut conn, &tokens, "cook", "cook@example.com", true)
+        .await
+        .unwrap();
+    let for_existing = started.elapsed();
+    let started = Instant::now();
+    request_password_reset(&mut conn, &tokens, "nobody", "nobody@example.com", true)
+        .await
+        .unwrap();
+    let for_missing = started.elapsed();
+
+    for elapsed in [for_existing, for_missing] {
+        assert!(elapsed >= MIN_RESPONSE_TIME, "{elapsed:?}");
+    }
+    let difference = for_existing.abs_diff(for_missing);
+    assert!(
+        difference <= MAX_JITTER + Duration::from_millis(100),
+        "{for_existing:?} and {for_missing:?}"
+    );
+}
+
+#[tokio::test]
+async fn the_floor_can_be_turned_off() {
+    let started = Instant::now();
+    ResponseFloor::start(false).wait().await;
+
+    assert!(started.elapsed() < MIN_RESPONSE_TIME);
+}
@@ -0,0 +1,93 @@
+use axum::{
+    body::Body,
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
+            ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+        },
+        Method, Request,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use axum1::{config::CorsSettings, cors::cors_layer};
+use tower::ServiceExt;
+
+fn app(settings: &CorsSettings) -> Router {
+    Router::new()
+        .route("/", get(|| async {}))
+        .layer(cors_layer(settings, "http://localhost:3001/"))
+}
+
+async fn preflight(settings: &CorsSettings, origin: &str) -> Response {
+    app(settings)
+        .oneshot(
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/")
+                .header(ORIGIN, origin)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn allowed_origins_are_echoed() {
+    let settings = CorsSettings {
+        allowed_origins: Some(vec![
+            "https://a.example.com".into(),
+            "https://b.example.com".into(),
+        ]),
+        max_age_seconds: Some(3600),
+    };
+
+    let response = preflight(&settings, "https://b.example.com").await;
+
+    let headers = response.headers();
+    assert_eq!(
+        headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://b.example.com"
+    );
+    assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "3600");
+}
+
+#[tokio::test]
+async fn the_frontend_url_is_allowed_by_default() {
+    let response = preflight(&CorsSettings::default(), "http://localhost:3001").await;
+
+    assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+        "http://localhost:3001"
+    );
+    assert_eq!(response.headers()[ACCESS_CONTROL_MAX_AGE], "600");
+}
+
+#[tokio::test]
+async fn other_origins_get_no_cors_headers() {
+    let response = preflight(&CorsSettings::default(), "https://evil.example.com").await;
+
+    assert!(response
+        .headers()
+        .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+#[tokio::test]
+async fn wildcards_are_never_used() {
+    let settings = CorsSettings {
+        allowed_origins: Some(vec!["*".into()]),
+        ..Default::default()
+    };
+
+    let response = preflight(&settings, "https://evil.example.com").await;
+
+    assert!(response
+        .headers()
+        .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
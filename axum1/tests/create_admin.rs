@@ -0,0 +1,49 @@
+use axum1::cli::admin::{insert_admin, CreateAdminError};
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn admins_are_created_confirmed(pool: PgPool) {
+    let user_id = insert_admin(
+        &pool,
+        "root",
+        "root@example.com",
+        &SecretString::from("hash"),
+    )
+    .await
+    .unwrap();
+
+    let (is_admin, confirmed): (bool, bool) =
+        sqlx::query_as("SELECT is_admin, confirmed FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(is_admin && confirmed);
+}
+
+#[sqlx::test]
+async fn existing_users_are_left_alone(pool: PgPool) {
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash) VALUES ('cook', 'cook@example.com', 'old')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let result = insert_admin(
+        &pool,
+        "root",
+        "Cook@Example.com",
+        &SecretString::from("new"),
+    )
+    .await;
+
+    assert!(matches!(result, Err(CreateAdminError::EmailTaken(_))));
+    let (password_hash, is_admin): (String, bool) =
+        sqlx::query_as("SELECT password_hash, is_admin FROM users WHERE name = 'cook'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!((password_hash.as_str(), is_admin), ("old", false));
+}
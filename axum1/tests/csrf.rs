@@ -0,0 +1,169 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+        Request, StatusCode,
+    },
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Router,
+};
+use axum1::csrf::{csrf_protect, csrf_token, CSRF_HEADER};
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn app(enabled: bool) -> Router {
+    Router::new()
+        .route("/csrf", get(csrf_token))
+        .route("/recipes", post(|| async {}).get(|| async {}))
+        .route("/digest/unsubscribe", post(|| async {}))
+        .route("/v1/digest/unsubscribe", post(|| async {}))
+        .layer(from_fn_with_state(enabled, csrf_protect))
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+}
+
+/// Fetches a token, returning it with the session cookie it belongs to.
+async fn token(app: &Router) -> (String, String) {
+    let response = app
+        .clone()
+        .oneshot(Request::get("/csrf").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let cookie = response.headers()[SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_owned();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    (cookie, body["token"].as_str().unwrap().to_owned())
+}
+
+async fn post_recipe(app: &Router, cookie: Option<&str>, token: Option<&str>) -> StatusCode {
+    let mut request = Request::post("/recipes");
+    if let Some(cookie) = cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    if let Some(token) = token {
+        request = request.header(CSRF_HEADER, token);
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn valid_token_is_accepted() {
+    let app = app(true);
+    let (cookie, token) = token(&app).await;
+
+    assert_eq!(
+        post_recipe(&app, Some(&cookie), Some(&token)).await,
+        StatusCode::OK
+    );
+    // The token isn't single use, concurrent requests of a page may share it.
+    assert_eq!(
+        post_recipe(&app, Some(&cookie), Some(&token)).await,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn missing_token_is_rejected() {
+    let app = app(true);
+    let (cookie, _) = token(&app).await;
+
+    assert_eq!(
+        post_recipe(&app, Some(&cookie), None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(post_recipe(&app, None, None).await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invalid_token_is_rejected() {
+    let app = app(true);
+    let (cookie, _) = token(&app).await;
+    let (_, other_sessions_token) = token(&app).await;
+
+    assert_eq!(
+        post_recipe(&app, Some(&cookie), Some("forged")).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        post_recipe(&app, Some(&cookie), Some(&other_sessions_token)).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn safe_methods_and_header_auth_are_exempt() {
+    let app = app(true);
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/recipes").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/recipes")
+                .header(AUTHORIZATION, "Bearer token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn header_auth_with_a_session_cookie_needs_the_token() {
+    let app = app(true);
+    let (cookie, _) = token(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/recipes")
+                .header(AUTHORIZATION, "Bearer token")
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn protection_can_be_turned_off() {
+    assert_eq!(post_recipe(&app(false), None, None).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn token_authenticated_paths_are_exempt() {
+    let app = app(true);
+    let (cookie, _) = token(&app).await;
+
+    for uri in ["/digest/unsubscribe", "/v1/digest/unsubscribe"] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post(uri)
+                    .header(COOKIE, &cookie)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    }
+}
@@ -0,0 +1,46 @@
+use axum1::routes::ingredient::diet::{dietary_profile, Compliance, Diet, DietaryFlags};
+
+fn flags(vegan: Option<bool>, contains_nuts: Option<bool>) -> DietaryFlags {
+    DietaryFlags {
+        vegan,
+        vegetarian: Some(true),
+        gluten_free: Some(true),
+        contains_nuts,
+    }
+}
+
+#[test]
+fn recipe_fits_a_diet_only_if_every_ingredient_does() {
+    let profile = dietary_profile(&[flags(Some(true), Some(false)), flags(Some(true), None)]);
+
+    assert_eq!(profile.get(Diet::Vegan), Compliance::Yes);
+    assert_eq!(profile.get(Diet::Vegetarian), Compliance::Yes);
+}
+
+#[test]
+fn one_non_compliant_ingredient_rules_the_diet_out() {
+    let profile = dietary_profile(&[
+        flags(None, Some(true)),
+        flags(Some(false), Some(false)),
+        flags(Some(true), None),
+    ]);
+
+    assert_eq!(profile.get(Diet::Vegan), Compliance::No);
+    assert_eq!(profile.get(Diet::NutFree), Compliance::No);
+}
+
+#[test]
+fn unknown_ingredients_leave_the_diet_unverified() {
+    let profile = dietary_profile(&[flags(Some(true), Some(false)), flags(None, None)]);
+
+    assert_eq!(profile.get(Diet::Vegan), Compliance::Unverified);
+    assert_eq!(profile.get(Diet::NutFree), Compliance::Unverified);
+    assert_eq!(profile.get(Diet::GlutenFree), Compliance::Yes);
+}
+
+#[test]
+fn recipe_without_ingredients_is_unverified() {
+    let profile = dietary_profile(&[]);
+
+    assert_eq!(profile.get(Diet::Vegetarian), Compliance::Unverified);
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use axum1::{
+    email::{EmailClient, EmailLinks},
+    queue::digest::{matching_recipes, send_next_digest, DigestOutcome},
+    routes::auth::{self, digest::unsubscribe_by_token},
+};
+use secrecy::SecretString;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+// Nothing listens there, so every delivery attempt fails fast.
+fn email_client() -> EmailClient {
+    EmailClient::new(
+        "http://127.0.0.1:9".into(),
+        "recipes@example.com".into(),
+        SecretString::from("token"),
+        Duration::from_millis(200),
+    )
+}
+
+fn links() -> EmailLinks {
+    EmailLinks::new("http://localhost:3001", "http://localhost:3000")
+}
+
+async fn frequency(pool: &PgPool) -> String {
+    sqlx::query_scalar("SELECT frequency::TEXT FROM digest_preferences")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn recipe(pool: &PgPool, name: &str, creator_id: uuid::Uuid, created_at: &str) {
+    let recipe_id = common::recipe(pool, creator_id, name).await;
+    sqlx::query(&format!(
+        "UPDATE recipes SET created_at = {created_at} WHERE id = $1"
+    ))
+    .bind(recipe_id)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn subscribe(pool: &PgPool, user_id: uuid::Uuid, topics: &[&str], time_zone: &str) {
+    sqlx::query(
+        "INSERT INTO digest_preferences (user_id, topics, time_zone, unsubscribe_token) VALUES ($1, $2, $3, $1::text)",
+    )
+    .bind(user_id)
+    .bind(topics)
+    .bind(time_zone)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+const LAST_WEEK: &str = "date_trunc('week', NOW()) - INTERVAL '1 day'";
+const THIS_WEEK: &str = "date_trunc('week', NOW()) + INTERVAL '1 minute'";
+
+#[sqlx::test]
+async fn only_last_weeks_matching_recipes_are_listed(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    let cook = common::user(&pool, "cook").await;
+    recipe(&pool, "goulash", cook, LAST_WEEK).await;
+    recipe(&pool, "lecso", cook, THIS_WEEK).await;
+    recipe(&pool, "my own", reader, LAST_WEEK).await;
+
+    let (start, end): (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>) =
+        sqlx::query_as(
+            "SELECT date_trunc('week', NOW()) - INTERVAL '1 week', date_trunc('week', NOW())",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let topics = ["Unspecified".to_owned()];
+    let recipes = matching_recipes(&mut conn, uuid::Uuid::nil(), reader, &topics, start, end)
+        .await
+        .unwrap();
+    let names: Vec<_> = recipes.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["goulash"]);
+
+    let recipes = matching_recipes(&mut conn, uuid::Uuid::nil(), reader, &[], start, end)
+        .await
+        .unwrap();
+    assert!(recipes.is_empty());
+}
+
+#[sqlx::test]
+async fn users_without_matching_content_get_no_email(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    let cook = common::user(&pool, "cook").await;
+    recipe(&pool, "goulash", cook, LAST_WEEK).await;
+    subscribe(&pool, reader, &["Italian"], "UTC").await;
+
+    let outcome = send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+    assert_eq!(outcome, Some(DigestOutcome::NothingNew));
+    let failed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM failed_jobs")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(failed, 0);
+
+    // The week is done either way.
+    let outcome = send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+    assert_eq!(outcome, None);
+}
+
+#[sqlx::test]
+async fn matching_content_is_delivered_once_a_week(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    let cook = common::user(&pool, "cook").await;
+    recipe(&pool, "goulash", cook, LAST_WEEK).await;
+    subscribe(&pool, reader, &["Unspecified"], "UTC").await;
+
+    let outcome = send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+    assert_eq!(outcome, Some(DigestOutcome::Failed));
+    let job_type: String = sqlx::query_scalar("SELECT job_type FROM failed_jobs")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(job_type, "digest_delivery");
+
+    let outcome = send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+    assert_eq!(outcome, None);
+}
+
+#[sqlx::test]
+async fn weeks_end_on_monday_midnight_in_the_users_time_zone(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    subscribe(&pool, reader, &[], "Pacific/Kiritimati").await;
+
+    send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+
+    let (day, hour): (f64, f64) = sqlx::query_as(
+        r#"
+        SELECT EXTRACT(isodow FROM last_period_end AT TIME ZONE time_zone)::float8,
+            EXTRACT(hour FROM last_period_end AT TIME ZONE time_zone)::float8
+        FROM digest_preferences
+        "#,
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((day, hour), (1.0, 0.0));
+}
+
+#[sqlx::test]
+async fn unsubscribing_needs_only_the_token(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    let cook = common::user(&pool, "cook").await;
+    recipe(&pool, "goulash", cook, LAST_WEEK).await;
+    subscribe(&pool, reader, &["Unspecified"], "UTC").await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    assert!(!unsubscribe_by_token(&mut conn, "unknown").await.unwrap());
+    assert!(unsubscribe_by_token(&mut conn, &reader.to_string())
+        .await
+        .unwrap());
+
+    let outcome = send_next_digest(&pool, &email_client(), &links())
+        .await
+        .unwrap();
+    assert_eq!(outcome, None);
+}
+
+#[sqlx::test]
+async fn the_unsubscribe_link_asks_before_unsubscribing(pool: PgPool) {
+    let reader = common::user(&pool, "reader").await;
+    subscribe(&pool, reader, &[], "UTC").await;
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .merge(auth::router(state.clone()))
+        .layer(SessionManagerLayer::new(MemoryStore::default()).with_secure(false))
+        .with_state(state);
+    let uri = format!("/digest/unsubscribe?token={reader}&lang=hu");
+
+    let response = app
+        .clone()
+        .oneshot(Request::get(&uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .contains("<form method=\"post\">"));
+    assert_eq!(frequency(&pool).await, "weekly");
+
+    let response = app
+        .clone()
+        .oneshot(Request::post(&uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(frequency(&pool).await, "off");
+
+    let response = app
+        .oneshot(
+            Request::post("/digest/unsubscribe?token=unknown")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,59 @@
+use axum1::config::AuthSettings;
+
+fn allowing(domains: &[&str]) -> AuthSettings {
+    AuthSettings {
+        allowed_email_domains: domains.iter().map(ToString::to_string).collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn anyone_may_sign_up_by_default() {
+    let settings = AuthSettings::default();
+
+    assert!(settings.is_email_domain_allowed("cook@example.com"));
+    assert!(settings.is_email_domain_allowed("cook@company.com"));
+}
+
+#[test]
+fn only_listed_domains_are_allowed() {
+    let settings = allowing(&["company.com", "partner.org"]);
+
+    assert!(settings.is_email_domain_allowed("cook@company.com"));
+    assert!(settings.is_email_domain_allowed("cook@partner.org"));
+    assert!(!settings.is_email_domain_allowed("cook@example.com"));
+    assert!(!settings.is_email_domain_allowed("cook@company.com.evil.com"));
+    assert!(!settings.is_email_domain_allowed("cook@notcompany.com"));
+    assert!(!settings.is_email_domain_allowed("not an email"));
+}
+
+#[test]
+fn domains_match_case_insensitively() {
+    let settings = allowing(&["Company.COM"]);
+
+    assert!(settings.is_email_domain_allowed("Cook@COMPANY.com"));
+}
+
+#[test]
+fn subdomains_need_a_wildcard() {
+    let exact = allowing(&["company.com"]);
+    assert!(!exact.is_email_domain_allowed("cook@eu.company.com"));
+
+    let wildcard = allowing(&["*.company.com"]);
+    assert!(wildcard.is_email_domain_allowed("cook@eu.company.com"));
+    assert!(wildcard.is_email_domain_allowed("cook@kitchen.eu.company.com"));
+    assert!(!wildcard.is_email_domain_allowed("cook@company.com"));
+    assert!(!wildcard.is_email_domain_allowed("cook@evilcompany.com"));
+
+    let both = allowing(&["company.com", "*.company.com"]);
+    assert!(both.is_email_domain_allowed("cook@company.com"));
+    assert!(both.is_email_domain_allowed("cook@eu.company.com"));
+}
+
+#[test]
+fn the_last_at_sign_starts_the_domain() {
+    let settings = allowing(&["company.com"]);
+
+    assert!(settings.is_email_domain_allowed("\"a@b\"@company.com"));
+    assert!(!settings.is_email_domain_allowed("cook@company.com@example.com"));
+}
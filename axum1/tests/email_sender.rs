@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use axum1::{
+    config::EmailClientSettings,
+    email::{
+        confirmation_message, digest_message, password_reset_message, Email, EmailClient,
+        EmailLinks,
+    },
+    locale::Locale,
+    queue::digest::DigestRecipe,
+};
+use secrecy::SecretString;
+
+fn settings() -> EmailClientSettings {
+    EmailClientSettings {
+        base_url: "http://127.0.0.1:9".into(),
+        sender_email: "recipes@example.com".into(),
+        from_name: None,
+        reply_to: None,
+        sending_domain: None,
+        authorization_token: SecretString::from("token"),
+        timeout_milliseconds: 200,
+        selfcheck_sink: None,
+    }
+}
+
+#[test]
+fn from_header_defaults_to_the_app_name() {
+    assert_eq!(
+        settings().client().from_header(),
+        r#""Recipe App" <recipes@example.com>"#
+    );
+}
+
+#[test]
+fn from_name_is_quoted() {
+    let client = EmailClient::new(
+        "http://127.0.0.1:9".into(),
+        "recipes@example.com".into(),
+        SecretString::from("token"),
+        Duration::from_millis(200),
+    );
+    assert_eq!(client.from_header(), "recipes@example.com");
+
+    let client = client.with_from_name(r#"The "Best" Recipes"#);
+    assert_eq!(
+        client.from_header(),
+        r#""The \"Best\" Recipes" <recipes@example.com>"#
+    );
+}
+
+#[test]
+fn invalid_reply_to_is_rejected() {
+    let mut settings = settings();
+    settings.reply_to = Some("not an address".into());
+    assert!(settings.reply_to().is_err());
+
+    settings.reply_to = Some("support@example.com".into());
+    assert!(settings.reply_to().unwrap().is_some());
+}
+
+#[test]
+fn links_lead_to_the_configured_frontend() {
+    let links = EmailLinks::new("https://recipes.example.com/", "https://api.example.com");
+    let to = || Email::parse("cook@example.com".to_owned()).unwrap();
+
+    let message = confirmation_message(to(), Locale::Hu, &links, "abc");
+    assert!(
+        format!("{message:?}").contains("https://recipes.example.com/confirm?token=abc&lang=hu")
+    );
+    let message = password_reset_message(to(), Locale::En, &links, "abc");
+    assert!(format!("{message:?}")
+        .contains("https://recipes.example.com/forget_password?token=abc&lang=en"));
+}
+
+#[test]
+fn digests_unsubscribe_at_the_api() {
+    let links = EmailLinks::new("https://recipes.example.com", "https://api.example.com/");
+    let to = Email::parse("cook@example.com".to_owned()).unwrap();
+    let recipes = [DigestRecipe {
+        name: "Goulash".to_owned(),
+        description: "A stew".to_owned(),
+    }];
+
+    let message = format!(
+        "{:?}",
+        digest_message(to, Locale::En, &links, &recipes, "abc")
+    );
+    assert!(message.contains(
+        r#"<a href=\"https://api.example.com/digest/unsubscribe?token=abc&amp;lang=en\">"#
+    ));
+    assert!(message.contains("Unsubscribe from these emails: https://api.example.com/digest/unsubscribe?token=abc&lang=en"));
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use axum1::{
+    error::ApiError,
+    org::Org,
+    routes::recipe::{
+        self,
+        favorite::{
+            set_favorite_recipe, set_favorite_recipes, BulkFavoriteOutcome, BulkFavoriteResponse,
+            Favoriter,
+        },
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+async fn seed(pool: &PgPool) -> uuid::Uuid {
+    let user_id = common::user(pool, "cook").await;
+    common::recipe(pool, user_id, "goulash").await;
+    user_id
+}
+
+async fn stored_rows(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn double_submit_stores_a_single_favorite(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    let favoriter = Favoriter::User(user_id);
+
+    let mut first = pool.acquire().await.unwrap();
+    let mut second = pool.acquire().await.unwrap();
+    let (first, second) = tokio::join!(
+        set_favorite_recipe(&mut first, Org::DEFAULT, favoriter, "goulash", true),
+        set_favorite_recipe(&mut second, Org::DEFAULT, favoriter, "goulash", true),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    assert!(first.favorited && second.favorited);
+    assert_eq!(stored_rows(&pool, "favorite_recipe").await, 1);
+
+    let mut conn = pool.acquire().await.unwrap();
+    let state = set_favorite_recipe(&mut conn, Org::DEFAULT, favoriter, "goulash", true)
+        .await
+        .unwrap();
+    assert_eq!(state.favorite_count, 1);
+}
+
+#[sqlx::test]
+async fn repeated_unfavorite_is_a_no_op(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    let favoriter = Favoriter::User(user_id);
+    let mut conn = pool.acquire().await.unwrap();
+
+    set_favorite_recipe(&mut conn, Org::DEFAULT, favoriter, "goulash", true)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        let state = set_favorite_recipe(&mut conn, Org::DEFAULT, favoriter, "goulash", false)
+            .await
+            .unwrap();
+        assert!(!state.favorited);
+        assert_eq!(state.favorite_count, 0);
+    }
+}
+
+#[sqlx::test]
+async fn guest_double_submit_stores_a_single_favorite(pool: PgPool) {
+    seed(&pool).await;
+    let favoriter = Favoriter::Guest(uuid::Uuid::new_v4());
+
+    let mut first = pool.acquire().await.unwrap();
+    let mut second = pool.acquire().await.unwrap();
+    let (first, second) = tokio::join!(
+        set_favorite_recipe(&mut first, Org::DEFAULT, favoriter, "goulash", true),
+        set_favorite_recipe(&mut second, Org::DEFAULT, favoriter, "goulash", true),
+    );
+    first.unwrap();
+    second.unwrap();
+
+    assert_eq!(stored_rows(&pool, "guest_favorite_recipes").await, 1);
+}
+
+#[sqlx::test]
+async fn bulk_favorites_report_each_recipe(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    let favoriter = Favoriter::User(user_id);
+    let names = ["goulash".to_owned(), "unknown".to_owned()];
+
+    let mut conn = pool.acquire().await.unwrap();
+    let added = set_favorite_recipes(&mut conn, Org::DEFAULT, favoriter, &names, true)
+        .await
+        .unwrap();
+    assert_eq!(added["goulash"], BulkFavoriteOutcome::Added);
+    assert_eq!(added["unknown"], BulkFavoriteOutcome::NotFound);
+
+    let again = set_favorite_recipes(&mut conn, Org::DEFAULT, favoriter, &names, true)
+        .await
+        .unwrap();
+    assert_eq!(again["goulash"], BulkFavoriteOutcome::AlreadyPresent);
+    assert_eq!(stored_rows(&pool, "favorite_recipe").await, 1);
+
+    for expected in [
+        BulkFavoriteOutcome::Removed,
+        BulkFavoriteOutcome::NotPresent,
+    ] {
+        let removed = set_favorite_recipes(&mut conn, Org::DEFAULT, favoriter, &names, false)
+            .await
+            .unwrap();
+        assert_eq!(removed["goulash"], expected);
+    }
+    assert_eq!(stored_rows(&pool, "favorite_recipe").await, 0);
+}
+
+#[sqlx::test]
+async fn bulk_results_are_keyed_by_the_requested_names(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    let store = MemoryStore::default();
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+
+    // Recipe names are case-insensitive, so these are all the same recipe.
+    let request = Request::put("/r/favorites")
+        .header(COOKIE, common::logged_in(&store, user_id).await)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "names": ["Goulash", "GOULASH", "goulash"] }).to_string(),
+        ))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let response: BulkFavoriteResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        response.results.into_iter().collect::<Vec<_>>(),
+        [("Goulash".to_owned(), BulkFavoriteOutcome::Added)]
+    );
+    assert_eq!(stored_rows(&pool, "favorite_recipe").await, 1);
+}
+
+#[sqlx::test]
+async fn private_and_hidden_recipes_of_others_are_not_found(pool: PgPool) {
+    let owner = seed(&pool).await;
+    let other = common::user(&pool, "other").await;
+    let admin = common::admin(&pool, "admin").await;
+    common::try_recipe(&pool, owner, "secret", "private")
+        .await
+        .unwrap();
+    let retired = common::recipe(&pool, owner, "retired").await;
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE id = $1")
+        .bind(retired)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    for favoriter in [
+        Favoriter::User(other),
+        Favoriter::Guest(uuid::Uuid::new_v4()),
+    ] {
+        for name in ["secret", "retired"] {
+            let result = set_favorite_recipe(&mut conn, Org::DEFAULT, favoriter, name, true).await;
+            assert!(matches!(result, Err(ApiError::NotFound)), "{name}");
+        }
+        let names = ["secret".to_owned(), "retired".to_owned()];
+        let results = set_favorite_recipes(&mut conn, Org::DEFAULT, favoriter, &names, true)
+            .await
+            .unwrap();
+        assert!(results
+            .values()
+            .all(|r| *r == BulkFavoriteOutcome::NotFound));
+    }
+    assert_eq!(stored_rows(&pool, "favorite_recipe").await, 0);
+    assert_eq!(stored_rows(&pool, "guest_favorite_recipes").await, 0);
+
+    // The owner sees both, admins see private recipes like on the detail page.
+    for name in ["secret", "retired"] {
+        set_favorite_recipe(&mut conn, Org::DEFAULT, Favoriter::User(owner), name, true)
+            .await
+            .unwrap();
+    }
+    set_favorite_recipe(
+        &mut conn,
+        Org::DEFAULT,
+        Favoriter::User(admin),
+        "secret",
+        true,
+    )
+    .await
+    .unwrap();
+}
+
+#[sqlx::test]
+async fn hidden_recipes_drop_out_of_the_favorites_of_others(pool: PgPool) {
+    let owner = seed(&pool).await;
+    let other = common::user(&pool, "other").await;
+    let guest_id = uuid::Uuid::new_v4();
+    let mut conn = pool.acquire().await.unwrap();
+    for favoriter in [
+        Favoriter::User(owner),
+        Favoriter::User(other),
+        Favoriter::Guest(guest_id),
+    ] {
+        set_favorite_recipe(&mut conn, Org::DEFAULT, favoriter, "goulash", true)
+            .await
+            .unwrap();
+    }
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE name = 'goulash'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let store = MemoryStore::default();
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+    let favorites = |cookie: String| {
+        let app = app.clone();
+        async move {
+            let request = Request::get("/r/action/favorites")
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let favorites: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            favorites["total"].as_i64().unwrap()
+        }
+    };
+
+    // Still listed to the owner, like the detail page still shows it to them.
+    assert_eq!(favorites(common::logged_in(&store, owner).await).await, 1);
+    assert_eq!(favorites(common::logged_in(&store, other).await).await, 0);
+    assert_eq!(favorites(common::guest(&store, guest_id).await).await, 0);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{COOKIE, SET_COOKIE},
+        Method, Request, StatusCode,
+    },
+    middleware::from_fn_with_state,
+    response::Response,
+    Router,
+};
+use axum1::routes::{
+    admin,
+    auth::{self, impersonation::audit_impersonation},
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn app(pool: PgPool, store: MemoryStore) -> Router {
+    let state = common::state(pool, common::settings(json!({})));
+    Router::new()
+        .nest("/admin", admin::router(state.clone()))
+        .merge(auth::router(state.clone()))
+        .layer(from_fn_with_state(state.clone(), audit_impersonation))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .with_state(state)
+}
+
+async fn send(app: &Router, method: Method, uri: &str, cookie: &str) -> Response {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+/// The cookie the response replaced the session cookie with.
+fn new_cookie(response: &Response) -> String {
+    let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_owned()
+}
+
+async fn get_json(app: &Router, uri: &str, cookie: &str) -> (StatusCode, serde_json::Value) {
+    let response = send(app, Method::GET, uri, cookie).await;
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn me(app: &Router, cookie: &str) -> String {
+    let (_, me) = get_json(app, "/me", cookie).await;
+    me["name"].as_str().unwrap().to_owned()
+}
+
+#[sqlx::test]
+async fn other_admins_are_only_impersonated_when_confirmed(pool: PgPool) {
+    let store = MemoryStore::default();
+    let app = app(pool.clone(), store.clone());
+    let admin = common::admin(&pool, "admin").await;
+    let other_admin = common::admin(&pool, "other_admin").await;
+    let cookie = common::logged_in(&store, admin).await;
+
+    let uri = format!("/admin/users/{other_admin}/impersonate");
+    let response = send(&app, Method::POST, &uri, &cookie).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let uri = format!("/admin/users/{other_admin}/impersonate?confirm_admin=true");
+    let response = send(&app, Method::POST, &uri, &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(me(&app, &new_cookie(&response)).await, "other_admin");
+}
+
+#[sqlx::test]
+async fn impersonations_do_not_nest(pool: PgPool) {
+    let store = MemoryStore::default();
+    let app = app(pool.clone(), store.clone());
+    let admin = common::admin(&pool, "admin").await;
+    let other_admin = common::admin(&pool, "other_admin").await;
+    let cook = common::user(&pool, "cook").await;
+    let cookie = common::logged_in(&store, admin).await;
+
+    let uri = format!("/admin/users/{other_admin}/impersonate?confirm_admin=true");
+    let response = send(&app, Method::POST, &uri, &cookie).await;
+    let cookie = new_cookie(&response);
+
+    // Acting as another admin, but still the same session.
+    let uri = format!("/admin/users/{cook}/impersonate");
+    let response = send(&app, Method::POST, &uri, &cookie).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(me(&app, &cookie).await, "other_admin");
+}
+
+#[sqlx::test]
+async fn stopping_returns_to_the_admin_and_changes_are_audited(pool: PgPool) {
+    let store = MemoryStore::default();
+    let app = app(pool.clone(), store.clone());
+    let admin = common::admin(&pool, "admin").await;
+    let cook = common::user(&pool, "cook").await;
+    let cookie = common::logged_in(&store, admin).await;
+
+    let uri = format!("/admin/users/{cook}/impersonate");
+    let response = send(&app, Method::POST, &uri, &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = new_cookie(&response);
+    assert_eq!(me(&app, &cookie).await, "cook");
+
+    let response = send(&app, Method::POST, "/me/stop_impersonation", &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(me(&app, &new_cookie(&response)).await, "admin");
+
+    // Reading `/me` changed nothing, so only stopping is on record.
+    let audited: Vec<(String, String, i16)> = sqlx::query_as(
+        r#"
+        SELECT r.method, r.path, r.status
+        FROM impersonated_requests r
+        JOIN impersonations i ON i.id = r.impersonation_id
+        WHERE i.admin_id = $1 AND i.user_id = $2
+        "#,
+    )
+    .bind(admin)
+    .bind(cook)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        audited,
+        [("POST".to_owned(), "/me/stop_impersonation".to_owned(), 200)]
+    );
+}
+
+#[sqlx::test]
+async fn audited_requests_are_listed_to_admins_and_the_user(pool: PgPool) {
+    let store = MemoryStore::default();
+    let app = app(pool.clone(), store.clone());
+    let admin = common::admin(&pool, "admin").await;
+    let cook = common::user(&pool, "cook").await;
+    let other_cook = common::user(&pool, "other_cook").await;
+    let admin_cookie = common::logged_in(&store, admin).await;
+
+    let uri = format!("/admin/users/{cook}/impersonate");
+    let response = send(&app, Method::POST, &uri, &admin_cookie).await;
+    let cookie = new_cookie(&response);
+    let response = send(&app, Method::POST, "/me/stop_impersonation", &cookie).await;
+    let admin_cookie = new_cookie(&response);
+
+    let (status, audit) = get_json(&app, "/admin/audit", &admin_cookie).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(audit["total"], 1);
+    assert_eq!(audit["items"][0]["admin"], "admin");
+    assert_eq!(audit["items"][0]["user"], "cook");
+    assert_eq!(audit["items"][0]["path"], "/me/stop_impersonation");
+
+    let cook_cookie = common::logged_in(&store, cook).await;
+    let (_, activity) = get_json(&app, "/me/activity", &cook_cookie).await;
+    assert_eq!(activity["total"], 1);
+    assert_eq!(activity["items"][0]["method"], "POST");
+    assert!(activity["items"][0].get("admin").is_none());
+    let other_cookie = common::logged_in(&store, other_cook).await;
+    let (_, activity) = get_json(&app, "/me/activity", &other_cookie).await;
+    assert_eq!(activity["total"], 0);
+}
+
+#[sqlx::test]
+async fn audited_requests_are_scoped_by_time(pool: PgPool) {
+    let store = MemoryStore::default();
+    let app = app(pool.clone(), store.clone());
+    let admin = common::admin(&pool, "admin").await;
+    let cook = common::user(&pool, "cook").await;
+    let cookie = common::logged_in(&store, admin).await;
+    let impersonation: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO impersonations (admin_id, user_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(admin)
+    .bind(cook)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    for created_at in [
+        "2026-10-14T12:00:00Z",
+        "2026-10-15T00:00:00Z",
+        "2026-10-16T00:00:00Z",
+    ] {
+        sqlx::query(
+            "INSERT INTO impersonated_requests (impersonation_id, method, path, status, created_at) VALUES ($1, 'POST', '/r', 201, $2::timestamptz)",
+        )
+        .bind(impersonation)
+        .bind(created_at)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    // `since` is inclusive, `until` exclusive.
+    let uri = "/admin/audit?since=2026-10-15T00:00:00Z&until=2026-10-16T00:00:00Z";
+    let (status, audit) = get_json(&app, uri, &cookie).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(audit["total"], 1);
+    assert_eq!(audit["items"][0]["created_at"], "2026-10-15T00:00:00Z");
+
+    let uri = "/admin/audit?since=2026-10-15T00:00:00Z&per_page=1&page=2";
+    let (_, audit) = get_json(&app, uri, &cookie).await;
+    assert_eq!(audit["total"], 2);
+    assert_eq!(audit["items"][0]["created_at"], "2026-10-15T00:00:00Z");
+
+    let (status, body) = get_json(&app, "/admin/audit?until=tomorrow", &cookie).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["until"].is_array());
+}
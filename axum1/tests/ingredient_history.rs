@@ -0,0 +1,199 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use axum1::{
+    error::ApiError,
+    org::Org,
+    pagination::Pagination,
+    routes::ingredient::{
+        self,
+        history::{ingredient_history, revert_ingredient, IngredientRevision},
+        suggestion::apply_ingredient_suggestion,
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+const ALL: Pagination = Pagination {
+    page: 1,
+    per_page: 50,
+};
+
+async fn suggest(
+    pool: &PgPool,
+    ingredient_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    name: Option<&str>,
+    protein: f32,
+) -> uuid::Uuid {
+    sqlx::query_scalar(
+        "INSERT INTO ingredient_suggestions (ingredient_id, user_id, name, protein) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(ingredient_id)
+    .bind(user_id)
+    .bind(name)
+    .bind(protein)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+async fn apply(pool: &PgPool, moderator: uuid::Uuid, name: &str, id: uuid::Uuid) {
+    let mut tx = pool.begin().await.unwrap();
+    apply_ingredient_suggestion(&mut tx, Org::DEFAULT, moderator, name, id)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+}
+
+async fn history(pool: &PgPool, name: &str) -> Vec<IngredientRevision> {
+    let mut conn = pool.acquire().await.unwrap();
+    ingredient_history(&mut conn, Org::DEFAULT, name, ALL)
+        .await
+        .unwrap()
+        .items
+}
+
+/// The protein of the recipe, as cached.
+async fn cached_protein(pool: &PgPool, recipe_id: uuid::Uuid) -> f32 {
+    sqlx::query_scalar("SELECT protein FROM recipe_nutrition WHERE recipe_id = $1")
+        .bind(recipe_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn applied_suggestions_are_recorded_after_the_original(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    let cook = common::user(&pool, "cook").await;
+    let other_cook = common::user(&pool, "other_cook").await;
+    let onion = common::ingredient(&pool, "Onion", 1.0).await;
+    let first = suggest(&pool, onion, cook, None, 1.5).await;
+    let second = suggest(&pool, onion, other_cook, None, 1.1).await;
+
+    apply(&pool, moderator, "Onion", first).await;
+    apply(&pool, moderator, "Onion", second).await;
+
+    let revisions = history(&pool, "Onion").await;
+    let summary: Vec<_> = revisions
+        .iter()
+        .map(|r| {
+            (
+                r.revision,
+                r.ingredient.protein,
+                r.applied_by.clone(),
+                r.suggestion_ids.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (3, 1.1, Some("moderator".to_owned()), vec![second]),
+            (2, 1.5, Some("moderator".to_owned()), vec![first]),
+            (1, 1.0, None, vec![]),
+        ]
+    );
+    assert!(revisions.iter().all(|r| r.reverted_to.is_none()));
+}
+
+#[sqlx::test]
+async fn direct_edits_are_recorded(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    common::ingredient(&pool, "Onion", 1.0).await;
+    let soup = common::recipe(&pool, admin, "Onion Soup").await;
+    common::add_ingredient(&pool, soup, "Onion", "200").await;
+    let store = MemoryStore::default();
+    let cookie = common::logged_in(&store, admin).await;
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/i", ingredient::router(state.clone()))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .with_state(state);
+
+    let request = Request::patch("/i/Onion")
+        .header(COOKIE, cookie)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("protein=1.5"))
+        .unwrap();
+    assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+    let revisions = history(&pool, "Onion").await;
+    let summary: Vec<_> = revisions
+        .iter()
+        .map(|r| (r.revision, r.ingredient.protein, r.applied_by.clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        [(2, 1.5, Some("admin".to_owned())), (1, 1.0, None)]
+    );
+    assert_eq!(cached_protein(&pool, soup).await, 3.0);
+}
+
+#[sqlx::test]
+async fn applying_and_reverting_refresh_the_nutrition_of_recipes(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    let cook = common::user(&pool, "cook").await;
+    let soup = common::recipe(&pool, cook, "Onion Soup").await;
+    let onion = common::add_ingredient(&pool, soup, "Onion", "200").await;
+    let suggestion = suggest(&pool, onion, cook, None, 1.5).await;
+
+    apply(&pool, moderator, "Onion", suggestion).await;
+    assert_eq!(cached_protein(&pool, soup).await, 3.0);
+
+    let mut tx = pool.begin().await.unwrap();
+    revert_ingredient(&mut tx, Org::DEFAULT, moderator, "Onion", 1)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(cached_protein(&pool, soup).await, 0.0);
+}
+
+#[sqlx::test]
+async fn reverting_restores_a_revision_as_a_new_one(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    let cook = common::user(&pool, "cook").await;
+    let onion = common::ingredient(&pool, "Onion", 1.0).await;
+    let rename = suggest(&pool, onion, cook, Some("Red onion"), 1.5).await;
+    apply(&pool, moderator, "Onion", rename).await;
+
+    let mut tx = pool.begin().await.unwrap();
+    let reverted = revert_ingredient(&mut tx, Org::DEFAULT, moderator, "Red onion", 1)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    assert_eq!(reverted.name, "Onion");
+    assert_eq!(reverted.protein, 1.0);
+    let revisions = history(&pool, "Onion").await;
+    assert_eq!(revisions.len(), 3);
+    assert_eq!(revisions[0].revision, 3);
+    assert_eq!(revisions[0].reverted_to, Some(1));
+    assert_eq!(revisions[0].applied_by.as_deref(), Some("moderator"));
+    assert_eq!(revisions[0].ingredient, revisions[2].ingredient);
+    // The reverted change is still there.
+    assert_eq!(revisions[1].ingredient.name, "Red onion");
+}
+
+#[sqlx::test]
+async fn missing_revisions_and_ingredients_are_not_found(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    common::ingredient(&pool, "Onion", 1.0).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    assert!(history(&pool, "Onion").await.is_empty());
+    let result = revert_ingredient(&mut conn, Org::DEFAULT, moderator, "Onion", 1).await;
+    assert!(matches!(result, Err(ApiError::NotFound)));
+    let result = ingredient_history(&mut conn, Org::DEFAULT, "Garlic", ALL).await;
+    assert!(matches!(result, Err(ApiError::NotFound)));
+}
@@ -0,0 +1,286 @@
+mod common;
+
+use axum1::{
+    error::ApiError,
+    org::Org,
+    routes::admin::merge::{merge_ingredients, DuplicatePolicy, MergeIngredients, NutritionPolicy},
+};
+use sqlx::PgPool;
+
+async fn recipe(
+    pool: &PgPool,
+    creator_id: uuid::Uuid,
+    name: &str,
+    ingredients: &[(uuid::Uuid, &str)],
+) {
+    let recipe_id = common::recipe(pool, creator_id, name).await;
+    for (ingredient_id, quantity) in ingredients {
+        sqlx::query(
+            "INSERT INTO ingredients_to_recipes (recipe_id, ingredient_id, quantity, quantity_unit) VALUES ($1, $2, $3, 'g')",
+        )
+        .bind(recipe_id)
+        .bind(ingredient_id)
+        .bind(quantity)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+}
+
+async fn suggest(pool: &PgPool, ingredient_id: uuid::Uuid, user_id: uuid::Uuid, protein: f32) {
+    sqlx::query(
+        "INSERT INTO ingredient_suggestions (ingredient_id, user_id, protein) VALUES ($1, $2, $3)",
+    )
+    .bind(ingredient_id)
+    .bind(user_id)
+    .bind(protein)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn merge(duplicates: Option<DuplicatePolicy>) -> MergeIngredients {
+    MergeIngredients {
+        source: "Tomatoes".into(),
+        target: "Tomato".into(),
+        nutrition: NutritionPolicy::Target,
+        duplicates,
+    }
+}
+
+async fn run(
+    pool: &PgPool,
+    merge: &MergeIngredients,
+) -> Result<axum1::routes::admin::merge::MergeOutcome, ApiError> {
+    let mut tx = pool.begin().await.unwrap();
+    let (outcome, _) = merge_ingredients(&mut tx, Org::DEFAULT, merge).await?;
+    tx.commit().await.unwrap();
+    Ok(outcome)
+}
+
+async fn quantities(pool: &PgPool, recipe: &str) -> Vec<(String, String)> {
+    sqlx::query_as(
+        r#"
+        SELECT i.name, ir.quantity FROM ingredients_to_recipes ir
+        INNER JOIN ingredients i ON i.id = ir.ingredient_id
+        INNER JOIN recipes r ON r.id = ir.recipe_id
+        WHERE r.name = $1
+        ORDER BY i.name
+        "#,
+    )
+    .bind(recipe)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn recipes_suggestions_and_favorites_move_to_the_target(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let fan = common::user(&pool, "fan").await;
+    let source = common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+    let salt = common::ingredient(&pool, "Salt", 0.0).await;
+    sqlx::query(
+        "UPDATE ingredients SET g_per_piece = 120, price_per_100g = 0.5, price_currency = 'EUR' WHERE id = $1",
+    )
+    .bind(source)
+    .execute(&pool)
+    .await
+    .unwrap();
+    recipe(&pool, owner, "Salsa", &[(source, "300"), (salt, "5")]).await;
+    suggest(&pool, source, fan, 1.5).await;
+    sqlx::query("INSERT INTO favorite_ingredient (ingredient_id, user_id) VALUES ($1, $2)")
+        .bind(source)
+        .bind(fan)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let mut tx = pool.begin().await.unwrap();
+    let (outcome, notifications) = merge_ingredients(&mut tx, Org::DEFAULT, &merge(None))
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    assert_eq!(outcome.recipes, ["Salsa"]);
+    assert!(outcome.duplicates.is_empty());
+    assert_eq!(outcome.suggestions_moved, 1);
+    assert_eq!(
+        quantities(&pool, "Salsa").await,
+        [("Salt".into(), "5".into()), ("Tomato".into(), "300".into())]
+    );
+
+    let source_left: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM ingredients WHERE id = $1)")
+            .bind(source)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(!source_left);
+    let (suggestions, favorites): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM ingredient_suggestions WHERE ingredient_id = $1),
+            (SELECT COUNT(*) FROM favorite_ingredient WHERE ingredient_id = $1)
+        "#,
+    )
+    .bind(target)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((suggestions, favorites), (1, 1));
+
+    // The target keeps its own nutrition, but gets what only the source had.
+    let (protein, g_per_piece, price, currency): (f32, Option<f32>, Option<f32>, Option<String>) =
+        sqlx::query_as(
+            "SELECT protein, g_per_piece, price_per_100g, price_currency FROM ingredients WHERE id = $1",
+        )
+        .bind(target)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(protein, 1.0);
+    assert_eq!(g_per_piece, Some(120.0));
+    assert_eq!((price, currency.as_deref()), (Some(0.5), Some("EUR")));
+
+    // The owner is notified, with a saved copy.
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].recipient(), Some(owner));
+    let payload: serde_json::Value = sqlx::query_scalar(
+        "SELECT payload FROM notifications WHERE user_id = $1 AND kind = 'ingredient_merged'",
+    )
+    .bind(owner)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        payload,
+        serde_json::json!({ "source": "Tomatoes", "target": "Tomato", "recipes": ["Salsa"] })
+    );
+}
+
+#[sqlx::test]
+async fn recipes_using_both_are_refused_without_a_policy(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let source = common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+    recipe(&pool, owner, "Salsa", &[(source, "300"), (target, "100")]).await;
+
+    let error = run(&pool, &merge(None)).await.unwrap_err();
+
+    let ApiError::UnprocessableEntity { errors } = error else {
+        panic!("unexpected error: {error:?}");
+    };
+    assert_eq!(errors["duplicates"], ["Salsa uses both ingredients"]);
+    assert_eq!(
+        quantities(&pool, "Salsa").await,
+        [
+            ("Tomato".into(), "100".into()),
+            ("Tomatoes".into(), "300".into())
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn duplicate_policies_pick_the_quantity(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let source = common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+    recipe(&pool, owner, "Salsa", &[(source, "300"), (target, "100")]).await;
+    recipe(&pool, owner, "Soup", &[(source, "500")]).await;
+
+    let outcome = run(&pool, &merge(Some(DuplicatePolicy::KeepSource)))
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.recipes, ["Salsa", "Soup"]);
+    assert_eq!(outcome.duplicates, ["Salsa"]);
+    assert_eq!(
+        quantities(&pool, "Salsa").await,
+        [("Tomato".into(), "300".into())]
+    );
+    assert_eq!(
+        quantities(&pool, "Soup").await,
+        [("Tomato".into(), "500".into())]
+    );
+}
+
+#[sqlx::test]
+async fn keeping_the_target_quantity_drops_the_source(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let source = common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+    recipe(&pool, owner, "Salsa", &[(source, "300"), (target, "100")]).await;
+
+    run(&pool, &merge(Some(DuplicatePolicy::KeepTarget)))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        quantities(&pool, "Salsa").await,
+        [("Tomato".into(), "100".into())]
+    );
+}
+
+#[sqlx::test]
+async fn the_source_nutrition_can_be_kept(pool: PgPool) {
+    common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+
+    run(
+        &pool,
+        &MergeIngredients {
+            nutrition: NutritionPolicy::Source,
+            ..merge(None)
+        },
+    )
+    .await
+    .unwrap();
+
+    let protein: f32 = sqlx::query_scalar("SELECT protein FROM ingredients WHERE id = $1")
+        .bind(target)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(protein, 2.0);
+}
+
+#[sqlx::test]
+async fn suggestions_that_cant_move_are_dropped(pool: PgPool) {
+    let fan = common::user(&pool, "fan").await;
+    let source = common::ingredient(&pool, "Tomatoes", 2.0).await;
+    let target = common::ingredient(&pool, "Tomato", 1.0).await;
+    suggest(&pool, source, fan, 1.5).await;
+    suggest(&pool, target, fan, 1.2).await;
+
+    let outcome = run(&pool, &merge(None)).await.unwrap();
+
+    assert_eq!(outcome.suggestions_moved, 0);
+    let protein: Vec<f32> =
+        sqlx::query_scalar("SELECT protein FROM ingredient_suggestions WHERE user_id = $1")
+            .bind(fan)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(protein, [1.2]);
+}
+
+#[sqlx::test]
+async fn the_ingredients_must_exist_and_differ(pool: PgPool) {
+    common::ingredient(&pool, "Tomato", 1.0).await;
+
+    let missing = run(&pool, &merge(None)).await.unwrap_err();
+    assert!(matches!(missing, ApiError::NotFound));
+
+    let same = run(
+        &pool,
+        &MergeIngredients {
+            source: "TOMATO".into(),
+            ..merge(None)
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(same, ApiError::UnprocessableEntity { .. }));
+}
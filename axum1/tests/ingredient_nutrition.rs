@@ -0,0 +1,127 @@
+use axum1::{
+    error::ApiError,
+    routes::ingredient::{Ingredient, UpgradeIngredient},
+};
+use serde_json::json;
+
+fn upgrade(fields: serde_json::Value) -> UpgradeIngredient {
+    serde_json::from_value(fields).unwrap()
+}
+
+fn tomato() -> Ingredient {
+    serde_json::from_value(json!({
+        "name": "Tomato",
+        "calories_per_100g": 18.0,
+        "category": ["vegetable"],
+        "g_per_piece": 120.0,
+        "protein": 0.9,
+        "water": 94.5,
+        "fat": 0.2,
+        "sugar": 2.6,
+        "carbohydrate": 3.9,
+        "fiber": 1.2,
+        "caffeine": 0.0,
+        "contains_alcohol": false,
+    }))
+    .unwrap()
+}
+
+fn error_keys(result: Result<(), ApiError>) -> Vec<String> {
+    let Err(ApiError::UnprocessableEntity { errors }) = result else {
+        panic!("expected a 422, got {result:?}");
+    };
+    let mut keys: Vec<_> = errors.into_keys().map(String::from).collect();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn real_ingredients_are_accepted() {
+    assert!(tomato().validate_nutrition().is_ok());
+}
+
+#[test]
+fn grams_at_the_boundaries_are_accepted() {
+    assert!(upgrade(json!({ "protein": 0.0 }))
+        .validate_nutrition(None)
+        .is_ok());
+    assert!(upgrade(json!({ "fat": 100.0 }))
+        .validate_nutrition(None)
+        .is_ok());
+    assert!(upgrade(json!({ "calories_per_100g": 0.0 }))
+        .validate_nutrition(None)
+        .is_ok());
+}
+
+#[test]
+fn grams_outside_the_boundaries_are_rejected() {
+    for field in [
+        "protein",
+        "water",
+        "fat",
+        "sugar",
+        "carbohydrate",
+        "fiber",
+        "caffeine",
+    ] {
+        for grams in [-0.1, 100.1] {
+            let result = upgrade(json!({ field: grams })).validate_nutrition(None);
+            assert_eq!(error_keys(result), [field], "{field}: {grams}");
+        }
+    }
+}
+
+#[test]
+fn negative_calories_are_rejected() {
+    let result = upgrade(json!({ "calories_per_100g": -1.0 })).validate_nutrition(None);
+
+    assert_eq!(error_keys(result), ["calories_per_100g"]);
+}
+
+#[test]
+fn every_violation_is_reported() {
+    let result =
+        upgrade(json!({ "protein": 900.0, "calories_per_100g": -5.0 })).validate_nutrition(None);
+
+    assert_eq!(
+        error_keys(result),
+        ["calories_per_100g", "macros", "protein"]
+    );
+}
+
+#[test]
+fn macros_may_add_up_to_a_little_over_100_grams() {
+    let rounded =
+        upgrade(json!({ "water": 60.0, "protein": 20.0, "fat": 10.0, "carbohydrate": 14.0 }));
+
+    assert!(rounded.validate_nutrition(None).is_ok());
+}
+
+#[test]
+fn macros_adding_up_to_too_much_are_rejected() {
+    let result =
+        upgrade(json!({ "water": 60.0, "protein": 30.0, "fat": 20.0 })).validate_nutrition(None);
+
+    assert_eq!(error_keys(result), ["macros"]);
+}
+
+#[test]
+fn the_sum_includes_the_current_values_of_unset_fields() {
+    let tomato = tomato();
+
+    // 94.5 grams of water already.
+    let result = upgrade(json!({ "protein": 20.0 })).validate_nutrition(Some(&tomato));
+    assert_eq!(error_keys(result), ["macros"]);
+
+    let replaced_water = upgrade(json!({ "protein": 20.0, "water": 70.0 }));
+    assert!(replaced_water.validate_nutrition(Some(&tomato)).is_ok());
+}
+
+#[test]
+fn sugar_and_fiber_are_not_counted_twice() {
+    // Both are part of the carbohydrates.
+    let result = upgrade(json!({ "carbohydrate": 90.0, "sugar": 85.0, "fiber": 5.0 }))
+        .validate_nutrition(None);
+
+    assert!(result.is_ok());
+}
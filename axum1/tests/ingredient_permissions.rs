@@ -0,0 +1,126 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Request, StatusCode,
+    },
+    response::IntoResponse,
+    Router,
+};
+use axum1::{
+    error::ApiError,
+    routes::ingredient::{
+        self,
+        permissions::{ensure_fields_editable, EditorRole},
+        UpgradeIngredient,
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn change(fields: serde_json::Value) -> UpgradeIngredient {
+    serde_json::from_value(fields).unwrap()
+}
+
+fn forbidden_field(result: Result<(), ApiError>) -> &'static str {
+    match result {
+        Err(ApiError::FieldForbidden { field }) => field,
+        other => panic!("expected a forbidden field, got {other:?}"),
+    }
+}
+
+#[test]
+fn users_may_change_nutrients_directly() {
+    let fields = change(json!({ "protein": 12.5, "fat": 3.0 }));
+    assert!(ensure_fields_editable(EditorRole::User, &fields).is_ok());
+}
+
+#[test]
+fn users_may_not_change_sensitive_fields() {
+    let fields = change(json!({ "protein": 12.5, "contains_alcohol": true }));
+    assert_eq!(
+        forbidden_field(ensure_fields_editable(EditorRole::User, &fields)),
+        "contains_alcohol"
+    );
+
+    let fields = change(json!({ "category": ["dairy"] }));
+    assert_eq!(
+        forbidden_field(ensure_fields_editable(EditorRole::User, &fields)),
+        "category"
+    );
+}
+
+#[test]
+fn moderators_may_change_sensitive_fields() {
+    let fields = change(json!({ "contains_alcohol": true, "category": ["beverage"] }));
+    assert!(ensure_fields_editable(EditorRole::Moderator, &fields).is_ok());
+}
+
+#[test]
+fn moderators_may_change_nutrients() {
+    let fields = change(json!({ "calories_per_100g": 120.0 }));
+    assert!(ensure_fields_editable(EditorRole::Moderator, &fields).is_ok());
+}
+
+#[tokio::test]
+async fn rejection_names_the_field() {
+    let response = ApiError::FieldForbidden {
+        field: "contains_alcohol",
+    }
+    .into_response();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(std::str::from_utf8(&body)
+        .unwrap()
+        .contains("contains_alcohol"));
+}
+
+#[sqlx::test]
+async fn admins_are_moderators(pool: PgPool) {
+    let mut conn = pool.acquire().await.unwrap();
+    for (name, is_admin, expected) in [
+        ("user", false, EditorRole::User),
+        ("admin", true, EditorRole::Moderator),
+    ] {
+        let user_id = if is_admin {
+            common::admin(&pool, name).await
+        } else {
+            common::user(&pool, name).await
+        };
+
+        assert_eq!(EditorRole::of(&mut conn, user_id).await.unwrap(), expected);
+    }
+}
+
+/// `PATCH /i/Onion` as the user, setting the protein.
+async fn edit_directly(pool: &PgPool, user_id: uuid::Uuid) -> StatusCode {
+    let store = MemoryStore::default();
+    let cookie = common::logged_in(&store, user_id).await;
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/i", ingredient::router(state.clone()))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .with_state(state);
+
+    let request = Request::patch("/i/Onion")
+        .header(COOKIE, cookie)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("protein=1.5"))
+        .unwrap();
+    app.oneshot(request).await.unwrap().status()
+}
+
+#[sqlx::test]
+async fn only_admins_edit_directly(pool: PgPool) {
+    common::ingredient(&pool, "Onion", 1.0).await;
+    let cook = common::user(&pool, "cook").await;
+    let admin = common::admin(&pool, "admin").await;
+
+    assert_eq!(edit_directly(&pool, cook).await, StatusCode::FORBIDDEN);
+    assert_eq!(edit_directly(&pool, admin).await, StatusCode::OK);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Method, Request, StatusCode,
+    },
+    Router,
+};
+use axum1::{
+    org::Org,
+    routes::ingredient::{self, suggestion::apply_ingredient_suggestion},
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+/// Onion, at 0.5 EUR per 100g.
+async fn priced_onion(pool: &PgPool) -> uuid::Uuid {
+    let onion = common::ingredient(pool, "Onion", 1.0).await;
+    sqlx::query(
+        "UPDATE ingredients SET price_per_100g = 0.5, price_currency = 'EUR' WHERE id = $1",
+    )
+    .bind(onion)
+    .execute(pool)
+    .await
+    .unwrap();
+    onion
+}
+
+async fn price(pool: &PgPool, ingredient_id: uuid::Uuid) -> (Option<f32>, Option<String>) {
+    sqlx::query_as("SELECT price_per_100g, price_currency FROM ingredients WHERE id = $1")
+        .bind(ingredient_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn send(pool: &PgPool, user_id: uuid::Uuid, request: Request<Body>) -> StatusCode {
+    let store = MemoryStore::default();
+    let cookie = common::logged_in(&store, user_id).await;
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/i", ingredient::router(state.clone()))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .with_state(state);
+
+    let (mut parts, body) = request.into_parts();
+    parts.headers.insert(COOKIE, cookie.parse().unwrap());
+    app.oneshot(Request::from_parts(parts, body))
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn edit_directly(pool: &PgPool, admin: uuid::Uuid, form: &'static str) -> StatusCode {
+    let request = Request::builder()
+        .method(Method::PATCH)
+        .uri("/i/Onion")
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap();
+    send(pool, admin, request).await
+}
+
+async fn suggest(pool: &PgPool, cook: uuid::Uuid, change: serde_json::Value) -> uuid::Uuid {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/i/Onion/suggestion")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "update_ingredient": change }).to_string(),
+        ))
+        .unwrap();
+    assert_eq!(send(pool, cook, request).await, StatusCode::OK);
+    sqlx::query_scalar("SELECT id FROM ingredient_suggestions WHERE user_id = $1")
+        .bind(cook)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn apply(pool: &PgPool, moderator: uuid::Uuid, id: uuid::Uuid) {
+    let mut tx = pool.begin().await.unwrap();
+    apply_ingredient_suggestion(&mut tx, Org::DEFAULT, moderator, "Onion", id)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+}
+
+#[sqlx::test]
+async fn direct_edits_only_remove_the_price_when_asked(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    let onion = priced_onion(&pool).await;
+
+    assert_eq!(
+        edit_directly(&pool, admin, "protein=1.5").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        price(&pool, onion).await,
+        (Some(0.5), Some("EUR".to_owned()))
+    );
+
+    assert_eq!(edit_directly(&pool, admin, "price=").await, StatusCode::OK);
+    assert_eq!(price(&pool, onion).await, (None, None));
+}
+
+#[sqlx::test]
+async fn suggestions_only_remove_the_price_when_asked(pool: PgPool) {
+    let admin = common::admin(&pool, "admin").await;
+    let cook = common::confirmed_user(&pool, "cook").await;
+    let other_cook = common::confirmed_user(&pool, "other_cook").await;
+    let onion = priced_onion(&pool).await;
+
+    let keeping = suggest(&pool, cook, json!({ "protein": 1.5 })).await;
+    apply(&pool, admin, keeping).await;
+    assert_eq!(
+        price(&pool, onion).await,
+        (Some(0.5), Some("EUR".to_owned()))
+    );
+
+    let removing = suggest(&pool, other_cook, json!({ "price": null })).await;
+    apply(&pool, admin, removing).await;
+    assert_eq!(price(&pool, onion).await, (None, None));
+}
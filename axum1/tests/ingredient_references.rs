@@ -0,0 +1,68 @@
+mod common;
+
+use axum1::{error::ApiError, org::Org, routes::recipe::references::resolve_ingredient_ids};
+use sqlx::PgPool;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|&n| n.to_owned()).collect()
+}
+
+#[sqlx::test]
+async fn known_ingredients_are_resolved(pool: PgPool) {
+    let flour = common::ingredient(&pool, "flour", 1.0).await;
+    let butter = common::ingredient(&pool, "butter", 1.0).await;
+    let mut tx = pool.begin().await.unwrap();
+
+    let ids = resolve_ingredient_ids(&mut tx, Org::DEFAULT, &names(&["flour", "butter"]))
+        .await
+        .unwrap();
+
+    assert_eq!(ids["flour"], flour);
+    assert_eq!(ids["butter"], butter);
+}
+
+#[sqlx::test]
+async fn every_unknown_ingredient_is_reported(pool: PgPool) {
+    common::ingredient(&pool, "flour", 1.0).await;
+    let mut tx = pool.begin().await.unwrap();
+
+    let result = resolve_ingredient_ids(
+        &mut tx,
+        Org::DEFAULT,
+        &names(&["flour", "unobtainium", "dragon-egg"]),
+    )
+    .await;
+
+    let Err(ApiError::UnprocessableEntity { errors }) = result else {
+        panic!("expected unknown ingredients to be rejected, got {result:?}");
+    };
+    assert_eq!(
+        errors["ingredient-name"],
+        [
+            "dragon-egg is not an ingredient",
+            "unobtainium is not an ingredient"
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn ingredients_of_other_orgs_are_unknown(pool: PgPool) {
+    let flour = common::ingredient(&pool, "flour", 1.0).await;
+    sqlx::query(
+        r#"
+        WITH other AS (
+            INSERT INTO organizations (slug, name) VALUES ('other', 'Other') RETURNING id
+        )
+        UPDATE ingredients SET org_id = (SELECT id FROM other) WHERE id = $1
+        "#,
+    )
+    .bind(flour)
+    .execute(&pool)
+    .await
+    .unwrap();
+    let mut tx = pool.begin().await.unwrap();
+
+    let result = resolve_ingredient_ids(&mut tx, Org::DEFAULT, &names(&["flour"])).await;
+
+    assert!(matches!(result, Err(ApiError::UnprocessableEntity { .. })));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::ingredient::resolve::{parse_names, resolve_names, Resolution},
+};
+use sqlx::PgPool;
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+async fn resolve(pool: &PgPool, names: &[&str]) -> Vec<Resolution> {
+    let mut conn = pool.acquire().await.unwrap();
+    resolve_names(&mut conn, Org::DEFAULT, &strings(names))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|resolved| resolved.resolution)
+        .collect()
+}
+
+#[test]
+fn names_are_trimmed_without_blanks_and_duplicates() {
+    assert_eq!(
+        parse_names(&strings(&[" flour", "", "salt ", "flour", "  "])),
+        ["flour", "salt"]
+    );
+}
+
+#[sqlx::test]
+async fn casing_accents_and_punctuation_match_exactly(pool: PgPool) {
+    let jalapeno = common::ingredient(&pool, "Jalapeño", 1.0).await;
+
+    let resolved = resolve(&pool, &["JALAPENO", "jalapeño", "Nuts, pecans"]).await;
+
+    for resolution in &resolved[..2] {
+        let Resolution::Exact { ingredient } = resolution else {
+            panic!("expected an exact match, got {resolution:?}");
+        };
+        assert_eq!(ingredient.id, jalapeno);
+        assert_eq!(ingredient.name, "Jalapeño");
+    }
+    let Resolution::Exact { ingredient } = &resolved[2] else {
+        panic!("expected an exact match, got {:?}", resolved[2]);
+    };
+    assert_eq!(ingredient.name, "Nuts pecans");
+}
+
+#[sqlx::test]
+async fn misspelled_names_get_similar_candidates(pool: PgPool) {
+    let resolved = resolve(&pool, &["cornstrach"]).await;
+
+    let Resolution::Fuzzy { candidates } = &resolved[0] else {
+        panic!("expected candidates, got {:?}", resolved[0]);
+    };
+    assert_eq!(candidates[0].name, "Cornstarch");
+    assert!(candidates.len() <= 5);
+    assert!(candidates
+        .windows(2)
+        .all(|pair| pair[0].similarity >= pair[1].similarity));
+}
+
+#[sqlx::test]
+async fn unmatched_names_are_offered_for_creation(pool: PgPool) {
+    let resolved = resolve(&pool, &["zqxvwk"]).await;
+
+    assert_eq!(
+        resolved,
+        [Resolution::New {
+            name: "zqxvwk".to_owned()
+        }]
+    );
+}
@@ -0,0 +1,102 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::get,
+    Router,
+};
+use axum1::load_shed::{shed_load, ConcurrencyLimit};
+use tokio::sync::Notify;
+use tower::ServiceExt;
+
+/// `/slow` waits until `release` is notified.
+fn app(limit: Option<usize>, release: Arc<Notify>) -> Router {
+    Router::new()
+        .route(
+            "/slow",
+            get(move || {
+                let release = release.clone();
+                async move { release.notified().await }
+            }),
+        )
+        .route("/fast", get(|| async {}))
+        .route("/admin/health_check", get(|| async {}))
+        .layer(from_fn_with_state(ConcurrencyLimit::new(limit), shed_load))
+}
+
+fn get_request(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+/// Starts a request to `/slow`, and waits until it's being handled.
+async fn occupy(app: &Router) -> tokio::task::JoinHandle<StatusCode> {
+    let slow = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tokio::spawn(async move { slow.await.unwrap().unwrap().status() })
+}
+
+#[tokio::test]
+async fn requests_over_the_limit_are_shed_right_away() {
+    let release = Arc::new(Notify::new());
+    let app = app(Some(1), release.clone());
+    let slow = occupy(&app).await;
+
+    let response = tokio::time::timeout(
+        Duration::from_millis(500),
+        app.clone().oneshot(get_request("/fast")),
+    )
+    .await
+    .expect("the request should be shed, not queued")
+    .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], "1");
+
+    release.notify_one();
+    assert_eq!(slow.await.unwrap(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn capacity_is_given_back_once_a_request_finishes() {
+    let release = Arc::new(Notify::new());
+    let app = app(Some(1), release.clone());
+    let slow = occupy(&app).await;
+    release.notify_one();
+    slow.await.unwrap();
+
+    let response = app.oneshot(get_request("/fast")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn health_checks_are_never_shed() {
+    let release = Arc::new(Notify::new());
+    let app = app(Some(1), release.clone());
+    let slow = occupy(&app).await;
+
+    let response = app
+        .clone()
+        .oneshot(get_request("/admin/health_check"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    release.notify_one();
+    slow.await.unwrap();
+}
+
+#[tokio::test]
+async fn nothing_is_shed_without_a_limit() {
+    let release = Arc::new(Notify::new());
+    let app = app(None, release.clone());
+    let slow = occupy(&app).await;
+
+    let response = app.clone().oneshot(get_request("/fast")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    release.notify_one();
+    slow.await.unwrap();
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use axum1::{
+    config::RetentionSettings,
+    queue::retention::{purge_expired_logs, LogCategory},
+};
+use sqlx::PgPool;
+
+async fn failed_job(pool: &PgPool, days_ago: i32) {
+    sqlx::query(
+        "INSERT INTO failed_jobs (job_type, failed_at) VALUES ('email_delivery', NOW() - make_interval(days => $1))",
+    )
+    .bind(days_ago)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn count(pool: &PgPool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn rows_past_the_retention_window_are_purged_in_batches(pool: PgPool) {
+    for days_ago in [1, 29, 31, 40, 100, 365, 400] {
+        failed_job(&pool, days_ago).await;
+    }
+    let settings = RetentionSettings {
+        batch_size: Some(2),
+        ..Default::default()
+    };
+
+    let purged = purge_expired_logs(&pool, &settings).await.unwrap();
+
+    assert!(purged.contains(&(LogCategory::FailedJobs, 5)));
+    assert_eq!(count(&pool, "failed_jobs").await, 2);
+}
+
+#[sqlx::test]
+async fn categories_have_their_own_retention(pool: PgPool) {
+    let admin = common::user(&pool, "admin").await;
+    let impersonated = common::user(&pool, "impersonated").await;
+    sqlx::query(
+        r#"
+        INSERT INTO impersonations (admin_id, user_id, ended_at, created_at) VALUES
+            ($1, $2, NOW() - INTERVAL '60 days', NOW() - INTERVAL '60 days'),
+            ($1, $2, NOW() - INTERVAL '10 days', NOW() - INTERVAL '10 days'),
+            ($1, $2, NULL, NOW() - INTERVAL '60 days')
+        "#,
+    )
+    .bind(admin)
+    .bind(impersonated)
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        INSERT INTO notifications (user_id, kind, payload, created_at) VALUES
+            ($1, 'system_announcement', '{}', NOW() - INTERVAL '60 days'),
+            ($1, 'system_announcement', '{}', NOW() - INTERVAL '10 days')
+        "#,
+    )
+    .bind(impersonated)
+    .execute(&pool)
+    .await
+    .unwrap();
+    failed_job(&pool, 60).await;
+    let settings = RetentionSettings {
+        security_days: Some(30),
+        failed_jobs_days: Some(0),
+        notifications_days: Some(90),
+        ..Default::default()
+    };
+
+    let purged = purge_expired_logs(&pool, &settings).await.unwrap();
+
+    // Only the finished impersonation is old enough, the ongoing one is kept.
+    assert!(purged.contains(&(LogCategory::Security, 1)));
+    assert!(purged.contains(&(LogCategory::Notifications, 0)));
+    // Disabled categories aren't purged at all.
+    assert!(!purged.iter().any(|(c, _)| *c == LogCategory::FailedJobs));
+    assert_eq!(count(&pool, "impersonations").await, 2);
+    assert_eq!(count(&pool, "notifications").await, 2);
+    assert_eq!(count(&pool, "failed_jobs").await, 1);
+}
@@ -0,0 +1,15 @@
+use axum1::routes::admin::meili::{meili_health, MeiliStatus};
+use meilisearch_sdk::client::Client;
+
+#[tokio::test]
+async fn unreachable_meili_is_reported_as_degraded() {
+    // Nothing listens on port 1, so the connection is refused right away.
+    let client = Client::new("http://127.0.0.1:1", Some("key")).unwrap();
+
+    let health = meili_health(&client).await;
+
+    assert_eq!(health.status, MeiliStatus::Degraded);
+    assert!(!health.reachable);
+    assert!(health.error.is_some());
+    assert!(health.indexes.is_empty());
+}
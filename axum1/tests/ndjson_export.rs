@@ -0,0 +1,80 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::admin::export::{export_stream, ExportTable},
+    time_range::TimeRange,
+};
+use futures::TryStreamExt;
+use sqlx::PgPool;
+
+async fn seed(pool: &PgPool) {
+    let user_id = common::user(pool, "cook").await;
+    for name in ["goulash", "lecso"] {
+        common::recipe(pool, user_id, name).await;
+    }
+}
+
+async fn export_lines(
+    pool: &PgPool,
+    table: ExportTable,
+    range: TimeRange,
+) -> Vec<serde_json::Value> {
+    let chunks: Vec<_> = export_stream(pool.clone(), Org::DEFAULT, table, range)
+        .try_collect()
+        .await
+        .unwrap();
+    chunks
+        .iter()
+        .map(|chunk| {
+            let line = std::str::from_utf8(chunk).unwrap();
+            assert!(line.ends_with('\n'));
+            serde_json::from_str(line.trim_end()).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn only_known_ndjson_files_are_exported() {
+    assert_eq!(
+        ExportTable::from_file_name("recipes.ndjson"),
+        Some(ExportTable::Recipes)
+    );
+    assert_eq!(
+        ExportTable::from_file_name("users.ndjson"),
+        Some(ExportTable::Users)
+    );
+    assert_eq!(ExportTable::from_file_name("users"), None);
+    assert_eq!(ExportTable::from_file_name("sessions.ndjson"), None);
+}
+
+#[sqlx::test]
+async fn every_line_is_a_json_row(pool: PgPool) {
+    seed(&pool).await;
+
+    let recipes = export_lines(&pool, ExportTable::Recipes, TimeRange::default()).await;
+    let names: Vec<_> = recipes
+        .iter()
+        .map(|r| r["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["goulash", "lecso"]);
+    assert_eq!(recipes[0]["creator"], "cook");
+
+    let users = export_lines(&pool, ExportTable::Users, TimeRange::default()).await;
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0]["email"], "cook@example.com");
+    assert!(users[0].get("password_hash").is_none());
+}
+
+#[sqlx::test]
+async fn time_range_filters_rows(pool: PgPool) {
+    seed(&pool).await;
+
+    let future = TimeRange {
+        since: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+        until: None,
+    };
+    assert!(export_lines(&pool, ExportTable::Recipes, future)
+        .await
+        .is_empty());
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use axum1::{
+    routes::auth::notifications::{count_unread, mark_all_read},
+    sse::{unread_backlog, Notification, SecurityAlertReason, MAX_REPLAYED},
+};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn live_alert_carries_the_id_of_its_saved_copy(pool: PgPool) {
+    let user_id = common::user(&pool, "alice").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let alert = Notification::security_alert(user_id, SecurityAlertReason::PasswordChanged)
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+
+    let Notification::SecurityAlert(sent) = &alert else {
+        panic!("expected a security alert, got {alert:?}");
+    };
+    let saved: (String, serde_json::Value) =
+        sqlx::query_as("SELECT kind, payload FROM notifications WHERE id = $1 AND user_id = $2")
+            .bind(sent.notification_id.unwrap())
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.0, "security_alert");
+    assert_eq!(saved.1["reason"], "password_changed");
+}
+
+#[sqlx::test]
+async fn notifications_for_everyone_are_not_saved(pool: PgPool) {
+    let user_id = common::user(&pool, "alice").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    Notification::new_recipe("goulash".to_owned())
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+
+    assert_eq!(count_unread(&mut conn, user_id).await.unwrap(), 0);
+}
+
+#[sqlx::test]
+async fn reading_all_clears_only_that_users_unread_count(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    let bob = common::user(&pool, "bob").await;
+    let mut conn = pool.acquire().await.unwrap();
+    for user_id in [alice, alice, bob] {
+        Notification::security_alert(user_id, SecurityAlertReason::PasswordChanged)
+            .save_for_recipient(&mut conn)
+            .await
+            .unwrap();
+    }
+    assert_eq!(count_unread(&mut conn, alice).await.unwrap(), 2);
+
+    mark_all_read(&mut conn, alice).await.unwrap();
+
+    assert_eq!(count_unread(&mut conn, alice).await.unwrap(), 0);
+    assert_eq!(count_unread(&mut conn, bob).await.unwrap(), 1);
+}
+
+#[sqlx::test]
+async fn reconnecting_replays_the_unread_backlog_latest_first(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    let bob = common::user(&pool, "bob").await;
+    let mut conn = pool.acquire().await.unwrap();
+    let read = Notification::security_alert(alice, SecurityAlertReason::PasswordChanged)
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+    mark_all_read(&mut conn, alice).await.unwrap();
+    let older = Notification::suggestion_applied(alice, "flour".to_owned(), false)
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+    let newer = Notification::security_alert(alice, SecurityAlertReason::PasswordChanged)
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+    Notification::security_alert(bob, SecurityAlertReason::PasswordChanged)
+        .save_for_recipient(&mut conn)
+        .await
+        .unwrap();
+
+    let backlog = unread_backlog(&mut conn, alice).await.unwrap();
+
+    let ids: Vec<_> = backlog.iter().map(|n| Some(n.notification_id)).collect();
+    assert_eq!(ids, [newer.notification_id(), older.notification_id()]);
+    assert!(!ids.contains(&read.notification_id()));
+    let data = backlog[1].data();
+    assert_eq!(backlog[1].kind, "suggestion_applied");
+    assert_eq!(data["ingredient"], "flour");
+    assert_eq!(data["replayed"], true);
+    assert_eq!(
+        data["notification_id"],
+        older.notification_id().unwrap().to_string()
+    );
+}
+
+#[sqlx::test]
+async fn the_replayed_backlog_is_capped(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    let mut conn = pool.acquire().await.unwrap();
+    let alert = Notification::security_alert(alice, SecurityAlertReason::PasswordChanged);
+    alert
+        .save_for_users(&mut conn, &vec![alice; MAX_REPLAYED as usize + 5])
+        .await
+        .unwrap();
+
+    let backlog = unread_backlog(&mut conn, alice).await.unwrap();
+
+    assert_eq!(backlog.len(), MAX_REPLAYED as usize);
+}
@@ -0,0 +1,32 @@
+use axum1::locale::{Locale, Unit};
+
+#[test]
+fn numbers_use_the_separators_of_the_locale() {
+    assert_eq!(Locale::En.format_number(1234567.891, 2), "1,234,567.89");
+    assert_eq!(
+        Locale::Hu.format_number(1234567.891, 2),
+        "1\u{a0}234\u{a0}567,89"
+    );
+    assert_eq!(Locale::En.format_number(-1234.56, 1), "-1,234.6");
+    assert_eq!(Locale::En.format_number(999.0, 2), "999");
+}
+
+#[test]
+fn trailing_zeros_and_negative_zero_are_dropped() {
+    assert_eq!(Locale::En.format_number(12.50, 2), "12.5");
+    assert_eq!(Locale::Hu.format_number(0.004, 2), "0");
+    assert_eq!(Locale::En.format_number(-0.001, 2), "0");
+}
+
+#[test]
+fn amounts_have_their_unit() {
+    assert_eq!(
+        Locale::Hu.format_amount(1.5, 2, Unit::parse("KG")),
+        "1,5\u{a0}kg"
+    );
+    assert_eq!(
+        Locale::En.format_amount(420.0, 0, Some(Unit::Kilocalorie)),
+        "420\u{a0}kcal"
+    );
+    assert_eq!(Locale::En.format_amount(3.0, 2, Unit::parse("")), "3");
+}
@@ -0,0 +1,72 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::ALLOW, Method, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use axum1::startup::{outermost, static_files};
+use tower::ServiceExt;
+
+/// Put together like the application.
+fn app() -> Router {
+    outermost(
+        Router::new()
+            .route("/r/:name", get(|| async { "recipe" }).post(|| async {}))
+            .fallback_service(static_files()),
+    )
+}
+
+fn request(method: Method, uri: &str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn options_lists_the_allowed_methods() {
+    let response = app()
+        .oneshot(request(Method::OPTIONS, "/r/goulash"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let allow = response.headers()[ALLOW].to_str().unwrap();
+    let mut methods: Vec<&str> = allow.split(',').map(str::trim).collect();
+    methods.sort_unstable();
+    assert_eq!(methods, ["GET", "HEAD", "OPTIONS", "POST"]);
+}
+
+#[tokio::test]
+async fn options_on_unknown_route_is_not_found() {
+    let response = app()
+        .oneshot(request(Method::OPTIONS, "/unknown"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn disallowed_method_keeps_the_allow_header() {
+    let response = app()
+        .oneshot(request(Method::DELETE, "/r/goulash"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(response.headers().contains_key(ALLOW));
+}
+
+#[tokio::test]
+async fn head_mirrors_get_without_a_body() {
+    let response = app()
+        .oneshot(request(Method::HEAD, "/r/goulash"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(body.is_empty());
+}
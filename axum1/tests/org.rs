@@ -0,0 +1,51 @@
+use axum::http::{header::HOST, HeaderMap, HeaderValue};
+use axum1::org::{requested_org_slug, ORG_HEADER};
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.insert(*name, HeaderValue::from_static(value));
+    }
+    headers
+}
+
+#[test]
+fn header_takes_precedence_over_subdomain() {
+    let headers = headers(&[(ORG_HEADER, "acme"), (HOST.as_str(), "other.recipes.test")]);
+
+    assert_eq!(
+        requested_org_slug(&headers, Some("recipes.test")).as_deref(),
+        Some("acme")
+    );
+}
+
+#[test]
+fn subdomain_of_base_domain_names_the_organization() {
+    let headers = headers(&[(HOST.as_str(), "acme.recipes.test:8000")]);
+
+    assert_eq!(
+        requested_org_slug(&headers, Some("recipes.test")).as_deref(),
+        Some("acme")
+    );
+    // Subdomains are ignored unless a base domain is configured.
+    assert_eq!(requested_org_slug(&headers, None), None);
+}
+
+#[test]
+fn base_domain_itself_is_the_default_organization() {
+    for host in [
+        "recipes.test",
+        "www.recipes.test",
+        "a.b.recipes.test",
+        "evilrecipes.test",
+    ] {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_str(host).unwrap());
+
+        assert_eq!(
+            requested_org_slug(&headers, Some("recipes.test")),
+            None,
+            "{host}"
+        );
+    }
+}
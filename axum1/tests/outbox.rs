@@ -0,0 +1,90 @@
+mod common;
+
+use axum1::{
+    queue::outbox::{enqueue, relay_pending},
+    sse::Notification,
+};
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+async fn pending(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM events_outbox WHERE sent_at IS NULL")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Commits an event without publishing it, like after a crash right after the commit.
+async fn commit_event(pool: &PgPool, notification: Notification) {
+    let mut tx = pool.begin().await.unwrap();
+    enqueue(&mut tx, &notification).await.unwrap();
+    tx.commit().await.unwrap();
+}
+
+#[sqlx::test]
+async fn committed_events_are_published_once(pool: PgPool) {
+    commit_event(&pool, Notification::new_recipe("goulash".into())).await;
+    let (channel, mut received) = broadcast::channel(16);
+    let mut conn = pool.acquire().await.unwrap();
+
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 1);
+    let Notification::NewRecipe(recipe) = received.try_recv().unwrap() else {
+        panic!("expected a new recipe");
+    };
+    assert_eq!(recipe.name, "goulash");
+    assert_eq!(pending(&pool).await, 0);
+
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 0);
+    assert!(received.try_recv().is_err());
+}
+
+#[sqlx::test]
+async fn rolled_back_events_are_never_published(pool: PgPool) {
+    let mut tx = pool.begin().await.unwrap();
+    enqueue(&mut tx, &Notification::new_recipe("goulash".into()))
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+
+    let (channel, _received) = broadcast::channel(16);
+    let mut conn = pool.acquire().await.unwrap();
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 0);
+}
+
+#[sqlx::test]
+async fn failed_publishes_are_retried(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    commit_event(
+        &pool,
+        Notification::suggestion_applied(user_id, "onion".into(), false),
+    )
+    .await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    // Nobody is listening, so it can't be published.
+    let (channel, received) = broadcast::channel(16);
+    drop(received);
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 0);
+    let (attempts, last_error): (i32, Option<String>) =
+        sqlx::query_as("SELECT attempts, last_error FROM events_outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(attempts, 1);
+    assert!(last_error.is_some());
+
+    // It backs off before the next attempt.
+    let mut received = channel.subscribe();
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 0);
+
+    sqlx::query("UPDATE events_outbox SET next_attempt_at = NOW()")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(relay_pending(&mut conn, &channel).await.unwrap(), 1);
+    let notification = received.try_recv().unwrap();
+    // The recipient isn't in the payload, it's restored from the outbox.
+    assert_eq!(notification.recipient(), Some(user_id));
+    assert_eq!(notification.name(), "suggestion_applied");
+    assert_eq!(pending(&pool).await, 0);
+}
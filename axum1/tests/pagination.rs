@@ -0,0 +1,143 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Query,
+    http::{header::LINK, Request, StatusCode},
+    middleware::from_fn,
+    routing::get,
+    Router,
+};
+use axum1::{
+    config::PaginationSettings,
+    pagination::{pagination_links, Paginated, Pagination},
+};
+use tower::ServiceExt;
+
+#[derive(serde::Deserialize)]
+struct Listing {
+    /// Whatever the client searched for, it has to survive the pagination links.
+    q: Option<String>,
+}
+
+/// A listing of 45 items, echoing back the search query instead of actual items.
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/r/search",
+            get(
+                |pagination: Pagination, Query(listing): Query<Listing>| async move {
+                    Paginated::new(listing.q.into_iter().collect(), pagination, 45)
+                },
+            ),
+        )
+        .with_state(PaginationSettings {
+            default_per_page: Some(20),
+            max_per_page: Some(100),
+        })
+        .layer(from_fn(pagination_links))
+}
+
+async fn request(uri: &str) -> (Option<String>, Paginated<String>) {
+    let response = app()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let link = response
+        .headers()
+        .get(LINK)
+        .map(|link| link.to_str().unwrap().to_owned());
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (link, serde_json::from_slice(&body).unwrap())
+}
+
+async fn get_page(uri: &str) -> Paginated<String> {
+    let path = uri.strip_prefix('/').unwrap();
+    request(&format!("/r/search{path}")).await.1
+}
+
+/// Splits a `Link` header into `(rel, uri)` pairs.
+fn parse_links(header: &str) -> Vec<(String, String)> {
+    header
+        .split(", ")
+        .map(|link| {
+            let (uri, params) = link.split_once("; ").unwrap();
+            let uri = uri.strip_prefix('<').unwrap().strip_suffix('>').unwrap();
+            let rel = params
+                .strip_prefix("rel=\"")
+                .unwrap()
+                .strip_suffix('"')
+                .unwrap();
+            (rel.to_owned(), uri.to_owned())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn huge_page_sizes_are_clamped() {
+    let page = get_page("/?per_page=100000").await;
+    assert_eq!(page.per_page, 100);
+}
+
+#[tokio::test]
+async fn defaults_apply_without_query() {
+    let page = get_page("/").await;
+    assert_eq!((page.page, page.per_page), (1, 20));
+}
+
+#[tokio::test]
+async fn nonsensical_values_are_clamped_to_the_first_page() {
+    let page = get_page("/?page=-3&per_page=0").await;
+    assert_eq!((page.page, page.per_page), (1, 1));
+}
+
+#[tokio::test]
+async fn legacy_limit_parameter_is_still_accepted() {
+    let page = get_page("/?limit=5").await;
+    assert_eq!(page.per_page, 5);
+}
+
+#[tokio::test]
+async fn links_round_trip_to_the_neighbouring_pages() {
+    let (link, page) = request("/r/search?q=pan%20cakes&page=2&per_page=10").await;
+    assert_eq!((page.total, page.last_page()), (45, 5));
+
+    let links = parse_links(&link.unwrap());
+    let rels: Vec<&str> = links.iter().map(|(rel, _)| rel.as_str()).collect();
+    assert_eq!(rels, ["first", "prev", "next", "last"]);
+
+    for ((_, uri), expected_page) in links.iter().zip([1, 1, 3, 5]) {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        assert_eq!(uri.path(), "/r/search");
+
+        let (_, linked) = request(&uri.to_string()).await;
+        assert_eq!((linked.page, linked.per_page), (expected_page, 10));
+        assert_eq!(linked.items, ["pan cakes"]);
+    }
+}
+
+#[tokio::test]
+async fn first_page_has_no_prev_and_last_page_no_next() {
+    let (link, _) = request("/r/search").await;
+    let rels: Vec<String> = parse_links(&link.unwrap())
+        .into_iter()
+        .map(|(rel, _)| rel)
+        .collect();
+    assert_eq!(rels, ["first", "next", "last"]);
+
+    let (link, _) = request("/r/search?page=3").await;
+    let rels: Vec<String> = parse_links(&link.unwrap())
+        .into_iter()
+        .map(|(rel, _)| rel)
+        .collect();
+    assert_eq!(rels, ["first", "prev", "last"]);
+}
+
+#[tokio::test]
+async fn legacy_limit_is_replaced_in_links() {
+    let (link, _) = request("/r/search?limit=5").await;
+    let links = parse_links(&link.unwrap());
+
+    assert!(links.iter().all(|(_, uri)| !uri.contains("limit=")));
+    assert!(links.contains(&("next".to_owned(), "/r/search?page=2&per_page=5".to_owned())));
+}
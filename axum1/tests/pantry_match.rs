@@ -0,0 +1,139 @@
+mod common;
+
+use axum1::{
+    error::ApiError,
+    org::Org,
+    routes::recipe::pantry::{pantry_match_recipes, PantryMatch, PantryMatchRequest},
+};
+use sqlx::PgPool;
+
+async fn recipe(
+    pool: &PgPool,
+    creator_id: uuid::Uuid,
+    name: &str,
+    visibility: &str,
+    ingredients: &[&str],
+) {
+    let recipe_id = common::try_recipe(pool, creator_id, name, visibility)
+        .await
+        .unwrap();
+    for ingredient in ingredients {
+        common::add_ingredient(pool, recipe_id, ingredient, "200").await;
+    }
+}
+
+/// A pancake, an omelette, and bread.
+async fn seed(pool: &PgPool) -> uuid::Uuid {
+    let cook = common::user(pool, "cook").await;
+    recipe(pool, cook, "pancake", "public", &["Egg", "Flour", "Milk"]).await;
+    recipe(pool, cook, "omelette", "public", &["Egg", "Milk"]).await;
+    recipe(pool, cook, "bread", "public", &["Flour", "Yeast", "Salt"]).await;
+    cook
+}
+
+async fn pantry_match(
+    pool: &PgPool,
+    viewer: Option<uuid::Uuid>,
+    ingredients: &[&str],
+    max_missing: u32,
+) -> Result<PantryMatch, ApiError> {
+    let mut conn = pool.acquire().await.unwrap();
+    let request = PantryMatchRequest {
+        ingredients: ingredients.iter().map(|i| i.to_string()).collect(),
+        max_missing,
+    };
+    pantry_match_recipes(&mut conn, Org::DEFAULT, viewer, &request).await
+}
+
+fn ranked(pantry: &PantryMatch) -> Vec<(&str, f64, Vec<&str>)> {
+    pantry
+        .recipes
+        .iter()
+        .map(|r| {
+            let missing = r.missing.iter().map(String::as_str).collect();
+            (r.name.as_str(), r.coverage, missing)
+        })
+        .collect()
+}
+
+#[sqlx::test]
+async fn only_recipes_with_every_ingredient_match_by_default(pool: PgPool) {
+    seed(&pool).await;
+
+    let pantry = pantry_match(&pool, None, &["egg", " MILK ", "flour"], 0)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ranked(&pantry),
+        [("omelette", 1.0, vec![]), ("pancake", 1.0, vec![])]
+    );
+    assert!(pantry.unknown.is_empty());
+}
+
+#[sqlx::test]
+async fn partial_matches_are_ranked_by_coverage(pool: PgPool) {
+    seed(&pool).await;
+
+    let pantry = pantry_match(&pool, None, &["Egg", "Flour"], 2)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ranked(&pantry),
+        [
+            ("pancake", 2.0 / 3.0, vec!["Milk"]),
+            ("omelette", 0.5, vec!["Milk"]),
+            ("bread", 1.0 / 3.0, vec!["Salt", "Yeast"]),
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn recipes_missing_too_much_are_left_out(pool: PgPool) {
+    seed(&pool).await;
+
+    let pantry = pantry_match(&pool, None, &["Egg", "Flour"], 1)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ranked(&pantry),
+        [
+            ("pancake", 2.0 / 3.0, vec!["Milk"]),
+            ("omelette", 0.5, vec!["Milk"])
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn unknown_names_are_reported(pool: PgPool) {
+    seed(&pool).await;
+
+    let pantry = pantry_match(&pool, None, &["Egg", "Unobtainium", "Milk"], 0)
+        .await
+        .unwrap();
+
+    assert_eq!(pantry.unknown, ["Unobtainium"]);
+    assert_eq!(ranked(&pantry), [("omelette", 1.0, vec![])]);
+}
+
+#[sqlx::test]
+async fn private_recipes_only_match_for_their_creator(pool: PgPool) {
+    let cook = seed(&pool).await;
+    recipe(&pool, cook, "secret eggs", "private", &["Egg"]).await;
+    let other = common::user(&pool, "other").await;
+
+    let theirs = pantry_match(&pool, Some(other), &["Egg"], 0).await.unwrap();
+    let own = pantry_match(&pool, Some(cook), &["Egg"], 0).await.unwrap();
+
+    assert!(ranked(&theirs).is_empty());
+    assert_eq!(ranked(&own), [("secret eggs", 1.0, vec![])]);
+}
+
+#[sqlx::test]
+async fn an_empty_pantry_is_rejected(pool: PgPool) {
+    let result = pantry_match(&pool, None, &[" ", ""], 0).await;
+
+    assert!(matches!(result, Err(ApiError::UnprocessableEntity { .. })));
+}
@@ -0,0 +1,107 @@
+use argon2::{Params, PasswordHash};
+use axum1::routes::auth::{
+    password::{compute_password_hash, needs_rehash, validate_credentials},
+    Credentials,
+};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn current() -> Params {
+    Params::new(15000, 2, 1, None).unwrap()
+}
+
+async fn user_with_hash(pool: &PgPool, params: Params) -> String {
+    let hash = compute_password_hash(SecretString::from(PASSWORD), params).unwrap();
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash) VALUES ('cook', 'cook@example.com', $1)",
+    )
+    .bind(hash.expose_secret())
+    .execute(pool)
+    .await
+    .unwrap();
+    hash.expose_secret().to_owned()
+}
+
+fn credentials(password: &str) -> Credentials {
+    serde_json::from_value(serde_json::json!({
+        "email": "cook@example.com",
+        "password": password,
+    }))
+    .unwrap()
+}
+
+async fn stored_hash(pool: &PgPool) -> String {
+    sqlx::query_scalar("SELECT password_hash FROM users WHERE email = 'cook@example.com'")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn weaker_hashes_are_upgraded_on_login(pool: PgPool) {
+    let old_hash = user_with_hash(&pool, Params::new(8192, 1, 1, None).unwrap()).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    validate_credentials(credentials(PASSWORD), &mut conn, current())
+        .await
+        .unwrap();
+
+    let new_hash = stored_hash(&pool).await;
+    assert_ne!(new_hash, old_hash);
+    let parsed = PasswordHash::new(&new_hash).unwrap();
+    assert!(!needs_rehash(&parsed, &current()));
+    // The upgraded hash still works.
+    validate_credentials(credentials(PASSWORD), &mut conn, current())
+        .await
+        .unwrap();
+}
+
+#[sqlx::test]
+async fn current_hashes_are_left_alone(pool: PgPool) {
+    let hash = user_with_hash(&pool, current()).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    validate_credentials(credentials(PASSWORD), &mut conn, current())
+        .await
+        .unwrap();
+
+    assert_eq!(stored_hash(&pool).await, hash);
+}
+
+#[sqlx::test]
+async fn failed_logins_dont_upgrade(pool: PgPool) {
+    let old_hash = user_with_hash(&pool, Params::new(8192, 1, 1, None).unwrap()).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    validate_credentials(credentials("wrong password"), &mut conn, current())
+        .await
+        .unwrap_err();
+
+    assert_eq!(stored_hash(&pool).await, old_hash);
+}
+
+#[test]
+fn any_weaker_parameter_needs_a_rehash() {
+    let hash = compute_password_hash(SecretString::from(PASSWORD), current()).unwrap();
+    let hash = PasswordHash::new(hash.expose_secret()).unwrap();
+
+    assert!(!needs_rehash(&hash, &current()));
+    assert!(!needs_rehash(
+        &hash,
+        &Params::new(8192, 2, 1, None).unwrap()
+    ));
+    assert!(needs_rehash(
+        &hash,
+        &Params::new(15000, 3, 1, None).unwrap()
+    ));
+    assert!(needs_rehash(
+        &hash,
+        &Params::new(15000, 2, 2, None).unwrap()
+    ));
+    assert!(needs_rehash(
+        &hash,
+        &Params::new(19456, 2, 1, None).unwrap()
+    ));
+}
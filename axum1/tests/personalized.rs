@@ -0,0 +1,86 @@
+use axum::{
+    body::Body,
+    http::{
+        header::{CACHE_CONTROL, VARY},
+        Request, StatusCode,
+    },
+    middleware::from_fn,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use axum1::{
+    extractors::{AuthUser, MaybeAuthUser},
+    personalized::{private_cache_control, vary_personalized},
+};
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/me",
+            get(|user: MaybeAuthUser| async move { format!("{}", user.into_inner().is_some()) }),
+        )
+        .route("/private", get(|_: AuthUser| async {}))
+        .route("/public", get(|| async { "public" }))
+        .route(
+            "/cached",
+            get(|_: MaybeAuthUser| async {
+                ([(CACHE_CONTROL, "public, max-age=60")], "cached").into_response()
+            }),
+        )
+        .layer(from_fn(vary_personalized))
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+}
+
+async fn get_path(path: &str) -> axum::response::Response {
+    app()
+        .oneshot(Request::get(path).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn maybe_authenticated_responses_are_private() {
+    let response = get_path("/me").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[VARY], "Cookie");
+    assert_eq!(response.headers()[CACHE_CONTROL], "private");
+}
+
+#[tokio::test]
+async fn rejected_authenticated_responses_are_private() {
+    let response = get_path("/private").await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()[VARY], "Cookie");
+    assert_eq!(response.headers()[CACHE_CONTROL], "private");
+}
+
+#[tokio::test]
+async fn public_responses_are_left_alone() {
+    let response = get_path("/public").await;
+
+    assert!(!response.headers().contains_key(VARY));
+    assert!(!response.headers().contains_key(CACHE_CONTROL));
+}
+
+#[tokio::test]
+async fn personalized_responses_are_never_public() {
+    let response = get_path("/cached").await;
+
+    assert_eq!(response.headers()[CACHE_CONTROL], "private, max-age=60");
+}
+
+#[test]
+fn cache_control_keeps_stricter_directives() {
+    assert_eq!(private_cache_control(None), "private");
+    assert_eq!(private_cache_control(Some("no-cache")), "private, no-cache");
+    assert_eq!(private_cache_control(Some("no-store")), "no-store");
+    assert_eq!(
+        private_cache_control(Some("private, max-age=10")),
+        "private, max-age=10"
+    );
+}
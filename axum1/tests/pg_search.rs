@@ -0,0 +1,75 @@
+mod common;
+
+use axum1::{org::Org, pagination::Pagination, search::search_recipes_pg};
+use sqlx::PgPool;
+
+fn first_page() -> Pagination {
+    Pagination {
+        page: 1,
+        per_page: 10,
+    }
+}
+
+async fn seed_recipes(pool: &PgPool, names: &[&str]) {
+    let user_id = common::user(pool, "cook").await;
+    for name in names {
+        common::recipe(pool, user_id, name).await;
+    }
+}
+
+#[sqlx::test]
+async fn closer_matches_rank_higher(pool: PgPool) {
+    seed_recipes(
+        &pool,
+        &["banana-pancakes", "pancakes", "pan-fried-fish", "goulash"],
+    )
+    .await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let results = search_recipes_pg(&mut conn, Org::DEFAULT, "pancakes", first_page())
+        .await
+        .unwrap();
+    let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+
+    assert_eq!(&names[..2], ["pancakes", "banana-pancakes"]);
+    assert!(!names.contains(&"goulash"));
+}
+
+#[sqlx::test]
+async fn hidden_recipes_are_not_found(pool: PgPool) {
+    seed_recipes(&pool, &["pancakes"]).await;
+    sqlx::query("UPDATE recipes SET hidden = TRUE")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let mut conn = pool.acquire().await.unwrap();
+    let results = search_recipes_pg(&mut conn, Org::DEFAULT, "pancakes", first_page())
+        .await
+        .unwrap();
+
+    assert!(results.is_empty());
+}
+
+#[sqlx::test]
+async fn recipes_of_other_organizations_are_not_found(pool: PgPool) {
+    seed_recipes(&pool, &["pancakes"]).await;
+    let org_id: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO organizations (slug, name) VALUES ('acme', 'Acme') RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE recipes SET org_id = $1")
+        .bind(org_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let mut conn = pool.acquire().await.unwrap();
+    let results = search_recipes_pg(&mut conn, Org::DEFAULT, "pancakes", first_page())
+        .await
+        .unwrap();
+
+    assert!(results.is_empty());
+}
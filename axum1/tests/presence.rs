@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use axum1::presence::Presence;
+use tower_sessions_redis_store::fred::prelude::*;
+
+/// The Redis of the development setup, like the database of the `sqlx::test`s.
+async fn presence(timeout: Duration) -> Presence {
+    let pool = RedisPool::new(
+        RedisConfig::from_url_centralized("redis://127.0.0.1:6379").unwrap(),
+        None,
+        None,
+        None,
+        1,
+    )
+    .unwrap();
+    let _redis_connection = pool.connect();
+    pool.wait_for_connect().await.unwrap();
+    Presence::new(pool, timeout)
+}
+
+#[tokio::test]
+async fn only_the_first_heartbeat_joins() {
+    let presence = presence(Duration::from_secs(30)).await;
+    let recipe_id = uuid::Uuid::new_v4();
+    let (user_id, other_user_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+
+    assert!(presence.heartbeat(recipe_id, user_id).await.unwrap());
+    assert!(!presence.heartbeat(recipe_id, user_id).await.unwrap());
+    assert!(presence.heartbeat(recipe_id, other_user_id).await.unwrap());
+
+    let (mut present, expired) = presence.list(recipe_id).await.unwrap();
+    present.sort();
+    let mut expected = vec![user_id, other_user_id];
+    expected.sort();
+    assert_eq!(present, expected);
+    assert!(expired.is_empty());
+}
+
+#[tokio::test]
+async fn leaving_removes_only_that_user() {
+    let presence = presence(Duration::from_secs(30)).await;
+    let recipe_id = uuid::Uuid::new_v4();
+    let (user_id, other_user_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    presence.heartbeat(recipe_id, user_id).await.unwrap();
+    presence.heartbeat(recipe_id, other_user_id).await.unwrap();
+
+    assert!(presence.leave(recipe_id, user_id).await.unwrap());
+    assert!(!presence.leave(recipe_id, user_id).await.unwrap());
+
+    let (present, expired) = presence.list(recipe_id).await.unwrap();
+    assert_eq!(present, [other_user_id]);
+    assert!(expired.is_empty());
+}
+
+#[tokio::test]
+async fn stale_presences_expire_once() {
+    let presence = presence(Duration::from_secs(1)).await;
+    let recipe_id = uuid::Uuid::new_v4();
+    let user_id = uuid::Uuid::new_v4();
+    presence.heartbeat(recipe_id, user_id).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let (present, expired) = presence.list(recipe_id).await.unwrap();
+    assert!(present.is_empty());
+    assert_eq!(expired, [user_id]);
+    assert_eq!(presence.list(recipe_id).await.unwrap(), (vec![], vec![]));
+    // Coming back is joining again.
+    assert!(presence.heartbeat(recipe_id, user_id).await.unwrap());
+}
@@ -0,0 +1,73 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::get,
+    Router,
+};
+use axum1::error::{problem_details, ApiError, PROBLEM_JSON};
+use tower::ServiceExt;
+
+fn app(always: bool) -> Router {
+    Router::new()
+        .route("/", get(|| async { Err::<(), _>(ApiError::NotFound) }))
+        .route(
+            "/invalid",
+            get(|| async { Err::<(), _>(ApiError::unprocessable_entity([("name", "too short")])) }),
+        )
+        .layer(from_fn_with_state(always, problem_details))
+}
+
+#[tokio::test]
+async fn default_error_shape_is_unchanged() {
+    let response = app(false)
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_ne!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"request path not found");
+}
+
+#[tokio::test]
+async fn accept_header_selects_problem_json() {
+    let response = app(false)
+        .oneshot(
+            Request::get("/invalid")
+                .header("accept", PROBLEM_JSON)
+                .header("x-request-id", "00000000-0000-0000-0000-000000000000")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["status"], 422);
+    assert_eq!(problem["code"], "unprocessable_entity");
+    assert_eq!(problem["type"], "/problems/unprocessable_entity");
+    assert_eq!(
+        problem["instance"],
+        "urn:uuid:00000000-0000-0000-0000-000000000000"
+    );
+    assert_eq!(problem["errors"]["name"][0], "too short");
+}
+
+#[tokio::test]
+async fn config_flag_forces_problem_json() {
+    let response = app(true)
+        .oneshot(Request::get("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(problem["title"], "Not Found");
+    assert_eq!(problem["code"], "not_found");
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::{collections::HashMap, time::Duration};
+
+use axum1::{
+    config::WorkerSettings,
+    email::{EmailClient, EmailLinks},
+    queue::run_workers,
+    routes::auth::confirm::{enqueue_delivery_task, store_token},
+};
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+const TASKS: i64 = 40;
+
+async fn seed_queue(pool: &PgPool) {
+    for i in 0..TASKS {
+        let user_id = common::user(pool, &format!("user{i}")).await;
+
+        let token = format!("token-{i}");
+        store_token(pool, &token, user_id, 24).await.unwrap();
+        enqueue_delivery_task(pool, token, format!("user{i}@example.com"))
+            .await
+            .unwrap();
+    }
+}
+
+async fn queue_drained(pool: PgPool) {
+    loop {
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM confirmation_delivery_queue")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if remaining == 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[sqlx::test]
+async fn concurrent_workers_run_each_task_exactly_once(pool: PgPool) {
+    seed_queue(&pool).await;
+
+    // Nothing listens on the discard port, so every delivery fails and lands in `failed_jobs`.
+    // That gives us a record of every time a task was executed.
+    let email_client = EmailClient::new(
+        "http://127.0.0.1:9".into(),
+        "sender@example.com".into(),
+        SecretString::from("token"),
+        Duration::from_millis(200),
+    );
+    let settings = WorkerSettings {
+        concurrency: Some(8),
+        job_type_limits: HashMap::from([("email_delivery".to_owned(), 4)]),
+        ..Default::default()
+    };
+
+    tokio::time::timeout(
+        Duration::from_secs(30),
+        run_workers(
+            pool.clone(),
+            email_client,
+            EmailLinks::new("http://localhost:3001", "http://localhost:3000"),
+            &settings,
+            queue_drained(pool.clone()),
+        ),
+    )
+    .await
+    .expect("workers did not drain the queue in time")
+    .unwrap();
+
+    let executions: Vec<(Option<String>, i64)> = sqlx::query_as(
+        r#"
+        SELECT context->>'confirmation_id', COUNT(*)
+        FROM failed_jobs
+        GROUP BY context->>'confirmation_id'
+        "#,
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(executions.len() as i64, TASKS);
+    assert!(executions.iter().all(|(_, count)| *count == 1));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::COOKIE, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use axum1::{
+    extractors::{DatabaseConnection, ReadDatabaseConnection},
+    routes::recipe,
+    state::AppState,
+};
+use serde_json::json;
+use sqlx::{postgres::PgPoolOptions, PgConnection, PgPool};
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+/// A second pool to the same database, told apart by its `application_name`.
+fn replica_of(pool: &PgPool) -> PgPool {
+    let options = (*pool.connect_options())
+        .clone()
+        .application_name("replica");
+    PgPoolOptions::new().connect_lazy_with(options)
+}
+
+async fn application_name(conn: &mut PgConnection) -> String {
+    sqlx::query_scalar("SELECT current_setting('application_name')")
+        .fetch_one(conn)
+        .await
+        .unwrap()
+}
+
+fn app(pool: PgPool, replica_pool: Option<PgPool>) -> Router {
+    let state = AppState {
+        replica_pool,
+        ..common::state(pool, common::settings(json!({})))
+    };
+
+    Router::new()
+        .route(
+            "/read",
+            get(
+                |ReadDatabaseConnection(mut conn): ReadDatabaseConnection| async move {
+                    application_name(&mut conn).await
+                },
+            ),
+        )
+        .route(
+            "/write",
+            get(
+                |DatabaseConnection(mut conn): DatabaseConnection| async move {
+                    application_name(&mut conn).await
+                },
+            ),
+        )
+        .with_state(state)
+}
+
+async fn served_by(app: Router, uri: &str) -> String {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn the_replica_shares_the_credentials_of_the_primary() {
+    let primary = common::settings(json!({})).database;
+    assert!(primary.replica_with_db().is_none());
+
+    let settings = common::settings(json!({
+        "database": { "replica": { "host": "replica.internal", "port": 5433 } },
+    }))
+    .database;
+    let replica = settings.replica_with_db().unwrap();
+    assert_eq!(replica.get_host(), "replica.internal");
+    assert_eq!(replica.get_port(), 5433);
+    assert_eq!(replica.get_database(), Some("hummus"));
+    assert_eq!(replica.get_username(), "postgres");
+}
+
+#[sqlx::test]
+async fn reads_use_the_replica_when_there_is_one(pool: PgPool) {
+    let app = app(pool.clone(), Some(replica_of(&pool)));
+
+    assert_eq!(served_by(app.clone(), "/read").await, "replica");
+    assert_ne!(served_by(app, "/write").await, "replica");
+}
+
+#[sqlx::test]
+async fn reads_fall_back_to_the_primary(pool: PgPool) {
+    let app = app(pool, None);
+
+    assert_ne!(served_by(app, "/read").await, "replica");
+}
+
+#[sqlx::test]
+async fn only_anonymous_recipe_reads_use_the_replica(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    common::recipe(&pool, cook, "goulash").await;
+    let replica = replica_of(&pool);
+    let store = MemoryStore::default();
+    let cookie = common::logged_in(&store, cook).await;
+    let state = AppState {
+        replica_pool: Some(replica.clone()),
+        ..common::state(pool, common::settings(json!({})))
+    };
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .with_state(state);
+    let read = |cookie: Option<&str>| {
+        let mut request = Request::get("/r/goulash");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    // Users may be reading what they've just written, the replica might not have it yet.
+    assert_eq!(read(Some(&cookie)).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(replica.size(), 0);
+    assert_eq!(read(None).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(replica.size(), 1);
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use axum1::health::Startup;
+use tower::ServiceExt;
+
+async fn status(router: &Router, uri: &str) -> StatusCode {
+    router
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn ready_only_once_the_app_is_served() {
+    let startup = Startup::default();
+    let router = startup.router();
+
+    // A slow migration, holding up startup until it's told to finish.
+    let (finish_migration, migration_finished) = tokio::sync::oneshot::channel::<()>();
+    let starting = tokio::spawn({
+        let startup = startup.clone();
+        async move {
+            migration_finished.await.unwrap();
+            let app = Router::new().route("/r", get(|| async { "recipes" }));
+            startup.serve_app(app);
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(status(&router, "/admin/live").await, StatusCode::OK);
+    assert_eq!(
+        status(&router, "/admin/ready").await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+    assert_eq!(status(&router, "/r").await, StatusCode::SERVICE_UNAVAILABLE);
+
+    finish_migration.send(()).unwrap();
+    starting.await.unwrap();
+
+    assert_eq!(status(&router, "/admin/live").await, StatusCode::OK);
+    assert_eq!(status(&router, "/admin/ready").await, StatusCode::OK);
+    assert_eq!(status(&router, "/r").await, StatusCode::OK);
+    assert_eq!(status(&router, "/missing").await, StatusCode::NOT_FOUND);
+}
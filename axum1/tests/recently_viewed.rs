@@ -0,0 +1,57 @@
+use axum1::recent::RecentlyViewed;
+use tower_sessions_redis_store::fred::prelude::*;
+
+/// The Redis of the development setup, like the database of the `sqlx::test`s.
+async fn recently_viewed(limit: usize) -> RecentlyViewed {
+    let pool = RedisPool::new(
+        RedisConfig::from_url_centralized("redis://127.0.0.1:6379").unwrap(),
+        None,
+        None,
+        None,
+        1,
+    )
+    .unwrap();
+    let _redis_connection = pool.connect();
+    pool.wait_for_connect().await.unwrap();
+    RecentlyViewed::new(pool, limit)
+}
+
+#[tokio::test]
+async fn latest_view_comes_first_without_duplicates() {
+    let recent = recently_viewed(20).await;
+    let user_id = uuid::Uuid::new_v4();
+
+    for name in ["goulash", "lecso", "goulash", "pancakes"] {
+        recent.record(user_id, name).await.unwrap();
+    }
+
+    assert_eq!(
+        recent.list(user_id).await.unwrap(),
+        ["pancakes", "goulash", "lecso"]
+    );
+}
+
+#[tokio::test]
+async fn history_is_capped_at_the_limit() {
+    let recent = recently_viewed(2).await;
+    let user_id = uuid::Uuid::new_v4();
+
+    for name in ["goulash", "lecso", "pancakes"] {
+        recent.record(user_id, name).await.unwrap();
+    }
+
+    assert_eq!(recent.list(user_id).await.unwrap(), ["pancakes", "lecso"]);
+}
+
+#[tokio::test]
+async fn clearing_forgets_only_that_user() {
+    let recent = recently_viewed(20).await;
+    let (user_id, other_user_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    recent.record(user_id, "goulash").await.unwrap();
+    recent.record(other_user_id, "lecso").await.unwrap();
+
+    recent.clear(user_id).await.unwrap();
+
+    assert!(recent.list(user_id).await.unwrap().is_empty());
+    assert_eq!(recent.list(other_user_id).await.unwrap(), ["lecso"]);
+}
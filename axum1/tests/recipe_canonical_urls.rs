@@ -0,0 +1,128 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header::LOCATION, Method, Request, StatusCode},
+    Router,
+};
+use axum1::routes::recipe;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn app(pool: PgPool, canonical_recipe_urls: bool) -> Router {
+    let settings = common::settings(json!({
+        "application_settings": { "canonical_recipe_urls": canonical_recipe_urls },
+    }));
+    let state = common::state(pool, settings);
+
+    Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+        .with_state(state)
+}
+
+/// Tomato Soup, at `/r/tomato-soup`.
+async fn seed(pool: &PgPool) {
+    let cook = common::user(pool, "cook").await;
+    common::recipe(pool, cook, "Tomato Soup").await;
+}
+
+async fn send(app: Router, method: Method, uri: &str) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn redirected_to(app: Router, uri: &str) -> String {
+    let response = send(app, Method::GET, uri).await;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY, "{uri}");
+    response.headers()[LOCATION].to_str().unwrap().to_owned()
+}
+
+#[sqlx::test]
+async fn other_casings_and_spellings_redirect_to_the_slug(pool: PgPool) {
+    seed(&pool).await;
+    let app = app(pool, true);
+
+    assert_eq!(
+        redirected_to(app.clone(), "/r/TOMATO-SOUP").await,
+        "/r/tomato-soup"
+    );
+    assert_eq!(
+        redirected_to(app, "/r/Tomato%20Soup/pdf?servings=2").await,
+        "/r/tomato-soup/pdf?servings=2"
+    );
+}
+
+#[sqlx::test]
+async fn trailing_slashes_redirect_in_a_single_hop(pool: PgPool) {
+    seed(&pool).await;
+    let app = app(pool, true);
+
+    assert_eq!(
+        redirected_to(app.clone(), "/r/tomato-soup/").await,
+        "/r/tomato-soup"
+    );
+    assert_eq!(
+        redirected_to(app.clone(), "/r/Tomato%20Soup//").await,
+        "/r/tomato-soup"
+    );
+    assert_eq!(
+        redirected_to(app.clone(), "/r/tomato-soup/cost/").await,
+        "/r/tomato-soup/cost"
+    );
+    assert_eq!(
+        redirected_to(app, "/r/compare/?names=a").await,
+        "/r/compare?names=a"
+    );
+}
+
+#[sqlx::test]
+async fn former_slugs_redirect_to_the_current_one(pool: PgPool) {
+    seed(&pool).await;
+    sqlx::query(
+        "INSERT INTO recipe_slug_history (org_id, slug, recipe_id) SELECT org_id, 'soup', id FROM recipes",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(
+        redirected_to(app(pool, true), "/r/soup/cost").await,
+        "/r/tomato-soup/cost"
+    );
+}
+
+#[sqlx::test]
+async fn canonical_urls_and_other_methods_are_not_redirected(pool: PgPool) {
+    seed(&pool).await;
+    let app = app(pool, true);
+
+    let canonical = send(app.clone(), Method::GET, "/r/tomato-soup/cost").await;
+    let renamed = send(app.clone(), Method::PUT, "/r/TOMATO-SOUP/name").await;
+    let missing = send(app, Method::GET, "/r/unknown").await;
+
+    assert_ne!(canonical.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_ne!(renamed.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn nothing_is_redirected_when_turned_off(pool: PgPool) {
+    seed(&pool).await;
+    let app = app(pool, false);
+
+    let casing = send(app.clone(), Method::GET, "/r/TOMATO-SOUP/cost").await;
+    let trailing_slash = send(app, Method::GET, "/r/tomato-soup/").await;
+
+    assert_ne!(casing.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(trailing_slash.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::recipe::changes::{fetch_changes, ChangedRecipe, Changes, Cursor},
+};
+use sqlx::PgPool;
+
+async fn changes_since(pool: &PgPool, since: Option<&str>, limit: usize) -> Changes {
+    let mut conn = pool.acquire().await.unwrap();
+    let since = since.map(|since| since.parse().unwrap());
+    fetch_changes(&mut conn, Org::DEFAULT, None, since, limit)
+        .await
+        .unwrap()
+}
+
+fn ids(changes: &[ChangedRecipe]) -> Vec<uuid::Uuid> {
+    changes.iter().map(|recipe| recipe.id).collect()
+}
+
+#[test]
+fn cursors_round_trip() {
+    let cursor = Cursor {
+        changed_at: chrono::DateTime::from_timestamp_micros(1_760_000_000_123_456).unwrap(),
+        id: uuid::Uuid::new_v4(),
+    };
+    assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+    assert!("yesterday".parse::<Cursor>().is_err());
+}
+
+#[sqlx::test]
+async fn created_updated_and_deleted_recipes_are_reported_once(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    let pancake = common::recipe(&pool, user_id, "pancake").await;
+    let draft = common::recipe(&pool, user_id, "draft").await;
+    sqlx::query("UPDATE recipes SET visibility = 'private' WHERE id = $1")
+        .bind(draft)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // The first sync lists what the user can see.
+    let first = changes_since(&pool, None, 100).await;
+    assert_eq!(ids(&first.created), [pancake]);
+    assert!(first.updated.is_empty() && first.deleted.is_empty());
+    let cursor = first.cursor.unwrap();
+
+    // The cursor moves past the draft, without reporting it.
+    let unchanged = changes_since(&pool, Some(&cursor), 100).await;
+    assert!(unchanged.created.is_empty() && unchanged.updated.is_empty());
+    assert!(unchanged.deleted.is_empty());
+    let cursor = unchanged.cursor.unwrap();
+
+    sqlx::query("UPDATE recipes SET description = 'Fluffier' WHERE id = $1")
+        .bind(pancake)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let omelette = common::recipe(&pool, user_id, "omelette").await;
+    let second = changes_since(&pool, Some(&cursor), 100).await;
+    assert_eq!(ids(&second.created), [omelette]);
+    assert_eq!(ids(&second.updated), [pancake]);
+    assert!(second.deleted.is_empty());
+    let cursor = second.cursor.unwrap();
+
+    sqlx::query("DELETE FROM recipes WHERE id = $1")
+        .bind(omelette)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE id = $1")
+        .bind(pancake)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let third = changes_since(&pool, Some(&cursor), 100).await;
+    assert!(third.created.is_empty() && third.updated.is_empty());
+    let mut deleted = third.deleted.clone();
+    deleted.sort();
+    let mut expected = vec![omelette, pancake];
+    expected.sort();
+    assert_eq!(deleted, expected);
+}
+
+#[sqlx::test]
+async fn large_deltas_are_paged(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    for name in ["pancake", "omelette", "bread"] {
+        common::recipe(&pool, user_id, name).await;
+    }
+
+    let first = changes_since(&pool, None, 2).await;
+    assert_eq!(first.created.len(), 2);
+    assert!(first.has_more);
+
+    let second = changes_since(&pool, first.cursor.as_deref(), 2).await;
+    assert_eq!(second.created.len(), 1);
+    assert!(!second.has_more);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use axum1::{
+    org::Org,
+    routes::recipe::compare::{compare, ingredient_overlap, parse_names, IngredientOverlap},
+};
+use sqlx::PgPool;
+
+fn recipe(name: &str, ingredients: &[&str]) -> (String, Vec<String>) {
+    (
+        name.to_owned(),
+        ingredients.iter().map(|i| i.to_string()).collect(),
+    )
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+#[test]
+fn ingredients_are_sorted_by_how_many_recipes_have_them() {
+    let overlap = ingredient_overlap(&[
+        recipe("pancake", &["egg", "flour", "milk"]),
+        recipe("omelette", &["egg", "milk", "cheese"]),
+        recipe("bread", &["egg", "flour", "yeast"]),
+    ]);
+
+    assert_eq!(
+        overlap,
+        IngredientOverlap {
+            shared: strings(&["egg"]),
+            partially_shared: BTreeMap::from([
+                ("flour".to_owned(), strings(&["bread", "pancake"])),
+                ("milk".to_owned(), strings(&["omelette", "pancake"])),
+            ]),
+            unique: BTreeMap::from([
+                ("pancake".to_owned(), vec![]),
+                ("omelette".to_owned(), strings(&["cheese"])),
+                ("bread".to_owned(), strings(&["yeast"])),
+            ]),
+        }
+    );
+}
+
+#[test]
+fn names_are_split_on_commas_without_duplicates() {
+    assert_eq!(
+        parse_names(" pancake,bread,, pancake ,omelette"),
+        ["pancake", "bread", "omelette"]
+    );
+    assert!(parse_names(" , ").is_empty());
+}
+
+async fn seed_recipe(pool: &PgPool, user_id: uuid::Uuid, name: &str, ingredients: &[&str]) {
+    let recipe_id = common::recipe(pool, user_id, name).await;
+    for ingredient in ingredients {
+        let ingredient_id = common::add_ingredient(pool, recipe_id, ingredient, "200").await;
+        sqlx::query(
+            "UPDATE ingredients SET price_per_100g = 1, price_currency = 'EUR' WHERE id = $1",
+        )
+        .bind(ingredient_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+}
+
+#[sqlx::test]
+async fn missing_recipes_are_reported_without_failing_the_comparison(pool: PgPool) {
+    let user_id = common::user(&pool, "cook").await;
+    seed_recipe(&pool, user_id, "pancake", &["egg", "flour"]).await;
+    seed_recipe(&pool, user_id, "omelette", &["egg"]).await;
+    sqlx::query(
+        "INSERT INTO favorite_recipe (user_id, recipe_id) SELECT $1, id FROM recipes WHERE name = 'omelette'",
+    )
+    .bind(user_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let mut conn = pool.acquire().await.unwrap();
+    let names = strings(&["omelette", "waffle", "pancake"]);
+    let comparison = compare(&mut conn, Org::DEFAULT, &names, None, 2)
+        .await
+        .unwrap();
+
+    assert_eq!(comparison.not_found, ["waffle"]);
+    let compared: Vec<_> = comparison
+        .recipes
+        .iter()
+        .map(|r| (r.name.as_str(), r.total_time, r.favorites))
+        .collect();
+    assert_eq!(compared, [("omelette", 30, 1), ("pancake", 30, 0)]);
+
+    // 200 g at 1 EUR per 100 g for each ingredient, for two servings.
+    let pancake = &comparison.recipes[1].cost.totals["EUR"];
+    assert_eq!(pancake.total, 4.0);
+    assert_eq!(pancake.per_serving, 2.0);
+
+    assert_eq!(comparison.ingredients.shared, ["egg"]);
+    assert_eq!(comparison.ingredients.unique["pancake"], ["flour"]);
+}
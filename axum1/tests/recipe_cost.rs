@@ -0,0 +1,124 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header::COOKIE, Request, StatusCode},
+    Router,
+};
+use axum1::routes::{
+    ingredient::IngredientPrice,
+    recipe::{
+        self,
+        cost::{estimate_cost, CostItem, CurrencyCost},
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+fn item(name: &str, quantity: &str, price: Option<(f32, &str)>) -> CostItem {
+    CostItem {
+        name: name.to_owned(),
+        quantity: quantity.to_owned(),
+        quantity_unit: "g".to_owned(),
+        price: price.map(|(amount_per_100g, currency)| IngredientPrice {
+            amount_per_100g,
+            currency: currency.to_owned(),
+        }),
+    }
+}
+
+#[test]
+fn costs_are_summed_per_serving() {
+    let cost = estimate_cost(
+        &[
+            item("flour", "500", Some((0.5, "EUR"))),
+            item("butter", "100", Some((1.5, "EUR"))),
+        ],
+        4,
+    );
+
+    assert_eq!(
+        cost.totals["EUR"],
+        CurrencyCost {
+            total: 4.0,
+            per_serving: 1.0
+        }
+    );
+    assert!(cost.missing_prices.is_empty());
+}
+
+#[test]
+fn currencies_are_never_mixed() {
+    let cost = estimate_cost(
+        &[
+            item("flour", "200", Some((0.5, "EUR"))),
+            item("paprika", "10", Some((100.0, "HUF"))),
+        ],
+        1,
+    );
+
+    assert_eq!(cost.totals.len(), 2);
+    assert_eq!(cost.totals["EUR"].total, 1.0);
+    assert_eq!(cost.totals["HUF"].total, 10.0);
+}
+
+#[test]
+fn unpriced_and_unquantified_ingredients_are_flagged() {
+    let cost = estimate_cost(
+        &[
+            item("flour", "200", Some((0.5, "EUR"))),
+            item("salt", "a pinch", Some((0.1, "EUR"))),
+            item("saffron", "1", None),
+        ],
+        0,
+    );
+
+    assert_eq!(cost.servings, 1);
+    assert_eq!(cost.totals["EUR"].total, 1.0);
+    assert_eq!(cost.missing_prices, ["saffron"]);
+    assert_eq!(cost.unknown_quantities, ["salt"]);
+}
+
+#[test]
+fn invalid_prices_are_rejected() {
+    let price = |amount_per_100g: f32, currency: &str| IngredientPrice {
+        amount_per_100g,
+        currency: currency.to_owned(),
+    };
+
+    assert!(price(1.0, "EUR").validate().is_ok());
+    assert!(price(-1.0, "EUR").validate().is_err());
+    assert!(price(1.0, "eur").validate().is_err());
+    assert!(price(1.0, "EURO").validate().is_err());
+}
+
+#[sqlx::test]
+async fn private_recipes_are_only_costed_for_their_owner_and_admins(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let other = common::user(&pool, "other").await;
+    let admin = common::admin(&pool, "admin").await;
+    common::try_recipe(&pool, owner, "secret", "private")
+        .await
+        .unwrap();
+    let store = MemoryStore::default();
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+
+    for (viewer, expected) in [
+        (owner, StatusCode::OK),
+        (other, StatusCode::NOT_FOUND),
+        (admin, StatusCode::OK),
+    ] {
+        let request = Request::get("/r/secret/cost")
+            .header(COOKIE, common::logged_in(&store, viewer).await)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), expected);
+    }
+}
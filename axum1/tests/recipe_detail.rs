@@ -0,0 +1,184 @@
+mod common;
+
+use axum1::{
+    locale::Locale,
+    org::Org,
+    routes::recipe::{
+        detail::{fetch_recipe_detailed, fetch_recipes_detailed, Sections},
+        nutrition::refresh_recipe_nutrition,
+    },
+};
+use sqlx::PgPool;
+
+async fn seed(pool: &PgPool) -> uuid::Uuid {
+    let user_id = common::user(pool, "cook").await;
+
+    for name in ["goulash", "pancakes", "porridge"] {
+        let recipe_id = common::recipe(pool, user_id, name).await;
+        sqlx::query("UPDATE recipes SET steps = '{first, second, third}' WHERE id = $1")
+            .bind(recipe_id)
+            .execute(pool)
+            .await
+            .unwrap();
+
+        // Porridge has no ingredients.
+        if name == "porridge" {
+            continue;
+        }
+        for (ingredient, vegan) in [("onion", true), ("base", false)] {
+            let ingredient = format!("{name} {ingredient}");
+            let ingredient_id = common::add_ingredient(pool, recipe_id, &ingredient, "200").await;
+            sqlx::query("UPDATE ingredients SET protein = 1, vegan = $2 WHERE id = $1")
+                .bind(ingredient_id)
+                .bind(vegan)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+
+        let mut conn = pool.acquire().await.unwrap();
+        refresh_recipe_nutrition(&mut conn, &[recipe_id])
+            .await
+            .unwrap();
+    }
+
+    user_id
+}
+
+#[sqlx::test]
+async fn every_section_is_included_by_default(pool: PgPool) {
+    seed(&pool).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let recipe = fetch_recipe_detailed(&mut conn, Org::DEFAULT, "goulash", None, Sections::ALL)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(recipe.steps, ["first", "second", "third"]);
+    let ingredients: Vec<_> = recipe
+        .ingredients
+        .unwrap()
+        .into_iter()
+        .map(|i| i.name)
+        .collect();
+    assert_eq!(ingredients, ["goulash base", "goulash onion"]);
+    assert_eq!(recipe.full_calories, Some(400.0));
+    assert!(recipe.dietary.is_some());
+    assert_eq!(recipe.nutrition.unwrap().calories, 400.0);
+    assert!(!recipe.favorited && !recipe.is_author);
+}
+
+#[sqlx::test]
+async fn left_out_sections_are_missing_from_the_response(pool: PgPool) {
+    seed(&pool).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let sections = Sections::parse("ingredients").unwrap();
+    let recipe = fetch_recipe_detailed(&mut conn, Org::DEFAULT, "goulash", None, sections)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(recipe.ingredients.is_some());
+    assert!(recipe.nutrition.is_none());
+
+    let sections = Sections::parse("").unwrap();
+    let recipe = fetch_recipe_detailed(&mut conn, Org::DEFAULT, "goulash", None, sections)
+        .await
+        .unwrap()
+        .unwrap();
+    let json = serde_json::to_value(recipe).unwrap();
+    for section in ["ingredients", "full_calories", "dietary", "nutrition"] {
+        assert!(json.get(section).is_none(), "{section} is included");
+    }
+    assert_eq!(json["name"], "goulash");
+}
+
+#[test]
+fn unknown_sections_are_rejected() {
+    assert_eq!(
+        Sections::parse("nutrition, ingredients").unwrap(),
+        Sections::ALL
+    );
+    assert!(Sections::parse("ingredients,ratings").is_err());
+}
+
+#[sqlx::test]
+async fn many_recipes_are_keyed_by_the_requested_name(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    sqlx::query(
+        "INSERT INTO favorite_recipe (user_id, recipe_id) SELECT $1, id FROM recipes WHERE name = 'pancakes'",
+    )
+    .bind(user_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let names = ["Goulash", "pancakes", "porridge", "missing"].map(String::from);
+    let recipes = fetch_recipes_detailed(
+        &mut conn,
+        Org::DEFAULT,
+        &names,
+        Some(user_id),
+        Sections::ALL,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(recipes.len(), 3);
+    assert_eq!(recipes["Goulash"].name, "goulash");
+    assert!(recipes["pancakes"].favorited);
+    assert!(!recipes["Goulash"].favorited);
+    assert!(recipes.values().all(|recipe| recipe.is_author));
+    // Recipes without ingredients have an empty list, not a missing one.
+    assert_eq!(recipes["porridge"].ingredients.as_deref().unwrap().len(), 0);
+}
+
+#[sqlx::test]
+async fn hidden_recipes_are_only_shown_to_their_author(pool: PgPool) {
+    let user_id = seed(&pool).await;
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE name = 'goulash'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let anonymous = fetch_recipe_detailed(&mut conn, Org::DEFAULT, "goulash", None, Sections::ALL)
+        .await
+        .unwrap();
+    assert!(anonymous.is_none());
+
+    let author = fetch_recipe_detailed(
+        &mut conn,
+        Org::DEFAULT,
+        "goulash",
+        Some(user_id),
+        Sections::ALL,
+    )
+    .await
+    .unwrap();
+    assert!(author.is_some());
+}
+
+#[sqlx::test]
+async fn numbers_are_formatted_next_to_the_raw_ones(pool: PgPool) {
+    seed(&pool).await;
+    let mut conn = pool.acquire().await.unwrap();
+    let mut recipe = fetch_recipe_detailed(&mut conn, Org::DEFAULT, "goulash", None, Sections::ALL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(recipe.formatted.is_none());
+
+    recipe.format_numbers(Locale::Hu);
+
+    let json = serde_json::to_value(&recipe).unwrap();
+    assert_eq!(json["full_calories"], 400.0);
+    let formatted = &json["formatted"];
+    assert_eq!(formatted["locale"], "hu");
+    assert_eq!(formatted["full_calories"], "400\u{a0}kcal");
+    assert_eq!(formatted["ingredients"][0]["name"], "goulash base");
+    assert_eq!(formatted["ingredients"][0]["quantity"], "200\u{a0}g");
+    assert_eq!(formatted["nutrition"]["protein"], "4\u{a0}g");
+}
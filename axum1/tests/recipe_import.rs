@@ -0,0 +1,214 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use axum1::{
+    org::Org,
+    routes::recipe::{
+        self,
+        import::{
+            draft_from_json_ld, find_recipe_json_ld, is_public_ip, match_draft,
+            parse_duration_minutes, parse_ingredient_line,
+        },
+    },
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+const PAGE: &str = r#"
+<html><head>
+<script type="application/ld+json">{"@type": "WebSite", "name": "Cooking"}</script>
+<script type="application/ld+json">
+{
+  "@context": "https://schema.org",
+  "@graph": [
+    {"@type": "BreadcrumbList"},
+    {
+      "@type": ["Recipe", "NewsArticle"],
+      "name": "Grandma's Goulash!",
+      "description": "A <b>hearty</b> stew &amp; a classic.",
+      "image": {"@type": "ImageObject", "url": "https://example.com/goulash.jpg"},
+      "prepTime": "PT20M",
+      "totalTime": "PT1H50M",
+      "recipeCategory": ["Dinner", "Main course"],
+      "recipeCuisine": "hungarian",
+      "recipeIngredient": ["500 g beef, cubed", "2 onions (chopped)", "1 1/2 tbsp paprika"],
+      "recipeInstructions": [
+        {"@type": "HowToSection", "name": "Prepare", "itemListElement": [
+          {"@type": "HowToStep", "text": "Brown the beef."}
+        ]},
+        {"@type": "HowToStep", "text": "Add the onions."},
+        "Simmer for an hour."
+      ]
+    }
+  ]
+}
+</script>
+</head></html>
+"#;
+
+#[test]
+fn recipes_are_found_in_json_ld_graphs() {
+    let recipe = find_recipe_json_ld(PAGE).unwrap();
+    let draft = draft_from_json_ld(&recipe, "https://example.com/goulash");
+
+    assert_eq!(draft.name, "Grandma s Goulash");
+    assert_eq!(draft.description, "A hearty stew & a classic.");
+    assert_eq!((draft.prep_time, draft.cook_time), (20, 90));
+    assert_eq!(
+        draft.steps,
+        ["Brown the beef.", "Add the onions.", "Simmer for an hour."]
+    );
+    assert_eq!(draft.cuisine, "hungarian");
+    assert_eq!(
+        draft.image_url.as_deref(),
+        Some("https://example.com/goulash.jpg")
+    );
+    let json = serde_json::to_value(&draft).unwrap();
+    assert_eq!(json["meal_type"], "dinner");
+    assert_eq!(json["difficulty"], "medium");
+
+    assert!(find_recipe_json_ld("<html><p>No recipe here</p></html>").is_none());
+}
+
+#[test]
+fn ingredient_lines_are_split_into_quantity_unit_and_name() {
+    let beef = parse_ingredient_line("500 g beef, cubed");
+    assert_eq!(
+        (
+            beef.quantity.as_str(),
+            beef.quantity_unit.as_str(),
+            beef.name.as_str()
+        ),
+        ("500", "g", "beef")
+    );
+
+    let paprika = parse_ingredient_line("1 1/2 tbsp paprika");
+    assert_eq!(
+        (paprika.quantity.as_str(), paprika.quantity_unit.as_str()),
+        ("1.5 tbsp", "")
+    );
+    assert_eq!(paprika.name, "paprika");
+
+    let onions = parse_ingredient_line("2 Onions (chopped)");
+    assert_eq!(
+        (onions.quantity.as_str(), onions.name.as_str()),
+        ("2", "onions")
+    );
+
+    let salt = parse_ingredient_line("Salt to taste");
+    assert_eq!(
+        (salt.quantity.as_str(), salt.name.as_str()),
+        ("", "salt to taste")
+    );
+    assert!(salt.needs_creation);
+}
+
+#[test]
+fn durations_are_read_as_minutes() {
+    assert_eq!(parse_duration_minutes("PT1H30M"), Some(90));
+    assert_eq!(parse_duration_minutes("P1DT2H"), Some(26 * 60));
+    assert_eq!(parse_duration_minutes("PT90S"), Some(2));
+    assert_eq!(parse_duration_minutes("about an hour"), None);
+}
+
+#[test]
+fn only_public_addresses_are_fetched() {
+    for address in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.20.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "::127.0.0.1",
+        "::a00:1",
+        "2002:7f00:1::",
+        "2002:a00:1::1",
+    ] {
+        assert!(!is_public_ip(address.parse().unwrap()), "{address}");
+    }
+    for address in ["93.184.215.14", "2606:4700:4700::1111"] {
+        assert!(is_public_ip(address.parse().unwrap()), "{address}");
+    }
+}
+
+#[sqlx::test]
+async fn unknown_ingredients_are_flagged_for_creation(pool: PgPool) {
+    sqlx::query(
+        r#"
+        INSERT INTO ingredients (name, original_name, calories_per_100g, protein, water, fat, sugar, carbohydrate, fiber, caffeine, contains_alcohol)
+        VALUES ('Beef', 'Beef', 250, 26, 60, 15, 0, 0, 0, 0, FALSE)
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let recipe = find_recipe_json_ld(PAGE).unwrap();
+    let mut draft = draft_from_json_ld(&recipe, "https://example.com/goulash");
+    let mut conn = pool.acquire().await.unwrap();
+
+    match_draft(&mut conn, Org::DEFAULT, &mut draft)
+        .await
+        .unwrap();
+
+    let beef = &draft.ingredients[0];
+    assert_eq!(beef.name, "Beef");
+    assert_eq!(beef.calories_per_100g, 250.0);
+    assert!(!beef.needs_creation);
+    assert!(draft.ingredients[1..].iter().all(|i| i.needs_creation));
+    // Cuisines take their known spelling too.
+    assert_eq!(draft.cuisine, "Hungarian");
+
+    draft.cuisine = "martian".into();
+    match_draft(&mut conn, Org::DEFAULT, &mut draft)
+        .await
+        .unwrap();
+    assert_eq!(draft.cuisine, "Unspecified");
+}
+
+#[sqlx::test]
+async fn imports_are_rate_limited(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    let store = MemoryStore::default();
+    let cookie = common::logged_in(&store, cook).await;
+    let settings = common::settings(json!({ "recipe_import": { "requests_per_minute": 1 } }));
+    let state = common::state(pool, settings);
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store).with_secure(false))
+        .layer(MockConnectInfo(
+            "203.0.113.7:4321".parse::<SocketAddr>().unwrap(),
+        ))
+        .with_state(state);
+
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        // Rejected before anything is fetched.
+        let request = Request::post("/r/import_url")
+            .header(COOKIE, &cookie)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"url": "not a url"}"#))
+            .unwrap();
+        statuses.push(app.clone().oneshot(request).await.unwrap().status());
+    }
+
+    assert_eq!(statuses[0], StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(statuses[1], StatusCode::TOO_MANY_REQUESTS);
+}
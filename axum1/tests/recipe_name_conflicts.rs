@@ -0,0 +1,51 @@
+mod common;
+
+use axum::{body::to_bytes, http::StatusCode, response::IntoResponse};
+use axum1::{error::ApiError, org::Org, routes::recipe::slug::suggest_recipe_name};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn the_first_free_number_is_suggested(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    common::try_recipe(&pool, cook, "Pancakes", "public")
+        .await
+        .unwrap();
+    let duplicate = common::try_recipe(&pool, cook, "Pancakes", "public")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        duplicate.as_database_error().unwrap().constraint(),
+        Some("recipes_name_key")
+    );
+
+    assert_eq!(
+        suggest_recipe_name(&pool, Org::DEFAULT, "Pancakes")
+            .await
+            .unwrap(),
+        "Pancakes 2"
+    );
+
+    common::try_recipe(&pool, cook, "pancakes 2", "public")
+        .await
+        .unwrap();
+    assert_eq!(
+        suggest_recipe_name(&pool, Org::DEFAULT, "Pancakes")
+            .await
+            .unwrap(),
+        "Pancakes 3"
+    );
+}
+
+#[tokio::test]
+async fn the_suggestion_is_in_the_response() {
+    let response = ApiError::RecipeNameTaken {
+        suggestion: "Pancakes 2".into(),
+    }
+    .into_response();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["suggestion"], "Pancakes 2");
+    assert_eq!(body["errors"]["name"][0], "is already taken");
+}
@@ -0,0 +1,55 @@
+use axum1::routes::recipe::{
+    nutrition::NutritionSummary,
+    pdf::{render_recipe_pdf, PrintableRecipe},
+};
+use tokio_util::sync::CancellationToken;
+
+fn recipe(steps: usize) -> PrintableRecipe {
+    PrintableRecipe {
+        name: "goulash".to_owned(),
+        description: "A hearty stew of beef and vegetables, seasoned with paprika.".to_owned(),
+        cuisine: "Hungarian".to_owned(),
+        prep_time: 20,
+        cook_time: 120,
+        ingredients: vec![
+            ("Beef".to_owned(), "500".to_owned(), "g".to_owned()),
+            ("Onion".to_owned(), "2".to_owned(), "piece".to_owned()),
+        ],
+        steps: (1..=steps)
+            .map(|i| format!("Step number {i}, which is long enough to be wrapped onto the next line when it's rendered on the page."))
+            .collect(),
+        nutrition: Some(NutritionSummary {
+            calories: 1450.0,
+            protein: 110.0,
+            fat: 80.0,
+            carbohydrate: 40.0,
+            sugar: 12.0,
+            fiber: 6.0,
+        }),
+    }
+}
+
+#[test]
+fn renders_a_pdf() {
+    let pdf = render_recipe_pdf(&recipe(3), &CancellationToken::new()).unwrap();
+
+    assert!(pdf.starts_with(b"%PDF"));
+}
+
+#[test]
+fn long_recipes_are_rendered_in_full() {
+    // Enough steps to need page breaks.
+    let short = render_recipe_pdf(&recipe(3), &CancellationToken::new()).unwrap();
+    let long = render_recipe_pdf(&recipe(100), &CancellationToken::new()).unwrap();
+
+    assert!(long.starts_with(b"%PDF"));
+    assert!(long.len() > short.len());
+}
+
+#[test]
+fn cancelled_renders_stop_early() {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+
+    assert!(render_recipe_pdf(&recipe(100), &cancel).is_err());
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    pagination::Pagination,
+    routes::{
+        ingredient::diet::Diet,
+        recipe::{
+            helpers::TypeByTime,
+            query::{RecipeFilters, RecipeQuery},
+        },
+    },
+};
+use sqlx::PgPool;
+
+#[derive(sqlx::FromRow)]
+struct Name {
+    name: String,
+}
+
+struct Recipe<'a> {
+    name: &'a str,
+    minutes: i32,
+    meal_type: &'a str,
+    vegan: Option<bool>,
+}
+
+async fn seed(pool: &PgPool, recipes: &[Recipe<'_>]) {
+    let user_id = common::user(pool, "cook").await;
+    for recipe in recipes {
+        // The soups are Hungarian.
+        let cuisine = if recipe.name.contains("soup") {
+            "Hungarian"
+        } else {
+            "Unspecified"
+        };
+        let recipe_id = common::recipe(pool, user_id, recipe.name).await;
+        sqlx::query(
+            r#"
+            UPDATE recipes
+            SET prep_time = $2 / 2,
+                cook_time = $2 - $2 / 2,
+                cuisine_id = (SELECT id FROM cuisines WHERE name = $3),
+                meal_type = $4::type_by_time
+            WHERE id = $1
+            "#,
+        )
+        .bind(recipe_id)
+        .bind(recipe.minutes)
+        .bind(cuisine)
+        .bind(recipe.meal_type)
+        .execute(pool)
+        .await
+        .unwrap();
+
+        if let Some(vegan) = recipe.vegan {
+            let ingredient = format!("{} ingredient", recipe.name);
+            let ingredient_id = common::add_ingredient(pool, recipe_id, &ingredient, "100").await;
+            sqlx::query("UPDATE ingredients SET vegan = $2 WHERE id = $1")
+                .bind(ingredient_id)
+                .bind(vegan)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+}
+
+async fn seed_all(pool: &PgPool) {
+    seed(
+        pool,
+        &[
+            Recipe {
+                name: "bean soup",
+                minutes: 60,
+                meal_type: "lunch",
+                vegan: Some(true),
+            },
+            Recipe {
+                name: "goulash soup",
+                minutes: 120,
+                meal_type: "lunch",
+                vegan: Some(false),
+            },
+            Recipe {
+                name: "pancakes",
+                minutes: 20,
+                meal_type: "breakfast",
+                vegan: Some(false),
+            },
+            Recipe {
+                name: "porridge",
+                minutes: 10,
+                meal_type: "breakfast",
+                vegan: None,
+            },
+        ],
+    )
+    .await;
+}
+
+fn page(per_page: i64) -> Pagination {
+    Pagination { page: 1, per_page }
+}
+
+/// The names on the first page, and the total.
+async fn list(pool: &PgPool, filters: RecipeFilters, per_page: i64) -> (Vec<String>, i64) {
+    let mut conn = pool.acquire().await.unwrap();
+    let query = RecipeQuery::new(Org::DEFAULT, &filters);
+    let names: Vec<Name> = query
+        .fetch_page(&mut conn, "r.name", page(per_page))
+        .await
+        .unwrap();
+    let total = query.fetch_count(&mut conn).await.unwrap();
+    (names.into_iter().map(|n| n.name).collect(), total)
+}
+
+#[sqlx::test]
+async fn without_filters_every_visible_recipe_is_listed(pool: PgPool) {
+    seed_all(&pool).await;
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE name = 'porridge'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let (names, total) = list(&pool, RecipeFilters::default(), 10).await;
+
+    assert_eq!(names, ["bean soup", "goulash soup", "pancakes"]);
+    assert_eq!(total, 3);
+}
+
+#[sqlx::test]
+async fn the_total_counts_all_pages(pool: PgPool) {
+    seed_all(&pool).await;
+    let filters = RecipeFilters {
+        max_minutes: Some(60),
+        ..Default::default()
+    };
+
+    let (names, total) = list(&pool, filters, 2).await;
+
+    assert_eq!(names, ["bean soup", "pancakes"]);
+    assert_eq!(total, 3);
+}
+
+#[sqlx::test]
+async fn diet_and_time_combine(pool: PgPool) {
+    seed_all(&pool).await;
+
+    let vegan = RecipeFilters {
+        diet: Some(Diet::Vegan),
+        ..Default::default()
+    };
+    // Porridge has no ingredients, so it's not known to be vegan.
+    assert_eq!(
+        list(&pool, vegan.clone(), 10).await,
+        (vec!["bean soup".into()], 1)
+    );
+
+    let quick_and_vegan = RecipeFilters {
+        max_minutes: Some(30),
+        ..vegan
+    };
+    assert_eq!(list(&pool, quick_and_vegan, 10).await, (vec![], 0));
+}
+
+#[sqlx::test]
+async fn meal_type_and_cuisine_combine(pool: PgPool) {
+    seed_all(&pool).await;
+
+    let lunch = RecipeFilters {
+        meal_type: Some(TypeByTime::Lunch),
+        ..Default::default()
+    };
+    assert_eq!(list(&pool, lunch.clone(), 10).await.1, 2);
+
+    let hungarian_lunch = RecipeFilters {
+        cuisine: Some("Hungarian".into()),
+        max_minutes: Some(90),
+        ..lunch
+    };
+    assert_eq!(
+        list(&pool, hungarian_lunch, 10).await,
+        (vec!["bean soup".into()], 1)
+    );
+
+    let hungarian_breakfast = RecipeFilters {
+        cuisine: Some("Hungarian".into()),
+        meal_type: Some(TypeByTime::Breakfast),
+        ..Default::default()
+    };
+    assert_eq!(list(&pool, hungarian_breakfast, 10).await, (vec![], 0));
+}
+
+#[sqlx::test]
+async fn search_ranks_and_combines_with_filters(pool: PgPool) {
+    seed_all(&pool).await;
+
+    let soup = RecipeFilters {
+        q: Some("goulash".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        list(&pool, soup, 10).await,
+        (vec!["goulash soup".into()], 1)
+    );
+
+    let vegan_soup = RecipeFilters {
+        q: Some("soup".into()),
+        diet: Some(Diet::Vegan),
+        ..Default::default()
+    };
+    assert_eq!(
+        list(&pool, vegan_soup, 10).await,
+        (vec!["bean soup".into()], 1)
+    );
+
+    // A blank search doesn't filter.
+    let blank = RecipeFilters {
+        q: Some("  ".into()),
+        ..Default::default()
+    };
+    assert_eq!(list(&pool, blank, 10).await.1, 4);
+}
+
+#[sqlx::test]
+async fn values_are_bound_not_interpolated(pool: PgPool) {
+    seed_all(&pool).await;
+    let filters = RecipeFilters {
+        cuisine: Some("x' OR TRUE) --".into()),
+        ..Default::default()
+    };
+
+    assert_eq!(list(&pool, filters, 10).await, (vec![], 0));
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{
+        header::{CONTENT_TYPE, COOKIE},
+        Request, StatusCode,
+    },
+    Router,
+};
+use axum1::routes::recipe;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+async fn report(app: &Router, cookie: &str, slug: &str) -> StatusCode {
+    let request = Request::post(format!("/r/{slug}/report"))
+        .header(COOKIE, cookie)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("reason=spam"))
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[sqlx::test]
+async fn recipes_the_reporter_cannot_open_are_not_found(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let reporter = common::user(&pool, "reporter").await;
+    common::recipe(&pool, owner, "goulash").await;
+    common::try_recipe(&pool, owner, "secret", "private")
+        .await
+        .unwrap();
+    let store = MemoryStore::default();
+    let state = common::state(pool.clone(), common::settings(json!({})));
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+    let cookie = common::logged_in(&store, reporter).await;
+
+    // A private recipe answers like one that doesn't exist, so names can't be probed.
+    assert_eq!(report(&app, &cookie, "secret").await, StatusCode::NOT_FOUND);
+    assert_eq!(
+        report(&app, &cookie, "missing").await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(report(&app, &cookie, "goulash").await, StatusCode::CREATED);
+}
+
+#[sqlx::test]
+async fn recipes_are_hidden_once_the_threshold_is_reached(pool: PgPool) {
+    let owner = common::user(&pool, "owner").await;
+    let goulash = common::recipe(&pool, owner, "goulash").await;
+    let store = MemoryStore::default();
+    let settings = common::settings(json!({
+        "application_settings": { "report_hide_threshold": 2 }
+    }));
+    let state = common::state(pool.clone(), settings);
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(store.clone()).with_secure(false))
+        .with_state(state);
+    let hidden = || async {
+        sqlx::query_scalar::<_, bool>("SELECT hidden FROM recipes WHERE id = $1")
+            .bind(goulash)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+    };
+
+    for (i, name) in ["first", "second"].into_iter().enumerate() {
+        let reporter = common::user(&pool, name).await;
+        let cookie = common::logged_in(&store, reporter).await;
+        assert_eq!(report(&app, &cookie, "goulash").await, StatusCode::CREATED);
+        // Removing it from the search index fails here, which doesn't fail the report.
+        assert_eq!(hidden().await, i == 1);
+    }
+}
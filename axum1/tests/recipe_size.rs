@@ -0,0 +1,59 @@
+use axum1::{config::ApplicationSettings, routes::recipe::size::RecipeSizeLimits};
+
+fn limits() -> RecipeSizeLimits {
+    RecipeSizeLimits {
+        max_ingredients: 3,
+        max_steps: 5,
+    }
+}
+
+#[test]
+fn recipes_at_the_limits_are_accepted() {
+    assert!(limits().check(3, 5).is_ok());
+    assert!(limits().check(0, 0).is_ok());
+}
+
+#[test]
+fn one_ingredient_too_many_is_rejected() {
+    let error = limits().check(4, 5).unwrap_err();
+
+    assert_eq!(error.code(), "unprocessable_entity");
+    assert_eq!(error_keys(error), ["ingredients"]);
+}
+
+#[test]
+fn one_step_too_many_is_rejected() {
+    let error = limits().check(3, 6).unwrap_err();
+
+    assert_eq!(error_keys(error), ["steps"]);
+}
+
+#[test]
+fn every_exceeded_limit_is_named() {
+    let error = limits().check(4, 6).unwrap_err();
+
+    assert_eq!(error_keys(error), ["ingredients", "steps"]);
+}
+
+#[test]
+fn the_defaults_are_generous() {
+    let settings: ApplicationSettings = serde_json::from_value(serde_json::json!({
+        "port": 3000,
+        "host": [127, 0, 0, 1],
+        "daily_upload_limit_bytes": 1024,
+    }))
+    .unwrap();
+    let limits = RecipeSizeLimits::from_settings(&settings);
+
+    assert_eq!(limits.max_ingredients, 100);
+    assert_eq!(limits.max_steps, 100);
+}
+
+fn error_keys(error: axum1::error::ApiError) -> Vec<String> {
+    let axum1::error::ApiError::UnprocessableEntity { errors } = error else {
+        panic!("expected a 422, got {error:?}");
+    };
+    let mut keys: Vec<_> = errors.into_keys().map(String::from).collect();
+    keys.sort();
+    keys
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use axum1::{org::Org, routes::recipe::slug::resolve_recipe_slug};
+use sqlx::PgPool;
+
+async fn recipe(pool: &PgPool, creator_id: uuid::Uuid, name: &str) -> String {
+    let recipe_id = common::recipe(pool, creator_id, name).await;
+    sqlx::query_scalar("SELECT slug FROM recipes WHERE id = $1")
+        .bind(recipe_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn slugs_fold_accents_and_separators(pool: PgPool) {
+    let creator = common::user(&pool, "cook").await;
+
+    assert_eq!(recipe(&pool, creator, "Túrós csusza").await, "turos-csusza");
+    assert_eq!(
+        recipe(&pool, creator, "Lecsó--Tojással").await,
+        "lecso-tojassal"
+    );
+}
+
+#[sqlx::test]
+async fn colliding_slugs_get_a_numeric_suffix(pool: PgPool) {
+    let creator = common::user(&pool, "cook").await;
+
+    assert_eq!(recipe(&pool, creator, "Gulyás").await, "gulyas");
+    assert_eq!(recipe(&pool, creator, "gulyas").await, "gulyas-2");
+    assert_eq!(recipe(&pool, creator, "Gulyás!").await, "gulyas-3");
+}
+
+#[sqlx::test]
+async fn former_slugs_are_not_reused(pool: PgPool) {
+    let creator = common::user(&pool, "cook").await;
+    recipe(&pool, creator, "Pörkölt").await;
+    sqlx::query(
+        "INSERT INTO recipe_slug_history (org_id, slug, recipe_id) SELECT org_id, 'stew', id FROM recipes WHERE slug = 'porkolt'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(recipe(&pool, creator, "Stew").await, "stew-2");
+}
+
+#[sqlx::test]
+async fn recipes_resolve_by_current_and_former_slugs(pool: PgPool) {
+    let creator = common::user(&pool, "cook").await;
+    recipe(&pool, creator, "Túrós csusza").await;
+    sqlx::query(
+        "INSERT INTO recipe_slug_history (org_id, slug, recipe_id) SELECT org_id, 'Túrós csusza', id FROM recipes WHERE slug = 'turos-csusza'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE recipes SET name = 'Turos csusza' WHERE slug = 'turos-csusza'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for slug in ["turos-csusza", "TUROS-CSUSZA", "Túrós csusza"] {
+        assert_eq!(
+            resolve_recipe_slug(&pool, Org::DEFAULT, slug)
+                .await
+                .unwrap(),
+            Some("Turos csusza".to_owned()),
+            "{slug}"
+        );
+    }
+    assert_eq!(
+        resolve_recipe_slug(&pool, Org::DEFAULT, "missing")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[sqlx::test]
+async fn slugs_can_not_be_changed(pool: PgPool) {
+    let creator = common::user(&pool, "cook").await;
+    recipe(&pool, creator, "Lecsó").await;
+
+    let changed = sqlx::query("UPDATE recipes SET slug = 'other' WHERE slug = 'lecso'")
+        .execute(&pool)
+        .await;
+
+    assert!(changed.is_err());
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    pagination::Pagination,
+    routes::recipe::{
+        detail::{fetch_recipe_detailed, Sections},
+        query::{RecipeFilters, RecipeQuery},
+    },
+};
+use sqlx::PgPool;
+
+#[derive(sqlx::FromRow)]
+struct Name {
+    name: String,
+}
+
+struct Users {
+    owner: uuid::Uuid,
+    other: uuid::Uuid,
+    admin: uuid::Uuid,
+}
+
+/// One recipe of each visibility, named after it.
+async fn seed(pool: &PgPool) -> Users {
+    let users = Users {
+        owner: common::user(pool, "owner").await,
+        other: common::user(pool, "other").await,
+        admin: common::admin(pool, "admin").await,
+    };
+    for visibility in ["private", "unlisted", "public"] {
+        common::try_recipe(pool, users.owner, visibility, visibility)
+            .await
+            .unwrap();
+    }
+    users
+}
+
+/// Which of the recipes the viewer can open directly.
+async fn openable(pool: &PgPool, viewer: Option<uuid::Uuid>) -> Vec<&'static str> {
+    let mut conn = pool.acquire().await.unwrap();
+    let mut names = Vec::new();
+    for name in ["private", "unlisted", "public"] {
+        let recipe = fetch_recipe_detailed(&mut conn, Org::DEFAULT, name, viewer, Sections::ALL)
+            .await
+            .unwrap();
+        if recipe.is_some() {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Which of the recipes are listed for the viewer.
+async fn listed(pool: &PgPool, viewer: Option<uuid::Uuid>) -> Vec<String> {
+    let mut conn = pool.acquire().await.unwrap();
+    let filters = RecipeFilters::default();
+    let query = RecipeQuery::new(Org::DEFAULT, &filters).viewer(viewer);
+    let names: Vec<Name> = query
+        .fetch_page(
+            &mut conn,
+            "r.name",
+            Pagination {
+                page: 1,
+                per_page: 10,
+            },
+        )
+        .await
+        .unwrap();
+    let total = query.fetch_count(&mut conn).await.unwrap();
+    assert_eq!(total as usize, names.len());
+    names.into_iter().map(|n| n.name).collect()
+}
+
+#[sqlx::test]
+async fn the_owner_sees_every_visibility(pool: PgPool) {
+    let users = seed(&pool).await;
+
+    assert_eq!(
+        openable(&pool, Some(users.owner)).await,
+        ["private", "unlisted", "public"]
+    );
+    assert_eq!(
+        listed(&pool, Some(users.owner)).await,
+        ["private", "public", "unlisted"]
+    );
+}
+
+#[sqlx::test]
+async fn other_users_open_unlisted_recipes_but_only_list_public_ones(pool: PgPool) {
+    let users = seed(&pool).await;
+
+    assert_eq!(
+        openable(&pool, Some(users.other)).await,
+        ["unlisted", "public"]
+    );
+    assert_eq!(listed(&pool, Some(users.other)).await, ["public"]);
+}
+
+#[sqlx::test]
+async fn anonymous_visitors_open_unlisted_recipes_but_only_list_public_ones(pool: PgPool) {
+    seed(&pool).await;
+
+    assert_eq!(openable(&pool, None).await, ["unlisted", "public"]);
+    assert_eq!(listed(&pool, None).await, ["public"]);
+}
+
+#[sqlx::test]
+async fn admins_see_every_visibility(pool: PgPool) {
+    let users = seed(&pool).await;
+
+    assert_eq!(
+        openable(&pool, Some(users.admin)).await,
+        ["private", "unlisted", "public"]
+    );
+    assert_eq!(listed(&pool, Some(users.admin)).await.len(), 3);
+}
+
+#[sqlx::test]
+async fn new_recipes_are_private(pool: PgPool) {
+    let users = seed(&pool).await;
+    sqlx::query(
+        r#"
+        INSERT INTO recipes (name, description, creator_id, prep_time, cook_time, difficulty, steps, cuisine_id, meal_type)
+        VALUES ('draft', 'A recipe', $1, 10, 10, 'easy', '{}', (SELECT id FROM cuisines WHERE name = 'Unspecified'), 'lunch')
+        "#,
+    )
+    .bind(users.owner)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let visibility: String =
+        sqlx::query_scalar("SELECT visibility::TEXT FROM recipes WHERE name = 'draft'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(visibility, "private");
+}
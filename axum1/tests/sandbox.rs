@@ -0,0 +1,85 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::post,
+    Router,
+};
+use axum1::{
+    extractors::DatabaseConnection,
+    sandbox::{sandbox, SANDBOX_HEADER},
+};
+use serde_json::json;
+use sqlx::{Acquire, PgPool};
+use tower::ServiceExt;
+
+// The handler writes in its own transaction and commits it, like the real handlers.
+fn app(pool: PgPool, sandbox_mode: bool) -> Router {
+    let settings = common::settings(json!({
+        "application_settings": { "sandbox_mode": sandbox_mode },
+    }));
+    let state = common::state(pool, settings);
+
+    Router::new()
+        .route(
+            "/",
+            post(
+                |DatabaseConnection(mut conn): DatabaseConnection| async move {
+                    let mut tx = conn.begin().await.unwrap();
+                    sqlx::query("INSERT INTO cuisines (name) VALUES ('Sandboxed')")
+                        .execute(&mut *tx)
+                        .await
+                        .unwrap();
+                    tx.commit().await.unwrap();
+                },
+            ),
+        )
+        .layer(from_fn_with_state(state.clone(), sandbox))
+        .with_state(state)
+}
+
+async fn send(pool: &PgPool, sandbox_mode: bool, header: Option<&str>) -> axum::response::Response {
+    let mut request = Request::post("/");
+    if let Some(header) = header {
+        request = request.header(SANDBOX_HEADER, header);
+    }
+    app(pool.clone(), sandbox_mode)
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn written(pool: &PgPool) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM cuisines WHERE name = 'Sandboxed')")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn sandboxed_writes_are_rolled_back(pool: PgPool) {
+    let response = send(&pool, true, Some("true")).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[SANDBOX_HEADER], "rolled-back");
+    assert!(!written(&pool).await);
+}
+
+#[sqlx::test]
+async fn other_requests_are_committed(pool: PgPool) {
+    let response = send(&pool, true, None).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(SANDBOX_HEADER).is_none());
+    assert!(written(&pool).await);
+}
+
+#[sqlx::test]
+async fn the_header_is_rejected_unless_enabled(pool: PgPool) {
+    let response = send(&pool, false, Some("true")).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!written(&pool).await);
+}
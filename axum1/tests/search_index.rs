@@ -0,0 +1,32 @@
+mod common;
+
+use axum1::search::{get_non_public_recipe_ids, get_recipe_records};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn hidden_recipes_are_removed_from_the_index(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    let goulash = common::recipe(&pool, cook, "goulash").await;
+    let reported = common::recipe(&pool, cook, "reported").await;
+    let secret = common::try_recipe(&pool, cook, "secret", "private")
+        .await
+        .unwrap();
+    sqlx::query("UPDATE recipes SET hidden = TRUE WHERE id = $1")
+        .bind(reported)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let indexed: Vec<_> = get_recipe_records(&pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|recipe| recipe.id)
+        .collect();
+    assert_eq!(indexed, [goulash]);
+    let mut removed = get_non_public_recipe_ids(&pool).await.unwrap();
+    removed.sort();
+    let mut expected = vec![reported, secret];
+    expected.sort();
+    assert_eq!(removed, expected);
+}
@@ -0,0 +1,93 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use axum1::{config::SearchSettings, routes::recipe::query::SearchFilters};
+use tower::ServiceExt;
+
+/// Echoes back the search query.
+fn app() -> Router {
+    Router::new()
+        .route(
+            "/r/search",
+            get(|SearchFilters(filters): SearchFilters| async move {
+                filters.q.unwrap_or_default()
+            }),
+        )
+        .with_state(SearchSettings {
+            max_query_length: Some(10),
+            max_filter_values: Some(3),
+        })
+}
+
+async fn request(uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = app()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&body)
+        .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned().into());
+    (status, body)
+}
+
+#[tokio::test]
+async fn normal_queries_are_unaffected() {
+    assert_eq!(
+        request("/r/search?q=soup&diet=vegan").await,
+        (StatusCode::OK, "soup".into())
+    );
+    assert_eq!(request("/r/search").await, (StatusCode::OK, "".into()));
+}
+
+#[tokio::test]
+async fn queries_up_to_the_limit_are_accepted() {
+    // Counted in characters, not bytes.
+    assert_eq!(
+        request("/r/search?q=%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1%C3%A1").await,
+        (StatusCode::OK, "áááááááááá".into())
+    );
+}
+
+#[tokio::test]
+async fn longer_queries_are_rejected() {
+    let (status, body) = request("/r/search?q=abcdefghijk").await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"]["q"][0], "must be at most 10 characters long");
+}
+
+#[tokio::test]
+async fn filters_up_to_the_limit_are_accepted() {
+    let (status, _) = request("/r/search?q=soup&diet=vegan&page=2").await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn more_filters_are_rejected_before_parsing_them() {
+    // Unknown and repeated parameters count too, they'd be parsed all the same.
+    let (status, body) = request("/r/search?q=soup&diet=vegan&page=2&x=1").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["errors"]["filters"][0],
+        "at most 3 query parameters are allowed"
+    );
+
+    let many = vec!["cuisine=x"; 1000].join("&");
+    let (status, _) = request(&format!("/r/search?{many}")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[test]
+fn the_limit_has_its_own_code() {
+    let error = axum1::error::ApiError::SearchLimitExceeded {
+        key: "q",
+        reason: "too long".into(),
+    };
+
+    assert_eq!(error.code(), "search_limit_exceeded");
+}
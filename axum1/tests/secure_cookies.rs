@@ -0,0 +1,114 @@
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{header::SET_COOKIE, Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Router,
+};
+use axum1::{
+    config::{SecuritySettings, SessionSettings},
+    extractors::TrustedProxies,
+    secure_cookies::{secure_cookies_only, SecureCookies, FORWARDED_PROTO},
+};
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore, Session, SessionManagerLayer};
+
+fn app(required: bool, peer: &str) -> Router {
+    let secure_cookies = SecureCookies {
+        required,
+        trusted_proxies: TrustedProxies(vec!["10.0.0.0/8".parse().unwrap()]),
+    };
+    Router::new()
+        .route(
+            "/login",
+            post(|session: Session| async move {
+                session.insert("user_id", "cook").await.unwrap();
+            }),
+        )
+        .route("/recipes", get(|| async {}))
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+        .layer(from_fn_with_state(secure_cookies, secure_cookies_only))
+        .layer(MockConnectInfo(peer.parse::<SocketAddr>().unwrap()))
+}
+
+fn login(proto: Option<&str>) -> Request<Body> {
+    let mut request = Request::post("/login");
+    if let Some(proto) = proto {
+        request = request.header(FORWARDED_PROTO, proto);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn cookies_are_set_over_https() {
+    let response = app(true, "10.0.0.1:1234")
+        .oneshot(login(Some("https")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(SET_COOKIE));
+}
+
+#[tokio::test]
+async fn cookies_are_refused_over_plain_http() {
+    let response = app(true, "10.0.0.1:1234")
+        .oneshot(login(Some("http")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!response.headers().contains_key(SET_COOKIE));
+}
+
+#[tokio::test]
+async fn untrusted_peers_cannot_claim_https() {
+    let response = app(true, "203.0.113.7:1234")
+        .oneshot(login(Some("https")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!response.headers().contains_key(SET_COOKIE));
+}
+
+#[tokio::test]
+async fn responses_without_cookies_are_served_over_plain_http() {
+    let response = app(true, "10.0.0.1:1234")
+        .oneshot(Request::get("/recipes").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn plain_http_is_fine_unless_required() {
+    let response = app(false, "203.0.113.7:1234")
+        .oneshot(login(None))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key(SET_COOKIE));
+}
+
+#[test]
+fn required_secure_cookies_conflict_with_an_insecure_session_cookie() {
+    let security = SecuritySettings {
+        require_secure_cookies: Some(true),
+        ..Default::default()
+    };
+    let insecure = SessionSettings {
+        secure: Some(false),
+        ..Default::default()
+    };
+
+    assert!(security.secure_session_cookie(&insecure).is_err());
+    assert!(security
+        .secure_session_cookie(&SessionSettings::default())
+        .unwrap());
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use axum1::{
+    config::EmailClientSettings,
+    routes::admin::selfcheck::{
+        check_email, check_queue, check_session_store, run_check, CheckStatus,
+    },
+};
+use secrecy::SecretString;
+use sqlx::PgPool;
+use tower_sessions::MemoryStore;
+
+#[tokio::test]
+async fn slow_checks_fail_with_a_timeout() {
+    let result = run_check(async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(())
+    })
+    .await;
+
+    assert_eq!(result.status, CheckStatus::Failed);
+    assert_eq!(result.error.as_deref(), Some("timed out"));
+}
+
+#[tokio::test]
+async fn failing_checks_report_why() {
+    let result = run_check(async { Err(anyhow::anyhow!("no route to host")) }).await;
+
+    assert_eq!(result.status, CheckStatus::Failed);
+    assert_eq!(result.error.as_deref(), Some("no route to host"));
+}
+
+#[sqlx::test]
+async fn the_queue_check_leaves_nothing_behind(pool: PgPool) {
+    check_queue(&pool).await.unwrap();
+    check_queue(&pool).await.unwrap();
+
+    let events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM events_outbox")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(events, 0);
+}
+
+#[tokio::test]
+async fn the_session_store_check_round_trips() {
+    let store = MemoryStore::default();
+
+    check_session_store(&store).await.unwrap();
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_fails_the_check() {
+    let client = EmailClientSettings {
+        base_url: "http://127.0.0.1:9".into(),
+        sender_email: "recipes@example.com".into(),
+        from_name: None,
+        reply_to: None,
+        sending_domain: None,
+        authorization_token: SecretString::from("token"),
+        timeout_milliseconds: 200,
+        selfcheck_sink: None,
+    }
+    .client();
+
+    let result = run_check(check_email(&client, "sink@example.com".into())).await;
+
+    assert_eq!(result.status, CheckStatus::Failed);
+    assert!(result.error.is_some());
+}
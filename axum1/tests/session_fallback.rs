@@ -0,0 +1,134 @@
+mod common;
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    http::{header::COOKIE, Method, Request, StatusCode},
+    routing::get,
+    Extension, Router,
+};
+use axum1::{
+    extractors::{AuthUser, MaybeAuthUser, SessionFallback},
+    routes::recipe,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt;
+use tower_sessions::{
+    session::{Id, Record},
+    session_store, SessionManagerLayer, SessionStore,
+};
+
+/// A session store that is down, like Redis during a blip.
+#[derive(Debug, Clone)]
+struct UnreachableStore;
+
+#[async_trait]
+impl SessionStore for UnreachableStore {
+    async fn save(&self, _: &Record) -> session_store::Result<()> {
+        Err(session_store::Error::Backend("connection refused".into()))
+    }
+
+    async fn load(&self, _: &Id) -> session_store::Result<Option<Record>> {
+        Err(session_store::Error::Backend("connection refused".into()))
+    }
+
+    async fn delete(&self, _: &Id) -> session_store::Result<()> {
+        Err(session_store::Error::Backend("connection refused".into()))
+    }
+}
+
+fn app(fallback: Option<bool>) -> Router {
+    let router = Router::new()
+        .route(
+            "/maybe",
+            get(|user: MaybeAuthUser| async move { format!("{}", user.into_inner().is_some()) })
+                .post(|_: MaybeAuthUser| async {}),
+        )
+        .route("/private", get(|_: AuthUser| async {}));
+    let router = match fallback {
+        Some(enabled) => router.layer(Extension(SessionFallback(enabled))),
+        None => router,
+    };
+    router.layer(SessionManagerLayer::new(UnreachableStore))
+}
+
+/// Sends a request with a session cookie, so the session has to be loaded from the store.
+async fn request(app: Router, method: Method, path: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(COOKIE, format!("id={}", Id::default()))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn public_reads_are_served_anonymously() {
+    assert_eq!(
+        request(app(Some(true)), Method::GET, "/maybe").await,
+        (StatusCode::OK, "false".to_owned())
+    );
+    // It's the default, too.
+    assert_eq!(
+        request(app(None), Method::GET, "/maybe").await,
+        (StatusCode::OK, "false".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn public_reads_fail_with_the_fallback_disabled() {
+    let (status, _) = request(app(Some(false)), Method::GET, "/maybe").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn changes_are_never_made_anonymously() {
+    let (status, _) = request(app(Some(true)), Method::POST, "/maybe").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn authenticated_endpoints_fail_with_503() {
+    for fallback in [Some(true), Some(false)] {
+        let (status, _) = request(app(fallback), Method::GET, "/private").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
+
+#[tokio::test]
+async fn requests_without_a_session_do_not_touch_the_store() {
+    let response = app(Some(false))
+        .oneshot(Request::get("/maybe").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn recipes_are_served_anonymously(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    common::recipe(&pool, cook, "Tomato Soup").await;
+    let state = common::state(pool, common::settings(json!({})));
+    let app = Router::new()
+        .nest("/r", recipe::router(state.clone()))
+        .layer(SessionManagerLayer::new(UnreachableStore))
+        .with_state(state);
+
+    // `Org` reads the session as well as `MaybeAuthUser`.
+    let (status, body) = request(app.clone(), Method::GET, "/r/tomato-soup").await;
+    assert_eq!(status, StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["name"], "Tomato Soup");
+
+    let (status, _) = request(app, Method::POST, "/r/tomato-soup/favorite").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::collections::HashMap;
+
+use axum1::routes::auth::sessions::revoke_user_sessions;
+use sqlx::PgPool;
+use tower_sessions::{
+    session::{Id, Record},
+    MemoryStore, SessionStore,
+};
+
+async fn create_session(store: &MemoryStore, pool: &PgPool, user_id: uuid::Uuid) -> Id {
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([("user_id".to_owned(), serde_json::json!(user_id))]),
+        expiry_date: time::OffsetDateTime::now_utc() + time::Duration::minutes(10),
+    };
+    store.create(&mut record).await.unwrap();
+
+    sqlx::query("INSERT INTO user_sessions (session_id, user_id) VALUES ($1, $2)")
+        .bind(record.id.to_string())
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .unwrap();
+
+    record.id
+}
+
+#[sqlx::test]
+async fn password_change_revokes_other_sessions(pool: PgPool) {
+    let store = MemoryStore::default();
+    let user_id = common::user(&pool, "victim").await;
+    let other_user_id = common::user(&pool, "bystander").await;
+
+    let current = create_session(&store, &pool, user_id).await;
+    let attacker = create_session(&store, &pool, user_id).await;
+    let stale = create_session(&store, &pool, user_id).await;
+    let unrelated = create_session(&store, &pool, other_user_id).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    let revoked = revoke_user_sessions(&mut conn, &store, user_id, Some(current))
+        .await
+        .unwrap();
+
+    assert_eq!(revoked, 2);
+    assert!(store.load(&current).await.unwrap().is_some());
+    assert!(store.load(&attacker).await.unwrap().is_none());
+    assert!(store.load(&stale).await.unwrap().is_none());
+    assert!(store.load(&unrelated).await.unwrap().is_some());
+}
+
+#[sqlx::test]
+async fn password_reset_revokes_every_session(pool: PgPool) {
+    let store = MemoryStore::default();
+    let user_id = common::user(&pool, "forgetful").await;
+
+    let first = create_session(&store, &pool, user_id).await;
+    let second = create_session(&store, &pool, user_id).await;
+
+    let mut conn = pool.acquire().await.unwrap();
+    revoke_user_sessions(&mut conn, &store, user_id, None)
+        .await
+        .unwrap();
+
+    assert!(store.load(&first).await.unwrap().is_none());
+    assert!(store.load(&second).await.unwrap().is_none());
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_sessions")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
@@ -0,0 +1,142 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum1::{error::ApiError, utils::SingleFlight};
+
+#[tokio::test]
+async fn concurrent_calls_with_the_same_key_run_once() {
+    let flights = Arc::new(SingleFlight::<String, usize>::default());
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let mut handles = Vec::new();
+    for _ in 0..10 {
+        let flights = Arc::clone(&flights);
+        let runs = Arc::clone(&runs);
+        handles.push(tokio::spawn(async move {
+            flights
+                .run("GET /r/goulash".to_owned(), async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    runs.fetch_add(1, Ordering::SeqCst) + 42
+                })
+                .await
+        }));
+    }
+
+    for handle in handles {
+        assert_eq!(handle.await.unwrap(), 42);
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn finished_keys_are_not_cached() {
+    let flights = SingleFlight::<String, usize>::default();
+
+    let first = flights.run("key".to_owned(), async { 1 }).await;
+    let second = flights.run("key".to_owned(), async { 2 }).await;
+
+    assert_eq!((first, second), (1, 2));
+}
+
+#[tokio::test]
+async fn keys_are_forgotten_once_every_waiter_gave_up() {
+    let flights = SingleFlight::<String, usize>::default();
+
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(20),
+        flights.run("key".to_owned(), std::future::pending()),
+    )
+    .await;
+    assert!(abandoned.is_err());
+
+    let next = tokio::time::timeout(
+        Duration::from_secs(1),
+        flights.run("key".to_owned(), async { 2 }),
+    )
+    .await;
+    assert_eq!(next.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn keys_are_forgotten_after_a_panic() {
+    let flights = Arc::new(SingleFlight::<String, usize>::default());
+
+    let panicked = tokio::spawn({
+        let flights = Arc::clone(&flights);
+        async move {
+            flights
+                .run("key".to_owned(), async { panic!("the computation failed") })
+                .await
+        }
+    })
+    .await;
+    assert!(panicked.is_err());
+
+    assert_eq!(flights.run("key".to_owned(), async { 2 }).await, 2);
+}
+
+#[tokio::test]
+async fn every_waiter_gets_the_error() {
+    let flights = Arc::new(SingleFlight::<String, Result<usize, Arc<ApiError>>>::default());
+
+    let mut handles = Vec::new();
+    for _ in 0..5 {
+        let flights = Arc::clone(&flights);
+        handles.push(tokio::spawn(async move {
+            flights
+                .try_run("GET /r/goulash".to_owned(), async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Err(ApiError::unprocessable_entity([("q", "is too long")]))
+                })
+                .await
+        }));
+    }
+
+    for handle in handles {
+        let error = handle.await.unwrap().unwrap_err();
+        assert!(
+            matches!(&error, ApiError::UnprocessableEntity { errors } if errors["q"] == ["is too long"]),
+            "{error:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn waiters_give_back_what_their_computation_holds() {
+    let flights = Arc::new(SingleFlight::<String, usize>::default());
+    let (done, finished) = tokio::sync::oneshot::channel();
+    let first = tokio::spawn({
+        let flights = Arc::clone(&flights);
+        async move {
+            flights
+                .run("key".to_owned(), async { finished.await.unwrap() })
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let connection = Arc::new(());
+    let waiter = tokio::spawn({
+        let flights = Arc::clone(&flights);
+        let connection = Arc::clone(&connection);
+        async move {
+            flights
+                .run("key".to_owned(), async move {
+                    let _connection = connection;
+                    2
+                })
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(Arc::strong_count(&connection), 1);
+
+    done.send(1).unwrap();
+    assert_eq!(first.await.unwrap(), 1);
+    assert_eq!(waiter.await.unwrap(), 1);
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use axum1::config::ApplicationSettings;
+
+fn settings(keep_alive_seconds: Option<u64>) -> ApplicationSettings {
+    serde_json::from_value(serde_json::json!({
+        "port": 3000,
+        "host": [127, 0, 0, 1],
+        "daily_upload_limit_bytes": 1024,
+        "sse_keep_alive_seconds": keep_alive_seconds,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn keep_alives_are_sent_every_15_seconds_by_default() {
+    assert_eq!(
+        settings(None).sse_keep_alive_interval(),
+        Some(Duration::from_secs(15))
+    );
+}
+
+#[test]
+fn the_interval_is_configurable() {
+    assert_eq!(
+        settings(Some(50)).sse_keep_alive_interval(),
+        Some(Duration::from_secs(50))
+    );
+}
+
+#[test]
+fn zero_disables_keep_alives() {
+    assert_eq!(settings(Some(0)).sse_keep_alive_interval(), None);
+}
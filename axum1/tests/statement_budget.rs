@@ -0,0 +1,59 @@
+mod common;
+
+use axum1::{
+    org::Org,
+    routes::recipe::compare::compare,
+    telemetry::{count_statements, StatementCounterLayer},
+};
+use sqlx::PgPool;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// What `GET /r/compare` may execute, however many recipes it compares.
+const COMPARE_BUDGET: usize = 10;
+
+async fn seed_recipe(pool: &PgPool, user_id: uuid::Uuid, name: &str) {
+    let recipe_id = common::recipe(pool, user_id, name).await;
+    for ingredient in ["egg", "flour", "milk"] {
+        common::add_ingredient(pool, recipe_id, ingredient, "200").await;
+    }
+}
+
+#[tokio::test]
+async fn statements_outside_of_the_counted_future_are_not_counted() {
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(StatementCounterLayer),
+    );
+    let ((), statements) = count_statements(async {}).await;
+    assert_eq!(statements, 0);
+}
+
+#[sqlx::test]
+async fn comparing_more_recipes_does_not_execute_more_statements(pool: PgPool) {
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(StatementCounterLayer),
+    );
+    let user_id = common::user(&pool, "cook").await;
+    let names: Vec<String> = ["pancake", "omelette", "bread", "waffle"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    for name in &names {
+        seed_recipe(&pool, user_id, name).await;
+    }
+
+    let mut conn = pool.acquire().await.unwrap();
+    // The first queries on a connection also look up the custom types they use.
+    compare(&mut conn, Org::DEFAULT, &names[..1], None, 1)
+        .await
+        .unwrap();
+    let (two, with_two) =
+        count_statements(compare(&mut conn, Org::DEFAULT, &names[..2], None, 1)).await;
+    let (four, with_four) =
+        count_statements(compare(&mut conn, Org::DEFAULT, &names, None, 1)).await;
+
+    assert_eq!(two.unwrap().recipes.len(), 2);
+    assert_eq!(four.unwrap().recipes.len(), 4);
+    assert!(with_two > 0, "the statements weren't counted");
+    assert_eq!(with_two, with_four);
+    assert!(with_four <= COMPARE_BUDGET, "{with_four} statements");
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use axum1::queue::{set_local_statement_timeout, with_statement_timeout};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    Connection,
+};
+
+// The query_canceled SQLSTATE, raised when `statement_timeout` is exceeded.
+const QUERY_CANCELED: &str = "57014";
+
+#[sqlx::test]
+async fn slow_statement_is_cancelled(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let pool = with_statement_timeout(pool_options, Some(Duration::from_millis(100)))
+        .connect_with(connect_options)
+        .await
+        .unwrap();
+
+    let error = sqlx::query("SELECT pg_sleep(2)")
+        .execute(&pool)
+        .await
+        .unwrap_err();
+
+    let code = error.as_database_error().and_then(|e| e.code());
+    assert_eq!(code.as_deref(), Some(QUERY_CANCELED));
+}
+
+#[sqlx::test]
+async fn transaction_can_lift_the_timeout(
+    pool_options: PgPoolOptions,
+    connect_options: PgConnectOptions,
+) {
+    let pool = with_statement_timeout(pool_options, Some(Duration::from_millis(100)))
+        .connect_with(connect_options)
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let mut tx = conn.begin().await.unwrap();
+    set_local_statement_timeout(&mut tx, None).await.unwrap();
+    sqlx::query("SELECT pg_sleep(0.3)")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    // The override ends with the transaction.
+    let error = sqlx::query("SELECT pg_sleep(0.3)")
+        .execute(&mut *conn)
+        .await
+        .unwrap_err();
+    let code = error.as_database_error().and_then(|e| e.code());
+    assert_eq!(code.as_deref(), Some(QUERY_CANCELED));
+}
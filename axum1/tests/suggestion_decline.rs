@@ -0,0 +1,175 @@
+mod common;
+
+use axum1::{
+    error::ApiError,
+    org::Org,
+    routes::admin::decline::{
+        decline_suggestion_batch, DeclineOutcome, DeclineSuggestions, MAX_DECLINED_SUGGESTIONS,
+    },
+};
+use sqlx::PgPool;
+
+async fn suggest(pool: &PgPool, ingredient_id: uuid::Uuid, user_id: uuid::Uuid) -> uuid::Uuid {
+    sqlx::query_scalar(
+        "INSERT INTO ingredient_suggestions (ingredient_id, user_id, protein) VALUES ($1, $2, 99) RETURNING id",
+    )
+    .bind(ingredient_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+async fn count(pool: &PgPool, query: &str) -> i64 {
+    sqlx::query_scalar(query).fetch_one(pool).await.unwrap()
+}
+
+async fn decline(
+    pool: &PgPool,
+    moderator: uuid::Uuid,
+    request: DeclineSuggestions,
+) -> Result<DeclineOutcome, ApiError> {
+    let mut tx = pool.begin().await.unwrap();
+    let outcome = decline_suggestion_batch(&mut tx, Org::DEFAULT, moderator, &request).await?;
+    tx.commit().await.unwrap();
+    Ok(outcome)
+}
+
+#[sqlx::test]
+async fn existing_and_missing_suggestions_are_counted_apart(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    let spammer = common::user(&pool, "spammer").await;
+    let cook = common::user(&pool, "cook").await;
+    let onion = common::ingredient(&pool, "Onion", 0.0).await;
+    let garlic = common::ingredient(&pool, "Garlic", 0.0).await;
+    let spam = suggest(&pool, onion, spammer).await;
+    let more_spam = suggest(&pool, garlic, spammer).await;
+    let kept = suggest(&pool, onion, cook).await;
+    let already_declined = suggest(&pool, garlic, cook).await;
+    decline(
+        &pool,
+        moderator,
+        DeclineSuggestions {
+            ids: vec![already_declined],
+            reason: None,
+            notify_authors: false,
+        },
+    )
+    .await
+    .unwrap();
+
+    let outcome = decline(
+        &pool,
+        moderator,
+        DeclineSuggestions {
+            ids: vec![
+                spam,
+                more_spam,
+                spam,
+                already_declined,
+                uuid::Uuid::new_v4(),
+            ],
+            reason: Some("  spam ".into()),
+            notify_authors: false,
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        outcome,
+        DeclineOutcome {
+            deleted: 2,
+            not_found: 2
+        }
+    );
+    let remaining: Vec<uuid::Uuid> = sqlx::query_scalar("SELECT id FROM ingredient_suggestions")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, [kept]);
+    let audited: Vec<(uuid::Uuid, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT moderator_id, ingredient, reason FROM suggestion_declines WHERE user_id = $1 ORDER BY ingredient",
+    )
+    .bind(spammer)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        audited,
+        [
+            (moderator, Some("Garlic".into()), Some("spam".into())),
+            (moderator, Some("Onion".into()), Some("spam".into())),
+        ]
+    );
+    assert_eq!(count(&pool, "SELECT COUNT(*) FROM notifications").await, 0);
+}
+
+#[sqlx::test]
+async fn authors_are_notified_once_per_ingredient(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+    let spammer = common::user(&pool, "spammer").await;
+    let onion = common::ingredient(&pool, "Onion", 0.0).await;
+    let garlic = common::ingredient(&pool, "Garlic", 0.0).await;
+    let ids = vec![
+        suggest(&pool, onion, spammer).await,
+        suggest(&pool, garlic, spammer).await,
+    ];
+
+    decline(
+        &pool,
+        moderator,
+        DeclineSuggestions {
+            ids,
+            reason: Some("Not a real value".into()),
+            notify_authors: true,
+        },
+    )
+    .await
+    .unwrap();
+
+    let saved: Vec<(uuid::Uuid, String)> =
+        sqlx::query_as("SELECT user_id, kind FROM notifications ORDER BY payload->>'ingredient'")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        saved,
+        [
+            (spammer, "suggestion_declined".to_owned()),
+            (spammer, "suggestion_declined".to_owned()),
+        ]
+    );
+    assert_eq!(
+        count(
+            &pool,
+            "SELECT COUNT(*) FROM events_outbox WHERE kind = 'suggestion_declined'"
+        )
+        .await,
+        2
+    );
+}
+
+#[sqlx::test]
+async fn too_many_or_no_ids_are_rejected(pool: PgPool) {
+    let moderator = common::user(&pool, "moderator").await;
+
+    for ids in [
+        vec![],
+        (0..=MAX_DECLINED_SUGGESTIONS)
+            .map(|_| uuid::Uuid::new_v4())
+            .collect(),
+    ] {
+        let result = decline(
+            &pool,
+            moderator,
+            DeclineSuggestions {
+                ids,
+                reason: None,
+                notify_authors: false,
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(ApiError::UnprocessableEntity { .. })));
+    }
+}
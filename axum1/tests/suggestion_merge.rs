@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use axum1::routes::ingredient::{
+    suggestion::{merge_suggested_fields, split_conflicting_suggestions},
+    UpgradeIngredient,
+};
+use serde_json::json;
+
+fn suggestion(fields: serde_json::Value) -> (uuid::Uuid, UpgradeIngredient) {
+    (
+        uuid::Uuid::new_v4(),
+        serde_json::from_value(fields).unwrap(),
+    )
+}
+
+#[test]
+fn non_overlapping_suggestions_are_merged() {
+    let suggestions = [
+        suggestion(json!({ "protein": 12.5 })),
+        suggestion(json!({ "fat": 3.0, "category": ["dairy"] })),
+    ];
+
+    let merged = merge_suggested_fields(&suggestions, &HashMap::new()).unwrap();
+    let merged = serde_json::to_value(merged).unwrap();
+
+    assert_eq!(merged["protein"], 12.5);
+    assert_eq!(merged["fat"], 3.0);
+    assert_eq!(merged["category"], json!(["dairy"]));
+    assert_eq!(merged["name"], serde_json::Value::Null);
+}
+
+#[test]
+fn equal_values_are_not_conflicts() {
+    let suggestions = [
+        suggestion(json!({ "water": 80.0 })),
+        suggestion(json!({ "water": 80.0 })),
+    ];
+
+    assert!(merge_suggested_fields(&suggestions, &HashMap::new()).is_ok());
+}
+
+#[test]
+fn conflicting_fields_are_rejected() {
+    let suggestions = [
+        suggestion(json!({ "protein": 12.5, "fat": 1.0 })),
+        suggestion(json!({ "protein": 13.0, "fat": 1.0 })),
+    ];
+
+    let conflicts = merge_suggested_fields(&suggestions, &HashMap::new()).unwrap_err();
+    assert_eq!(conflicts, vec!["protein"]);
+}
+
+#[test]
+fn overrides_resolve_conflicts() {
+    let suggestions = [
+        suggestion(json!({ "protein": 12.5 })),
+        suggestion(json!({ "protein": 13.0 })),
+    ];
+    let overrides = HashMap::from([("protein".to_owned(), suggestions[1].0)]);
+
+    let merged = merge_suggested_fields(&suggestions, &overrides).unwrap();
+    assert_eq!(serde_json::to_value(merged).unwrap()["protein"], 13.0);
+}
+
+#[test]
+fn only_conflicting_suggestions_are_split_off() {
+    let suggestions = vec![
+        suggestion(json!({ "protein": 12.5, "fat": 1.0 })),
+        suggestion(json!({ "protein": 13.0 })),
+        suggestion(json!({ "fat": 1.0, "water": 70.0 })),
+        suggestion(json!({ "category": ["dairy"] })),
+    ];
+    let ids: Vec<_> = suggestions.iter().map(|(id, _)| *id).collect();
+
+    let (applicable, conflicting) = split_conflicting_suggestions(suggestions);
+
+    let applicable: Vec<_> = applicable.into_iter().map(|(id, _)| id).collect();
+    assert_eq!(applicable, [ids[2], ids[3]]);
+    assert_eq!(
+        conflicting,
+        [(ids[0], vec!["protein"]), (ids[1], vec!["protein"])]
+    );
+}
+
+#[test]
+fn nothing_is_split_off_without_conflicts() {
+    let suggestions = vec![
+        suggestion(json!({ "protein": 12.5 })),
+        suggestion(json!({ "fat": 3.0 })),
+    ];
+
+    let (applicable, conflicting) = split_conflicting_suggestions(suggestions);
+
+    assert_eq!(applicable.len(), 2);
+    assert!(conflicting.is_empty());
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use axum1::{
+    error::ApiError,
+    routes::ingredient::quota::{
+        consume_suggestion_quota, SuggestionQuota, LIMIT_HEADER, REMAINING_HEADER,
+    },
+};
+use sqlx::PgPool;
+
+#[sqlx::test]
+async fn suggestions_over_the_daily_cap_are_rejected(pool: PgPool) {
+    let spammer = common::user(&pool, "spammer").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    for remaining in [2, 1, 0] {
+        let quota = consume_suggestion_quota(&mut conn, spammer, 3)
+            .await
+            .unwrap();
+        assert_eq!(
+            quota,
+            SuggestionQuota {
+                limit: 3,
+                remaining
+            }
+        );
+    }
+
+    let result = consume_suggestion_quota(&mut conn, spammer, 3).await;
+    assert!(matches!(result, Err(ApiError::TooManyRequests)));
+    assert_eq!(
+        result.unwrap_err().into_response().status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[sqlx::test]
+async fn each_user_has_their_own_quota(pool: PgPool) {
+    let spammer = common::user(&pool, "spammer").await;
+    let other = common::user(&pool, "other").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    consume_suggestion_quota(&mut conn, spammer, 1)
+        .await
+        .unwrap();
+    assert!(consume_suggestion_quota(&mut conn, spammer, 1)
+        .await
+        .is_err());
+
+    assert!(consume_suggestion_quota(&mut conn, other, 1).await.is_ok());
+}
+
+#[sqlx::test]
+async fn yesterdays_suggestions_dont_count(pool: PgPool) {
+    let spammer = common::user(&pool, "spammer").await;
+    sqlx::query(
+        "INSERT INTO suggestion_counts (user_id, day, count) VALUES ($1, (NOW() AT TIME ZONE 'UTC')::DATE - 1, 5)",
+    )
+    .bind(spammer)
+    .execute(&pool)
+    .await
+    .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let quota = consume_suggestion_quota(&mut conn, spammer, 5)
+        .await
+        .unwrap();
+
+    assert_eq!(quota.remaining, 4);
+}
+
+#[sqlx::test]
+async fn rolled_back_suggestions_dont_count(pool: PgPool) {
+    let spammer = common::user(&pool, "spammer").await;
+
+    let mut tx = pool.begin().await.unwrap();
+    consume_suggestion_quota(&mut tx, spammer, 1).await.unwrap();
+    tx.rollback().await.unwrap();
+
+    let mut conn = pool.acquire().await.unwrap();
+    assert!(consume_suggestion_quota(&mut conn, spammer, 1)
+        .await
+        .is_ok());
+}
+
+#[test]
+fn quota_is_reported_in_headers() {
+    let response = SuggestionQuota {
+        limit: 50,
+        remaining: 7,
+    }
+    .into_response();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[LIMIT_HEADER], "50");
+    assert_eq!(response.headers()[REMAINING_HEADER], "7");
+}
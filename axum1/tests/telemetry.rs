@@ -0,0 +1,50 @@
+use axum::http::Request;
+use axum1::{
+    config::TelemetrySettings,
+    telemetry::{init_tracer, make_span},
+};
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::TracerProvider};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[test]
+fn nothing_is_exported_by_default() {
+    let settings = TelemetrySettings::default();
+
+    assert!(init_tracer(&settings).unwrap().is_none());
+    assert_eq!(settings.service_name(), "axum1");
+    assert_eq!(settings.sample_ratio(), 1.0);
+}
+
+#[test]
+fn the_sample_ratio_is_clamped() {
+    let settings = TelemetrySettings {
+        sample_ratio: Some(4.0),
+        ..Default::default()
+    };
+
+    assert_eq!(settings.sample_ratio(), 1.0);
+}
+
+#[test]
+fn requests_continue_the_incoming_trace() {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = TracerProvider::builder().build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let request = Request::get("/")
+        .header(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .body(())
+        .unwrap();
+
+    let trace_id = tracing::subscriber::with_default(subscriber, || {
+        let span = make_span(&request);
+        span.context().span().span_context().trace_id()
+    });
+
+    assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+}
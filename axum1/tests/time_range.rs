@@ -0,0 +1,66 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+};
+use axum1::time_range::TimeRange;
+use chrono::{TimeZone, Utc};
+use tower::ServiceExt;
+
+async fn request(uri: &str) -> (StatusCode, serde_json::Value) {
+    let app = Router::new().route(
+        "/",
+        get(|range: TimeRange| async move { format!("{:?} {:?}", range.since, range.until) }),
+    );
+    let response = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[test]
+fn offsets_are_normalized_to_utc() {
+    let range = TimeRange::parse(Some("2026-10-15T12:00:00+02:00"), None).unwrap();
+
+    assert_eq!(
+        range.since,
+        Some(Utc.with_ymd_and_hms(2026, 10, 15, 10, 0, 0).unwrap())
+    );
+    assert_eq!(range.until, None);
+}
+
+#[test]
+fn empty_range_is_allowed() {
+    // `since` is inclusive and `until` is exclusive, so this matches nothing, but it's valid.
+    let range = TimeRange::parse(Some("2026-10-15T00:00:00Z"), Some("2026-10-15T00:00:00Z"));
+
+    assert!(range.is_ok());
+}
+
+#[tokio::test]
+async fn missing_bounds_are_unbounded() {
+    let (status, _) = request("/").await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn malformed_timestamps_are_unprocessable() {
+    let (status, body) = request("/?since=yesterday&until=2026-10-15").await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["since"].is_array());
+    assert!(body["errors"]["until"].is_array());
+}
+
+#[tokio::test]
+async fn reversed_range_is_unprocessable() {
+    let (status, body) = request("/?since=2026-10-16T00:00:00Z&until=2026-10-15T00:00:00Z").await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["since"].is_array());
+}
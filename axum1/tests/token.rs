@@ -0,0 +1,85 @@
+mod common;
+
+use axum1::{
+    config::{TokenEncoding, TokenSettings, MIN_TOKEN_BYTES},
+    routes::auth::confirm::{get_user_id_from_token, store_token},
+    token::{generate_token, hash_token, verify_token},
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+#[test]
+fn token_length_follows_configured_entropy() {
+    let base64 = TokenSettings::default();
+    // 32 bytes in unpadded base64 is 43 characters.
+    assert_eq!(generate_token(&base64).len(), 43);
+
+    let hex = TokenSettings {
+        bytes: Some(16),
+        encoding: Some(TokenEncoding::Hex),
+        ..Default::default()
+    };
+    assert_eq!(generate_token(&hex).len(), 32);
+}
+
+#[test]
+fn tokens_are_url_safe() {
+    let token = generate_token(&TokenSettings::default());
+    assert!(token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+}
+
+#[test]
+fn too_few_bytes_are_rejected() {
+    let settings = serde_json::from_value::<TokenSettings>(json!({ "bytes": 0 }));
+    assert!(settings.is_err());
+    let settings = serde_json::from_value::<TokenSettings>(json!({ "bytes": MIN_TOKEN_BYTES }));
+    assert_eq!(settings.unwrap().bytes(), MIN_TOKEN_BYTES);
+}
+
+#[test]
+fn verify_token_rejects_other_tokens() {
+    let token = generate_token(&TokenSettings::default());
+    assert!(verify_token(&token, &token));
+    assert!(!verify_token(
+        &token,
+        &generate_token(&TokenSettings::default())
+    ));
+    assert!(!verify_token(&token, &token[..token.len() - 1]));
+    assert!(!verify_token(&token, &format!("{token}a")));
+    assert!(!verify_token(&token, ""));
+}
+
+#[sqlx::test]
+async fn tokens_are_hashed_like_postgres_does(pool: PgPool) {
+    let token = generate_token(&TokenSettings::default());
+    let hashed: String = sqlx::query_scalar("SELECT encode(sha256(convert_to($1, 'UTF8')), 'hex')")
+        .bind(&token)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(hash_token(&token), hashed);
+}
+
+#[sqlx::test]
+async fn confirmation_tokens_are_only_found_by_the_token_itself(pool: PgPool) {
+    let cook = common::user(&pool, "cook").await;
+    let token = generate_token(&TokenSettings::default());
+    store_token(&pool, &token, cook, 24).await.unwrap();
+
+    let stored: String =
+        sqlx::query_scalar("SELECT confirmation_token FROM confirmation_tokens WHERE user_id = $1")
+            .bind(cook)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_ne!(stored, token);
+
+    let found = get_user_id_from_token(&pool, &token).await.unwrap();
+    assert_eq!(found, Some((cook, false)));
+    // Neither what's in the table nor another token gets anywhere.
+    assert_eq!(get_user_id_from_token(&pool, &stored).await.unwrap(), None);
+    let other = generate_token(&TokenSettings::default());
+    assert_eq!(get_user_id_from_token(&pool, &other).await.unwrap(), None);
+}
@@ -0,0 +1,29 @@
+use axum1::upload::should_compress;
+
+fn types(types: &[&str]) -> Vec<String> {
+    types.iter().map(|&t| t.to_owned()).collect()
+}
+
+#[test]
+fn configured_text_types_are_compressed() {
+    let compressed = types(&["text/*", "application/json"]);
+
+    assert!(should_compress("text/markdown", &compressed));
+    assert!(should_compress("text/plain; charset=utf-8", &compressed));
+    assert!(should_compress("Application/JSON", &compressed));
+}
+
+#[test]
+fn binary_and_precompressed_types_are_stored_as_is() {
+    let compressed = types(&["text/*", "application/json"]);
+
+    assert!(!should_compress("image/png", &compressed));
+    assert!(!should_compress("application/gzip", &compressed));
+    assert!(!should_compress("application/octet-stream", &compressed));
+    assert!(!should_compress("not a content type", &compressed));
+}
+
+#[test]
+fn nothing_is_compressed_by_default() {
+    assert!(!should_compress("text/markdown", &[]));
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use axum1::{error::ApiError, upload::delete_owned_upload};
+use sqlx::PgPool;
+
+async fn upload(pool: &PgPool, user_id: uuid::Uuid, file_name: &str, bytes: i32) {
+    sqlx::query(
+        "INSERT INTO uploads (uploader_id, file_name, bytes, stored_bytes) VALUES ($1, $2, $3, $3)",
+    )
+    .bind(user_id)
+    .bind(file_name)
+    .bind(bytes)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn recipe_with_image(pool: &PgPool, user_id: uuid::Uuid, image: &str) {
+    let recipe_id = common::recipe(pool, user_id, "goulash").await;
+    sqlx::query("UPDATE recipes SET image = $2 WHERE id = $1")
+        .bind(recipe_id)
+        .bind(image)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+/// The bytes counted towards the daily upload limit.
+async fn used_today(pool: &PgPool, user_id: uuid::Uuid) -> i64 {
+    sqlx::query_scalar(
+        "SELECT COALESCE(SUM(bytes), 0) FROM uploads WHERE uploader_id = $1 AND created_at > NOW() - INTERVAL '1 days'",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test]
+async fn deleting_an_own_upload_frees_its_bytes(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    upload(&pool, alice, "a.png", 1000).await;
+    upload(&pool, alice, "b.png", 500).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    delete_owned_upload(&mut conn, alice, "a.png", false)
+        .await
+        .unwrap();
+
+    assert_eq!(used_today(&pool, alice).await, 500);
+}
+
+#[sqlx::test]
+async fn uploads_of_others_cant_be_deleted(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    let bob = common::user(&pool, "bob").await;
+    upload(&pool, alice, "a.png", 1000).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let error = delete_owned_upload(&mut conn, bob, "a.png", true)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, ApiError::NotFound));
+    assert_eq!(used_today(&pool, alice).await, 1000);
+}
+
+#[sqlx::test]
+async fn recipe_images_are_only_deleted_with_force(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    upload(&pool, alice, "goulash.png", 1000).await;
+    recipe_with_image(&pool, alice, "goulash.png").await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let error = delete_owned_upload(&mut conn, alice, "goulash.png", false)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::Conflict));
+    assert_eq!(used_today(&pool, alice).await, 1000);
+
+    delete_owned_upload(&mut conn, alice, "goulash.png", true)
+        .await
+        .unwrap();
+    assert_eq!(used_today(&pool, alice).await, 0);
+    let image: Option<String> = sqlx::query_scalar("SELECT image FROM recipes")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(image, None);
+}
+
+#[sqlx::test]
+async fn avatars_are_only_deleted_with_force(pool: PgPool) {
+    let alice = common::user(&pool, "alice").await;
+    upload(&pool, alice, "alice.png", 1000).await;
+    sqlx::query("UPDATE users SET avatar = 'alice.png' WHERE user_id = $1")
+        .bind(alice)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+
+    let error = delete_owned_upload(&mut conn, alice, "alice.png", false)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::Conflict));
+
+    delete_owned_upload(&mut conn, alice, "alice.png", true)
+        .await
+        .unwrap();
+    let avatar: Option<String> = sqlx::query_scalar("SELECT avatar FROM users")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(avatar, None);
+}
@@ -0,0 +1,65 @@
+use axum1::{error::ApiError, utils::with_transaction};
+use sqlx::PgPool;
+
+async fn insert_user(conn: &mut sqlx::PgConnection, name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO users (name, email, password_hash) VALUES ($1, $1 || '@example.com', '')",
+    )
+    .bind(name)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn user_count(pool: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn success_commits(pool: PgPool) {
+    let name = with_transaction(&pool, |tx| {
+        Box::pin(async move {
+            insert_user(&mut *tx, "first").await?;
+            insert_user(&mut *tx, "second").await?;
+            Ok::<_, ApiError>("second")
+        })
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(name, "second");
+    assert_eq!(user_count(&pool).await, 2);
+}
+
+#[sqlx::test]
+async fn an_error_mid_closure_rolls_back(pool: PgPool) {
+    let result = with_transaction(&pool, |tx| {
+        Box::pin(async move {
+            insert_user(&mut *tx, "first").await?;
+            // Already taken, so this fails after the first insert went through.
+            insert_user(&mut *tx, "first").await?;
+            Ok::<_, ApiError>(())
+        })
+    })
+    .await;
+
+    assert!(matches!(result, Err(ApiError::Sqlx(_))));
+    assert_eq!(user_count(&pool).await, 0);
+}
+
+#[sqlx::test]
+async fn returning_an_error_rolls_back(pool: PgPool) {
+    let result: Result<(), _> = with_transaction(&pool, |tx| {
+        Box::pin(async move {
+            insert_user(&mut *tx, "first").await?;
+            Err(ApiError::Conflict)
+        })
+    })
+    .await;
+
+    assert!(matches!(result, Err(ApiError::Conflict)));
+    assert_eq!(user_count(&pool).await, 0);
+}
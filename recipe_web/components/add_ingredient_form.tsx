@@ -16,6 +16,7 @@ import { useSWRConfig } from 'swr';
 import { useAddIngredient } from '../stores/useAddIngredient';
 import { intoFormBody } from '../utils/form';
 import IngredientSearch from './search/IngredientSearch';
+import { apiFetch } from '../utils/api';
 
 export function AddIngredientForm() {
   const { mutate } = useSWRConfig();
@@ -50,10 +51,9 @@ export function AddIngredientForm() {
         return;
       }
       setLoading(true);
-      const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/ingredient`, {
+      const response = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/ingredient`, {
         method: 'POST',
         body: intoFormBody({ ...values, name: selected! }),
-        credentials: 'include',
         headers: {
           'Content-Type': 'application/x-www-form-urlencoded',
         },
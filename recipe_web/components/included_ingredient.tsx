@@ -15,6 +15,7 @@ import { useState } from 'react';
 import { intoFormBody } from '../utils/form';
 import { EditableControls } from './editable_custom_controls';
 import NextLink from 'next/link';
+import { apiFetch } from '../utils/api';
 
 interface IncludedIngredientProps {
   name: string;
@@ -32,9 +33,8 @@ export default function IncludedIngredient({
   const { name: rName } = router.query;
   const deleteIngredient = async (rName: string, iName: string) => {
     const body = intoFormBody({ name: iName });
-    const { ok } = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${rName}/ingredient`, {
+    const { ok } = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${rName}/ingredient`, {
       method: 'DELETE',
-      credentials: 'include',
       body,
       headers: {
         'Content-Type': 'application/x-www-form-urlencoded',
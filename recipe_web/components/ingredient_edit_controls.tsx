@@ -4,6 +4,7 @@ import { useSWRConfig } from 'swr';
 import { useIngredientEditMode } from '../stores/useIngredientEditMode';
 import { diffObjects } from '../utils/diff';
 import { IngredientProps } from './ingredient';
+import { apiFetch } from '../utils/api';
 
 export const IngredientEditControls = ({
   name,
@@ -30,9 +31,8 @@ export const IngredientEditControls = ({
       });
       return;
     }
-    const { ok, status } = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/i/${name}/suggestion`, {
+    const { ok, status } = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/i/${name}/suggestion`, {
       method: 'POST',
-      credentials: 'include',
       body: JSON.stringify(data),
       headers: {
         'Content-Type': 'application/json',
@@ -59,9 +59,8 @@ export const IngredientEditControls = ({
   };
 
   const handleSubmitDelete = async () => {
-    const { ok, status } = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/i/${name}/suggestion`, {
+    const { ok, status } = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/i/${name}/suggestion`, {
       method: 'POST',
-      credentials: 'include',
       body: JSON.stringify({ is_delete_vote: true }),
       headers: {
         'Content-Type': 'application/json',
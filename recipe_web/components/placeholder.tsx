@@ -27,6 +27,7 @@ import { first, PreviewImage, second, third } from './form/upload';
 import { Listable } from './Listable';
 import { DurationSlider } from './slider';
 import RecipeSearch from './search/RecipeSearch';
+import { apiFetch } from '../utils/api';
 
 export default function Placeholder() {
   const [setPrepTime, setDifficulty, steps, pushStep, removeStepByIndex] = useAddRecipe((state) => [
@@ -72,10 +73,9 @@ export default function Placeholder() {
             let file = e.target.uploadFile.files[0];
             let formData = new FormData();
             formData.append('file', file);
-            fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/upload`, {
+            apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/upload`, {
               method: 'POST',
               body: formData,
-              credentials: 'include',
             }).then((resp) => console.log(resp));
           }}
         >
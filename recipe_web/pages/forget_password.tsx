@@ -21,6 +21,7 @@ import NextLink from 'next/link';
 import { CloseIcon } from '@chakra-ui/icons';
 import { useToast } from '@chakra-ui/react';
 import { useValidToken } from '../hooks/token';
+import { apiFetch } from '../utils/api';
 
 function ForgetPasswordGen() {
   const router = useRouter();
@@ -62,12 +63,11 @@ function ForgetPasswordGen() {
       }
       setLoading(true);
       const formBody = intoFormBody({ password: values.password });
-      const response = await fetch(
+      const response = await apiFetch(
         `${process.env.NEXT_PUBLIC_BASE_URL}/forget_password?token=${token}`,
         {
           method: 'POST',
           body: formBody,
-          credentials: 'include',
           headers: {
             'Content-Type': 'application/x-www-form-urlencoded',
           },
@@ -18,6 +18,7 @@ import { useFormik } from 'formik';
 import { useState } from 'react';
 import dynamic from 'next/dynamic';
 import NextLink from 'next/link';
+import { apiFetch } from '../utils/api';
 
 function ForgetPasswordGen() {
   const router = useRouter();
@@ -33,10 +34,9 @@ function ForgetPasswordGen() {
     onSubmit: async (values) => {
       setLoading(true);
       const formBody = intoFormBody(values);
-      const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/forget_password_gen`, {
+      const response = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/forget_password_gen`, {
         method: 'POST',
         body: formBody,
-        credentials: 'include',
         headers: {
           'Content-Type': 'application/x-www-form-urlencoded',
         },
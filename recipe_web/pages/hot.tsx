@@ -43,7 +43,7 @@ export default function HotRecipes() {
           <VStack>
             <Heading>{'Most popular recipes (last two weeks)'}</Heading>
             <UnorderedList>
-              {data.map((recipe: any) => (
+              {data.items.map((recipe: any) => (
                 <ListItem key={recipe.name}>
                   <NextLink passHref href={`/r/${recipe.name}`}>
                     <Flex as="a" _hover={{ color: 'orange.400' }}>
@@ -25,6 +25,7 @@ import { AuthFormWrapper } from '../components/auth_form_wrapper';
 import { OAuthButtonGroup } from '../components/OAuthGroup';
 import { intoFormBody } from '../utils/form';
 import { useAlreadyAuth } from '../utils/useAlreadyAuth';
+import { apiFetch } from '../utils/api';
 
 function Login() {
   useAlreadyAuth();
@@ -43,10 +44,9 @@ function Login() {
       setErrors({});
       setLoading(true);
       const formBody = intoFormBody(values);
-      const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/auth`, {
+      const response = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/auth`, {
         method: 'POST',
         body: formBody,
-        credentials: 'include',
         headers: {
           'Content-Type': 'application/x-www-form-urlencoded',
         },
@@ -19,6 +19,7 @@ import { Layout } from '../../components/layout';
 import { useMe } from '../../hooks/me';
 import { fetcher } from '../../utils/fetcher';
 import NextLink from 'next/link';
+import { apiFetch } from '../../utils/api';
 
 export default function RecipeDetailed() {
   const { me } = useMe();
@@ -31,9 +32,8 @@ export default function RecipeDetailed() {
     fetcher
   );
   const toggleFavorite = async () => {
-    const { ok } = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/favorite`, {
+    const { ok } = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/favorite`, {
       method: 'POST',
-      credentials: 'include',
     });
     if (ok) {
       // Let's just refetch on favorite change, we can always optimize later
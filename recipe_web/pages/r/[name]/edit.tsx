@@ -21,6 +21,7 @@ import { useMe } from '../../../hooks/me';
 import { fetcher } from '../../../utils/fetcher';
 import NextLink from 'next/link';
 import { useEffect } from 'react';
+import { apiFetch } from '../../../utils/api';
 
 export default function RecipeDetailedEdit() {
   const { me } = useMe();
@@ -33,9 +34,8 @@ export default function RecipeDetailedEdit() {
     fetcher
   );
   const toggleFavorite = async () => {
-    const { ok } = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/favorite`, {
+    const { ok } = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r/${name}/favorite`, {
       method: 'POST',
-      credentials: 'include',
     });
     if (ok) {
       // Let's just refetch on favorite change, we can always optimize later
@@ -51,7 +51,7 @@ export default function MyRecipes() {
           <VStack>
             <Heading>{'Favorite recipes'}</Heading>
             <UnorderedList>
-              {data.map((recipe: IRecipe) => (
+              {data.items.map((recipe: IRecipe) => (
                 <ListItem key={recipe.name}>
                   <NextLink passHref href={`/r/${recipe.name}`}>
                     <Flex as="a" _hover={{ color: 'orange.400' }}>
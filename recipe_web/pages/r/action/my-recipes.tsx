@@ -51,7 +51,7 @@ export default function MyRecipes() {
       <Box>
         <Center mt="14">
           <UnorderedList>
-            {data.map((recipe: IRecipe) => (
+            {data.items.map((recipe: IRecipe) => (
               <ListItem key={recipe.name}>
                 <NextLink href={`/r/${recipe.name}`} passHref>
                   <Flex as="a" _hover={{ color: 'orange.400' }}>
@@ -12,6 +12,7 @@ import { difficultyLevels, mealTypes } from '../../../utils/types';
 import { useAuth } from '../../../utils/useAuth';
 import type { DifficultyLevel, MealType } from '../../../utils/types';
 import CuisineSearch from '../../../components/search/CuisineSearch';
+import { apiFetch } from '../../../utils/api';
 
 const NewRecipe = () => {
   const { push } = useRouter();
@@ -100,7 +101,7 @@ const NewRecipe = () => {
           }}
           onSubmit={async (values, { setFieldError }) => {
             // TODO: we do not use Formik's values anyway..
-            const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r`, {
+            const response = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/r`, {
               method: 'POST',
               body: JSON.stringify({
                 name,
@@ -113,7 +114,6 @@ const NewRecipe = () => {
                 meal_type,
                 ingredients,
               }),
-              credentials: 'include',
               headers: { 'Content-Type': 'application/json' },
             });
 
@@ -22,6 +22,7 @@ import { useState } from 'react';
 import { AuthFormWrapper } from '../components/auth_form_wrapper';
 import { intoFormBody } from '../utils/form';
 import { useAlreadyAuth } from '../utils/useAlreadyAuth';
+import { apiFetch } from '../utils/api';
 
 export default function SignupCard() {
   useAlreadyAuth();
@@ -40,10 +41,9 @@ export default function SignupCard() {
     onSubmit: async (values) => {
       setLoading(true);
       const formBody = intoFormBody(values);
-      const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/register`, {
+      const response = await apiFetch(`${process.env.NEXT_PUBLIC_BASE_URL}/register`, {
         method: 'POST',
         body: formBody,
-        credentials: 'include',
         headers: {
           'Content-Type': 'application/x-www-form-urlencoded',
         },
@@ -0,0 +1,37 @@
+const SAFE_METHODS = ['GET', 'HEAD', 'OPTIONS'];
+
+let csrfToken: string | null = null;
+
+const fetchCsrfToken = async (): Promise<string> => {
+  const response = await fetch(`${process.env.NEXT_PUBLIC_BASE_URL}/csrf`, {
+    credentials: 'include',
+  });
+  const { token } = await response.json();
+  return token;
+};
+
+// `fetch` with the session cookie, and on unsafe requests the CSRF token of the session, which
+// the API requires. The token changes on login, so a rejected request is retried once with a
+// fresh one.
+export const apiFetch = async (url: string, init: RequestInit = {}): Promise<Response> => {
+  const method = (init.method ?? 'GET').toUpperCase();
+  if (SAFE_METHODS.includes(method)) {
+    return fetch(url, { ...init, credentials: 'include' });
+  }
+
+  const send = (token: string) =>
+    fetch(url, {
+      ...init,
+      credentials: 'include',
+      headers: { ...(init.headers as Record<string, string>), 'X-CSRF-Token': token },
+    });
+  if (!csrfToken) {
+    csrfToken = await fetchCsrfToken();
+  }
+  let response = await send(csrfToken);
+  if (response.status === 403) {
+    csrfToken = await fetchCsrfToken();
+    response = await send(csrfToken);
+  }
+  return response;
+};